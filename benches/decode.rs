@@ -0,0 +1,150 @@
+//! Decode-stage benchmarks over the same synthetic fixtures as the golden tests.
+//! Run with `cargo bench`; compare runs before and after performance work.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::*;
+use criterion::{Criterion, Throughput, black_box, criterion_group, criterion_main};
+use image::{GrayAlphaImage, buffer::ConvertBuffer};
+use matroska_demuxer::Frame;
+use subproc::{bdsup::PgsParser, preprocess::crop_image, vobs};
+
+/// A single-object epoch start covering a `width`x`height` region
+fn pgs_frame(width: u16, height: u16) -> Frame {
+    let data = PgsDisplaySetBuilder::new()
+        .pcs(
+            1,
+            0x80,
+            0,
+            &[PgsObjectRef {
+                object_id: 1,
+                window_id: 0,
+                x: 0,
+                y: 0,
+                crop: None,
+            }],
+        )
+        .wds(&[(0, 100, 1000 - height, width, height)])
+        .pds(0, 0, &[(1, 235, 255), (2, 16, 255), (3, 128, 128)])
+        .ods(
+            1,
+            0,
+            width,
+            height,
+            &pgs_rle(&outlined_bar(width as usize, height as usize, 1, 2)),
+            1,
+        )
+        .finish();
+    return mkv_frame(0, data);
+}
+
+fn pgs_parsing(c: &mut Criterion) {
+    // Tiny object, so the cost is dominated by segment parsing & cache updates
+    let frame = pgs_frame(8, 2);
+    let mut parser = PgsParser::new();
+    let mut image = GrayAlphaImage::new(0, 0);
+    c.bench_function("pgs/display_set_parse", |b| {
+        b.iter(|| {
+            parser
+                .process_mkv_frame_into(black_box(&frame), &mut image)
+                .unwrap()
+        })
+    });
+}
+
+/// Show, palette update and clear, as in most subtitles. Parsing these
+/// repeatedly reuses the parser's buffers rather than allocating per
+/// composition.
+fn pgs_sequence(c: &mut Criterion) {
+    let objects = [PgsObjectRef {
+        object_id: 1,
+        window_id: 0,
+        x: 0,
+        y: 0,
+        crop: None,
+    }];
+    let display_sets = [
+        pgs_frame(8, 2).data,
+        PgsDisplaySetBuilder::new()
+            .pcs(2, 0x00, 0, &objects)
+            .pds(0, 1, &[(1, 235, 64), (2, 16, 64)])
+            .finish(),
+        PgsDisplaySetBuilder::new().pcs(3, 0x00, 0, &[]).finish(),
+    ];
+    let mut parser = PgsParser::new();
+    let mut image = GrayAlphaImage::new(0, 0);
+    c.bench_function("pgs/display_set_sequence", |b| {
+        b.iter(|| {
+            for data in display_sets.iter() {
+                parser
+                    .process_reused_into(black_box(data), &mut image)
+                    .unwrap();
+            }
+        })
+    });
+}
+
+fn pgs_rendering(c: &mut Criterion) {
+    let frame = pgs_frame(1700, 200);
+    let mut group = c.benchmark_group("pgs");
+    group.throughput(Throughput::Elements(1700 * 200));
+    let mut parser = PgsParser::new();
+    let mut image = GrayAlphaImage::new(0, 0);
+    group.bench_function("rle_render_reused_buffer", |b| {
+        b.iter(|| {
+            parser
+                .process_mkv_frame_into(black_box(&frame), &mut image)
+                .unwrap()
+        })
+    });
+    // What the extractor does, since the demuxer reuses its frame buffer
+    let mut parser = PgsParser::new();
+    group.bench_function("rle_render_copied_object", |b| {
+        b.iter(|| {
+            parser
+                .process_reused_into(black_box(&frame.data), &mut image)
+                .unwrap()
+        })
+    });
+    let mut parser = PgsParser::new();
+    group.bench_function("rle_render_fresh_buffer", |b| {
+        b.iter(|| parser.process_mkv_frame(black_box(&frame)).unwrap())
+    });
+    group.finish();
+}
+
+fn vobsub_decode(c: &mut Criterion) {
+    let idx = vobs::parse_idx(VOBSUB_IDX.as_bytes()).unwrap();
+    let rows = outlined_bar(600, 80, 1, 2);
+    let packet = vobsub_subpicture(60, 380, &rows, [1, 2, 3, 0], [15, 15, 15, 0]);
+    let mut group = c.benchmark_group("vobsub");
+    group.throughput(Throughput::Elements(600 * 80));
+    group.bench_function("frame_decode", |b| {
+        b.iter(|| vobs::parse_frame(&idx, black_box(&packet)).unwrap())
+    });
+    group.finish();
+}
+
+fn preprocessing(c: &mut Criterion) {
+    let image = PgsParser::new()
+        .process_mkv_frame(&pgs_frame(1700, 200))
+        .unwrap()
+        .unwrap();
+    c.bench_function("preprocess/crop_and_flatten", |b| {
+        b.iter(|| {
+            let cropped: image::GrayImage = crop_image(black_box(&image)).convert();
+            cropped
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    pgs_parsing,
+    pgs_sequence,
+    pgs_rendering,
+    vobsub_decode,
+    preprocessing
+);
+criterion_main!(benches);
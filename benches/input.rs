@@ -0,0 +1,87 @@
+//! Demuxing a large MKV read through each input strategy: buffered reads,
+//! read-ahead and memory mapping. Set `SUBPROC_BENCH_MKV` to a real remux,
+//! since multi-GB files on the storage in question are where the differences
+//! show; otherwise a 256 MiB synthetic file in the temp directory is used.
+//! Run with `cargo bench --features mmap --bench input`.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::path::PathBuf;
+
+use common::*;
+use criterion::{Criterion, Throughput, black_box, criterion_group, criterion_main};
+use matroska_demuxer::{Frame, MatroskaFile};
+use subproc::input::{Input, IoStrategy};
+
+/// A video track of 64 KiB frames at 25 fps, with a subtitle every 2 seconds
+fn synthetic_mkv() -> PathBuf {
+    let path = std::env::temp_dir().join("subproc-bench-input.mkv");
+    if path.exists() {
+        return path;
+    }
+    let mut frames = Vec::new();
+    for index in 0..4096u64 {
+        frames.push((1, index * 40, vec![(index % 251) as u8; 64 * 1024]));
+        if index % 50 == 0 {
+            frames.push((2, index * 40, b"Hello".to_vec()));
+        }
+    }
+    let file = build_mkv(
+        &[(1, "V_MPEG4/ISO/AVC", None), (2, "S_TEXT/UTF8", None)],
+        &frames,
+    );
+    std::fs::write(&path, file).unwrap();
+    return path;
+}
+
+/// Reads every frame, as extraction does
+fn demux(input: Input) -> usize {
+    let mut mkv = MatroskaFile::open(input).unwrap();
+    let mut frame = Frame::default();
+    let mut frames = 0;
+    while mkv.next_frame(&mut frame).unwrap() {
+        frames += 1;
+    }
+    return frames;
+}
+
+fn strategies(c: &mut Criterion) {
+    let path = std::env::var_os("SUBPROC_BENCH_MKV")
+        .map(PathBuf::from)
+        .unwrap_or_else(synthetic_mkv);
+    let size = std::fs::metadata(&path).unwrap().len();
+    let mut group = c.benchmark_group("input");
+    group.throughput(Throughput::Bytes(size));
+    let buffered = |buffer_size| IoStrategy {
+        buffer_size,
+        ..IoStrategy::default()
+    };
+    for (name, strategy) in [
+        ("buffered_8k", buffered(8 * 1024)),
+        ("buffered_256k", buffered(256 * 1024)),
+        (
+            "read_ahead_4x1m",
+            IoStrategy {
+                buffer_size: 1024 * 1024,
+                read_ahead: 4,
+                ..IoStrategy::default()
+            },
+        ),
+        (
+            "mmap",
+            IoStrategy {
+                mmap: true,
+                ..IoStrategy::default()
+            },
+        ),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| demux(black_box(strategy.open(&path).unwrap())))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, strategies);
+criterion_main!(benches);
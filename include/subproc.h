@@ -0,0 +1,107 @@
+#ifndef SUBPROC_H
+#define SUBPROC_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit by hand. */
+
+#include <stdbool.h>
+#include <stdint.h>
+
+// Where an event's end time came from, as recorded in the manifest
+typedef enum SubprocEndSource {
+  // The event has no end time
+  SubprocEndSource_Unknown,
+  // The subtitle stream itself, e.g. the PGS display set that cleared it
+  SubprocEndSource_Stream,
+  // The container's block duration
+  SubprocEndSource_Container,
+  // Inferred: it lasts until the next event starts, up to a limit
+  SubprocEndSource_NextEvent,
+  // Inferred: no event follows, so it lasts a fixed time
+  SubprocEndSource_Fallback,
+} SubprocEndSource;
+
+// Opaque handle to a decoded event
+typedef struct SubprocEvent SubprocEvent;
+
+// Opaque handle to an open subtitle track
+typedef struct SubprocExtractor SubprocExtractor;
+
+// Borrowed view of an event's bitmap. Pixels are 8-bit gray + 8-bit alpha
+// pairs, `stride` bytes per row.
+typedef struct SubprocImage {
+  uint32_t width;
+  uint32_t height;
+  uint32_t stride;
+  const uint8_t *data;
+} SubprocImage;
+
+// Returns the message for the last failed call on this thread, or NULL. The
+// string stays valid until the next failing call on this thread.
+const char *subproc_last_error(void);
+
+// Opens `path` and selects subtitle track `track`, or the first subtitle track
+// if `track` is 0. Returns NULL on failure.
+//
+// # Safety
+// `path` must be a valid NUL-terminated string.
+SubprocExtractor *subproc_open(const char *path, uint64_t track);
+
+// Decodes the next event into `*event`. Returns 1 if an event was produced,
+// 0 at the end of the track, and -1 if a frame failed to decode; decoding can
+// continue after an error. A panicking decoder also returns -1, after which
+// the extractor should only be freed.
+//
+// # Safety
+// `extractor` must come from `subproc_open`, and `event` must be writable.
+int32_t subproc_next_event(SubprocExtractor *extractor, SubprocEvent **event);
+
+// Start time in nanoseconds
+//
+// # Safety
+// `event` must come from `subproc_next_event`.
+uint64_t subproc_event_start(const SubprocEvent *event);
+
+// End time in nanoseconds. Events get one even when neither the stream nor
+// the container gives it, inferred from the next event;
+// `subproc_event_end_source` tells which. -1 if the event has none.
+//
+// # Safety
+// `event` must come from `subproc_next_event`.
+int64_t subproc_event_end(const SubprocEvent *event);
+
+// Where the end time came from, and so whether it was inferred
+//
+// # Safety
+// `event` must come from `subproc_next_event`.
+SubprocEndSource subproc_event_end_source(const SubprocEvent *event);
+
+// # Safety
+// `event` must come from `subproc_next_event`.
+bool subproc_event_forced(const SubprocEvent *event);
+
+// Text of a text event, or NULL for image events. Valid until the event is freed.
+//
+// # Safety
+// `event` must come from `subproc_next_event`.
+const char *subproc_event_text(const SubprocEvent *event);
+
+// Fills `*image` with the bitmap of an image event and returns true, or
+// returns false for text events. The pixels are valid until the event is freed.
+//
+// # Safety
+// `event` must come from `subproc_next_event`, and `image` must be writable.
+bool subproc_event_image(const SubprocEvent *event, SubprocImage *image);
+
+// # Safety
+// `event` must come from `subproc_next_event` and not be used afterwards.
+// NULL is ignored.
+void subproc_event_free(SubprocEvent *event);
+
+// Closes an extractor. Events already returned stay valid.
+//
+// # Safety
+// `extractor` must come from `subproc_open` and not be used afterwards.
+// NULL is ignored.
+void subproc_free(SubprocExtractor *extractor);
+
+#endif  /* SUBPROC_H */
@@ -0,0 +1,281 @@
+//! Rasterizes ASS/SSA tracks (`S_TEXT/ASS`, `S_TEXT/SSA`) with libass, so
+//! styled text subtitles come out as positioned bitmaps like PGS and VobSub
+//! ones, for contact sheets and burn-in. Fonts attached to the MKV are used
+//! along with the system's.
+
+use std::{
+    ffi::{CString, c_char, c_int, c_longlong},
+    ptr,
+};
+
+use image::{Rgba, RgbaImage};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AssError {
+    #[error("Failed to initialize libass")]
+    Init,
+}
+
+/// The size scripts are laid out for when their header doesn't give
+/// `PlayResX`/`PlayResY`
+pub const DEFAULT_PLAY_RES: (u32, u32) = (384, 288);
+
+/// The parts of the libass API used here
+mod sys {
+    use std::ffi::{c_char, c_int, c_longlong, c_uchar};
+
+    pub enum AssLibrary {}
+    pub enum AssRenderer {}
+    pub enum AssTrack {}
+
+    /// One layer of a rendered frame: an alpha mask drawn in a single color
+    #[repr(C)]
+    pub struct AssImage {
+        pub w: c_int,
+        pub h: c_int,
+        pub stride: c_int,
+        pub bitmap: *const c_uchar,
+        /// `0xRRGGBBAA`, where AA is the transparency rather than the opacity
+        pub color: u32,
+        pub dst_x: c_int,
+        pub dst_y: c_int,
+        pub next: *const AssImage,
+        pub kind: c_int,
+    }
+
+    pub const ASS_FONTPROVIDER_AUTODETECT: c_int = 1;
+
+    #[link(name = "ass")]
+    unsafe extern "C" {
+        pub fn ass_library_init() -> *mut AssLibrary;
+        pub fn ass_library_done(library: *mut AssLibrary);
+        pub fn ass_add_font(
+            library: *mut AssLibrary,
+            name: *const c_char,
+            data: *const c_char,
+            data_size: c_int,
+        );
+        pub fn ass_renderer_init(library: *mut AssLibrary) -> *mut AssRenderer;
+        pub fn ass_renderer_done(renderer: *mut AssRenderer);
+        pub fn ass_set_frame_size(renderer: *mut AssRenderer, w: c_int, h: c_int);
+        pub fn ass_set_fonts(
+            renderer: *mut AssRenderer,
+            default_font: *const c_char,
+            default_family: *const c_char,
+            font_provider: c_int,
+            config: *const c_char,
+            update: c_int,
+        );
+        pub fn ass_new_track(library: *mut AssLibrary) -> *mut AssTrack;
+        pub fn ass_free_track(track: *mut AssTrack);
+        pub fn ass_process_codec_private(track: *mut AssTrack, data: *const c_char, size: c_int);
+        pub fn ass_process_chunk(
+            track: *mut AssTrack,
+            data: *const c_char,
+            size: c_int,
+            timecode: c_longlong,
+            duration: c_longlong,
+        );
+        pub fn ass_render_frame(
+            renderer: *mut AssRenderer,
+            track: *mut AssTrack,
+            now: c_longlong,
+            detect_change: *mut c_int,
+        ) -> *const AssImage;
+    }
+}
+
+/// Renders the events of one ASS/SSA track
+pub struct AssRenderer {
+    library: *mut sys::AssLibrary,
+    renderer: *mut sys::AssRenderer,
+    /// The script's header, with its styles
+    header: Vec<u8>,
+    width: u32,
+    height: u32,
+    /// Whether fonts were added since the renderer last looked for them
+    fonts_changed: bool,
+}
+// SAFETY: libass objects aren't tied to the thread that made them, and
+// `&mut self` keeps them from being used by two at once
+unsafe impl Send for AssRenderer {}
+
+impl AssRenderer {
+    /// `header` is the script's header (a Matroska track's `CodecPrivate`).
+    /// Frames are rendered at the size the script is laid out for.
+    pub fn new(header: &[u8]) -> Result<Self, AssError> {
+        let (width, height) = play_res(header).unwrap_or(DEFAULT_PLAY_RES);
+        // SAFETY: plain constructors; failures come back as null
+        let library = unsafe { sys::ass_library_init() };
+        if library.is_null() {
+            return Err(AssError::Init);
+        }
+        let renderer = unsafe { sys::ass_renderer_init(library) };
+        if renderer.is_null() {
+            unsafe { sys::ass_library_done(library) };
+            return Err(AssError::Init);
+        }
+        unsafe { sys::ass_set_frame_size(renderer, width as c_int, height as c_int) };
+        return Ok(Self {
+            library,
+            renderer,
+            header: header.to_vec(),
+            width,
+            height,
+            fonts_changed: true,
+        });
+    }
+
+    /// Makes a font available to the script, such as one attached to the MKV
+    pub fn add_font(&mut self, name: &str, data: &[u8]) {
+        let name = CString::new(name.replace('\0', "")).unwrap_or_default();
+        // SAFETY: libass copies the name and data
+        unsafe {
+            sys::ass_add_font(
+                self.library,
+                name.as_ptr(),
+                data.as_ptr() as *const c_char,
+                data.len() as c_int,
+            );
+        }
+        self.fonts_changed = true;
+    }
+
+    /// Width and height of the frames rendered
+    pub fn frame_size(&self) -> (u32, u32) {
+        return (self.width, self.height);
+    }
+
+    /// Renders a single event (a Matroska block: `ReadOrder,Layer,Style,...`)
+    /// shown at `start` for `duration`, as it looks at `timestamp`, all in
+    /// nanoseconds. It's drawn on its own, without any others shown at the
+    /// same time. `None` if nothing of it is visible then.
+    pub fn render_event(
+        &mut self,
+        data: &[u8],
+        start: u64,
+        duration: u64,
+        timestamp: u64,
+    ) -> Option<RgbaImage> {
+        if self.fonts_changed {
+            let family = c"sans-serif";
+            // SAFETY: the renderer is valid, and the strings are only read
+            unsafe {
+                sys::ass_set_fonts(
+                    self.renderer,
+                    ptr::null(),
+                    family.as_ptr(),
+                    sys::ASS_FONTPROVIDER_AUTODETECT,
+                    ptr::null(),
+                    1,
+                );
+            }
+            self.fonts_changed = false;
+        }
+        let ms = |ns: u64| (ns / 1_000_000) as c_longlong;
+        let mut frame = RgbaImage::new(self.width, self.height);
+        let mut visible = false;
+        // SAFETY: the track is freed before returning, and the images it
+        // renders are only read until then
+        unsafe {
+            let track = sys::ass_new_track(self.library);
+            if track.is_null() {
+                return None;
+            }
+            sys::ass_process_codec_private(
+                track,
+                self.header.as_ptr() as *const c_char,
+                self.header.len() as c_int,
+            );
+            sys::ass_process_chunk(
+                track,
+                data.as_ptr() as *const c_char,
+                data.len() as c_int,
+                ms(start),
+                ms(duration),
+            );
+            let mut image =
+                sys::ass_render_frame(self.renderer, track, ms(timestamp), ptr::null_mut());
+            while let Some(layer) = image.as_ref() {
+                visible |= draw_layer(&mut frame, layer);
+                image = layer.next;
+            }
+            sys::ass_free_track(track);
+        }
+        return visible.then_some(frame);
+    }
+}
+impl Drop for AssRenderer {
+    fn drop(&mut self) {
+        // SAFETY: both were created in `new` and aren't used after this
+        unsafe {
+            sys::ass_renderer_done(self.renderer);
+            sys::ass_library_done(self.library);
+        }
+    }
+}
+
+/// Blends one layer of a rendered frame over `frame`. Returns whether any of
+/// it is visible.
+///
+/// # Safety
+///
+/// `layer.bitmap` has to point to `layer.h` rows of `layer.stride` bytes
+unsafe fn draw_layer(frame: &mut RgbaImage, layer: &sys::AssImage) -> bool {
+    let [r, g, b, transparency] = layer.color.to_be_bytes();
+    let opacity = 255 - transparency as u32;
+    let mut visible = false;
+    for y in 0..layer.h.max(0) {
+        // SAFETY: within the bitmap, as promised by the caller
+        let row = unsafe {
+            std::slice::from_raw_parts(
+                layer.bitmap.add((y * layer.stride) as usize),
+                layer.w.max(0) as usize,
+            )
+        };
+        for (x, coverage) in row.iter().enumerate() {
+            let (frame_x, frame_y) = (layer.dst_x + x as c_int, layer.dst_y + y);
+            if frame_x < 0
+                || frame_y < 0
+                || frame_x as u32 >= frame.width()
+                || frame_y as u32 >= frame.height()
+            {
+                continue;
+            }
+            let alpha = *coverage as u32 * opacity / 255;
+            if alpha == 0 {
+                continue;
+            }
+            visible = true;
+            let under = frame.get_pixel_mut(frame_x as u32, frame_y as u32);
+            let under_alpha = under.0[3] as u32 * (255 - alpha) / 255;
+            let out_alpha = alpha + under_alpha;
+            let blend = |over: u8, under: u8| {
+                ((over as u32 * alpha + under as u32 * under_alpha) / out_alpha) as u8
+            };
+            *under = Rgba([
+                blend(r, under.0[0]),
+                blend(g, under.0[1]),
+                blend(b, under.0[2]),
+                out_alpha as u8,
+            ]);
+        }
+    }
+    return visible;
+}
+
+/// The `PlayResX`/`PlayResY` a script's header lays it out for, if it gives both
+pub fn play_res(header: &[u8]) -> Option<(u32, u32)> {
+    let header = String::from_utf8_lossy(header);
+    let value = |key: &str| {
+        return header.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            if !name.trim().eq_ignore_ascii_case(key) {
+                return None;
+            }
+            return value.trim().parse::<u32>().ok().filter(|value| *value > 0);
+        });
+    };
+    return Some((value("PlayResX")?, value("PlayResY")?));
+}
@@ -0,0 +1,69 @@
+//! Files attached to an MKV, typically fonts for ASS tracks, but some muxers
+//! also attach the `.idx` of a VobSub track instead of (or besides) putting
+//! it in `CodecPrivate`. `matroska_demuxer` doesn't read attachments, so
+//! they're read with [`crate::ebml`].
+
+use std::io::{self, Read, Seek};
+
+use crate::ebml::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub uid: u64,
+    pub name: String,
+    /// e.g. `font/ttf`
+    pub media_type: String,
+    pub description: Option<String>,
+    pub data: Vec<u8>,
+}
+impl Attachment {
+    /// Whether this looks like VobSub idx data
+    pub fn is_idx(&self) -> bool {
+        return self.name.to_lowercase().ends_with(".idx");
+    }
+
+    /// Whether this looks like a font, going by its media type or name
+    pub fn is_font(&self) -> bool {
+        let name = self.name.to_lowercase();
+        // font/ttf, application/x-truetype-font, application/vnd.ms-opentype...
+        return self.media_type.contains("font")
+            || self.media_type.contains("opentype")
+            || [".ttf", ".otf", ".ttc"]
+                .iter()
+                .any(|extension| name.ends_with(extension));
+    }
+}
+
+/// Reads every attachment of a Matroska file, data included
+pub fn read_attachments<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<Attachment>> {
+    let mut attachments = Vec::new();
+    for (_, data) in read_top_level_elements(reader, &[ID_ATTACHMENTS])? {
+        for child in ChildIter::new(&data) {
+            let (id, _, file) = child?;
+            if id != ID_ATTACHED_FILE {
+                continue;
+            }
+            let mut attachment = Attachment {
+                uid: 0,
+                name: String::new(),
+                media_type: String::new(),
+                description: None,
+                data: Vec::new(),
+            };
+            let string = |value: &[u8]| String::from_utf8_lossy(value).into_owned();
+            for field in ChildIter::new(file) {
+                let (id, _, value) = field?;
+                match id {
+                    ID_FILE_UID => attachment.uid = parse_uint(value),
+                    ID_FILE_NAME => attachment.name = string(value),
+                    ID_FILE_MEDIA_TYPE => attachment.media_type = string(value),
+                    ID_FILE_DESCRIPTION => attachment.description = Some(string(value)),
+                    ID_FILE_DATA => attachment.data = value.to_vec(),
+                    _ => {}
+                }
+            }
+            attachments.push(attachment);
+        }
+    }
+    return Ok(attachments);
+}
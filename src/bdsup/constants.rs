@@ -3,3 +3,8 @@ pub const PGS_SEGMENT_TYPE_ODS: u8 = 0x15;
 pub const PGS_SEGMENT_TYPE_PCS: u8 = 0x16;
 pub const PGS_SEGMENT_TYPE_WDS: u8 = 0x17;
 pub const PGS_SEGMENT_TYPE_END: u8 = 0x80;
+
+/// Largest video width or height accepted, with room above UHD Blu-ray's
+/// 3840x2160. Bigger sizes only come from corrupted data, and would allocate
+/// a screen of up to 8 GB.
+pub const PGS_MAX_VIDEO_SIZE: u16 = 4096;
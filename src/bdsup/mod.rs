@@ -1,30 +1,42 @@
 //! This implements a PGS parser for the S_HDMV/PGS subtitle format.
 //! It is intended to be used for parsing data from MKV files, though
-//! it could be adapted to support other containers, or raw SUP files
-//! as well.
+//! it could be adapted to support other containers. Raw SUP files are
+//! split into display sets by [`sup::SupReader`].
 //!
 //! This code was implemented from the format described here:
 //! https://blog.thescorpius.com/index.php/2017/07/15/presentation-graphic-stream-sup-files-bluray-subtitle-format/
 
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap};
 
 use constants::{
-    PGS_SEGMENT_TYPE_END, PGS_SEGMENT_TYPE_ODS, PGS_SEGMENT_TYPE_PCS, PGS_SEGMENT_TYPE_PDS,
-    PGS_SEGMENT_TYPE_WDS,
+    PGS_MAX_VIDEO_SIZE, PGS_SEGMENT_TYPE_END, PGS_SEGMENT_TYPE_ODS, PGS_SEGMENT_TYPE_PCS,
+    PGS_SEGMENT_TYPE_PDS, PGS_SEGMENT_TYPE_WDS,
 };
-use image::LumaA;
+use image::{LumaA, Rgba};
+#[cfg(feature = "demux-mkv")]
 use matroska_demuxer::Frame;
 use pgs_types::{
-    CompositionObject, CompositionState, LastInSequence, ObjectDefinition, PaletteDefinition,
-    PaletteEntry, PgsDisplaySet, PresentationComposition, SingleWindowDefinition,
+    CompositionObject, CompositionState, LastInSequence, ObjectDefinition, ObjectFragment,
+    PgsDisplaySet, PresentationComposition, SingleWindowDefinition,
 };
+pub use pgs_types::{PaletteDefinition, PaletteEntry};
+use pool::DisplaySetPool;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use window_adapter::ImageWindow;
 
-use crate::binary_reader::PacketReader;
+use crate::{
+    binary_reader::PacketReader,
+    color::{self, ColorMatrix},
+    indexed::IndexedImage,
+    preprocess::Region,
+};
 
 mod constants;
 mod pgs_types;
+mod pool;
+pub mod reference;
+pub mod sup;
 mod window_adapter;
 
 #[derive(Error, Debug)]
@@ -58,11 +70,13 @@ pub enum PgsError {
     FormatError,
 }
 
+/// `color` maps palette entries to what's drawn for them, returning `None` for
+/// entries the palette doesn't define
 fn render_into_image<'a>(
     image: &mut ImageWindow<'a>,
     palette_id: u8,
     composition_number: u16,
-    palette: &HashMap<u8, image::LumaA<u8>>,
+    color: &impl Fn(u8) -> Option<LumaA<u8>>,
     data: &[u8],
 ) -> Result<(), PgsError> {
     let mut data = PacketReader::new(data);
@@ -80,249 +94,891 @@ fn render_into_image<'a>(
                     0b00000000 => {
                         // L pixels in color 0 (1-byte)
                         let l = follower_value;
-                        for _ in 0..l {
-                            image.push_pixel(image::LumaA([0, 0]));
-                        }
+                        image.push_run(image::LumaA([0, 0]), l as u32);
                     }
                     0b01000000 => {
                         // L pixels in color 0 (2-byte)
                         let l_cont = data.read_u8().ok_or(PgsError::RleFormatError)?;
                         let l = u16::from_be_bytes([follower_value, l_cont]);
-                        for _ in 0..l {
-                            image.push_pixel(image::LumaA([0, 0]));
-                        }
+                        image.push_run(image::LumaA([0, 0]), l as u32);
                     }
                     0b10000000 => {
                         // L pixels in color C (L: 1-byte, C: 1-byte)
                         let l = follower_value;
                         let c = data.read_u8().ok_or(PgsError::RleFormatError)?;
-                        let color = palette.get(&c).ok_or(PgsError::MissingColor {
+                        let color = color(c).ok_or(PgsError::MissingColor {
                             color_id: c,
                             palette_id,
                             composition_number,
                         })?;
-                        for _ in 0..l {
-                            image.push_pixel(color.clone());
-                        }
+                        image.push_run(color, l as u32);
                     }
                     0b11000000 => {
                         // L pixels in color C (L: 2-byte, C: 1-byte)
                         let l_cont = data.read_u8().ok_or(PgsError::RleFormatError)?;
                         let l = u16::from_be_bytes([follower_value, l_cont]);
                         let c = data.read_u8().ok_or(PgsError::RleFormatError)?;
-                        let color = palette.get(&c).ok_or(PgsError::MissingColor {
+                        let color = color(c).ok_or(PgsError::MissingColor {
                             color_id: c,
                             palette_id,
                             composition_number,
                         })?;
-                        for _ in 0..l {
-                            image.push_pixel(color.clone());
-                        }
+                        image.push_run(color, l as u32);
                     }
                     _ => unreachable!(),
                 }
             }
             c => {
                 // One pixel in color
-                let color = palette.get(&c).ok_or(PgsError::MissingColor {
+                let color = color(c).ok_or(PgsError::MissingColor {
                     color_id: c,
                     palette_id,
                     composition_number,
                 })?;
-                image.push_pixel(color.clone());
+                image.push_pixel(color);
             }
         }
     }
     return Ok(());
 }
 
+/// A [`PgsParser`]'s decoding state between two display sets: the running
+/// composition and the windows, palettes and objects of the current epoch.
+/// Restoring it carries on decoding from that point without going back to
+/// the epoch's start. It holds a copy of every cached object, so it's about
+/// as big as the bitmaps the epoch has defined so far.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PgsSnapshot {
+    running_pcs: Option<PresentationComposition>,
+    windows: Vec<SingleWindowDefinition>,
+    /// With the version of each
+    palettes: Vec<PaletteDefinition>,
+    objects: Vec<ObjectSnapshot>,
+    generation: u32,
+    synced: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ObjectSnapshot {
+    id: u16,
+    version: u8,
+    /// [`LastInSequence`] bits; incomplete objects are still being reassembled
+    sequence: u8,
+    width: u16,
+    height: u16,
+    rle_data: Vec<u8>,
+    last_referenced: u32,
+}
+
+/// Objects are borrowed from the display sets they're defined in where
+/// possible, so `'a` is the lifetime of that data. Use
+/// [`Self::process_reused_into`] (and a `PgsParser<'static>`) to feed it from
+/// a buffer that's overwritten with every frame instead.
 #[derive(Default)]
-pub struct PgsParser {
+pub struct PgsParser<'a> {
     running_pcs: Option<PresentationComposition>,
     window_table: HashMap<u8, SingleWindowDefinition>,
     /// palette_id -> color_id -> color
-    palette_table: HashMap<u8, HashMap<u8, LumaA<u8>>>,
-    object_table: HashMap<u16, ObjectDefinition>,
+    palette_table: HashMap<u8, HashMap<u8, PaletteEntry>>,
+    /// palette_id -> version of the palette in `palette_table`
+    palette_versions: HashMap<u8, u8>,
+    object_table: HashMap<u16, ObjectDefinition<'a>>,
+    /// Number of acquisition points seen in the current epoch, used to age
+    /// out objects that are no longer referenced
+    generation: u32,
+    stale_updates: usize,
+    /// Skip compositions referencing data we haven't seen instead of erroring
+    recovery: bool,
+    /// Whether an EpochStart or AcquisitionPoint has been seen, meaning the
+    /// cache holds everything compositions can reference
+    synced: bool,
+    /// Whether the last display set was an EpochStart or AcquisitionPoint
+    /// that could be decoded on its own
+    refreshed: bool,
+    skipped: usize,
+    padded_segments: usize,
+    color_matrix: ColorMatrix,
+    pool: DisplaySetPool,
+    /// The last composition rendered, so the next one only has to redraw
+    /// what changed
+    canvas: image::GrayAlphaImage,
+    /// Objects drawn on `canvas`, with the area each could have drawn over
+    drawn: Vec<(CompositionObject, Region)>,
+    /// Scratch space for the next `drawn`
+    drawing: Vec<(CompositionObject, Region)>,
+    /// Scratch space for which of `drawing` to draw
+    redraw: Vec<bool>,
+    /// `None` when `canvas` can't be updated and has to be redrawn
+    drawn_palette: Option<u8>,
+    /// What was updated since `canvas` was drawn
+    changes: Changes,
 }
-impl PgsParser {
+
+#[derive(Default)]
+struct Changes {
+    everything: bool,
+    palettes: Vec<u8>,
+    objects: Vec<u16>,
+}
+impl Changes {
+    fn clear(&mut self) {
+        self.everything = false;
+        self.palettes.clear();
+        self.objects.clear();
+    }
+}
+impl<'a> PgsParser<'a> {
     pub fn new() -> Self {
         return PgsParser::default();
     }
 
+    /// Creates a parser for a stream that doesn't start at the beginning of an
+    /// epoch (e.g. after seeking). Compositions are skipped until the next
+    /// EpochStart or AcquisitionPoint, and whenever they reference a palette,
+    /// object or window that was never defined, rather than returning an error.
+    pub fn with_recovery() -> Self {
+        return PgsParser {
+            recovery: true,
+            ..Default::default()
+        };
+    }
+
+    /// Where each object of the current composition is placed, in the order
+    /// the composition lists them. Separate objects are typically separate
+    /// pieces of text, like a sign at the top and dialogue at the bottom.
+    pub fn regions(&self) -> Vec<Region> {
+        let Some(ref pcs) = self.running_pcs else {
+            return Vec::new();
+        };
+        return pcs
+            .composition_objects
+            .iter()
+            .filter_map(|object| {
+                let window = self.window_table.get(&object.window_id)?;
+                let (width, height) = match object.object_cropped_flag {
+                    true => (object.object_cropping_width, object.object_cropping_height),
+                    false => {
+                        let definition = self.object_table.get(&object.object_id)?;
+                        (definition.width, definition.height)
+                    }
+                };
+                return Some(Region {
+                    x: window.horizontal_pos as u32 + object.object_horizontal_pos as u32,
+                    y: window.vertical_pos as u32 + object.object_vertical_pos as u32,
+                    width: width as u32,
+                    height: height as u32,
+                });
+            })
+            .collect();
+    }
+
+    /// Number of palette or object definitions that were ignored because they
+    /// had an older version than the one already cached
+    pub fn stale_updates(&self) -> usize {
+        return self.stale_updates;
+    }
+
+    /// Number of compositions with visible content that recovery mode skipped
+    pub fn skipped_compositions(&self) -> usize {
+        return self.skipped;
+    }
+
+    /// Number of segments that declared more bytes than their fields use,
+    /// whose extra bytes were ignored. Some authoring tools pad segments, but
+    /// it can also mean a field was misread.
+    pub fn padded_segments(&self) -> usize {
+        return self.padded_segments;
+    }
+
+    /// How [`Self::render_indexed`] converts palettes to RGB
+    pub fn set_color_matrix(&mut self, matrix: ColorMatrix) {
+        self.color_matrix = matrix;
+    }
+
+    pub fn color_matrix(&self) -> ColorMatrix {
+        return self.color_matrix;
+    }
+
+    /// Whether the last display set processed was an epoch start or
+    /// acquisition point, so decoding could have started from it
+    pub fn refreshed(&self) -> bool {
+        return self.refreshed;
+    }
+
+    /// Copies the decoding state, to come back to with [`Self::restore`].
+    /// Settings and the counters of stale, skipped and padded data aren't
+    /// included.
+    pub fn snapshot(&self) -> PgsSnapshot {
+        let mut windows: Vec<SingleWindowDefinition> =
+            self.window_table.values().cloned().collect();
+        windows.sort_by_key(|window| window.window_id);
+        let mut palettes: Vec<PaletteDefinition> = self
+            .palette_table
+            .iter()
+            .map(|(id, entries)| {
+                let mut entries: Vec<PaletteEntry> = entries.values().cloned().collect();
+                entries.sort_by_key(|entry| entry.palette_entry_id);
+                return PaletteDefinition {
+                    palette_id: *id,
+                    palette_version: self.palette_versions.get(id).copied().unwrap_or(0),
+                    entries,
+                };
+            })
+            .collect();
+        palettes.sort_by_key(|palette| palette.palette_id);
+        let mut objects: Vec<ObjectSnapshot> = self
+            .object_table
+            .values()
+            .map(|object| ObjectSnapshot {
+                id: object.object_id,
+                version: object.object_version,
+                sequence: object.last_in_sequence.bits(),
+                width: object.width,
+                height: object.height,
+                rle_data: object.rle_data.to_vec(),
+                last_referenced: object.last_referenced,
+            })
+            .collect();
+        objects.sort_by_key(|object| object.id);
+        return PgsSnapshot {
+            running_pcs: self.running_pcs.clone(),
+            windows,
+            palettes,
+            objects,
+            generation: self.generation,
+            synced: self.synced,
+        };
+    }
+
+    /// Puts the parser back in the state `snapshot` was taken in, so the
+    /// display set that followed it can be processed next. The next
+    /// composition is drawn from scratch.
+    pub fn restore(&mut self, snapshot: &PgsSnapshot) {
+        self.window_table.clear();
+        for (_, palette) in self.palette_table.drain() {
+            self.pool.put_palette_map(palette);
+        }
+        self.palette_versions.clear();
+        for (_, object) in self.object_table.drain() {
+            self.pool.put_rle_data(object.rle_data);
+        }
+
+        self.running_pcs = snapshot.running_pcs.clone();
+        for window in &snapshot.windows {
+            self.window_table.insert(window.window_id, window.clone());
+        }
+        for palette in &snapshot.palettes {
+            self.palette_versions
+                .insert(palette.palette_id, palette.palette_version);
+            let mut entries = self.pool.palette_map();
+            for entry in &palette.entries {
+                entries.insert(entry.palette_entry_id, entry.clone());
+            }
+            self.palette_table.insert(palette.palette_id, entries);
+        }
+        for object in &snapshot.objects {
+            let mut rle_data = Cow::Borrowed(&[][..]);
+            let buffer = self.pool.own(&mut rle_data);
+            buffer.clear();
+            buffer.extend_from_slice(&object.rle_data);
+            self.object_table.insert(
+                object.id,
+                ObjectDefinition {
+                    object_id: object.id,
+                    object_version: object.version,
+                    last_in_sequence: LastInSequence::from_bits_truncate(object.sequence),
+                    width: object.width,
+                    height: object.height,
+                    rle_data,
+                    last_referenced: object.last_referenced,
+                },
+            );
+        }
+        self.generation = snapshot.generation;
+        self.synced = snapshot.synced;
+        self.refreshed = false;
+
+        self.drawn.clear();
+        self.drawn_palette = None;
+        self.changes.clear();
+        self.changes.everything = true;
+    }
+
     /// NOTE: This assumes frame times have already been scaled
+    #[cfg(feature = "demux-mkv")]
     pub fn process_mkv_frame(
         &mut self,
-        frame: &Frame,
+        frame: &'a Frame,
     ) -> Result<Option<image::GrayAlphaImage>, PgsError> {
-        // Parse display set
-        let mut data = PacketReader::new(&frame.data);
-        let display_set = read_display_set(&mut data)?;
+        return self.process_display_set(&frame.data);
+    }
+
+    /// Same as [`PgsParser::process_display_set_into`], for MKV blocks
+    ///
+    /// NOTE: This assumes frame times have already been scaled
+    #[cfg(feature = "demux-mkv")]
+    pub fn process_mkv_frame_into(
+        &mut self,
+        frame: &'a Frame,
+        image: &mut image::GrayAlphaImage,
+    ) -> Result<bool, PgsError> {
+        return self.process_display_set_into(&frame.data, image);
+    }
+
+    /// Processes one display set (PCS through END), returning the rendered
+    /// composition if it shows anything
+    pub fn process_display_set(
+        &mut self,
+        data: &'a [u8],
+    ) -> Result<Option<image::GrayAlphaImage>, PgsError> {
+        let mut image = image::GrayAlphaImage::new(0, 0);
+        if self.process_display_set_into(data, &mut image)? {
+            return Ok(Some(image));
+        }
+        return Ok(None);
+    }
+
+    /// Same as [`PgsParser::process_display_set`], but renders into a caller-provided
+    /// buffer so it can be reused between frames. The buffer is only reallocated
+    /// when the composition size changes. Returns `false` if nothing was rendered,
+    /// in which case the buffer's contents are unspecified.
+    pub fn process_display_set_into(
+        &mut self,
+        data: &'a [u8],
+        image: &mut image::GrayAlphaImage,
+    ) -> Result<bool, PgsError> {
+        let display_set = read_display_set_into(&mut PacketReader::new(data), &mut self.pool)?;
+        return self.apply_display_set(display_set, image, |rle_data, fragment, pool| {
+            pool.put_rle_data(std::mem::replace(rle_data, Cow::Borrowed(fragment)));
+        });
+    }
+
+    /// Same as [`PgsParser::process_display_set_into`], for data that's
+    /// overwritten after the call, like the frame buffer
+    /// `MatroskaFile::next_frame` reuses. Objects are copied into the parser,
+    /// reusing the buffer of the version they replace.
+    pub fn process_reused_into(
+        &mut self,
+        data: &[u8],
+        image: &mut image::GrayAlphaImage,
+    ) -> Result<bool, PgsError> {
+        let display_set = read_display_set_into(&mut PacketReader::new(data), &mut self.pool)?;
+        return self.apply_display_set(display_set, image, |rle_data, fragment, pool| {
+            let rle_data = pool.own(rle_data);
+            rle_data.clear();
+            rle_data.extend_from_slice(fragment);
+        });
+    }
+
+    /// Updates the cache from `display_set` and renders the result. `store`
+    /// sets an object's RLE data from its first fragment.
+    fn apply_display_set<'b>(
+        &mut self,
+        mut display_set: PgsDisplaySet<'b>,
+        image: &mut image::GrayAlphaImage,
+        store: impl Fn(&mut Cow<'a, [u8]>, &'b [u8], &mut DisplaySetPool),
+    ) -> Result<bool, PgsError> {
+        self.padded_segments += display_set.padded_segments;
+        self.refreshed = display_set.pcs.composition_state != CompositionState::Normal;
+        if self.refreshed {
+            self.synced = true;
+        } else if self.recovery && !self.synced {
+            // Palettes and objects in this display set may be partial updates
+            // to an epoch we never saw, so they aren't worth caching either
+            if !display_set.pcs.composition_objects.is_empty() {
+                self.skipped += 1;
+            }
+            self.pool.recycle(display_set);
+            return Ok(false);
+        }
 
         // Clear cache if requested
-        if display_set.pcs.composition_state == CompositionState::EpochStart {
-            // New epoch. Clear cache
-            self.window_table.clear();
-            self.palette_table.clear();
-            self.object_table.clear();
+        match display_set.pcs.composition_state {
+            CompositionState::EpochStart => {
+                // New epoch. Clear cache
+                self.changes.everything = true;
+                self.window_table.clear();
+                for (_, palette) in self.palette_table.drain() {
+                    self.pool.put_palette_map(palette);
+                }
+                self.palette_versions.clear();
+                for (_, object) in self.object_table.drain() {
+                    self.pool.put_rle_data(object.rle_data);
+                }
+                self.generation = 0;
+            }
+            CompositionState::AcquisitionPoint => {
+                // Epochs can span a whole film, so drop objects nothing has
+                // used since the previous acquisition point
+                self.generation += 1;
+                let generation = self.generation;
+                let unused = self
+                    .object_table
+                    .extract_if(|_, object| object.last_referenced + 1 < generation);
+                for (_, object) in unused {
+                    self.pool.put_rle_data(object.rle_data);
+                }
+            }
+            CompositionState::Normal => {}
         }
 
         // Update cache with new data
-        for palette in display_set.pds {
-            let stored_palette = match self.palette_table.get_mut(&palette.palette_id) {
-                Some(palette) => palette,
-                None => {
-                    self.palette_table
-                        .insert(palette.palette_id, HashMap::new());
-                    // Unwrap here because we *just* added this entry
-                    self.palette_table.get_mut(&palette.palette_id).unwrap()
+        for palette in display_set.pds.iter_mut() {
+            if let Some(version) = self.palette_versions.get(&palette.palette_id)
+                && !is_newer_version(*version, palette.palette_version)
+            {
+                if palette.palette_version != *version {
+                    self.stale_updates += 1;
                 }
-            };
-            for entry in palette.entries {
-                stored_palette.insert(
-                    entry.palette_entry_id,
-                    LumaA([entry.luminance, entry.transparency]),
-                );
+                continue;
+            }
+            self.palette_versions
+                .insert(palette.palette_id, palette.palette_version);
+            self.changes.palettes.push(palette.palette_id);
+            let stored_palette = self
+                .palette_table
+                .entry(palette.palette_id)
+                .or_insert_with(|| self.pool.palette_map());
+            for entry in palette.entries.drain(..) {
+                stored_palette.insert(entry.palette_entry_id, entry);
             }
         }
-        for window in display_set.wds {
+        for window in display_set.wds.drain(..) {
             self.window_table.insert(window.window_id, window);
         }
-        for object in display_set.ods {
-            self.object_table.insert(object.object_id, object);
+        // Objects whose fragments in this display set are being ignored
+        let mut skipped_objects = Vec::new();
+        for fragment in display_set.ods.iter() {
+            if fragment
+                .last_in_sequence
+                .contains(LastInSequence::FIRST_IN_SEQUENCE)
+            {
+                if let Some(object) = self.object_table.get(&fragment.object_id)
+                    && object
+                        .last_in_sequence
+                        .contains(LastInSequence::LAST_IN_SEQUENCE)
+                    && !is_newer_version(object.object_version, fragment.object_version)
+                {
+                    // Either a repeat (acquisition points resend everything) or
+                    // an older version arriving late
+                    if fragment.object_version != object.object_version {
+                        self.stale_updates += 1;
+                    }
+                    skipped_objects.push(fragment.object_id);
+                    continue;
+                }
+                skipped_objects.retain(|id| *id != fragment.object_id);
+
+                let (width, height) = fragment.dimensions.unwrap_or_default();
+                // Reuse the previous version's buffer when an object is redefined
+                let object = self
+                    .object_table
+                    .entry(fragment.object_id)
+                    .or_insert_with(|| ObjectDefinition {
+                        object_id: fragment.object_id,
+                        object_version: fragment.object_version,
+                        last_in_sequence: fragment.last_in_sequence,
+                        width,
+                        height,
+                        rle_data: Cow::Borrowed(&[]),
+                        last_referenced: 0,
+                    });
+                object.object_version = fragment.object_version;
+                object.last_in_sequence = fragment.last_in_sequence;
+                object.width = width;
+                object.height = height;
+                store(&mut object.rle_data, fragment.rle_data, &mut self.pool);
+                object.last_referenced = self.generation;
+                self.changes.objects.push(fragment.object_id);
+            } else if skipped_objects.contains(&fragment.object_id) {
+                continue;
+            } else if let Some(object) = self.object_table.get_mut(&fragment.object_id) {
+                object.last_in_sequence = fragment.last_in_sequence;
+                // Reassembling takes a copy of the first fragment
+                self.pool
+                    .own(&mut object.rle_data)
+                    .extend_from_slice(fragment.rle_data);
+                self.changes.objects.push(fragment.object_id);
+            }
         }
 
-        // Update running PCS
-        match display_set.pcs.composition_state {
-            CompositionState::AcquisitionPoint => {
-                if let Some(ref mut running_pcs) = self.running_pcs {
-                    running_pcs.composition_number = display_set.pcs.composition_number;
-                    running_pcs
-                        .composition_objects
-                        .extend(display_set.pcs.composition_objects);
+        // Update running PCS. The one it replaces goes back to the pool with
+        // the rest of the display set.
+        match (&display_set.pcs.composition_state, &mut self.running_pcs) {
+            (CompositionState::AcquisitionPoint, Some(running_pcs)) => {
+                running_pcs.composition_number = display_set.pcs.composition_number;
+                running_pcs
+                    .composition_objects
+                    .append(&mut display_set.pcs.composition_objects);
+            }
+            (_, Some(running_pcs)) => std::mem::swap(running_pcs, &mut display_set.pcs),
+            // Joined mid-stream; an acquisition point is a complete refresh
+            (_, None) => self.running_pcs = Some(display_set.pcs.clone()),
+        }
+        self.pool.recycle(display_set);
+
+        if let Some(ref pcs) = self.running_pcs {
+            for composition_object in pcs.composition_objects.iter() {
+                if let Some(object) = self.object_table.get_mut(&composition_object.object_id) {
+                    object.last_referenced = self.generation;
                 }
             }
-            CompositionState::EpochStart | CompositionState::Normal => {
-                self.running_pcs = Some(display_set.pcs);
+        }
+
+        match self.render_changes(image) {
+            Err(
+                PgsError::MissingPalette { .. }
+                | PgsError::MissingObject { .. }
+                | PgsError::MissingWindow { .. },
+            ) if self.recovery => {
+                // Still missing data from an epoch we didn't see. Wait for the next one.
+                self.synced = false;
+                self.refreshed = false;
+                self.running_pcs = None;
+                self.skipped += 1;
+                return Ok(false);
             }
+            result => return result,
         }
+    }
 
-        // Render PCS
-        if let Some(ref pcs) = self.running_pcs {
-            let mut image = image::GrayAlphaImage::new(pcs.width as _, pcs.height as _);
-            let palette =
-                self.palette_table
-                    .get(&pcs.palette_id)
-                    .ok_or(PgsError::MissingPalette {
-                        palette_id: pcs.palette_id,
-                        composition_number: pcs.composition_number,
-                    })?;
-            for object in pcs.composition_objects.iter() {
-                let object_def =
-                    self.object_table
-                        .get(&object.object_id)
-                        .ok_or(PgsError::MissingObject {
-                            object_id: object.object_id,
-                            composition_number: pcs.composition_number,
-                        })?;
-                let window_def =
-                    self.window_table
-                        .get(&object.window_id)
-                        .ok_or(PgsError::MissingWindow {
-                            window_id: object.window_id,
-                            composition_number: pcs.composition_number,
-                        })?;
-                let mut image_window = if object.object_cropped_flag {
-                    ImageWindow::with_window_cropped(
-                        &mut image,
-                        window_def.horizontal_pos as u32 + object.object_horizontal_pos as u32,
-                        window_def.vertical_pos as u32 + object.object_vertical_pos as u32,
-                        object.object_cropping_width as u32,
-                        object.object_cropping_height as u32,
-                        object.object_cropping_horizontal_pos as u32,
-                        object.object_cropping_vertical_pos as u32,
-                    )
+    /// Renders the last composition shown by [`Self::process_display_set`]
+    /// again, keeping palette entry IDs instead of converting them to gray.
+    /// RLE runs of color 0 become entry 0, which is usually transparent.
+    pub fn render_indexed(&self) -> Result<Option<IndexedImage>, PgsError> {
+        let Some(ref pcs) = self.running_pcs else {
+            return Ok(None);
+        };
+        let mut image = image::GrayAlphaImage::new(0, 0);
+        if !self.render(&mut image, true)? {
+            return Ok(None);
+        }
+        let mut entries: Vec<&PaletteEntry> =
+            self.palette_table[&pcs.palette_id].values().collect();
+        entries.sort_by_key(|entry| entry.palette_entry_id);
+        let matrix = self.color_matrix.resolve(pcs.height);
+        let mut palette = vec![Rgba([0, 0, 0, 0]); 256];
+        for entry in entries.iter() {
+            palette[entry.palette_entry_id as usize] = color::to_rgba(entry, matrix);
+        }
+        let source_palette: Vec<String> = entries.iter().map(ToString::to_string).collect();
+        let mut indexed = IndexedImage::new(
+            image.width(),
+            image.height(),
+            palette,
+            source_palette.join(", "),
+        );
+        for (index, pixel) in indexed.indices.iter_mut().zip(image.pixels()) {
+            *index = pixel.0[0];
+        }
+        return Ok(Some(indexed));
+    }
+
+    /// Renders the running composition. With `indexed`, each pixel's luma is
+    /// the palette entry ID instead.
+    fn render(&self, image: &mut image::GrayAlphaImage, indexed: bool) -> Result<bool, PgsError> {
+        let Some(ref pcs) = self.running_pcs else {
+            return Ok(false);
+        };
+        if image.width() != pcs.width as u32 || image.height() != pcs.height as u32 {
+            *image = image::GrayAlphaImage::new(pcs.width as _, pcs.height as _);
+        } else {
+            let pixels: &mut [u8] = image;
+            pixels.fill(0);
+        }
+        self.draw(image, indexed, |_| true)?;
+        return Ok(true);
+    }
+
+    /// Brings `canvas` up to date with the running composition and copies it
+    /// into `image`. Within an epoch, only objects that changed since the
+    /// last composition are drawn again, along with anything overlapping
+    /// them. A new screen size or palette redraws everything.
+    fn render_changes(&mut self, image: &mut image::GrayAlphaImage) -> Result<bool, PgsError> {
+        let mut canvas = std::mem::take(&mut self.canvas);
+        let result = self.update_canvas(&mut canvas);
+        self.changes.clear();
+        match result {
+            Ok(true) => {
+                if image.dimensions() == canvas.dimensions() {
+                    let pixels: &mut [u8] = image;
+                    pixels.copy_from_slice(&canvas);
                 } else {
-                    ImageWindow::with_window(
-                        &mut image,
-                        window_def.horizontal_pos as u32 + object.object_horizontal_pos as u32,
-                        window_def.vertical_pos as u32 + object.object_vertical_pos as u32,
-                        window_def.width as u32,
-                        window_def.height as u32,
-                    )
+                    *image = canvas.clone();
+                }
+            }
+            Ok(false) => {}
+            Err(_) => self.drawn_palette = None,
+        }
+        self.canvas = canvas;
+        return result;
+    }
+
+    fn update_canvas(&mut self, canvas: &mut image::GrayAlphaImage) -> Result<bool, PgsError> {
+        let Some(ref pcs) = self.running_pcs else {
+            self.drawn_palette = None;
+            return Ok(false);
+        };
+        if !self.palette_table.contains_key(&pcs.palette_id) {
+            return Err(PgsError::MissingPalette {
+                palette_id: pcs.palette_id,
+                composition_number: pcs.composition_number,
+            });
+        }
+        let screen = Region {
+            x: 0,
+            y: 0,
+            width: pcs.width as u32,
+            height: pcs.height as u32,
+        };
+        let mut drawing = std::mem::take(&mut self.drawing);
+        drawing.clear();
+        for object in pcs.composition_objects.iter() {
+            let (_, window_def) = self.lookup(pcs, object)?;
+            let (width, height) = match object.object_cropped_flag {
+                true => (object.object_cropping_width, object.object_cropping_height),
+                false => (window_def.width, window_def.height),
+            };
+            let area = Region {
+                x: window_def.horizontal_pos as u32 + object.object_horizontal_pos as u32,
+                y: window_def.vertical_pos as u32 + object.object_vertical_pos as u32,
+                width: width as u32,
+                height: height as u32,
+            };
+            let area = area.intersect(&screen).unwrap_or(Region {
+                width: 0,
+                height: 0,
+                ..area
+            });
+            drawing.push((object.clone(), area));
+        }
+
+        let everything = self.changes.everything
+            || canvas.dimensions() != (screen.width, screen.height)
+            || self.drawn_palette != Some(pcs.palette_id)
+            || self.changes.palettes.contains(&pcs.palette_id);
+        // Acquisition points add to the running composition, so it can hold
+        // more objects than any one segment lists
+        let mut redraw = std::mem::take(&mut self.redraw);
+        redraw.clear();
+        redraw.resize(drawing.len(), everything);
+        if everything {
+            if canvas.dimensions() != (screen.width, screen.height) {
+                *canvas = image::GrayAlphaImage::new(screen.width, screen.height);
+            } else {
+                let pixels: &mut [u8] = canvas;
+                pixels.fill(0);
+            }
+        } else {
+            // Objects are compared by position in the composition, since
+            // later ones are drawn over earlier ones
+            let unchanged = |index: usize| {
+                return self.drawn.get(index) == drawing.get(index)
+                    && !self.changes.objects.contains(&drawing[index].0.object_id);
+            };
+            for (index, (_, area)) in self.drawn.iter().enumerate() {
+                if !unchanged(index) {
+                    clear_area(canvas, area);
+                }
+            }
+            for (index, (_, area)) in drawing.iter().enumerate() {
+                if !unchanged(index) {
+                    clear_area(canvas, area);
+                    redraw[index] = true;
+                }
+            }
+            // Unchanged objects on cleared areas are drawn again, and so is
+            // anything overlapping what's drawn again, to keep the stacking
+            // order
+            let cleared =
+                |area: &Region| {
+                    return self.drawn.iter().enumerate().any(|(index, (_, old))| {
+                        !unchanged(index) && old.intersect(area).is_some()
+                    });
                 };
-                render_into_image(
-                    &mut image_window,
-                    pcs.palette_id,
-                    pcs.composition_number,
-                    palette,
-                    &object_def.rle_data,
-                );
+            loop {
+                let mut grew = false;
+                for (index, (_, area)) in drawing.iter().enumerate() {
+                    if !redraw[index]
+                        && (cleared(area)
+                            || drawing.iter().enumerate().any(|(other, (_, redrawn))| {
+                                redraw[other] && redrawn.intersect(area).is_some()
+                            }))
+                    {
+                        redraw[index] = true;
+                        grew = true;
+                    }
+                }
+                if !grew {
+                    break;
+                }
             }
-            return Ok(Some(image));
         }
+        self.draw(canvas, false, |index| redraw[index])?;
+        self.redraw = redraw;
 
-        return Ok(None);
+        self.drawn_palette = Some(pcs.palette_id);
+        self.drawing = std::mem::replace(&mut self.drawn, drawing);
+        return Ok(true);
+    }
+
+    /// The cached object and window a composition object refers to
+    fn lookup(
+        &self,
+        pcs: &PresentationComposition,
+        object: &CompositionObject,
+    ) -> Result<(&ObjectDefinition<'a>, &SingleWindowDefinition), PgsError> {
+        let object_def =
+            self.object_table
+                .get(&object.object_id)
+                .ok_or(PgsError::MissingObject {
+                    object_id: object.object_id,
+                    composition_number: pcs.composition_number,
+                })?;
+        let window_def =
+            self.window_table
+                .get(&object.window_id)
+                .ok_or(PgsError::MissingWindow {
+                    window_id: object.window_id,
+                    composition_number: pcs.composition_number,
+                })?;
+        return Ok((object_def, window_def));
+    }
+
+    /// Draws the running composition's objects that `redraw` accepts (by
+    /// index) over `image`
+    fn draw(
+        &self,
+        image: &mut image::GrayAlphaImage,
+        indexed: bool,
+        redraw: impl Fn(usize) -> bool,
+    ) -> Result<(), PgsError> {
+        let Some(ref pcs) = self.running_pcs else {
+            return Ok(());
+        };
+        let palette = self
+            .palette_table
+            .get(&pcs.palette_id)
+            .ok_or(PgsError::MissingPalette {
+                palette_id: pcs.palette_id,
+                composition_number: pcs.composition_number,
+            })?;
+        for (index, object) in pcs.composition_objects.iter().enumerate() {
+            let (object_def, window_def) = self.lookup(pcs, object)?;
+            if !redraw(index) {
+                continue;
+            }
+            let mut image_window = if object.object_cropped_flag {
+                ImageWindow::with_window_cropped(
+                    image,
+                    window_def.horizontal_pos as u32 + object.object_horizontal_pos as u32,
+                    window_def.vertical_pos as u32 + object.object_vertical_pos as u32,
+                    object.object_cropping_width as u32,
+                    object.object_cropping_height as u32,
+                    object.object_cropping_horizontal_pos as u32,
+                    object.object_cropping_vertical_pos as u32,
+                )
+            } else {
+                ImageWindow::with_window(
+                    image,
+                    window_def.horizontal_pos as u32 + object.object_horizontal_pos as u32,
+                    window_def.vertical_pos as u32 + object.object_vertical_pos as u32,
+                    window_def.width as u32,
+                    window_def.height as u32,
+                )
+            };
+            let color = |c: u8| {
+                let entry = palette.get(&c)?;
+                return Some(match indexed {
+                    true => LumaA([c, 255]),
+                    false => LumaA([entry.luminance, entry.transparency]),
+                });
+            };
+            render_into_image(
+                &mut image_window,
+                pcs.palette_id,
+                pcs.composition_number,
+                &color,
+                &object_def.rle_data,
+            )?;
+        }
+        return Ok(());
+    }
+}
+
+/// Makes `area` of `image` transparent
+fn clear_area(image: &mut image::GrayAlphaImage, area: &Region) {
+    let stride = image.width() as usize * 2;
+    let pixels: &mut [u8] = image;
+    for y in area.y..area.y + area.height {
+        let row = y as usize * stride;
+        pixels[row + area.x as usize * 2..row + (area.x + area.width) as usize * 2].fill(0);
     }
 }
 
-fn read_display_set<'a>(data: &mut PacketReader<'a>) -> Result<PgsDisplaySet, PgsError> {
+/// Whether `new` is a later version than `current`. Versions are 8 bits and
+/// wrap around, so anything up to half the range ahead counts as newer.
+fn is_newer_version(current: u8, new: u8) -> bool {
+    return (new.wrapping_sub(current) as i8) > 0;
+}
+
+/// Returns the palette definitions of a display set as-is (YCrCb), without
+/// decoding anything else
+pub fn read_palettes(data: &[u8]) -> Result<Vec<PaletteDefinition>, PgsError> {
+    let mut data = PacketReader::new(data);
+    return Ok(read_display_set(&mut data)?.pds);
+}
+
+/// Whether a display set shows anything, without rendering it. Ones without
+/// composition objects just clear the screen.
+pub fn shows_objects(data: &[u8]) -> Result<bool, PgsError> {
+    let mut data = PacketReader::new(data);
+    return Ok(!read_display_set(&mut data)?
+        .pcs
+        .composition_objects
+        .is_empty());
+}
+
+/// Whether a display set is an epoch start or acquisition point, which
+/// decoding can start from, without rendering it
+pub fn refreshes(data: &[u8]) -> Result<bool, PgsError> {
+    let mut data = PacketReader::new(data);
+    return Ok(read_display_set(&mut data)?.pcs.composition_state != CompositionState::Normal);
+}
+
+fn read_display_set<'a>(data: &mut PacketReader<'a>) -> Result<PgsDisplaySet<'a>, PgsError> {
+    return read_display_set_into(data, &mut DisplaySetPool::default());
+}
+
+/// Same as [`read_display_set`], taking the display set's buffers from `pool`
+fn read_display_set_into<'a>(
+    data: &mut PacketReader<'a>,
+    pool: &mut DisplaySetPool,
+) -> Result<PgsDisplaySet<'a>, PgsError> {
     let mut pcs: Option<PresentationComposition> = None;
-    let mut wds: Vec<SingleWindowDefinition> = Vec::new();
-    let mut pds: Vec<PaletteDefinition> = Vec::new();
-    let mut ods: Vec<ObjectDefinition> = Vec::new();
-    let mut current_ods: Option<ObjectDefinition> = None;
+    let mut wds = pool.windows();
+    let mut pds = pool.palettes();
+    let mut ods = pool.fragments();
+    let mut padded_segments = 0;
     loop {
         let segment_type = data.read_u8().ok_or(PgsError::FormatError)?;
         let segment_size = data.read_u16().ok_or(PgsError::FormatError)?;
-
-        if data.get_remaining_bytes() < segment_size as usize {
-            panic!("Segment length is greater than data length");
-        }
-        let data = data
-            .take_bytes(segment_size as usize)
+        let mut segment = data
+            .sub_reader(segment_size as usize)
             .ok_or(PgsError::FormatError)?;
 
         match segment_type {
             PGS_SEGMENT_TYPE_PDS => {
-                pds.push(parse_pds(&data)?);
+                pds.push(parse_pds(&mut segment, pool.palette_entries())?);
             }
             PGS_SEGMENT_TYPE_ODS => {
-                let this_ods = parse_ods(&data)?;
-                if this_ods
-                    .last_in_sequence
-                    .contains(LastInSequence::FIRST_IN_SEQUENCE | LastInSequence::LAST_IN_SEQUENCE)
-                {
-                    if let Some(old_ods) = std::mem::take(&mut current_ods) {
-                        ods.push(old_ods);
-                    }
-                    ods.push(this_ods);
-                } else if this_ods
-                    .last_in_sequence
-                    .contains(LastInSequence::FIRST_IN_SEQUENCE)
-                {
-                    if let Some(old_ods) = std::mem::take(&mut current_ods) {
-                        ods.push(old_ods);
-                    }
-                    current_ods = Some(this_ods);
-                } else if this_ods
-                    .last_in_sequence
-                    .contains(LastInSequence::LAST_IN_SEQUENCE)
-                {
-                    if let Some(mut current_ods) = std::mem::take(&mut current_ods) {
-                        current_ods.rle_data.extend(this_ods.rle_data);
-                        ods.push(current_ods);
-                    }
-                } else {
-                    if let Some(ref mut current_ods) = current_ods {
-                        current_ods.rle_data.extend(this_ods.rle_data);
-                    }
-                }
+                ods.push(parse_ods(&mut segment)?);
             }
             PGS_SEGMENT_TYPE_PCS => {
-                pcs = Some(parse_pcs(&data)?);
+                pcs = Some(parse_pcs(&mut segment, pool.composition_objects())?);
             }
             PGS_SEGMENT_TYPE_WDS => {
-                wds.extend(parse_wds(&data)?);
+                parse_wds(&mut segment, &mut wds)?;
             }
             PGS_SEGMENT_TYPE_END => {
                 return Ok(PgsDisplaySet {
@@ -330,18 +986,23 @@ fn read_display_set<'a>(data: &mut PacketReader<'a>) -> Result<PgsDisplaySet, Pg
                     wds,
                     pds,
                     ods,
+                    padded_segments,
                 });
             }
-            _ => panic!("Invalid segment type"),
+            _ => return Err(PgsError::FormatError),
+        }
+        if segment.get_remaining_bytes() > 0 {
+            padded_segments += 1;
         }
     }
 }
 
-fn parse_pds(data: &[u8]) -> Result<PaletteDefinition, PgsError> {
-    let mut data = PacketReader::new(data);
+fn parse_pds(
+    data: &mut PacketReader,
+    mut entries: Vec<PaletteEntry>,
+) -> Result<PaletteDefinition, PgsError> {
     let palette_id = data.read_u8().ok_or(PgsError::FormatError)?;
     let palette_version = data.read_u8().ok_or(PgsError::FormatError)?;
-    let mut entries = Vec::new();
     while let Some(palette_entry_id) = data.read_u8() {
         entries.push(PaletteEntry {
             palette_entry_id,
@@ -357,53 +1018,53 @@ fn parse_pds(data: &[u8]) -> Result<PaletteDefinition, PgsError> {
         entries,
     });
 }
-fn parse_ods(data: &[u8]) -> Result<ObjectDefinition, PgsError> {
-    let mut data = PacketReader::new(data);
+fn parse_ods<'a>(data: &mut PacketReader<'a>) -> Result<ObjectFragment<'a>, PgsError> {
     let object_id = data.read_u16().ok_or(PgsError::FormatError)?;
     let object_version = data.read_u8().ok_or(PgsError::FormatError)?;
     let last_in_sequence_flag = data.read_u8().ok_or(PgsError::FormatError)?;
-    let object_data_length_buf = data.take_bytes(3).ok_or(PgsError::FormatError)?;
-    let object_data_length = u32::from_be_bytes([
-        0,
-        object_data_length_buf[0],
-        object_data_length_buf[1],
-        object_data_length_buf[2],
-    ])
-    .saturating_sub(4); // Subtract size of width & height
-    let width = data.read_u16().ok_or(PgsError::FormatError)?;
-    let height = data.read_u16().ok_or(PgsError::FormatError)?;
-    let rle_data = Vec::from(
-        data.take_bytes(object_data_length as usize)
-            .ok_or(PgsError::FormatError)?,
-    );
-    return Ok(ObjectDefinition {
+    let last_in_sequence =
+        LastInSequence::from_bits(last_in_sequence_flag).ok_or(PgsError::FormatError)?;
+
+    // Only the first fragment of an object carries its length and dimensions.
+    // Everything after that is RLE data until the end of the segment.
+    let dimensions = if last_in_sequence.contains(LastInSequence::FIRST_IN_SEQUENCE) {
+        let _object_data_length = data.read_u24().ok_or(PgsError::FormatError)?;
+        let width = data.read_u16().ok_or(PgsError::FormatError)?;
+        let height = data.read_u16().ok_or(PgsError::FormatError)?;
+        Some((width, height))
+    } else {
+        None
+    };
+    let rle_data = data.take_bytes(data.get_remaining_bytes()).unwrap();
+    return Ok(ObjectFragment {
         object_id,
         object_version,
-        last_in_sequence: LastInSequence::from_bits(last_in_sequence_flag)
-            .ok_or(PgsError::FormatError)?,
-        width,
-        height,
+        last_in_sequence,
+        dimensions,
         rle_data,
     });
 }
-fn parse_pcs(data: &[u8]) -> Result<PresentationComposition, PgsError> {
-    let mut data = PacketReader::new(data);
-
+fn parse_pcs(
+    data: &mut PacketReader,
+    mut composition_objects: Vec<CompositionObject>,
+) -> Result<PresentationComposition, PgsError> {
     let width = data.read_u16().ok_or(PgsError::FormatError)?;
     let height = data.read_u16().ok_or(PgsError::FormatError)?;
+    if width > PGS_MAX_VIDEO_SIZE || height > PGS_MAX_VIDEO_SIZE {
+        return Err(PgsError::FormatError);
+    }
     let frame_rate = data.read_u8().ok_or(PgsError::FormatError)?;
     let composition_number = data.read_u16().ok_or(PgsError::FormatError)?;
     let composition_state = match data.read_u8().ok_or(PgsError::FormatError)? {
         0x00 => CompositionState::Normal,
         0x40 => CompositionState::AcquisitionPoint,
         0x80 => CompositionState::EpochStart,
-        _ => panic!("Invalid composition state"),
+        _ => return Err(PgsError::FormatError),
     };
     let palette_update_flag = data.read_u8().ok_or(PgsError::FormatError)? > 0;
     let palette_id = data.read_u8().ok_or(PgsError::FormatError)?;
     let composition_object_len = data.read_u8().ok_or(PgsError::FormatError)?;
 
-    let mut composition_objects = Vec::new();
     for _ in 0..composition_object_len {
         let object_id = data.read_u16().ok_or(PgsError::FormatError)?;
         let window_id = data.read_u8().ok_or(PgsError::FormatError)?;
@@ -455,10 +1116,11 @@ fn parse_pcs(data: &[u8]) -> Result<PresentationComposition, PgsError> {
         composition_objects,
     });
 }
-fn parse_wds(data: &[u8]) -> Result<Vec<SingleWindowDefinition>, PgsError> {
-    let mut data = PacketReader::new(data);
+fn parse_wds(
+    data: &mut PacketReader,
+    windows: &mut Vec<SingleWindowDefinition>,
+) -> Result<(), PgsError> {
     let num_windows = data.read_u8().ok_or(PgsError::FormatError)?;
-    let mut windows = Vec::new();
     for _ in 0..num_windows {
         windows.push(SingleWindowDefinition {
             window_id: data.read_u8().ok_or(PgsError::FormatError)?,
@@ -468,5 +1130,5 @@ fn parse_wds(data: &[u8]) -> Result<Vec<SingleWindowDefinition>, PgsError> {
             height: data.read_u16().ok_or(PgsError::FormatError)?,
         });
     }
-    return Ok(windows);
+    return Ok(());
 }
@@ -1,6 +1,9 @@
+use std::{borrow::Cow, fmt};
+
 use bitflags::bitflags;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SingleWindowDefinition {
     pub window_id: u8,
     pub horizontal_pos: u16,
@@ -9,7 +12,7 @@ pub struct SingleWindowDefinition {
     pub height: u16,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PaletteEntry {
     pub palette_entry_id: u8,
     pub luminance: u8,
@@ -17,11 +20,26 @@ pub struct PaletteEntry {
     pub color_diff_blue: u8,
     pub transparency: u8,
 }
+/// `<id>=<YYCrCbAA>`, in hex
+impl fmt::Display for PaletteEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(
+            f,
+            "{:02x}={:02x}{:02x}{:02x}{:02x}",
+            self.palette_entry_id,
+            self.luminance,
+            self.color_diff_red,
+            self.color_diff_blue,
+            self.transparency,
+        );
+    }
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PresentationComposition {
     pub width: u16,
     pub height: u16,
+    /// See [`FrameRate::from_pgs_code`](crate::timebase::FrameRate::from_pgs_code)
     pub frame_rate: u8,
     pub composition_number: u16,
     pub composition_state: CompositionState,
@@ -31,16 +49,34 @@ pub struct PresentationComposition {
 }
 
 #[derive(Debug, Clone)]
-pub struct ObjectDefinition {
+pub struct ObjectDefinition<'a> {
     pub object_id: u16,
     pub object_version: u8,
     pub last_in_sequence: LastInSequence,
     pub width: u16,
     pub height: u16,
-    pub rle_data: Vec<u8>,
+    /// Borrowed from the display set when it holds the whole object in one
+    /// segment, and the data outlives the parser
+    pub rle_data: Cow<'a, [u8]>,
+    /// Parser generation (acquisition point count) this object was last
+    /// defined or displayed in
+    pub last_referenced: u32,
 }
 
+/// A single ODS segment, borrowing its RLE data from the frame it was read from.
+/// Objects larger than one segment arrive as several fragments, which are
+/// reassembled into an [`ObjectDefinition`] in the parser's object cache.
 #[derive(Debug, Clone)]
+pub struct ObjectFragment<'a> {
+    pub object_id: u16,
+    pub object_version: u8,
+    pub last_in_sequence: LastInSequence,
+    /// Only present on the first fragment of an object
+    pub dimensions: Option<(u16, u16)>,
+    pub rle_data: &'a [u8],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PaletteDefinition {
     pub palette_id: u8,
     pub palette_version: u8,
@@ -55,7 +91,7 @@ bitflags! {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CompositionObject {
     pub object_id: u16,
     pub window_id: u8,
@@ -68,7 +104,7 @@ pub struct CompositionObject {
     pub object_cropping_height: u16,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum CompositionState {
     Normal,
     AcquisitionPoint,
@@ -76,9 +112,12 @@ pub enum CompositionState {
 }
 
 #[derive(Debug, Clone)]
-pub struct PgsDisplaySet {
+pub struct PgsDisplaySet<'a> {
     pub pcs: PresentationComposition,
     pub wds: Vec<SingleWindowDefinition>,
     pub pds: Vec<PaletteDefinition>,
-    pub ods: Vec<ObjectDefinition>,
+    pub ods: Vec<ObjectFragment<'a>>,
+    /// Segments declaring more bytes than their fields use. The extra bytes
+    /// are ignored.
+    pub padded_segments: usize,
 }
@@ -0,0 +1,99 @@
+//! Buffers kept between display sets. Every composition needs the same
+//! handful of vectors and maps, so a parser hands them back here once it's
+//! done with them instead of allocating them again for the next frame.
+
+use std::{borrow::Cow, collections::HashMap};
+
+use super::pgs_types::{
+    CompositionObject, ObjectFragment, PaletteDefinition, PaletteEntry, PgsDisplaySet,
+    SingleWindowDefinition,
+};
+
+#[derive(Default)]
+pub struct DisplaySetPool {
+    windows: Vec<SingleWindowDefinition>,
+    palettes: Vec<PaletteDefinition>,
+    /// Always empty. Only the allocation is kept, since fragments borrow from
+    /// the display set they were read from.
+    fragments: Vec<ObjectFragment<'static>>,
+    palette_entries: Vec<Vec<PaletteEntry>>,
+    composition_objects: Vec<Vec<CompositionObject>>,
+    palette_maps: Vec<HashMap<u8, PaletteEntry>>,
+    rle_buffers: Vec<Vec<u8>>,
+}
+impl DisplaySetPool {
+    pub fn windows(&mut self) -> Vec<SingleWindowDefinition> {
+        return std::mem::take(&mut self.windows);
+    }
+
+    pub fn palettes(&mut self) -> Vec<PaletteDefinition> {
+        return std::mem::take(&mut self.palettes);
+    }
+
+    pub fn fragments<'a>(&mut self) -> Vec<ObjectFragment<'a>> {
+        return recycle(std::mem::take(&mut self.fragments));
+    }
+
+    pub fn palette_entries(&mut self) -> Vec<PaletteEntry> {
+        return self.palette_entries.pop().unwrap_or_default();
+    }
+
+    pub fn composition_objects(&mut self) -> Vec<CompositionObject> {
+        return self.composition_objects.pop().unwrap_or_default();
+    }
+
+    pub fn palette_map(&mut self) -> HashMap<u8, PaletteEntry> {
+        return self.palette_maps.pop().unwrap_or_default();
+    }
+
+    pub fn put_palette_entries(&mut self, mut entries: Vec<PaletteEntry>) {
+        entries.clear();
+        self.palette_entries.push(entries);
+    }
+
+    pub fn put_palette_map(&mut self, mut palette: HashMap<u8, PaletteEntry>) {
+        palette.clear();
+        self.palette_maps.push(palette);
+    }
+
+    /// Keeps `rle_data`'s buffer if it has one
+    pub fn put_rle_data(&mut self, rle_data: Cow<[u8]>) {
+        if let Cow::Owned(mut buffer) = rle_data {
+            buffer.clear();
+            self.rle_buffers.push(buffer);
+        }
+    }
+
+    /// Makes `rle_data` owned so it can be appended to, copying borrowed data
+    /// into a pooled buffer
+    pub fn own<'c>(&mut self, rle_data: &'c mut Cow<[u8]>) -> &'c mut Vec<u8> {
+        if let Cow::Borrowed(borrowed) = *rle_data {
+            let mut buffer = self.rle_buffers.pop().unwrap_or_default();
+            buffer.extend_from_slice(borrowed);
+            *rle_data = Cow::Owned(buffer);
+        }
+        return rle_data.to_mut();
+    }
+
+    /// Takes back everything in a display set that has been applied
+    pub fn recycle(&mut self, mut display_set: PgsDisplaySet) {
+        display_set.wds.clear();
+        self.windows = display_set.wds;
+        for palette in display_set.pds.drain(..) {
+            self.put_palette_entries(palette.entries);
+        }
+        self.palettes = display_set.pds;
+        self.fragments = recycle(display_set.ods);
+        display_set.pcs.composition_objects.clear();
+        self.composition_objects
+            .push(display_set.pcs.composition_objects);
+    }
+}
+
+/// Reuses the allocation of a vector of fragments for fragments of another
+/// lifetime. Collecting a vector's own iterator into a vector with the same
+/// layout happens in place.
+fn recycle<'a, 'b>(mut fragments: Vec<ObjectFragment<'a>>) -> Vec<ObjectFragment<'b>> {
+    fragments.clear();
+    return fragments.into_iter().map(|_| unreachable!()).collect();
+}
@@ -0,0 +1,199 @@
+//! A deliberately naive PGS renderer, used to check [`super::PgsParser`].
+//! It keeps no buffers between frames, ignores palette and object versions
+//! (the latest definition always wins), takes every composition as complete,
+//! and decodes each object into a bitmap of its declared size before placing
+//! it. Only segment parsing is shared, so a disagreement points at the
+//! parser's caching, RLE decoding or compositing.
+
+use std::collections::HashMap;
+
+use image::{GrayAlphaImage, LumaA};
+
+use super::{
+    PgsError,
+    pgs_types::{
+        CompositionState, LastInSequence, PaletteEntry, PresentationComposition,
+        SingleWindowDefinition,
+    },
+    read_display_set,
+};
+use crate::binary_reader::PacketReader;
+
+struct Object {
+    width: u16,
+    height: u16,
+    rle_data: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct ReferenceRenderer {
+    windows: HashMap<u8, SingleWindowDefinition>,
+    palettes: HashMap<u8, HashMap<u8, PaletteEntry>>,
+    objects: HashMap<u16, Object>,
+    composition: Option<PresentationComposition>,
+}
+impl ReferenceRenderer {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Same contract as [`super::PgsParser::process_display_set`]
+    pub fn process_display_set(&mut self, data: &[u8]) -> Result<Option<GrayAlphaImage>, PgsError> {
+        let display_set = read_display_set(&mut PacketReader::new(data))?;
+        if display_set.pcs.composition_state == CompositionState::EpochStart {
+            self.windows.clear();
+            self.palettes.clear();
+            self.objects.clear();
+        }
+        for palette in display_set.pds {
+            let entries = self.palettes.entry(palette.palette_id).or_default();
+            for entry in palette.entries {
+                entries.insert(entry.palette_entry_id, entry);
+            }
+        }
+        for window in display_set.wds {
+            self.windows.insert(window.window_id, window);
+        }
+        for fragment in display_set.ods {
+            if fragment
+                .last_in_sequence
+                .contains(LastInSequence::FIRST_IN_SEQUENCE)
+            {
+                let (width, height) = fragment.dimensions.unwrap_or_default();
+                self.objects.insert(
+                    fragment.object_id,
+                    Object {
+                        width,
+                        height,
+                        rle_data: fragment.rle_data.to_vec(),
+                    },
+                );
+            } else if let Some(object) = self.objects.get_mut(&fragment.object_id) {
+                object.rle_data.extend_from_slice(fragment.rle_data);
+            }
+        }
+        self.composition = Some(display_set.pcs);
+        return self.render();
+    }
+
+    fn render(&self) -> Result<Option<GrayAlphaImage>, PgsError> {
+        let Some(ref pcs) = self.composition else {
+            return Ok(None);
+        };
+        let mut image = GrayAlphaImage::new(pcs.width as u32, pcs.height as u32);
+        let palette = self
+            .palettes
+            .get(&pcs.palette_id)
+            .ok_or(PgsError::MissingPalette {
+                palette_id: pcs.palette_id,
+                composition_number: pcs.composition_number,
+            })?;
+        for reference in pcs.composition_objects.iter() {
+            let object = self
+                .objects
+                .get(&reference.object_id)
+                .ok_or(PgsError::MissingObject {
+                    object_id: reference.object_id,
+                    composition_number: pcs.composition_number,
+                })?;
+            let window = self
+                .windows
+                .get(&reference.window_id)
+                .ok_or(PgsError::MissingWindow {
+                    window_id: reference.window_id,
+                    composition_number: pcs.composition_number,
+                })?;
+            let indices = decode_rle(&object.rle_data, object.width, object.height)?;
+            let color = |index: u8| {
+                return palette
+                    .get(&index)
+                    .map(|entry| LumaA([entry.luminance, entry.transparency]))
+                    .ok_or(PgsError::MissingColor {
+                        color_id: index,
+                        palette_id: pcs.palette_id,
+                        composition_number: pcs.composition_number,
+                    });
+            };
+
+            // The part of the object that's shown, in object coordinates
+            let (left, top, width, height) = match reference.object_cropped_flag {
+                true => (
+                    reference.object_cropping_horizontal_pos as u32,
+                    reference.object_cropping_vertical_pos as u32,
+                    reference.object_cropping_width as u32,
+                    reference.object_cropping_height as u32,
+                ),
+                false => (0, 0, window.width as u32, window.height as u32),
+            };
+            let origin_x = window.horizontal_pos as u32 + reference.object_horizontal_pos as u32;
+            let origin_y = window.vertical_pos as u32 + reference.object_vertical_pos as u32;
+            for y in top..top + height {
+                for x in left..left + width {
+                    if x >= object.width as u32 || y >= object.height as u32 {
+                        continue;
+                    }
+                    let index = indices[y as usize * object.width as usize + x as usize];
+                    // Index 0 runs are transparent without consulting the palette
+                    let Some(index) = index else {
+                        continue;
+                    };
+                    let pixel = color(index)?;
+                    let (target_x, target_y) = (origin_x + x - left, origin_y + y - top);
+                    if pixel.0[1] != 0 && target_x < image.width() && target_y < image.height() {
+                        image.put_pixel(target_x, target_y, pixel);
+                    }
+                }
+            }
+        }
+        return Ok(Some(image));
+    }
+}
+
+/// Decodes an object into one palette index per pixel, row by row. `None`
+/// marks pixels of the color 0 runs, which don't reference the palette.
+/// Anything outside `width` by `height` is dropped.
+fn decode_rle(data: &[u8], width: u16, height: u16) -> Result<Vec<Option<u8>>, PgsError> {
+    let (width, height) = (width as usize, height as usize);
+    let mut pixels = vec![None; width * height];
+    let (mut x, mut y) = (0, 0);
+    let mut data = PacketReader::new(data);
+    while let Some(byte) = data.read_u8() {
+        let (index, length) = match byte {
+            0 => {
+                let flags = data.read_u8().ok_or(PgsError::RleFormatError)?;
+                let short = (flags & 0x3f) as usize;
+                let length = match flags & 0x40 {
+                    0 => short,
+                    _ => short << 8 | data.read_u8().ok_or(PgsError::RleFormatError)? as usize,
+                };
+                let index = match flags & 0x80 {
+                    0 => None,
+                    _ => Some(data.read_u8().ok_or(PgsError::RleFormatError)?),
+                };
+                if flags == 0 {
+                    x = 0;
+                    y += 1;
+                    continue;
+                }
+                (index, length)
+            }
+            index => (Some(index), 1),
+        };
+        for _ in 0..length {
+            if x < width && y < height {
+                pixels[y * width + x] = index;
+            }
+            x += 1;
+        }
+    }
+    return Ok(pixels);
+}
+
+/// How many pixels differ between two renderings, or `None` if they aren't
+/// the same size
+pub fn differing_pixels(a: &GrayAlphaImage, b: &GrayAlphaImage) -> Option<usize> {
+    if a.dimensions() != b.dimensions() {
+        return None;
+    }
+    return Some(a.pixels().zip(b.pixels()).filter(|(a, b)| a != b).count());
+}
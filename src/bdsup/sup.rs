@@ -0,0 +1,81 @@
+//! Raw `.sup` files, as BDSup2Sub and Blu-ray rippers write them: the same
+//! segments as in MKV blocks, each behind a `PG` marker and its timestamps.
+//! Read sequentially, so the file can come from a pipe.
+
+use std::io::{self, Read};
+
+use super::constants::PGS_SEGMENT_TYPE_END;
+use crate::timebase::pts_to_ns;
+
+const MAGIC: [u8; 2] = *b"PG";
+
+/// A display set as an MKV block would carry it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SupDisplaySet {
+    /// Nanoseconds, from the presentation timestamp of its first segment
+    pub timestamp: u64,
+    /// Its segments, without the `PG` headers, for
+    /// [`super::PgsParser::process_display_set`]
+    pub data: Vec<u8>,
+}
+
+/// Splits a `.sup` stream into display sets
+pub struct SupReader<R> {
+    reader: R,
+    done: bool,
+}
+impl<R: Read> SupReader<R> {
+    pub fn new(reader: R) -> Self {
+        return Self {
+            reader,
+            done: false,
+        };
+    }
+
+    fn next_display_set(&mut self) -> io::Result<Option<SupDisplaySet>> {
+        let mut timestamp = None;
+        let mut data = Vec::new();
+        loop {
+            let mut header = [0; 13];
+            match self.reader.read_exact(&mut header) {
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof && timestamp.is_none() => {
+                    return Ok(None);
+                }
+                result => result?,
+            }
+            if header[..2] != MAGIC {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Missing PG marker in the SUP stream",
+                ));
+            }
+            let pts = u32::from_be_bytes(header[2..6].try_into().unwrap());
+            timestamp.get_or_insert(pts_to_ns(pts as u64));
+            let size = u16::from_be_bytes([header[11], header[12]]);
+            data.extend_from_slice(&header[10..]);
+            let start = data.len();
+            (&mut self.reader)
+                .take(size as u64)
+                .read_to_end(&mut data)?;
+            if data.len() - start != size as usize {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if header[10] == PGS_SEGMENT_TYPE_END {
+                return Ok(timestamp.map(|timestamp| SupDisplaySet { timestamp, data }));
+            }
+        }
+    }
+}
+impl<R: Read> Iterator for SupReader<R> {
+    type Item = io::Result<SupDisplaySet>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_display_set();
+        // There's no finding the next segment after a bad one
+        self.done = !matches!(result, Ok(Some(_)));
+        return result.transpose();
+    }
+}
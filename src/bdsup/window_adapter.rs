@@ -78,8 +78,8 @@ impl<'a> ImageWindow<'a> {
             // which we don't really have control over, so just forget it.
             return;
         }
-        x = x + self.x;
-        y = y + self.y;
+        x = x.saturating_add(self.x);
+        y = y.saturating_add(self.y);
         if x >= self.image.width() || y >= self.image.height() {
             return;
         }
@@ -89,10 +89,52 @@ impl<'a> ImageWindow<'a> {
     }
     pub fn push_pixel(&mut self, pixel: image::LumaA<u8>) {
         self.put_pixel(self.x_cursor, self.y_cursor, pixel);
-        self.x_cursor += 1;
+        self.x_cursor = self.x_cursor.saturating_add(1);
+    }
+    /// Pushes `count` copies of `pixel`, filling the visible part of the run with
+    /// a single slice write rather than going through `put_pixel` per pixel.
+    /// Run lengths come from the stream, so a corrupt one saturates and gets
+    /// clipped to the window instead of overflowing.
+    pub fn push_run(&mut self, pixel: image::LumaA<u8>, count: u32) {
+        let mut x1 = self.x_cursor;
+        let mut x2 = self.x_cursor.saturating_add(count);
+        let mut y = self.y_cursor;
+        self.x_cursor = x2;
+        if pixel.0[1] == 0 || count == 0 {
+            // Transparent pixels are never written, see `put_pixel`
+            return;
+        }
+        if let Some((crop_x, crop_y)) = self.crop_origin {
+            if y < crop_y {
+                return;
+            }
+            y = y - crop_y;
+            x1 = x1.max(crop_x) - crop_x;
+            x2 = x2.saturating_sub(crop_x);
+        }
+        if y >= self.height {
+            return;
+        }
+        x1 = x1.min(self.width);
+        x2 = x2.min(self.width);
+        y = y.saturating_add(self.y);
+        let image_width = self.image.width();
+        if y >= self.image.height() {
+            return;
+        }
+        x1 = x1.saturating_add(self.x).min(image_width);
+        x2 = x2.saturating_add(self.x).min(image_width);
+        if x1 >= x2 {
+            return;
+        }
+        let row = (y as usize) * (image_width as usize);
+        let pixels: &mut [u8] = self.image;
+        for target in pixels[(row + x1 as usize) * 2..(row + x2 as usize) * 2].chunks_exact_mut(2) {
+            target.copy_from_slice(&pixel.0);
+        }
     }
     pub fn end_line(&mut self) {
         self.x_cursor = 0;
-        self.y_cursor += 1;
+        self.y_cursor = self.y_cursor.saturating_add(1);
     }
 }
@@ -0,0 +1,196 @@
+//! Tracks carrying two languages at once, as some discs do: each subtitle is
+//! made of separately placed objects, the lines of one language stacked
+//! above the other's, in the same layout throughout. Once the row between
+//! them is found, each language can be split off into a track of its own and
+//! recognized with its own OCR language.
+
+use image::{GrayAlphaImage, imageops};
+
+use crate::{
+    extract::{EventPayload, SubtitleEvent},
+    preprocess::{Placement, Region},
+};
+
+/// Share of image subtitles that must have a region on each side of the
+/// split for the track to count as dual-language
+pub const MIN_CONSISTENCY: f64 = 0.8;
+/// Fewer stacked subtitles than this could be signs or captions that happen
+/// to line up
+const MIN_STACKED: usize = 5;
+
+/// Where the two languages of a track are, as found by [`detect_layout`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DualLayout {
+    /// Screen row between the languages. Regions centered above it belong
+    /// to the upper language.
+    pub split: u32,
+    /// Image subtitles with a region on each side of `split`
+    pub stacked: usize,
+    /// Image subtitles looked at
+    pub images: usize,
+}
+impl DualLayout {
+    /// Share of image subtitles that follow the layout
+    pub fn consistency(&self) -> f64 {
+        if self.images == 0 {
+            return 0.0;
+        }
+        return self.stacked as f64 / self.images as f64;
+    }
+}
+
+/// Looks for two languages stacked in separate regions. The split is the
+/// median of the rows midway between the two regions of each two-region
+/// subtitle, and the layout is only reported if most image subtitles have a
+/// region on either side of it.
+pub fn detect_layout(events: &[SubtitleEvent]) -> Option<DualLayout> {
+    let mut images = 0;
+    let mut candidates = Vec::new();
+    for event in events {
+        if !matches!(event.payload, EventPayload::Image(_)) {
+            continue;
+        }
+        images += 1;
+        let Some(placement) = event.placement else {
+            continue;
+        };
+        let [mut upper, mut lower] = event.regions[..] else {
+            continue;
+        };
+        if upper.y > lower.y {
+            (upper, lower) = (lower, upper);
+        }
+        if upper.y + upper.height > lower.y {
+            continue;
+        }
+        candidates.push(placement.y + (upper.y + upper.height + lower.y) / 2);
+    }
+    if candidates.len() < MIN_STACKED {
+        return None;
+    }
+    candidates.sort_unstable();
+    let split = candidates[candidates.len() / 2];
+    let stacked = events
+        .iter()
+        .filter(|event| {
+            let sides = sides(event, split);
+            return sides.iter().any(|upper| *upper) && sides.iter().any(|upper| !*upper);
+        })
+        .count();
+    let layout = DualLayout {
+        split,
+        stacked,
+        images,
+    };
+    if layout.consistency() < MIN_CONSISTENCY {
+        return None;
+    }
+    return Some(layout);
+}
+
+/// Whether each region of an image event is centered above `split`. Empty
+/// for events without an image or placement.
+fn sides(event: &SubtitleEvent, split: u32) -> Vec<bool> {
+    let (EventPayload::Image(image), Some(placement)) = (&event.payload, event.placement) else {
+        return Vec::new();
+    };
+    return regions(event, image.width(), image.height())
+        .iter()
+        .map(|region| placement.y + region.y + region.height / 2 < split)
+        .collect();
+}
+
+/// The event's regions, or the whole image if it has none
+fn regions(event: &SubtitleEvent, width: u32, height: u32) -> Vec<Region> {
+    if event.regions.is_empty() {
+        return vec![Region {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }];
+    }
+    return event.regions.clone();
+}
+
+/// Splits each image subtitle into the regions above `split` and those below
+/// it, each cropped to an event of its own, returned as the upper and lower
+/// tracks. Text subtitles and images without a placement can't be told
+/// apart, and go to the lower track.
+pub fn split_languages(
+    events: Vec<SubtitleEvent>,
+    split: u32,
+) -> (Vec<SubtitleEvent>, Vec<SubtitleEvent>) {
+    let mut upper = Vec::new();
+    let mut lower = Vec::new();
+    for event in events {
+        let sides = sides(&event, split);
+        if sides.is_empty() || sides.iter().all(|upper| !*upper) {
+            lower.push(event);
+            continue;
+        }
+        if sides.iter().all(|upper| *upper) {
+            upper.push(event);
+            continue;
+        }
+        let EventPayload::Image(ref image) = event.payload else {
+            continue;
+        };
+        let regions = regions(&event, image.width(), image.height());
+        for (side, events) in [(true, &mut upper), (false, &mut lower)] {
+            let part: Vec<Region> = regions
+                .iter()
+                .zip(sides.iter())
+                .filter(|(_, upper)| **upper == side)
+                .map(|(region, _)| *region)
+                .collect();
+            events.push(crop(&event, image, &part));
+        }
+    }
+    return (upper, lower);
+}
+
+/// The part of an image event covering `regions`
+fn crop(event: &SubtitleEvent, image: &GrayAlphaImage, regions: &[Region]) -> SubtitleEvent {
+    let x1 = regions.iter().map(|region| region.x).min().unwrap_or(0);
+    let y1 = regions.iter().map(|region| region.y).min().unwrap_or(0);
+    let x2 = regions
+        .iter()
+        .map(|region| region.x + region.width)
+        .max()
+        .unwrap_or(0);
+    let y2 = regions
+        .iter()
+        .map(|region| region.y + region.height)
+        .max()
+        .unwrap_or(0);
+    let area = Region {
+        x: x1,
+        y: y1,
+        width: x2 - x1,
+        height: y2 - y1,
+    };
+    return SubtitleEvent {
+        start: event.start,
+        end: event.end,
+        end_source: event.end_source,
+        forced: event.forced,
+        payload: EventPayload::Image(
+            imageops::crop_imm(image, area.x, area.y, area.width, area.height).to_image(),
+        ),
+        regions: regions
+            .iter()
+            .map(|region| Region {
+                x: region.x - area.x,
+                y: region.y - area.y,
+                ..*region
+            })
+            .collect(),
+        placement: event.placement.map(|placement| Placement {
+            x: placement.x + area.x,
+            y: placement.y + area.y,
+            ..placement
+        }),
+        indexed: event.indexed.as_ref().map(|indexed| indexed.crop(area)),
+    };
+}
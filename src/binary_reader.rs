@@ -2,6 +2,7 @@ extern crate alloc;
 extern crate core;
 use alloc::vec::Vec;
 use core::str::{self, Utf8Error};
+use std::io::SeekFrom;
 
 #[derive(Debug, Clone)]
 pub enum PacketWriteError {
@@ -94,6 +95,20 @@ impl<'a> PacketReader<'a> {
         self.cursor += 2;
         return Some(num);
     }
+    /// Reads a 24-bit big-endian integer, as used for PGS object lengths
+    pub fn read_u24(&mut self) -> Option<u32> {
+        if self.cursor + 3 > self.packet.len() {
+            return None;
+        }
+        let num = u32::from_be_bytes([
+            0,
+            self.packet[self.cursor],
+            self.packet[self.cursor + 1],
+            self.packet[self.cursor + 2],
+        ]);
+        self.cursor += 3;
+        return Some(num);
+    }
     pub fn read_u32(&mut self) -> Option<u32> {
         if self.cursor + 4 > self.packet.len() {
             return None;
@@ -222,6 +237,28 @@ impl<'a> PacketReader<'a> {
         return Some(num);
     }
 
+    /// Reads the next byte without consuming it
+    pub fn peek_u8(&self) -> Option<u8> {
+        return self.packet.get(self.cursor).copied();
+    }
+    /// Reads the next two bytes without consuming them
+    pub fn peek_u16(&self) -> Option<u16> {
+        if self.cursor + 2 > self.packet.len() {
+            return None;
+        }
+        return Some(u16::from_be_bytes([
+            self.packet[self.cursor],
+            self.packet[self.cursor + 1],
+        ]));
+    }
+
+    /// Fills `buf` from the packet. Nothing is consumed if there aren't
+    /// enough bytes left.
+    pub fn read_exact_into(&mut self, buf: &mut [u8]) -> Option<()> {
+        buf.copy_from_slice(self.take_bytes(buf.len())?);
+        return Some(());
+    }
+
     pub fn take_bytes(&mut self, num_bytes: usize) -> Option<&'a [u8]> {
         if self.packet.len() - self.cursor < num_bytes {
             return None;
@@ -231,6 +268,34 @@ impl<'a> PacketReader<'a> {
         return Some(buf);
     }
 
+    /// Splits off the next `len` bytes into their own reader, so a parser
+    /// handed it can't run past the end of its segment. The bytes are
+    /// consumed from this reader whether or not the child reads them all.
+    pub fn sub_reader(&mut self, len: usize) -> Option<PacketReader<'a>> {
+        return Some(PacketReader::new(self.take_bytes(len)?));
+    }
+
+    /// Offset of the next byte to be read
+    pub fn position(&self) -> usize {
+        return self.cursor;
+    }
+
+    /// Moves the cursor, returning the new position. Positions past the end
+    /// of the packet are rejected and leave the cursor where it was, but
+    /// seeking to the very end is allowed.
+    pub fn seek(&mut self, pos: SeekFrom) -> Option<usize> {
+        let cursor = match pos {
+            SeekFrom::Start(offset) => usize::try_from(offset).ok()?,
+            SeekFrom::End(offset) => self.packet.len().checked_add_signed(offset as isize)?,
+            SeekFrom::Current(offset) => self.cursor.checked_add_signed(offset as isize)?,
+        };
+        if cursor > self.packet.len() {
+            return None;
+        }
+        self.cursor = cursor;
+        return Some(cursor);
+    }
+
     pub fn get_remainder(&'a self) -> &'a [u8] {
         return &self.packet[self.cursor..];
     }
@@ -239,3 +304,78 @@ impl<'a> PacketReader<'a> {
         return self.get_remainder().len();
     }
 }
+
+/// Reads fields of arbitrary bit widths, most significant bit first, as used
+/// by VobSub RLE data and DVB and teletext bitstreams
+#[derive(Debug, Clone)]
+pub struct BitReader<'a> {
+    /// Offset in bits
+    cursor: usize,
+    data: &'a [u8],
+}
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        return Self { cursor: 0, data };
+    }
+
+    /// Reads the next `bits` bits (at most 32) as an unsigned integer.
+    /// Nothing is consumed if there aren't enough left.
+    pub fn read_bits(&mut self, bits: u32) -> Option<u32> {
+        let value = self.peek_bits(bits)?;
+        self.cursor += bits as usize;
+        return Some(value);
+    }
+
+    /// Reads the next `bits` bits (at most 32) without consuming them
+    pub fn peek_bits(&self, bits: u32) -> Option<u32> {
+        assert!(bits <= 32, "Can't read more than 32 bits at once");
+        if bits as usize > self.remaining_bits() {
+            return None;
+        }
+        let mut value: u64 = 0;
+        let mut cursor = self.cursor;
+        let mut left = bits as usize;
+        while left > 0 {
+            let byte = self.data[cursor / 8];
+            let offset = cursor % 8;
+            let take = (8 - offset).min(left);
+            let chunk = (byte >> (8 - offset - take)) & ((1u16 << take) - 1) as u8;
+            value = value << take | chunk as u64;
+            cursor += take;
+            left -= take;
+        }
+        return Some(value as u32);
+    }
+
+    pub fn read_bit(&mut self) -> Option<bool> {
+        return Some(self.read_bits(1)? == 1);
+    }
+
+    /// Skips `bits` bits, returning `None` and leaving the cursor alone if
+    /// that would run past the end
+    pub fn skip_bits(&mut self, bits: usize) -> Option<()> {
+        if bits > self.remaining_bits() {
+            return None;
+        }
+        self.cursor += bits;
+        return Some(());
+    }
+
+    pub fn is_byte_aligned(&self) -> bool {
+        return self.cursor.is_multiple_of(8);
+    }
+
+    /// Skips to the start of the next byte, unless already on one
+    pub fn byte_align(&mut self) {
+        self.cursor = self.cursor.next_multiple_of(8).min(self.data.len() * 8);
+    }
+
+    /// Offset of the next bit to be read
+    pub fn position(&self) -> usize {
+        return self.cursor;
+    }
+
+    pub fn remaining_bits(&self) -> usize {
+        return self.data.len() * 8 - self.cursor;
+    }
+}
@@ -0,0 +1,74 @@
+//! Cooperative cancellation, so an embedding application can stop a long
+//! extraction from another thread. The extractor and OCR engines check the
+//! token between units of work, keeping what they finished, and writers
+//! wrapped with [`CancellationToken::writer`] fail their next write.
+
+use std::{
+    io::{self, Write},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Cancelled")]
+pub struct Cancelled;
+
+/// Shared between the code doing the work and whoever may cancel it. Clones
+/// refer to the same token.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+impl CancellationToken {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        return self.0.load(Ordering::Relaxed);
+    }
+
+    /// `Err(Cancelled)` once cancelled
+    pub fn check(&self) -> Result<(), Cancelled> {
+        return match self.is_cancelled() {
+            true => Err(Cancelled),
+            false => Ok(()),
+        };
+    }
+
+    /// Wraps `inner` so writes fail with a [`Cancelled`] error once this is
+    /// cancelled
+    pub fn writer<W: Write>(&self, inner: W) -> CancellableWriter<W> {
+        return CancellableWriter {
+            inner,
+            token: self.clone(),
+        };
+    }
+}
+
+pub struct CancellableWriter<W: Write> {
+    inner: W,
+    token: CancellationToken,
+}
+impl<W: Write> CancellableWriter<W> {
+    pub fn into_inner(self) -> W {
+        return self.inner;
+    }
+}
+impl<W: Write> Write for CancellableWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.token.check().map_err(io::Error::other)?;
+        return self.inner.write(buf);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.token.check().map_err(io::Error::other)?;
+        return self.inner.flush();
+    }
+}
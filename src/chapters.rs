@@ -0,0 +1,212 @@
+//! Matroska chapters, for splitting the subtitles of a file holding several
+//! episodes (common with disc rips) into one file per chapter, and for
+//! following ordered chapters, which play pieces of one or more files
+//! (segment linking) in an order of their own.
+
+use std::io::{self, Read, Seek};
+
+#[cfg(feature = "demux-mkv")]
+use matroska_demuxer::MatroskaFile;
+
+use crate::{ebml::*, srt::SrtCue};
+
+/// Identifies a Matroska segment, which ordered chapters refer to other files by
+pub type SegmentUid = [u8; 16];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    /// Nanoseconds
+    pub start: u64,
+    /// Nanoseconds. `None` for the last chapter if the file doesn't say.
+    pub end: Option<u64>,
+    pub title: Option<String>,
+}
+
+/// The top-level chapters of the file's first edition, in order. Chapters
+/// without an end time last until the next one starts.
+#[cfg(feature = "demux-mkv")]
+pub fn read_chapters<R: Read + Seek>(mkv: &MatroskaFile<R>) -> Vec<Chapter> {
+    let Some(edition) = mkv.chapters().and_then(|editions| editions.first()) else {
+        return Vec::new();
+    };
+    let mut chapters: Vec<Chapter> = edition
+        .chapter_atoms()
+        .iter()
+        .map(|atom| Chapter {
+            start: atom.time_start(),
+            end: atom.time_end(),
+            title: atom
+                .displays()
+                .first()
+                .map(|display| display.string().to_owned()),
+        })
+        .collect();
+    chapters.sort_by_key(|chapter| chapter.start);
+    let starts: Vec<u64> = chapters.iter().map(|chapter| chapter.start).collect();
+    for (chapter, next) in chapters.iter_mut().zip(starts.into_iter().skip(1)) {
+        chapter.end = Some(chapter.end.map_or(next, |end| end.min(next)));
+    }
+    return chapters;
+}
+
+/// Splits `cues` by chapter, with timestamps relative to the chapter's start.
+/// Each cue goes to the chapter it starts in, and is cut short at the end of
+/// the chapter. Cues before the first chapter go to the first one, and ones
+/// in a gap between chapters are dropped.
+pub fn split_cues(cues: &[SrtCue], chapters: &[Chapter]) -> Vec<Vec<SrtCue>> {
+    let mut split = vec![Vec::new(); chapters.len()];
+    if chapters.is_empty() {
+        return split;
+    }
+    for cue in cues {
+        let index = chapters
+            .iter()
+            .rposition(|chapter| chapter.start <= cue.start)
+            .unwrap_or(0);
+        let chapter = &chapters[index];
+        if chapter.end.is_some_and(|end| cue.start >= end) {
+            continue;
+        }
+        let end = chapter.end.map_or(cue.end, |end| cue.end.min(end));
+        split[index].push(SrtCue {
+            start: cue.start.saturating_sub(chapter.start),
+            end: end.saturating_sub(chapter.start),
+            text: cue.text.clone(),
+        });
+    }
+    return split;
+}
+
+/// A chapter of an ordered edition: a piece of some segment, played after
+/// the edition's previous chapters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderedChapter {
+    /// The segment the chapter plays from, or `None` for the file's own
+    pub segment: Option<SegmentUid>,
+    /// Nanoseconds, in the source segment
+    pub start: u64,
+    /// Nanoseconds, in the source segment
+    pub end: u64,
+    pub title: Option<String>,
+}
+impl OrderedChapter {
+    pub fn duration(&self) -> u64 {
+        return self.end.saturating_sub(self.start);
+    }
+}
+
+/// What a file says about segment linking. `matroska_demuxer` doesn't read
+/// these parts of the format, so they're read with [`crate::ebml`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SegmentLinks {
+    pub uid: Option<SegmentUid>,
+    /// Enabled chapters of the default ordered edition (or the first one if
+    /// none is the default), or `None` if the file has no ordered edition
+    pub ordered: Option<Vec<OrderedChapter>>,
+}
+
+/// Reads the segment UID and ordered chapters of a Matroska file
+pub fn read_segment_links<R: Read + Seek>(reader: &mut R) -> io::Result<SegmentLinks> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut links = SegmentLinks::default();
+    for (id, data) in read_top_level_elements(reader, &[ID_INFO, ID_CHAPTERS])? {
+        if id == ID_CHAPTERS {
+            links.ordered = read_ordered_edition(&data)?;
+            continue;
+        }
+        for child in ChildIter::new(&data) {
+            let (id, _, value) = child?;
+            if id == ID_SEGMENT_UID {
+                links.uid = Some(
+                    value
+                        .try_into()
+                        .map_err(|_| invalid("Invalid segment UID"))?,
+                );
+            }
+        }
+    }
+    return Ok(links);
+}
+
+fn read_ordered_edition(chapters: &[u8]) -> io::Result<Option<Vec<OrderedChapter>>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut edition = None;
+    for child in ChildIter::new(chapters) {
+        let (id, _, entry) = child?;
+        if id != ID_EDITION_ENTRY {
+            continue;
+        }
+        let (mut ordered, mut default) = (false, false);
+        let mut atoms = Vec::new();
+        for field in ChildIter::new(entry) {
+            let (id, _, value) = field?;
+            match id {
+                ID_EDITION_FLAG_ORDERED => ordered = parse_uint(value) != 0,
+                ID_EDITION_FLAG_DEFAULT => default = parse_uint(value) != 0,
+                ID_CHAPTER_ATOM => atoms.push(value),
+                _ => {}
+            }
+        }
+        if !ordered || (edition.is_some() && !default) {
+            continue;
+        }
+        let mut chapters = Vec::new();
+        for atom in atoms {
+            let (mut start, mut end, mut enabled) = (0, None, true);
+            let (mut segment, mut title) = (None, None);
+            for field in ChildIter::new(atom) {
+                let (id, _, value) = field?;
+                match id {
+                    ID_CHAPTER_TIME_START => start = parse_uint(value),
+                    ID_CHAPTER_TIME_END => end = Some(parse_uint(value)),
+                    ID_CHAPTER_FLAG_ENABLED => enabled = parse_uint(value) != 0,
+                    ID_CHAPTER_SEGMENT_UID => {
+                        segment = Some(
+                            value
+                                .try_into()
+                                .map_err(|_| invalid("Invalid chapter segment UID"))?,
+                        );
+                    }
+                    ID_CHAPTER_DISPLAY if title.is_none() => {
+                        for field in ChildIter::new(value) {
+                            let (id, _, value) = field?;
+                            if id == ID_CHAP_STRING {
+                                title = Some(String::from_utf8_lossy(value).into_owned());
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            if !enabled {
+                continue;
+            }
+            chapters.push(OrderedChapter {
+                segment,
+                start,
+                end: end.ok_or_else(|| invalid("Ordered chapter without an end time"))?,
+                title,
+            });
+        }
+        edition = Some(chapters);
+        if default {
+            break;
+        }
+    }
+    return Ok(edition);
+}
+
+/// The cues shown during `chapter`, out of all `cues` of its segment, moved
+/// to `offset` (where the chapter starts in the edition's timeline) and cut
+/// to the chapter's bounds
+pub fn place_cues(cues: &[SrtCue], chapter: &OrderedChapter, offset: u64) -> Vec<SrtCue> {
+    return cues
+        .iter()
+        .filter(|cue| cue.end > chapter.start && cue.start < chapter.end)
+        .map(|cue| SrtCue {
+            start: cue.start.max(chapter.start) - chapter.start + offset,
+            end: cue.end.min(chapter.end) - chapter.start + offset,
+            text: cue.text.clone(),
+        })
+        .collect();
+}
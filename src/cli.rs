@@ -0,0 +1,1606 @@
+//! Command line parsing. The option set is small enough that it's handled by
+//! hand rather than pulling in an argument parsing crate.
+
+use std::path::{Path, PathBuf};
+
+use image::Rgb;
+use subproc::{
+    color::ColorMatrix,
+    contact_sheet::SheetLayout,
+    density::{DEFAULT_BUCKET, DEFAULT_GAP, DEFAULT_TAIL_GAP},
+    filter::Filter,
+    input::IoStrategy,
+    non_text::NonTextRules,
+    ocr::{CommandEngine, OcrConstraints, RegionPolicy, RetryPolicy, charset},
+    preprocess::{Background, FlattenOptions},
+    qc::ReadingSpeedLimits,
+    queue::{JobLimits, QueueLimits},
+    sync::SyncOptions,
+    terminal::{PreviewMode, PreviewWidth},
+    text_filter::Replace,
+    timebase::{FrameRate, Timestamp},
+    timing::{OverlapPolicy, TimingRules},
+    transform::{ScaleFilter, Transform},
+    vobs::parse_palette,
+    wrap::WrapOptions,
+};
+
+use crate::config::Config;
+
+pub const USAGE: &str = "\
+Usage: subproc [OPTIONS] <INPUT.mkv>
+       subproc serve [--listen <ADDR>] [--max-open-files <N>] [--read-buffer <SIZE>]
+                     [--read-ahead <N>] [--mmap] [QUEUE OPTIONS]
+       subproc watch [--settle <TIME>] [--poll <TIME>] [--recursive] --sidecar
+                     [QUEUE OPTIONS] [OPTIONS] <DIR>
+       subproc contact-sheet [--track <N>] [--columns <N>] [--rows <N>]
+                             [--thumb-size <W>x<H>] -o <OUT.png> <INPUT.mkv>
+       subproc palette dump [--track <N>] <INPUT.mkv>
+       subproc density [--track <N>] [--bucket <TIME>] [--gap <TIME>]
+                       [--tail-gap <TIME>] [--json] <INPUT.mkv>
+       subproc compare --reference <REF.srt> [--track <N>] [--background <MODE>]...
+                       [--min-alpha <N>] [--binarize <LUMA>] [--strip-outline]
+                       [--ocr-* ...] <INPUT.mkv>
+       subproc subtitle-ocr -o <OUT.srt> [--regions <POLICY>] [--position-tags]
+                            [--min-alpha <N>] [--binarize <LUMA>] [--background <MODE>]
+                            [--strip-outline] [--ocr-* ...] <DIR>
+       subproc rehydrate --text <FIXED.srt|FIXED.json> [-o <OUT.srt>] [--json <OUT.json>]
+                         [--mux <OUT.mkv>] [--source <INPUT.mkv>] [--position-tags]
+                         [--forced-only] <DIR|MANIFEST.json>
+       subproc sync --reference <REF.srt> -o <OUT.srt> [--max-offset <TIME>] [--no-scale]
+                    <INPUT.srt>
+       subproc attachments [--extract <DIR>] <INPUT.mkv>
+
+Extracts a subtitle track from an MKV file, or from an MKV piped to stdin if
+INPUT is -. Without an output option, each subtitle is previewed in the
+terminal (images as sixel or colored blocks, text as-is).
+
+Options:
+  --preset <NAME>         Apply the options of a preset from subproc.toml (looked
+                          up in the current directory, then ~/.config/subproc, or
+                          %APPDATA%\\subproc on Windows).
+                          Options given on the command line take precedence.
+  --config <FILE>         Read presets from FILE instead
+  --track <N>             Track number to extract (default: first subtitle track)
+  --start <TIME>          Start extracting at TIME (HH:MM:SS[.mmm] or seconds).
+                          Subtitles that depend on earlier data are skipped.
+  --filter <EXPR>         Only keep subtitles matching EXPR, e.g.
+                          \"duration >= 100ms and area < 80%\". Compares start, end,
+                          duration, width, height, area or regions, tests the flags
+                          forced, image and text, or checks \"time in 00:10..00:20\";
+                          combine with and/or/not and parentheses
+  --keep-non-text         OCR every bitmap. By default ones that don't look like
+                          text (nearly transparent, a few specks, or far too thin
+                          or narrow for a line) are only saved and previewed,
+                          each with a warning.
+  --min-ink <PIXELS>      Don't OCR bitmaps with fewer opaque pixels than this,
+                          as they aren't text (default: 40)
+  -o, --output <FILE>     Write an SRT file
+  --output-format <FORMAT>
+                          Write --output and --sidecar files as srt (default), microdvd
+                          (frame-based .sub) or subviewer (SubViewer 2.0 .sub) instead
+  --frame-rate <RATE>     Video frame rate for microdvd, e.g. 25 or 24000/1001 (default:
+                          the MKV's video track)
+  --out <FORMAT>[,<OPTION>=<VALUE>...][:<FILE>]
+                          Also write FILE in FORMAT, or a file next to the input named
+                          like --sidecar without one. Options apply to this output
+                          only: line-length and max-lines re-wrap its text, and
+                          frame-rate overrides --frame-rate. May be repeated; all
+                          outputs are written from a single decoding pass.
+  --save-images <DIR>     Save each subtitle bitmap as a numbered PNG in DIR, along
+                          with a manifest.json of every subtitle's timing. Without
+                          another output, this replaces the terminal preview.
+  --preview               Also preview each subtitle in the terminal as it's decoded
+                          while writing the outputs, rather than only without them
+  --preview-width <W>     Scale terminal previews down to at most W, given in pixels
+                          (640px) or terminal columns (80cols)
+  --preview-mode <MODE>   How to draw images in the terminal: sixel, blocks (Unicode
+                          half blocks, for terminals without sixel support such as
+                          the Windows console) or auto (default)
+  --preview-background <rrggbb>
+                          Color terminal previews are drawn over (default: 000000)
+  --composite             Preview each subtitle drawn over the video frame at its start
+                          time (needs ffmpeg), or save the composites to the
+                          --save-images directory, alongside any outputs
+  --indexed               Save images as 8-bit indexed PNGs with the track's own
+                          palette instead of grayscale, for restyling or re-encoding
+  --color-matrix <MATRIX> How PGS palettes are converted to RGB for --indexed and
+                          previews: bt601, bt709 or auto (default; bt601 up to 576
+                          lines, bt709 above)
+  --sidecar               Write an SRT next to the input, named for media servers
+                          (<name>.<lang>[.sdh][.forced].srt)
+  --split-chapters        Write one --output file per MKV chapter (numbered like
+                          <name>-01.srt), timed from the chapter's start
+  --ordered-chapters      Time --output and --sidecar files by the input's ordered
+                          chapters, following segment links to the other MKVs in
+                          its directory, so they line up with playback
+  --ascii-punctuation     Write curly quotes and ellipses as plain ASCII
+  --replace <EXPR>        Apply a regex replacement to the text, written as
+                          /PATTERN/REPLACEMENT/ (any delimiter works, e.g. |a/b|c|).
+                          May be repeated; replacements run in order.
+  --wrap                  Re-wrap text into at most 2 lines of 42 characters, breaking
+                          at punctuation and before conjunctions where possible
+  --line-length <N>       Characters per line for --wrap (implies --wrap)
+  --max-lines <N>         Lines per subtitle for --wrap (implies --wrap)
+  --overlaps <POLICY>     Fix cues that start before the previous one ends: trim (end
+                          the earlier cue sooner, default) or merge (show both texts)
+  --min-gap <MS>          Leave at least MS milliseconds between consecutive cues
+  --max-duration <TIME>   Cut cues longer than TIME (seconds or MM:SS) short. Any of
+                          these three options also fixes overlaps.
+  --snap-keyframes <MS>   Move subtitle starts and ends within MS milliseconds of a
+                          video keyframe (from the MKV's Cues index) onto it, so they
+                          line up with scene changes
+  --sync-to <REF.srt>     Shift (and for frame rate conversions, stretch) the timing
+                          to line up with REF.srt, a track known to be in sync
+  --qc-report <FILE>      Write a CSV of every cue's reading speed, flagging the ones
+                          over --max-cps (default: 20) or --max-wpm (default: 180), or
+                          shorter than --min-duration (default: 0.833 seconds)
+  --diagnostics <FILE>    Write a CSV of the problems skipped over during the run
+                          (decoding errors, missing linked segments, OCR failures),
+                          with the event and time of each
+  --mux <FILE>            Write a copy of the input MKV with the subtitles added as
+                          a text track
+  --language <CODE>       Language to use when naming outputs (default: track language,
+                          or detected from the subtitle text if that's undefined)
+  --forced                Mark the output as forced, regardless of the track name
+  --sdh                   Mark the output as SDH. Otherwise this is guessed from the
+                          track name and the subtitles themselves.
+  --strip-sdh             Remove sound descriptions, speaker labels and music-only
+                          lines, producing a non-SDH variant
+  --palette <COLORS>      Decode VobSub with these 16 comma-separated rrggbb colors
+                          instead of the palette from the track's idx data, or
+                          without idx data if it's missing or malformed
+  --regions <POLICY>      How to output subtitles with several separately placed parts
+                          (e.g. a sign at the top and dialogue at the bottom), each of
+                          which is recognized on its own: merge (one cue, default),
+                          separate (one cue each) or position (one cue each, with
+                          {\\an8} on the ones at the top)
+  --position-tags         Start subtitles shown in the top half of the screen with
+                          {\\an8}, so players put them at the top too
+  --split-languages <UPPER>,<LOWER>
+                          Split a track with two languages stacked in separate
+                          objects into a track each, recognized with these Tesseract
+                          languages (e.g. chi_sim,eng) and written as separate --output
+                          and --sidecar files named with each language
+  --scale <W>x<H>         Rescale subtitle images and positions from the video's
+                          resolution to W by H, e.g. for a 720p re-encode
+  --scale-filter <NAME>   bilinear (default) or nearest; nearest keeps hard edges.
+                          Indexed images always use nearest.
+  --min-alpha <N>         Treat pixels less opaque than N (0-255) as background for OCR
+  --binarize <LUMA>       Make images black and white for OCR, with pixels at least
+                          LUMA (0-255) bright becoming white
+  --background <MODE>     What images are flattened against for OCR: keep (luma as-is,
+                          default), black, white, auto (whichever contrasts more with
+                          the text), alpha (the alpha channel as a mask, with
+                          --binarize as its threshold) or palette (only the palette
+                          colors classified as the letters' fill, white on black)
+  --strip-outline         Remove the outline around glyphs before OCR, keeping only
+                          the letters' fill
+  --ocr-command <CMD>     Run CMD for OCR instead of Tesseract, with each image piped
+                          to stdin as PNG and the text read from stdout
+  --ocr-url <URL>         Send images to a hosted OCR service (not implemented yet)
+  --ocr-language <LANG>   Tesseract language to recognize (default: eng). Combine
+                          several with +, e.g. chi_sim+eng.
+  --ocr-whitelist <CHARS> Only let Tesseract recognize these characters. `latin1` is
+                          short for Latin-1 and music notes.
+  --ocr-blacklist <CHARS> Never let Tesseract recognize these characters
+                          (default: |\\/`_~!)
+  --ocr-psm <N>           Tesseract page segmentation mode (default: 6, a single block)
+  --ocr-cache <DIR>       Keep recognized text in DIR and reuse it for identical images
+                          on later runs with the same OCR backend
+  --ocr-retry <CONF>      Re-run images Tesseract is less than CONF% (0-100) sure of
+                          inverted, upscaled and thresholded, keeping the most
+                          confident text
+  --set-track-language    If the track's language is undefined, write --language (or the
+                          detected language) into the MKV (requires mkvpropedit)
+  --read-buffer <SIZE>    Read the input SIZE bytes at a time (e.g. 64K or 4M, default:
+                          256K). Larger reads help on network shares.
+  --read-ahead <N>        Keep up to N reads of --read-buffer done ahead on a
+                          background thread, so slow storage is read while the
+                          last reads are being processed
+  --mmap                  Memory-map the input instead of reading it (builds with the
+                          mmap feature)
+  --dry-run               Print the track that would be extracted, roughly how many
+                          subtitles it has and the files that would be written,
+                          without decoding or running OCR
+  --verify                Decode a PGS track with both the normal decoder and a naive
+                          reference renderer and report where their images or
+                          timelines differ. With --save-images, both versions of
+                          every differing subtitle are saved there.
+  -h, --help              Show this message
+
+The serve command runs an HTTP service accepting extraction jobs instead
+(default address: 127.0.0.1:8350). It accepts the --ocr-* options as well,
+and reads inputs with --read-buffer, --read-ahead or --mmap. With --max-open-files,
+jobs beyond N wait for another to finish reading its input before starting.
+
+The watch command (in builds with the watch feature) waits for MKVs to show
+up in DIR and extracts each one with the extraction options given, usually
+a --preset, writing --sidecar files next to it. A file is picked up once its
+size hasn't changed for --settle (default: 5 seconds), so copies in progress
+are left alone, and files already there when watching starts are skipped.
+--recursive watches subdirectories too. Network shares often don't report
+changes made by other machines; --poll rescans DIR every TIME instead.
+
+Both run their jobs through a queue, highest priority first (serve takes a
+`priority` with each job), with these options:
+  --decode-jobs <N>       Jobs reading their input at once (default: 2)
+  --ocr-jobs <N>          Jobs running OCR at once (default: one per CPU core)
+  --max-input-size <SIZE> Fail jobs on inputs larger than SIZE, e.g. 20G
+  --max-events <N>        Fail jobs on tracks with more than N subtitles
+  --job-timeout <TIME>    Cancel jobs still running after TIME, not counting
+                          time spent queued
+  --state <FILE>          Keep the queue in FILE, so jobs queued or running
+                          when the process stops are run when it's restarted
+
+The contact-sheet command tiles every subtitle bitmap of a track, with its
+number and start time, into PNG sheets (default: 4 columns, 12 rows and
+480x120 thumbnails). Sheets beyond the first get numbered file names.
+
+The palette dump command prints the palette of a VobSub track as an idx
+`palette:` line (also accepted by --palette), or each distinct palette of a
+PGS track as <entry>=<Y><Cr><Cb><alpha> in hex.
+
+The density command maps how many subtitle blocks a track has per --bucket
+(default: a minute) as a sparkline, or as JSON with --json, and lists
+stretches of at least --gap (default: 5 minutes) without any, to spot ranges
+missing from a rip. A track whose last subtitle is at least --tail-gap
+(default: 15 minutes) before the end of the file is flagged as cut off.
+It reads block timestamps from the Cues index where the track is indexed
+there, and otherwise skims the clusters without decoding anything.
+
+The compare command recognizes an image track once per --background mode
+(default: all of them) and prints each one's character error rate against
+REF.srt, a known good transcript, to find the settings that suit a disc.
+
+The subtitle-ocr command recognizes a --save-images directory and writes the
+SRT, taking the timing from its manifest.json. The images can be touched up
+in between; indexed ones are recognized with their palette, grayscale and
+color ones as their luma. It accepts the OCR options of extraction.
+
+The rehydrate command merges hand-corrected text (an edited SRT, or a JSON
+manifest with edited text) back into a manifest, matching each cue to the
+subtitle it overlaps the most, and regenerates outputs from the result
+without decoding or OCR: an SRT (with {\\an8} on subtitles at the top given
+--position-tags, and only forced ones given --forced-only), the updated
+manifest with --json, or with --mux a copy of the source MKV (--source, by
+default the one the manifest was saved from) with the text added as a track.
+
+The sync command fixes an SRT that drifts from the audio by lining it up with
+REF.srt, another track of the same video known to be in sync (in any
+language). It tries offsets of up to --max-offset either way (default: 60
+seconds), and unless --no-scale is given the stretches between 23.976, 24
+and 25 fps, keeping the one where the two tracks disagree the least about
+when something is being said.
+
+The attachments command lists the files attached to an MKV (fonts, or the
+idx of a VobSub track, which is used when the track has none of its own),
+and with --extract saves them to DIR.";
+
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:8350";
+/// Tesseract language used without `--ocr-language`
+pub const DEFAULT_OCR_LANGUAGE: &str = "eng";
+/// Input path that reads an MKV from stdin
+pub const STDIN: &str = "-";
+/// How long `watch` waits for a new file's size to stop changing: 5 seconds
+#[cfg(feature = "watch")]
+pub const DEFAULT_SETTLE: u64 = 5_000_000_000;
+
+pub enum Command {
+    Extract(Box<Options>),
+    Serve(ServeOptions),
+    #[cfg(feature = "watch")]
+    Watch(WatchOptions),
+    ContactSheet(SheetOptions),
+    PaletteDump(PaletteOptions),
+    Density(DensityOptions),
+    Attachments(AttachmentOptions),
+    Compare(CompareOptions),
+    SubtitleOcr(SubtitleOcrOptions),
+    Rehydrate(RehydrateOptions),
+    Sync(SyncCommandOptions),
+}
+
+/// Format of `--output` and `--sidecar` files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Srt,
+    /// Timed in video frames
+    MicroDvd,
+    SubViewer,
+}
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        return match name {
+            "srt" => Ok(OutputFormat::Srt),
+            "microdvd" => Ok(OutputFormat::MicroDvd),
+            "subviewer" => Ok(OutputFormat::SubViewer),
+            other => Err(format!("Unknown output format: {other}")),
+        };
+    }
+
+    /// The name it's registered under in a [`subproc::writer::WriterRegistry`]
+    pub fn name(self) -> &'static str {
+        return match self {
+            OutputFormat::Srt => "srt",
+            OutputFormat::MicroDvd => "microdvd",
+            OutputFormat::SubViewer => "subviewer",
+        };
+    }
+
+    pub fn extension(self) -> &'static str {
+        return match self {
+            OutputFormat::Srt => "srt",
+            OutputFormat::MicroDvd | OutputFormat::SubViewer => "sub",
+        };
+    }
+}
+
+/// An output given with `--out`, on top of `--output` and `--sidecar`
+#[derive(Debug, Clone)]
+pub struct OutputSpec {
+    pub format: OutputFormat,
+    /// Named like `--sidecar` files if not given
+    pub path: Option<PathBuf>,
+    /// Re-wraps this output's text, after `--wrap`
+    pub wrap: Option<WrapOptions>,
+    /// Overrides `--frame-rate`
+    pub frame_rate: Option<FrameRate>,
+}
+impl OutputSpec {
+    /// Parses `<FORMAT>[,<OPTION>=<VALUE>...][:<FILE>]`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (format, path) = match spec.split_once(':') {
+            Some((format, path)) if !path.is_empty() => (format, Some(PathBuf::from(path))),
+            Some((format, _)) => (format, None),
+            None => (spec, None),
+        };
+        let mut parts = format.split(',');
+        let mut output = OutputSpec {
+            format: OutputFormat::from_name(parts.next().unwrap_or_default())?,
+            path,
+            wrap: None,
+            frame_rate: None,
+        };
+        for option in parts {
+            let (key, value) = option
+                .split_once('=')
+                .ok_or_else(|| format!("Expected <OPTION>=<VALUE>: {option}"))?;
+            match key {
+                "line-length" => {
+                    output.wrap.get_or_insert_default().max_line_length = parse_count(value)?;
+                }
+                "max-lines" => output.wrap.get_or_insert_default().max_lines = parse_count(value)?,
+                "frame-rate" => output.frame_rate = Some(value.parse()?),
+                other => return Err(format!("Unknown output option: {other}")),
+            }
+        }
+        return Ok(output);
+    }
+}
+
+/// Which OCR engine to use for image-based subtitles
+#[derive(Debug, Clone)]
+pub enum OcrBackend {
+    Tesseract(OcrConstraints),
+    Command(CommandEngine),
+    Http(String),
+}
+impl Default for OcrBackend {
+    fn default() -> Self {
+        return OcrBackend::Tesseract(OcrConstraints::default());
+    }
+}
+
+/// The `--ocr-*` options, shared by extraction and the service
+#[derive(Debug, Clone, Default)]
+pub struct OcrOptions {
+    pub backend: OcrBackend,
+    /// Tesseract language(s), e.g. `jpn` or `chi_sim+eng`
+    pub language: Option<String>,
+    /// Directory recognized text is kept in between runs
+    pub cache: Option<PathBuf>,
+    /// Retries images recognized with low confidence
+    pub retry: Option<RetryPolicy>,
+}
+
+#[derive(Debug)]
+pub struct ServeOptions {
+    pub listen: String,
+    pub ocr: OcrOptions,
+    pub io: IoStrategy,
+    /// Inputs read at once, 0 for no limit
+    pub max_open_files: usize,
+    pub queue: QueueOptions,
+}
+
+/// The job queue options shared by `serve` and `watch`
+#[derive(Debug, Default)]
+pub struct QueueOptions {
+    pub limits: QueueLimits,
+    /// Applied to every job
+    pub job: JobLimits,
+    /// Where the queue is kept between runs
+    pub state: Option<PathBuf>,
+}
+
+#[cfg(feature = "watch")]
+#[derive(Debug)]
+pub struct WatchOptions {
+    pub directory: PathBuf,
+    pub recursive: bool,
+    /// Nanoseconds a new file's size must stay the same before it's extracted
+    pub settle: u64,
+    /// Nanoseconds between rescans, for file systems without change events
+    pub poll: Option<u64>,
+    pub queue: QueueOptions,
+    /// Extraction arguments, with the preset expanded. Their input is the
+    /// directory, replaced with each file.
+    extract_args: Vec<String>,
+}
+#[cfg(feature = "watch")]
+impl WatchOptions {
+    /// The extraction options for a file that showed up
+    pub fn extract_options(&self, input: &Path) -> Result<Options, String> {
+        let mut options = parse_extract_args(self.extract_args.iter().cloned())?
+            .ok_or_else(|| String::from("Unexpected --help"))?;
+        options.input = input.to_owned();
+        return Ok(options);
+    }
+}
+
+#[derive(Debug)]
+pub struct SheetOptions {
+    pub input: PathBuf,
+    pub track: Option<u64>,
+    pub output: PathBuf,
+    pub layout: SheetLayout,
+}
+
+#[derive(Debug)]
+pub struct PaletteOptions {
+    pub input: PathBuf,
+    pub track: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct DensityOptions {
+    pub input: PathBuf,
+    pub track: Option<u64>,
+    /// Nanoseconds
+    pub bucket: u64,
+    /// Shortest gap between subtitles to report, in nanoseconds
+    pub gap: u64,
+    /// Shortest gap after the last subtitle to report, in nanoseconds
+    pub tail_gap: u64,
+    pub json: bool,
+}
+
+#[derive(Debug)]
+pub struct CompareOptions {
+    pub input: PathBuf,
+    pub track: Option<u64>,
+    pub reference: PathBuf,
+    pub backgrounds: Vec<Background>,
+    /// Applied with each of `backgrounds`
+    pub flatten: FlattenOptions,
+    pub ocr: OcrOptions,
+}
+
+#[derive(Debug)]
+pub struct SubtitleOcrOptions {
+    /// Directory saved with `--save-images`
+    pub directory: PathBuf,
+    pub output: PathBuf,
+    pub regions: RegionPolicy,
+    pub position_tags: bool,
+    pub flatten: FlattenOptions,
+    pub ocr: OcrOptions,
+}
+
+#[derive(Debug)]
+pub struct RehydrateOptions {
+    /// A manifest, or the `--save-images` directory holding it
+    pub manifest: PathBuf,
+    /// The corrected SRT or JSON
+    pub text: PathBuf,
+    pub output: Option<PathBuf>,
+    /// Where to write the manifest with the corrected text
+    pub json: Option<PathBuf>,
+    pub mux: Option<PathBuf>,
+    /// MKV to mux into, instead of the manifest's source
+    pub source: Option<PathBuf>,
+    pub position_tags: bool,
+    /// Only output subtitles flagged as forced
+    pub forced_only: bool,
+}
+
+#[derive(Debug)]
+pub struct SyncCommandOptions {
+    pub input: PathBuf,
+    pub reference: PathBuf,
+    pub output: PathBuf,
+    pub sync: SyncOptions,
+}
+
+#[derive(Debug)]
+pub struct AttachmentOptions {
+    pub input: PathBuf,
+    /// Directory to save the attachments to
+    pub extract: Option<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub input: PathBuf,
+    pub track: Option<u64>,
+    /// Nanoseconds
+    pub start: Option<u64>,
+    pub filter: Option<Filter>,
+    /// OCR bitmaps that don't look like text rather than skipping them
+    pub keep_non_text: bool,
+    pub non_text: NonTextRules,
+    pub output: Option<PathBuf>,
+    pub output_format: OutputFormat,
+    /// Overrides the video's frame rate for frame-based formats
+    pub frame_rate: Option<FrameRate>,
+    pub sidecar: bool,
+    /// `--out` outputs, in order
+    pub outputs: Vec<OutputSpec>,
+    /// Write one --output file per chapter
+    pub split_chapters: bool,
+    /// Time outputs by the ordered edition, following linked segments
+    pub ordered_chapters: bool,
+    /// Normalize quotes and ellipses to ASCII
+    pub ascii_punctuation: bool,
+    /// Regex replacements, in order
+    pub replacements: Vec<Replace>,
+    /// Re-wrap the text of every cue
+    pub wrap: Option<WrapOptions>,
+    /// Repair overlaps, gaps and durations before writing
+    pub timing: Option<TimingRules>,
+    /// Nanoseconds from a keyframe within which cue times are snapped to it
+    pub keyframe_window: Option<u64>,
+    /// SRT to line the timing up with
+    pub sync_to: Option<PathBuf>,
+    /// Where to write the reading speed report
+    pub qc_report: Option<PathBuf>,
+    /// Where to write the CSV of problems found along the way
+    pub diagnostics: Option<PathBuf>,
+    pub reading_speed: ReadingSpeedLimits,
+    pub save_images: Option<PathBuf>,
+    /// Save images with their palette indices
+    pub indexed: bool,
+    pub color_matrix: ColorMatrix,
+    pub composite: bool,
+    /// Preview events alongside the outputs
+    pub preview: bool,
+    pub preview_mode: PreviewMode,
+    pub preview_width: Option<PreviewWidth>,
+    pub preview_background: Option<Rgb<u8>>,
+    pub mux: Option<PathBuf>,
+    pub language: Option<String>,
+    pub forced: bool,
+    pub sdh: bool,
+    pub strip_sdh: bool,
+    /// Replaces the VobSub idx palette
+    pub palette: Option<[Rgb<u8>; 16]>,
+    pub regions: RegionPolicy,
+    /// Tag cues from images in the top half of the screen with `{\an8}`
+    pub position_tags: bool,
+    /// OCR languages of the upper and lower halves of a dual-language track,
+    /// which are split into a track each
+    pub split_languages: Option<(String, String)>,
+    pub scale: Option<Transform>,
+    pub flatten: FlattenOptions,
+    pub set_track_language: bool,
+    pub dry_run: bool,
+    /// Check the PGS decoder against the reference renderer instead of extracting
+    pub verify: bool,
+    pub ocr: OcrOptions,
+    pub io: IoStrategy,
+}
+impl Options {
+    /// Whether any file output was requested. If not, we just preview.
+    pub fn has_outputs(&self) -> bool {
+        return self.output.is_some()
+            || self.sidecar
+            || !self.outputs.is_empty()
+            || self.mux.is_some()
+            || self.qc_report.is_some();
+    }
+}
+
+/// Parses arguments, excluding the program name. `Err` holds a message for
+/// the user; `Ok(None)` means help was requested.
+pub fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Option<Command>, String> {
+    let mut args = args.peekable();
+    if args.next_if(|arg| arg == "serve").is_some() {
+        return Ok(parse_serve_args(args)?.map(Command::Serve));
+    }
+    if args.next_if(|arg| arg == "watch").is_some() {
+        #[cfg(feature = "watch")]
+        return Ok(parse_watch_args(args)?.map(Command::Watch));
+        #[cfg(not(feature = "watch"))]
+        return Err(String::from("This build has no watch support"));
+    }
+    if args.next_if(|arg| arg == "contact-sheet").is_some() {
+        return Ok(parse_sheet_args(args)?.map(Command::ContactSheet));
+    }
+    if args.next_if(|arg| arg == "palette").is_some() {
+        if args.next_if(|arg| arg == "dump").is_none() {
+            return Err(String::from("Expected `palette dump`"));
+        }
+        return Ok(parse_palette_args(args)?.map(Command::PaletteDump));
+    }
+    if args.next_if(|arg| arg == "density").is_some() {
+        return Ok(parse_density_args(args)?.map(Command::Density));
+    }
+    if args.next_if(|arg| arg == "attachments").is_some() {
+        return Ok(parse_attachment_args(args)?.map(Command::Attachments));
+    }
+    if args.next_if(|arg| arg == "compare").is_some() {
+        return Ok(parse_compare_args(args)?.map(Command::Compare));
+    }
+    if args.next_if(|arg| arg == "subtitle-ocr").is_some() {
+        return Ok(parse_subtitle_ocr_args(args)?.map(Command::SubtitleOcr));
+    }
+    if args.next_if(|arg| arg == "rehydrate").is_some() {
+        return Ok(parse_rehydrate_args(args)?.map(Command::Rehydrate));
+    }
+    if args.next_if(|arg| arg == "sync").is_some() {
+        return Ok(parse_sync_args(args)?.map(Command::Sync));
+    }
+    let args = expand_preset(args.collect())?;
+    return Ok(
+        parse_extract_args(args.into_iter())?.map(|options| Command::Extract(Box::new(options)))
+    );
+}
+
+/// Replaces `--preset <NAME>` with the preset's options, placed first so the
+/// rest of the command line overrides them
+fn expand_preset(args: Vec<String>) -> Result<Vec<String>, String> {
+    let mut rest = Vec::new();
+    let mut preset = None;
+    let mut config = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--preset" | "--config" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{arg} requires a value"))?;
+                match arg.as_str() {
+                    "--preset" => preset = Some(value),
+                    _ => config = Some(PathBuf::from(value)),
+                }
+            }
+            _ => rest.push(arg),
+        }
+    }
+    let Some(preset) = preset else {
+        if config.is_some() {
+            return Err(String::from("--config requires --preset"));
+        }
+        return Ok(rest);
+    };
+    let mut expanded = Config::load(config.as_deref())?.preset_args(&preset)?;
+    expanded.extend(rest);
+    return Ok(expanded);
+}
+
+fn parse_serve_args<I: Iterator<Item = String>>(
+    mut args: I,
+) -> Result<Option<ServeOptions>, String> {
+    let mut options = ServeOptions {
+        listen: String::from(DEFAULT_LISTEN),
+        ocr: OcrOptions::default(),
+        io: IoStrategy::default(),
+        max_open_files: 0,
+        queue: QueueOptions::default(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            return args
+                .next()
+                .ok_or_else(|| format!("{option} requires a value"));
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--listen" => options.listen = value("--listen")?,
+            "--max-open-files" => {
+                options.max_open_files = parse_count(&value("--max-open-files")?)?;
+            }
+            option @ ("--read-buffer" | "--read-ahead") => {
+                parse_io_option(&mut options.io, option, &value(option)?)?;
+            }
+            #[cfg(feature = "mmap")]
+            "--mmap" => options.io.mmap = true,
+            #[cfg(not(feature = "mmap"))]
+            "--mmap" => return Err(String::from("This build has no mmap support")),
+            option @ ("--decode-jobs" | "--ocr-jobs" | "--max-input-size" | "--max-events"
+            | "--job-timeout" | "--state") => {
+                parse_queue_option(&mut options.queue, option, value(option)?)?;
+            }
+            option if option.starts_with("--ocr-") => {
+                parse_ocr_option(&mut options.ocr, option, value(option)?)?;
+            }
+            other => return Err(format!("Unexpected argument: {other}")),
+        }
+    }
+    return Ok(Some(options));
+}
+
+/// Takes out the options of `watch` itself and checks the rest as extraction
+/// arguments, whose input is the directory
+#[cfg(feature = "watch")]
+fn parse_watch_args<I: Iterator<Item = String>>(
+    mut args: I,
+) -> Result<Option<WatchOptions>, String> {
+    let mut recursive = false;
+    let mut settle = DEFAULT_SETTLE;
+    let mut poll = None;
+    let mut queue = QueueOptions::default();
+    let mut rest = Vec::new();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            return args
+                .next()
+                .ok_or_else(|| format!("{option} requires a value"));
+        };
+        match arg.as_str() {
+            "--recursive" => recursive = true,
+            "--settle" => settle = parse_time(&value("--settle")?)?,
+            "--poll" => {
+                let interval = parse_time(&value("--poll")?)?;
+                if interval == 0 {
+                    return Err(String::from("--poll must not be zero"));
+                }
+                poll = Some(interval);
+            }
+            option @ ("--decode-jobs" | "--ocr-jobs" | "--max-input-size" | "--max-events"
+            | "--job-timeout" | "--state") => {
+                parse_queue_option(&mut queue, option, value(option)?)?;
+            }
+            _ => rest.push(arg),
+        }
+    }
+    let extract_args = expand_preset(rest)?;
+    let Some(options) = parse_extract_args(extract_args.iter().cloned())? else {
+        return Ok(None);
+    };
+    if !options.sidecar
+        || options.output.is_some()
+        || options.mux.is_some()
+        || options.qc_report.is_some()
+        || options.diagnostics.is_some()
+        || options.save_images.is_some()
+        || options.outputs.iter().any(|output| output.path.is_some())
+        || options.dry_run
+        || options.verify
+    {
+        return Err(String::from(
+            "watch requires --sidecar, and can't be combined with --output, --mux, --qc-report, --diagnostics, --save-images, --out with a file, --dry-run or --verify, whose files every input would overwrite",
+        ));
+    }
+    if options.input == Path::new(STDIN) {
+        return Err(String::from("watch requires a directory"));
+    }
+    return Ok(Some(WatchOptions {
+        directory: options.input,
+        recursive,
+        settle,
+        poll,
+        queue,
+        extract_args,
+    }));
+}
+
+fn parse_sheet_args<I: Iterator<Item = String>>(
+    mut args: I,
+) -> Result<Option<SheetOptions>, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut track = None;
+    let mut layout = SheetLayout::default();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            return args
+                .next()
+                .ok_or_else(|| format!("{name} requires a value"));
+        };
+        let number = |value: String| {
+            return value
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid number: {value}"));
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--track" => {
+                let value = value("--track")?;
+                track = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid track number: {value}"))?,
+                );
+            }
+            "--columns" => layout.columns = number(value("--columns")?)?,
+            "--rows" => layout.rows = number(value("--rows")?)?,
+            "--thumb-size" => {
+                (layout.thumb_width, layout.thumb_height) = parse_size(&value("--thumb-size")?)?;
+            }
+            "-o" | "--output" => output = Some(PathBuf::from(value("--output")?)),
+            other if other.starts_with('-') && other.len() > 1 => {
+                return Err(format!("Unknown option: {other}"));
+            }
+            _ => {
+                if input.replace(PathBuf::from(arg)).is_some() {
+                    return Err(String::from("Only one input file may be given"));
+                }
+            }
+        }
+    }
+    return Ok(Some(SheetOptions {
+        input: input.ok_or_else(|| String::from("No input file given"))?,
+        track,
+        output: output.ok_or_else(|| String::from("contact-sheet requires --output"))?,
+        layout,
+    }));
+}
+
+fn parse_palette_args<I: Iterator<Item = String>>(
+    mut args: I,
+) -> Result<Option<PaletteOptions>, String> {
+    let mut input = None;
+    let mut track = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--track" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| String::from("--track requires a value"))?;
+                track = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid track number: {value}"))?,
+                );
+            }
+            other if other.starts_with('-') && other.len() > 1 => {
+                return Err(format!("Unknown option: {other}"));
+            }
+            _ => {
+                if input.replace(PathBuf::from(arg)).is_some() {
+                    return Err(String::from("Only one input file may be given"));
+                }
+            }
+        }
+    }
+    return Ok(Some(PaletteOptions {
+        input: input.ok_or_else(|| String::from("No input file given"))?,
+        track,
+    }));
+}
+
+fn parse_density_args<I: Iterator<Item = String>>(
+    mut args: I,
+) -> Result<Option<DensityOptions>, String> {
+    let mut input = None;
+    let mut track = None;
+    let mut bucket = DEFAULT_BUCKET;
+    let mut gap = DEFAULT_GAP;
+    let mut tail_gap = DEFAULT_TAIL_GAP;
+    let mut json = false;
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            return args
+                .next()
+                .ok_or_else(|| format!("{option} requires a value"));
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--track" => {
+                let value = value("--track")?;
+                track = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid track number: {value}"))?,
+                );
+            }
+            "--bucket" => {
+                bucket = parse_time(&value("--bucket")?)?;
+                if bucket == 0 {
+                    return Err(String::from("--bucket must not be zero"));
+                }
+            }
+            "--gap" => gap = parse_time(&value("--gap")?)?,
+            "--tail-gap" => tail_gap = parse_time(&value("--tail-gap")?)?,
+            "--json" => json = true,
+            other if other.starts_with('-') && other.len() > 1 => {
+                return Err(format!("Unknown option: {other}"));
+            }
+            _ => {
+                if input.replace(PathBuf::from(arg)).is_some() {
+                    return Err(String::from("Only one input file may be given"));
+                }
+            }
+        }
+    }
+    return Ok(Some(DensityOptions {
+        input: input.ok_or_else(|| String::from("No input file given"))?,
+        track,
+        bucket,
+        gap,
+        tail_gap,
+        json,
+    }));
+}
+
+fn parse_compare_args<I: Iterator<Item = String>>(
+    mut args: I,
+) -> Result<Option<CompareOptions>, String> {
+    let mut input = None;
+    let mut track = None;
+    let mut reference = None;
+    let mut backgrounds = Vec::new();
+    let mut flatten = FlattenOptions::default();
+    let mut ocr = OcrOptions::default();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            return args
+                .next()
+                .ok_or_else(|| format!("{option} requires a value"));
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--track" => {
+                let value = value("--track")?;
+                track = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid track number: {value}"))?,
+                );
+            }
+            "--reference" => reference = Some(PathBuf::from(value("--reference")?)),
+            "--background" => backgrounds.push(value("--background")?.parse()?),
+            "--min-alpha" => flatten.min_alpha = parse_byte(&value("--min-alpha")?)?,
+            "--binarize" => flatten.binarize = Some(parse_byte(&value("--binarize")?)?),
+            "--strip-outline" => flatten.strip_outline = true,
+            option if option.starts_with("--ocr-") => {
+                parse_ocr_option(&mut ocr, option, value(option)?)?;
+            }
+            other if other.starts_with('-') && other.len() > 1 => {
+                return Err(format!("Unknown option: {other}"));
+            }
+            _ => {
+                if input.replace(PathBuf::from(arg)).is_some() {
+                    return Err(String::from("Only one input file may be given"));
+                }
+            }
+        }
+    }
+    if backgrounds.is_empty() {
+        backgrounds = Background::ALL.to_vec();
+    }
+    return Ok(Some(CompareOptions {
+        input: input.ok_or_else(|| String::from("No input file given"))?,
+        track,
+        reference: reference.ok_or_else(|| String::from("compare requires --reference"))?,
+        backgrounds,
+        flatten,
+        ocr,
+    }));
+}
+
+fn parse_subtitle_ocr_args<I: Iterator<Item = String>>(
+    mut args: I,
+) -> Result<Option<SubtitleOcrOptions>, String> {
+    let mut directory = None;
+    let mut output = None;
+    let mut regions = RegionPolicy::default();
+    let mut position_tags = false;
+    let mut flatten = FlattenOptions::default();
+    let mut ocr = OcrOptions::default();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            return args
+                .next()
+                .ok_or_else(|| format!("{option} requires a value"));
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => output = Some(PathBuf::from(value("--output")?)),
+            "--regions" => regions = parse_region_policy(&value("--regions")?)?,
+            "--position-tags" => position_tags = true,
+            "--min-alpha" => flatten.min_alpha = parse_byte(&value("--min-alpha")?)?,
+            "--binarize" => flatten.binarize = Some(parse_byte(&value("--binarize")?)?),
+            "--background" => flatten.background = value("--background")?.parse()?,
+            "--strip-outline" => flatten.strip_outline = true,
+            option if option.starts_with("--ocr-") => {
+                parse_ocr_option(&mut ocr, option, value(option)?)?;
+            }
+            other if other.starts_with('-') && other.len() > 1 => {
+                return Err(format!("Unknown option: {other}"));
+            }
+            _ => {
+                if directory.replace(PathBuf::from(arg)).is_some() {
+                    return Err(String::from("Only one image directory may be given"));
+                }
+            }
+        }
+    }
+    return Ok(Some(SubtitleOcrOptions {
+        directory: directory.ok_or_else(|| String::from("No image directory given"))?,
+        output: output.ok_or_else(|| String::from("subtitle-ocr requires --output"))?,
+        regions,
+        position_tags,
+        flatten,
+        ocr,
+    }));
+}
+
+fn parse_rehydrate_args<I: Iterator<Item = String>>(
+    mut args: I,
+) -> Result<Option<RehydrateOptions>, String> {
+    let mut manifest = None;
+    let mut text = None;
+    let mut output = None;
+    let mut json = None;
+    let mut mux = None;
+    let mut source = None;
+    let mut position_tags = false;
+    let mut forced_only = false;
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            return args
+                .next()
+                .map(PathBuf::from)
+                .ok_or_else(|| format!("{option} requires a value"));
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--text" => text = Some(value("--text")?),
+            "-o" | "--output" => output = Some(value("--output")?),
+            "--json" => json = Some(value("--json")?),
+            "--mux" => mux = Some(value("--mux")?),
+            "--source" => source = Some(value("--source")?),
+            "--position-tags" => position_tags = true,
+            "--forced-only" => forced_only = true,
+            other if other.starts_with('-') && other.len() > 1 => {
+                return Err(format!("Unknown option: {other}"));
+            }
+            _ => {
+                if manifest.replace(PathBuf::from(arg)).is_some() {
+                    return Err(String::from("Only one manifest may be given"));
+                }
+            }
+        }
+    }
+    if output.is_none() && json.is_none() && mux.is_none() {
+        return Err(String::from("rehydrate requires --output, --json or --mux"));
+    }
+    if source.is_some() && mux.is_none() {
+        return Err(String::from("--source requires --mux"));
+    }
+    return Ok(Some(RehydrateOptions {
+        manifest: manifest.ok_or_else(|| String::from("No manifest given"))?,
+        text: text.ok_or_else(|| String::from("rehydrate requires --text"))?,
+        output,
+        json,
+        mux,
+        source,
+        position_tags,
+        forced_only,
+    }));
+}
+
+fn parse_sync_args<I: Iterator<Item = String>>(
+    mut args: I,
+) -> Result<Option<SyncCommandOptions>, String> {
+    let mut input = None;
+    let mut reference = None;
+    let mut output = None;
+    let mut sync = SyncOptions::default();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            return args
+                .next()
+                .ok_or_else(|| format!("{option} requires a value"));
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--reference" => reference = Some(PathBuf::from(value("--reference")?)),
+            "-o" | "--output" => output = Some(PathBuf::from(value("--output")?)),
+            "--max-offset" => sync.max_offset = parse_time(&value("--max-offset")?)?,
+            "--no-scale" => sync.scale = false,
+            other if other.starts_with('-') && other.len() > 1 => {
+                return Err(format!("Unknown option: {other}"));
+            }
+            _ => {
+                if input.replace(PathBuf::from(arg)).is_some() {
+                    return Err(String::from("Only one input file may be given"));
+                }
+            }
+        }
+    }
+    return Ok(Some(SyncCommandOptions {
+        input: input.ok_or_else(|| String::from("No input file given"))?,
+        reference: reference.ok_or_else(|| String::from("sync requires --reference"))?,
+        output: output.ok_or_else(|| String::from("sync requires --output"))?,
+        sync,
+    }));
+}
+
+fn parse_attachment_args<I: Iterator<Item = String>>(
+    mut args: I,
+) -> Result<Option<AttachmentOptions>, String> {
+    let mut input = None;
+    let mut extract = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--extract" => {
+                extract = Some(PathBuf::from(
+                    args.next()
+                        .ok_or_else(|| String::from("--extract requires a value"))?,
+                ));
+            }
+            other if other.starts_with('-') && other.len() > 1 => {
+                return Err(format!("Unknown option: {other}"));
+            }
+            _ => {
+                if input.replace(PathBuf::from(arg)).is_some() {
+                    return Err(String::from("Only one input file may be given"));
+                }
+            }
+        }
+    }
+    return Ok(Some(AttachmentOptions {
+        input: input.ok_or_else(|| String::from("No input file given"))?,
+        extract,
+    }));
+}
+
+fn parse_extract_args<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Options>, String> {
+    let mut options = Options::default();
+    let mut input = None;
+    let mut scale = None;
+    let mut scale_filter = ScaleFilter::default();
+    let mut reading_speed = false;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            return args
+                .next()
+                .ok_or_else(|| format!("{name} requires a value"));
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--track" => {
+                let track = value("--track")?;
+                options.track = Some(
+                    track
+                        .parse()
+                        .map_err(|_| format!("Invalid track number: {track}"))?,
+                );
+            }
+            "--start" => options.start = Some(parse_time(&value("--start")?)?),
+            "--filter" => {
+                let expression = value("--filter")?;
+                let filter = Filter::parse(&expression).map_err(|err| err.to_string())?;
+                // Repeated filters must all match
+                options.filter = Some(match options.filter.take() {
+                    Some(previous) => Filter::And(Box::new(previous), Box::new(filter)),
+                    None => filter,
+                });
+            }
+            "--keep-non-text" => options.keep_non_text = true,
+            "--min-ink" => {
+                let value = value("--min-ink")?;
+                options.non_text.min_ink = value
+                    .parse()
+                    .map_err(|_| format!("Invalid pixel count: {value}"))?;
+            }
+            "-o" | "--output" => options.output = Some(PathBuf::from(value("--output")?)),
+            "--output-format" => {
+                options.output_format = OutputFormat::from_name(&value("--output-format")?)?;
+            }
+            "--out" => options.outputs.push(OutputSpec::parse(&value("--out")?)?),
+            "--frame-rate" => options.frame_rate = Some(value("--frame-rate")?.parse()?),
+            "--sidecar" => options.sidecar = true,
+            "--split-chapters" => options.split_chapters = true,
+            "--ordered-chapters" => options.ordered_chapters = true,
+            "--ascii-punctuation" => options.ascii_punctuation = true,
+            "--replace" => options
+                .replacements
+                .push(Replace::parse(&value("--replace")?)?),
+            "--wrap" => _ = options.wrap.get_or_insert_default(),
+            "--line-length" => {
+                options.wrap.get_or_insert_default().max_line_length =
+                    parse_count(&value("--line-length")?)?;
+            }
+            "--max-lines" => {
+                options.wrap.get_or_insert_default().max_lines =
+                    parse_count(&value("--max-lines")?)?;
+            }
+            "--overlaps" => {
+                options.timing.get_or_insert_default().overlaps =
+                    match value("--overlaps")?.as_str() {
+                        "trim" => OverlapPolicy::Trim,
+                        "merge" => OverlapPolicy::Merge,
+                        other => return Err(format!("Unknown overlap policy: {other}")),
+                    };
+            }
+            "--min-gap" => {
+                let gap = value("--min-gap")?;
+                options.timing.get_or_insert_default().min_gap = gap
+                    .parse()
+                    .ok()
+                    .and_then(Timestamp::from_millis)
+                    .ok_or_else(|| format!("Invalid gap: {gap}"))?
+                    .as_nanos();
+            }
+            "--max-duration" => {
+                let duration = parse_time(&value("--max-duration")?)?;
+                if duration == 0 {
+                    return Err(String::from("--max-duration must not be zero"));
+                }
+                options.timing.get_or_insert_default().max_duration = Some(duration);
+            }
+            "--snap-keyframes" => {
+                let window = value("--snap-keyframes")?;
+                let window = window
+                    .parse()
+                    .ok()
+                    .and_then(Timestamp::from_millis)
+                    .ok_or_else(|| format!("Invalid keyframe window: {window}"))?;
+                options.keyframe_window = Some(window.as_nanos());
+            }
+            "--sync-to" => options.sync_to = Some(PathBuf::from(value("--sync-to")?)),
+            "--qc-report" => options.qc_report = Some(PathBuf::from(value("--qc-report")?)),
+            "--diagnostics" => {
+                options.diagnostics = Some(PathBuf::from(value("--diagnostics")?));
+            }
+            "--max-cps" => {
+                options.reading_speed.max_cps = parse_rate(&value("--max-cps")?)?;
+                reading_speed = true;
+            }
+            "--max-wpm" => {
+                options.reading_speed.max_wpm = parse_rate(&value("--max-wpm")?)?;
+                reading_speed = true;
+            }
+            "--min-duration" => {
+                options.reading_speed.min_duration = parse_time(&value("--min-duration")?)?;
+                reading_speed = true;
+            }
+            "--save-images" => {
+                options.save_images = Some(PathBuf::from(value("--save-images")?));
+            }
+            "--indexed" => options.indexed = true,
+            "--color-matrix" => options.color_matrix = value("--color-matrix")?.parse()?,
+            "--composite" => options.composite = true,
+            "--preview" => options.preview = true,
+            "--preview-mode" => {
+                options.preview_mode = match value("--preview-mode")?.as_str() {
+                    "auto" => PreviewMode::Auto,
+                    #[cfg(feature = "preview")]
+                    "sixel" => PreviewMode::Sixel,
+                    #[cfg(not(feature = "preview"))]
+                    "sixel" => return Err(String::from("This build has no sixel support")),
+                    "blocks" => PreviewMode::Blocks,
+                    other => return Err(format!("Unknown preview mode: {other}")),
+                };
+            }
+            "--preview-background" => {
+                let color = value("--preview-background")?;
+                let mut background = Rgb([0, 0, 0]);
+                hex::decode_to_slice(color.trim_start_matches('#'), &mut background.0)
+                    .map_err(|_| format!("Invalid color: {color}"))?;
+                options.preview_background = Some(background);
+            }
+            "--preview-width" => {
+                let width = value("--preview-width")?;
+                options.preview_width = Some(
+                    PreviewWidth::parse(&width)
+                        .ok_or_else(|| format!("Invalid preview width: {width}"))?,
+                );
+            }
+            "--mux" => options.mux = Some(PathBuf::from(value("--mux")?)),
+            "--language" => options.language = Some(value("--language")?),
+            "--forced" => options.forced = true,
+            "--sdh" => options.sdh = true,
+            "--strip-sdh" => options.strip_sdh = true,
+            "--palette" => {
+                let palette = value("--palette")?;
+                options.palette = Some(parse_palette(&palette).ok_or_else(|| {
+                    format!("Invalid palette, expected 16 comma-separated rrggbb colors: {palette}")
+                })?);
+            }
+            "--regions" => options.regions = parse_region_policy(&value("--regions")?)?,
+            "--position-tags" => options.position_tags = true,
+            "--split-languages" => {
+                let languages = value("--split-languages")?;
+                let (upper, lower) = languages
+                    .split_once(',')
+                    .filter(|(upper, lower)| !upper.is_empty() && !lower.is_empty())
+                    .ok_or_else(|| {
+                        format!("Expected two comma-separated languages: {languages}")
+                    })?;
+                options.split_languages = Some((upper.to_owned(), lower.to_owned()));
+            }
+            "--scale" => {
+                let (width, height) = parse_size(&value("--scale")?)?;
+                if width == 0 || height == 0 {
+                    return Err(String::from("--scale must not be zero"));
+                }
+                scale = Some(Transform::new(width, height));
+            }
+            "--scale-filter" => {
+                scale_filter = match value("--scale-filter")?.as_str() {
+                    "bilinear" => ScaleFilter::Bilinear,
+                    "nearest" => ScaleFilter::Nearest,
+                    other => return Err(format!("Unknown scale filter: {other}")),
+                };
+            }
+            "--min-alpha" => options.flatten.min_alpha = parse_byte(&value("--min-alpha")?)?,
+            "--binarize" => options.flatten.binarize = Some(parse_byte(&value("--binarize")?)?),
+            "--background" => options.flatten.background = value("--background")?.parse()?,
+            "--strip-outline" => options.flatten.strip_outline = true,
+            "--set-track-language" => options.set_track_language = true,
+            option @ ("--read-buffer" | "--read-ahead") => {
+                parse_io_option(&mut options.io, option, &value(option)?)?;
+            }
+            #[cfg(feature = "mmap")]
+            "--mmap" => options.io.mmap = true,
+            #[cfg(not(feature = "mmap"))]
+            "--mmap" => return Err(String::from("This build has no mmap support")),
+            "--dry-run" => options.dry_run = true,
+            "--verify" => options.verify = true,
+            option if option.starts_with("--ocr-") => {
+                parse_ocr_option(&mut options.ocr, option, value(option)?)?;
+            }
+            other if other.starts_with('-') && other.len() > 1 => {
+                return Err(format!("Unknown option: {other}"));
+            }
+            _ => {
+                if input.replace(PathBuf::from(arg)).is_some() {
+                    return Err(String::from("Only one input file may be given"));
+                }
+            }
+        }
+    }
+    options.input = input.ok_or_else(|| String::from("No input file given"))?;
+    if options.input == Path::new(STDIN)
+        && (options.start.is_some()
+            || options.sidecar
+            || options.outputs.iter().any(|output| output.path.is_none())
+            || options.mux.is_some()
+            || options.split_chapters
+            || options.ordered_chapters
+            || options.keyframe_window.is_some()
+            || options.set_track_language
+            || options.dry_run
+            || options.verify)
+    {
+        return Err(String::from(
+            "Reading from stdin can't be combined with --start, --sidecar, --out without a file, --mux, --split-chapters, --ordered-chapters, --snap-keyframes, --set-track-language, --dry-run or --verify, which seek in or reopen the input",
+        ));
+    }
+    options.scale = scale.map(|scale| Transform {
+        filter: scale_filter,
+        ..scale
+    });
+    if options.split_chapters && options.output.is_none() {
+        return Err(String::from("--split-chapters requires --output"));
+    }
+    if options.ordered_chapters
+        && (options.mux.is_some() || options.split_chapters || !options.has_outputs())
+    {
+        return Err(String::from(
+            "--ordered-chapters requires --output or --sidecar, and can't be combined with --mux or --split-chapters",
+        ));
+    }
+    if reading_speed && options.qc_report.is_none() {
+        return Err(String::from(
+            "--max-cps, --max-wpm and --min-duration require --qc-report",
+        ));
+    }
+    if options.timing.is_some() && !options.has_outputs() {
+        return Err(String::from(
+            "--overlaps, --min-gap and --max-duration require an output",
+        ));
+    }
+    if options.keyframe_window.is_some() && (options.ordered_chapters || !options.has_outputs()) {
+        return Err(String::from(
+            "--snap-keyframes requires an output, and can't be combined with --ordered-chapters",
+        ));
+    }
+    if options.sync_to.is_some() && !options.has_outputs() {
+        return Err(String::from("--sync-to requires an output"));
+    }
+    if options.diagnostics.is_some() && !options.has_outputs() {
+        return Err(String::from("--diagnostics requires an output"));
+    }
+    if options.wrap.is_some() && !options.has_outputs() {
+        return Err(String::from("--wrap requires an output"));
+    }
+    if options.verify && (options.has_outputs() || options.dry_run || options.composite) {
+        return Err(String::from(
+            "--verify can't be combined with outputs, --dry-run or --composite",
+        ));
+    }
+    if options.split_languages.is_some()
+        && (options.mux.is_some()
+            || options.qc_report.is_some()
+            || options.split_chapters
+            || options.ordered_chapters
+            || options.set_track_language
+            || !options.has_outputs())
+    {
+        return Err(String::from(
+            "--split-languages requires --output or --sidecar, and can't be combined with --mux, --qc-report, --split-chapters, --ordered-chapters or --set-track-language",
+        ));
+    }
+    if options.indexed && options.save_images.is_none() && !options.composite {
+        return Err(String::from(
+            "--indexed requires --save-images or --composite",
+        ));
+    }
+    return Ok(Some(options));
+}
+
+/// Applies one of the `--ocr-*` options, which take a value each
+fn parse_ocr_option(ocr: &mut OcrOptions, option: &str, value: String) -> Result<(), String> {
+    match option {
+        "--ocr-command" | "--ocr-url" => ocr.backend = parse_ocr_backend(option, value)?,
+        "--ocr-whitelist" | "--ocr-blacklist" | "--ocr-psm" => {
+            parse_ocr_constraint(&mut ocr.backend, option, value)?;
+        }
+        "--ocr-language" => {
+            if !matches!(ocr.backend, OcrBackend::Tesseract(_)) {
+                return Err(format!("{option} only applies to Tesseract"));
+            }
+            ocr.language = Some(value);
+        }
+        "--ocr-cache" => ocr.cache = Some(PathBuf::from(value)),
+        "--ocr-retry" => {
+            let threshold = value
+                .parse()
+                .ok()
+                .filter(|threshold| (0.0..=100.0).contains(threshold))
+                .ok_or_else(|| format!("Expected a confidence from 0 to 100: {value}"))?;
+            ocr.retry = Some(RetryPolicy::new(threshold));
+        }
+        other => return Err(format!("Unknown option: {other}")),
+    }
+    return Ok(());
+}
+
+fn parse_io_option(io: &mut IoStrategy, option: &str, value: &str) -> Result<(), String> {
+    match option {
+        "--read-buffer" => io.buffer_size = parse_bytes(value)?,
+        _ => {
+            io.read_ahead = value
+                .parse()
+                .map_err(|_| format!("Invalid read-ahead count: {value}"))?;
+        }
+    }
+    return Ok(());
+}
+
+/// Applies one of the job queue options, which take a value each
+fn parse_queue_option(queue: &mut QueueOptions, option: &str, value: String) -> Result<(), String> {
+    match option {
+        "--decode-jobs" => queue.limits.decode = parse_count(&value)?,
+        "--ocr-jobs" => queue.limits.ocr = parse_count(&value)?,
+        "--max-input-size" => queue.job.max_input_size = Some(parse_bytes(&value)? as u64),
+        "--max-events" => queue.job.max_events = Some(parse_count(&value)?),
+        "--job-timeout" => queue.job.timeout = Some(parse_time(&value)?),
+        _ => queue.state = Some(PathBuf::from(value)),
+    }
+    return Ok(());
+}
+
+fn parse_ocr_backend(option: &str, value: String) -> Result<OcrBackend, String> {
+    if option == "--ocr-url" {
+        return Ok(OcrBackend::Http(value));
+    }
+    return CommandEngine::from_command_line(&value)
+        .map(OcrBackend::Command)
+        .ok_or_else(|| String::from("--ocr-command must not be empty"));
+}
+
+/// Applies one of the Tesseract-only `--ocr-*` options
+fn parse_ocr_constraint(ocr: &mut OcrBackend, option: &str, value: String) -> Result<(), String> {
+    let OcrBackend::Tesseract(constraints) = ocr else {
+        return Err(format!("{option} only applies to Tesseract"));
+    };
+    match option {
+        "--ocr-whitelist" => constraints.whitelist = Some(charset(&value).unwrap_or(value)),
+        "--ocr-blacklist" => constraints.blacklist = Some(value),
+        _ => {
+            let mode = value
+                .parse()
+                .ok()
+                .filter(|mode| *mode <= 13)
+                .ok_or_else(|| format!("Invalid page segmentation mode: {value}"))?;
+            constraints.page_seg_mode = Some(mode);
+        }
+    }
+    return Ok(());
+}
+
+fn parse_region_policy(value: &str) -> Result<RegionPolicy, String> {
+    return match value {
+        "merge" => Ok(RegionPolicy::Merge),
+        "separate" => Ok(RegionPolicy::Separate),
+        "position" => Ok(RegionPolicy::Position),
+        other => Err(format!("Unknown region policy: {other}")),
+    };
+}
+
+/// Parses `<W>x<H>`
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("Invalid size: {value}");
+    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+    return Ok((
+        width.parse().map_err(|_| invalid())?,
+        height.parse().map_err(|_| invalid())?,
+    ));
+}
+
+/// Parses a line length or count, which must be at least 1
+fn parse_count(value: &str) -> Result<usize, String> {
+    return value
+        .parse()
+        .ok()
+        .filter(|count| *count > 0)
+        .ok_or_else(|| format!("Expected a positive whole number: {value}"));
+}
+
+/// Parses a positive CPS or WPM limit
+fn parse_rate(value: &str) -> Result<f64, String> {
+    return value
+        .parse()
+        .ok()
+        .filter(|rate: &f64| rate.is_finite() && *rate > 0.0)
+        .ok_or_else(|| format!("Expected a positive number: {value}"));
+}
+
+/// Parses a positive byte count, optionally with a K, M or G (binary) suffix
+fn parse_bytes(value: &str) -> Result<usize, String> {
+    let invalid = || format!("Invalid size: {value} (expected e.g. 65536, 64K or 4M)");
+    let (digits, unit) = match value.to_ascii_uppercase().trim_end_matches('B') {
+        rest if rest.ends_with('K') => (rest.len() - 1, 1 << 10),
+        rest if rest.ends_with('M') => (rest.len() - 1, 1 << 20),
+        rest if rest.ends_with('G') => (rest.len() - 1, 1 << 30),
+        rest => (rest.len(), 1),
+    };
+    let count: usize = value[..digits].parse().map_err(|_| invalid())?;
+    return count
+        .checked_mul(unit)
+        .filter(|bytes| *bytes > 0)
+        .ok_or_else(invalid);
+}
+
+fn parse_byte(value: &str) -> Result<u8, String> {
+    return value
+        .parse()
+        .map_err(|_| format!("Expected a number from 0 to 255: {value}"));
+}
+
+/// Parses `HH:MM:SS[.mmm]`, `MM:SS[.mmm]` or plain seconds into nanoseconds
+fn parse_time(value: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid time: {value}");
+    let mut seconds = 0.0;
+    for part in value.split(':') {
+        let part: f64 = part.parse().map_err(|_| invalid())?;
+        if !part.is_finite() || part < 0.0 {
+            return Err(invalid());
+        }
+        seconds = seconds * 60.0 + part;
+    }
+    return Timestamp::from_secs_f64(seconds)
+        .map(Timestamp::as_nanos)
+        .ok_or_else(invalid);
+}
@@ -0,0 +1,86 @@
+//! Converting PGS palette entries (limited range YCrCb) to RGB. Blu-ray uses
+//! the BT.709 matrix for HD video and BT.601 for SD, and picking the wrong one
+//! shifts hues, most visibly in yellow and colored captions.
+
+use std::{fmt, str::FromStr};
+
+use image::{Rgb, Rgba};
+
+use crate::bdsup::PaletteEntry;
+
+/// Largest video height that counts as SD (576 for PAL, 480 for NTSC)
+const SD_MAX_HEIGHT: u16 = 576;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorMatrix {
+    Bt601,
+    Bt709,
+    /// BT.601 for SD video, BT.709 for anything larger
+    #[default]
+    Auto,
+}
+impl ColorMatrix {
+    /// The matrix to use for video `height` pixels tall, resolving
+    /// [`ColorMatrix::Auto`]
+    pub fn resolve(self, height: u16) -> Self {
+        return match self {
+            ColorMatrix::Auto if height <= SD_MAX_HEIGHT => ColorMatrix::Bt601,
+            ColorMatrix::Auto => ColorMatrix::Bt709,
+            matrix => matrix,
+        };
+    }
+
+    /// Kr and Kb, the luma weights of red and blue
+    fn coefficients(self) -> (f32, f32) {
+        return match self {
+            ColorMatrix::Bt601 => (0.299, 0.114),
+            ColorMatrix::Bt709 | ColorMatrix::Auto => (0.2126, 0.0722),
+        };
+    }
+}
+impl fmt::Display for ColorMatrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f.write_str(match self {
+            ColorMatrix::Bt601 => "bt601",
+            ColorMatrix::Bt709 => "bt709",
+            ColorMatrix::Auto => "auto",
+        });
+    }
+}
+impl FromStr for ColorMatrix {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        return match value.to_ascii_lowercase().replace('.', "").as_str() {
+            "bt601" | "601" => Ok(ColorMatrix::Bt601),
+            "bt709" | "709" => Ok(ColorMatrix::Bt709),
+            "auto" => Ok(ColorMatrix::Auto),
+            _ => Err(format!(
+                "Invalid color matrix: {value} (expected auto, bt601 or bt709)"
+            )),
+        };
+    }
+}
+
+/// Converts a palette entry's color with `matrix`. [`ColorMatrix::Auto`]
+/// means BT.709 here, since there's no video size to go by; use
+/// [`ColorMatrix::resolve`] first.
+pub fn to_rgb(entry: &PaletteEntry, matrix: ColorMatrix) -> Rgb<u8> {
+    let (kr, kb) = matrix.coefficients();
+    let kg = 1.0 - kr - kb;
+    // Expand limited range (16-235 luma, 16-240 chroma) to full range
+    let y = (entry.luminance as f32 - 16.0) * 255.0 / 219.0;
+    let cr = (entry.color_diff_red as f32 - 128.0) * 255.0 / 224.0;
+    let cb = (entry.color_diff_blue as f32 - 128.0) * 255.0 / 224.0;
+    let r = y + 2.0 * (1.0 - kr) * cr;
+    let b = y + 2.0 * (1.0 - kb) * cb;
+    let g = (y - kr * r - kb * b) / kg;
+    let channel = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+    return Rgb([channel(r), channel(g), channel(b)]);
+}
+
+/// [`to_rgb`], with the entry's transparency as alpha
+pub fn to_rgba(entry: &PaletteEntry, matrix: ColorMatrix) -> Rgba<u8> {
+    let Rgb([r, g, b]) = to_rgb(entry, matrix);
+    return Rgba([r, g, b, entry.transparency]);
+}
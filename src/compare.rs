@@ -0,0 +1,84 @@
+//! Scoring recognized cues against a reference transcript, for picking OCR
+//! settings on a track that already has good text subtitles (or a corrected
+//! SRT). Cues are matched up by time, and the score is the character error
+//! rate: edits needed to turn the recognized text into the reference, per
+//! reference character.
+
+use crate::srt::{SrtCue, strip_tags};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Score {
+    /// Characters inserted, deleted or substituted
+    pub errors: usize,
+    /// Characters in the reference
+    pub characters: usize,
+}
+impl Score {
+    /// Errors per reference character. Can be over 1 when the recognized
+    /// text has a lot of extra characters.
+    pub fn error_rate(&self) -> f64 {
+        if self.characters == 0 {
+            return 0.0;
+        }
+        return self.errors as f64 / self.characters as f64;
+    }
+}
+
+/// Scores `cues` against `reference`. Each cue is compared with the
+/// reference cue it overlaps the most; cues overlapping none count as
+/// entirely wrong.
+pub fn score(cues: &[SrtCue], reference: &[SrtCue]) -> Score {
+    let mut matched: Vec<Vec<Vec<char>>> = vec![Vec::new(); reference.len()];
+    let mut score = Score::default();
+    for cue in cues {
+        let text = normalize(&cue.text);
+        if text.is_empty() {
+            continue;
+        }
+        let best = reference
+            .iter()
+            .enumerate()
+            .map(|(index, other)| {
+                let overlap = cue
+                    .end
+                    .min(other.end)
+                    .saturating_sub(cue.start.max(other.start));
+                return (overlap, index);
+            })
+            .filter(|(overlap, _)| *overlap > 0)
+            .max_by_key(|(overlap, index)| (*overlap, std::cmp::Reverse(*index)));
+        match best {
+            Some((_, index)) => matched[index].push(text),
+            None => score.errors += text.len(),
+        }
+    }
+    for (cue, texts) in reference.iter().zip(matched) {
+        let expected = normalize(&cue.text);
+        let recognized = texts.join(&' ');
+        score.errors += edit_distance(&recognized, &expected);
+        score.characters += expected.len();
+    }
+    return score;
+}
+
+/// The text without tags, with line breaks and runs of spaces as one space
+fn normalize(text: &str) -> Vec<char> {
+    let text = strip_tags(text);
+    let words: Vec<&str> = text.split_whitespace().collect();
+    return words.join(" ").chars().collect();
+}
+
+/// Levenshtein distance
+pub fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, x) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(x != y);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    return previous[b.len()];
+}
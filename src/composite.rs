@@ -0,0 +1,166 @@
+//! Draws subtitles over the video frame they appear on, to check positioning
+//! and palette decoding against the real picture. Frames are grabbed by
+//! running `ffmpeg`, so it needs to be installed. [`render_overlay`] and
+//! [`overlay_at`] leave the compositing to the caller instead, for burning
+//! subtitles into video in a transcoding pipeline.
+
+use std::{
+    io::{self, Read, Seek},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use image::{ImageError, Rgb, RgbImage, Rgba, RgbaImage};
+use thiserror::Error;
+
+use crate::{
+    extract::{EventPayload, ExtractError, SubtitleEvent, SubtitleExtractor},
+    preprocess::Placement,
+    sink::{EventSink, SinkError},
+    transform::Transform,
+};
+
+#[derive(Error, Debug)]
+pub enum CompositeError {
+    #[error("Failed to run ffmpeg: {0}")]
+    Io(#[from] io::Error),
+    #[error("ffmpeg failed: {0}")]
+    Ffmpeg(String),
+    #[error("Failed to read frame from ffmpeg: {0}")]
+    Image(#[from] ImageError),
+}
+impl From<CompositeError> for SinkError {
+    fn from(err: CompositeError) -> Self {
+        return SinkError::Other(err.to_string());
+    }
+}
+
+/// Grabs the frame shown at `timestamp` (nanoseconds) from `video`
+pub fn grab_frame(ffmpeg: &str, video: &Path, timestamp: u64) -> Result<RgbImage, CompositeError> {
+    let output = Command::new(ffmpeg)
+        .args(["-v", "error", "-ss"])
+        .arg(format!("{:.3}", timestamp as f64 / 1_000_000_000.0))
+        .arg("-i")
+        .arg(video)
+        .args(["-frames:v", "1", "-f", "image2pipe", "-c:v", "png", "-"])
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() || output.stdout.is_empty() {
+        return Err(CompositeError::Ffmpeg(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+    return Ok(image::load_from_memory(&output.stdout)?.to_rgb8());
+}
+
+/// Blends an image event onto `frame` where it's shown, scaled to the frame's
+/// size. Indexed images are drawn in their palette colors, others in gray.
+/// Events without a placement are centered near the bottom.
+pub fn overlay(frame: &mut RgbImage, event: &SubtitleEvent) {
+    let (width, height) = frame.dimensions();
+    draw(event, width, height, |x, y, Rgba([r, g, b, alpha])| {
+        let background = frame.get_pixel_mut(x, y);
+        let blend = |over: u8, under: u8| {
+            return ((over as u32 * alpha as u32 + under as u32 * (255 - alpha as u32)) / 255)
+                as u8;
+        };
+        *background = Rgb([
+            blend(r, background.0[0]),
+            blend(g, background.0[1]),
+            blend(b, background.0[2]),
+        ]);
+    });
+}
+
+/// Renders an image event onto a transparent `width`x`height` canvas, placed
+/// and colored as [`overlay`] would draw it, ready to be composited over a
+/// video frame of that size. Alpha isn't premultiplied. Text events give an
+/// empty canvas.
+pub fn render_overlay(event: &SubtitleEvent, width: u32, height: u32) -> RgbaImage {
+    let mut canvas = RgbaImage::new(width, height);
+    draw(event, width, height, |x, y, color| {
+        canvas.put_pixel(x, y, color);
+    });
+    return canvas;
+}
+
+/// The overlay ([`render_overlay`]) for the subtitle shown at `timestamp`
+/// (nanoseconds), found with [`SubtitleExtractor::subtitle_at`]. `None` if
+/// there's none, or it's text. Turns on indexed decoding, so PGS and VobSub
+/// subtitles keep the colors of their palettes.
+pub fn overlay_at<R: Read + Seek>(
+    extractor: &mut SubtitleExtractor<R>,
+    timestamp: u64,
+    width: u32,
+    height: u32,
+) -> Result<Option<RgbaImage>, ExtractError> {
+    extractor.set_indexed(true);
+    let Some(event) = extractor.subtitle_at(timestamp)? else {
+        return Ok(None);
+    };
+    if let EventPayload::Text(_) = event.payload {
+        return Ok(None);
+    }
+    return Ok(Some(render_overlay(&event, width, height)));
+}
+
+/// Scales an image event to a `width`x`height` frame and calls `plot` with
+/// the position and color of each of its pixels that land on it. Indexed
+/// images are drawn in their palette colors, others in gray. Events without
+/// a placement are centered near the bottom.
+fn draw(event: &SubtitleEvent, width: u32, height: u32, mut plot: impl FnMut(u32, u32, Rgba<u8>)) {
+    let event = Transform::new(width, height).apply(event.clone());
+    let EventPayload::Image(ref image) = event.payload else {
+        return;
+    };
+    let placement = event.placement.unwrap_or(Placement {
+        x: width.saturating_sub(image.width()) / 2,
+        y: height.saturating_sub(image.height() + height / 20),
+        screen_width: width,
+        screen_height: height,
+    });
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let (frame_x, frame_y) = (placement.x + x, placement.y + y);
+        if frame_x >= width || frame_y >= height {
+            continue;
+        }
+        let color = match event.indexed {
+            Some(ref indexed) => indexed.color(indexed.index(x, y)),
+            None => Rgba([pixel.0[0], pixel.0[0], pixel.0[0], pixel.0[1]]),
+        };
+        plot(frame_x, frame_y, color);
+    }
+}
+
+/// Composites each image event over its video frame and hands the result to
+/// `output`, e.g. to print or save it
+pub struct CompositeSink<F: FnMut(usize, &RgbImage) -> Result<(), SinkError>> {
+    video: PathBuf,
+    ffmpeg: String,
+    output: F,
+}
+impl<F: FnMut(usize, &RgbImage) -> Result<(), SinkError>> CompositeSink<F> {
+    pub fn new(video: impl Into<PathBuf>, output: F) -> Self {
+        return Self {
+            video: video.into(),
+            ffmpeg: String::from("ffmpeg"),
+            output,
+        };
+    }
+
+    /// Runs `ffmpeg` from somewhere other than the `PATH`
+    pub fn with_ffmpeg(mut self, ffmpeg: impl Into<String>) -> Self {
+        self.ffmpeg = ffmpeg.into();
+        return self;
+    }
+}
+impl<F: FnMut(usize, &RgbImage) -> Result<(), SinkError>> EventSink for CompositeSink<F> {
+    fn event(&mut self, index: usize, event: &SubtitleEvent) -> Result<(), SinkError> {
+        if let EventPayload::Text(_) = event.payload {
+            return Ok(());
+        }
+        let mut frame = grab_frame(&self.ffmpeg, &self.video, event.start)?;
+        overlay(&mut frame, event);
+        return (self.output)(index, &frame);
+    }
+}
@@ -0,0 +1,219 @@
+//! Presets from `subproc.toml`, so batch jobs can share a set of options
+//! instead of repeating them on every command line. Each preset is a table of
+//! extraction options, named like their long flags without the dashes:
+//!
+//! ```toml
+//! [preset.anime-bd]
+//! language = "jpn"
+//! regions = "position"
+//! min-alpha = 128
+//! strip-sdh = true
+//! filter = ["duration >= 100ms", "area < 80%"]
+//! ```
+//!
+//! `true` passes a switch, `false` leaves it out and arrays repeat the option.
+//! Presets are turned into arguments that come before the real ones, so
+//! anything given on the command line still wins.
+//!
+//! Only the part of TOML needed for this is understood: tables, and string,
+//! number, boolean and single-line array values.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub const CONFIG_FILE: &str = "subproc.toml";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    /// Kept as written, since it's only passed on as an argument
+    Number(String),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    /// name -> (option, value), in file order
+    presets: BTreeMap<String, Vec<(String, Value)>>,
+}
+impl Config {
+    /// Reads `path`, or the first `subproc.toml` found in the current
+    /// directory or the user's config directory (`%APPDATA%` on Windows)
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => default_paths()
+                .into_iter()
+                .find(|path| path.is_file())
+                .ok_or_else(|| format!("No {CONFIG_FILE} found"))?,
+        };
+        let text = fs::read_to_string(&path).map_err(|err| format!("{}: {err}", path.display()))?;
+        return Self::parse(&text).map_err(|err| format!("{}: {err}", path.display()));
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Config::default();
+        let mut preset: Option<String> = None;
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: &str| format!("line {}: {message}", number + 1);
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header
+                    .strip_suffix(']')
+                    .ok_or_else(|| error("unterminated table header"))?
+                    .trim();
+                // Other tables are left for future use
+                preset = header
+                    .strip_prefix("preset.")
+                    .map(|name| name.trim().trim_matches('"').to_owned());
+                if let Some(ref name) = preset {
+                    config.presets.entry(name.clone()).or_default();
+                }
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected `key = value`"))?;
+            let key = key.trim().trim_matches('"');
+            let (value, rest) = parse_value(value.trim()).ok_or_else(|| error("invalid value"))?;
+            if !rest.trim().is_empty() {
+                return Err(error("unexpected text after value"));
+            }
+            if let Some(ref name) = preset {
+                config
+                    .presets
+                    .get_mut(name)
+                    .unwrap()
+                    .push((key.to_owned(), value));
+            }
+        }
+        return Ok(config);
+    }
+
+    /// The command line arguments preset `name` stands for
+    pub fn preset_args(&self, name: &str) -> Result<Vec<String>, String> {
+        let options = self.presets.get(name).ok_or_else(|| {
+            let names: Vec<&str> = self.presets.keys().map(String::as_str).collect();
+            format!("Unknown preset {name} (available: {})", names.join(", "))
+        })?;
+        let mut args = Vec::new();
+        for (key, value) in options {
+            push_args(&mut args, key, value)?;
+        }
+        return Ok(args);
+    }
+}
+
+fn push_args(args: &mut Vec<String>, key: &str, value: &Value) -> Result<(), String> {
+    match value {
+        Value::Bool(false) => {}
+        Value::Bool(true) => args.push(format!("--{key}")),
+        Value::String(value) | Value::Number(value) => {
+            args.push(format!("--{key}"));
+            args.push(value.clone());
+        }
+        Value::Array(values) => {
+            for value in values {
+                if let Value::Array(_) = value {
+                    return Err(format!("Nested arrays aren't supported for {key}"));
+                }
+                push_args(args, key, value)?;
+            }
+        }
+    }
+    return Ok(());
+}
+
+fn default_paths() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(CONFIG_FILE)];
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|_| !cfg!(windows))
+        .map(PathBuf::from)
+        .or_else(|| match cfg!(windows) {
+            true => std::env::var_os("APPDATA").map(PathBuf::from),
+            false => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+        });
+    if let Some(config_home) = config_home {
+        paths.push(config_home.join("subproc").join(CONFIG_FILE));
+    }
+    return paths;
+}
+
+/// Removes a `#` comment, unless it's inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (c, quote) {
+            _ if escaped => escaped = false,
+            ('\\', Some('"')) => escaped = true,
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(open)) if c == open => quote = None,
+            ('#', None) => return &line[..i],
+            _ => {}
+        }
+    }
+    return line;
+}
+
+/// Parses the value at the start of `text`, returning it and what follows
+fn parse_value(text: &str) -> Option<(Value, &str)> {
+    if let Some(rest) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Some((Value::String(value), &rest[i + 1..])),
+                '\\' => value.push(match chars.next()?.1 {
+                    'n' => '\n',
+                    't' => '\t',
+                    c @ ('"' | '\\') => c,
+                    _ => return None,
+                }),
+                c => value.push(c),
+            }
+        }
+        return None;
+    }
+    if let Some(rest) = text.strip_prefix('\'') {
+        let end = rest.find('\'')?;
+        return Some((Value::String(rest[..end].to_owned()), &rest[end + 1..]));
+    }
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut values = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Some((Value::Array(values), after));
+            }
+            let (value, after) = parse_value(rest)?;
+            values.push(value);
+            rest = after.trim_start();
+            match rest.strip_prefix(',') {
+                Some(after) => rest = after,
+                None if rest.starts_with(']') => {}
+                None => return None,
+            }
+        }
+    }
+    let end = text
+        .find(|c: char| c == ',' || c == ']' || c.is_whitespace())
+        .unwrap_or(text.len());
+    let (word, rest) = text.split_at(end);
+    let value = match word {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        word if word.replace('_', "").parse::<f64>().is_ok() => {
+            Value::Number(word.replace('_', ""))
+        }
+        _ => return None,
+    };
+    return Some((value, rest));
+}
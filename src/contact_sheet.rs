@@ -0,0 +1,194 @@
+//! Contact sheets: every subtitle bitmap of a track, scaled down and tiled into
+//! large images with their event number and start time, for quick visual QC.
+
+use image::{
+    GrayAlphaImage, Rgba, RgbaImage,
+    buffer::ConvertBuffer,
+    imageops::{self, FilterType},
+};
+
+use crate::srt::format_timestamp;
+
+const BACKGROUND: Rgba<u8> = Rgba([48, 48, 48, 255]);
+const CELL_BACKGROUND: Rgba<u8> = Rgba([80, 80, 80, 255]);
+const LABEL_COLOR: Rgba<u8> = Rgba([255, 220, 0, 255]);
+const PADDING: u32 = 8;
+/// Labels are drawn with the 3x5 font below, scaled up by this factor
+const FONT_SCALE: u32 = 3;
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+/// Tiny bitmap font covering what labels need. Each row is 3 bits, MSB on the left.
+fn glyph(c: char) -> Option<[u8; 5]> {
+    return Some(match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ' ' => [0; 5],
+        _ => return None,
+    });
+}
+
+fn draw_text(image: &mut RgbaImage, x: u32, y: u32, text: &str) {
+    for (i, c) in text.chars().enumerate() {
+        let Some(rows) = glyph(c) else {
+            continue;
+        };
+        let glyph_x = x + i as u32 * (GLYPH_WIDTH + 1) * FONT_SCALE;
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..FONT_SCALE {
+                    for dx in 0..FONT_SCALE {
+                        let px = glyph_x + column * FONT_SCALE + dx;
+                        let py = y + row as u32 * FONT_SCALE + dy;
+                        if px < image.width() && py < image.height() {
+                            image.put_pixel(px, py, LABEL_COLOR);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SheetLayout {
+    pub columns: u32,
+    pub rows: u32,
+    /// Bitmaps are scaled down (never up) to fit in this box
+    pub thumb_width: u32,
+    pub thumb_height: u32,
+}
+impl Default for SheetLayout {
+    fn default() -> Self {
+        return Self {
+            columns: 4,
+            rows: 12,
+            thumb_width: 480,
+            thumb_height: 120,
+        };
+    }
+}
+
+struct Thumbnail {
+    label: String,
+    image: RgbaImage,
+}
+
+/// Collects thumbnails as events are decoded, so full-size bitmaps don't have
+/// to be kept around
+pub struct ContactSheet {
+    layout: SheetLayout,
+    thumbnails: Vec<Thumbnail>,
+}
+impl ContactSheet {
+    pub fn new(layout: SheetLayout) -> Self {
+        return Self {
+            layout: SheetLayout {
+                columns: layout.columns.max(1),
+                rows: layout.rows.max(1),
+                ..layout
+            },
+            thumbnails: Vec::new(),
+        };
+    }
+
+    /// Adds event number `index` starting at `start` (nanoseconds)
+    pub fn add(&mut self, index: usize, start: u64, image: &GrayAlphaImage) {
+        let scale = f64::min(
+            1.0,
+            f64::min(
+                self.layout.thumb_width as f64 / image.width().max(1) as f64,
+                self.layout.thumb_height as f64 / image.height().max(1) as f64,
+            ),
+        );
+        let width = ((image.width() as f64 * scale).round() as u32).max(1);
+        let height = ((image.height() as f64 * scale).round() as u32).max(1);
+        let rgba: RgbaImage = image.convert();
+        let image = match scale < 1.0 {
+            true => imageops::resize(&rgba, width, height, FilterType::Triangle),
+            false => rgba,
+        };
+        self.thumbnails.push(Thumbnail {
+            label: format!("{index}  {}", format_timestamp(start)),
+            image,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        return self.thumbnails.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.thumbnails.is_empty();
+    }
+
+    /// Renders the sheets, each holding up to `columns * rows` thumbnails
+    pub fn render(&self) -> Vec<RgbaImage> {
+        let layout = self.layout;
+        let label_height = GLYPH_HEIGHT * FONT_SCALE + PADDING;
+        let label_width = self
+            .thumbnails
+            .iter()
+            .map(|thumbnail| thumbnail.label.len() as u32 * (GLYPH_WIDTH + 1) * FONT_SCALE)
+            .max()
+            .unwrap_or(0);
+        let content_width = layout.thumb_width.max(label_width);
+        let cell_width = content_width + 2 * PADDING;
+        let cell_height = layout.thumb_height + label_height + 2 * PADDING;
+        let per_sheet = (layout.columns * layout.rows) as usize;
+
+        let mut sheets = Vec::new();
+        for chunk in self.thumbnails.chunks(per_sheet) {
+            let rows = (chunk.len() as u32).div_ceil(layout.columns);
+            let columns = layout.columns.min(chunk.len() as u32);
+            let mut sheet = RgbaImage::from_pixel(
+                columns * (cell_width + PADDING) + PADDING,
+                rows * (cell_height + PADDING) + PADDING,
+                BACKGROUND,
+            );
+            for (i, thumbnail) in chunk.iter().enumerate() {
+                let cell_x = PADDING + (i as u32 % layout.columns) * (cell_width + PADDING);
+                let cell_y = PADDING + (i as u32 / layout.columns) * (cell_height + PADDING);
+                imageops::replace(
+                    &mut sheet,
+                    &RgbaImage::from_pixel(cell_width, cell_height, CELL_BACKGROUND),
+                    cell_x as i64,
+                    cell_y as i64,
+                );
+                draw_text(
+                    &mut sheet,
+                    cell_x + PADDING,
+                    cell_y + PADDING,
+                    &thumbnail.label,
+                );
+                // Centered in the space below the label
+                let image = &thumbnail.image;
+                imageops::overlay(
+                    &mut sheet,
+                    image,
+                    (cell_x + PADDING + content_width.saturating_sub(image.width()) / 2) as i64,
+                    (cell_y
+                        + PADDING
+                        + label_height
+                        + layout.thumb_height.saturating_sub(image.height()) / 2)
+                        as i64,
+                );
+            }
+            sheets.push(sheet);
+        }
+        return sheets;
+    }
+}
@@ -0,0 +1,343 @@
+//! A quick map of where a subtitle track has subtitles, for spotting ranges
+//! missing from a rip. Only block timestamps are needed, so nothing is
+//! decoded: mkvmerge indexes every subtitle block in Cues by default, and
+//! without such entries the clusters are walked reading just the block
+//! headers, seeking past the video and audio data.
+
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+};
+
+use serde::Serialize;
+
+use crate::ebml::*;
+
+/// Default width of a [`Density`] bucket: a minute
+pub const DEFAULT_BUCKET: u64 = 60_000_000_000;
+/// Default shortest gap [`find_gaps`] reports. Dialogue rarely stops for
+/// five minutes, so a longer stretch usually means the rip lost part of the
+/// track.
+pub const DEFAULT_GAP: u64 = 5 * 60_000_000_000;
+/// Default shortest stretch from the last subtitle to the end of the file
+/// [`find_gaps`] reports. Longer than [`DEFAULT_GAP`] to allow for end
+/// credits.
+pub const DEFAULT_TAIL_GAP: u64 = 15 * 60_000_000_000;
+/// Bars for 1/8 to 8/8 of the busiest bucket
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// Elements that can follow a cluster, ending one of unknown size
+const TOP_LEVEL: [u32; 8] = [
+    ID_CLUSTER,
+    ID_CUES,
+    ID_TAGS,
+    ID_CHAPTERS,
+    ID_ATTACHMENTS,
+    ID_SEEK_HEAD,
+    ID_INFO,
+    ID_TRACKS,
+];
+
+/// Where [`scan_track`] found the block times
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanSource {
+    Cues,
+    Clusters,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackScan {
+    /// Nanoseconds, sorted. Image tracks usually have a block clearing each
+    /// subtitle as well as the one showing it.
+    pub times: Vec<u64>,
+    /// Nanoseconds, from the segment info
+    pub duration: Option<u64>,
+    pub source: ScanSource,
+}
+
+/// Finds the timestamp of every block of `track`, from Cues if they index
+/// the track and the clusters otherwise
+pub fn scan_track<R: Read + Seek>(reader: &mut R, track: u64) -> io::Result<TrackScan> {
+    let mut scale = 1_000_000;
+    let mut duration = None;
+    let mut times = Vec::new();
+    for (id, data) in read_top_level_elements(reader, &[ID_INFO, ID_CUES])? {
+        for child in ChildIter::new(&data) {
+            let (child_id, _, value) = child?;
+            match (id, child_id) {
+                (ID_INFO, ID_TIMESTAMP_SCALE) => scale = parse_uint(value),
+                (ID_INFO, ID_DURATION) => duration = parse_float(value),
+                (ID_CUES, ID_CUE_POINT) => {
+                    if let Some(time) = cue_point_time(value, track)? {
+                        times.push(time);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    let mut source = ScanSource::Cues;
+    if times.is_empty() {
+        source = ScanSource::Clusters;
+        times = scan_clusters(reader, track)?;
+    }
+    let mut times: Vec<u64> = times
+        .into_iter()
+        .map(|time| time.saturating_mul(scale))
+        .collect();
+    times.sort_unstable();
+    return Ok(TrackScan {
+        times,
+        duration: duration.map(|duration| (duration * scale as f64) as u64),
+        source,
+    });
+}
+
+/// A cue point's time, if it indexes `track`
+fn cue_point_time(point: &[u8], track: u64) -> io::Result<Option<u64>> {
+    let mut time = None;
+    let mut indexed = false;
+    for child in ChildIter::new(point) {
+        let (id, _, value) = child?;
+        match id {
+            ID_CUE_TIME => time = Some(parse_uint(value)),
+            ID_CUE_TRACK_POSITIONS => {
+                for field in ChildIter::new(value) {
+                    let (id, _, value) = field?;
+                    indexed |= id == ID_CUE_TRACK && parse_uint(value) == track;
+                }
+            }
+            _ => {}
+        }
+    }
+    return Ok(time.filter(|_| indexed));
+}
+
+/// Block timestamps of `track` in every cluster, in timestamp scale units
+fn scan_clusters<R: Read + Seek>(reader: &mut R, track: u64) -> io::Result<Vec<u64>> {
+    let segment = read_segment_header(reader)?;
+    let mut times = Vec::new();
+    let mut position = segment.data_position();
+    while segment.size == UNKNOWN_SIZE || position < segment.end_position() {
+        reader.seek(SeekFrom::Start(position))?;
+        let header = match read_element_header(reader) {
+            Ok(header) => header,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        };
+        position = match header.id {
+            ID_CLUSTER => scan_cluster(reader, &header, track, &mut times)?,
+            _ if header.size == UNKNOWN_SIZE => break,
+            _ => header.end_position(),
+        };
+    }
+    return Ok(times);
+}
+
+/// Adds the timestamps of `track`'s blocks in a cluster to `times`, and
+/// returns where the cluster ends
+fn scan_cluster<R: Read + Seek>(
+    reader: &mut R,
+    cluster: &ElementHeader,
+    track: u64,
+    times: &mut Vec<u64>,
+) -> io::Result<u64> {
+    let mut timestamp = 0;
+    let mut position = cluster.data_position();
+    while cluster.size == UNKNOWN_SIZE || position < cluster.end_position() {
+        reader.seek(SeekFrom::Start(position))?;
+        let child = match read_element_header(reader) {
+            Ok(child) => child,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        };
+        if cluster.size == UNKNOWN_SIZE && TOP_LEVEL.contains(&child.id) {
+            break;
+        }
+        if child.size == UNKNOWN_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unknown-size element in a cluster",
+            ));
+        }
+        let block = match child.id {
+            ID_TIMESTAMP => {
+                timestamp = parse_uint(&read_element_data(reader, &child)?);
+                None
+            }
+            ID_SIMPLE_BLOCK => read_block_header(reader, track)?,
+            ID_BLOCK_GROUP => scan_block_group(reader, &child, track)?,
+            _ => None,
+        };
+        if let Some(relative) = block {
+            times.push(timestamp.saturating_add_signed(relative as i64));
+        }
+        position = child.end_position();
+    }
+    return Ok(position);
+}
+
+/// The relative timestamp of a block group's block, if it's of `track`
+fn scan_block_group<R: Read + Seek>(
+    reader: &mut R,
+    group: &ElementHeader,
+    track: u64,
+) -> io::Result<Option<i16>> {
+    let mut position = group.data_position();
+    while position < group.end_position() {
+        reader.seek(SeekFrom::Start(position))?;
+        let child = read_element_header(reader)?;
+        if child.id == ID_BLOCK {
+            return read_block_header(reader, track);
+        }
+        position = child.end_position();
+    }
+    return Ok(None);
+}
+
+/// Reads the start of the block at the reader's position, returning its
+/// relative timestamp if it's of `track`
+fn read_block_header<R: Read>(reader: &mut R, track: u64) -> io::Result<Option<i16>> {
+    // The track number is coded like an element size
+    let (number, _) = read_size(reader)?;
+    if number != track {
+        return Ok(None);
+    }
+    let mut relative = [0; 2];
+    reader.read_exact(&mut relative)?;
+    return Ok(Some(i16::from_be_bytes(relative)));
+}
+
+/// Where a [`Gap`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GapKind {
+    /// Between two subtitles
+    Between,
+    /// After the last subtitle, up to the end of the file: the track stops
+    /// early, as when a mastering error cuts it off mid-movie
+    Tail,
+}
+
+/// A suspiciously long stretch without subtitles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Gap {
+    /// Nanoseconds
+    pub start: u64,
+    /// Nanoseconds
+    pub end: u64,
+    pub kind: GapKind,
+}
+impl Gap {
+    pub fn duration(&self) -> u64 {
+        return self.end - self.start;
+    }
+}
+
+/// Stretches of at least `min` between consecutive block `times` (sorted),
+/// and from the last block to the end of the file if `duration` is known
+/// and that's at least `min_tail`. Unlike [`Density::empty_ranges`] these
+/// are exact rather than whole buckets. Before the first block is left out,
+/// since openings rarely have subtitles.
+pub fn find_gaps(times: &[u64], duration: Option<u64>, min: u64, min_tail: u64) -> Vec<Gap> {
+    let mut gaps: Vec<Gap> = times
+        .windows(2)
+        .filter(|pair| pair[1] - pair[0] >= min.max(1))
+        .map(|pair| Gap {
+            start: pair[0],
+            end: pair[1],
+            kind: GapKind::Between,
+        })
+        .collect();
+    if let (Some(&last), Some(duration)) = (times.last(), duration)
+        && duration.saturating_sub(last) >= min_tail.max(1)
+    {
+        gaps.push(Gap {
+            start: last,
+            end: duration,
+            kind: GapKind::Tail,
+        });
+    }
+    return gaps;
+}
+
+/// Blocks per bucket of time
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Density {
+    /// Nanoseconds
+    pub bucket: u64,
+    pub counts: Vec<usize>,
+}
+impl Density {
+    /// Counts `times` into buckets, with enough of them to cover `duration`
+    /// if it's given
+    pub fn new(times: &[u64], bucket: u64, duration: Option<u64>) -> Self {
+        let end = times
+            .last()
+            .map_or(0, |time| time + 1)
+            .max(duration.unwrap_or(0));
+        let mut counts = vec![0; end.div_ceil(bucket) as usize];
+        for time in times {
+            counts[(time / bucket) as usize] += 1;
+        }
+        return Self { bucket, counts };
+    }
+
+    /// One bar per bucket, scaled to the busiest one. Empty buckets are
+    /// spaces, so gaps stand out.
+    pub fn sparkline(&self) -> String {
+        let max = self.counts.iter().copied().max().unwrap_or(0);
+        return self
+            .counts
+            .iter()
+            .map(|count| match count {
+                0 => ' ',
+                count => BARS[((count * BARS.len() - 1) / max).min(BARS.len() - 1)],
+            })
+            .collect();
+    }
+
+    /// The sparkline in lines of at most `columns` buckets, each labelled
+    /// with its start time as `HH:MM:SS`
+    pub fn render(&self, columns: usize) -> String {
+        let sparkline: Vec<char> = self.sparkline().chars().collect();
+        let mut lines = Vec::new();
+        for (index, line) in sparkline.chunks(columns.max(1)).enumerate() {
+            let seconds = (index * columns.max(1)) as u64 * self.bucket / 1_000_000_000;
+            let line: String = line.iter().collect();
+            lines.push(format!(
+                "{:02}:{:02}:{:02} {}",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60,
+                line.trim_end()
+            ));
+        }
+        return lines.join("\n");
+    }
+
+    /// Runs of at least `min_buckets` empty buckets between the first and
+    /// last subtitle, in nanoseconds. Before the first and after the last
+    /// are left out, since openings and credits rarely have subtitles.
+    pub fn empty_ranges(&self, min_buckets: usize) -> Vec<Range<u64>> {
+        let bucket = |index: usize| index as u64 * self.bucket;
+        let mut ranges = Vec::new();
+        let mut empty_since = None;
+        let mut seen = false;
+        for (index, count) in self.counts.iter().enumerate() {
+            if *count == 0 {
+                if seen && empty_since.is_none() {
+                    empty_since = Some(index);
+                }
+                continue;
+            }
+            if let Some(start) = empty_since.take()
+                && index - start >= min_buckets.max(1)
+            {
+                ranges.push(bucket(start)..bucket(index));
+            }
+            seen = true;
+        }
+        return ranges;
+    }
+}
@@ -0,0 +1,139 @@
+//! Problems that didn't stop a run, collected per event so they can be
+//! reviewed afterwards instead of scrolling past on stderr. A long disc can
+//! produce hundreds of these, and a batch pipeline needs them in a file.
+
+use std::{
+    fmt,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{qc::csv_field, srt::format_timestamp};
+
+/// The part of the pipeline a problem came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Demuxing and decoding
+    Extract,
+    /// Following ordered chapters to linked segments
+    Link,
+    Ocr,
+}
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f.write_str(match self {
+            Stage::Extract => "extract",
+            Stage::Link => "link",
+            Stage::Ocr => "ocr",
+        });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub stage: Stage,
+    /// The event it concerns, numbered from 1
+    pub event: Option<usize>,
+    /// Nanoseconds into the track
+    pub time: Option<u64>,
+    /// The linked file it came from, if not the input
+    pub source: Option<PathBuf>,
+    pub message: String,
+}
+impl Diagnostic {
+    pub fn new(stage: Stage, message: impl fmt::Display) -> Self {
+        return Self {
+            stage,
+            event: None,
+            time: None,
+            source: None,
+            message: message.to_string(),
+        };
+    }
+
+    pub fn at_event(mut self, event: usize, time: u64) -> Self {
+        self.event = Some(event);
+        self.time = Some(time);
+        return self;
+    }
+
+    pub fn at_time(mut self, time: u64) -> Self {
+        self.time = Some(time);
+        return self;
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    entries: Vec<Diagnostic>,
+}
+impl Diagnostics {
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.entries.push(diagnostic);
+    }
+
+    /// Adds diagnostics collected while processing the linked file `source`
+    pub fn append(&mut self, other: Diagnostics, source: &Path) {
+        for mut diagnostic in other.entries {
+            diagnostic.source.get_or_insert_with(|| source.to_owned());
+            self.entries.push(diagnostic);
+        }
+    }
+
+    pub fn entries(&self) -> &[Diagnostic] {
+        return &self.entries;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.entries.is_empty();
+    }
+
+    pub fn count(&self, stage: Stage) -> usize {
+        return self
+            .entries
+            .iter()
+            .filter(|diagnostic| diagnostic.stage == stage)
+            .count();
+    }
+
+    /// One line with the number of problems in each stage, like
+    /// `3 problems (extract: 2, ocr: 1)`
+    pub fn summary(&self) -> String {
+        let counts: Vec<String> = [Stage::Extract, Stage::Link, Stage::Ocr]
+            .into_iter()
+            .map(|stage| (stage, self.count(stage)))
+            .filter(|(_, count)| *count > 0)
+            .map(|(stage, count)| format!("{stage}: {count}"))
+            .collect();
+        return match self.entries.len() {
+            0 => String::from("No problems"),
+            1 => format!("1 problem ({})", counts.join(", ")),
+            total => format!("{total} problems ({})", counts.join(", ")),
+        };
+    }
+
+    /// Writes a CSV report with one row per diagnostic, in the order they
+    /// were found
+    pub fn write_report<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "stage,event,time,source,message")?;
+        for diagnostic in self.entries.iter() {
+            writeln!(
+                out,
+                "{},{},{},{},{}",
+                diagnostic.stage,
+                diagnostic
+                    .event
+                    .map_or(String::new(), |event| event.to_string()),
+                csv_field(&diagnostic.time.map_or(String::new(), format_timestamp)),
+                csv_field(
+                    &diagnostic
+                        .source
+                        .as_ref()
+                        .map_or(String::new(), |source| source.display().to_string())
+                ),
+                csv_field(&diagnostic.message),
+            )?;
+        }
+        return Ok(());
+    }
+}
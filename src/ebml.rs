@@ -0,0 +1,340 @@
+//! Low-level EBML (the binary format underneath Matroska) reading and writing.
+//! `matroska_demuxer` covers the reading side for frames and metadata; this is
+//! for the cases where we need to look at or produce raw elements ourselves.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+pub const ID_EBML: u32 = 0x1A45DFA3;
+pub const ID_SEGMENT: u32 = 0x18538067;
+pub const ID_SEEK_HEAD: u32 = 0x114D9B74;
+pub const ID_SEEK: u32 = 0x4DBB;
+pub const ID_SEEK_ID: u32 = 0x53AB;
+pub const ID_SEEK_POSITION: u32 = 0x53AC;
+pub const ID_INFO: u32 = 0x1549A966;
+pub const ID_TIMESTAMP_SCALE: u32 = 0x2AD7B1;
+pub const ID_DURATION: u32 = 0x4489;
+pub const ID_SEGMENT_UID: u32 = 0x73A4;
+pub const ID_TRACKS: u32 = 0x1654AE6B;
+pub const ID_TRACK_ENTRY: u32 = 0xAE;
+pub const ID_TRACK_NUMBER: u32 = 0xD7;
+pub const ID_TRACK_UID: u32 = 0x73C5;
+pub const ID_TRACK_TYPE: u32 = 0x83;
+pub const ID_FLAG_DEFAULT: u32 = 0x88;
+pub const ID_FLAG_FORCED: u32 = 0x55AA;
+pub const ID_FLAG_HEARING_IMPAIRED: u32 = 0x55AB;
+pub const ID_FLAG_LACING: u32 = 0x9C;
+pub const ID_CODEC_ID: u32 = 0x86;
+pub const ID_CODEC_PRIVATE: u32 = 0x63A2;
+pub const ID_LANGUAGE: u32 = 0x22B59C;
+pub const ID_LANGUAGE_BCP47: u32 = 0x22B59D;
+pub const ID_NAME: u32 = 0x536E;
+pub const ID_CLUSTER: u32 = 0x1F43B675;
+pub const ID_TIMESTAMP: u32 = 0xE7;
+pub const ID_POSITION: u32 = 0xA7;
+pub const ID_PREV_SIZE: u32 = 0xAB;
+pub const ID_SIMPLE_BLOCK: u32 = 0xA3;
+pub const ID_BLOCK_GROUP: u32 = 0xA0;
+pub const ID_BLOCK: u32 = 0xA1;
+pub const ID_BLOCK_DURATION: u32 = 0x9B;
+pub const ID_CUES: u32 = 0x1C53BB6B;
+pub const ID_CUE_POINT: u32 = 0xBB;
+pub const ID_CUE_TIME: u32 = 0xB3;
+pub const ID_CUE_TRACK_POSITIONS: u32 = 0xB7;
+pub const ID_CUE_TRACK: u32 = 0xF7;
+pub const ID_CUE_CLUSTER_POSITION: u32 = 0xF1;
+pub const ID_CHAPTERS: u32 = 0x1043A770;
+pub const ID_EDITION_ENTRY: u32 = 0x45B9;
+pub const ID_EDITION_FLAG_DEFAULT: u32 = 0x45DB;
+pub const ID_EDITION_FLAG_ORDERED: u32 = 0x45DD;
+pub const ID_CHAPTER_ATOM: u32 = 0xB6;
+pub const ID_CHAPTER_UID: u32 = 0x73C4;
+pub const ID_CHAPTER_TIME_START: u32 = 0x91;
+pub const ID_CHAPTER_TIME_END: u32 = 0x92;
+pub const ID_CHAPTER_FLAG_ENABLED: u32 = 0x4598;
+pub const ID_CHAPTER_SEGMENT_UID: u32 = 0x6E67;
+pub const ID_CHAPTER_DISPLAY: u32 = 0x80;
+pub const ID_CHAP_STRING: u32 = 0x85;
+pub const ID_TAGS: u32 = 0x1254C367;
+pub const ID_ATTACHMENTS: u32 = 0x1941A469;
+pub const ID_ATTACHED_FILE: u32 = 0x61A7;
+pub const ID_FILE_DESCRIPTION: u32 = 0x467E;
+pub const ID_FILE_NAME: u32 = 0x466E;
+pub const ID_FILE_MEDIA_TYPE: u32 = 0x4660;
+pub const ID_FILE_DATA: u32 = 0x465C;
+pub const ID_FILE_UID: u32 = 0x46AE;
+pub const ID_VOID: u32 = 0xEC;
+pub const ID_CRC32: u32 = 0xBF;
+
+/// Size value reserved for "unknown size" (all data bits set)
+pub const UNKNOWN_SIZE: u64 = u64::MAX;
+
+/// Header of an element, as found in a file
+#[derive(Debug, Clone, Copy)]
+pub struct ElementHeader {
+    pub id: u32,
+    /// `UNKNOWN_SIZE` if the element doesn't declare one
+    pub size: u64,
+    /// Position of the first byte of the element's ID
+    pub position: u64,
+    /// Length of the ID and size fields
+    pub header_len: u64,
+}
+impl ElementHeader {
+    pub fn data_position(&self) -> u64 {
+        return self.position + self.header_len;
+    }
+    pub fn end_position(&self) -> u64 {
+        return self.data_position() + self.size;
+    }
+}
+
+fn vint_length(first: u8) -> Option<usize> {
+    if first == 0 {
+        return None;
+    }
+    return Some(first.leading_zeros() as usize + 1);
+}
+
+/// Reads an element ID, keeping its length marker as Matroska specs list them
+pub fn read_id<R: Read>(reader: &mut R) -> io::Result<(u32, usize)> {
+    let mut first = [0u8];
+    reader.read_exact(&mut first)?;
+    let length = vint_length(first[0])
+        .filter(|length| *length <= 4)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid EBML ID"))?;
+    let mut id = first[0] as u32;
+    for _ in 1..length {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        id = id << 8 | byte[0] as u32;
+    }
+    return Ok((id, length));
+}
+
+/// Reads an element data size, returning `UNKNOWN_SIZE` for the reserved value
+pub fn read_size<R: Read>(reader: &mut R) -> io::Result<(u64, usize)> {
+    let mut first = [0u8];
+    reader.read_exact(&mut first)?;
+    let length = vint_length(first[0])
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid EBML size"))?;
+    let mut size = (first[0] as u64) & (0xFF >> length);
+    let mut all_ones = size == (0xFF >> length);
+    for _ in 1..length {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        all_ones &= byte[0] == 0xFF;
+        size = size << 8 | byte[0] as u64;
+    }
+    if all_ones {
+        return Ok((UNKNOWN_SIZE, length));
+    }
+    return Ok((size, length));
+}
+
+/// Reads the header of the element at the reader's current position
+pub fn read_element_header<R: Read + Seek>(reader: &mut R) -> io::Result<ElementHeader> {
+    let position = reader.stream_position()?;
+    let (id, id_len) = read_id(reader)?;
+    let (size, size_len) = read_size(reader)?;
+    return Ok(ElementHeader {
+        id,
+        size,
+        position,
+        header_len: (id_len + size_len) as u64,
+    });
+}
+
+/// Decodes an unsigned integer element's data
+pub fn parse_uint(data: &[u8]) -> u64 {
+    return data.iter().fold(0u64, |acc, byte| acc << 8 | *byte as u64);
+}
+
+/// Decodes a float element's data, which is 4 or 8 bytes (or empty for 0)
+pub fn parse_float(data: &[u8]) -> Option<f64> {
+    return match data.len() {
+        0 => Some(0.0),
+        4 => Some(f32::from_be_bytes(data.try_into().ok()?) as f64),
+        8 => Some(f64::from_be_bytes(data.try_into().ok()?)),
+        _ => None,
+    };
+}
+
+/// Iterates over the child elements contained in an in-memory element body
+pub struct ChildIter<'a> {
+    data: &'a [u8],
+    cursor: usize,
+}
+impl<'a> ChildIter<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        return Self { data, cursor: 0 };
+    }
+}
+impl<'a> Iterator for ChildIter<'a> {
+    /// (id, full element bytes, data bytes)
+    type Item = io::Result<(u32, &'a [u8], &'a [u8])>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cursor >= self.data.len() {
+            return None;
+        }
+        let mut reader = &self.data[self.cursor..];
+        let result = (|| {
+            let (id, id_len) = read_id(&mut reader)?;
+            let (size, size_len) = read_size(&mut reader)?;
+            let start = self.cursor;
+            let data_start = start + id_len + size_len;
+            let end = data_start
+                .checked_add(usize::try_from(size).unwrap_or(usize::MAX))
+                .filter(|end| *end <= self.data.len())
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "EBML element overruns parent")
+                })?;
+            self.cursor = end;
+            return Ok((id, &self.data[start..end], &self.data[data_start..end]));
+        })();
+        if result.is_err() {
+            self.cursor = self.data.len();
+        }
+        return Some(result);
+    }
+}
+
+/// Reads an element's whole body into memory. The buffer grows as data is
+/// read, so a corrupted size can't allocate more than the file holds.
+pub fn read_element_data<R: Read + Seek>(
+    reader: &mut R,
+    header: &ElementHeader,
+) -> io::Result<Vec<u8>> {
+    if header.size == UNKNOWN_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unknown-size elements are not supported",
+        ));
+    }
+    reader.seek(SeekFrom::Start(header.data_position()))?;
+    let mut data = Vec::new();
+    reader.take(header.size).read_to_end(&mut data)?;
+    if data.len() as u64 != header.size {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    return Ok(data);
+}
+
+/// Reads the bodies of the top-level elements of a Matroska file's segment
+/// with one of the given IDs, in file order. Clusters are skipped over, and
+/// reading stops at the first element of unknown size (live-style clusters),
+/// which metadata comes before in practice.
+pub fn read_top_level_elements<R: Read + Seek>(
+    reader: &mut R,
+    ids: &[u32],
+) -> io::Result<Vec<(u32, Vec<u8>)>> {
+    let segment = read_segment_header(reader)?;
+    let mut elements = Vec::new();
+    let mut position = segment.data_position();
+    while segment.size == UNKNOWN_SIZE || position < segment.end_position() {
+        reader.seek(SeekFrom::Start(position))?;
+        let header = match read_element_header(reader) {
+            Ok(header) => header,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        };
+        if header.size == UNKNOWN_SIZE {
+            break;
+        }
+        if ids.contains(&header.id) {
+            elements.push((header.id, read_element_data(reader, &header)?));
+        }
+        position = header.end_position();
+    }
+    return Ok(elements);
+}
+
+/// Reads the header of a Matroska file's segment, checking the EBML header
+/// before it
+pub fn read_segment_header<R: Read + Seek>(reader: &mut R) -> io::Result<ElementHeader> {
+    let not_matroska = || io::Error::new(io::ErrorKind::InvalidData, "Not a Matroska file");
+    reader.seek(SeekFrom::Start(0))?;
+    let header = read_element_header(reader)?;
+    if header.id != ID_EBML || header.size == UNKNOWN_SIZE {
+        return Err(not_matroska());
+    }
+    reader.seek(SeekFrom::Start(header.end_position()))?;
+    let segment = read_element_header(reader)?;
+    if segment.id != ID_SEGMENT {
+        return Err(not_matroska());
+    }
+    return Ok(segment);
+}
+
+// Writing ---------------------------------------------------------------------
+
+pub fn encode_id(id: u32) -> Vec<u8> {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|byte| **byte == 0).count().min(3);
+    return bytes[skip..].to_vec();
+}
+
+/// Encodes a data size using the shortest form, or exactly `width` bytes if given
+pub fn encode_size(size: u64, width: Option<usize>) -> Vec<u8> {
+    let length = width.unwrap_or_else(|| {
+        // The all-ones value is reserved, hence the `+ 1`
+        (1..=8)
+            .find(|length| size + 1 < 1u64 << (7 * length))
+            .unwrap_or(8)
+    });
+    let mut bytes = Vec::with_capacity(length);
+    for i in (0..length).rev() {
+        bytes.push((size >> (8 * i)) as u8);
+    }
+    bytes[0] |= 0x80 >> (length - 1);
+    return bytes;
+}
+
+pub fn encode_element(id: u32, data: &[u8]) -> Vec<u8> {
+    let mut element = encode_id(id);
+    element.extend(encode_size(data.len() as u64, None));
+    element.extend_from_slice(data);
+    return element;
+}
+
+pub fn encode_uint(id: u32, value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|byte| **byte == 0).count().min(7);
+    return encode_element(id, &bytes[skip..]);
+}
+
+pub fn encode_string(id: u32, value: &str) -> Vec<u8> {
+    return encode_element(id, value.as_bytes());
+}
+
+/// Encodes a Void element occupying exactly `total_len` bytes (at least 2)
+pub fn encode_void(total_len: usize) -> Vec<u8> {
+    // 1 byte ID, and a size field wide enough for the remainder
+    let size_width = if total_len - 1 > 8 { 8 } else { 1 };
+    let mut element = encode_id(ID_VOID);
+    element.extend(encode_size(
+        (total_len - 1 - size_width) as u64,
+        Some(size_width),
+    ));
+    element.resize(total_len, 0);
+    return element;
+}
+
+/// Writes an element header with a fixed-width size field, so the size can be
+/// patched later with [`patch_size`]. Returns the position of the size field.
+pub fn write_header_placeholder<W: Write + Seek>(writer: &mut W, id: u32) -> io::Result<u64> {
+    writer.write_all(&encode_id(id))?;
+    let position = writer.stream_position()?;
+    writer.write_all(&encode_size(0, Some(8)))?;
+    return Ok(position);
+}
+
+/// Fills in a size field written by [`write_header_placeholder`], using the
+/// writer's current position as the end of the element.
+pub fn patch_size<W: Write + Seek>(writer: &mut W, size_position: u64) -> io::Result<()> {
+    let end = writer.stream_position()?;
+    writer.seek(SeekFrom::Start(size_position))?;
+    writer.write_all(&encode_size(end - size_position - 8, Some(8)))?;
+    writer.seek(SeekFrom::Start(end))?;
+    return Ok(());
+}
@@ -0,0 +1,48 @@
+//! Where decoding a PGS track can start from. Compositions depend on the
+//! palettes and objects defined since the last epoch start or acquisition
+//! point, so finding what's on screen at a given time means decoding from
+//! the last of those before it. Indexing them once lets an interactive
+//! preview jump around the track, backwards included, decoding one epoch
+//! at a time instead of everything from the start of the file.
+
+use serde::{Deserialize, Serialize};
+
+/// Timestamps (nanoseconds) of the display sets decoding can start from.
+/// It doesn't have to be complete: a point that's missing just means
+/// decoding from an earlier one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpochIndex {
+    /// Sorted, without duplicates
+    points: Vec<u64>,
+}
+impl EpochIndex {
+    pub fn new() -> Self {
+        return EpochIndex::default();
+    }
+
+    /// Records a point. They can come in any order, such as when seeking
+    /// around a file that's being indexed as it's read.
+    pub fn insert(&mut self, timestamp: u64) {
+        if let Err(index) = self.points.binary_search(&timestamp) {
+            self.points.insert(index, timestamp);
+        }
+    }
+
+    /// The last point at or before `timestamp`
+    pub fn before(&self, timestamp: u64) -> Option<u64> {
+        let index = self.points.partition_point(|point| *point <= timestamp);
+        return index.checked_sub(1).map(|index| self.points[index]);
+    }
+
+    pub fn points(&self) -> &[u64] {
+        return &self.points;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.points.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.points.is_empty();
+    }
+}
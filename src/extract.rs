@@ -0,0 +1,975 @@
+//! Pulls a single subtitle track out of an MKV file and runs it through the
+//! matching decoder, yielding timed events. Image-based formats produce bitmaps
+//! that still need OCR, while text formats produce their text directly.
+//! [`SubtitleExtractor::play`] reports the track as it would be played back
+//! instead, one change to the screen at a time.
+
+use std::{
+    collections::VecDeque,
+    io::{Read, Seek},
+};
+
+use image::{GrayAlphaImage, Rgb, RgbaImage, buffer::ConvertBuffer, imageops};
+use matroska_demuxer::{DemuxError, Frame, MatroskaFile, TrackEntry, TrackType};
+use thiserror::Error;
+
+#[cfg(feature = "ass")]
+use crate::ass::{AssError, AssRenderer};
+use crate::{
+    bdsup::{self, PgsError, PgsParser},
+    cancel::CancellationToken,
+    color::ColorMatrix,
+    epochs::EpochIndex,
+    indexed::IndexedImage,
+    model::EndSource,
+    preprocess::{Placement, Region, content_bounds},
+    textst::{TextstError, TextstParser},
+    timebase::Timestamp,
+    vobs::{self, ControlData, IdxData, SubpictureAssembler, SubsError},
+};
+
+/// How long the last event lasts when nothing says when it ends
+pub const FALLBACK_DURATION: u64 = 5_000_000_000;
+
+#[derive(Error, Debug)]
+pub enum ExtractError {
+    #[error("Error reading MKV file: {0}")]
+    Demux(#[from] DemuxError),
+    #[error("Track {0} not found.")]
+    TrackNotFound(u64),
+    #[error("Track {0} is not a subtitle track.")]
+    NotASubtitleTrack(u64),
+    #[error("No subtitle tracks found.")]
+    NoSubtitleTracks,
+    #[error("Unsupported subtitle codec {0}.")]
+    UnsupportedCodec(String),
+    #[error(transparent)]
+    Pgs(#[from] PgsError),
+    #[error(transparent)]
+    Textst(#[from] TextstError),
+    #[error(transparent)]
+    VobSub(#[from] SubsError),
+    #[cfg(feature = "ass")]
+    #[error(transparent)]
+    Ass(#[from] AssError),
+    #[error("Block timestamp {0} is too large to count in nanoseconds.")]
+    TimestampOverflow(u64),
+}
+
+#[derive(Debug, Clone)]
+pub enum EventPayload {
+    /// A subtitle bitmap, cropped to its visible content
+    Image(GrayAlphaImage),
+    Text(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct SubtitleEvent {
+    /// Nanoseconds
+    pub start: u64,
+    /// Nanoseconds. Always set on events from [`SubtitleExtractor`], which
+    /// infers it when neither the stream nor the container gives one.
+    pub end: Option<u64>,
+    /// How `end` was arrived at
+    pub end_source: Option<EndSource>,
+    pub forced: bool,
+    pub payload: EventPayload,
+    /// Separately positioned parts of an image event (PGS composition
+    /// objects), relative to the image. Empty for text events.
+    pub regions: Vec<Region>,
+    /// Where an image event is shown on screen, if the format says
+    pub placement: Option<Placement>,
+    /// The image with its original palette indices, cropped the same way.
+    /// Only set when enabled with [`SubtitleExtractor::set_indexed`].
+    pub indexed: Option<IndexedImage>,
+}
+impl SubtitleEvent {
+    /// Splits an image event with several regions into one event per region,
+    /// so each can be recognized on its own. Other events are returned as-is.
+    pub fn split_regions(self) -> Vec<SubtitleEvent> {
+        let EventPayload::Image(ref image) = self.payload else {
+            return vec![self];
+        };
+        if self.regions.len() < 2 {
+            return vec![self];
+        }
+        return self
+            .regions
+            .iter()
+            .map(|region| SubtitleEvent {
+                start: self.start,
+                end: self.end,
+                end_source: self.end_source,
+                forced: self.forced,
+                payload: EventPayload::Image(
+                    imageops::crop_imm(image, region.x, region.y, region.width, region.height)
+                        .to_image(),
+                ),
+                regions: vec![Region {
+                    x: 0,
+                    y: 0,
+                    ..*region
+                }],
+                placement: self.placement.map(|placement| Placement {
+                    x: placement.x + region.x,
+                    y: placement.y + region.y,
+                    ..placement
+                }),
+                indexed: self.indexed.as_ref().map(|indexed| indexed.crop(*region)),
+            })
+            .collect();
+    }
+}
+
+/// What's on screen, as [`Playback`] reports it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Screen {
+    Clear,
+    /// Cropped to its visible content, in its palette's colors
+    Image {
+        image: RgbaImage,
+        placement: Option<Placement>,
+    },
+    Text(String),
+}
+
+/// A change to what's on screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScreenState {
+    /// Nanoseconds
+    pub timestamp: u64,
+    /// What's shown from `timestamp` until the next state
+    pub screen: Screen,
+}
+
+/// How far before the requested time [`SubtitleExtractor::subtitle_at`] first
+/// looks for the start of a PGS epoch, doubling until it finds one
+const EPOCH_LOOKBACK: u64 = 10_000_000_000;
+
+enum Decoder {
+    Pgs(Box<PgsParser<'static>>, GrayAlphaImage),
+    Textst(TextstParser),
+    /// Along with the timestamp of the frame the subpicture being assembled
+    /// started in
+    VobSub(Box<IdxData>, SubpictureAssembler, u64),
+    Utf8,
+    #[cfg(feature = "ass")]
+    Ass(Box<AssRenderer>),
+}
+
+/// Finds `track_number`, or the first subtitle track if `None`
+pub fn select_track<R: Read + Seek>(
+    mkv: &MatroskaFile<R>,
+    track_number: Option<u64>,
+) -> Result<TrackEntry, ExtractError> {
+    let track = match track_number {
+        Some(track_number) => {
+            let track = mkv
+                .tracks()
+                .iter()
+                .find(|t| t.track_number().get() == track_number)
+                .ok_or(ExtractError::TrackNotFound(track_number))?;
+            if track.track_type() != TrackType::Subtitle {
+                return Err(ExtractError::NotASubtitleTrack(track_number));
+            }
+            track
+        }
+        None => mkv
+            .tracks()
+            .iter()
+            .find(|t| t.track_type() == TrackType::Subtitle)
+            .ok_or(ExtractError::NoSubtitleTracks)?,
+    };
+    return Ok(track.clone());
+}
+
+/// Finds the subtitle shown at `timestamp` (nanoseconds) on `track_number`,
+/// or the first subtitle track if `None`, reading only the part of the file
+/// around it. See [`SubtitleExtractor::subtitle_at`].
+pub fn get_subtitle_at<R: Read + Seek>(
+    reader: R,
+    track_number: Option<u64>,
+    timestamp: u64,
+) -> Result<Option<SubtitleEvent>, ExtractError> {
+    let mut extractor = SubtitleExtractor::new(MatroskaFile::open(reader)?, track_number)?;
+    return extractor.subtitle_at(timestamp);
+}
+
+pub struct SubtitleExtractor<R: Read + Seek> {
+    mkv: MatroskaFile<R>,
+    track: TrackEntry,
+    decoder: Decoder,
+    timestamp_scale: u64,
+    frame: Frame,
+    /// PGS or VobSub event waiting for the next one to tell when it ends
+    pending: Option<SubtitleEvent>,
+    /// Decoded event without an end, waiting for the next one to start, or
+    /// that next one once the held event has been returned
+    held: Option<SubtitleEvent>,
+    indexed: bool,
+    /// Why a VobSub track's idx data couldn't be used
+    idx_error: Option<SubsError>,
+    cancel: Option<CancellationToken>,
+    /// Nanoseconds
+    position: u64,
+    /// PGS epoch starts and acquisition points read so far
+    epochs: EpochIndex,
+}
+impl<R: Read + Seek> SubtitleExtractor<R> {
+    /// Selects `track_number`, or the first subtitle track if `None`
+    pub fn new(mkv: MatroskaFile<R>, track_number: Option<u64>) -> Result<Self, ExtractError> {
+        return Self::with_idx(mkv, track_number, None, None);
+    }
+
+    /// Like [`Self::new`], with idx data for a VobSub track whose
+    /// `CodecPrivate` is missing, such as one attached to the file instead.
+    /// `palette` replaces the idx palette, and stands in for the idx data
+    /// if there's none or it's malformed (see [`Self::idx_error`]).
+    pub fn with_idx(
+        mkv: MatroskaFile<R>,
+        track_number: Option<u64>,
+        idx: Option<&[u8]>,
+        palette: Option<[Rgb<u8>; 16]>,
+    ) -> Result<Self, ExtractError> {
+        let track = select_track(&mkv, track_number)?;
+        let mut idx_error = None;
+        let decoder = match track.codec_id() {
+            "S_HDMV/PGS" => Decoder::Pgs(Box::default(), GrayAlphaImage::new(0, 0)),
+            "S_HDMV/TEXTST" => Decoder::Textst(TextstParser::with_codec_private(
+                track.codec_private().unwrap_or_default(),
+            )?),
+            "S_VOBSUB" => {
+                let data = track
+                    .codec_private()
+                    .filter(|data| !data.is_empty())
+                    .or(idx)
+                    .unwrap_or_default();
+                let idx = match (vobs::parse_idx(data), palette) {
+                    (Ok(mut idx), palette) => {
+                        idx.palette = palette.unwrap_or(idx.palette);
+                        idx
+                    }
+                    (Err(err), Some(palette)) => {
+                        idx_error = Some(err);
+                        IdxData::with_palette(palette)
+                    }
+                    (Err(err), None) => return Err(err.into()),
+                };
+                Decoder::VobSub(Box::new(idx), SubpictureAssembler::new(), 0)
+            }
+            "S_TEXT/UTF8" => Decoder::Utf8,
+            #[cfg(feature = "ass")]
+            "S_TEXT/ASS" | "S_TEXT/SSA" => Decoder::Ass(Box::new(AssRenderer::new(
+                track.codec_private().unwrap_or_default(),
+            )?)),
+            other => return Err(ExtractError::UnsupportedCodec(other.to_owned())),
+        };
+        return Ok(Self {
+            timestamp_scale: mkv.info().timestamp_scale().get(),
+            mkv,
+            track,
+            decoder,
+            frame: Frame::default(),
+            pending: None,
+            held: None,
+            indexed: false,
+            idx_error,
+            cancel: None,
+            position: 0,
+            epochs: EpochIndex::new(),
+        });
+    }
+
+    pub fn track(&self) -> &TrackEntry {
+        return &self.track;
+    }
+
+    /// Why a VobSub track's idx data was replaced by the palette given to
+    /// [`Self::with_idx`], if it was. Events then have no placement, since the
+    /// video size comes from the idx.
+    pub fn idx_error(&self) -> Option<&SubsError> {
+        return self.idx_error.as_ref();
+    }
+
+    /// Replaces the palette from a VobSub track's idx data, for rips where it's
+    /// wrong. Has no effect on other formats.
+    pub fn set_palette(&mut self, palette: [Rgb<u8>; 16]) {
+        if let Decoder::VobSub(ref mut idx, _, _) = self.decoder {
+            idx.palette = palette;
+        }
+    }
+
+    /// Makes a font available to an ASS/SSA track, such as one attached to
+    /// the MKV. Has no effect on other formats.
+    #[cfg(feature = "ass")]
+    pub fn add_font(&mut self, name: &str, data: &[u8]) {
+        if let Decoder::Ass(ref mut renderer) = self.decoder {
+            renderer.add_font(name, data);
+        }
+    }
+
+    /// How PGS palettes are converted to RGB for indexed images. Has no
+    /// effect on other formats.
+    pub fn set_color_matrix(&mut self, matrix: ColorMatrix) {
+        if let Decoder::Pgs(ref mut parser, _) = self.decoder {
+            parser.set_color_matrix(matrix);
+        }
+    }
+
+    /// Also decode image events with their original palette indices, into
+    /// [`SubtitleEvent::indexed`]. This costs a second render of each image.
+    pub fn set_indexed(&mut self, indexed: bool) {
+        self.indexed = indexed;
+    }
+
+    /// Stops extraction early once `token` is cancelled: the event in
+    /// progress is returned as it is, and the stream ends after it
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancel = Some(token);
+    }
+
+    /// Continues extraction from `timestamp` (nanoseconds). Since PGS
+    /// compositions depend on earlier display sets, events are skipped until
+    /// the decoder has resynchronized; see [`Self::skipped_events`].
+    pub fn seek(&mut self, timestamp: u64) -> Result<(), ExtractError> {
+        self.mkv.seek(timestamp / self.timestamp_scale)?;
+        self.pending = None;
+        self.held = None;
+        match self.decoder {
+            Decoder::Pgs(ref mut parser, _) => {
+                let matrix = parser.color_matrix();
+                **parser = PgsParser::with_recovery();
+                parser.set_color_matrix(matrix);
+            }
+            Decoder::VobSub(_, ref mut assembler, _) => *assembler = SubpictureAssembler::new(),
+            _ => {}
+        }
+        return Ok(());
+    }
+
+    /// Continues extraction from the last point in [`Self::epochs`] at or
+    /// before `timestamp`, so the event shown at `timestamp` comes out whole,
+    /// after the earlier ones of its epoch. Returns the timestamp extraction
+    /// continues from. Without such a point, or for formats other than PGS,
+    /// this is the same as [`Self::seek`].
+    pub fn seek_epoch(&mut self, timestamp: u64) -> Result<u64, ExtractError> {
+        let point = self.epochs.before(timestamp).unwrap_or(timestamp);
+        self.seek(point)?;
+        return Ok(point);
+    }
+
+    /// Where PGS decoding can start from, as far as the track has been read.
+    /// Every display set extraction passes adds to it, and
+    /// [`Self::index_epochs`] fills it in for the whole track.
+    pub fn epochs(&self) -> &EpochIndex {
+        return &self.epochs;
+    }
+
+    /// Replaces [`Self::epochs`], such as with one saved from an earlier run
+    pub fn set_epochs(&mut self, epochs: EpochIndex) {
+        self.epochs = epochs;
+    }
+
+    /// Reads through a PGS track to index its epochs, without rendering
+    /// anything, then starts extraction over from the beginning. Other
+    /// formats have no epochs; their events each decode on their own.
+    pub fn index_epochs(&mut self) -> Result<(), ExtractError> {
+        if !matches!(self.decoder, Decoder::Pgs(..)) {
+            return Ok(());
+        }
+        let track_num = self.track.track_number().get();
+        self.mkv.seek(0)?;
+        while self.mkv.next_frame(&mut self.frame)? {
+            if self
+                .cancel
+                .as_ref()
+                .is_some_and(CancellationToken::is_cancelled)
+            {
+                break;
+            }
+            // Malformed display sets fail again when they're extracted
+            if self.frame.track == track_num && bdsup::refreshes(&self.frame.data).unwrap_or(false)
+            {
+                self.epochs.insert(self.scaled(self.frame.timestamp)?);
+            }
+        }
+        return self.seek(0);
+    }
+
+    /// The event shown at `timestamp` (nanoseconds), if any. PGS tracks are
+    /// decoded from the last point in [`Self::epochs`] before it, found by
+    /// reading backwards from `timestamp` if there's none. Other formats are
+    /// read from [`vobs::MAX_INFERRED_DURATION`] earlier, so longer events
+    /// that started before then are missed. Extraction carries on after the
+    /// event.
+    pub fn subtitle_at(&mut self, timestamp: u64) -> Result<Option<SubtitleEvent>, ExtractError> {
+        let from = match self.decoder {
+            Decoder::Pgs(..) => match self.epochs.before(timestamp) {
+                Some(point) => point,
+                None => match self.find_epoch(timestamp)? {
+                    Some(point) => point,
+                    // Nothing can be shown before the first epoch
+                    None => return Ok(None),
+                },
+            },
+            _ => timestamp.saturating_sub(vobs::MAX_INFERRED_DURATION),
+        };
+        self.seek(from)?;
+        while let Some(event) = self.next_event()? {
+            if event.start > timestamp {
+                return Ok(None);
+            }
+            if event.end.is_none_or(|end| end > timestamp) {
+                return Ok(Some(event));
+            }
+        }
+        return Ok(None);
+    }
+
+    /// Indexes the PGS epochs in a stretch before `timestamp`, doubling it
+    /// until there's one, and returns the last. `None` if there are none
+    /// before `timestamp` at all.
+    fn find_epoch(&mut self, timestamp: u64) -> Result<Option<u64>, ExtractError> {
+        let track_num = self.track.track_number().get();
+        let mut lookback = EPOCH_LOOKBACK;
+        loop {
+            let from = timestamp.saturating_sub(lookback);
+            self.mkv.seek(from / self.timestamp_scale)?;
+            while self.mkv.next_frame(&mut self.frame)? {
+                let frame_timestamp = self.scaled(self.frame.timestamp)?;
+                if frame_timestamp > timestamp {
+                    break;
+                }
+                if self.frame.track == track_num
+                    && bdsup::refreshes(&self.frame.data).unwrap_or(false)
+                {
+                    self.epochs.insert(frame_timestamp);
+                }
+            }
+            if let Some(point) = self.epochs.before(timestamp) {
+                return Ok(Some(point));
+            }
+            if from == 0 {
+                return Ok(None);
+            }
+            lookback = lookback.saturating_mul(2);
+        }
+    }
+
+    /// Timestamp of the last frame read from the track, in nanoseconds, for
+    /// placing errors from [`Self::next_event`]
+    pub fn position(&self) -> u64 {
+        return self.position;
+    }
+
+    /// Number of events dropped because they depended on data from before a seek
+    pub fn skipped_events(&self) -> usize {
+        return match self.decoder {
+            Decoder::Pgs(ref parser, _) => parser.skipped_compositions(),
+            _ => 0,
+        };
+    }
+
+    /// Number of PGS segments with extra bytes that were ignored, see
+    /// [`PgsParser::padded_segments`]
+    pub fn padded_segments(&self) -> usize {
+        return match self.decoder {
+            Decoder::Pgs(ref parser, _) => parser.padded_segments(),
+            _ => 0,
+        };
+    }
+
+    /// Returns the next event, or `None` at the end of the file. Errors only
+    /// affect the frame they occurred in, so it's fine to keep calling this
+    /// after an `Err`.
+    ///
+    /// PGS events are held back until the next display set is read, which
+    /// gives them the time they were cleared or replaced as their end.
+    /// VobSub events are held back the same way, and ones without a stop
+    /// time (or block duration) end when the next starts, up to
+    /// [`vobs::MAX_INFERRED_DURATION`] later. A subpicture repeated before
+    /// the previous one ends just extends it.
+    ///
+    /// Text events from blocks without a duration end the same way as
+    /// VobSub ones, and an event that's still without an end when the track
+    /// runs out lasts [`FALLBACK_DURATION`]. [`SubtitleEvent::end_source`]
+    /// tells which it was.
+    pub fn next_event(&mut self) -> Result<Option<SubtitleEvent>, ExtractError> {
+        if let Some(event) = self.held.take_if(|event| event.end.is_some()) {
+            return Ok(Some(event));
+        }
+        loop {
+            let next = self.decode_event()?;
+            let Some(mut held) = self.held.take() else {
+                match next {
+                    Some(event) if event.end.is_none() => self.held = Some(event),
+                    next => return Ok(next),
+                }
+                continue;
+            };
+            let inferred = next
+                .as_ref()
+                .and_then(|next| vobs::inferred_end(held.start, next.start));
+            (held.end, held.end_source) = match inferred {
+                Some(end) => (Some(end), Some(EndSource::NextEvent)),
+                None => (
+                    Some(held.start.saturating_add(FALLBACK_DURATION)),
+                    Some(EndSource::Fallback),
+                ),
+            };
+            self.held = next;
+            return Ok(Some(held));
+        }
+    }
+
+    /// [`Self::next_event`], before events without an end are given one
+    fn decode_event(&mut self) -> Result<Option<SubtitleEvent>, ExtractError> {
+        while self.read_frame()? {
+            let frame = &mut self.frame;
+            let end = frame
+                .duration
+                .map(|duration| frame.timestamp.saturating_add(duration));
+            let end_source = end.map(|_| EndSource::Container);
+
+            match self.decoder {
+                Decoder::Pgs(ref mut parser, ref mut image) => {
+                    let shown = parser.process_reused_into(&frame.data, image)?;
+                    if parser.refreshed() {
+                        self.epochs.insert(frame.timestamp);
+                    }
+                    // Every display set replaces what's on screen, including
+                    // ones without objects that just clear it, so this is
+                    // where the previous event ends
+                    let closed = self.pending.take().map(|mut event| {
+                        (event.end, event.end_source) = match event.end {
+                            Some(end) if end < frame.timestamp => {
+                                (Some(end), Some(EndSource::Container))
+                            }
+                            _ => (Some(frame.timestamp), Some(EndSource::Stream)),
+                        };
+                        return event;
+                    });
+                    let full = Region {
+                        x: 0,
+                        y: 0,
+                        width: image.width(),
+                        height: image.height(),
+                    };
+                    if shown && let Some(bounds) = content_bounds(image, full) {
+                        // Each window's visible part, relative to the cropped image
+                        let regions = parser
+                            .regions()
+                            .iter()
+                            .filter_map(|region| content_bounds(image, *region))
+                            .map(|region| Region {
+                                x: region.x - bounds.x,
+                                y: region.y - bounds.y,
+                                ..region
+                            })
+                            .collect();
+                        let cropped = imageops::crop_imm(
+                            image,
+                            bounds.x,
+                            bounds.y,
+                            bounds.width,
+                            bounds.height,
+                        )
+                        .to_image();
+                        let indexed = match self.indexed {
+                            true => parser.render_indexed()?.map(|indexed| indexed.crop(bounds)),
+                            false => None,
+                        };
+                        self.pending = Some(SubtitleEvent {
+                            start: frame.timestamp,
+                            end,
+                            end_source,
+                            forced: false,
+                            payload: EventPayload::Image(cropped),
+                            regions,
+                            placement: Some(Placement {
+                                x: bounds.x,
+                                y: bounds.y,
+                                screen_width: image.width(),
+                                screen_height: image.height(),
+                            }),
+                            indexed,
+                        });
+                    }
+                    if closed.is_some() {
+                        return Ok(closed);
+                    }
+                }
+                Decoder::Textst(ref mut parser) => {
+                    if let Some(event) = parser.process_mkv_frame(frame)? {
+                        return Ok(Some(SubtitleEvent {
+                            start: event.start,
+                            end: Some(event.end),
+                            end_source: Some(EndSource::Stream),
+                            forced: event.forced(),
+                            payload: EventPayload::Text(event.text()),
+                            regions: Vec::new(),
+                            placement: None,
+                            indexed: None,
+                        }));
+                    }
+                }
+                Decoder::VobSub(ref idx, ref mut assembler, ref mut timestamp) => {
+                    if !assembler.in_progress() {
+                        *timestamp = frame.timestamp;
+                    }
+                    let Some(data) = assembler.push(&frame.data)? else {
+                        continue;
+                    };
+                    let subpicture = vobs::decode_frame(idx, &data)?;
+                    let image: GrayAlphaImage = subpicture.image.convert();
+                    let full = Region {
+                        x: 0,
+                        y: 0,
+                        width: image.width(),
+                        height: image.height(),
+                    };
+                    let Some(bounds) = content_bounds(&image, full) else {
+                        continue;
+                    };
+                    let indexed = match self.indexed {
+                        true => Some(vobs::parse_frame_indexed(idx, &data)?.crop(bounds)),
+                        false => None,
+                    };
+                    let control = ControlData::from_sequences(&subpicture.sequences);
+                    let placement = match (idx.size, control.coordinates) {
+                        (Some((screen_width, screen_height)), Some(coordinates)) => {
+                            Some(Placement {
+                                x: coordinates.x1 as u32 + bounds.x,
+                                y: coordinates.y1 as u32 + bounds.y,
+                                screen_width,
+                                screen_height,
+                            })
+                        }
+                        _ => None,
+                    };
+                    let event = SubtitleEvent {
+                        start: timestamp.saturating_add(subpicture.start),
+                        end: match subpicture.end {
+                            Some(stop) => Some(timestamp.saturating_add(stop)),
+                            None => end,
+                        },
+                        end_source: match subpicture.end {
+                            Some(_) => Some(EndSource::Stream),
+                            None => end_source,
+                        },
+                        forced: subpicture.forced,
+                        payload: EventPayload::Image(
+                            imageops::crop_imm(
+                                &image,
+                                bounds.x,
+                                bounds.y,
+                                bounds.width,
+                                bounds.height,
+                            )
+                            .to_image(),
+                        ),
+                        regions: Vec::new(),
+                        placement,
+                        indexed,
+                    };
+                    // Events without a stop time end when the next one starts
+                    let Some(mut pending) = self.pending.replace(event) else {
+                        continue;
+                    };
+                    let event = self.pending.as_mut().unwrap();
+                    let repeated = matches!(
+                        (&pending.payload, &event.payload),
+                        (EventPayload::Image(a), EventPayload::Image(b)) if a == b
+                    ) && pending.end.is_none_or(|end| event.start <= end);
+                    if repeated {
+                        // The same subpicture again, so the same subtitle
+                        // stays up
+                        event.start = event.start.min(pending.start);
+                        continue;
+                    }
+                    if pending.end.is_none() {
+                        pending.end = vobs::inferred_end(pending.start, event.start);
+                        pending.end_source = pending.end.map(|_| EndSource::NextEvent);
+                    }
+                    return Ok(Some(pending));
+                }
+                Decoder::Utf8 => {
+                    return Ok(Some(SubtitleEvent {
+                        start: frame.timestamp,
+                        end,
+                        end_source,
+                        forced: false,
+                        payload: EventPayload::Text(
+                            String::from_utf8_lossy(&frame.data).into_owned(),
+                        ),
+                        regions: Vec::new(),
+                        placement: None,
+                        indexed: None,
+                    }));
+                }
+                #[cfg(feature = "ass")]
+                Decoder::Ass(ref mut renderer) => {
+                    let duration = frame.duration.unwrap_or(0);
+                    // Halfway through, clear of any fade in or out
+                    let Some(image) = renderer.render_event(
+                        &frame.data,
+                        frame.timestamp,
+                        duration,
+                        frame.timestamp.saturating_add(duration / 2),
+                    ) else {
+                        continue;
+                    };
+                    let image: GrayAlphaImage = image.convert();
+                    let full = Region {
+                        x: 0,
+                        y: 0,
+                        width: image.width(),
+                        height: image.height(),
+                    };
+                    let Some(bounds) = content_bounds(&image, full) else {
+                        continue;
+                    };
+                    return Ok(Some(SubtitleEvent {
+                        start: frame.timestamp,
+                        end,
+                        end_source,
+                        forced: false,
+                        payload: EventPayload::Image(
+                            imageops::crop_imm(
+                                &image,
+                                bounds.x,
+                                bounds.y,
+                                bounds.width,
+                                bounds.height,
+                            )
+                            .to_image(),
+                        ),
+                        regions: Vec::new(),
+                        placement: Some(Placement {
+                            x: bounds.x,
+                            y: bounds.y,
+                            screen_width: image.width(),
+                            screen_height: image.height(),
+                        }),
+                        indexed: None,
+                    }));
+                }
+            }
+        }
+        // The last event keeps whatever end time the container gave it,
+        // and gets one in `next_event` otherwise
+        return Ok(self.pending.take());
+    }
+
+    /// Reads the track's next frame into `self.frame`, with its timestamp
+    /// and duration in nanoseconds. `false` at the end of the file, or once
+    /// cancelled.
+    fn read_frame(&mut self) -> Result<bool, ExtractError> {
+        let track_num = self.track.track_number().get();
+        loop {
+            if self
+                .cancel
+                .as_ref()
+                .is_some_and(CancellationToken::is_cancelled)
+            {
+                return Ok(false);
+            }
+            if !self.mkv.next_frame(&mut self.frame)? {
+                return Ok(false);
+            }
+            if self.frame.track != track_num {
+                continue;
+            }
+            self.frame.timestamp = self.scaled(self.frame.timestamp)?;
+            self.frame.duration = match self.frame.duration {
+                Some(duration) => Some(self.scaled(duration)?),
+                None => None,
+            };
+            self.position = self.frame.timestamp;
+            return Ok(true);
+        }
+    }
+
+    /// Matroska ticks in nanoseconds
+    fn scaled(&self, ticks: u64) -> Result<u64, ExtractError> {
+        return Timestamp::from_ticks(ticks, self.timestamp_scale)
+            .map(Timestamp::as_nanos)
+            .ok_or(ExtractError::TimestampOverflow(ticks));
+    }
+
+    /// Plays the track from where extraction is, yielding every change to
+    /// what's on screen; see [`Playback`]
+    pub fn play(self) -> Playback<R> {
+        return Playback {
+            extractor: self,
+            queue: VecDeque::new(),
+            shown: Screen::Clear,
+            clear_at: None,
+        };
+    }
+}
+impl<R: Read + Seek> Iterator for SubtitleExtractor<R> {
+    type Item = Result<SubtitleEvent, ExtractError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        return self.next_event().transpose();
+    }
+}
+
+/// Every change to what a track puts on screen, in order, from
+/// [`SubtitleExtractor::play`]. Where events pair one image with its start
+/// and end, this reports each display set on its own (including the palette
+/// updates that fade a subtitle in or out, and the ones that erase it), each
+/// of a VobSub subpicture's control sequences, and the times subtitles end,
+/// each as soon as it's read. States that don't change anything are left
+/// out. A subtitle without an end of its own stays up until the next state.
+pub struct Playback<R: Read + Seek> {
+    extractor: SubtitleExtractor<R>,
+    /// States read but not yielded yet
+    queue: VecDeque<ScreenState>,
+    /// The last state queued
+    shown: Screen,
+    /// When what's shown ends, if its frame said
+    clear_at: Option<u64>,
+}
+impl<R: Read + Seek> Playback<R> {
+    fn show(&mut self, timestamp: u64, screen: Screen) {
+        if screen == self.shown {
+            return;
+        }
+        self.shown = screen.clone();
+        self.queue.push_back(ScreenState { timestamp, screen });
+    }
+
+    /// Queues the states the track's next frame shows. `false` at the end.
+    fn read(&mut self) -> Result<bool, ExtractError> {
+        let extractor = &mut self.extractor;
+        if !extractor.read_frame()? {
+            return Ok(false);
+        }
+        let frame = &extractor.frame;
+        let mut end = frame
+            .duration
+            .map(|duration| frame.timestamp.saturating_add(duration));
+        let mut states = Vec::new();
+        match extractor.decoder {
+            Decoder::Pgs(ref mut parser, ref mut image) => {
+                let shown = parser.process_reused_into(&frame.data, image)?;
+                if parser.refreshed() {
+                    extractor.epochs.insert(frame.timestamp);
+                }
+                let screen = match shown {
+                    true => parser
+                        .render_indexed()?
+                        .map(|indexed| visible(indexed.to_rgba(), 0, 0, Some(image.dimensions())))
+                        .unwrap_or(Screen::Clear),
+                    false => Screen::Clear,
+                };
+                states.push((frame.timestamp, screen));
+            }
+            Decoder::Textst(ref mut parser) => {
+                if let Some(event) = parser.process_mkv_frame(frame)? {
+                    states.push((event.start, Screen::Text(event.text())));
+                    end = Some(event.end);
+                }
+            }
+            Decoder::VobSub(ref idx, ref mut assembler, ref mut timestamp) => {
+                if !assembler.in_progress() {
+                    *timestamp = frame.timestamp;
+                }
+                if let Some(data) = assembler.push(&frame.data)? {
+                    for (delay, image, control) in vobs::decode_states(idx, &data)? {
+                        let screen = match (image, control.coordinates) {
+                            (Some(image), Some(coordinates)) => visible(
+                                image.to_rgba(),
+                                coordinates.x1 as u32,
+                                coordinates.y1 as u32,
+                                idx.size,
+                            ),
+                            (Some(image), None) => visible(image.to_rgba(), 0, 0, None),
+                            (None, _) => Screen::Clear,
+                        };
+                        states.push((timestamp.saturating_add(delay), screen));
+                    }
+                }
+            }
+            Decoder::Utf8 => {
+                let text = String::from_utf8_lossy(&frame.data).into_owned();
+                states.push((frame.timestamp, Screen::Text(text)));
+            }
+            // Only as the event starts; libass animations aren't followed
+            #[cfg(feature = "ass")]
+            Decoder::Ass(ref mut renderer) => {
+                let duration = frame.duration.unwrap_or(0);
+                let size = renderer.frame_size();
+                let screen = renderer
+                    .render_event(&frame.data, frame.timestamp, duration, frame.timestamp)
+                    .map(|image| visible(image, 0, 0, Some(size)))
+                    .unwrap_or(Screen::Clear);
+                states.push((frame.timestamp, screen));
+            }
+        }
+
+        let Some(&(first, _)) = states.first() else {
+            return Ok(true);
+        };
+        // What's shown ends on its own unless it's replaced first
+        if let Some(clear) = self.clear_at.take()
+            && clear <= first
+        {
+            self.show(clear, Screen::Clear);
+        }
+        for (timestamp, screen) in states {
+            self.show(timestamp, screen);
+        }
+        if self.shown != Screen::Clear {
+            self.clear_at = end;
+        }
+        return Ok(true);
+    }
+}
+impl<R: Read + Seek> Iterator for Playback<R> {
+    type Item = Result<ScreenState, ExtractError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(state) = self.queue.pop_front() {
+                return Some(Ok(state));
+            }
+            match self.read() {
+                Ok(true) => {}
+                Ok(false) => {
+                    // The end of the last subtitle, if it has one
+                    let clear = self.clear_at.take()?;
+                    self.show(clear, Screen::Clear);
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// The visible part of a decoded image drawn at (`x`, `y`) on a screen of
+/// the given size, or [`Screen::Clear`] if none of it is
+fn visible(image: RgbaImage, x: u32, y: u32, screen: Option<(u32, u32)>) -> Screen {
+    let alpha: GrayAlphaImage = image.convert();
+    let full = Region {
+        x: 0,
+        y: 0,
+        width: image.width(),
+        height: image.height(),
+    };
+    let Some(bounds) = content_bounds(&alpha, full) else {
+        return Screen::Clear;
+    };
+    return Screen::Image {
+        image: imageops::crop_imm(&image, bounds.x, bounds.y, bounds.width, bounds.height)
+            .to_image(),
+        placement: screen.map(|(screen_width, screen_height)| Placement {
+            x: x + bounds.x,
+            y: y + bounds.y,
+            screen_width,
+            screen_height,
+        }),
+    };
+}
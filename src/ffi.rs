@@ -0,0 +1,281 @@
+//! C API for embedding the decoders without going through the binary. The
+//! matching header is `include/subproc.h`, generated with
+//! `cbindgen --config cbindgen.toml --output include/subproc.h`.
+//!
+//! Errors are reported through return values, with the message available from
+//! `subproc_last_error` on the same thread. Panics in the decoders are caught
+//! and reported the same way rather than unwinding into the caller.
+
+use std::{
+    any::Any,
+    cell::RefCell,
+    ffi::{CStr, CString, c_char},
+    fs::File,
+    io::BufReader,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use matroska_demuxer::MatroskaFile;
+
+use crate::{
+    extract::{EventPayload, SubtitleEvent, SubtitleExtractor},
+    model::EndSource,
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// The last error for a panic caught at the API boundary
+fn panic_error(payload: Box<dyn Any + Send>) -> String {
+    let message = match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => String::from(*message),
+            Err(_) => String::from("unknown cause"),
+        },
+    };
+    return format!("decoder panicked: {message}");
+}
+
+/// Opaque handle to an open subtitle track
+pub struct SubprocExtractor {
+    inner: SubtitleExtractor<BufReader<File>>,
+}
+
+/// Opaque handle to a decoded event
+pub struct SubprocEvent {
+    event: SubtitleEvent,
+    /// NUL-terminated copy of the text payload
+    text: Option<CString>,
+}
+
+/// Borrowed view of an event's bitmap. Pixels are 8-bit gray + 8-bit alpha
+/// pairs, `stride` bytes per row.
+#[repr(C)]
+pub struct SubprocImage {
+    pub width: u32,
+    pub height: u32,
+    pub stride: u32,
+    pub data: *const u8,
+}
+
+/// Where an event's end time came from, as recorded in the manifest
+#[repr(C)]
+pub enum SubprocEndSource {
+    /// The event has no end time
+    Unknown,
+    /// The subtitle stream itself, e.g. the PGS display set that cleared it
+    Stream,
+    /// The container's block duration
+    Container,
+    /// Inferred: it lasts until the next event starts, up to a limit
+    NextEvent,
+    /// Inferred: no event follows, so it lasts a fixed time
+    Fallback,
+}
+
+/// Returns the message for the last failed call on this thread, or NULL. The
+/// string stays valid until the next failing call on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn subproc_last_error() -> *const c_char {
+    return LAST_ERROR.with(|last| match *last.borrow() {
+        Some(ref message) => message.as_ptr(),
+        None => ptr::null(),
+    });
+}
+
+/// Opens `path` and selects subtitle track `track`, or the first subtitle track
+/// if `track` is 0. Returns NULL on failure.
+///
+/// # Safety
+/// `path` must be a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn subproc_open(path: *const c_char, track: u64) -> *mut SubprocExtractor {
+    if path.is_null() {
+        set_last_error(String::from("path is NULL"));
+        return ptr::null_mut();
+    }
+    let path = match unsafe { CStr::from_ptr(path) }.to_str() {
+        Ok(path) => path,
+        Err(err) => {
+            set_last_error(format!("path is not valid UTF-8: {err}"));
+            return ptr::null_mut();
+        }
+    };
+    let result = panic::catch_unwind(|| {
+        return File::open(path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| {
+                MatroskaFile::open(BufReader::new(file)).map_err(|err| err.to_string())
+            })
+            .and_then(|mkv| {
+                SubtitleExtractor::new(mkv, Some(track).filter(|track| *track != 0))
+                    .map_err(|err| err.to_string())
+            });
+    })
+    .unwrap_or_else(|payload| Err(panic_error(payload)));
+    return match result {
+        Ok(inner) => Box::into_raw(Box::new(SubprocExtractor { inner })),
+        Err(err) => {
+            set_last_error(err);
+            ptr::null_mut()
+        }
+    };
+}
+
+/// Decodes the next event into `*event`. Returns 1 if an event was produced,
+/// 0 at the end of the track, and -1 if a frame failed to decode; decoding can
+/// continue after an error. A panicking decoder also returns -1, after which
+/// the extractor should only be freed.
+///
+/// # Safety
+/// `extractor` must come from `subproc_open`, and `event` must be writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn subproc_next_event(
+    extractor: *mut SubprocExtractor,
+    event: *mut *mut SubprocEvent,
+) -> i32 {
+    let (Some(extractor), false) = (unsafe { extractor.as_mut() }, event.is_null()) else {
+        set_last_error(String::from("extractor or event is NULL"));
+        return -1;
+    };
+    unsafe { *event = ptr::null_mut() };
+    let next = match panic::catch_unwind(AssertUnwindSafe(|| extractor.inner.next_event())) {
+        Ok(next) => next,
+        Err(payload) => {
+            set_last_error(panic_error(payload));
+            return -1;
+        }
+    };
+    return match next {
+        Ok(Some(next)) => {
+            let text = match next.payload {
+                EventPayload::Text(ref text) => {
+                    Some(CString::new(text.replace('\0', "")).unwrap_or_default())
+                }
+                EventPayload::Image(_) => None,
+            };
+            let boxed = Box::new(SubprocEvent { event: next, text });
+            unsafe { *event = Box::into_raw(boxed) };
+            1
+        }
+        Ok(None) => 0,
+        Err(err) => {
+            set_last_error(err.to_string());
+            -1
+        }
+    };
+}
+
+/// Start time in nanoseconds
+///
+/// # Safety
+/// `event` must come from `subproc_next_event`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn subproc_event_start(event: *const SubprocEvent) -> u64 {
+    return unsafe { &*event }.event.start;
+}
+
+/// End time in nanoseconds. Events get one even when neither the stream nor
+/// the container gives it, inferred from the next event;
+/// `subproc_event_end_source` tells which. -1 if the event has none.
+///
+/// # Safety
+/// `event` must come from `subproc_next_event`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn subproc_event_end(event: *const SubprocEvent) -> i64 {
+    return unsafe { &*event }
+        .event
+        .end
+        .map_or(-1, |end| end.min(i64::MAX as u64) as i64);
+}
+
+/// Where the end time came from, and so whether it was inferred
+///
+/// # Safety
+/// `event` must come from `subproc_next_event`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn subproc_event_end_source(event: *const SubprocEvent) -> SubprocEndSource {
+    return match unsafe { &*event }.event.end_source {
+        None => SubprocEndSource::Unknown,
+        Some(EndSource::Stream) => SubprocEndSource::Stream,
+        Some(EndSource::Container) => SubprocEndSource::Container,
+        Some(EndSource::NextEvent) => SubprocEndSource::NextEvent,
+        Some(EndSource::Fallback) => SubprocEndSource::Fallback,
+    };
+}
+
+/// # Safety
+/// `event` must come from `subproc_next_event`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn subproc_event_forced(event: *const SubprocEvent) -> bool {
+    return unsafe { &*event }.event.forced;
+}
+
+/// Text of a text event, or NULL for image events. Valid until the event is freed.
+///
+/// # Safety
+/// `event` must come from `subproc_next_event`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn subproc_event_text(event: *const SubprocEvent) -> *const c_char {
+    return match unsafe { &*event }.text {
+        Some(ref text) => text.as_ptr(),
+        None => ptr::null(),
+    };
+}
+
+/// Fills `*image` with the bitmap of an image event and returns true, or
+/// returns false for text events. The pixels are valid until the event is freed.
+///
+/// # Safety
+/// `event` must come from `subproc_next_event`, and `image` must be writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn subproc_event_image(
+    event: *const SubprocEvent,
+    image: *mut SubprocImage,
+) -> bool {
+    let EventPayload::Image(ref bitmap) = unsafe { &*event }.event.payload else {
+        return false;
+    };
+    if image.is_null() {
+        return false;
+    }
+    unsafe {
+        *image = SubprocImage {
+            width: bitmap.width(),
+            height: bitmap.height(),
+            stride: bitmap.width() * 2,
+            data: bitmap.as_raw().as_ptr(),
+        };
+    }
+    return true;
+}
+
+/// # Safety
+/// `event` must come from `subproc_next_event` and not be used afterwards.
+/// NULL is ignored.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn subproc_event_free(event: *mut SubprocEvent) {
+    if !event.is_null() {
+        drop(unsafe { Box::from_raw(event) });
+    }
+}
+
+/// Closes an extractor. Events already returned stay valid.
+///
+/// # Safety
+/// `extractor` must come from `subproc_open` and not be used afterwards.
+/// NULL is ignored.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn subproc_free(extractor: *mut SubprocExtractor) {
+    if !extractor.is_null() {
+        drop(unsafe { Box::from_raw(extractor) });
+    }
+}
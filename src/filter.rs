@@ -0,0 +1,392 @@
+//! Event filtering, so junk like logo bitmaps and one-frame flashes can be
+//! dropped before OCR and output. Filters are either a closure (see
+//! [`filter_events`]) or a small expression parsed by [`Filter::parse`]:
+//!
+//! ```text
+//! duration >= 100ms and area < 80%
+//! forced or (text and time in 00:10..00:20)
+//! not image
+//! ```
+//!
+//! - Flags: `forced`, `image`, `text`
+//! - Times: `start`, `end` and `duration`, compared against `500ms`, `2s`,
+//!   `1.5m`, `1h`, `MM:SS[.mmm]` or `HH:MM:SS[.mmm]` (plain numbers are seconds)
+//! - Sizes: `width`, `height` and `area`, in pixels or as a percentage of the
+//!   screen (only known for image formats that say where they're placed)
+//! - `regions`: the number of separately placed parts of an image
+//! - `time in A..B`: the event starts at or after `A` and before `B`
+//! - Comparisons are `<`, `<=`, `>`, `>=`, `==` and `!=`, combined with
+//!   `and`/`&&`, `or`/`||`, `not`/`!` and parentheses
+//!
+//! Comparisons involving something the event doesn't have (the end time of an
+//! event the container gave no duration, the area of a text event) are false.
+
+use thiserror::Error;
+
+use crate::{
+    extract::{EventPayload, SubtitleEvent},
+    timebase::Timestamp,
+};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum FilterError {
+    #[error("Unexpected end of filter expression.")]
+    UnexpectedEnd,
+    #[error("Unexpected `{0}` in filter expression.")]
+    Unexpected(String),
+    #[error("Unknown field `{0}` in filter expression.")]
+    UnknownField(String),
+    #[error("Invalid value `{value}` for `{field}`.")]
+    InvalidValue { field: String, value: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Start,
+    End,
+    Duration,
+    Width,
+    Height,
+    Area,
+    Regions,
+}
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        return Some(match name {
+            "start" => Self::Start,
+            "end" => Self::End,
+            "duration" => Self::Duration,
+            "width" => Self::Width,
+            "height" => Self::Height,
+            "area" => Self::Area,
+            "regions" => Self::Regions,
+            _ => return None,
+        });
+    }
+
+    fn is_time(&self) -> bool {
+        return matches!(self, Self::Start | Self::End | Self::Duration);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+impl Comparison {
+    fn holds(&self, a: f64, b: f64) -> bool {
+        return match self {
+            Self::Less => a < b,
+            Self::LessOrEqual => a <= b,
+            Self::Greater => a > b,
+            Self::GreaterOrEqual => a >= b,
+            Self::Equal => a == b,
+            Self::NotEqual => a != b,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    /// Times, in nanoseconds
+    Nanoseconds(u64),
+    /// Sizes relative to the screen
+    Percent(f64),
+    /// Pixels, or a count
+    Number(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Forced,
+    Image,
+    Text,
+    Compare(Field, Comparison, Value),
+    /// Start time in `[from, to)`, nanoseconds
+    Within(u64, u64),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+impl Filter {
+    pub fn parse(expression: &str) -> Result<Self, FilterError> {
+        let mut parser = Parser {
+            tokens: tokenize(expression)?,
+            position: 0,
+        };
+        let filter = parser.parse_or()?;
+        if let Some(token) = parser.tokens.get(parser.position) {
+            return Err(FilterError::Unexpected(token.to_string()));
+        }
+        return Ok(filter);
+    }
+
+    pub fn matches(&self, event: &SubtitleEvent) -> bool {
+        return match self {
+            Self::Forced => event.forced,
+            Self::Image => matches!(event.payload, EventPayload::Image(_)),
+            Self::Text => matches!(event.payload, EventPayload::Text(_)),
+            Self::Compare(field, comparison, value) => field_value(event, *field, value)
+                .is_some_and(|(actual, expected)| {
+                    return comparison.holds(actual, expected);
+                }),
+            Self::Within(from, to) => (*from..*to).contains(&event.start),
+            Self::Not(filter) => !filter.matches(event),
+            Self::And(a, b) => a.matches(event) && b.matches(event),
+            Self::Or(a, b) => a.matches(event) || b.matches(event),
+        };
+    }
+}
+
+/// Keeps the events `predicate` accepts. Errors are passed through, so this
+/// can sit directly on top of a [`crate::extract::SubtitleExtractor`].
+pub fn filter_events<I, E, P>(events: I, mut predicate: P) -> impl Iterator<Item = I::Item>
+where
+    I: IntoIterator<Item = Result<SubtitleEvent, E>>,
+    P: FnMut(&SubtitleEvent) -> bool,
+{
+    return events
+        .into_iter()
+        .filter(move |event| event.as_ref().map_or(true, &mut predicate));
+}
+
+/// The event's value for `field` and the value it's compared against, in the
+/// same unit
+fn field_value(event: &SubtitleEvent, field: Field, value: &Value) -> Option<(f64, f64)> {
+    let image = match event.payload {
+        EventPayload::Image(ref image) => Some(image.dimensions()),
+        EventPayload::Text(_) => None,
+    };
+    let screen = event.placement.map(|placement| {
+        (
+            placement.screen_width as f64,
+            placement.screen_height as f64,
+        )
+    });
+    let actual = match field {
+        Field::Start => event.start as f64,
+        Field::End => event.end? as f64,
+        Field::Duration => event.end?.saturating_sub(event.start) as f64,
+        Field::Width => image?.0 as f64,
+        Field::Height => image?.1 as f64,
+        Field::Area => (image?.0 * image?.1) as f64,
+        Field::Regions => event.regions.len().max(image.map_or(0, |_| 1)) as f64,
+    };
+    return match *value {
+        Value::Nanoseconds(ns) => Some((actual, ns as f64)),
+        Value::Number(number) => Some((actual, number)),
+        Value::Percent(percent) => {
+            let (width, height) = screen?;
+            let total = match field {
+                Field::Width => width,
+                Field::Height => height,
+                _ => width * height,
+            };
+            Some((actual * 100.0 / total, percent))
+        }
+    };
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Compare(Comparison),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Self::Word(word) => f.write_str(word),
+            Self::Compare(comparison) => f.write_str(match comparison {
+                Comparison::Less => "<",
+                Comparison::LessOrEqual => "<=",
+                Comparison::Greater => ">",
+                Comparison::GreaterOrEqual => ">=",
+                Comparison::Equal => "==",
+                Comparison::NotEqual => "!=",
+            }),
+            Self::And => f.write_str("and"),
+            Self::Or => f.write_str("or"),
+            Self::Not => f.write_str("not"),
+            Self::Open => f.write_str("("),
+            Self::Close => f.write_str(")"),
+        };
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, FilterError> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(c) = chars.next() {
+        let mut followed_by = |next: char| chars.next_if_eq(&next).is_some();
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '<' if followed_by('=') => Token::Compare(Comparison::LessOrEqual),
+            '<' => Token::Compare(Comparison::Less),
+            '>' if followed_by('=') => Token::Compare(Comparison::GreaterOrEqual),
+            '>' => Token::Compare(Comparison::Greater),
+            '=' if followed_by('=') => Token::Compare(Comparison::Equal),
+            '=' => Token::Compare(Comparison::Equal),
+            '!' if followed_by('=') => Token::Compare(Comparison::NotEqual),
+            '!' => Token::Not,
+            '&' if followed_by('&') => Token::And,
+            '|' if followed_by('|') => Token::Or,
+            '&' | '|' => return Err(FilterError::Unexpected(c.to_string())),
+            c => {
+                let mut word = String::from(c);
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !"()<>=!&|".contains(*c))
+                {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Word(word),
+                }
+            }
+        };
+        tokens.push(token);
+    }
+    return Ok(tokens);
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+impl Parser {
+    fn next(&mut self) -> Result<Token, FilterError> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or(FilterError::UnexpectedEnd)?;
+        self.position += 1;
+        return Ok(token);
+    }
+
+    fn next_if(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.position) == Some(token) {
+            self.position += 1;
+            return true;
+        }
+        return false;
+    }
+
+    fn parse_or(&mut self) -> Result<Filter, FilterError> {
+        let mut filter = self.parse_and()?;
+        while self.next_if(&Token::Or) {
+            filter = Filter::Or(Box::new(filter), Box::new(self.parse_and()?));
+        }
+        return Ok(filter);
+    }
+
+    fn parse_and(&mut self) -> Result<Filter, FilterError> {
+        let mut filter = self.parse_unary()?;
+        while self.next_if(&Token::And) {
+            filter = Filter::And(Box::new(filter), Box::new(self.parse_unary()?));
+        }
+        return Ok(filter);
+    }
+
+    fn parse_unary(&mut self) -> Result<Filter, FilterError> {
+        let word = match self.next()? {
+            Token::Not => return Ok(Filter::Not(Box::new(self.parse_unary()?))),
+            Token::Open => {
+                let filter = self.parse_or()?;
+                return match self.next()? {
+                    Token::Close => Ok(filter),
+                    token => Err(FilterError::Unexpected(token.to_string())),
+                };
+            }
+            Token::Word(word) => word,
+            token => return Err(FilterError::Unexpected(token.to_string())),
+        };
+        match word.as_str() {
+            "forced" => return Ok(Filter::Forced),
+            "image" => return Ok(Filter::Image),
+            "text" => return Ok(Filter::Text),
+            "time" => {
+                match self.next()? {
+                    Token::Word(word) if word == "in" => {}
+                    token => return Err(FilterError::Unexpected(token.to_string())),
+                }
+                let Token::Word(range) = self.next()? else {
+                    return Err(FilterError::Unexpected(
+                        self.tokens[self.position - 1].to_string(),
+                    ));
+                };
+                let invalid = || FilterError::InvalidValue {
+                    field: String::from("time"),
+                    value: range.clone(),
+                };
+                let (from, to) = range.split_once("..").ok_or_else(invalid)?;
+                return Ok(Filter::Within(
+                    parse_time(from).ok_or_else(invalid)?,
+                    parse_time(to).ok_or_else(invalid)?,
+                ));
+            }
+            _ => {}
+        }
+        let field = Field::from_name(&word).ok_or(FilterError::UnknownField(word.clone()))?;
+        let comparison = match self.next()? {
+            Token::Compare(comparison) => comparison,
+            token => return Err(FilterError::Unexpected(token.to_string())),
+        };
+        let Token::Word(value) = self.next()? else {
+            return Err(FilterError::Unexpected(
+                self.tokens[self.position - 1].to_string(),
+            ));
+        };
+        let parsed = if field.is_time() {
+            parse_time(&value).map(Value::Nanoseconds)
+        } else if let Some(percent) = value.strip_suffix('%') {
+            percent
+                .parse()
+                .ok()
+                .filter(|_| field != Field::Regions)
+                .map(Value::Percent)
+        } else {
+            value.parse().ok().map(Value::Number)
+        };
+        let value = parsed.ok_or_else(|| FilterError::InvalidValue {
+            field: word.clone(),
+            value: value.clone(),
+        })?;
+        return Ok(Filter::Compare(field, comparison, value));
+    }
+}
+
+/// Parses `500ms`, `2s`, `1.5m`, `1h`, `[HH:]MM:SS[.mmm]` or plain seconds
+/// into nanoseconds
+fn parse_time(value: &str) -> Option<u64> {
+    let seconds = if let Some(ms) = value.strip_suffix("ms") {
+        ms.parse::<f64>().ok()? / 1000.0
+    } else if let Some(s) = value.strip_suffix('s') {
+        s.parse().ok()?
+    } else if let Some(m) = value.strip_suffix('m') {
+        m.parse::<f64>().ok()? * 60.0
+    } else if let Some(h) = value.strip_suffix('h') {
+        h.parse::<f64>().ok()? * 3600.0
+    } else {
+        let mut seconds = 0.0;
+        for part in value.split(':') {
+            seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+        }
+        seconds
+    };
+    return Timestamp::from_secs_f64(seconds).map(Timestamp::as_nanos);
+}
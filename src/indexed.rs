@@ -0,0 +1,140 @@
+//! Paletted subtitle bitmaps that keep the stream's own color indices, for
+//! exporting without flattening to grayscale. Tools downstream can then
+//! requantize or restyle the subtitles, or write them back out, without
+//! recolor artifacts.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Read, Write},
+    path::Path,
+};
+
+use image::{Rgba, RgbaImage};
+
+use crate::preprocess::Region;
+
+/// PNG text keyword the source palette is stored under
+pub const PALETTE_KEYWORD: &str = "subproc:palette";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedImage {
+    pub width: u32,
+    pub height: u32,
+    /// One palette index per pixel, row by row
+    pub indices: Vec<u8>,
+    /// The color of each index. Indices past the end are transparent.
+    pub palette: Vec<Rgba<u8>>,
+    /// The palette in the source format's own terms, since converting it to
+    /// RGBA loses precision. PGS gives `<id>=<YYCrCbAA>` per entry; VobSub
+    /// gives its 16 idx colors, with each index being `color << 4 | alpha`.
+    pub source_palette: String,
+}
+impl IndexedImage {
+    pub fn new(width: u32, height: u32, palette: Vec<Rgba<u8>>, source_palette: String) -> Self {
+        return Self {
+            width,
+            height,
+            indices: vec![0; width as usize * height as usize],
+            palette,
+            source_palette,
+        };
+    }
+
+    pub fn index(&self, x: u32, y: u32) -> u8 {
+        return self.indices[(y * self.width + x) as usize];
+    }
+
+    pub fn set_index(&mut self, x: u32, y: u32, index: u8) {
+        self.indices[(y * self.width + x) as usize] = index;
+    }
+
+    pub fn color(&self, index: u8) -> Rgba<u8> {
+        return self
+            .palette
+            .get(index as usize)
+            .copied()
+            .unwrap_or(Rgba([0, 0, 0, 0]));
+    }
+
+    /// The part of the image inside `region`, with the same palette
+    pub fn crop(&self, region: Region) -> Self {
+        let mut indices = Vec::with_capacity(region.width as usize * region.height as usize);
+        for y in region.y..region.y + region.height {
+            let start = (y * self.width + region.x) as usize;
+            indices.extend_from_slice(&self.indices[start..start + region.width as usize]);
+        }
+        return Self {
+            width: region.width,
+            height: region.height,
+            indices,
+            palette: self.palette.clone(),
+            source_palette: self.source_palette.clone(),
+        };
+    }
+
+    pub fn to_rgba(&self) -> RgbaImage {
+        return RgbaImage::from_fn(self.width, self.height, |x, y| self.color(self.index(x, y)));
+    }
+
+    /// Writes an 8-bit indexed PNG, with alpha in a tRNS chunk and
+    /// [`Self::source_palette`] as text under [`PALETTE_KEYWORD`]
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        // PNG needs an entry for every index used, so always write all 256
+        let mut plte = Vec::with_capacity(256 * 3);
+        let mut trns = Vec::with_capacity(256);
+        for index in 0..=255 {
+            let Rgba([r, g, b, a]) = self.color(index);
+            plte.extend_from_slice(&[r, g, b]);
+            trns.push(a);
+        }
+        encoder.set_palette(plte);
+        encoder.set_trns(trns);
+        encoder.add_text_chunk(PALETTE_KEYWORD.to_owned(), self.source_palette.clone())?;
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.indices)?;
+        return writer.finish();
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), png::EncodingError> {
+        let file = File::create(path).map_err(png::EncodingError::from)?;
+        return self.write_png(BufWriter::new(file));
+    }
+}
+
+/// Reads an indexed PNG written by [`IndexedImage::write_png`]. Returns
+/// `None` for PNGs that aren't 8-bit indexed.
+pub fn read_png<R: Read>(reader: R) -> Result<Option<IndexedImage>, png::DecodingError> {
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let mut reader = decoder.read_info()?;
+    let mut indices = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut indices)?;
+    let info = reader.info();
+    if frame.color_type != png::ColorType::Indexed || frame.bit_depth != png::BitDepth::Eight {
+        return Ok(None);
+    }
+    indices.truncate(frame.buffer_size());
+    let plte = info.palette.as_deref().unwrap_or_default();
+    let trns = info.trns.as_deref().unwrap_or_default();
+    let palette = plte
+        .chunks_exact(3)
+        .enumerate()
+        .map(|(i, rgb)| Rgba([rgb[0], rgb[1], rgb[2], trns.get(i).copied().unwrap_or(255)]))
+        .collect();
+    let source_palette = info
+        .uncompressed_latin1_text
+        .iter()
+        .find(|chunk| chunk.keyword == PALETTE_KEYWORD)
+        .map(|chunk| chunk.text.clone())
+        .unwrap_or_default();
+    return Ok(Some(IndexedImage {
+        width: frame.width,
+        height: frame.height,
+        indices,
+        palette,
+        source_palette,
+    }));
+}
@@ -0,0 +1,321 @@
+//! Reading inputs from slow storage. Media libraries often live on SMB or NFS
+//! mounts, where every small read is a network round trip and several jobs
+//! reading at once fight over the link, so how inputs are read is tunable:
+//! bigger reads, reading ahead on a background thread while the demuxer works
+//! through what's already arrived, and a cap on how many files are open at
+//! once. Local files can also be memory-mapped (with the `mmap` feature),
+//! leaving the caching to the OS and giving parsers the whole file as a slice,
+//! and MKVs streamed through a pipe are read with [`StreamInput`].
+
+#[cfg(feature = "mmap")]
+use std::io::Cursor;
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    marker::PhantomData,
+    path::Path,
+    sync::{
+        Arc, Condvar, Mutex,
+        mpsc::{self, Receiver, Sender, SyncSender},
+    },
+    thread,
+};
+
+use crate::stream::StreamInput;
+
+/// Bytes read at a time by default. Much more than `BufReader`'s 8 KiB, which
+/// is a lot of round trips over a network share.
+pub const DEFAULT_BUFFER_SIZE: usize = 256 * 1024;
+
+/// How inputs are read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoStrategy {
+    /// Bytes read from the file at a time
+    pub buffer_size: usize,
+    /// Reads of `buffer_size` done ahead of the reader on a background
+    /// thread. 0 reads only when asked.
+    pub read_ahead: usize,
+    /// Map the file into memory instead of reading it, ignoring the options
+    /// above
+    #[cfg(feature = "mmap")]
+    pub mmap: bool,
+}
+impl Default for IoStrategy {
+    fn default() -> Self {
+        return Self {
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            read_ahead: 0,
+            #[cfg(feature = "mmap")]
+            mmap: false,
+        };
+    }
+}
+impl IoStrategy {
+    pub fn open(&self, path: &Path) -> io::Result<Input> {
+        let file = File::open(path)?;
+        #[cfg(feature = "mmap")]
+        if self.mmap {
+            // SAFETY: the map is only read. Another process truncating the
+            // file while it's mapped would fault, which is the documented
+            // catch of mapping files at all.
+            let map = unsafe { memmap2::Mmap::map(&file)? };
+            return Ok(Input::Mapped(Cursor::new(map)));
+        }
+        if self.read_ahead > 0 {
+            return Ok(Input::ReadAhead(ReadAhead::new(
+                file,
+                self.buffer_size,
+                self.read_ahead,
+            )?));
+        }
+        return Ok(Input::Buffered(BufReader::with_capacity(
+            self.buffer_size,
+            file,
+        )));
+    }
+}
+
+/// A file opened with an [`IoStrategy`]
+pub enum Input {
+    Buffered(BufReader<File>),
+    ReadAhead(ReadAhead<File>),
+    #[cfg(feature = "mmap")]
+    Mapped(Cursor<memmap2::Mmap>),
+    Stream(StreamInput<Box<dyn Read + Send>>),
+}
+impl Input {
+    /// The whole file, if it's mapped, for parsers that work on slices
+    pub fn as_slice(&self) -> Option<&[u8]> {
+        #[cfg(feature = "mmap")]
+        if let Input::Mapped(map) = self {
+            return Some(map.get_ref());
+        }
+        return None;
+    }
+}
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        return match self {
+            Input::Buffered(reader) => reader.read(buf),
+            Input::ReadAhead(reader) => reader.read(buf),
+            #[cfg(feature = "mmap")]
+            Input::Mapped(map) => map.read(buf),
+            Input::Stream(stream) => stream.read(buf),
+        };
+    }
+}
+impl Seek for Input {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        return match self {
+            Input::Buffered(reader) => reader.seek(pos),
+            Input::ReadAhead(reader) => reader.seek(pos),
+            #[cfg(feature = "mmap")]
+            Input::Mapped(map) => map.seek(pos),
+            Input::Stream(stream) => stream.seek(pos),
+        };
+    }
+}
+
+/// A chunk read by the worker, tagged with the seek it follows from
+type Chunk = (u64, io::Result<Vec<u8>>);
+
+/// Reads sequentially on a background thread, keeping up to `depth` chunks
+/// ready. Seeks within the current chunk are free; others discard what was
+/// read ahead and restart the worker from the new position.
+pub struct ReadAhead<R> {
+    chunks: Receiver<Chunk>,
+    seeks: Sender<(u64, u64)>,
+    /// Counts seeks, so chunks read before one can be told apart
+    generation: u64,
+    chunk: Vec<u8>,
+    /// Position in the stream of the start of `chunk`
+    chunk_start: u64,
+    offset: usize,
+    /// The worker has reached the end, or failed, since the last seek
+    done: bool,
+    len: u64,
+    reader: PhantomData<R>,
+}
+impl<R: Read + Seek + Send + 'static> ReadAhead<R> {
+    pub fn new(mut reader: R, chunk_size: usize, depth: usize) -> io::Result<Self> {
+        let start = reader.stream_position()?;
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(start))?;
+        let (chunk_sender, chunks) = mpsc::sync_channel(depth.max(1));
+        let (seeks, seek_receiver) = mpsc::channel();
+        thread::spawn(move || prefetch(reader, chunk_size.max(1), chunk_sender, seek_receiver));
+        return Ok(Self {
+            chunks,
+            seeks,
+            generation: 0,
+            chunk: Vec::new(),
+            chunk_start: start,
+            offset: 0,
+            done: false,
+            len,
+            reader: PhantomData,
+        });
+    }
+
+    /// Takes the worker's next chunk for the current position
+    fn next_chunk(&mut self) -> io::Result<()> {
+        loop {
+            let (generation, chunk) = self
+                .chunks
+                .recv()
+                .map_err(|_| io::Error::other("The read-ahead thread stopped"))?;
+            if generation != self.generation {
+                continue;
+            }
+            let position = self.chunk_start + self.chunk.len() as u64;
+            match chunk {
+                Ok(chunk) => {
+                    self.done = chunk.is_empty();
+                    self.chunk = chunk;
+                    self.chunk_start = position;
+                    self.offset = 0;
+                    return Ok(());
+                }
+                Err(err) => {
+                    // Reading again retries from the same place
+                    self.restart(position)?;
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Drops the chunks read ahead and has the worker continue from `position`
+    fn restart(&mut self, position: u64) -> io::Result<()> {
+        self.generation += 1;
+        self.seeks
+            .send((self.generation, position))
+            .map_err(|_| io::Error::other("The read-ahead thread stopped"))?;
+        self.chunk.clear();
+        self.chunk_start = position;
+        self.offset = 0;
+        self.done = false;
+        return Ok(());
+    }
+}
+impl<R: Read + Seek + Send + 'static> Read for ReadAhead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.offset >= self.chunk.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+        let available = &self.chunk[self.offset..];
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.offset += read;
+        return Ok(read);
+    }
+}
+impl<R: Read + Seek + Send + 'static> Seek for ReadAhead<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::Current(delta) => {
+                (self.chunk_start + self.offset as u64).checked_add_signed(delta)
+            }
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start"))?;
+        let chunk_end = self.chunk_start + self.chunk.len() as u64;
+        if target >= self.chunk_start && target <= chunk_end {
+            self.offset = (target - self.chunk_start) as usize;
+        } else {
+            self.restart(target)?;
+        }
+        return Ok(target);
+    }
+}
+
+/// The read-ahead worker. Reads chunks until the end of the input (or an
+/// error), then waits to be told where to continue from. Stops once the
+/// [`ReadAhead`] is dropped.
+fn prefetch<R: Read + Seek>(
+    mut reader: R,
+    chunk_size: usize,
+    chunks: SyncSender<Chunk>,
+    seeks: Receiver<(u64, u64)>,
+) {
+    let mut generation = 0;
+    let mut pending = None;
+    loop {
+        let mut result = Ok(());
+        // Only the latest seek matters
+        if let Some((seek_generation, position)) = seeks.try_iter().last().or(pending.take()) {
+            generation = seek_generation;
+            result = reader.seek(SeekFrom::Start(position)).map(|_| ());
+        }
+        let chunk = result.and_then(|()| read_chunk(&mut reader, chunk_size));
+        let finished = chunk.as_ref().map_or(true, Vec::is_empty);
+        if chunks.send((generation, chunk)).is_err() {
+            return;
+        }
+        if finished {
+            match seeks.recv() {
+                Ok(seek) => pending = Some(seek),
+                Err(_) => return,
+            }
+        }
+    }
+}
+
+/// Reads up to `size` bytes, only stopping short at the end of the input
+fn read_chunk<R: Read>(reader: &mut R, size: usize) -> io::Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(size);
+    reader.take(size as u64).read_to_end(&mut chunk)?;
+    return Ok(chunk);
+}
+
+/// Limits how many files are read at once. Each job holds a [`FilePermit`]
+/// while it reads its input, and jobs past the limit wait for one.
+#[derive(Debug)]
+pub struct FileLimiter {
+    /// 0 for no limit
+    limit: usize,
+    open: Mutex<usize>,
+    released: Condvar,
+}
+impl FileLimiter {
+    pub fn new(limit: usize) -> Self {
+        return Self {
+            limit,
+            open: Mutex::new(0),
+            released: Condvar::new(),
+        };
+    }
+
+    /// Waits until fewer than the limit of files are open
+    pub fn acquire(self: &Arc<Self>) -> FilePermit {
+        let mut open = self.open.lock().unwrap();
+        while self.limit > 0 && *open >= self.limit {
+            open = self.released.wait(open).unwrap();
+        }
+        *open += 1;
+        return FilePermit {
+            limiter: self.clone(),
+        };
+    }
+
+    /// Files currently open through permits
+    pub fn open_files(&self) -> usize {
+        return *self.open.lock().unwrap();
+    }
+}
+
+/// Permission to have one file open, given back when dropped
+#[derive(Debug)]
+pub struct FilePermit {
+    limiter: Arc<FileLimiter>,
+}
+impl Drop for FilePermit {
+    fn drop(&mut self) {
+        *self.limiter.open.lock().unwrap() -= 1;
+        self.limiter.released.notify_one();
+    }
+}
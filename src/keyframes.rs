@@ -0,0 +1,109 @@
+//! Snapping cue times to the video's keyframes. Scene changes nearly always
+//! start a new keyframe, and subtitles that appear or vanish a few frames off
+//! a cut look sloppy, which OCR'd timings (taken from when the bitmaps were
+//! shown, not from the video) often are. The keyframes come from the
+//! Matroska Cues index, read with [`crate::ebml`] since `matroska_demuxer`
+//! doesn't expose it.
+
+use std::io::{self, Read, Seek};
+
+use crate::{ebml::*, srt::SrtCue};
+
+/// `TrackType` of video tracks
+const TRACK_TYPE_VIDEO: u64 = 1;
+/// Timestamp scale of files whose Info doesn't give one
+const DEFAULT_TIMESTAMP_SCALE: u64 = 1_000_000;
+
+/// Reads the times of the cue points indexing a video track, in nanoseconds,
+/// sorted. Muxers only index keyframes, so these are keyframe times, though
+/// usually not all of them: mkvmerge indexes one per cluster. Files without
+/// a video track give all of their cue points.
+pub fn read_keyframes<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<u64>> {
+    let mut scale = DEFAULT_TIMESTAMP_SCALE;
+    let mut video = Vec::new();
+    let mut points = Vec::new();
+    for (id, data) in read_top_level_elements(reader, &[ID_INFO, ID_TRACKS, ID_CUES])? {
+        for child in ChildIter::new(&data) {
+            let (child_id, _, value) = child?;
+            match (id, child_id) {
+                (ID_INFO, ID_TIMESTAMP_SCALE) => scale = parse_uint(value),
+                (ID_TRACKS, ID_TRACK_ENTRY) => {
+                    let mut number = None;
+                    let mut track_type = None;
+                    for field in ChildIter::new(value) {
+                        let (id, _, value) = field?;
+                        match id {
+                            ID_TRACK_NUMBER => number = Some(parse_uint(value)),
+                            ID_TRACK_TYPE => track_type = Some(parse_uint(value)),
+                            _ => {}
+                        }
+                    }
+                    if let (Some(number), Some(TRACK_TYPE_VIDEO)) = (number, track_type) {
+                        video.push(number);
+                    }
+                }
+                (ID_CUES, ID_CUE_POINT) => points.push(read_cue_point(value)?),
+                _ => {}
+            }
+        }
+    }
+    let mut keyframes: Vec<u64> = points
+        .into_iter()
+        .filter(|(_, tracks)| video.is_empty() || tracks.iter().any(|track| video.contains(track)))
+        .map(|(time, _)| time.saturating_mul(scale))
+        .collect();
+    keyframes.sort_unstable();
+    keyframes.dedup();
+    return Ok(keyframes);
+}
+
+/// A cue point's time, in timestamp scale units, and the tracks it indexes
+fn read_cue_point(point: &[u8]) -> io::Result<(u64, Vec<u64>)> {
+    let mut time = 0;
+    let mut tracks = Vec::new();
+    for child in ChildIter::new(point) {
+        let (id, _, value) = child?;
+        match id {
+            ID_CUE_TIME => time = parse_uint(value),
+            ID_CUE_TRACK_POSITIONS => {
+                for field in ChildIter::new(value) {
+                    let (id, _, value) = field?;
+                    if id == ID_CUE_TRACK {
+                        tracks.push(parse_uint(value));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    return Ok((time, tracks));
+}
+
+/// Moves each cue's start and end to the nearest of the sorted `keyframes`
+/// within `window` nanoseconds, if there is one. A cue that would be left
+/// with no duration is kept as it was. Returns how many cues were changed.
+pub fn snap_to_keyframes(cues: &mut [SrtCue], keyframes: &[u64], window: u64) -> usize {
+    let mut snapped = 0;
+    for cue in cues.iter_mut() {
+        let start = nearest(keyframes, cue.start, window).unwrap_or(cue.start);
+        let end = nearest(keyframes, cue.end, window).unwrap_or(cue.end);
+        if end <= start || (start == cue.start && end == cue.end) {
+            continue;
+        }
+        cue.start = start;
+        cue.end = end;
+        snapped += 1;
+    }
+    return snapped;
+}
+
+/// The keyframe nearest to `time`, if it's within `window`
+fn nearest(keyframes: &[u64], time: u64, window: u64) -> Option<u64> {
+    let index = keyframes.partition_point(|keyframe| *keyframe < time);
+    return [index.checked_sub(1), Some(index)]
+        .into_iter()
+        .flatten()
+        .filter_map(|index| keyframes.get(index).copied())
+        .filter(|keyframe| keyframe.abs_diff(time) <= window)
+        .min_by_key(|keyframe| keyframe.abs_diff(time));
+}
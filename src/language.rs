@@ -0,0 +1,52 @@
+//! Guessing a track's language from its text, for tracks tagged `und`. Only
+//! as good as the text, so OCR'd tracks need a reasonable amount of dialogue
+//! before the guess is trusted.
+
+use whatlang::Lang;
+
+/// A language guessed from subtitle text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DetectedLanguage {
+    /// ISO 639-2/B code, as Matroska's `Language` element uses
+    pub code: &'static str,
+    /// English name, for messages
+    pub name: &'static str,
+    /// 0 to 1
+    pub confidence: f64,
+    /// Whether there was enough text, and one language was far enough ahead,
+    /// for the guess to be used
+    pub reliable: bool,
+}
+impl DetectedLanguage {
+    pub fn from_texts<'a, I: IntoIterator<Item = &'a str>>(texts: I) -> Option<Self> {
+        let text: Vec<&str> = texts.into_iter().collect();
+        let info = whatlang::detect(&text.join("\n"))?;
+        return Some(Self {
+            code: matroska_code(info.lang()),
+            name: info.lang().eng_name(),
+            confidence: info.confidence(),
+            reliable: info.is_reliable(),
+        });
+    }
+}
+
+/// whatlang gives ISO 639-3 codes, which match 639-2/T. Matroska wants the
+/// bibliographic variant where the two differ.
+fn matroska_code(lang: Lang) -> &'static str {
+    return match lang {
+        Lang::Cmn => "chi",
+        Lang::Fra => "fre",
+        Lang::Deu => "ger",
+        Lang::Kat => "geo",
+        Lang::Nld => "dut",
+        Lang::Ces => "cze",
+        Lang::Ell => "gre",
+        Lang::Ron => "rum",
+        Lang::Mkd => "mac",
+        Lang::Pes => "per",
+        Lang::Mya => "bur",
+        Lang::Slk => "slo",
+        Lang::Hye => "arm",
+        other => other.code(),
+    };
+}
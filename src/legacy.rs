@@ -0,0 +1,155 @@
+//! Writers for the text formats older hardware players read: MicroDVD, which
+//! counts video frames rather than time, and SubViewer 2.0. They take the
+//! same cues as the SRT writer. Tags are dropped, since players that need
+//! these formats don't read them.
+
+use std::io::{self, Write};
+
+use crate::{
+    srt::{SrtCue, strip_tags},
+    timebase::{FrameRate, Timestamp},
+    writer::{SubtitleWriter, WriterError},
+};
+
+/// Writes cues as MicroDVD (`{start frame}{end frame}line|line`), preceded by
+/// the `{1}{1}<fps>` line players take the frame rate from. Blank cues are
+/// skipped, and every cue lasts at least a frame.
+pub fn write_microdvd<W: Write>(out: W, cues: &[SrtCue], rate: FrameRate) -> io::Result<()> {
+    let mut writer = MicroDvdWriter::new(out, rate);
+    for cue in cues {
+        writer.write_cue(cue)?;
+    }
+    return writer.end();
+}
+
+/// Writes cues as SubViewer 2.0, with an empty information header. Blank cues
+/// are skipped.
+pub fn write_subviewer<W: Write>(out: W, cues: &[SrtCue]) -> io::Result<()> {
+    let mut writer = SubViewerWriter::new(out);
+    for cue in cues {
+        writer.write_cue(cue)?;
+    }
+    return writer.end();
+}
+
+/// [`write_microdvd`] one cue at a time
+pub struct MicroDvdWriter<W> {
+    out: W,
+    rate: FrameRate,
+    header_written: bool,
+}
+impl<W: Write> MicroDvdWriter<W> {
+    pub fn new(out: W, rate: FrameRate) -> Self {
+        return Self {
+            out,
+            rate,
+            header_written: false,
+        };
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            writeln!(self.out, "{{1}}{{1}}{}", self.rate)?;
+            self.header_written = true;
+        }
+        return Ok(());
+    }
+
+    fn write_cue(&mut self, cue: &SrtCue) -> io::Result<()> {
+        self.write_header()?;
+        let lines = lines(&cue.text);
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let start = self.rate.nearest_frame(cue.start);
+        let end = self.rate.nearest_frame(cue.end).max(start + 1);
+        writeln!(self.out, "{{{start}}}{{{end}}}{}", lines.join("|"))?;
+        return Ok(());
+    }
+
+    /// Writes the header if there were no cues, and flushes
+    fn end(&mut self) -> io::Result<()> {
+        self.write_header()?;
+        return self.out.flush();
+    }
+}
+impl<W: Write> SubtitleWriter for MicroDvdWriter<W> {
+    fn write_event(&mut self, cue: &SrtCue) -> Result<(), WriterError> {
+        return Ok(self.write_cue(cue)?);
+    }
+
+    fn finish(&mut self) -> Result<(), WriterError> {
+        return Ok(self.end()?);
+    }
+}
+
+/// [`write_subviewer`] one cue at a time
+pub struct SubViewerWriter<W> {
+    out: W,
+    header_written: bool,
+}
+impl<W: Write> SubViewerWriter<W> {
+    pub fn new(out: W) -> Self {
+        return Self {
+            out,
+            header_written: false,
+        };
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            writeln!(self.out, "[INFORMATION]")?;
+            writeln!(self.out, "[END INFORMATION]")?;
+            writeln!(self.out, "[SUBTITLE]")?;
+            self.header_written = true;
+        }
+        return Ok(());
+    }
+
+    fn write_cue(&mut self, cue: &SrtCue) -> io::Result<()> {
+        self.write_header()?;
+        let lines = lines(&cue.text);
+        if lines.is_empty() {
+            return Ok(());
+        }
+        writeln!(
+            self.out,
+            "{},{}",
+            format_subviewer_timestamp(cue.start),
+            format_subviewer_timestamp(cue.end)
+        )?;
+        writeln!(self.out, "{}", lines.join("[br]"))?;
+        writeln!(self.out)?;
+        return Ok(());
+    }
+
+    /// Writes the header if there were no cues, and flushes
+    fn end(&mut self) -> io::Result<()> {
+        self.write_header()?;
+        return self.out.flush();
+    }
+}
+impl<W: Write> SubtitleWriter for SubViewerWriter<W> {
+    fn write_event(&mut self, cue: &SrtCue) -> Result<(), WriterError> {
+        return Ok(self.write_cue(cue)?);
+    }
+
+    fn finish(&mut self) -> Result<(), WriterError> {
+        return Ok(self.end()?);
+    }
+}
+
+/// Formats nanoseconds as `HH:MM:SS.hh`, in hundredths of a second
+pub fn format_subviewer_timestamp(ns: u64) -> String {
+    return Timestamp::from_nanos(ns).to_subviewer();
+}
+
+/// The cue's non-blank lines, without tags
+fn lines(text: &str) -> Vec<String> {
+    return strip_tags(text)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_owned)
+        .collect();
+}
@@ -0,0 +1,80 @@
+//! Library half of the subtitle processing POC. The decoders live here so they
+//! can be exercised by the integration tests and, eventually, embedded into
+//! mediacorral's workers. `main.rs` is a thin driver on top of this.
+
+#[cfg(feature = "ass")]
+pub mod ass;
+pub mod attachments;
+pub mod bdsup;
+#[cfg(feature = "demux-mkv")]
+pub mod bilingual;
+pub mod binary_reader;
+pub mod cancel;
+#[cfg(feature = "writers")]
+pub mod chapters;
+pub mod color;
+#[cfg(feature = "writers")]
+pub mod compare;
+#[cfg(feature = "demux-mkv")]
+pub mod composite;
+#[cfg(feature = "writers")]
+pub mod contact_sheet;
+pub mod density;
+#[cfg(feature = "writers")]
+pub mod diagnostics;
+pub mod ebml;
+pub mod epochs;
+#[cfg(feature = "demux-mkv")]
+pub mod extract;
+#[cfg(feature = "demux-mkv")]
+pub mod ffi;
+#[cfg(feature = "demux-mkv")]
+pub mod filter;
+pub mod indexed;
+pub mod input;
+#[cfg(feature = "writers")]
+pub mod keyframes;
+pub mod language;
+#[cfg(feature = "writers")]
+pub mod legacy;
+pub mod model;
+pub mod music_notes;
+pub mod non_text;
+pub mod ocr;
+pub mod preprocess;
+pub mod program_stream;
+#[cfg(feature = "writers")]
+pub mod qc;
+#[cfg(feature = "writers")]
+pub mod queue;
+#[cfg(feature = "writers")]
+pub mod remux;
+pub mod sdh;
+#[cfg(feature = "writers")]
+pub mod sidecar;
+#[cfg(feature = "demux-mkv")]
+pub mod sink;
+#[cfg(feature = "preview")]
+pub mod sixel;
+#[cfg(feature = "writers")]
+pub mod srt;
+pub mod stream;
+#[cfg(feature = "writers")]
+pub mod sync;
+pub mod terminal;
+#[cfg(feature = "ocr")]
+pub mod tess;
+pub mod text_filter;
+pub mod textst;
+pub mod timebase;
+#[cfg(feature = "writers")]
+pub mod timing;
+#[cfg(feature = "demux-mkv")]
+pub mod transform;
+pub mod vobs;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "writers")]
+pub mod wrap;
+#[cfg(feature = "writers")]
+pub mod writer;
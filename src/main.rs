@@ -1,91 +1,1696 @@
-//! This is a proof-of-concept for extracting vobsub subtitles from an MKV file.
-//! It makes use of some private functions from the vobsub crate, and requires a
-//! modified copy to export them.
+//! This is a proof-of-concept for extracting image-based subtitles from an MKV file.
 //!
 //! This is primarily created as a testing ground for integrating subtitle extraction
-//! into mediacorral. The current version really only works for vobsub, and converts
-//! the vobsub images into sixel images, printing them to the terminal.
+//! into mediacorral. Subtitles are either previewed in the terminal (images are
+//! printed using sixel encoding, or half blocks where that isn't supported), or
+//! run through OCR and written out as SRT.
+//! `subproc serve` exposes the same pipeline as an HTTP service, and
+//! `subproc watch` runs it on files copied into a directory.
 
-use bdsup::PgsParser;
-use image::{GrayAlphaImage, buffer::ConvertBuffer};
+use image::buffer::ConvertBuffer;
 use matroska_demuxer::*;
-use sixel::print_gray_image;
-use std::fs::File;
+use std::{
+    collections::{HashMap, HashSet, hash_map::Entry},
+    error::Error,
+    fs::File,
+    io::{self, BufReader, BufWriter, Cursor, Read, Seek},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use subproc::{
+    attachments::read_attachments,
+    bdsup::{
+        PgsError, PgsParser, read_palettes,
+        reference::{ReferenceRenderer, differing_pixels},
+        shows_objects,
+    },
+    bilingual::{detect_layout, split_languages},
+    cancel::CancellationToken,
+    chapters::{
+        SegmentLinks, SegmentUid, place_cues, read_chapters, read_segment_links, split_cues,
+    },
+    compare,
+    composite::CompositeSink,
+    contact_sheet::ContactSheet,
+    density::{Density, GapKind, ScanSource, find_gaps, scan_track},
+    diagnostics::{Diagnostic, Diagnostics, Stage},
+    extract::{
+        EventPayload, ExtractError, FALLBACK_DURATION, SubtitleEvent, SubtitleExtractor,
+        select_track,
+    },
+    filter::filter_events,
+    indexed,
+    input::{Input, IoStrategy},
+    keyframes::{read_keyframes, snap_to_keyframes},
+    language::DetectedLanguage,
+    model::{self, MANIFEST_FILE, SubtitleDocument, SubtitleTrack},
+    non_text,
+    ocr::{
+        CachedEngine, CancellableEngine, HttpEngine, OcrEngine, OcrError, RegionPolicy,
+        RetryEngine, RetryPolicy, RetryRecord, TesseractEngine, Variant, recognize_regions,
+    },
+    preprocess::{Background, FlattenOptions, segment},
+    qc,
+    queue::JobTicket,
+    remux::{TextTrack, remux_with_text_track},
+    sdh::{SdhClassification, strip_sdh},
+    sidecar::{TrackRole, set_track_language, sidecar_path, track_language},
+    sink::{EventSink, ImageDirSink, PreviewSink, SinkError, TeeSink},
+    srt::{SrtCue, format_timestamp, parse_srt, sort_cues, write_srt},
+    stream::StreamInput,
+    sync::{Alignment, SyncOptions, align, cue_intervals},
+    terminal,
+    text_filter::{AsciiPunctuation, FilterChain},
+    timebase::{FrameRate, Timestamp},
+    timing::repair_timing,
+    vobs::{self, SubsError},
+    wrap::{RewrapWriter, rewrap},
+    writer::{MultiWriter, WriterOptions, WriterRegistry, write_all},
+};
 
-mod bdsup;
-mod binary_reader;
-mod sixel;
-mod tess;
-mod vobs;
+mod cli;
+mod config;
+mod serve;
+#[cfg(feature = "watch")]
+mod watch;
+
+/// Rough OCR time per image in seconds, for the dry run's estimate
+const ESTIMATED_OCR_SECONDS: f64 = 0.3;
+/// Buckets per line of the density sparkline
+const DENSITY_COLUMNS: usize = 60;
 
 fn main() {
-    let file = File::open("test_bd.mkv").unwrap();
-    let mut mkv = MatroskaFile::open(file).unwrap();
-    let video_track = mkv
+    // Tesseract's own OpenMP threads only compete with the engine per thread.
+    // SAFETY: set before anything is spawned, so no other thread can be
+    // reading the environment.
+    unsafe {
+        std::env::set_var("OMP_THREAD_LIMIT", "1");
+    }
+    let command = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(Some(command)) => command,
+        Ok(None) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{err}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let result = match command {
+        cli::Command::Extract(options) => run(*options, None),
+        cli::Command::Serve(options) => serve::serve(options).map_err(Into::into),
+        #[cfg(feature = "watch")]
+        cli::Command::Watch(options) => watch::watch(options),
+        cli::Command::ContactSheet(options) => contact_sheet(options),
+        cli::Command::PaletteDump(options) => palette_dump(options),
+        cli::Command::Density(options) => density(options),
+        cli::Command::Attachments(options) => attachments(options),
+        cli::Command::Compare(options) => compare(options),
+        cli::Command::SubtitleOcr(options) => subtitle_ocr(options),
+        cli::Command::Rehydrate(options) => rehydrate(options),
+        cli::Command::Sync(options) => sync(options),
+    };
+    if let Err(err) = result {
+        eprintln!("Error: {err}");
+        std::process::exit(1);
+    }
+}
+
+/// Extracts according to `options`. A queued `job` is held to its limits,
+/// and waits for an OCR slot once the events are read.
+fn run(options: cli::Options, mut job: Option<&mut JobTicket>) -> Result<(), Box<dyn Error>> {
+    if options.dry_run {
+        return dry_run(&options);
+    }
+    if options.verify {
+        return verify(&options);
+    }
+    let mut diagnostics = Diagnostics::default();
+    let mut extractor =
+        open_extractor(&options.input, &options.io, options.track, options.palette)?;
+    let track = extractor.track().clone();
+    // Previews show palette colors, which need the indexed images, as does
+    // segmenting by palette for OCR
+    let previewing = options.preview
+        || options.composite
+        || (!options.has_outputs() && options.save_images.is_none());
+    extractor.set_indexed(
+        options.indexed || previewing || options.flatten.background == Background::Palette,
+    );
+    extractor.set_color_matrix(options.color_matrix);
+    let cancel = job
+        .as_ref()
+        .map_or_else(CancellationToken::new, |job| job.cancellation());
+    extractor.set_cancellation(cancel.clone());
+    if let Some(start) = options.start {
+        extractor.seek(start)?;
+    }
+    let keep = |event: &SubtitleEvent| {
+        return options
+            .filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches(event));
+    };
+
+    let transform = |event: SubtitleEvent| {
+        return match options.scale {
+            Some(ref scale) => scale.apply(event),
+            None => event,
+        };
+    };
+
+    let image_sink = options.save_images.as_ref().map(|directory| {
+        let sink = ImageDirSink::new(directory).with_manifest(
+            Some(options.input.display().to_string()),
+            SubtitleTrack::from_entry(&track),
+        );
+        return Box::new(sink) as Box<dyn EventSink>;
+    });
+
+    if !options.has_outputs() {
+        // Nothing to OCR for, so events go straight to the preview, or to
+        // the image directory instead if there is one
+        let mut sink = match options.composite {
+            true => composite_sink(&options)?,
+            false => image_sink.unwrap_or_else(|| Box::new(preview_sink(&options))),
+        };
+        let mut index = 0;
+        for event in filter_events(&mut extractor, keep) {
+            match event.map(transform) {
+                Ok(event) => {
+                    sink.event(index, &event)?;
+                    index += 1;
+                }
+                Err(err) => eprintln!("Warning: {err}"),
+            }
+        }
+        sink.finish()?;
+        report_skipped(&extractor, &mut diagnostics);
+        return Ok(());
+    }
+
+    // Events are saved and previewed as they're decoded, ahead of OCR
+    let mut sink = TeeSink::new();
+    if options.composite {
+        // Saved to the image directory if there is one, and previewed
+        // otherwise, as without outputs
+        sink.push(composite_sink(&options)?);
+    } else {
+        if let Some(image_sink) = image_sink {
+            sink.push(image_sink);
+        }
+        if options.preview {
+            sink.push(Box::new(preview_sink(&options)));
+        }
+    }
+    let mut events = Vec::new();
+    let mut extracted = 0;
+    let mut kept = 0;
+    while let Some(event) = extractor.next().transpose().unwrap_or_else(|err| {
+        warn(
+            &mut diagnostics,
+            Diagnostic::new(Stage::Extract, err).at_time(extractor.position()),
+        );
+        None
+    }) {
+        extracted += 1;
+        if let Some(ref job) = job {
+            job.check_events(extracted)?;
+        }
+        if !keep(&event) {
+            continue;
+        }
+        let event = transform(event);
+        // Images that don't look like text are still saved and previewed,
+        // just not OCR'd
+        sink.event(kept, &event)?;
+        kept += 1;
+        if !options.keep_non_text
+            && let EventPayload::Image(ref image) = event.payload
+            && let Some(reason) = non_text::classify(image, &event.regions, &options.non_text)
+        {
+            warn(
+                &mut diagnostics,
+                Diagnostic::new(
+                    Stage::Ocr,
+                    format!(
+                        "Not recognizing subtitle {kept} at {}, which doesn't look like text: {reason} (--keep-non-text recognizes it)",
+                        format_timestamp(event.start)
+                    ),
+                )
+                .at_event(kept, event.start),
+            );
+            continue;
+        }
+        events.push(event);
+    }
+    sink.finish()?;
+    if let Some(ref job) = job {
+        job.check_time()?;
+    }
+    report_skipped(&extractor, &mut diagnostics);
+    drop(extractor);
+    if kept < extracted {
+        eprintln!("Filtered out {} of {extracted} subtitles", extracted - kept);
+    }
+
+    if let Some(job) = job.as_deref_mut() {
+        job.start_ocr();
+    }
+    // Checked after OCR, which stops early on a timeout
+    let check_time = || job.as_ref().map_or(Ok(()), |job| job.check_time());
+    let ocr = events
+        .iter()
+        .any(|event| matches!(event.payload, EventPayload::Image(_)));
+    let Some((ref upper, ref lower)) = options.split_languages else {
+        let layout = ocr.then(|| detect_layout(&events)).flatten();
+        if let Some(layout) = layout {
+            eprintln!(
+                "{} of {} subtitles look like two languages stacked; --split-languages would split them into a track each",
+                layout.stacked, layout.images
+            );
+        }
+        let cues = to_cues(
+            events,
+            &options.ocr,
+            options.regions,
+            options.flatten,
+            options.position_tags,
+            &cancel,
+            &mut diagnostics,
+        )?;
+        check_time()?;
+        write_outputs(&options, &track, cues, ocr, None, &mut diagnostics)?;
+        return finish_run(&options, &diagnostics);
+    };
+    let layout = detect_layout(&events)
+        .ok_or("The track doesn't have two languages stacked consistently enough to split")?;
+    eprintln!(
+        "Splitting {} of {} subtitles at screen row {}",
+        layout.stacked, layout.images, layout.split
+    );
+    let (upper_events, lower_events) = split_languages(events, layout.split);
+    for (events, language) in [(upper_events, upper), (lower_events, lower)] {
+        let ocr_options = cli::OcrOptions {
+            language: Some(language.clone()),
+            ..options.ocr.clone()
+        };
+        let cues = to_cues(
+            events,
+            &ocr_options,
+            options.regions,
+            options.flatten,
+            options.position_tags,
+            &cancel,
+            &mut diagnostics,
+        )?;
+        check_time()?;
+        write_outputs(
+            &options,
+            &track,
+            cues,
+            ocr,
+            Some(language_code(language)),
+            &mut diagnostics,
+        )?;
+    }
+    return finish_run(&options, &diagnostics);
+}
+
+/// Writes the diagnostics report and summary at the end of a run
+fn finish_run(options: &cli::Options, diagnostics: &Diagnostics) -> Result<(), Box<dyn Error>> {
+    if let Some(ref path) = options.diagnostics {
+        diagnostics.write_report(BufWriter::new(File::create(path)?))?;
+        eprintln!("Wrote {}", path.display());
+    }
+    if !diagnostics.is_empty() {
+        eprintln!("{}", diagnostics.summary());
+    }
+    return Ok(());
+}
+
+/// Post-processes a track's cues according to `options` and writes them to
+/// each requested output. With `split_language`, the cues are one language
+/// of a dual-language track, which goes in the output names.
+fn write_outputs(
+    options: &cli::Options,
+    track: &TrackEntry,
+    mut cues: Vec<SrtCue>,
+    ocr: bool,
+    split_language: Option<&str>,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), Box<dyn Error>> {
+    if options.ordered_chapters {
+        cues = ordered_cues(options, cues, diagnostics)?;
+    }
+    sort_cues(&mut cues);
+    if let Some(ref reference) = options.sync_to {
+        let alignment = align(
+            &cues,
+            &cue_intervals(&read_reference(reference)?),
+            SyncOptions::default(),
+        );
+        alignment.correction.apply_cues(&mut cues);
+        print_alignment(&alignment);
+    }
+    let mut filters = FilterChain::default();
+    if options.ascii_punctuation {
+        filters.push(AsciiPunctuation);
+    }
+    for replacement in options.replacements.iter() {
+        filters.push(replacement.clone());
+    }
+    for cue in cues.iter_mut() {
+        cue.text = filters.apply(&cue.text);
+    }
+    let language = match split_language
+        .or(options.language.as_deref())
+        .or(track_language(track))
+    {
+        Some(language) => Some(language),
+        None => detect_language(&cues),
+    };
+    let mut role = TrackRole::from_track(track);
+    role.forced |= options.forced;
+    role.sdh |= options.sdh;
+
+    if options.strip_sdh {
+        for cue in cues.iter_mut() {
+            cue.text = strip_sdh(&cue.text);
+        }
+        role.sdh = false;
+    } else if !role.sdh {
+        let sdh = SdhClassification::from_texts(cues.iter().map(|cue| cue.text.as_str()));
+        if sdh.is_sdh() {
+            eprintln!(
+                "{} of {} subtitles contain SDH markers; marking the output as SDH",
+                sdh.marked, sdh.events
+            );
+            role.sdh = true;
+        }
+    }
+
+    if let Some(ref wrap) = options.wrap {
+        for cue in cues.iter_mut() {
+            cue.text = rewrap(&cue.text, wrap);
+        }
+    }
+    if let Some(window) = options.keyframe_window {
+        let keyframes = read_keyframes(&mut BufReader::new(File::open(&options.input)?))?;
+        if keyframes.is_empty() {
+            eprintln!("Warning: the input has no Cues index to find keyframes in; not snapping");
+        } else {
+            let snapped = snap_to_keyframes(&mut cues, &keyframes, window);
+            eprintln!("Snapped {snapped} subtitles to keyframes");
+        }
+    }
+    if let Some(ref rules) = options.timing {
+        let report;
+        (cues, report) = repair_timing(cues, rules);
+        if !report.is_empty() {
+            eprintln!(
+                "Fixed timing: trimmed {}, merged {} and capped {} subtitles",
+                report.trimmed, report.merged, report.capped
+            );
+        }
+    }
+
+    if let Some(ref path) = options.qc_report {
+        let speeds = qc::analyze(&cues, &options.reading_speed);
+        qc::write_report(BufWriter::new(File::create(path)?), &speeds)?;
+        let flagged = speeds
+            .iter()
+            .filter(|speed| !speed.issues.is_empty())
+            .count();
+        eprintln!(
+            "Wrote {} ({flagged} of {} subtitles flagged)",
+            path.display(),
+            speeds.len()
+        );
+    }
+    let rate = match options.output_format {
+        cli::OutputFormat::MicroDvd => Some(microdvd_rate(&options.input, options.frame_rate)?),
+        _ => None,
+    };
+    let write = |path: &Path, cues: &[SrtCue]| {
+        return write_cues(path, cues, options.output_format, rate);
+    };
+    if let Some(ref output) = options.output {
+        match (options.split_chapters, split_language) {
+            (true, _) => write_chapters(&options.input, output, &cues, write)?,
+            (false, Some(language)) => {
+                let path = with_language(output, language);
+                write(&path, &cues)?;
+                eprintln!("Wrote {}", path.display());
+            }
+            (false, None) => write(output, &cues)?,
+        }
+    }
+    if options.sidecar {
+        let extension = options.output_format.extension();
+        let path = sidecar_path(&options.input, language, role, extension);
+        write(&path, &cues)?;
+        eprintln!("Wrote {}", path.display());
+    }
+    if !options.outputs.is_empty() {
+        write_extra_outputs(options, &cues, language, role, split_language)?;
+    }
+    if let Some(ref mux) = options.mux {
+        let name = match track.name() {
+            Some(name) if ocr => Some(format!("{name} (OCR)")),
+            Some(name) => Some(name.to_owned()),
+            None if ocr => Some(String::from("OCR")),
+            None => None,
+        };
+        let mut input = BufReader::new(File::open(&options.input)?);
+        let mut output = BufWriter::new(File::create(mux)?);
+        let track_number = remux_with_text_track(
+            &mut input,
+            &mut output,
+            &TextTrack {
+                cues: &cues,
+                language,
+                name: name.as_deref(),
+                forced: role.forced,
+                sdh: role.sdh,
+                default: role.default,
+            },
+        )?;
+        eprintln!("Wrote {} (added track {track_number})", mux.display());
+    }
+    if options.set_track_language
+        && track_language(track).is_none()
+        && let Some(language) = language
+    {
+        set_track_language(&options.input, track.track_number().get(), language)?;
+    }
+    return Ok(());
+}
+
+/// Writes the `--out` outputs, all in one pass over `cues`
+fn write_extra_outputs(
+    options: &cli::Options,
+    cues: &[SrtCue],
+    language: Option<&str>,
+    role: TrackRole,
+    split_language: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let registry = WriterRegistry::default();
+    let mut writers = MultiWriter::new();
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for output in options.outputs.iter() {
+        let path = match (output.path.as_deref(), split_language) {
+            (Some(path), Some(language)) => with_language(path, language),
+            (Some(path), None) => path.to_owned(),
+            (None, _) => sidecar_path(&options.input, language, role, output.format.extension()),
+        };
+        if paths.contains(&path) {
+            return Err(format!("More than one --out would write {}", path.display()).into());
+        }
+        let frame_rate = match output.format {
+            cli::OutputFormat::MicroDvd => Some(microdvd_rate(
+                &options.input,
+                output.frame_rate.or(options.frame_rate),
+            )?),
+            _ => None,
+        };
+        let writer = registry.create(
+            output.format.name(),
+            Box::new(BufWriter::new(File::create(&path)?)),
+            &WriterOptions { frame_rate },
+        )?;
+        writers.push(match output.wrap {
+            Some(wrap) => Box::new(RewrapWriter::new(writer, wrap)),
+            None => writer,
+        });
+        paths.push(path);
+    }
+    write_all(&mut writers, cues)?;
+    for path in paths {
+        eprintln!("Wrote {}", path.display());
+    }
+    return Ok(());
+}
+
+/// `rate` if given, or else the input's video frame rate
+fn microdvd_rate(input: &Path, rate: Option<FrameRate>) -> Result<FrameRate, Box<dyn Error>> {
+    return Ok(rate.or_else(|| video_frame_rate(input)).ok_or(
+        "MicroDVD counts video frames, but the input doesn't give a frame rate; set one with --frame-rate",
+    )?);
+}
+
+/// `path` with `language` before the extension, e.g. `out.eng.srt`
+fn with_language(path: &Path, language: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(language);
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    return path.with_file_name(name);
+}
+
+/// The ISO 639-2 code of a Tesseract language, for naming outputs:
+/// `chi_sim` is `chi`, and `chi_sim+eng` is named for its first language
+fn language_code(language: &str) -> &str {
+    return language.split(['_', '+']).next().unwrap_or(language);
+}
+
+/// Prints a warning and keeps it for the `--diagnostics` report
+fn warn(diagnostics: &mut Diagnostics, diagnostic: Diagnostic) {
+    eprintln!("Warning: {}", diagnostic.message);
+    diagnostics.push(diagnostic);
+}
+
+/// Rearranges `cues` (the input's own) into the timeline of the input's
+/// ordered edition, extracting the same track from linked segments as needed
+fn ordered_cues(
+    options: &cli::Options,
+    cues: Vec<SrtCue>,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<SrtCue>, Box<dyn Error>> {
+    let links = read_segment_links(&mut BufReader::new(File::open(&options.input)?))?;
+    let Some(chapters) = links.ordered else {
+        return Err("The input has no ordered chapters".into());
+    };
+    let mut linked_files = None;
+    let mut linked_cues: HashMap<SegmentUid, Vec<SrtCue>> = HashMap::new();
+    let mut ordered = Vec::new();
+    let mut offset = 0;
+    for chapter in chapters.iter() {
+        let source = match chapter.segment {
+            Some(uid) if Some(uid) != links.uid => match linked_cues.entry(uid) {
+                Entry::Occupied(entry) => &*entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let files = linked_files.get_or_insert_with(|| segment_files(&options.input));
+                    let Some(path) = files.get(&uid) else {
+                        warn(
+                            diagnostics,
+                            Diagnostic::new(
+                                Stage::Link,
+                                format!(
+                                    "No file next to the input has segment {}; leaving its chapter empty",
+                                    hex::encode(uid)
+                                ),
+                            )
+                            .at_time(offset),
+                        );
+                        offset += chapter.duration();
+                        continue;
+                    };
+                    eprintln!("Extracting linked segment {}", path.display());
+                    let mut linked = Diagnostics::default();
+                    let cues = file_cues(path, options, &mut linked)?;
+                    diagnostics.append(linked, path);
+                    &*entry.insert(cues)
+                }
+            },
+            _ => &cues,
+        };
+        ordered.extend(place_cues(source, chapter, offset));
+        offset += chapter.duration();
+    }
+    eprintln!(
+        "Arranged subtitles by {} ordered chapters from {} segments",
+        chapters.len(),
+        linked_cues.len() + 1
+    );
+    return Ok(ordered);
+}
+
+/// The Matroska files next to `input` by segment UID
+fn segment_files(input: &Path) -> HashMap<SegmentUid, PathBuf> {
+    let directory = match input.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return HashMap::new();
+    };
+    let mut files = HashMap::new();
+    for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+        let matroska = path
+            .extension()
+            .is_some_and(|extension| matches!(extension.to_str(), Some("mkv" | "mka" | "mks")));
+        if !matroska {
+            continue;
+        }
+        let Ok(file) = File::open(&path) else {
+            continue;
+        };
+        if let Ok(SegmentLinks { uid: Some(uid), .. }) =
+            read_segment_links(&mut BufReader::new(file))
+        {
+            files.entry(uid).or_insert(path);
+        }
+    }
+    return files;
+}
+
+/// Extracts and recognizes the selected track of another file the way `run`
+/// does for the input
+fn file_cues(
+    path: &Path,
+    options: &cli::Options,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<SrtCue>, Box<dyn Error>> {
+    let mut extractor = open_extractor(path, &options.io, options.track, options.palette)?;
+    extractor.set_indexed(options.flatten.background == Background::Palette);
+    let mut events = Vec::new();
+    while let Some(event) = extractor.next() {
+        match event {
+            Ok(event) => events.push(event),
+            Err(err) => warn(
+                diagnostics,
+                Diagnostic::new(Stage::Extract, err).at_time(extractor.position()),
+            ),
+        }
+    }
+    events.retain(|event| {
+        return options
+            .filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches(event));
+    });
+    if let Some(ref scale) = options.scale {
+        events = events.into_iter().map(|event| scale.apply(event)).collect();
+    }
+    return Ok(to_cues(
+        events,
+        &options.ocr,
+        options.regions,
+        options.flatten,
+        options.position_tags,
+        &CancellationToken::new(),
+        diagnostics,
+    )?);
+}
+
+/// Writes an SRT per chapter of `input`, numbering the file names after `output`
+fn write_chapters(
+    input: &Path,
+    output: &Path,
+    cues: &[SrtCue],
+    write: impl Fn(&Path, &[SrtCue]) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let mkv = MatroskaFile::open(BufReader::new(File::open(input)?))?;
+    let chapters = read_chapters(&mkv);
+    if chapters.is_empty() {
+        return Err("The input has no chapters to split by".into());
+    }
+    let stem = output.file_stem().unwrap_or_default().to_os_string();
+    let extension = output.extension().unwrap_or("srt".as_ref()).to_os_string();
+    for (i, (chapter, cues)) in chapters.iter().zip(split_cues(cues, &chapters)).enumerate() {
+        let mut name = stem.clone();
+        name.push(format!("-{:02}.", i + 1));
+        name.push(&extension);
+        let path = output.with_file_name(name);
+        write(&path, &cues)?;
+        eprintln!(
+            "Wrote {} ({}, {} subtitles)",
+            path.display(),
+            chapter
+                .title
+                .clone()
+                .unwrap_or_else(|| format!("chapter {}", i + 1)),
+            cues.len()
+        );
+    }
+    return Ok(());
+}
+
+/// Writes `cues` to `path` in `format`. Frame-based formats need `rate`.
+fn write_cues(
+    path: &Path,
+    cues: &[SrtCue],
+    format: cli::OutputFormat,
+    rate: Option<FrameRate>,
+) -> Result<(), Box<dyn Error>> {
+    let out = Box::new(BufWriter::new(File::create(path)?));
+    let options = WriterOptions { frame_rate: rate };
+    let mut writer = WriterRegistry::default().create(format.name(), out, &options)?;
+    write_all(&mut writer, cues)?;
+    return Ok(());
+}
+
+/// The frame rate of the first video track with a known frame duration
+fn video_frame_rate(input: &Path) -> Option<FrameRate> {
+    let mkv = MatroskaFile::open(BufReader::new(File::open(input).ok()?)).ok()?;
+    return mkv
         .tracks()
         .iter()
-        .find(|t| t.track_type() == TrackType::Subtitle)
-        // .inspect(|t| {
-        //     dbg!(t.codec_id());
-        //     dbg!(t.codec_name());
-        // })
-        .unwrap()
-        .clone();
+        .filter(|track| track.track_type() == TrackType::Video)
+        .find_map(|track| FrameRate::from_frame_duration(track.default_duration()?.get()));
+}
+
+/// Guesses the language of a track tagged `und` from its text, if the guess
+/// is reliable enough to name outputs with
+fn detect_language(cues: &[SrtCue]) -> Option<&'static str> {
+    let detected = DetectedLanguage::from_texts(cues.iter().map(|cue| cue.text.as_str()))?;
+    let confidence = (detected.confidence * 100.0).round();
+    if !detected.reliable {
+        eprintln!(
+            "The track's language is undefined and the text looks like {} ({}), but only with {confidence}% confidence; leaving it undefined",
+            detected.name, detected.code
+        );
+        return None;
+    }
+    eprintln!(
+        "The track's language is undefined; detected {} ({}) with {confidence}% confidence",
+        detected.name, detected.code
+    );
+    return Some(detected.code);
+}
+
+/// Previews events over their video frames, saving the composites to the
+/// image directory if there is one
+fn composite_sink(options: &cli::Options) -> Result<Box<dyn EventSink + '_>, String> {
+    let directory = options.save_images.as_ref();
+    if directory.is_none() && !terminal::is_available() {
+        return Err(String::from(
+            "Output is not a terminal; pass --save-images to save the composites instead",
+        ));
+    }
+    let preview = preview_sink(options);
+    return Ok(Box::new(CompositeSink::new(
+        &options.input,
+        move |index: usize, frame: &image::RgbImage| -> Result<(), SinkError> {
+            match directory {
+                Some(directory) => {
+                    std::fs::create_dir_all(directory)?;
+                    frame.save(directory.join(format!("{:05}.png", index + 1)))?;
+                }
+                None => preview.print(&frame.convert())?,
+            }
+            return Ok(());
+        },
+    )));
+}
+
+fn preview_sink(options: &cli::Options) -> PreviewSink {
+    let sink = PreviewSink::new(options.preview_mode, options.preview_width);
+    return match options.preview_background {
+        Some(background) => sink.with_background(background),
+        None => sink,
+    };
+}
+
+/// Prints what `run` would do. Events are counted from the container blocks
+/// (and PGS display set headers) without decoding anything.
+fn dry_run(options: &cli::Options) -> Result<(), Box<dyn Error>> {
+    let file = BufReader::new(File::open(&options.input)?);
+    let mut mkv = MatroskaFile::open(file)?;
+    let track = select_track(&mkv, options.track)?;
+    let track_number = track.track_number().get();
+    let codec = track.codec_id().to_owned();
+    let language = options.language.as_deref().or(track_language(&track));
+    let mut role = TrackRole::from_track(&track);
+    role.forced |= options.forced;
+    role.sdh = (role.sdh || options.sdh) && !options.strip_sdh;
+
+    println!("Input:  {}", options.input.display());
+    println!(
+        "Track:  {track_number} ({codec}, language {}{}{}{})",
+        language.unwrap_or("unknown"),
+        track
+            .name()
+            .map_or(String::new(), |name| format!(", \"{name}\"")),
+        if role.forced { ", forced" } else { "" },
+        if role.sdh { ", SDH" } else { "" },
+    );
+
+    if let Some(start) = options.start {
+        mkv.seek(start / mkv.info().timestamp_scale().get())?;
+    }
+    let mut events = 0;
+    let mut frame = Frame::default();
+    while mkv.next_frame(&mut frame)? {
+        if frame.track != track_number {
+            continue;
+        }
+        // PGS clears are display sets of their own
+        if codec != "S_HDMV/PGS" || shows_objects(&frame.data).unwrap_or(false) {
+            events += 1;
+        }
+    }
+    let images = matches!(codec.as_str(), "S_HDMV/PGS" | "S_VOBSUB");
+    println!(
+        "Events: about {events}{}",
+        if options.filter.is_some() {
+            " (before --filter)"
+        } else {
+            ""
+        }
+    );
+
+    if !options.has_outputs() {
+        println!("Output: terminal preview");
+        return Ok(());
+    }
+    if let Some(ref output) = options.output {
+        match options.split_chapters {
+            true => println!(
+                "Output: {}, split into {} chapters",
+                output.display(),
+                read_chapters(&mkv).len()
+            ),
+            false => println!("Output: {}", output.display()),
+        }
+    }
+    if options.sidecar {
+        let extension = options.output_format.extension();
+        let path = sidecar_path(&options.input, language, role, extension);
+        let mut gains = Vec::new();
+        if language.is_none() {
+            gains.push("a language if one can be detected from the text");
+        }
+        if !role.sdh && !options.strip_sdh {
+            gains.push(".sdh if enough subtitles have SDH markers");
+        }
+        let note = match gains.is_empty() {
+            true => String::new(),
+            false => format!(" (gains {})", gains.join(", and ")),
+        };
+        println!("Output: {}{note}", path.display());
+    }
+    for output in options.outputs.iter() {
+        let path = match output.path {
+            Some(ref path) => path.clone(),
+            None => sidecar_path(&options.input, language, role, output.format.extension()),
+        };
+        println!("Output: {} ({})", path.display(), output.format.name());
+    }
+    if let Some(ref mux) = options.mux {
+        println!("Output: {} (copy with an added text track)", mux.display());
+    }
+    if let Some(ref path) = options.qc_report {
+        println!("Output: {} (reading speed report)", path.display());
+    }
+    if let Some(ref path) = options.diagnostics {
+        println!("Output: {} (diagnostics)", path.display());
+    }
+    if options.set_track_language && track_language(&track).is_none() {
+        match options.language {
+            Some(ref language) => {
+                println!("Would set the language of track {track_number} to {language}");
+            }
+            None => println!(
+                "Would set the language of track {track_number} to the one detected from its text"
+            ),
+        }
+    }
+    if images {
+        let seconds = (events as f64 * ESTIMATED_OCR_SECONDS).round() as u64;
+        println!(
+            "OCR:    {events} images, roughly {}m{:02}s",
+            seconds / 60,
+            seconds % 60
+        );
+    }
+    return Ok(());
+}
+
+/// Decodes a PGS track with both `PgsParser` and the reference renderer,
+/// reporting every display set they disagree on
+fn verify(options: &cli::Options) -> Result<(), Box<dyn Error>> {
+    let file = BufReader::new(File::open(&options.input)?);
+    let mut mkv = MatroskaFile::open(file)?;
+    let track = select_track(&mkv, options.track)?;
+    if track.codec_id() != "S_HDMV/PGS" {
+        return Err(format!(
+            "--verify only supports PGS tracks, not {}",
+            track.codec_id()
+        )
+        .into());
+    }
+    let track_number = track.track_number().get();
+    if let Some(ref directory) = options.save_images {
+        std::fs::create_dir_all(directory)?;
+    }
+    if let Some(start) = options.start {
+        mkv.seek(start / mkv.info().timestamp_scale().get())?;
+    }
+
     let timestamp_scale = mkv.info().timestamp_scale().get();
-    let track_num = video_track.track_number().get();
-    let mut sub_reader = PgsParser::new();
 
+    let mut parser = PgsParser::new();
+    let mut reference = ReferenceRenderer::new();
     let mut frame = Frame::default();
-    while mkv.next_frame(&mut frame).unwrap() {
-        if frame.track != track_num {
+    let (mut display_sets, mut image_differences, mut timeline_differences) = (0, 0, 0);
+    while mkv.next_frame(&mut frame)? {
+        if frame.track != track_number {
+            continue;
+        }
+        display_sets += 1;
+        let time = Timestamp::from_ticks(frame.timestamp, timestamp_scale)
+            .ok_or(ExtractError::TimestampOverflow(frame.timestamp))?;
+        let at = format!(
+            "Display set {display_sets} at {}",
+            format_timestamp(time.as_nanos())
+        );
+        let (ours, theirs) = match (
+            {
+                // The parser copies objects out of `frame`, which is reused
+                let mut image = image::GrayAlphaImage::new(0, 0);
+                parser
+                    .process_reused_into(&frame.data, &mut image)
+                    .map(|shown| shown.then_some(image))
+            },
+            reference.process_display_set(&frame.data),
+        ) {
+            (Ok(ours), Ok(theirs)) => (ours, theirs),
+            (Err(ours), Err(theirs)) => {
+                if ours.to_string() != theirs.to_string() {
+                    println!("{at}: decoder failed with \"{ours}\", reference with \"{theirs}\"");
+                    timeline_differences += 1;
+                }
+                continue;
+            }
+            (ours, theirs) => {
+                let describe = |result: Result<_, PgsError>| match result {
+                    Ok(_) => String::from("succeeded"),
+                    Err(err) => format!("failed with \"{err}\""),
+                };
+                println!(
+                    "{at}: decoder {}, reference {}",
+                    describe(ours.map(|_| ())),
+                    describe(theirs.map(|_| ()))
+                );
+                timeline_differences += 1;
+                continue;
+            }
+        };
+        // Empty compositions just clear the screen
+        let visible = |image: &Option<image::GrayAlphaImage>| {
+            return image
+                .as_ref()
+                .is_some_and(|image| image.pixels().any(|pixel| pixel.0[1] != 0));
+        };
+        if visible(&ours) != visible(&theirs) {
+            let shown = |visible: bool| if visible { "shows" } else { "clears" };
+            println!(
+                "{at}: decoder {}, reference {}",
+                shown(visible(&ours)),
+                shown(visible(&theirs))
+            );
+            timeline_differences += 1;
+        } else if let (Some(ours), Some(theirs)) = (&ours, &theirs) {
+            match differing_pixels(ours, theirs) {
+                Some(0) => continue,
+                Some(pixels) => println!("{at}: {pixels} pixels differ"),
+                None => println!(
+                    "{at}: decoder rendered {}x{}, reference {}x{}",
+                    ours.width(),
+                    ours.height(),
+                    theirs.width(),
+                    theirs.height()
+                ),
+            }
+            image_differences += 1;
+        } else {
+            continue;
+        }
+        if let Some(ref directory) = options.save_images {
+            for (name, image) in [("decoder", ours), ("reference", theirs)] {
+                if let Some(image) = image {
+                    image.save(directory.join(format!("{display_sets:05}-{name}.png")))?;
+                }
+            }
+        }
+    }
+
+    println!(
+        "Verified {display_sets} display sets: {image_differences} image and {timeline_differences} timeline differences"
+    );
+    if image_differences + timeline_differences > 0 {
+        return Err("The decoder and the reference renderer disagree".into());
+    }
+    return Ok(());
+}
+
+/// Opens `path` (or stdin, for [`cli::STDIN`]) for extraction. VobSub tracks
+/// without idx data in the track fall back to an idx file attached to the
+/// MKV, then to `palette`.
+fn open_extractor(
+    path: &Path,
+    io: &IoStrategy,
+    track: Option<u64>,
+    palette: Option<[image::Rgb<u8>; 16]>,
+) -> Result<SubtitleExtractor<Input>, Box<dyn Error>> {
+    let input = match path == Path::new(cli::STDIN) {
+        true => Input::Stream(StreamInput::new(
+            Box::new(io::stdin()) as Box<dyn Read + Send>
+        )?),
+        false => io.open(path)?,
+    };
+    // Stdin can't be read again for the attachments, which come before the
+    // clusters, so they're kept from what was read
+    let head = match input {
+        Input::Stream(ref stream) => Some(stream.head().to_vec()),
+        _ => None,
+    };
+    let mkv = MatroskaFile::open(input)?;
+    let entry = select_track(&mkv, track)?;
+    let needs_idx =
+        entry.codec_id() == "S_VOBSUB" && entry.codec_private().is_none_or(<[u8]>::is_empty);
+    // ASS/SSA tracks are rendered with the fonts attached to the MKV
+    let needs_fonts =
+        cfg!(feature = "ass") && matches!(entry.codec_id(), "S_TEXT/ASS" | "S_TEXT/SSA");
+    let mut attachments = Vec::new();
+    if needs_idx || needs_fonts {
+        attachments = match head {
+            Some(head) => read_attachments(&mut Cursor::new(head))?,
+            None => read_attachments(&mut BufReader::new(File::open(path)?))?,
+        };
+    }
+    let idx = attachments
+        .iter()
+        .find(|attachment| needs_idx && attachment.is_idx());
+    if let Some(idx) = idx {
+        eprintln!("The track has no idx data, using the attached {}", idx.name);
+    }
+    let extractor =
+        SubtitleExtractor::with_idx(mkv, track, idx.map(|idx| idx.data.as_slice()), palette)
+            .map_err(|err| match err {
+                ExtractError::VobSub(SubsError::InvalidIdx | SubsError::MissingIdx) => {
+                    format!("{err} Give its palette with --palette to decode it anyway.").into()
+                }
+                err => Box::<dyn Error>::from(err),
+            })?;
+    #[cfg(feature = "ass")]
+    let mut extractor = extractor;
+    #[cfg(feature = "ass")]
+    for font in attachments.iter().filter(|attachment| attachment.is_font()) {
+        extractor.add_font(&font.name, &font.data);
+    }
+    if let Some(err) = extractor.idx_error() {
+        eprintln!("Warning: {err} Decoding with --palette instead.");
+    }
+    return Ok(extractor);
+}
+
+fn report_skipped<R: Read + Seek>(extractor: &SubtitleExtractor<R>, diagnostics: &mut Diagnostics) {
+    let skipped = extractor.skipped_events();
+    if skipped > 0 {
+        let message =
+            format!("Skipped {skipped} subtitles that depended on data before the start position");
+        eprintln!("{message}");
+        diagnostics.push(Diagnostic::new(Stage::Extract, message));
+    }
+    let padded = extractor.padded_segments();
+    if padded > 0 {
+        warn(
+            diagnostics,
+            Diagnostic::new(
+                Stage::Extract,
+                format!("{padded} PGS segments had bytes past their fields, which were ignored"),
+            ),
+        );
+    }
+}
+
+fn contact_sheet(options: cli::SheetOptions) -> Result<(), Box<dyn Error>> {
+    let extractor = open_extractor(&options.input, &IoStrategy::default(), options.track, None)?;
+    let mut sheet = ContactSheet::new(options.layout);
+    let mut index = 0;
+    for event in extractor {
+        match event {
+            Ok(event) => {
+                sheet.event(index, &event)?;
+                index += 1;
+            }
+            Err(err) => eprintln!("Warning: {err}"),
+        }
+    }
+    if sheet.is_empty() {
+        return Err("The track has no image-based subtitles".into());
+    }
+
+    let sheets = sheet.render();
+    for (i, image) in sheets.iter().enumerate() {
+        let path = match sheets.len() {
+            1 => options.output.clone(),
+            _ => {
+                let mut name = options
+                    .output
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_os_string();
+                name.push(format!("-{:02}.png", i + 1));
+                options.output.with_file_name(name)
+            }
+        };
+        image.save(&path)?;
+        eprintln!("Wrote {}", path.display());
+    }
+    return Ok(());
+}
+
+/// OCRs a track with each background and scores the text against a
+/// reference transcript
+fn compare(options: cli::CompareOptions) -> Result<(), Box<dyn Error>> {
+    let reference = parse_srt(&std::fs::read_to_string(&options.reference)?);
+    if reference.is_empty() {
+        return Err(format!("No cues found in {}", options.reference.display()).into());
+    }
+    let mut extractor =
+        open_extractor(&options.input, &IoStrategy::default(), options.track, None)?;
+    extractor.set_indexed(options.backgrounds.contains(&Background::Palette));
+    let mut events = Vec::new();
+    for event in extractor {
+        match event {
+            Ok(event) if matches!(event.payload, EventPayload::Image(_)) => events.push(event),
+            Ok(_) => {}
+            Err(err) => eprintln!("Warning: {err}"),
+        }
+    }
+    if events.is_empty() {
+        return Err("The track has no image-based subtitles".into());
+    }
+
+    println!("background  error rate     errors  seconds");
+    for background in options.backgrounds {
+        let started = Instant::now();
+        let cues = to_cues(
+            events.clone(),
+            &options.ocr,
+            RegionPolicy::Merge,
+            FlattenOptions {
+                background,
+                ..options.flatten
+            },
+            false,
+            &CancellationToken::new(),
+            &mut Diagnostics::default(),
+        )?;
+        let score = compare::score(&cues, &reference);
+        println!(
+            "{:<10}  {:>9.2}%  {:>9}  {:>7.1}",
+            background.to_string(),
+            score.error_rate() * 100.0,
+            score.errors,
+            started.elapsed().as_secs_f64()
+        );
+    }
+    return Ok(());
+}
+
+/// Recognizes the images in a `--save-images` directory, timed by its
+/// manifest
+fn subtitle_ocr(options: cli::SubtitleOcrOptions) -> Result<(), Box<dyn Error>> {
+    let manifest = read_manifest(&options.directory.join(MANIFEST_FILE))?;
+    let track = manifest.tracks.into_iter().next().unwrap_or_default();
+    let mut events = Vec::new();
+    let mut removed = 0;
+    for mut event in track.events {
+        if let Some(image) = event.image.take() {
+            // Images deleted while reviewing are left out
+            match load_image_event(&options.directory, &event, image)? {
+                Some(event) => events.push(event),
+                None => removed += 1,
+            }
+            continue;
+        }
+        let Some(text) = event.text else {
+            continue;
+        };
+        events.push(SubtitleEvent {
+            start: event.start,
+            end: event.end,
+            end_source: event.end_source,
+            forced: event.forced,
+            payload: EventPayload::Text(text.text),
+            regions: Vec::new(),
+            placement: None,
+            indexed: None,
+        });
+    }
+
+    if removed > 0 {
+        eprintln!("Skipped {removed} subtitles whose images were removed");
+    }
+
+    let mut diagnostics = Diagnostics::default();
+    let mut cues = to_cues(
+        events,
+        &options.ocr,
+        options.regions,
+        options.flatten,
+        options.position_tags,
+        &CancellationToken::new(),
+        &mut diagnostics,
+    )?;
+    sort_cues(&mut cues);
+    write_srt(BufWriter::new(File::create(&options.output)?), &cues)?;
+    eprintln!(
+        "Wrote {} ({} subtitles)",
+        options.output.display(),
+        cues.len()
+    );
+    if !diagnostics.is_empty() {
+        eprintln!("{}", diagnostics.summary());
+    }
+    return Ok(());
+}
+
+/// Merges corrected text back into a manifest and writes outputs from the
+/// result
+fn rehydrate(options: cli::RehydrateOptions) -> Result<(), Box<dyn Error>> {
+    let path = match options.manifest.is_dir() {
+        true => options.manifest.join(MANIFEST_FILE),
+        false => options.manifest.clone(),
+    };
+    let mut manifest = read_manifest(&path)?;
+    let json = options
+        .text
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+    let corrected = match json {
+        true => manifest_cues(&read_manifest(&options.text)?.tracks[0], false, false),
+        false => parse_srt(&std::fs::read_to_string(&options.text)?),
+    };
+    let report = manifest.tracks[0].merge_text(&corrected);
+    eprintln!(
+        "Merged {} corrected subtitles: {} updated, {} added, {} left without text",
+        corrected.len(),
+        report.updated,
+        report.added,
+        report.cleared
+    );
+
+    let track = &manifest.tracks[0];
+    let cues = manifest_cues(track, options.position_tags, options.forced_only);
+    if let Some(ref output) = options.output {
+        write_srt(BufWriter::new(File::create(output)?), &cues)?;
+        eprintln!("Wrote {}", output.display());
+    }
+    if let Some(ref mux) = options.mux {
+        let source = options
+            .source
+            .clone()
+            .or_else(|| manifest.source.as_ref().map(PathBuf::from))
+            .ok_or("The manifest doesn't say which MKV it came from; give it with --source")?;
+        let sdh = SdhClassification::from_texts(cues.iter().map(|cue| cue.text.as_str()));
+        let mut input = BufReader::new(File::open(&source)?);
+        let mut output = BufWriter::new(File::create(mux)?);
+        let track_number = remux_with_text_track(
+            &mut input,
+            &mut output,
+            &TextTrack {
+                cues: &cues,
+                language: track
+                    .language
+                    .as_deref()
+                    .filter(|language| *language != "und"),
+                name: track.name.as_deref(),
+                forced: track.forced || options.forced_only,
+                sdh: track.hearing_impaired || sdh.is_sdh(),
+                default: track.default,
+            },
+        )?;
+        eprintln!("Wrote {} (added track {track_number})", mux.display());
+    }
+    if let Some(ref path) = options.json {
+        manifest.write_json(BufWriter::new(File::create(path)?))?;
+        eprintln!("Wrote {}", path.display());
+    }
+    return Ok(());
+}
+
+/// Reads a manifest saved with `--save-images`, which should have a track
+fn read_manifest(path: &Path) -> Result<SubtitleDocument, Box<dyn Error>> {
+    let file =
+        File::open(path).map_err(|err| format!("Failed to open {}: {err}", path.display()))?;
+    let manifest = SubtitleDocument::read_json(BufReader::new(file))
+        .map_err(|err| format!("Failed to read {}: {err}", path.display()))?;
+    if manifest.tracks.is_empty() {
+        return Err(format!("{} lists no tracks", path.display()).into());
+    }
+    return Ok(manifest);
+}
+
+/// The text of a manifest's events as cues, with missing end times taken
+/// from the next event
+fn manifest_cues(track: &SubtitleTrack, position_tags: bool, forced_only: bool) -> Vec<SrtCue> {
+    let mut cues = Vec::new();
+    for (i, event) in track.events.iter().enumerate() {
+        let Some(ref text) = event.text else {
             continue;
+        };
+        if forced_only && !event.forced {
+            continue;
+        }
+        let end = event
+            .end
+            .or_else(|| track.events.get(i + 1).map(|next| next.start))
+            .unwrap_or(event.start.saturating_add(FALLBACK_DURATION));
+        let top = event.image.as_ref().is_some_and(|image| {
+            return image
+                .placement
+                .is_some_and(|placement| placement.is_top(0, image.height));
+        });
+        let mut text = text.text.clone();
+        if position_tags && top && !text.starts_with("{\\an") {
+            text = format!("{{\\an8}}{text}");
+        }
+        cues.push(SrtCue {
+            start: event.start,
+            end,
+            text,
+        });
+    }
+    return cues;
+}
+
+/// Reads the image a manifest event refers to. Indexed PNGs keep their
+/// palette for `--background palette`; anything else is taken as its luma.
+/// `None` if the image no longer exists.
+fn load_image_event(
+    directory: &Path,
+    event: &model::SubtitleEvent,
+    image: model::ImageRef,
+) -> Result<Option<SubtitleEvent>, Box<dyn Error>> {
+    let path = directory.join(&image.path);
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("Failed to read {}: {err}", path.display()).into()),
+    };
+    let indexed = indexed::read_png(data.as_slice()).ok().flatten();
+    let bitmap = match indexed {
+        Some(ref indexed) => indexed.to_rgba().convert(),
+        None => image::load_from_memory(&data)
+            .map_err(|err| format!("Failed to read {}: {err}", path.display()))?
+            .to_luma_alpha8(),
+    };
+    return Ok(Some(SubtitleEvent {
+        start: event.start,
+        end: event.end,
+        end_source: event.end_source,
+        forced: event.forced,
+        payload: EventPayload::Image(bitmap),
+        regions: image.regions,
+        placement: image.placement,
+        indexed,
+    }));
+}
+
+/// Maps where a track has subtitles, without decoding them
+fn density(options: cli::DensityOptions) -> Result<(), Box<dyn Error>> {
+    let mkv = MatroskaFile::open(BufReader::new(File::open(&options.input)?))?;
+    let track = select_track(&mkv, options.track)?;
+    let number = track.track_number().get();
+    let scan = scan_track(&mut BufReader::new(File::open(&options.input)?), number)?;
+    let density = Density::new(&scan.times, options.bucket, scan.duration);
+    let gaps = find_gaps(&scan.times, scan.duration, options.gap, options.tail_gap);
+    if options.json {
+        let json = serde_json::json!({
+            "track": number,
+            "source": scan.source,
+            "blocks": scan.times.len(),
+            "bucket": density.bucket,
+            "counts": density.counts,
+            "gaps": gaps,
+        });
+        println!("{}", serde_json::to_string_pretty(&json)?);
+        return Ok(());
+    }
+
+    let source = match scan.source {
+        ScanSource::Cues => "the Cues index",
+        ScanSource::Clusters => "the clusters",
+    };
+    println!(
+        "Track {number} ({}): {} blocks, read from {source}",
+        track.codec_id(),
+        scan.times.len()
+    );
+    if scan.times.is_empty() {
+        return Ok(());
+    }
+    println!(
+        "Blocks per {}s, up to {}:",
+        options.bucket as f64 / 1e9,
+        density.counts.iter().max().unwrap_or(&0)
+    );
+    println!("{}", density.render(DENSITY_COLUMNS));
+    for gap in gaps {
+        match gap.kind {
+            GapKind::Between => println!(
+                "No subtitles from {} to {}",
+                format_timestamp(gap.start),
+                format_timestamp(gap.end)
+            ),
+            GapKind::Tail => println!(
+                "No subtitles after {}, {} minutes before the end of the file: the track may be cut off",
+                format_timestamp(gap.start),
+                gap.duration() / 60_000_000_000
+            ),
+        }
+    }
+    return Ok(());
+}
+
+fn palette_dump(options: cli::PaletteOptions) -> Result<(), Box<dyn Error>> {
+    let file = BufReader::new(File::open(&options.input)?);
+    let mut mkv = MatroskaFile::open(file)?;
+    let track = select_track(&mkv, options.track)?;
+    match track.codec_id() {
+        "S_VOBSUB" => {
+            let idx = vobs::parse_idx(track.codec_private().unwrap_or_default())?;
+            println!("palette: {}", vobs::format_palette(&idx.palette));
         }
-        frame.timestamp = frame.timestamp * timestamp_scale;
-        frame.duration = frame.duration.map(|duration| duration * timestamp_scale);
-        if let Ok(Some(image)) = sub_reader.process_mkv_frame(&frame) {
-            print_gray_image(&crop_image(&image).convert());
-        }
-    }
-}
-
-fn crop_image(image: &GrayAlphaImage) -> GrayAlphaImage {
-    let mut bounds: Option<(u32, u32, u32, u32)> = None;
-    for y in 0..image.height() {
-        for x in 0..image.width() {
-            let pixel = image.get_pixel(x, y);
-            if pixel.0[1] > 0 {
-                match bounds {
-                    Some((ref mut x1, _y1, ref mut x2, ref mut y2)) => {
-                        if *x1 > x {
-                            *x1 = x;
-                        }
-                        if *x2 < x {
-                            *x2 = x;
-                        }
-                        // y1 not needed due to scanning semantics
-                        if *y2 < y {
-                            *y2 = y;
-                        }
+        "S_HDMV/PGS" => {
+            // Palettes are redefined in every epoch, usually identically
+            let mut printed = HashSet::new();
+            let track_number = track.track_number().get();
+            let mut frame = Frame::default();
+            while mkv.next_frame(&mut frame)? {
+                if frame.track != track_number {
+                    continue;
+                }
+                let palettes = match read_palettes(&frame.data) {
+                    Ok(palettes) => palettes,
+                    Err(err) => {
+                        eprintln!("Warning: {err}");
+                        continue;
                     }
-                    None => {
-                        bounds = Some((x, y, x, y));
+                };
+                for palette in palettes {
+                    let entries: Vec<String> =
+                        palette.entries.iter().map(ToString::to_string).collect();
+                    let line = format!("palette {}: {}", palette.palette_id, entries.join(", "));
+                    if printed.insert(line.clone()) {
+                        println!("{line}");
                     }
                 }
             }
         }
+        other => return Err(format!("{other} tracks don't have a palette").into()),
+    }
+    return Ok(());
+}
+
+fn attachments(options: cli::AttachmentOptions) -> Result<(), Box<dyn Error>> {
+    let attachments = read_attachments(&mut BufReader::new(File::open(&options.input)?))?;
+    if attachments.is_empty() {
+        println!("No attachments");
+        return Ok(());
+    }
+    if let Some(ref directory) = options.extract {
+        std::fs::create_dir_all(directory)?;
+    }
+    for attachment in attachments {
+        println!(
+            "{} ({}, {} bytes)",
+            attachment.name,
+            attachment.media_type,
+            attachment.data.len()
+        );
+        let Some(ref directory) = options.extract else {
+            continue;
+        };
+        // Only the file name, so an attachment can't write outside the directory
+        let Some(name) = Path::new(&attachment.name).file_name() else {
+            eprintln!(
+                "Warning: skipping attachment with invalid name {:?}",
+                attachment.name
+            );
+            continue;
+        };
+        std::fs::write(directory.join(name), &attachment.data)?;
     }
-    match bounds {
-        None => {
-            return GrayAlphaImage::new(0, 0);
+    return Ok(());
+}
+
+/// Starts the OCR backend, caching its results in `cache` if given
+fn ocr_engine(ocr: &cli::OcrOptions) -> Result<Box<dyn OcrEngine>, OcrError> {
+    let backend = &ocr.backend;
+    let engine: Box<dyn OcrEngine> = match backend {
+        cli::OcrBackend::Tesseract(constraints) => Box::new(TesseractEngine::with_constraints(
+            ocr.language.as_deref().unwrap_or(cli::DEFAULT_OCR_LANGUAGE),
+            constraints,
+        )?),
+        cli::OcrBackend::Command(engine) => Box::new(engine.clone()),
+        cli::OcrBackend::Http(endpoint) => Box::new(HttpEngine::new(endpoint.as_str())),
+    };
+    let Some(ref cache) = ocr.cache else {
+        return Ok(engine);
+    };
+    // The backend's description covers everything that changes its output
+    // but the language, which is left out when it's the default so existing
+    // caches stay valid
+    let key = match ocr.language {
+        Some(ref language) => format!("{backend:?} {language}"),
+        None => format!("{backend:?}"),
+    };
+    return Ok(Box::new(CachedEngine::new(engine, cache, key)?));
+}
+
+/// OCRs image events, flattened according to `flatten` and with multi-region
+/// ones handled according to `regions`, and resolves missing end times from the following event.
+/// The OCR engine is only started if there are images to recognize.
+fn to_cues(
+    events: Vec<SubtitleEvent>,
+    ocr: &cli::OcrOptions,
+    regions: RegionPolicy,
+    flatten: FlattenOptions,
+    position_tags: bool,
+    cancel: &CancellationToken,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<SrtCue>, OcrError> {
+    let mut engine = None;
+    let mut cues = Vec::new();
+    for (i, event) in events.iter().enumerate() {
+        if cancel.is_cancelled() {
+            break;
         }
-        Some((x1, y1, x2, y2)) => {
-            let mut new_image = GrayAlphaImage::new(x2 + 1 - x1, y2 + 1 - y1);
-            for (new_y, y) in (y1..=y2).enumerate() {
-                for (new_x, x) in (x1..=x2).enumerate() {
-                    new_image.put_pixel(new_x as _, new_y as _, image.get_pixel(x, y).clone());
-                }
+        let texts = match event.payload {
+            EventPayload::Image(ref image) => {
+                let engine = match engine {
+                    Some(ref mut engine) => engine,
+                    // A zero threshold never retries, which keeps one engine
+                    // type either way
+                    None => engine.insert(CancellableEngine::new(
+                        RetryEngine::new(
+                            ocr_engine(ocr)?,
+                            ocr.retry.clone().unwrap_or(RetryPolicy::new(0.0)),
+                        ),
+                        cancel.clone(),
+                    )),
+                };
+                let segmented;
+                let image = match event.indexed {
+                    Some(ref indexed) if flatten.background == Background::Palette => {
+                        segmented = segment(indexed);
+                        &segmented
+                    }
+                    _ => image,
+                };
+                recognize_regions(
+                    engine,
+                    image,
+                    &event.regions,
+                    event.placement,
+                    regions,
+                    flatten,
+                )
+                .unwrap_or_else(|err| {
+                    if !matches!(err, OcrError::Cancelled(_)) {
+                        warn(
+                            diagnostics,
+                            Diagnostic::new(
+                                Stage::Ocr,
+                                format!("OCR failed for event {}: {err}", i + 1),
+                            )
+                            .at_event(i + 1, event.start),
+                        );
+                    }
+                    Vec::new()
+                })
             }
-            return new_image;
+            EventPayload::Text(ref text) => vec![text.clone()],
+        };
+        let end = event
+            .end
+            .or_else(|| events.get(i + 1).map(|next| next.start))
+            .unwrap_or(event.start.saturating_add(FALLBACK_DURATION));
+        let top = match (&event.payload, event.placement) {
+            (EventPayload::Image(image), Some(placement)) => placement.is_top(0, image.height()),
+            _ => false,
+        };
+        for mut text in texts {
+            if position_tags && top && !text.starts_with("{\\an") {
+                text = format!("{{\\an8}}{text}");
+            }
+            cues.push(SrtCue {
+                start: event.start,
+                end,
+                text,
+            });
+        }
+    }
+    if let Some(engine) = engine {
+        report_retries(engine.inner().records());
+    }
+    return Ok(cues);
+}
+
+/// Summarizes `--ocr-retry`, including which preprocessing helped
+fn report_retries(records: &[RetryRecord]) {
+    if records.is_empty() {
+        return;
+    }
+    let mut winners: Vec<(Variant, usize)> = Vec::new();
+    for winner in records.iter().filter_map(|record| record.winner) {
+        match winners.iter_mut().find(|(variant, _)| *variant == winner) {
+            Some((_, count)) => *count += 1,
+            None => winners.push((winner, 1)),
         }
     }
+    let improved: usize = winners.iter().map(|(_, count)| count).sum();
+    eprintln!(
+        "Retried {} low-confidence images with other preprocessing, {improved} improved",
+        records.len()
+    );
+    for (variant, count) in winners {
+        eprintln!("  {variant:?}: {count}");
+    }
+}
+
+/// Lines an SRT up with a reference track
+fn sync(options: cli::SyncCommandOptions) -> Result<(), Box<dyn Error>> {
+    let mut cues = parse_srt(&std::fs::read_to_string(&options.input)?);
+    if cues.is_empty() {
+        return Err(format!("No cues found in {}", options.input.display()).into());
+    }
+    let reference = read_reference(&options.reference)?;
+    let alignment = align(&cues, &cue_intervals(&reference), options.sync);
+    alignment.correction.apply_cues(&mut cues);
+    print_alignment(&alignment);
+    write_srt(BufWriter::new(File::create(&options.output)?), &cues)?;
+    eprintln!("Wrote {}", options.output.display());
+    return Ok(());
+}
+
+/// Reads the SRT to sync against, which must have cues
+fn read_reference(path: &Path) -> Result<Vec<SrtCue>, Box<dyn Error>> {
+    let reference = parse_srt(&std::fs::read_to_string(path)?);
+    if reference.is_empty() {
+        return Err(format!("No cues found in {}", path.display()).into());
+    }
+    return Ok(reference);
+}
+
+fn print_alignment(alignment: &Alignment) {
+    let correction = alignment.correction;
+    eprintln!(
+        "Synced: offset {:+.3}s, scale {:.5}; agreement {:.1}% -> {:.1}%",
+        correction.offset as f64 / 1e9,
+        correction.scale,
+        alignment.before * 100.0,
+        alignment.after * 100.0
+    );
 }
@@ -0,0 +1,273 @@
+//! Serializable subtitle model, used as the interchange format between
+//! readers and writers and for persisting or sending results over the
+//! service API. Unlike [`crate::extract::SubtitleEvent`], images are
+//! referenced rather than carried inline, and image events can have
+//! recognized text alongside them.
+
+use std::path::PathBuf;
+#[cfg(feature = "writers")]
+use std::{
+    cmp::Reverse,
+    io::{Read, Write},
+};
+
+#[cfg(feature = "demux-mkv")]
+use matroska_demuxer::TrackEntry;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "demux-mkv")]
+use crate::extract::{self, EventPayload};
+use crate::preprocess::{Placement, Region};
+#[cfg(feature = "writers")]
+use crate::srt::SrtCue;
+
+/// What the manifest listing saved images is called, in the same directory
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// A set of tracks from one source, e.g. every subtitle track in an MKV
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SubtitleDocument {
+    /// Where the tracks came from, usually the input path
+    pub source: Option<String>,
+    pub tracks: Vec<SubtitleTrack>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SubtitleTrack {
+    /// Track number in the container
+    pub number: Option<u64>,
+    /// Container codec ID, e.g. `S_HDMV/PGS`
+    pub codec: String,
+    pub language: Option<String>,
+    pub name: Option<String>,
+    /// The container's default, forced and hearing impaired track flags
+    #[serde(default)]
+    pub default: bool,
+    #[serde(default)]
+    pub forced: bool,
+    #[serde(default)]
+    pub hearing_impaired: bool,
+    pub events: Vec<SubtitleEvent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubtitleEvent {
+    /// Nanoseconds
+    pub start: u64,
+    /// Nanoseconds. `None` if the container didn't say how long the event lasts.
+    pub end: Option<u64>,
+    /// How `end` was arrived at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_source: Option<EndSource>,
+    pub forced: bool,
+    /// The event's text, either from the stream itself or recognized from
+    /// its image
+    pub text: Option<TextPayload>,
+    pub image: Option<ImageRef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextPayload {
+    pub text: String,
+    pub origin: TextOrigin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextOrigin {
+    /// Text subtitles, taken as-is
+    Stream,
+    /// Recognized from the event's image
+    Ocr,
+    /// Corrected by hand and merged back in with
+    /// [`SubtitleTrack::merge_text`]
+    Edited,
+}
+
+/// Where an event's end time came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndSource {
+    /// The subtitle stream itself: the PGS display set that cleared or
+    /// replaced it, a VobSub stop command or a TextST end time
+    Stream,
+    /// The container's block duration
+    Container,
+    /// Neither gave one, so it lasts until the next event starts, up to a
+    /// limit
+    NextEvent,
+    /// Neither gave one and no event follows, so it lasts a fixed time
+    Fallback,
+}
+
+/// A subtitle bitmap stored outside the model
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageRef {
+    /// Where the image was saved, relative to the document if not absolute
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    /// Where the image is shown on screen, if the format says
+    pub placement: Option<Placement>,
+    /// Separately positioned parts of the image, relative to it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub regions: Vec<Region>,
+}
+
+/// What [`SubtitleTrack::merge_text`] did with the corrected cues
+#[cfg(feature = "writers")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Events given corrected text
+    pub updated: usize,
+    /// Events left without text, since no cue overlaps them any more
+    pub cleared: usize,
+    /// Cues overlapping no event, added as text events of their own
+    pub added: usize,
+}
+
+#[cfg(feature = "writers")]
+impl SubtitleDocument {
+    pub fn read_json<R: Read>(reader: R) -> Result<Self, serde_json::Error> {
+        return serde_json::from_reader(reader);
+    }
+
+    pub fn write_json<W: Write>(&self, writer: W) -> Result<(), serde_json::Error> {
+        return serde_json::to_writer_pretty(writer, self);
+    }
+}
+
+#[cfg(feature = "writers")]
+impl SubtitleTrack {
+    /// Replaces the text of each event with the corrected `cues` that
+    /// overlap it the most, keeping the timing, position and flags that were
+    /// stored with it. Several cues on one event are joined into one text.
+    pub fn merge_text(&mut self, cues: &[SrtCue]) -> MergeReport {
+        let ends = self.resolved_ends();
+        let mut texts: Vec<Vec<&str>> = vec![Vec::new(); self.events.len()];
+        let mut added = Vec::new();
+        for cue in cues {
+            let best = self
+                .events
+                .iter()
+                .zip(ends.iter())
+                .enumerate()
+                .map(|(index, (event, end))| {
+                    let overlap = cue.end.min(*end).saturating_sub(cue.start.max(event.start));
+                    return (overlap, index);
+                })
+                .filter(|(overlap, _)| *overlap > 0)
+                .max_by_key(|(overlap, index)| (*overlap, Reverse(*index)));
+            match best {
+                Some((_, index)) => texts[index].push(&cue.text),
+                None => added.push(cue),
+            }
+        }
+
+        let mut report = MergeReport::default();
+        for (event, texts) in self.events.iter_mut().zip(texts) {
+            if texts.is_empty() {
+                event.text = None;
+                report.cleared += 1;
+                continue;
+            }
+            event.text = Some(TextPayload {
+                text: texts.join("\n"),
+                origin: TextOrigin::Edited,
+            });
+            report.updated += 1;
+        }
+        for cue in added {
+            self.events.push(SubtitleEvent {
+                start: cue.start,
+                end: Some(cue.end),
+                end_source: None,
+                forced: false,
+                text: Some(TextPayload {
+                    text: cue.text.clone(),
+                    origin: TextOrigin::Edited,
+                }),
+                image: None,
+            });
+            report.added += 1;
+        }
+        // Stable, so events starting together keep their order
+        self.events.sort_by_key(|event| event.start);
+        return report;
+    }
+
+    /// Each event's end, with missing ones taken as the next event's start
+    fn resolved_ends(&self) -> Vec<u64> {
+        return self
+            .events
+            .iter()
+            .enumerate()
+            .map(|(i, event)| {
+                return event
+                    .end
+                    .or_else(|| self.events.get(i + 1).map(|next| next.start))
+                    .unwrap_or(u64::MAX);
+            })
+            .collect();
+    }
+}
+
+#[cfg(feature = "demux-mkv")]
+impl SubtitleTrack {
+    /// An empty track described by `entry`
+    pub fn from_entry(entry: &TrackEntry) -> Self {
+        return Self {
+            number: Some(entry.track_number().get()),
+            codec: entry.codec_id().to_owned(),
+            language: entry.language().map(str::to_owned),
+            name: entry.name().map(str::to_owned),
+            default: entry.flag_default(),
+            forced: entry.flag_forced(),
+            hearing_impaired: entry.flag_hearing_impaired(),
+            events: Vec::new(),
+        };
+    }
+}
+
+#[cfg(feature = "demux-mkv")]
+impl SubtitleEvent {
+    /// Converts an extracted event. Image events are referenced by
+    /// `image_path`, which the caller is expected to save them to.
+    pub fn from_extracted(event: &extract::SubtitleEvent, image_path: impl Into<PathBuf>) -> Self {
+        let (text, image) = match event.payload {
+            EventPayload::Text(ref text) => (
+                Some(TextPayload {
+                    text: text.clone(),
+                    origin: TextOrigin::Stream,
+                }),
+                None,
+            ),
+            EventPayload::Image(ref image) => (
+                None,
+                Some(ImageRef {
+                    path: image_path.into(),
+                    width: image.width(),
+                    height: image.height(),
+                    placement: event.placement,
+                    regions: event.regions.clone(),
+                }),
+            ),
+        };
+        return Self {
+            start: event.start,
+            end: event.end,
+            end_source: event.end_source,
+            forced: event.forced,
+            text,
+            image,
+        };
+    }
+
+    /// Attaches text recognized from the event's image
+    pub fn set_ocr_text(&mut self, text: impl Into<String>) {
+        self.text = Some(TextPayload {
+            text: text.into(),
+            origin: TextOrigin::Ocr,
+        });
+    }
+}
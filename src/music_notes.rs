@@ -0,0 +1,336 @@
+//! Music note (♪/♫) handling for OCR. Tesseract has no idea what to do with
+//! these glyphs and turns them into `J`, `f` or `&`, so they're found by
+//! template matching first, erased from the bitmap, and put back into the
+//! recognized text at the matching position.
+
+use image::GrayAlphaImage;
+
+/// Size of the normalized grid components are compared on
+const GRID: usize = 24;
+/// Minimum intersection-over-union with a template to count as a note
+const MATCH_THRESHOLD: f64 = 0.62;
+/// Components smaller than this (in pixels of height) are punctuation or noise
+const MIN_HEIGHT: u32 = 8;
+/// Pixels around a note's fill that are erased too, to take the outline with it
+const ERASE_MARGIN: u32 = 3;
+
+type Template = [[bool; GRID]; GRID];
+
+fn fill_ellipse(grid: &mut Template, cx: f64, cy: f64, rx: f64, ry: f64) {
+    for (y, row) in grid.iter_mut().enumerate() {
+        for (x, cell) in row.iter_mut().enumerate() {
+            let dx = (x as f64 + 0.5 - cx) / rx;
+            let dy = (y as f64 + 0.5 - cy) / ry;
+            if dx * dx + dy * dy <= 1.0 {
+                *cell = true;
+            }
+        }
+    }
+}
+
+fn fill_rect(grid: &mut Template, x1: usize, y1: usize, x2: usize, y2: usize) {
+    for row in grid.iter_mut().take(y2).skip(y1) {
+        for cell in row.iter_mut().take(x2).skip(x1) {
+            *cell = true;
+        }
+    }
+}
+
+/// Rough shapes of the glyphs as most subtitle fonts draw them, normalized to
+/// the component's bounding box
+fn templates() -> [(char, Template); 2] {
+    // ♪: filled head bottom left, stem on its right, flag hanging off the top
+    let mut eighth = [[false; GRID]; GRID];
+    fill_ellipse(&mut eighth, 7.0, 19.0, 7.0, 5.0);
+    fill_rect(&mut eighth, 11, 0, 14, 19);
+    for i in 0..10 {
+        fill_rect(&mut eighth, 14, i, 14 + (10 - i).min(8), i + 2);
+    }
+
+    // ♫: two heads, two stems and a thick beam across the top
+    let mut beamed = [[false; GRID]; GRID];
+    fill_ellipse(&mut beamed, 4.5, 20.0, 4.5, 3.5);
+    fill_ellipse(&mut beamed, 19.0, 18.0, 4.5, 3.5);
+    fill_rect(&mut beamed, 7, 2, 9, 20);
+    fill_rect(&mut beamed, 21, 0, 24, 18);
+    fill_rect(&mut beamed, 7, 0, 24, 5);
+
+    return [('♪', eighth), ('♫', beamed)];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteMatch {
+    pub glyph: char,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Music notes found in a subtitle bitmap, along with the text rows they
+/// belong to
+#[derive(Debug, Clone, Default)]
+pub struct NoteDetection {
+    pub notes: Vec<NoteMatch>,
+    rows: Vec<Row>,
+}
+
+/// A row of glyphs in the bitmap
+#[derive(Debug, Clone, Copy)]
+struct Row {
+    top: u32,
+    bottom: u32,
+    /// Horizontal extent of everything but the notes, `None` if the row only
+    /// contains notes
+    extent: Option<(u32, u32)>,
+}
+
+struct Component {
+    label: u32,
+    x1: u32,
+    y1: u32,
+    x2: u32,
+    y2: u32,
+}
+
+fn is_fill(image: &GrayAlphaImage, x: u32, y: u32) -> bool {
+    let [luma, alpha] = image.get_pixel(x, y).0;
+    return alpha >= 128 && luma >= 128;
+}
+
+/// Labels 8-connected regions of glyph fill. Label 0 is background.
+fn label_components(image: &GrayAlphaImage) -> (Vec<u32>, Vec<Component>) {
+    let (width, height) = image.dimensions();
+    let mut labels = vec![0u32; (width * height) as usize];
+    let mut components = Vec::new();
+    let mut stack = Vec::new();
+    for start_y in 0..height {
+        for start_x in 0..width {
+            if labels[(start_y * width + start_x) as usize] != 0
+                || !is_fill(image, start_x, start_y)
+            {
+                continue;
+            }
+            let label = components.len() as u32 + 1;
+            let mut component = Component {
+                label,
+                x1: start_x,
+                y1: start_y,
+                x2: start_x,
+                y2: start_y,
+            };
+            labels[(start_y * width + start_x) as usize] = label;
+            stack.push((start_x, start_y));
+            while let Some((x, y)) = stack.pop() {
+                component.x1 = component.x1.min(x);
+                component.x2 = component.x2.max(x);
+                component.y1 = component.y1.min(y);
+                component.y2 = component.y2.max(y);
+                for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                    for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                        let index = (ny * width + nx) as usize;
+                        if labels[index] == 0 && is_fill(image, nx, ny) {
+                            labels[index] = label;
+                            stack.push((nx, ny));
+                        }
+                    }
+                }
+            }
+            components.push(component);
+        }
+    }
+    return (labels, components);
+}
+
+/// Splits the image into rows of glyphs using the horizontal projection of `mask`
+fn find_rows(width: u32, height: u32, mask: impl Fn(u32, u32) -> bool) -> Vec<(u32, u32)> {
+    let mut rows: Vec<(u32, u32)> = Vec::new();
+    let mut start = None;
+    for y in 0..=height {
+        let inked = y < height && (0..width).any(|x| mask(x, y));
+        match (inked, start) {
+            (true, None) => start = Some(y),
+            (false, Some(top)) => {
+                // Accents and dots sit a couple of pixels above their letters
+                match rows.last_mut() {
+                    Some(last) if top - last.1 <= 3 => last.1 = y,
+                    _ => rows.push((top, y)),
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    return rows;
+}
+
+fn match_score(labels: &[u32], width: u32, component: &Component, template: &Template) -> f64 {
+    let component_width = component.x2 - component.x1 + 1;
+    let component_height = component.y2 - component.y1 + 1;
+    let mut intersection = 0;
+    let mut union = 0;
+    for (ty, row) in template.iter().enumerate() {
+        for (tx, expected) in row.iter().enumerate() {
+            let x = component.x1 + (tx as u32 * component_width) / GRID as u32;
+            let y = component.y1 + (ty as u32 * component_height) / GRID as u32;
+            let actual = labels[(y * width + x) as usize] == component.label;
+            if actual && *expected {
+                intersection += 1;
+            }
+            if actual || *expected {
+                union += 1;
+            }
+        }
+    }
+    return intersection as f64 / union.max(1) as f64;
+}
+
+impl NoteDetection {
+    pub fn detect(image: &GrayAlphaImage) -> Self {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return Self::default();
+        }
+        let (labels, components) = label_components(image);
+        let all_rows = find_rows(width, height, |x, y| labels[(y * width + x) as usize] != 0);
+        let templates = templates();
+
+        let mut notes = Vec::new();
+        for component in components.iter() {
+            let component_height = component.y2 - component.y1 + 1;
+            let component_width = component.x2 - component.x1 + 1;
+            let Some(row) = all_rows
+                .iter()
+                .find(|row| row.0 <= component.y1 && component.y2 < row.1)
+            else {
+                continue;
+            };
+            let row_height = row.1 - row.0;
+            let aspect = component_width as f64 / component_height as f64;
+            if component_height < MIN_HEIGHT
+                || component_height * 2 < row_height
+                || !(0.4..=1.4).contains(&aspect)
+            {
+                continue;
+            }
+            let best = templates
+                .iter()
+                .map(|(glyph, template)| (*glyph, match_score(&labels, width, component, template)))
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((glyph, score)) = best
+                && score >= MATCH_THRESHOLD
+            {
+                notes.push(NoteMatch {
+                    glyph,
+                    x: component.x1,
+                    y: component.y1,
+                    width: component_width,
+                    height: component_height,
+                });
+            }
+        }
+        if notes.is_empty() {
+            return Self::default();
+        }
+
+        let is_note = |x: u32, y: u32| {
+            return notes.iter().any(|note| {
+                (note.x..note.x + note.width).contains(&x)
+                    && (note.y..note.y + note.height).contains(&y)
+            });
+        };
+        let rows = all_rows
+            .iter()
+            .map(|(top, bottom)| {
+                let mut extent: Option<(u32, u32)> = None;
+                for y in *top..*bottom {
+                    for x in 0..width {
+                        if labels[(y * width + x) as usize] != 0 && !is_note(x, y) {
+                            extent = Some(match extent {
+                                Some((left, right)) => (left.min(x), right.max(x)),
+                                None => (x, x),
+                            });
+                        }
+                    }
+                }
+                return Row {
+                    top: *top,
+                    bottom: *bottom,
+                    extent,
+                };
+            })
+            .collect();
+        return Self { notes, rows };
+    }
+
+    /// Makes the detected notes (and their outlines) transparent, so OCR
+    /// doesn't see them
+    pub fn erase(&self, image: &mut GrayAlphaImage) {
+        for note in self.notes.iter() {
+            let x2 = (note.x + note.width + ERASE_MARGIN).min(image.width());
+            let y2 = (note.y + note.height + ERASE_MARGIN).min(image.height());
+            for y in note.y.saturating_sub(ERASE_MARGIN)..y2 {
+                for x in note.x.saturating_sub(ERASE_MARGIN)..x2 {
+                    image.get_pixel_mut(x, y).0 = [0, 0];
+                }
+            }
+        }
+    }
+
+    /// Puts the notes back into the text recognized from the erased image.
+    /// Lines of text are matched up with rows of glyphs in order, and notes
+    /// are inserted at the word boundary closest to their horizontal position.
+    pub fn reinsert(&self, text: &str) -> String {
+        if self.notes.is_empty() {
+            return text.to_owned();
+        }
+        let mut text_lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(String::from);
+
+        let mut lines = Vec::new();
+        for row in self.rows.iter() {
+            let mut notes: Vec<&NoteMatch> = self
+                .notes
+                .iter()
+                .filter(|note| (row.top..row.bottom).contains(&(note.y + note.height / 2)))
+                .collect();
+            notes.sort_by_key(|note| note.x);
+
+            let Some((left, right)) = row.extent else {
+                // Only notes on this row, so there's no OCR line for it
+                let glyphs: Vec<String> = notes.iter().map(|note| note.glyph.to_string()).collect();
+                lines.push(glyphs.join(" "));
+                continue;
+            };
+            let mut line = text_lines.next().unwrap_or_default();
+            // Insert right to left so earlier positions stay valid
+            for note in notes.iter().rev() {
+                let center = note.x + note.width / 2;
+                if center <= left {
+                    line = format!("{} {line}", note.glyph);
+                } else if center >= right {
+                    line = format!("{line} {}", note.glyph);
+                } else {
+                    let fraction = (center - left) as f64 / (right - left) as f64;
+                    let target = (line.chars().count() as f64 * fraction) as usize;
+                    let boundary = line
+                        .char_indices()
+                        .filter(|(_, c)| *c == ' ')
+                        .map(|(i, _)| i)
+                        .min_by_key(|i| line[..*i].chars().count().abs_diff(target));
+                    match boundary {
+                        Some(i) => line.insert_str(i, &format!(" {}", note.glyph)),
+                        None => line = format!("{line} {}", note.glyph),
+                    }
+                }
+            }
+            lines.push(line.trim().to_owned());
+        }
+        // OCR may have split lines differently than the rows; keep the rest
+        lines.extend(text_lines);
+        return lines.join("\n");
+    }
+}
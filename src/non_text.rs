@@ -0,0 +1,151 @@
+//! Spotting subtitle bitmaps that aren't text, so they can be skipped
+//! before OCR. Some discs show logos, decorative dots or rules, or bitmaps
+//! so faint they're practically transparent, which OCR turns into a stray
+//! character or two at best.
+//!
+//! The checks are deliberately loose, since dropping a real subtitle is
+//! worse than OCRing some junk: a bitmap has to have almost no ink, be
+//! nearly invisible, or be far too thin or narrow for a line of text.
+
+use std::fmt;
+
+use image::GrayAlphaImage;
+
+use crate::preprocess::Region;
+
+/// Alpha at which a pixel counts as ink
+const INK_ALPHA: u8 = 128;
+
+/// When [`classify`] considers a bitmap not to be text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NonTextRules {
+    /// Fewest opaque pixels a bitmap needs. A period in a small font has a
+    /// couple of dozen.
+    pub min_ink: u32,
+    /// Smallest share of the box around a region's ink that's opaque, from
+    /// 0 to 1. Text covers a good part of its box, while a few specks far
+    /// apart barely cover theirs.
+    pub min_coverage: f32,
+    /// Narrowest width as a multiple of height. Text lines are wide, while
+    /// vertical bars and dotted columns are not.
+    pub min_aspect: f32,
+    /// Widest width as a multiple of height, past which it's a rule rather
+    /// than a line of text
+    pub max_aspect: f32,
+}
+impl Default for NonTextRules {
+    fn default() -> Self {
+        return Self {
+            min_ink: 40,
+            min_coverage: 0.02,
+            min_aspect: 0.1,
+            max_aspect: 80.0,
+        };
+    }
+}
+
+/// Why [`classify`] considers a bitmap not to be text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NonTextReason {
+    /// No pixel is even half opaque. Holds the highest alpha.
+    Faint(u8),
+    /// Opaque pixels
+    LittleInk(u32),
+    /// Share of the box around the ink that's opaque, from 0 to 1
+    Sparse(f32),
+    /// Width as a multiple of height
+    Aspect(f32),
+}
+impl fmt::Display for NonTextReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            NonTextReason::Faint(alpha) => write!(f, "nearly transparent, alpha {alpha} at most"),
+            NonTextReason::LittleInk(pixels) => write!(f, "only {pixels} pixels of ink"),
+            NonTextReason::Sparse(coverage) => {
+                write!(f, "only {:.1}% of it opaque", coverage * 100.0)
+            }
+            NonTextReason::Aspect(aspect) => write!(f, "{aspect:.2} times as wide as it is tall"),
+        };
+    }
+}
+
+/// Whether `image` looks like something other than text, and why. `None`
+/// if it may well be text.
+///
+/// `regions` are the separately placed parts of the image, like a PGS
+/// composition's objects. Sparseness and shape are judged per region, each
+/// within the box around its ink, so a sign at the top and dialogue at the
+/// bottom aren't taken for a few specks in a tall box. The image counts as
+/// text if any region does. Without regions the whole image is one.
+pub fn classify(
+    image: &GrayAlphaImage,
+    regions: &[Region],
+    rules: &NonTextRules,
+) -> Option<NonTextReason> {
+    let max_alpha = image.pixels().map(|pixel| pixel[1]).max().unwrap_or(0);
+    if max_alpha < INK_ALPHA {
+        return Some(NonTextReason::Faint(max_alpha));
+    }
+    let ink = image.pixels().filter(|pixel| pixel[1] >= INK_ALPHA).count() as u32;
+    if ink < rules.min_ink {
+        return Some(NonTextReason::LittleInk(ink));
+    }
+    let full = Region {
+        x: 0,
+        y: 0,
+        width: image.width(),
+        height: image.height(),
+    };
+    let regions = match regions.is_empty() {
+        true => std::slice::from_ref(&full),
+        false => regions,
+    };
+    let mut reason = None;
+    for region in regions {
+        match classify_region(image, region, rules) {
+            None => return None,
+            Some(found) => {
+                reason.get_or_insert(found);
+            }
+        }
+    }
+    return reason;
+}
+
+/// The sparseness and shape checks of [`classify`], for the ink within one
+/// region
+fn classify_region(
+    image: &GrayAlphaImage,
+    region: &Region,
+    rules: &NonTextRules,
+) -> Option<NonTextReason> {
+    let mut ink = 0;
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    let x_end = region.x.saturating_add(region.width).min(image.width());
+    let y_end = region.y.saturating_add(region.height).min(image.height());
+    for y in region.y..y_end {
+        for x in region.x..x_end {
+            if image.get_pixel(x, y)[1] < INK_ALPHA {
+                continue;
+            }
+            ink += 1;
+            bounds = Some(match bounds {
+                Some((x1, y1, x2, y2)) => (x1.min(x), y1.min(y), x2.max(x), y2.max(y)),
+                None => (x, y, x, y),
+            });
+        }
+    }
+    let Some((x1, y1, x2, y2)) = bounds else {
+        return Some(NonTextReason::LittleInk(0));
+    };
+    let (width, height) = (x2 + 1 - x1, y2 + 1 - y1);
+    let coverage = ink as f32 / (width as f32 * height as f32);
+    if coverage < rules.min_coverage {
+        return Some(NonTextReason::Sparse(coverage));
+    }
+    let aspect = width as f32 / height as f32;
+    if aspect < rules.min_aspect || aspect > rules.max_aspect {
+        return Some(NonTextReason::Aspect(aspect));
+    }
+    return None;
+}
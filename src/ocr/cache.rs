@@ -0,0 +1,120 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use image::GrayImage;
+
+use super::{OcrEngine, OcrError, Recognition};
+
+/// Bumped when the entry format changes, so old entries are ignored
+const FORMAT_VERSION: &[u8] = b"2";
+
+/// Remembers what another engine recognized, in a directory with one text
+/// file per image, so re-running an extraction with different output options
+/// doesn't redo the OCR. Entries are keyed by a hash of the image and a
+/// settings string, which should change whenever the engine would give
+/// different results (another backend, language...). Engine upgrades aren't
+/// noticed, so clear the directory after one.
+pub struct CachedEngine<E: OcrEngine> {
+    engine: E,
+    dir: PathBuf,
+    settings: String,
+    hits: usize,
+}
+impl<E: OcrEngine> CachedEngine<E> {
+    /// Caches `engine`'s results in `dir`, creating it if needed
+    pub fn new(
+        engine: E,
+        dir: impl Into<PathBuf>,
+        settings: impl Into<String>,
+    ) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        return Ok(Self {
+            engine,
+            dir,
+            settings: settings.into(),
+            hits: 0,
+        });
+    }
+
+    /// How many images were answered from the cache
+    pub fn hits(&self) -> usize {
+        return self.hits;
+    }
+
+    fn entry_path(&self, image: &GrayImage) -> PathBuf {
+        let mut hash = Fnv128::new();
+        hash.write(FORMAT_VERSION);
+        hash.write(self.settings.as_bytes());
+        hash.write(&[0]);
+        hash.write(&image.width().to_be_bytes());
+        hash.write(&image.height().to_be_bytes());
+        hash.write(image.as_raw());
+        return self.dir.join(format!("{:032x}.txt", hash.finish()));
+    }
+}
+impl<E: OcrEngine> OcrEngine for CachedEngine<E> {
+    fn recognize(&mut self, image: &GrayImage) -> Result<String, OcrError> {
+        return Ok(self.recognize_scored(image)?.text);
+    }
+
+    fn recognize_scored(&mut self, image: &GrayImage) -> Result<Recognition, OcrError> {
+        let path = self.entry_path(image);
+        if let Some(recognition) = read_entry(&path) {
+            self.hits += 1;
+            return Ok(recognition);
+        }
+        let recognition = self.engine.recognize_scored(image)?;
+        // A failed write only costs a cache miss next time, so it isn't worth
+        // failing the OCR over
+        let _ = write_entry(&path, &recognition);
+        return Ok(recognition);
+    }
+}
+
+/// Entries are the confidence on the first line, empty if unknown, and the
+/// text after it
+fn read_entry(path: &Path) -> Option<Recognition> {
+    let entry = fs::read_to_string(path).ok()?;
+    let (confidence, text) = entry.split_once('\n')?;
+    return Some(Recognition {
+        text: text.to_owned(),
+        confidence: confidence.parse().ok(),
+    });
+}
+
+/// Writes through a temporary file, so concurrent runs sharing a cache never
+/// read a partial entry
+fn write_entry(path: &Path, recognition: &Recognition) -> io::Result<()> {
+    let confidence = recognition
+        .confidence
+        .map_or(String::new(), |confidence| confidence.to_string());
+    let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&temporary, format!("{confidence}\n{}", recognition.text))?;
+    return fs::rename(&temporary, path);
+}
+
+/// 128-bit FNV-1a. Unlike std's hasher it's guaranteed stable, which matters
+/// for keys stored on disk.
+struct Fnv128(u128);
+impl Fnv128 {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    fn new() -> Self {
+        return Self(Self::OFFSET_BASIS);
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u128;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn finish(&self) -> u128 {
+        return self.0;
+    }
+}
@@ -0,0 +1,32 @@
+use image::GrayImage;
+
+use super::{OcrEngine, OcrError, Recognition};
+use crate::cancel::CancellationToken;
+
+/// Fails with [`OcrError::Cancelled`] instead of recognizing anything once
+/// `token` is cancelled. Wrap the outermost engine, so retries and cache
+/// misses stop too.
+pub struct CancellableEngine<E: OcrEngine> {
+    engine: E,
+    token: CancellationToken,
+}
+impl<E: OcrEngine> CancellableEngine<E> {
+    pub fn new(engine: E, token: CancellationToken) -> Self {
+        return Self { engine, token };
+    }
+
+    pub fn inner(&self) -> &E {
+        return &self.engine;
+    }
+}
+impl<E: OcrEngine> OcrEngine for CancellableEngine<E> {
+    fn recognize(&mut self, image: &GrayImage) -> Result<String, OcrError> {
+        self.token.check()?;
+        return self.engine.recognize(image);
+    }
+
+    fn recognize_scored(&mut self, image: &GrayImage) -> Result<Recognition, OcrError> {
+        self.token.check()?;
+        return self.engine.recognize_scored(image);
+    }
+}
@@ -0,0 +1,64 @@
+use std::{
+    io::{Cursor, Write},
+    process::{Command, Stdio},
+    thread,
+};
+
+use image::{GrayImage, ImageFormat};
+
+use super::{OcrEngine, OcrError};
+
+/// Runs an external program for each image, writing it as PNG to stdin and
+/// reading the recognized text from stdout. For example
+/// `tesseract stdin stdout --psm 6`, or a wrapper script around PaddleOCR.
+#[derive(Debug, Clone)]
+pub struct CommandEngine {
+    program: String,
+    args: Vec<String>,
+}
+impl CommandEngine {
+    pub fn new(program: impl Into<String>, args: Vec<String>) -> Self {
+        return Self {
+            program: program.into(),
+            args,
+        };
+    }
+
+    /// Splits a command line on whitespace. There's no quoting support, so
+    /// use a wrapper script for anything more involved.
+    pub fn from_command_line(command_line: &str) -> Option<Self> {
+        let mut parts = command_line.split_whitespace().map(String::from);
+        let program = parts.next()?;
+        return Some(Self::new(program, parts.collect()));
+    }
+}
+impl OcrEngine for CommandEngine {
+    fn recognize(&mut self, image: &GrayImage) -> Result<String, OcrError> {
+        let mut png = Cursor::new(Vec::new());
+        image.write_to(&mut png, ImageFormat::Png)?;
+
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Feed stdin from another thread so a chatty child can't deadlock us
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let writer = thread::spawn(move || stdin.write_all(png.get_ref()));
+        let output = child.wait_with_output()?;
+        writer
+            .join()
+            .map_err(|_| OcrError::Command(String::from("stdin writer panicked")))??;
+
+        if !output.status.success() {
+            return Err(OcrError::Command(format!(
+                "{} exited with {}: {}",
+                self.program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            )));
+        }
+        return Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned());
+    }
+}
@@ -0,0 +1,26 @@
+use image::GrayImage;
+
+use super::{OcrEngine, OcrError};
+
+/// Placeholder for hosted OCR services. The intended contract is a `POST` of
+/// the PNG to `endpoint`, answered with the plain text, but there's no HTTP
+/// client in the dependency tree yet, so every call fails for now.
+#[derive(Debug, Clone)]
+pub struct HttpEngine {
+    endpoint: String,
+}
+impl HttpEngine {
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        return Self {
+            endpoint: endpoint.into(),
+        };
+    }
+}
+impl OcrEngine for HttpEngine {
+    fn recognize(&mut self, _image: &GrayImage) -> Result<String, OcrError> {
+        return Err(OcrError::Unsupported(format!(
+            "HTTP OCR backends are not implemented yet (endpoint: {})",
+            self.endpoint
+        )));
+    }
+}
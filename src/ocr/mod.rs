@@ -0,0 +1,167 @@
+//! OCR backends. The pipeline only talks to [`OcrEngine`], so Tesseract can be
+//! swapped for another engine (PaddleOCR behind a script, a hosted service...)
+//! without touching extraction.
+
+use image::{GrayAlphaImage, GrayImage, ImageError, imageops};
+use thiserror::Error;
+
+use crate::{
+    cancel::Cancelled,
+    music_notes::NoteDetection,
+    preprocess::{FlattenOptions, Placement, Region, flatten},
+};
+
+mod cache;
+mod cancel;
+mod command;
+mod http;
+mod retry;
+
+#[cfg(feature = "ocr")]
+pub use crate::tess::TesseractEngine;
+pub use cache::CachedEngine;
+pub use cancel::CancellableEngine;
+pub use command::CommandEngine;
+pub use http::HttpEngine;
+pub use retry::{RetryEngine, RetryPolicy, RetryRecord, Variant};
+
+#[derive(Error, Debug)]
+pub enum OcrError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Failed to encode image for OCR: {0}")]
+    Image(#[from] ImageError),
+    #[error("OCR command failed: {0}")]
+    Command(String),
+    #[error("OCR engine error: {0}")]
+    Engine(String),
+    #[error("{0}")]
+    Unsupported(String),
+    #[error(transparent)]
+    Cancelled(#[from] Cancelled),
+}
+
+/// Text recognized from an image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recognition {
+    pub text: String,
+    /// How sure the engine is of the text, 0-100, if it says
+    pub confidence: Option<f32>,
+}
+
+/// Turns a preprocessed subtitle bitmap (dark text on a light background)
+/// into text
+pub trait OcrEngine {
+    fn recognize(&mut self, image: &GrayImage) -> Result<String, OcrError>;
+
+    /// Like [`Self::recognize`], along with the engine's confidence for
+    /// engines that report one
+    fn recognize_scored(&mut self, image: &GrayImage) -> Result<Recognition, OcrError> {
+        return Ok(Recognition {
+            text: self.recognize(image)?,
+            confidence: None,
+        });
+    }
+}
+impl<T: OcrEngine + ?Sized> OcrEngine for Box<T> {
+    fn recognize(&mut self, image: &GrayImage) -> Result<String, OcrError> {
+        return (**self).recognize(image);
+    }
+
+    fn recognize_scored(&mut self, image: &GrayImage) -> Result<Recognition, OcrError> {
+        return (**self).recognize_scored(image);
+    }
+}
+
+/// Limits on what Tesseract recognizes. Left alone, it readily turns noise
+/// on a bitmap into CJK characters, even on English tracks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OcrConstraints {
+    /// Only these characters are recognized
+    pub whitelist: Option<String>,
+    /// These characters are never recognized. Defaults to ``|\/`_~!``, which
+    /// specks and edges tend to come out as.
+    pub blacklist: Option<String>,
+    /// Tesseract's page segmentation mode, 0-13. Defaults to 6, a single
+    /// block of text.
+    pub page_seg_mode: Option<u8>,
+}
+
+/// Characters in a named set, for use as a whitelist. `latin1` is printable
+/// ASCII and Latin-1 plus music notes, which covers most Western European
+/// languages.
+pub fn charset(name: &str) -> Option<String> {
+    return match name {
+        "latin1" => Some(
+            (' '..='~')
+                .chain('\u{a0}'..='\u{ff}')
+                .chain(['♪', '♫'])
+                .collect(),
+        ),
+        _ => None,
+    };
+}
+
+/// What to do with subtitles made of several separately placed regions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RegionPolicy {
+    /// One cue, with each region's text on its own line(s), top to bottom
+    #[default]
+    Merge,
+    /// One cue per region
+    Separate,
+    /// One cue per region, tagging ones in the top half of the screen with
+    /// `{\an8}` so players show them at the top
+    Position,
+}
+
+/// Recognizes a subtitle bitmap, flattened according to `options`. Music
+/// notes are found and put back separately, since OCR engines only mangle them.
+pub fn recognize_subtitle<E: OcrEngine + ?Sized>(
+    engine: &mut E,
+    image: &GrayAlphaImage,
+    options: FlattenOptions,
+) -> Result<String, OcrError> {
+    let notes = NoteDetection::detect(image);
+    if notes.notes.is_empty() {
+        return engine.recognize(&flatten(image, options));
+    }
+    let mut image = image.clone();
+    notes.erase(&mut image);
+    return Ok(notes.reinsert(&engine.recognize(&flatten(&image, options))?));
+}
+
+/// Recognizes each region of a subtitle on its own, so text from different
+/// parts of the screen isn't run together, and combines the results according
+/// to `policy`. Returns the text of each cue to create.
+pub fn recognize_regions<E: OcrEngine + ?Sized>(
+    engine: &mut E,
+    image: &GrayAlphaImage,
+    regions: &[Region],
+    placement: Option<Placement>,
+    policy: RegionPolicy,
+    options: FlattenOptions,
+) -> Result<Vec<String>, OcrError> {
+    if regions.len() < 2 {
+        return Ok(vec![recognize_subtitle(engine, image, options)?]);
+    }
+    let mut regions = regions.to_vec();
+    regions.sort_by_key(|region| (region.y, region.x));
+    let mut texts = Vec::new();
+    for region in regions.iter() {
+        let part = imageops::crop_imm(image, region.x, region.y, region.width, region.height);
+        let text = recognize_subtitle(engine, &part.to_image(), options)?;
+        if text.trim().is_empty() {
+            continue;
+        }
+        let top = placement.is_some_and(|placement| placement.is_top(region.y, region.height));
+        texts.push(match policy {
+            RegionPolicy::Position if top => format!("{{\\an8}}{}", text.trim()),
+            _ => text.trim().to_owned(),
+        });
+    }
+    if policy == RegionPolicy::Merge {
+        return Ok(vec![texts.join("\n")]);
+    }
+    return Ok(texts);
+}
@@ -0,0 +1,125 @@
+use image::{GrayImage, Luma, imageops};
+
+use super::{OcrEngine, OcrError, Recognition};
+
+/// Alternate preprocessing for images the engine isn't sure about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// Light text on dark instead of the other way around
+    Inverted,
+    /// Scaled up by this factor, which helps with small text
+    Upscaled(u32),
+    /// Black and white, with pixels at least this bright becoming white
+    Threshold(u8),
+}
+impl Variant {
+    pub fn apply(&self, image: &GrayImage) -> GrayImage {
+        return match *self {
+            Variant::Inverted => {
+                let mut inverted = image.clone();
+                imageops::invert(&mut inverted);
+                inverted
+            }
+            Variant::Upscaled(factor) => imageops::resize(
+                image,
+                image.width() * factor,
+                image.height() * factor,
+                imageops::FilterType::CatmullRom,
+            ),
+            Variant::Threshold(threshold) => {
+                GrayImage::from_fn(image.width(), image.height(), |x, y| {
+                    return match image.get_pixel(x, y).0[0] >= threshold {
+                        true => Luma([255]),
+                        false => Luma([0]),
+                    };
+                })
+            }
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Images recognized with less confidence than this (0-100) are retried
+    pub threshold: f32,
+    /// Tried in order, keeping whichever result is most confident
+    pub variants: Vec<Variant>,
+}
+impl RetryPolicy {
+    pub fn new(threshold: f32) -> Self {
+        return Self {
+            threshold,
+            variants: vec![
+                Variant::Inverted,
+                Variant::Upscaled(2),
+                Variant::Threshold(128),
+            ],
+        };
+    }
+}
+
+/// What happened to an image that was retried
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryRecord {
+    /// Confidence of the first attempt
+    pub original: f32,
+    /// The variant whose text was kept, or `None` if none beat the original
+    pub winner: Option<Variant>,
+    pub confidence: f32,
+}
+
+/// Re-runs images recognized with low confidence through other preprocessing
+/// and keeps the most confident text. Engines that don't report confidence
+/// are never retried.
+pub struct RetryEngine<E: OcrEngine> {
+    engine: E,
+    policy: RetryPolicy,
+    records: Vec<RetryRecord>,
+}
+impl<E: OcrEngine> RetryEngine<E> {
+    pub fn new(engine: E, policy: RetryPolicy) -> Self {
+        return Self {
+            engine,
+            policy,
+            records: Vec::new(),
+        };
+    }
+
+    /// Every retry so far, in order, for diagnostics
+    pub fn records(&self) -> &[RetryRecord] {
+        return &self.records;
+    }
+}
+impl<E: OcrEngine> OcrEngine for RetryEngine<E> {
+    fn recognize(&mut self, image: &GrayImage) -> Result<String, OcrError> {
+        return Ok(self.recognize_scored(image)?.text);
+    }
+
+    fn recognize_scored(&mut self, image: &GrayImage) -> Result<Recognition, OcrError> {
+        let mut best = self.engine.recognize_scored(image)?;
+        let Some(original) = best.confidence else {
+            return Ok(best);
+        };
+        if original >= self.policy.threshold {
+            return Ok(best);
+        }
+        let mut record = RetryRecord {
+            original,
+            winner: None,
+            confidence: original,
+        };
+        for variant in self.policy.variants.iter() {
+            let retry = self.engine.recognize_scored(&variant.apply(image))?;
+            let Some(confidence) = retry.confidence else {
+                continue;
+            };
+            if confidence > record.confidence {
+                record.winner = Some(*variant);
+                record.confidence = confidence;
+                best = retry;
+            }
+        }
+        self.records.push(record);
+        return Ok(best);
+    }
+}
@@ -0,0 +1,464 @@
+//! Image transformations applied to decoded subtitles before OCR/preview.
+
+use std::{fmt, str::FromStr};
+
+use image::{GrayAlphaImage, GrayImage, Luma, LumaA};
+use serde::{Deserialize, Serialize};
+
+use crate::indexed::IndexedImage;
+
+/// Crops an image down to the bounding box of its non-transparent pixels
+pub fn crop_image(image: &GrayAlphaImage) -> GrayAlphaImage {
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let pixel = image.get_pixel(x, y);
+            if pixel.0[1] > 0 {
+                match bounds {
+                    Some((ref mut x1, _y1, ref mut x2, ref mut y2)) => {
+                        if *x1 > x {
+                            *x1 = x;
+                        }
+                        if *x2 < x {
+                            *x2 = x;
+                        }
+                        // y1 not needed due to scanning semantics
+                        if *y2 < y {
+                            *y2 = y;
+                        }
+                    }
+                    None => {
+                        bounds = Some((x, y, x, y));
+                    }
+                }
+            }
+        }
+    }
+    match bounds {
+        None => {
+            return GrayAlphaImage::new(0, 0);
+        }
+        Some((x1, y1, x2, y2)) => {
+            let mut new_image = GrayAlphaImage::new(x2 + 1 - x1, y2 + 1 - y1);
+            for (new_y, y) in (y1..=y2).enumerate() {
+                for (new_x, x) in (x1..=x2).enumerate() {
+                    new_image.put_pixel(new_x as _, new_y as _, image.get_pixel(x, y).clone());
+                }
+            }
+            return new_image;
+        }
+    }
+}
+
+/// A rectangle within a subtitle bitmap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+impl Region {
+    /// The overlap of two regions, if any
+    pub fn intersect(&self, other: &Region) -> Option<Region> {
+        let x1 = self.x.max(other.x);
+        let y1 = self.y.max(other.y);
+        let x2 = (self.x + self.width).min(other.x + other.width);
+        let y2 = (self.y + self.height).min(other.y + other.height);
+        if x1 >= x2 || y1 >= y2 {
+            return None;
+        }
+        return Some(Region {
+            x: x1,
+            y: y1,
+            width: x2 - x1,
+            height: y2 - y1,
+        });
+    }
+}
+
+/// Where a cropped subtitle image sits on the video frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Placement {
+    pub x: u32,
+    pub y: u32,
+    pub screen_width: u32,
+    pub screen_height: u32,
+}
+impl Placement {
+    /// Whether rows `y..y + height` of the image are centered in the top
+    /// half of the screen
+    pub fn is_top(&self, y: u32, height: u32) -> bool {
+        return (self.y + y + height / 2) * 2 < self.screen_height;
+    }
+}
+
+/// Bounding box of the non-transparent pixels within `region`, or `None` if
+/// it has nothing visible
+pub fn content_bounds(image: &GrayAlphaImage, region: Region) -> Option<Region> {
+    let full = Region {
+        x: 0,
+        y: 0,
+        width: image.width(),
+        height: image.height(),
+    };
+    let region = region.intersect(&full)?;
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for y in region.y..region.y + region.height {
+        for x in region.x..region.x + region.width {
+            if image.get_pixel(x, y).0[1] == 0 {
+                continue;
+            }
+            bounds = Some(match bounds {
+                Some((x1, y1, x2, y2)) => (x1.min(x), y1.min(y), x2.max(x), y2.max(y)),
+                None => (x, y, x, y),
+            });
+        }
+    }
+    let (x1, y1, x2, y2) = bounds?;
+    return Some(Region {
+        x: x1,
+        y: y1,
+        width: x2 + 1 - x1,
+        height: y2 + 1 - y1,
+    });
+}
+
+/// What a subtitle bitmap's alpha is flattened against for OCR. Engines read
+/// text best with strong contrast and without the halo antialiasing leaves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Background {
+    /// Luma as-is, ignoring alpha, which leaves transparent pixels black
+    #[default]
+    Keep,
+    /// Composited over black
+    Black,
+    /// Composited over white
+    White,
+    /// Composited over black or white, whichever contrasts more with the
+    /// text's own brightness
+    Estimated,
+    /// The alpha channel alone as a mask: opaque pixels become white and the
+    /// rest black, with `binarize` (128 by default) as the alpha threshold
+    Alpha,
+    /// Only the pixels whose palette entry is glyph fill, white on black (see
+    /// [`segment`]). Images without their palette indices are flattened the
+    /// same as [`Background::Alpha`].
+    Palette,
+}
+impl fmt::Display for Background {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f.write_str(match self {
+            Background::Keep => "keep",
+            Background::Black => "black",
+            Background::White => "white",
+            Background::Estimated => "auto",
+            Background::Alpha => "alpha",
+            Background::Palette => "palette",
+        });
+    }
+}
+impl FromStr for Background {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        return match value {
+            "keep" => Ok(Background::Keep),
+            "black" => Ok(Background::Black),
+            "white" => Ok(Background::White),
+            "auto" => Ok(Background::Estimated),
+            "alpha" => Ok(Background::Alpha),
+            "palette" => Ok(Background::Palette),
+            _ => Err(format!(
+                "Invalid background: {value} (expected keep, black, white, auto, alpha or palette)"
+            )),
+        };
+    }
+}
+impl Background {
+    /// Every background, for comparing them
+    pub const ALL: [Background; 6] = [
+        Background::Keep,
+        Background::Black,
+        Background::White,
+        Background::Estimated,
+        Background::Alpha,
+        Background::Palette,
+    ];
+}
+
+/// How subtitle bitmaps are flattened into the grayscale images OCR engines
+/// are given. The default keeps the luma of every pixel as-is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlattenOptions {
+    /// Pixels less opaque than this become background, which takes
+    /// antialiasing and faint shadows out of the picture
+    pub min_alpha: u8,
+    /// Makes the image pure black and white, with pixels at least this
+    /// bright becoming white
+    pub binarize: Option<u8>,
+    pub background: Background,
+    /// Removes the outline around glyphs first, see [`strip_outline`]
+    pub strip_outline: bool,
+}
+
+/// Drops the alpha channel according to `options`
+pub fn flatten(image: &GrayAlphaImage, options: FlattenOptions) -> GrayImage {
+    let stripped;
+    let image = match options.strip_outline {
+        true => {
+            stripped = strip_outline(image);
+            &stripped
+        }
+        false => image,
+    };
+    if matches!(options.background, Background::Alpha | Background::Palette) {
+        let threshold = options.binarize.unwrap_or(128).max(options.min_alpha);
+        return GrayImage::from_fn(image.width(), image.height(), |x, y| {
+            return match image.get_pixel(x, y).0[1] >= threshold {
+                true => Luma([255]),
+                false => Luma([0]),
+            };
+        });
+    }
+    let background = match options.background {
+        Background::Estimated => estimate_background(image, options.min_alpha),
+        background => background,
+    };
+    let background_luma = match background {
+        Background::White => 255,
+        _ => 0,
+    };
+    return GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        let [luma, alpha] = image.get_pixel(x, y).0;
+        if alpha < options.min_alpha {
+            return Luma([background_luma]);
+        }
+        let luma = match background {
+            Background::Black | Background::White => {
+                let blended =
+                    luma as u32 * alpha as u32 + background_luma as u32 * (255 - alpha as u32);
+                ((blended + 127) / 255) as u8
+            }
+            _ => luma,
+        };
+        return match options.binarize {
+            Some(threshold) if luma >= threshold => Luma([255]),
+            Some(_) => Luma([0]),
+            None => Luma([luma]),
+        };
+    });
+}
+
+/// White for dark text and black for light text, going by the average
+/// brightness of the pixels at least `min_alpha` opaque
+fn estimate_background(image: &GrayAlphaImage, min_alpha: u8) -> Background {
+    let (mut total, mut weight) = (0u64, 0u64);
+    for pixel in image.pixels() {
+        let [luma, alpha] = pixel.0;
+        if alpha > 0 && alpha >= min_alpha {
+            total += luma as u64 * alpha as u64;
+            weight += alpha as u64;
+        }
+    }
+    if weight > 0 && total / weight < 128 {
+        return Background::White;
+    }
+    return Background::Black;
+}
+
+/// Pixels less opaque than this are background to [`strip_outline`]
+const OUTLINE_MIN_ALPHA: u8 = 64;
+/// How far apart (in luma) the clusters' averages have to be to count as
+/// a fill and an outline
+const OUTLINE_MIN_CONTRAST: f64 = 48.0;
+
+/// Removes the border drawn around glyphs (usually dark around white text),
+/// which binarization otherwise merges into the letters. Visible pixels are
+/// split into two brightness clusters; the one that touches the transparent
+/// background more is the outline, and is flood filled away from the
+/// background inwards, leaving the glyph cores. Images without two distinct
+/// clusters are returned as they are.
+pub fn strip_outline(image: &GrayAlphaImage) -> GrayAlphaImage {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let visible =
+        |x: usize, y: usize| image.get_pixel(x as u32, y as u32).0[1] >= OUTLINE_MIN_ALPHA;
+    let mut histogram = [0u64; 256];
+    for pixel in image
+        .pixels()
+        .filter(|pixel| pixel.0[1] >= OUTLINE_MIN_ALPHA)
+    {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+    let Some(threshold) = otsu_threshold(&histogram) else {
+        return image.clone();
+    };
+    let bright = |x: usize, y: usize| image.get_pixel(x as u32, y as u32).0[0] >= threshold;
+
+    let outline_is_bright = outline_is_bright(width, height, visible, bright);
+
+    let mut stripped = image.clone();
+    let mut removed = vec![false; width * height];
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            if !visible(x, y) {
+                stack.push((x, y));
+            }
+        }
+    }
+    while let Some((x, y)) = stack.pop() {
+        for (x, y) in neighbors(x, y, width, height) {
+            if removed[y * width + x] || !visible(x, y) || bright(x, y) != outline_is_bright {
+                continue;
+            }
+            removed[y * width + x] = true;
+            stripped.put_pixel(x as u32, y as u32, LumaA([0, 0]));
+            stack.push((x, y));
+        }
+    }
+    return stripped;
+}
+
+/// The pixels next to `(x, y)` within a `width` by `height` image
+fn neighbors(
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    return [
+        (x.wrapping_sub(1), y),
+        (x + 1, y),
+        (x, y.wrapping_sub(1)),
+        (x, y + 1),
+    ]
+    .into_iter()
+    .filter(move |(x, y)| *x < width && *y < height);
+}
+
+/// Whether the bright cluster is the one bordering the background more
+fn outline_is_bright(
+    width: usize,
+    height: usize,
+    visible: impl Fn(usize, usize) -> bool,
+    bright: impl Fn(usize, usize) -> bool,
+) -> bool {
+    let (mut bright_edges, mut dark_edges) = (0, 0);
+    for y in 0..height {
+        for x in 0..width {
+            if visible(x, y) && neighbors(x, y, width, height).any(|(x, y)| !visible(x, y)) {
+                match bright(x, y) {
+                    true => bright_edges += 1,
+                    false => dark_edges += 1,
+                }
+            }
+        }
+    }
+    return bright_edges > dark_edges;
+}
+
+/// The luma splitting `histogram` into two clusters with the most variance
+/// between them (Otsu's method), if they're far enough apart to be separate
+fn otsu_threshold(histogram: &[u64; 256]) -> Option<u8> {
+    let total: u64 = histogram.iter().sum();
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(luma, count)| luma as f64 * *count as f64)
+        .sum();
+    let (mut below, mut below_sum) = (0u64, 0.0);
+    let mut best: Option<(f64, u8, f64)> = None;
+    for threshold in 1..256 {
+        below += histogram[threshold - 1];
+        below_sum += (threshold - 1) as f64 * histogram[threshold - 1] as f64;
+        let above = total - below;
+        if below == 0 || above == 0 {
+            continue;
+        }
+        let dark_mean = below_sum / below as f64;
+        let bright_mean = (sum - below_sum) / above as f64;
+        let variance = below as f64 * above as f64 * (bright_mean - dark_mean).powi(2);
+        if best.is_none_or(|(best, _, _)| variance > best) {
+            best = Some((variance, threshold as u8, bright_mean - dark_mean));
+        }
+    }
+    let (_, threshold, contrast) = best?;
+    return (contrast >= OUTLINE_MIN_CONTRAST).then_some(threshold);
+}
+
+/// What a palette entry draws, as far as [`classify_palette`] can tell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteRole {
+    /// Transparent, or too faint to matter
+    Background,
+    /// The letters themselves
+    Fill,
+    /// The border around the letters
+    Outline,
+    /// Like the outline, but translucent
+    Shadow,
+}
+
+/// Outline-colored entries less opaque than this are a drop shadow
+const SHADOW_MAX_ALPHA: u8 = 192;
+
+/// Classifies every palette index of `image`. The entries it uses are split
+/// into two brightness clusters the way [`strip_outline`] splits pixels, but
+/// since each index is one exact color, antialiasing can't blur where one
+/// cluster ends. Without two distinct clusters, everything visible is fill.
+pub fn classify_palette(image: &IndexedImage) -> [PaletteRole; 256] {
+    let (width, height) = (image.width as usize, image.height as usize);
+    let colors: [_; 256] = std::array::from_fn(|index| image.color(index as u8));
+    let luma: [u8; 256] = std::array::from_fn(|index| {
+        let [r, g, b, _] = colors[index].0;
+        return ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114 + 500) / 1000) as u8;
+    });
+    let visible_index = |index: u8| colors[index as usize].0[3] >= OUTLINE_MIN_ALPHA;
+    let mut histogram = [0u64; 256];
+    for index in image
+        .indices
+        .iter()
+        .copied()
+        .filter(|index| visible_index(*index))
+    {
+        histogram[luma[index as usize] as usize] += 1;
+    }
+    let threshold = otsu_threshold(&histogram);
+
+    let index = |x: usize, y: usize| image.indices[y * width + x];
+    let bright_index =
+        |index: u8| threshold.is_some_and(|threshold| luma[index as usize] >= threshold);
+    let outline_is_bright = threshold.is_some()
+        && outline_is_bright(
+            width,
+            height,
+            |x, y| visible_index(index(x, y)),
+            |x, y| bright_index(index(x, y)),
+        );
+    return std::array::from_fn(|entry| {
+        let entry = entry as u8;
+        if !visible_index(entry) {
+            return PaletteRole::Background;
+        }
+        if threshold.is_none() || bright_index(entry) != outline_is_bright {
+            return PaletteRole::Fill;
+        }
+        if colors[entry as usize].0[3] < SHADOW_MAX_ALPHA {
+            return PaletteRole::Shadow;
+        }
+        return PaletteRole::Outline;
+    });
+}
+
+/// A mask of `image`'s glyph fill, going by [`classify_palette`]: fill is
+/// opaque white, and everything else is transparent
+pub fn segment(image: &IndexedImage) -> GrayAlphaImage {
+    let roles = classify_palette(image);
+    return GrayAlphaImage::from_fn(image.width, image.height, |x, y| {
+        return match roles[image.index(x, y) as usize] {
+            PaletteRole::Fill => LumaA([255, 255]),
+            _ => LumaA([0, 0]),
+        };
+    });
+}
@@ -0,0 +1,135 @@
+//! Minimal MPEG-2 program stream demuxing, enough to pull the private stream 1
+//! packets (where DVD subpictures live) out of a VobSub `.sub` file.
+
+use thiserror::Error;
+
+const START_CODE_PREFIX: [u8; 3] = [0, 0, 1];
+const PACK_HEADER: u8 = 0xBA;
+const PROGRAM_END: u8 = 0xB9;
+pub const PRIVATE_STREAM_1: u8 = 0xBD;
+
+#[derive(Error, Debug, Clone)]
+pub enum ProgramStreamError {
+    #[error("Truncated program stream packet at offset {0}.")]
+    Truncated(usize),
+    #[error("Invalid PES header at offset {0}.")]
+    InvalidPesHeader(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct PesPacket<'a> {
+    pub stream_id: u8,
+    /// Presentation timestamp, in 90kHz ticks
+    pub pts: Option<u64>,
+    pub payload: &'a [u8],
+}
+
+fn read_pts(bytes: &[u8]) -> u64 {
+    return (bytes[0] as u64 >> 1 & 0x7) << 30
+        | (bytes[1] as u64) << 22
+        | (bytes[2] as u64 >> 1) << 15
+        | (bytes[3] as u64) << 7
+        | bytes[4] as u64 >> 1;
+}
+
+/// Iterates over the PES packets of a program stream, skipping pack headers.
+/// Garbage between packets is skipped up to the next start code.
+pub struct ProgramStream<'a> {
+    data: &'a [u8],
+    cursor: usize,
+}
+impl<'a> ProgramStream<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        return Self { data, cursor: 0 };
+    }
+
+    fn next_packet(&mut self) -> Result<Option<PesPacket<'a>>, ProgramStreamError> {
+        loop {
+            let Some(found) = self.data[self.cursor..]
+                .windows(4)
+                .position(|window| window[..3] == START_CODE_PREFIX)
+            else {
+                self.cursor = self.data.len();
+                return Ok(None);
+            };
+            let start = self.cursor + found;
+            let stream_id = self.data[start + 3];
+            match stream_id {
+                PACK_HEADER => {
+                    let header = &self.data[start..];
+                    let length = match header.get(4) {
+                        // MPEG-2: 14 bytes plus stuffing
+                        Some(marker) if marker >> 6 == 0b01 => {
+                            let stuffing =
+                                header.get(13).ok_or(ProgramStreamError::Truncated(start))?;
+                            14 + (stuffing & 0x7) as usize
+                        }
+                        // MPEG-1
+                        Some(_) => 12,
+                        None => return Err(ProgramStreamError::Truncated(start)),
+                    };
+                    self.cursor = start + length;
+                }
+                PROGRAM_END => self.cursor = start + 4,
+                // Everything else is a PES packet with a length field. IDs
+                // below 0xB9 aren't valid here, so resync past them.
+                0xBB..=0xFF => {
+                    let length = self
+                        .data
+                        .get(start + 4..start + 6)
+                        .map(|length| u16::from_be_bytes([length[0], length[1]]) as usize)
+                        .ok_or(ProgramStreamError::Truncated(start))?;
+                    let end = start + 6 + length;
+                    let body = self
+                        .data
+                        .get(start + 6..end)
+                        .ok_or(ProgramStreamError::Truncated(start))?;
+                    self.cursor = end;
+                    if stream_id != PRIVATE_STREAM_1 {
+                        continue;
+                    }
+                    return Ok(Some(parse_pes_body(stream_id, body, start)?));
+                }
+                _ => self.cursor = start + 3,
+            }
+        }
+    }
+}
+impl<'a> Iterator for ProgramStream<'a> {
+    type Item = Result<PesPacket<'a>, ProgramStreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.next_packet();
+        if result.is_err() {
+            // Don't keep returning the same error
+            self.cursor = self.data.len();
+        }
+        return result.transpose();
+    }
+}
+
+/// Parses the MPEG-2 PES header of a packet's body (everything after the length)
+fn parse_pes_body(
+    stream_id: u8,
+    body: &[u8],
+    offset: usize,
+) -> Result<PesPacket<'_>, ProgramStreamError> {
+    if body.len() < 3 || body[0] >> 6 != 0b10 {
+        return Err(ProgramStreamError::InvalidPesHeader(offset));
+    }
+    let has_pts = body[1] & 0x80 != 0;
+    let header_length = body[2] as usize;
+    let payload = body
+        .get(3 + header_length..)
+        .ok_or(ProgramStreamError::InvalidPesHeader(offset))?;
+    let pts = match has_pts {
+        true if header_length >= 5 => Some(read_pts(&body[3..8])),
+        true => return Err(ProgramStreamError::InvalidPesHeader(offset)),
+        false => None,
+    };
+    return Ok(PesPacket {
+        stream_id,
+        pts,
+        payload,
+    });
+}
@@ -0,0 +1,139 @@
+//! Reading speed checks, for spotting cues that are on screen too briefly for
+//! their text. OCR'd tracks inherit whatever timing the disc had, and merging
+//! or trimming cues can make it worse.
+
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+use crate::srt::{SrtCue, format_timestamp, strip_tags};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadingSpeedLimits {
+    /// Characters per second, counting spaces but not line breaks or tags
+    pub max_cps: f64,
+    /// Words per minute
+    pub max_wpm: f64,
+    /// Nanoseconds
+    pub min_duration: u64,
+}
+impl Default for ReadingSpeedLimits {
+    /// Common limits for adult programming
+    fn default() -> Self {
+        return Self {
+            max_cps: 20.0,
+            max_wpm: 180.0,
+            min_duration: 833_333_333,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeedIssue {
+    Cps,
+    Wpm,
+    TooShort,
+}
+impl fmt::Display for SpeedIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f.write_str(match self {
+            SpeedIssue::Cps => "CPS too high",
+            SpeedIssue::Wpm => "WPM too high",
+            SpeedIssue::TooShort => "duration too short",
+        });
+    }
+}
+
+/// The reading speed of one cue
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueSpeed {
+    /// Numbered from 1, skipping blank cues like [`crate::srt::write_srt`]
+    pub index: usize,
+    /// Nanoseconds
+    pub start: u64,
+    /// Nanoseconds
+    pub end: u64,
+    pub text: String,
+    pub characters: usize,
+    pub words: usize,
+    pub issues: Vec<SpeedIssue>,
+}
+impl CueSpeed {
+    fn seconds(&self) -> f64 {
+        return self.end.saturating_sub(self.start) as f64 / 1e9;
+    }
+
+    /// Infinite for cues without a duration
+    pub fn cps(&self) -> f64 {
+        return self.characters as f64 / self.seconds();
+    }
+
+    /// Infinite for cues without a duration
+    pub fn wpm(&self) -> f64 {
+        return self.words as f64 * 60.0 / self.seconds();
+    }
+}
+
+/// Measures every non-blank cue against `limits`
+pub fn analyze(cues: &[SrtCue], limits: &ReadingSpeedLimits) -> Vec<CueSpeed> {
+    let mut speeds = Vec::new();
+    for cue in cues.iter().filter(|cue| !cue.text.trim().is_empty()) {
+        let text = strip_tags(&cue.text);
+        let mut speed = CueSpeed {
+            index: speeds.len() + 1,
+            start: cue.start,
+            end: cue.end,
+            characters: text.lines().map(|line| line.trim().chars().count()).sum(),
+            words: text.split_whitespace().count(),
+            text: cue.text.clone(),
+            issues: Vec::new(),
+        };
+        if speed.cps() > limits.max_cps {
+            speed.issues.push(SpeedIssue::Cps);
+        }
+        if speed.wpm() > limits.max_wpm {
+            speed.issues.push(SpeedIssue::Wpm);
+        }
+        if cue.end.saturating_sub(cue.start) < limits.min_duration {
+            speed.issues.push(SpeedIssue::TooShort);
+        }
+        speeds.push(speed);
+    }
+    return speeds;
+}
+
+/// Writes a CSV report with one row per cue, leaving `issues` empty for the
+/// ones within the limits
+pub fn write_report<W: Write>(mut out: W, speeds: &[CueSpeed]) -> io::Result<()> {
+    writeln!(
+        out,
+        "index,start,end,duration,characters,words,cps,wpm,issues,text"
+    )?;
+    for speed in speeds {
+        let issues: Vec<String> = speed.issues.iter().map(ToString::to_string).collect();
+        writeln!(
+            out,
+            "{},{},{},{:.3},{},{},{:.1},{:.0},{},{}",
+            speed.index,
+            csv_field(&format_timestamp(speed.start)),
+            csv_field(&format_timestamp(speed.end)),
+            speed.seconds(),
+            speed.characters,
+            speed.words,
+            speed.cps(),
+            speed.wpm(),
+            csv_field(&issues.join("; ")),
+            csv_field(&speed.text),
+        )?;
+    }
+    return Ok(());
+}
+
+/// Quotes `value` if it contains anything CSV treats specially
+pub(crate) fn csv_field(value: &str) -> String {
+    if !value.contains([',', '"', '\n', '\r']) {
+        return value.to_owned();
+    }
+    return format!("\"{}\"", value.replace('"', "\"\""));
+}
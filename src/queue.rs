@@ -0,0 +1,390 @@
+//! A job queue for running many extractions at once, shared by `serve` and
+//! `watch`. Decoding is mostly waiting on storage and OCR mostly on the CPU,
+//! so each has its own limit on how many jobs are in it at once: a job takes
+//! a decode slot when it starts and trades it for an OCR slot once its events
+//! are read. Jobs start in order of priority, then submission.
+//!
+//! With a state file, the queue is written out whenever a job is added or
+//! finishes, and jobs that were queued or running when the process died are
+//! queued again when it's reopened.
+
+use std::{
+    cmp::Reverse,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Condvar, Mutex,
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use thiserror::Error;
+
+use crate::cancel::CancellationToken;
+
+/// Jobs decoding at once by default. Inputs often share a disk or network
+/// link, which more readers only slow down.
+pub const DEFAULT_DECODE_JOBS: usize = 2;
+
+#[derive(Error, Debug)]
+pub enum QueueError {
+    #[error("Failed to access the queue state: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid queue state: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// A job going over one of its [`JobLimits`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum LimitExceeded {
+    #[error("The input is {size} bytes, more than the limit of {limit}")]
+    InputSize { size: u64, limit: u64 },
+    #[error("The track has more than {limit} subtitles")]
+    Events { limit: usize },
+    #[error("The job took longer than {}s", .limit / 1_000_000_000)]
+    Timeout { limit: u64 },
+}
+
+/// How many jobs may be in each stage at once. 0 is no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueLimits {
+    pub decode: usize,
+    pub ocr: usize,
+}
+impl Default for QueueLimits {
+    /// [`DEFAULT_DECODE_JOBS`], and OCR on every core
+    fn default() -> Self {
+        return Self {
+            decode: DEFAULT_DECODE_JOBS,
+            ocr: thread::available_parallelism().map_or(1, Into::into),
+        };
+    }
+}
+
+/// Limits on what a single job may use. Jobs going over them fail.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobLimits {
+    /// Bytes
+    pub max_input_size: Option<u64>,
+    pub max_events: Option<usize>,
+    /// Nanoseconds from when the job starts, not counting time queued
+    pub timeout: Option<u64>,
+}
+
+/// Which of the [`QueueLimits`] a running job counts against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Decode,
+    Ocr,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedJob<T> {
+    pub id: u64,
+    /// Higher starts first
+    pub priority: i32,
+    pub limits: JobLimits,
+    /// What the caller needs to run the job, e.g. its input
+    pub spec: T,
+}
+
+/// The state file's contents
+#[derive(Serialize, Deserialize)]
+struct Persisted<T> {
+    next_id: u64,
+    jobs: Vec<QueuedJob<T>>,
+}
+
+struct State<T> {
+    next_id: u64,
+    queued: Vec<QueuedJob<T>>,
+    running: Vec<QueuedJob<T>>,
+    decoding: usize,
+    recognizing: usize,
+}
+
+struct Shared<T> {
+    limits: QueueLimits,
+    path: Option<PathBuf>,
+    state: Mutex<State<T>>,
+    /// Signalled whenever a job is added or a slot is freed
+    changed: Condvar,
+}
+impl<T: Serialize + Clone> Shared<T> {
+    /// Writes the queued and running jobs to the state file, if there is one
+    fn persist(&self, state: &State<T>) -> Result<(), QueueError> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+        let mut jobs: Vec<QueuedJob<T>> =
+            state.queued.iter().chain(&state.running).cloned().collect();
+        jobs.sort_by_key(|job| job.id);
+        let persisted = Persisted {
+            next_id: state.next_id,
+            jobs,
+        };
+        // Replaced in one go, so a crash mid-write leaves the old state
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        let mut file = BufWriter::new(File::create(&temporary)?);
+        serde_json::to_writer_pretty(&mut file, &persisted)?;
+        file.into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        fs::rename(&temporary, path)?;
+        return Ok(());
+    }
+}
+
+/// What a [`JobTicket`] needs of its queue, without the job type
+trait Slots: Send + Sync {
+    fn start_ocr(&self);
+    fn finish(&self, id: u64, stage: Stage);
+}
+impl<T: Serialize + Clone + Send> Slots for Shared<T> {
+    fn start_ocr(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.decoding -= 1;
+        self.changed.notify_all();
+        while self.limits.ocr > 0 && state.recognizing >= self.limits.ocr {
+            state = self.changed.wait(state).unwrap();
+        }
+        state.recognizing += 1;
+    }
+
+    fn finish(&self, id: u64, stage: Stage) {
+        let mut state = self.state.lock().unwrap();
+        match stage {
+            Stage::Decode => state.decoding -= 1,
+            Stage::Ocr => state.recognizing -= 1,
+        }
+        state.running.retain(|job| job.id != id);
+        // Can't be reported from a drop; the next change writes it again
+        let _ = self.persist(&state);
+        self.changed.notify_all();
+    }
+}
+
+/// A priority queue of jobs, limiting how many run in each [`Stage`].
+/// Clones refer to the same queue.
+pub struct JobQueue<T> {
+    shared: Arc<Shared<T>>,
+}
+impl<T> Clone for JobQueue<T> {
+    fn clone(&self) -> Self {
+        return Self {
+            shared: self.shared.clone(),
+        };
+    }
+}
+impl<T: Serialize + DeserializeOwned + Clone + Send + 'static> JobQueue<T> {
+    /// An empty queue, kept in memory only
+    pub fn new(limits: QueueLimits) -> Self {
+        return Self::with_state(limits, None, 0, Vec::new());
+    }
+
+    /// A queue kept in the state file at `path`, starting with the jobs left
+    /// in it, if it exists
+    pub fn open(path: &Path, limits: QueueLimits) -> Result<Self, QueueError> {
+        let persisted = match File::open(path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Persisted {
+                next_id: 0,
+                jobs: Vec::new(),
+            },
+            Err(err) => return Err(err.into()),
+        };
+        return Ok(Self::with_state(
+            limits,
+            Some(path.to_owned()),
+            persisted.next_id,
+            persisted.jobs,
+        ));
+    }
+
+    fn with_state(
+        limits: QueueLimits,
+        path: Option<PathBuf>,
+        next_id: u64,
+        queued: Vec<QueuedJob<T>>,
+    ) -> Self {
+        return Self {
+            shared: Arc::new(Shared {
+                limits,
+                path,
+                state: Mutex::new(State {
+                    next_id,
+                    queued,
+                    running: Vec::new(),
+                    decoding: 0,
+                    recognizing: 0,
+                }),
+                changed: Condvar::new(),
+            }),
+        };
+    }
+
+    /// Adds a job, returning its ID. IDs start at 1 and aren't reused, even
+    /// across restarts with the same state file.
+    pub fn push(&self, spec: T, priority: i32, limits: JobLimits) -> Result<u64, QueueError> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.queued.push(QueuedJob {
+            id,
+            priority,
+            limits,
+            spec,
+        });
+        if let Err(err) = self.shared.persist(&state) {
+            state.queued.pop();
+            return Err(err);
+        }
+        self.shared.changed.notify_all();
+        return Ok(id);
+    }
+
+    /// Removes a job that hasn't started yet
+    pub fn remove(&self, id: u64) -> Option<QueuedJob<T>> {
+        let mut state = self.shared.state.lock().unwrap();
+        let index = state.queued.iter().position(|job| job.id == id)?;
+        let job = state.queued.remove(index);
+        let _ = self.shared.persist(&state);
+        return Some(job);
+    }
+
+    /// The jobs that haven't started, in the order they'll start in
+    pub fn queued(&self) -> Vec<QueuedJob<T>> {
+        let state = self.shared.state.lock().unwrap();
+        let mut queued = state.queued.clone();
+        queued.sort_by_key(start_order);
+        return queued;
+    }
+
+    /// Waits for a job and a decode slot for it, and takes the job off the
+    /// queue. It counts as running until the ticket is dropped.
+    pub fn next(&self) -> (QueuedJob<T>, JobTicket) {
+        let shared = &self.shared;
+        let mut state = shared.state.lock().unwrap();
+        while state.queued.is_empty()
+            || (shared.limits.decode > 0 && state.decoding >= shared.limits.decode)
+        {
+            state = shared.changed.wait(state).unwrap();
+        }
+        let index = (0..state.queued.len())
+            .min_by_key(|&index| start_order(&state.queued[index]))
+            .unwrap();
+        let job = state.queued.remove(index);
+        state.running.push(job.clone());
+        state.decoding += 1;
+        drop(state);
+        let ticket = JobTicket::new(job.id, job.limits, shared.clone());
+        return (job, ticket);
+    }
+}
+
+/// Sorts jobs by priority, then by when they were added
+fn start_order<T>(job: &QueuedJob<T>) -> (Reverse<i32>, u64) {
+    return (Reverse(job.priority), job.id);
+}
+
+/// A running job's hold on its slot. Dropping it finishes the job, freeing
+/// the slot and taking the job out of the state file.
+pub struct JobTicket {
+    id: u64,
+    limits: JobLimits,
+    stage: Stage,
+    queue: Arc<dyn Slots>,
+    cancel: CancellationToken,
+    started: Instant,
+    /// Dropped with the ticket, which stops the timeout thread
+    _timer: Option<Sender<()>>,
+}
+impl JobTicket {
+    fn new(id: u64, limits: JobLimits, queue: Arc<dyn Slots>) -> Self {
+        let cancel = CancellationToken::new();
+        let timer = limits.timeout.map(|timeout| {
+            let (sender, stop) = mpsc::channel::<()>();
+            let cancel = cancel.clone();
+            thread::spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) =
+                    stop.recv_timeout(Duration::from_nanos(timeout))
+                {
+                    cancel.cancel();
+                }
+            });
+            return sender;
+        });
+        return Self {
+            id,
+            limits,
+            stage: Stage::Decode,
+            queue,
+            cancel,
+            started: Instant::now(),
+            _timer: timer,
+        };
+    }
+
+    pub fn id(&self) -> u64 {
+        return self.id;
+    }
+
+    pub fn stage(&self) -> Stage {
+        return self.stage;
+    }
+
+    pub fn limits(&self) -> &JobLimits {
+        return &self.limits;
+    }
+
+    /// Cancelled once the job runs past its timeout. Callers can cancel it
+    /// too.
+    pub fn cancellation(&self) -> CancellationToken {
+        return self.cancel.clone();
+    }
+
+    /// Gives back the decode slot and waits for an OCR slot. Does nothing if
+    /// the job already has one.
+    pub fn start_ocr(&mut self) {
+        if self.stage == Stage::Ocr {
+            return;
+        }
+        self.queue.start_ocr();
+        self.stage = Stage::Ocr;
+    }
+
+    pub fn check_input(&self, size: u64) -> Result<(), LimitExceeded> {
+        return match self.limits.max_input_size {
+            Some(limit) if size > limit => Err(LimitExceeded::InputSize { size, limit }),
+            _ => Ok(()),
+        };
+    }
+
+    /// Fails once more than the limit of events have been decoded
+    pub fn check_events(&self, events: usize) -> Result<(), LimitExceeded> {
+        return match self.limits.max_events {
+            Some(limit) if events > limit => Err(LimitExceeded::Events { limit }),
+            _ => Ok(()),
+        };
+    }
+
+    /// Fails once the job has run past its timeout
+    pub fn check_time(&self) -> Result<(), LimitExceeded> {
+        return match self.limits.timeout {
+            Some(limit) if self.started.elapsed() >= Duration::from_nanos(limit) => {
+                Err(LimitExceeded::Timeout { limit })
+            }
+            _ => Ok(()),
+        };
+    }
+}
+impl Drop for JobTicket {
+    fn drop(&mut self) {
+        self.queue.finish(self.id, self.stage);
+    }
+}
@@ -0,0 +1,408 @@
+//! Writes a copy of an MKV file with an extra `S_TEXT/UTF8` track holding the
+//! OCR'd subtitles. All original elements are copied as-is; the only things
+//! rewritten are the Tracks element (to add the new entry), the Clusters that
+//! receive subtitle blocks, and the SeekHead/Cues since their offsets move.
+//!
+//! Subtitle blocks are appended to the end of the cluster covering their start
+//! time, so the relative positions of existing blocks (referenced by Cues) stay
+//! the same.
+
+use std::{
+    collections::{HashMap, hash_map::RandomState},
+    hash::{BuildHasher, Hasher},
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+use thiserror::Error;
+
+use crate::{ebml::*, srt::SrtCue};
+
+/// Matroska's default when Info doesn't specify one
+const DEFAULT_TIMESTAMP_SCALE: u64 = 1_000_000;
+/// Space reserved at the start of the segment for the rewritten SeekHead
+const SEEK_HEAD_RESERVED: usize = 256;
+const TRACK_TYPE_SUBTITLE: u64 = 0x11;
+
+#[derive(Error, Debug)]
+pub enum RemuxError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Input is not a Matroska file.")]
+    NotMatroska,
+    #[error("Elements with unknown size are not supported for remuxing.")]
+    UnknownSize,
+}
+
+/// Description of the text track to add
+#[derive(Debug, Clone, Default)]
+pub struct TextTrack<'a> {
+    pub cues: &'a [SrtCue],
+    /// ISO 639-2 or BCP 47 code
+    pub language: Option<&'a str>,
+    pub name: Option<&'a str>,
+    pub forced: bool,
+    pub sdh: bool,
+    /// Whether players should pick the track without being asked
+    pub default: bool,
+}
+
+/// Top-level elements that get an entry in the rewritten SeekHead
+const INDEXED_ELEMENTS: [u32; 6] = [
+    ID_INFO,
+    ID_TRACKS,
+    ID_CHAPTERS,
+    ID_TAGS,
+    ID_ATTACHMENTS,
+    ID_CUES,
+];
+
+/// Copies `input` into `output`, adding `track`. Returns the new track's number.
+pub fn remux_with_text_track<R, W>(
+    input: &mut R,
+    output: &mut W,
+    track: &TextTrack,
+) -> Result<u64, RemuxError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let file_len = input.seek(SeekFrom::End(0))?;
+    input.seek(SeekFrom::Start(0))?;
+    let ebml_header = read_element_header(input).map_err(|_| RemuxError::NotMatroska)?;
+    if ebml_header.id != ID_EBML || ebml_header.size == UNKNOWN_SIZE {
+        return Err(RemuxError::NotMatroska);
+    }
+    input.seek(SeekFrom::Start(ebml_header.end_position()))?;
+    let segment = read_element_header(input).map_err(|_| RemuxError::NotMatroska)?;
+    if segment.id != ID_SEGMENT {
+        return Err(RemuxError::NotMatroska);
+    }
+    let segment_end = match segment.size {
+        UNKNOWN_SIZE => file_len,
+        size => (segment.data_position() + size).min(file_len),
+    };
+
+    // First pass: find the top-level elements and cluster timestamps
+    let mut elements = Vec::new();
+    let mut cluster_timestamps = Vec::new();
+    let mut timestamp_scale = DEFAULT_TIMESTAMP_SCALE;
+    let mut track_number = 1;
+    let mut position = segment.data_position();
+    while position < segment_end {
+        input.seek(SeekFrom::Start(position))?;
+        let header = match read_element_header(input) {
+            Ok(header) => header,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.into()),
+        };
+        if header.size == UNKNOWN_SIZE {
+            return Err(RemuxError::UnknownSize);
+        }
+        match header.id {
+            ID_INFO => {
+                let data = read_element_data(input, &header)?;
+                for child in ChildIter::new(&data) {
+                    let (id, _, value) = child?;
+                    if id == ID_TIMESTAMP_SCALE {
+                        timestamp_scale = parse_uint(value).max(1);
+                    }
+                }
+            }
+            ID_TRACKS => {
+                let data = read_element_data(input, &header)?;
+                for child in ChildIter::new(&data) {
+                    let (id, _, entry) = child?;
+                    if id != ID_TRACK_ENTRY {
+                        continue;
+                    }
+                    for field in ChildIter::new(entry) {
+                        let (id, _, value) = field?;
+                        if id == ID_TRACK_NUMBER {
+                            track_number = track_number.max(parse_uint(value) + 1);
+                        }
+                    }
+                }
+            }
+            ID_CLUSTER => {
+                cluster_timestamps.push(read_cluster_timestamp(input, &header)?);
+            }
+            _ => {}
+        }
+        elements.push(header);
+        position = header.end_position();
+    }
+
+    // Subtitle blocks, in cluster timestamp units
+    let mut blocks: Vec<(u64, u64, &[u8])> = track
+        .cues
+        .iter()
+        .filter(|cue| !cue.text.trim().is_empty())
+        .map(|cue| {
+            (
+                cue.start / timestamp_scale,
+                cue.end.saturating_sub(cue.start) / timestamp_scale,
+                cue.text.as_bytes(),
+            )
+        })
+        .collect();
+    blocks.sort_by_key(|block| block.0);
+    let mut blocks = blocks.into_iter().peekable();
+
+    // Second pass: write everything out
+    copy_range(input, output, 0, ebml_header.end_position())?;
+    let segment_size_position = write_header_placeholder(output, ID_SEGMENT)?;
+    let segment_start = output.stream_position()?;
+    output.write_all(&encode_void(SEEK_HEAD_RESERVED))?;
+
+    let mut indexed: Vec<(u32, u64)> = Vec::new();
+    let mut cluster_positions: HashMap<u64, u64> = HashMap::new();
+    let mut cues_header = None;
+    let mut cluster_index = 0;
+    for header in elements {
+        let new_position = output.stream_position()? - segment_start;
+        match header.id {
+            ID_SEEK_HEAD | ID_VOID | ID_CRC32 => continue,
+            ID_CUES => {
+                // Written last, once every cluster has its new position
+                cues_header = Some(header);
+                continue;
+            }
+            ID_TRACKS => {
+                let mut data = read_element_data(input, &header)?;
+                data.extend(encode_track_entry(track_number, track));
+                output.write_all(&encode_element(ID_TRACKS, &data))?;
+            }
+            ID_CLUSTER => {
+                let timestamp = cluster_timestamps[cluster_index];
+                cluster_index += 1;
+                let next_timestamp = cluster_timestamps.get(cluster_index).copied();
+                let mut before = Vec::new();
+                let mut inside = Vec::new();
+                let mut after = Vec::new();
+                while let Some(block) =
+                    blocks.next_if(|block| next_timestamp.is_none_or(|next| block.0 < next))
+                {
+                    let relative = block.0 as i64 - timestamp as i64;
+                    if relative < i16::MIN as i64 {
+                        before.push(block);
+                    } else if relative > i16::MAX as i64 {
+                        after.push(block);
+                    } else {
+                        inside.push((relative as i16, block.1, block.2));
+                    }
+                }
+
+                for (start, duration, text) in before {
+                    write_standalone_cluster(output, track_number, start, duration, text)?;
+                }
+                let new_position = output.stream_position()? - segment_start;
+                cluster_positions.insert(header.position - segment.data_position(), new_position);
+                let mut data = Vec::with_capacity(header.size as usize);
+                for child in ChildIter::new(&read_element_data(input, &header)?) {
+                    let (id, element, _) = child?;
+                    // These depend on the cluster's position, but replacing them
+                    // with equal-length Voids keeps the Cues' relative positions valid
+                    match id {
+                        ID_CRC32 | ID_POSITION | ID_PREV_SIZE => {
+                            data.extend(encode_void(element.len()))
+                        }
+                        _ => data.extend_from_slice(element),
+                    }
+                }
+                for (relative, duration, text) in inside {
+                    data.extend(encode_block_group(track_number, relative, duration, text));
+                }
+                output.write_all(&encode_element(ID_CLUSTER, &data))?;
+                for (start, duration, text) in after {
+                    write_standalone_cluster(output, track_number, start, duration, text)?;
+                }
+                continue;
+            }
+            _ => copy_range(
+                input,
+                output,
+                header.position,
+                header.end_position() - header.position,
+            )?,
+        }
+        if INDEXED_ELEMENTS.contains(&header.id) {
+            indexed.push((header.id, new_position));
+        }
+    }
+    // A file without clusters still gets its subtitles
+    for (start, duration, text) in blocks {
+        write_standalone_cluster(output, track_number, start, duration, text)?;
+    }
+    if let Some(header) = cues_header {
+        let data = read_element_data(input, &header)?;
+        indexed.push((ID_CUES, output.stream_position()? - segment_start));
+        output.write_all(&encode_element(
+            ID_CUES,
+            &rewrite_cues(&data, &cluster_positions)?,
+        ))?;
+    }
+    patch_size(output, segment_size_position)?;
+
+    let end = output.stream_position()?;
+    output.seek(SeekFrom::Start(segment_start))?;
+    output.write_all(&encode_seek_head(&indexed))?;
+    output.seek(SeekFrom::Start(end))?;
+    output.flush()?;
+
+    return Ok(track_number);
+}
+
+fn copy_range<R, W>(input: &mut R, output: &mut W, start: u64, len: u64) -> io::Result<()>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    input.seek(SeekFrom::Start(start))?;
+    let copied = io::copy(&mut input.take(len), output)?;
+    if copied != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    return Ok(());
+}
+
+/// Finds the Timestamp child of a cluster without reading the whole thing
+fn read_cluster_timestamp<R: Read + Seek>(
+    input: &mut R,
+    cluster: &ElementHeader,
+) -> Result<u64, RemuxError> {
+    let mut position = cluster.data_position();
+    while position < cluster.end_position() {
+        input.seek(SeekFrom::Start(position))?;
+        let child = read_element_header(input)?;
+        if child.size == UNKNOWN_SIZE {
+            return Err(RemuxError::UnknownSize);
+        }
+        if child.id == ID_TIMESTAMP {
+            return Ok(parse_uint(&read_element_data(input, &child)?));
+        }
+        position = child.end_position();
+    }
+    // Timestamp is mandatory, but 0 is the only sensible default
+    return Ok(0);
+}
+
+fn encode_track_entry(track_number: u64, track: &TextTrack) -> Vec<u8> {
+    let mut uid = RandomState::new().build_hasher();
+    uid.write_u64(track_number);
+
+    let mut entry = Vec::new();
+    entry.extend(encode_uint(ID_TRACK_NUMBER, track_number));
+    entry.extend(encode_uint(ID_TRACK_UID, uid.finish().max(1)));
+    entry.extend(encode_uint(ID_TRACK_TYPE, TRACK_TYPE_SUBTITLE));
+    entry.extend(encode_uint(ID_FLAG_LACING, 0));
+    entry.extend(encode_uint(ID_FLAG_DEFAULT, u64::from(track.default)));
+    if track.forced {
+        entry.extend(encode_uint(ID_FLAG_FORCED, 1));
+    }
+    if track.sdh {
+        entry.extend(encode_uint(ID_FLAG_HEARING_IMPAIRED, 1));
+    }
+    entry.extend(encode_string(ID_CODEC_ID, "S_TEXT/UTF8"));
+    match track.language {
+        // The legacy element only holds ISO 639-2 codes
+        Some(language)
+            if language.len() == 3 && language.chars().all(|c| c.is_ascii_lowercase()) =>
+        {
+            entry.extend(encode_string(ID_LANGUAGE, language));
+        }
+        Some(language) => {
+            entry.extend(encode_string(ID_LANGUAGE, "und"));
+            entry.extend(encode_string(ID_LANGUAGE_BCP47, language));
+        }
+        None => entry.extend(encode_string(ID_LANGUAGE, "und")),
+    }
+    if let Some(name) = track.name {
+        entry.extend(encode_string(ID_NAME, name));
+    }
+    return encode_element(ID_TRACK_ENTRY, &entry);
+}
+
+fn encode_block_group(track_number: u64, relative: i16, duration: u64, text: &[u8]) -> Vec<u8> {
+    let mut block = encode_size(track_number, None);
+    block.extend(relative.to_be_bytes());
+    block.push(0); // flags: no lacing
+    block.extend_from_slice(text);
+
+    let mut group = encode_element(ID_BLOCK, &block);
+    group.extend(encode_uint(ID_BLOCK_DURATION, duration));
+    return encode_element(ID_BLOCK_GROUP, &group);
+}
+
+/// Writes a cluster holding a single subtitle block, for subtitles too far
+/// from any existing cluster to be expressed as a relative timestamp
+fn write_standalone_cluster<W: Write>(
+    output: &mut W,
+    track_number: u64,
+    start: u64,
+    duration: u64,
+    text: &[u8],
+) -> io::Result<()> {
+    let mut data = encode_uint(ID_TIMESTAMP, start);
+    data.extend(encode_block_group(track_number, 0, duration, text));
+    return output.write_all(&encode_element(ID_CLUSTER, &data));
+}
+
+fn encode_seek_head(indexed: &[(u32, u64)]) -> Vec<u8> {
+    let mut seeks = Vec::new();
+    for (id, position) in indexed {
+        let mut seek = encode_element(ID_SEEK_ID, &encode_id(*id));
+        seek.extend(encode_uint(ID_SEEK_POSITION, *position));
+        seeks.extend(encode_element(ID_SEEK, &seek));
+    }
+    let mut seek_head = encode_element(ID_SEEK_HEAD, &seeks);
+    // Pad out the rest of the reserved space. A Void is at least 2 bytes.
+    match SEEK_HEAD_RESERVED - seek_head.len() {
+        0 => {}
+        1 => unreachable!("SeekHead leaves 1 byte of reserved space"),
+        padding => seek_head.extend(encode_void(padding)),
+    }
+    return seek_head;
+}
+
+/// Points each CueClusterPosition at the cluster's new location. Cue points
+/// whose cluster can't be found are dropped.
+fn rewrite_cues(data: &[u8], cluster_positions: &HashMap<u64, u64>) -> io::Result<Vec<u8>> {
+    let mut cues = Vec::new();
+    for child in ChildIter::new(data) {
+        let (id, element, value) = child?;
+        if id != ID_CUE_POINT {
+            cues.extend_from_slice(element);
+            continue;
+        }
+        let mut point = Vec::new();
+        let mut has_positions = false;
+        for child in ChildIter::new(value) {
+            let (id, element, value) = child?;
+            if id != ID_CUE_TRACK_POSITIONS {
+                point.extend_from_slice(element);
+                continue;
+            }
+            let mut positions = Vec::new();
+            let mut found = false;
+            for child in ChildIter::new(value) {
+                let (id, element, value) = child?;
+                if id == ID_CUE_CLUSTER_POSITION {
+                    if let Some(position) = cluster_positions.get(&parse_uint(value)) {
+                        positions.extend(encode_uint(ID_CUE_CLUSTER_POSITION, *position));
+                        found = true;
+                    }
+                } else {
+                    positions.extend_from_slice(element);
+                }
+            }
+            if found {
+                point.extend(encode_element(ID_CUE_TRACK_POSITIONS, &positions));
+                has_positions = true;
+            }
+        }
+        if has_positions {
+            cues.extend(encode_element(ID_CUE_POINT, &point));
+        }
+    }
+    return Ok(cues);
+}
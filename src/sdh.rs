@@ -0,0 +1,133 @@
+//! Heuristics for subtitles for the deaf and hard of hearing (SDH). These add
+//! sound descriptions (`[door slams]`), music notes and speaker labels
+//! (`MAN:`) on top of the dialogue, which is enough to tell them apart from
+//! regular subtitles and to strip them back down to a non-SDH variant.
+
+/// Music note glyphs used to mark lyrics and background music
+const MUSIC_NOTES: [char; 2] = ['♪', '♫'];
+
+/// Fraction of events that need SDH markers before the whole track is
+/// considered SDH. Regular subtitles occasionally contain brackets too.
+const TRACK_THRESHOLD: f64 = 0.1;
+/// Minimum number of marked events, so a short track isn't decided by one line
+const TRACK_MIN_MARKED: usize = 2;
+
+/// Returns the byte length of a speaker label (`MAN:`, `JOHN SMITH:`,
+/// `GUARD #2:`) at the start of `line`, including the colon and the space
+/// after it.
+fn speaker_label_len(line: &str) -> Option<usize> {
+    let (label, _) = line.split_once(':')?;
+    let letters = label.chars().filter(|c| c.is_alphabetic()).count();
+    let valid = letters >= 2
+        && label
+            .chars()
+            .all(|c| c.is_uppercase() || c.is_ascii_digit() || " '#.-".contains(c));
+    if !valid {
+        return None;
+    }
+    let rest = &line[label.len() + 1..];
+    return Some(label.len() + 1 + (rest.len() - rest.trim_start().len()));
+}
+
+/// Removes `[...]` and `(...)` spans. Unclosed brackets are left alone.
+fn remove_descriptions(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(['[', '(']) {
+        let close = match rest.as_bytes()[start] {
+            b'[' => ']',
+            _ => ')',
+        };
+        let Some(length) = rest[start..].find(close) else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        rest = &rest[start + length + 1..];
+    }
+    result.push_str(rest);
+    return result;
+}
+
+fn has_description(line: &str) -> bool {
+    return remove_descriptions(line).len() != line.len();
+}
+
+fn dialogue_dash(line: &str) -> (&str, &str) {
+    let trimmed = line.trim_start_matches(['-', ' ']);
+    return (&line[..line.len() - trimmed.len()], trimmed);
+}
+
+/// Whether a cue's text contains any SDH markers
+pub fn has_sdh_markers(text: &str) -> bool {
+    return text.lines().any(|line| {
+        let (_, line) = dialogue_dash(line.trim());
+        return has_description(line)
+            || line.contains(MUSIC_NOTES)
+            || speaker_label_len(line).is_some();
+    });
+}
+
+/// Tally of SDH markers over a track
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SdhClassification {
+    pub events: usize,
+    pub marked: usize,
+}
+impl SdhClassification {
+    pub fn from_texts<'a, I: IntoIterator<Item = &'a str>>(texts: I) -> Self {
+        let mut classification = Self::default();
+        for text in texts {
+            if text.trim().is_empty() {
+                continue;
+            }
+            classification.events += 1;
+            if has_sdh_markers(text) {
+                classification.marked += 1;
+            }
+        }
+        return classification;
+    }
+
+    pub fn is_sdh(&self) -> bool {
+        return self.marked >= TRACK_MIN_MARKED
+            && self.marked as f64 >= self.events as f64 * TRACK_THRESHOLD;
+    }
+}
+
+/// Produces the non-SDH version of a cue: sound descriptions, speaker labels
+/// and music-only lines are removed. Returns an empty string if nothing but
+/// SDH content was left, in which case the cue should be dropped.
+pub fn strip_sdh(text: &str) -> String {
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let line = remove_descriptions(line);
+        let (dash, mut content) = dialogue_dash(line.trim());
+        if let Some(length) = speaker_label_len(content) {
+            content = &content[length..];
+        }
+        let mut content = content.split_whitespace().collect::<Vec<_>>().join(" ");
+        // Removing a description can leave a gap before the punctuation
+        for punctuation in [".", ",", "!", "?"] {
+            content = content.replace(&format!(" {punctuation}"), punctuation);
+        }
+        let only_music = content
+            .chars()
+            .all(|c| MUSIC_NOTES.contains(&c) || c.is_whitespace() || c.is_ascii_punctuation());
+        if only_music {
+            continue;
+        }
+        lines.push((dash.trim_end().to_owned(), content));
+    }
+    // A dialogue dash only makes sense with more than one speaker left
+    if lines.len() == 1 {
+        return lines.remove(0).1;
+    }
+    return lines
+        .into_iter()
+        .map(|(dash, content)| match dash.is_empty() {
+            true => content,
+            false => format!("{dash} {content}"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+}
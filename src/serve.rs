@@ -0,0 +1,721 @@
+//! `serve` mode: exposes the pipeline over a minimal HTTP/1.1 API so mediacorral
+//! can drive it as a separate process. Each job runs on its own thread and keeps
+//! its results in memory until deleted.
+//!
+//! - `POST /jobs?path=<file>&track=<n>&priority=<n>` queues a job on a file
+//!   the server can read. Without `path`, the request body is taken as the
+//!   MKV itself.
+//! - `GET /jobs` and `GET /jobs/<id>` report state and progress.
+//! - `GET /jobs/<id>/events` lists events, `GET /jobs/<id>/srt` returns the
+//!   finished SRT and `GET /jobs/<id>/images/<n>` returns event `n` as a PNG.
+//! - `DELETE /jobs/<id>` forgets a job, cancelling it if it's still running.
+//!
+//! Jobs go through a [`JobQueue`], which decides when each starts decoding
+//! and running OCR, and with `--state` keeps them across restarts.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+};
+
+use image::ImageFormat;
+use matroska_demuxer::MatroskaFile;
+use serde::{Deserialize, Serialize};
+use subproc::{
+    cancel::CancellationToken,
+    diagnostics::Diagnostics,
+    extract::{EventPayload, SubtitleEvent, SubtitleExtractor},
+    input::{FileLimiter, FilePermit, IoStrategy},
+    ocr::RegionPolicy,
+    preprocess::FlattenOptions,
+    queue::{JobLimits, JobQueue, JobTicket, QueuedJob},
+    sdh::{SdhClassification, has_sdh_markers},
+    srt::{SrtCue, write_srt},
+    text_filter::FilterChain,
+};
+
+use crate::{
+    cli::{OcrOptions, ServeOptions},
+    to_cues,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JobState {
+    /// Waiting for a decode slot, or with `--max-open-files` for another job
+    /// to finish reading
+    Queued,
+    Extracting,
+    Recognizing,
+    Done,
+    Failed,
+}
+impl JobState {
+    fn as_str(&self) -> &'static str {
+        return match self {
+            Self::Queued => "queued",
+            Self::Extracting => "extracting",
+            Self::Recognizing => "recognizing",
+            Self::Done => "done",
+            Self::Failed => "failed",
+        };
+    }
+}
+
+/// What the queue keeps of a job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ServeJob {
+    path: PathBuf,
+    track: Option<u64>,
+    /// An upload spooled to disk, removed once the job is over
+    temporary: bool,
+}
+
+struct Job {
+    state: JobState,
+    priority: i32,
+    /// Bytes of the input consumed so far, updated by the worker without locking
+    bytes_read: Arc<AtomicU64>,
+    total_bytes: u64,
+    events: Vec<SubtitleEvent>,
+    /// One per event, once OCR has finished
+    cues: Vec<SrtCue>,
+    warnings: Vec<String>,
+    error: Option<String>,
+    cancel: CancellationToken,
+}
+impl Job {
+    fn new(priority: i32, total_bytes: u64) -> Self {
+        return Self {
+            state: JobState::Queued,
+            priority,
+            bytes_read: Arc::new(AtomicU64::new(0)),
+            total_bytes,
+            events: Vec::new(),
+            cues: Vec::new(),
+            warnings: Vec::new(),
+            error: None,
+            cancel: CancellationToken::new(),
+        };
+    }
+
+    fn progress(&self) -> f64 {
+        return match self.state {
+            JobState::Extracting if self.total_bytes > 0 => {
+                self.bytes_read.load(Ordering::Relaxed) as f64 / self.total_bytes as f64
+            }
+            JobState::Queued | JobState::Extracting => 0.0,
+            JobState::Recognizing | JobState::Done | JobState::Failed => 1.0,
+        };
+    }
+
+    fn status_json(&self, id: u64) -> String {
+        // Only known once all the text is available
+        let sdh = match self.state {
+            JobState::Done => {
+                SdhClassification::from_texts(self.cues.iter().map(|cue| cue.text.as_str()))
+                    .is_sdh()
+                    .to_string()
+            }
+            _ => String::from("null"),
+        };
+        return format!(
+            "{{\"id\":{id},\"state\":{},\"priority\":{},\"progress\":{:.3},\"events\":{},\"sdh\":{sdh},\"warnings\":[{}],\"error\":{}}}",
+            json_string(self.state.as_str()),
+            self.priority,
+            self.progress().min(1.0),
+            self.events.len(),
+            self.warnings
+                .iter()
+                .map(|warning| json_string(warning))
+                .collect::<Vec<_>>()
+                .join(","),
+            self.error
+                .as_deref()
+                .map_or(String::from("null"), json_string),
+        );
+    }
+}
+
+struct Jobs {
+    ocr: OcrOptions,
+    io: IoStrategy,
+    files: Arc<FileLimiter>,
+    queue: JobQueue<ServeJob>,
+    /// Applied to each new job
+    limits: JobLimits,
+    /// Numbers uploads spooled to disk
+    next_upload: AtomicU64,
+    jobs: Mutex<BTreeMap<u64, Arc<Mutex<Job>>>>,
+}
+impl Jobs {
+    fn get(&self, id: u64) -> Option<Arc<Mutex<Job>>> {
+        return self.jobs.lock().unwrap().get(&id).cloned();
+    }
+}
+
+/// Tracks how far into the input the demuxer has read
+struct ProgressReader<R> {
+    inner: R,
+    position: Arc<AtomicU64>,
+}
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position.fetch_add(read as u64, Ordering::Relaxed);
+        return Ok(read);
+    }
+}
+impl<R: Seek> Seek for ProgressReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = self.inner.seek(pos)?;
+        self.position.store(position, Ordering::Relaxed);
+        return Ok(position);
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: BTreeMap<String, String>,
+    content_length: Option<u64>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+impl Response {
+    fn json(status: u16, body: String) -> Self {
+        return Self {
+            status,
+            content_type: "application/json",
+            body: body.into_bytes(),
+        };
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        return Self::json(status, format!("{{\"error\":{}}}", json_string(message)));
+    }
+}
+
+pub fn serve(options: ServeOptions) -> io::Result<()> {
+    let listener = TcpListener::bind(&options.listen)?;
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+    let queue: JobQueue<ServeJob> = match options.queue.state {
+        Some(ref path) => JobQueue::open(path, options.queue.limits).map_err(io::Error::other)?,
+        None => JobQueue::new(options.queue.limits),
+    };
+    // Jobs left in the state file, which the dispatcher picks up
+    let mut resumed = BTreeMap::new();
+    for job in queue.queued() {
+        let total_bytes = fs::metadata(&job.spec.path).map_or(0, |metadata| metadata.len());
+        resumed.insert(
+            job.id,
+            Arc::new(Mutex::new(Job::new(job.priority, total_bytes))),
+        );
+    }
+    if !resumed.is_empty() {
+        eprintln!("Resuming {} queued jobs", resumed.len());
+    }
+    let jobs = Arc::new(Jobs {
+        ocr: options.ocr,
+        io: options.io,
+        files: Arc::new(FileLimiter::new(options.max_open_files)),
+        queue,
+        limits: options.queue.job,
+        next_upload: AtomicU64::new(0),
+        jobs: Mutex::new(resumed),
+    });
+    let dispatcher = jobs.clone();
+    thread::spawn(move || dispatch(&dispatcher));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("Warning: {err}");
+                continue;
+            }
+        };
+        let jobs = jobs.clone();
+        thread::spawn(move || {
+            if let Err(err) = handle_connection(stream, &jobs) {
+                eprintln!("Warning: {err}");
+            }
+        });
+    }
+    return Ok(());
+}
+
+fn handle_connection(stream: TcpStream, jobs: &Arc<Jobs>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader) {
+        Ok(request) => route(request, &mut reader, jobs),
+        Err(err) => Response::error(400, &err.to_string()),
+    };
+
+    let reason = match response.status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    };
+    let mut writer = BufWriter::new(stream);
+    write!(
+        writer,
+        "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len(),
+    )?;
+    writer.write_all(&response.body)?;
+    return writer.flush();
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_owned());
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().ok_or_else(|| invalid("Empty request"))?;
+    let target = parts
+        .next()
+        .ok_or_else(|| invalid("Missing request target"))?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = Request {
+        method: method.to_owned(),
+        path: percent_decode(path),
+        query: query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (percent_decode(key), percent_decode(value))
+            })
+            .collect(),
+        content_length: None,
+    };
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Err(invalid("Connection closed in headers"));
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            request.content_length = Some(
+                value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("Invalid Content-Length"))?,
+            );
+        }
+    }
+    return Ok(request);
+}
+
+fn route<R: Read>(request: Request, body: &mut R, jobs: &Arc<Jobs>) -> Response {
+    let segments: Vec<&str> = request
+        .path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    let method = request.method.as_str();
+    let job_id = segments.get(1).and_then(|id| id.parse::<u64>().ok());
+
+    return match (method, segments.as_slice()) {
+        ("POST", ["jobs"]) => match submit_job(&request, body, jobs) {
+            Ok(id) => Response::json(201, format!("{{\"id\":{id}}}")),
+            Err(response) => response,
+        },
+        ("GET", ["jobs"]) => {
+            let jobs = jobs.jobs.lock().unwrap();
+            let statuses: Vec<String> = jobs
+                .iter()
+                .map(|(id, job)| job.lock().unwrap().status_json(*id))
+                .collect();
+            Response::json(200, format!("[{}]", statuses.join(",")))
+        }
+        ("DELETE", ["jobs", _]) => {
+            match job_id.and_then(|id| Some((id, jobs.jobs.lock().unwrap().remove(&id)?))) {
+                Some((id, job)) => {
+                    job.lock().unwrap().cancel.cancel();
+                    // Running jobs clean up after themselves
+                    if let Some(queued) = jobs.queue.remove(id) {
+                        remove_upload(&queued.spec);
+                    }
+                    Response::json(200, String::from("{}"))
+                }
+                None => Response::error(404, "No such job"),
+            }
+        }
+        ("GET", ["jobs", _, rest @ ..]) => {
+            let Some((id, job)) = job_id.and_then(|id| Some((id, jobs.get(id)?))) else {
+                return Response::error(404, "No such job");
+            };
+            let job = job.lock().unwrap();
+            match rest {
+                [] => Response::json(200, job.status_json(id)),
+                ["events"] => Response::json(200, events_json(id, &job)),
+                ["srt"] if job.state != JobState::Done => {
+                    Response::error(409, "Job has not finished")
+                }
+                ["srt"] => {
+                    let mut srt = Vec::new();
+                    match write_srt(&mut srt, &job.cues) {
+                        Ok(()) => Response {
+                            status: 200,
+                            content_type: "application/x-subrip",
+                            body: srt,
+                        },
+                        Err(err) => Response::error(500, &err.to_string()),
+                    }
+                }
+                ["images", index] => {
+                    let event = index.parse::<usize>().ok().and_then(|i| job.events.get(i));
+                    match event.map(|event| &event.payload) {
+                        Some(EventPayload::Image(image)) => {
+                            let mut png = Cursor::new(Vec::new());
+                            match image.write_to(&mut png, ImageFormat::Png) {
+                                Ok(()) => Response {
+                                    status: 200,
+                                    content_type: "image/png",
+                                    body: png.into_inner(),
+                                },
+                                Err(err) => Response::error(500, &err.to_string()),
+                            }
+                        }
+                        _ => Response::error(404, "No such image"),
+                    }
+                }
+                _ => Response::error(404, "Not found"),
+            }
+        }
+        (_, ["jobs", ..]) => Response::error(405, "Method not allowed"),
+        _ => Response::error(404, "Not found"),
+    };
+}
+
+/// Queues a job from the request, returning its ID
+fn submit_job<R: Read>(request: &Request, body: &mut R, jobs: &Arc<Jobs>) -> Result<u64, Response> {
+    let track = match request.query.get("track") {
+        Some(track) => Some(
+            track
+                .parse::<u64>()
+                .map_err(|_| Response::error(400, "Invalid track number"))?,
+        ),
+        None => None,
+    };
+    let priority = match request.query.get("priority") {
+        Some(priority) => priority
+            .parse::<i32>()
+            .map_err(|_| Response::error(400, "Invalid priority"))?,
+        None => 0,
+    };
+    let too_large = |size: u64| match jobs.limits.max_input_size {
+        Some(limit) if size > limit => Err(Response::error(
+            413,
+            &format!("The input is {size} bytes, more than the limit of {limit}"),
+        )),
+        _ => Ok(()),
+    };
+
+    // Uploads are spooled to disk since the demuxer needs to seek
+    let spec = match request.query.get("path") {
+        Some(path) => ServeJob {
+            path: PathBuf::from(path),
+            track,
+            temporary: false,
+        },
+        None => {
+            let Some(length) = request.content_length.filter(|length| *length > 0) else {
+                return Err(Response::error(
+                    411,
+                    "Either `path` or a request body is required",
+                ));
+            };
+            too_large(length)?;
+            let upload = jobs.next_upload.fetch_add(1, Ordering::Relaxed) + 1;
+            let path =
+                std::env::temp_dir().join(format!("subproc-{}-{upload}.mkv", std::process::id()));
+            let mut spool = || -> io::Result<()> {
+                let mut file = BufWriter::new(File::create(&path)?);
+                if io::copy(&mut body.take(length), &mut file)? != length {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                return file.flush();
+            };
+            if let Err(err) = spool() {
+                let _ = fs::remove_file(&path);
+                return Err(Response::error(
+                    400,
+                    &format!("Failed to read upload: {err}"),
+                ));
+            }
+            ServeJob {
+                path,
+                track,
+                temporary: true,
+            }
+        }
+    };
+    // Opened once the job starts, but checked for now
+    let total_bytes = match fs::metadata(&spec.path) {
+        Ok(metadata) if metadata.is_file() => metadata.len(),
+        Ok(_) => {
+            return Err(Response::error(
+                400,
+                &format!("{} is not a file", spec.path.display()),
+            ));
+        }
+        Err(err) => {
+            return Err(Response::error(
+                400,
+                &format!("{}: {err}", spec.path.display()),
+            ));
+        }
+    };
+    if let Err(response) = too_large(total_bytes) {
+        remove_upload(&spec);
+        return Err(response);
+    }
+
+    // Held while queueing, so the dispatcher can't start the job before it's
+    // listed
+    let mut listed = jobs.jobs.lock().unwrap();
+    let id = match jobs.queue.push(spec.clone(), priority, jobs.limits) {
+        Ok(id) => id,
+        Err(err) => {
+            remove_upload(&spec);
+            return Err(Response::error(500, &err.to_string()));
+        }
+    };
+    listed.insert(id, Arc::new(Mutex::new(Job::new(priority, total_bytes))));
+    return Ok(id);
+}
+
+/// Starts each job the queue hands out on a thread of its own
+fn dispatch(jobs: &Arc<Jobs>) {
+    loop {
+        let (queued, ticket) = jobs.queue.next();
+        let Some(job) = jobs.get(queued.id) else {
+            // Deleted while being taken off the queue
+            remove_upload(&queued.spec);
+            continue;
+        };
+        {
+            let mut job = job.lock().unwrap();
+            if job.cancel.is_cancelled() {
+                drop(job);
+                remove_upload(&queued.spec);
+                continue;
+            }
+            // Cancelled on a timeout, as well as on DELETE from now on
+            job.cancel = ticket.cancellation();
+        }
+        let ocr = jobs.ocr.clone();
+        let io = jobs.io;
+        let files = jobs.files.clone();
+        thread::spawn(move || run_queued(&job, queued, ticket, &ocr, io, &files));
+    }
+}
+
+fn run_queued(
+    job: &Mutex<Job>,
+    queued: QueuedJob<ServeJob>,
+    mut ticket: JobTicket,
+    ocr: &OcrOptions,
+    io: IoStrategy,
+    files: &Arc<FileLimiter>,
+) {
+    let spec = queued.spec;
+    let permit = files.acquire();
+    let (bytes_read, total_bytes) = {
+        let mut job = job.lock().unwrap();
+        job.state = JobState::Extracting;
+        (job.bytes_read.clone(), job.total_bytes)
+    };
+    let result = ticket
+        .check_input(total_bytes)
+        .map_err(|err| err.to_string())
+        .and_then(|()| {
+            return io
+                .open(&spec.path)
+                .map_err(|err| format!("{}: {err}", spec.path.display()));
+        })
+        .and_then(|input| {
+            let reader = ProgressReader {
+                inner: input,
+                position: bytes_read,
+            };
+            return run_job(job, reader, spec.track, ocr, permit, &mut ticket);
+        });
+    let mut job = job.lock().unwrap();
+    match result {
+        Ok(()) => job.state = JobState::Done,
+        Err(err) => {
+            job.state = JobState::Failed;
+            job.error = Some(err);
+        }
+    }
+    drop(job);
+    remove_upload(&spec);
+}
+
+/// Extracts and recognizes a job's input. `permit` is given back once the
+/// input has been read, so another job can start reading while this one
+/// waits for an OCR slot.
+fn run_job<R: Read + Seek>(
+    job: &Mutex<Job>,
+    reader: R,
+    track: Option<u64>,
+    ocr: &OcrOptions,
+    permit: FilePermit,
+    ticket: &mut JobTicket,
+) -> Result<(), String> {
+    let mkv = MatroskaFile::open(reader).map_err(|err| err.to_string())?;
+    let mut extractor = SubtitleExtractor::new(mkv, track).map_err(|err| err.to_string())?;
+    let cancel = job.lock().unwrap().cancel.clone();
+    extractor.set_cancellation(cancel.clone());
+    for event in extractor {
+        let mut job = job.lock().unwrap();
+        match event {
+            Ok(event) => job.events.push(event),
+            Err(err) => job.warnings.push(err.to_string()),
+        }
+        ticket
+            .check_events(job.events.len())
+            .map_err(|err| err.to_string())?;
+    }
+    ticket.check_time().map_err(|err| err.to_string())?;
+
+    drop(permit);
+    ticket.start_ocr();
+    let events = {
+        let mut job = job.lock().unwrap();
+        job.state = JobState::Recognizing;
+        job.events.clone()
+    };
+    let mut diagnostics = Diagnostics::default();
+    let mut cues = to_cues(
+        events,
+        ocr,
+        RegionPolicy::default(),
+        FlattenOptions::default(),
+        false,
+        &cancel,
+        &mut diagnostics,
+    )
+    .map_err(|err| err.to_string())?;
+    ticket.check_time().map_err(|err| err.to_string())?;
+    let filters = FilterChain::default();
+    for cue in cues.iter_mut() {
+        cue.text = filters.apply(&cue.text);
+    }
+    let mut job = job.lock().unwrap();
+    job.warnings.extend(
+        diagnostics
+            .entries()
+            .iter()
+            .map(|diagnostic| diagnostic.message.clone()),
+    );
+    job.cues = cues;
+    return Ok(());
+}
+
+/// Deletes a job's input if it was uploaded
+fn remove_upload(spec: &ServeJob) {
+    if spec.temporary {
+        let _ = fs::remove_file(&spec.path);
+    }
+}
+
+fn events_json(id: u64, job: &Job) -> String {
+    let events: Vec<String> = job
+        .events
+        .iter()
+        .enumerate()
+        .map(|(i, event)| {
+            let text = match event.payload {
+                EventPayload::Text(ref text) => Some(text.as_str()),
+                EventPayload::Image(_) => job.cues.get(i).map(|cue| cue.text.as_str()),
+            };
+            let image = match event.payload {
+                EventPayload::Image(_) => json_string(&format!("/jobs/{id}/images/{i}")),
+                EventPayload::Text(_) => String::from("null"),
+            };
+            return format!(
+                "{{\"index\":{i},\"start\":{},\"end\":{},\"forced\":{},\"sdh\":{},\"text\":{},\"image\":{image}}}",
+                event.start,
+                event.end.map_or(String::from("null"), |end| end.to_string()),
+                event.forced,
+                text.is_some_and(has_sdh_markers),
+                text.map_or(String::from("null"), json_string),
+            );
+        })
+        .collect();
+    return format!("[{}]", events.join(","));
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    return json;
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let byte = std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match byte {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 3;
+                        continue;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    return String::from_utf8_lossy(&decoded).into_owned();
+}
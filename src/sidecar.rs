@@ -0,0 +1,94 @@
+//! Media server (Plex/Jellyfin) sidecar conventions. Subtitles written as
+//! `<video stem>.<language>[.sdh][.forced].srt` next to the video are picked up
+//! and labelled automatically.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[cfg(feature = "demux-mkv")]
+use matroska_demuxer::TrackEntry;
+
+/// Semantic role of a subtitle track, as far as sidecar naming is concerned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrackRole {
+    pub forced: bool,
+    pub sdh: bool,
+    /// Whether players should pick the track without being asked. Not part
+    /// of sidecar names, since Matroska tracks are default unless they say
+    /// otherwise and most would be marked.
+    pub default: bool,
+}
+impl TrackRole {
+    /// Takes the role from the track's forced, hearing impaired and default
+    /// flags. Since few rips set the first two, it's also guessed from the
+    /// track name, which is commonly "English (Forced)" or "English SDH".
+    #[cfg(feature = "demux-mkv")]
+    pub fn from_track(track: &TrackEntry) -> Self {
+        let name = track.name().unwrap_or_default().to_lowercase();
+        let words: Vec<&str> = name
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+        return Self {
+            forced: track.flag_forced() || words.contains(&"forced"),
+            sdh: track.flag_hearing_impaired()
+                || words.contains(&"sdh")
+                || words.contains(&"cc")
+                || name.contains("hearing impaired"),
+            default: track.flag_default(),
+        };
+    }
+}
+
+/// Returns the track's language, treating Matroska's `und` as missing
+#[cfg(feature = "demux-mkv")]
+pub fn track_language(track: &TrackEntry) -> Option<&str> {
+    return track
+        .language_bcp47()
+        .or(track.language())
+        .filter(|language| !language.is_empty() && *language != "und");
+}
+
+/// Builds the sidecar path for `video`. `language` is omitted from the name
+/// when unknown, which media servers treat as an unlabelled track.
+pub fn sidecar_path(
+    video: &Path,
+    language: Option<&str>,
+    role: TrackRole,
+    extension: &str,
+) -> PathBuf {
+    let mut name = video.file_stem().unwrap_or_default().to_os_string();
+    if let Some(language) = language {
+        name.push(".");
+        name.push(language);
+    }
+    if role.sdh {
+        name.push(".sdh");
+    }
+    if role.forced {
+        name.push(".forced");
+    }
+    name.push(".");
+    name.push(extension);
+    return video.with_file_name(name);
+}
+
+/// Sets the language of an MKV track in place using `mkvpropedit` from MKVToolNix
+pub fn set_track_language(video: &Path, track_number: u64, language: &str) -> io::Result<()> {
+    let status = Command::new("mkvpropedit")
+        .arg(video)
+        .arg("--edit")
+        .arg(format!("track:@{track_number}"))
+        .arg("--set")
+        .arg(format!("language={language}"))
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "mkvpropedit exited with {status}"
+        )));
+    }
+    return Ok(());
+}
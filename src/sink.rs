@@ -0,0 +1,245 @@
+//! Hooks for doing something with each decoded event before OCR. Embedding
+//! applications implement [`EventSink`] to keep bitmaps in their own storage
+//! (mediacorral puts them in its database) rather than having the crate write
+//! files. The terminal preview, image directory and contact sheet outputs are
+//! sinks too.
+
+use std::{fs, io, path::PathBuf};
+
+use image::{ImageError, Rgb, RgbaImage, buffer::ConvertBuffer};
+use thiserror::Error;
+
+#[cfg(feature = "writers")]
+use crate::{
+    contact_sheet::ContactSheet,
+    model::{MANIFEST_FILE, SubtitleDocument, SubtitleTrack},
+};
+use crate::{
+    extract::{EventPayload, SubtitleEvent},
+    terminal::{self, PreviewMode, PreviewWidth, over_background},
+};
+
+#[derive(Error, Debug)]
+pub enum SinkError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Failed to save image: {0}")]
+    Image(#[from] ImageError),
+    #[error("Failed to save image: {0}")]
+    Png(#[from] png::EncodingError),
+    #[error("{0}")]
+    Other(String),
+}
+
+/// Receives every event, in order, before OCR. `index` is the event's
+/// position in the stream, starting at 0.
+pub trait EventSink {
+    fn event(&mut self, index: usize, event: &SubtitleEvent) -> Result<(), SinkError>;
+
+    /// Called once the last event has been passed in
+    fn finish(&mut self) -> Result<(), SinkError> {
+        return Ok(());
+    }
+}
+impl<F: FnMut(usize, &SubtitleEvent) -> Result<(), SinkError>> EventSink for F {
+    fn event(&mut self, index: usize, event: &SubtitleEvent) -> Result<(), SinkError> {
+        return self(index, event);
+    }
+}
+
+/// Passes every event to each of its sinks in turn, such as a terminal
+/// preview and an image directory at once
+#[derive(Default)]
+pub struct TeeSink<'a> {
+    sinks: Vec<Box<dyn EventSink + 'a>>,
+}
+impl<'a> TeeSink<'a> {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn push(&mut self, sink: Box<dyn EventSink + 'a>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.sinks.is_empty();
+    }
+}
+impl EventSink for TeeSink<'_> {
+    fn event(&mut self, index: usize, event: &SubtitleEvent) -> Result<(), SinkError> {
+        for sink in self.sinks.iter_mut() {
+            sink.event(index, event)?;
+        }
+        return Ok(());
+    }
+
+    /// Finishes every sink, even if one of them fails. The first error is
+    /// returned.
+    fn finish(&mut self) -> Result<(), SinkError> {
+        let mut result = Ok(());
+        for sink in self.sinks.iter_mut() {
+            let finished = sink.finish();
+            if result.is_ok() {
+                result = finished;
+            }
+        }
+        return result;
+    }
+}
+
+/// Previews events in the terminal: images as sixel or half blocks, text
+/// as-is. Images are drawn over a background color, in their palette colors
+/// if the event has [`SubtitleEvent::indexed`] set and in gray otherwise.
+/// Images that can't be shown, e.g. because output is piped, are skipped with
+/// a warning rather than failing.
+#[derive(Debug)]
+pub struct PreviewSink {
+    mode: PreviewMode,
+    /// Images wider than this are scaled down
+    pub max_width: Option<PreviewWidth>,
+    /// Black by default
+    pub background: Rgb<u8>,
+    warned: bool,
+}
+impl PreviewSink {
+    /// [`PreviewMode::Auto`] is resolved here, once
+    pub fn new(mode: PreviewMode, max_width: Option<PreviewWidth>) -> Self {
+        return Self {
+            mode: mode.resolve(),
+            max_width,
+            background: Rgb([0, 0, 0]),
+            warned: false,
+        };
+    }
+
+    pub fn with_background(mut self, background: Rgb<u8>) -> Self {
+        self.background = background;
+        return self;
+    }
+
+    /// Prints an opaque image with the sink's mode and width
+    pub fn print(&self, image: &RgbaImage) -> Result<(), SinkError> {
+        match self.mode {
+            #[cfg(feature = "preview")]
+            PreviewMode::Sixel => {
+                crate::sixel::print_rgba_image(image, self.max_width)
+                    .map_err(|err| SinkError::Other(err.to_string()))?;
+            }
+            _ => terminal::print_blocks(image, self.max_width)?,
+        }
+        return Ok(());
+    }
+}
+impl EventSink for PreviewSink {
+    fn event(&mut self, index: usize, event: &SubtitleEvent) -> Result<(), SinkError> {
+        match event.payload {
+            EventPayload::Image(ref image) => {
+                // Escape sequences would only garble a pipe or file
+                if !terminal::is_available() {
+                    if !self.warned {
+                        eprintln!("Warning: output is not a terminal, so images are skipped");
+                        self.warned = true;
+                    }
+                    return Ok(());
+                }
+                let colored = match event.indexed {
+                    Some(ref indexed) => indexed.to_rgba(),
+                    None => image.convert(),
+                };
+                let preview = over_background(&colored, self.background);
+                if let Err(err) = self.print(&preview) {
+                    eprintln!("Warning: subtitle {}: {err}", index + 1);
+                }
+            }
+            EventPayload::Text(ref text) => println!("{text}"),
+        }
+        return Ok(());
+    }
+}
+
+/// Saves each image event as `<index>.png` (numbered from 1, zero-padded) in
+/// a directory, which is created if needed. Text events are skipped. Events
+/// with [`SubtitleEvent::indexed`] set are saved as indexed PNGs.
+#[derive(Debug)]
+pub struct ImageDirSink {
+    directory: PathBuf,
+    created: bool,
+    /// Every event so far, written to [`MANIFEST_FILE`] when finished
+    #[cfg(feature = "writers")]
+    manifest: Option<SubtitleDocument>,
+}
+impl ImageDirSink {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        return Self {
+            directory: directory.into(),
+            created: false,
+            #[cfg(feature = "writers")]
+            manifest: None,
+        };
+    }
+
+    /// Also writes a [`MANIFEST_FILE`] listing every event (text ones
+    /// included) in `track`, with the file name of its image and its timing
+    /// and position, so the images can be recognized later on their own
+    #[cfg(feature = "writers")]
+    pub fn with_manifest(mut self, source: Option<String>, track: SubtitleTrack) -> Self {
+        self.manifest = Some(SubtitleDocument {
+            source,
+            tracks: vec![track],
+        });
+        return self;
+    }
+
+    fn create_directory(&mut self) -> Result<(), SinkError> {
+        if !self.created {
+            fs::create_dir_all(&self.directory)?;
+            self.created = true;
+        }
+        return Ok(());
+    }
+}
+impl EventSink for ImageDirSink {
+    fn event(&mut self, index: usize, event: &SubtitleEvent) -> Result<(), SinkError> {
+        let name = format!("{:05}.png", index + 1);
+        #[cfg(feature = "writers")]
+        if let Some(ref mut manifest) = self.manifest {
+            manifest.tracks[0]
+                .events
+                .push(crate::model::SubtitleEvent::from_extracted(event, &name));
+        }
+        let EventPayload::Image(ref image) = event.payload else {
+            return Ok(());
+        };
+        self.create_directory()?;
+        let path = self.directory.join(name);
+        match event.indexed {
+            Some(ref indexed) => indexed.save_png(path)?,
+            None => image.save(path)?,
+        }
+        return Ok(());
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        #[cfg(feature = "writers")]
+        if let Some(manifest) = self.manifest.take() {
+            self.create_directory()?;
+            let file = fs::File::create(self.directory.join(MANIFEST_FILE))?;
+            manifest
+                .write_json(io::BufWriter::new(file))
+                .map_err(io::Error::from)?;
+        }
+        return Ok(());
+    }
+}
+
+/// Collects image events into the sheet, labelled with their number from 1
+#[cfg(feature = "writers")]
+impl EventSink for ContactSheet {
+    fn event(&mut self, index: usize, event: &SubtitleEvent) -> Result<(), SinkError> {
+        if let EventPayload::Image(ref image) = event.payload {
+            self.add(index + 1, event.start, image);
+        }
+        return Ok(());
+    }
+}
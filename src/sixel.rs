@@ -1,55 +1,61 @@
-use image::{Rgb, Rgba};
+//! Terminal previews using sixel graphics
 
-pub fn print_rgba_image(image: &image::ImageBuffer<Rgba<u8>, Vec<u8>>) {
-    let mut pix_buf: Vec<u8> = Vec::new();
-    for pixel in image.pixels() {
-        pix_buf.extend_from_slice(&pixel.0);
-    }
+use image::{ImageBuffer, Pixel, Rgb, Rgba};
+use thiserror::Error;
 
-    let encoder = sixel::encoder::Encoder::new().unwrap();
-    encoder
-        .encode_bytes(
-            sixel::encoder::QuickFrameBuilder::new()
-                .width(image.width() as _)
-                .height(image.height() as _)
-                .format(sixel_sys::PixelFormat::RGBA8888)
-                .pixels(pix_buf),
-        )
-        .unwrap();
+use crate::terminal::{PreviewWidth, fit, is_available};
+
+#[derive(Error, Debug)]
+pub enum SixelError {
+    #[error("Output is not a terminal, so images can't be previewed.")]
+    NotATerminal,
+    #[error("Failed to encode sixel image: {0:?}")]
+    Encoder(sixel::status::Error),
 }
 
-pub fn print_rgb_image(image: &image::ImageBuffer<Rgb<u8>, Vec<u8>>) {
-    let mut pix_buf: Vec<u8> = Vec::new();
-    for pixel in image.pixels() {
-        pix_buf.extend_from_slice(&pixel.0);
+fn print_pixels<P: Pixel<Subpixel = u8> + 'static>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    max_width: Option<PreviewWidth>,
+    format: sixel_sys::PixelFormat,
+) -> Result<(), SixelError> {
+    // Sixel escapes would only garble a pipe or file
+    if !is_available() {
+        return Err(SixelError::NotATerminal);
     }
+    let fitted = max_width.and_then(|max_width| fit(image, max_width.pixels()));
+    let image = fitted.as_ref().unwrap_or(image);
+    let pix_buf: Vec<u8> = image.as_raw().clone();
 
-    let encoder = sixel::encoder::Encoder::new().unwrap();
+    let encoder = sixel::encoder::Encoder::new().map_err(SixelError::Encoder)?;
     encoder
         .encode_bytes(
             sixel::encoder::QuickFrameBuilder::new()
                 .width(image.width() as _)
                 .height(image.height() as _)
-                .format(sixel_sys::PixelFormat::RGB888)
+                .format(format)
                 .pixels(pix_buf),
         )
-        .unwrap();
+        .map_err(SixelError::Encoder)?;
+    return Ok(());
 }
 
-pub fn print_gray_image(image: &image::GrayImage) {
-    let mut pix_buf: Vec<u8> = Vec::new();
-    for pixel in image.pixels() {
-        pix_buf.extend_from_slice(&pixel.0);
-    }
+pub fn print_rgba_image(
+    image: &image::ImageBuffer<Rgba<u8>, Vec<u8>>,
+    max_width: Option<PreviewWidth>,
+) -> Result<(), SixelError> {
+    return print_pixels(image, max_width, sixel_sys::PixelFormat::RGBA8888);
+}
 
-    let encoder = sixel::encoder::Encoder::new().unwrap();
-    encoder
-        .encode_bytes(
-            sixel::encoder::QuickFrameBuilder::new()
-                .width(image.width() as _)
-                .height(image.height() as _)
-                .format(sixel_sys::PixelFormat::G8)
-                .pixels(pix_buf),
-        )
-        .unwrap();
+pub fn print_rgb_image(
+    image: &image::ImageBuffer<Rgb<u8>, Vec<u8>>,
+    max_width: Option<PreviewWidth>,
+) -> Result<(), SixelError> {
+    return print_pixels(image, max_width, sixel_sys::PixelFormat::RGB888);
+}
+
+pub fn print_gray_image(
+    image: &image::GrayImage,
+    max_width: Option<PreviewWidth>,
+) -> Result<(), SixelError> {
+    return print_pixels(image, max_width, sixel_sys::PixelFormat::G8);
 }
@@ -0,0 +1,142 @@
+//! Minimal SubRip (SRT) reading and writing.
+
+use std::io::{self, Write};
+
+use crate::{
+    timebase::Timestamp,
+    writer::{SubtitleWriter, WriterError},
+};
+
+#[derive(Debug, Clone)]
+pub struct SrtCue {
+    /// Nanoseconds
+    pub start: u64,
+    /// Nanoseconds
+    pub end: u64,
+    pub text: String,
+}
+
+/// Formats nanoseconds as `HH:MM:SS,mmm`
+pub fn format_timestamp(ns: u64) -> String {
+    return Timestamp::from_nanos(ns).to_srt();
+}
+
+/// Sorts cues by start time, then end time. The sort is stable, so cues that
+/// tie on both (like the regions of one image) stay in the order they were
+/// recognized in, and the same input always gives the same output.
+pub fn sort_cues(cues: &mut [SrtCue]) {
+    cues.sort_by_key(|cue| (cue.start, cue.end));
+}
+
+/// Removes `{\an8}`-style override tags and `<i>`-style HTML tags, leaving
+/// the text that's read
+pub fn strip_tags(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut closing = None;
+    for c in text.chars() {
+        match (closing, c) {
+            (None, '{') => closing = Some('}'),
+            (None, '<') => closing = Some('>'),
+            (None, c) => stripped.push(c),
+            (Some(close), c) if c == close => closing = None,
+            _ => {}
+        }
+    }
+    return stripped;
+}
+
+/// Writes cues in order, numbering them from 1. Blank cues are skipped, since
+/// most players treat a blank line as the end of the cue.
+pub fn write_srt<W: Write>(out: W, cues: &[SrtCue]) -> io::Result<()> {
+    let mut writer = SrtWriter::new(out);
+    for cue in cues {
+        writer.write_cue(cue)?;
+    }
+    return writer.out.flush();
+}
+
+/// [`write_srt`] one cue at a time
+pub struct SrtWriter<W> {
+    out: W,
+    /// Number of the next cue
+    index: usize,
+}
+impl<W: Write> SrtWriter<W> {
+    pub fn new(out: W) -> Self {
+        return Self { out, index: 1 };
+    }
+
+    fn write_cue(&mut self, cue: &SrtCue) -> io::Result<()> {
+        let text = cue.text.trim();
+        if text.is_empty() {
+            return Ok(());
+        }
+        writeln!(self.out, "{}", self.index)?;
+        writeln!(
+            self.out,
+            "{} --> {}",
+            format_timestamp(cue.start),
+            format_timestamp(cue.end)
+        )?;
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            writeln!(self.out, "{line}")?;
+        }
+        writeln!(self.out)?;
+        self.index += 1;
+        return Ok(());
+    }
+}
+impl<W: Write> SubtitleWriter for SrtWriter<W> {
+    fn write_event(&mut self, cue: &SrtCue) -> Result<(), WriterError> {
+        return Ok(self.write_cue(cue)?);
+    }
+
+    fn finish(&mut self) -> Result<(), WriterError> {
+        return Ok(self.out.flush()?);
+    }
+}
+
+/// Parses `HH:MM:SS,mmm` (or with a `.`) into nanoseconds
+pub fn parse_timestamp(text: &str) -> Option<u64> {
+    let (time, ms) = text.trim().split_once([',', '.'])?;
+    let mut parts = time.split(':');
+    let (hours, minutes, seconds) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || ms.len() != 3 {
+        return None;
+    }
+    let total_ms = hours
+        .parse::<u64>()
+        .ok()?
+        .checked_mul(3_600_000)?
+        .checked_add(minutes.parse::<u64>().ok()?.checked_mul(60_000)?)?
+        .checked_add(seconds.parse::<u64>().ok()?.checked_mul(1000)?)?
+        .checked_add(ms.parse::<u64>().ok()?)?;
+    return Some(Timestamp::from_millis(total_ms)?.as_nanos());
+}
+
+/// Reads the cues of an SRT file, keeping their tags. Blocks without a valid
+/// `start --> end` line are skipped, and the cue numbers aren't checked.
+pub fn parse_srt(text: &str) -> Vec<SrtCue> {
+    let text = text.trim_start_matches('\u{FEFF}').replace("\r\n", "\n");
+    let mut cues = Vec::new();
+    for block in text.split("\n\n") {
+        let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+        let Some(timing) = lines.next() else {
+            continue;
+        };
+        let Some((start, end)) = timing.split_once("-->") else {
+            continue;
+        };
+        // Positions can follow the end time
+        let end = end.split_whitespace().next().unwrap_or_default();
+        let (Some(start), Some(end)) = (parse_timestamp(start), parse_timestamp(end)) else {
+            continue;
+        };
+        cues.push(SrtCue {
+            start,
+            end,
+            text: lines.collect::<Vec<_>>().join("\n"),
+        });
+    }
+    return cues;
+}
@@ -0,0 +1,256 @@
+//! Demuxing MKVs that can't be seeked, like a pipe from `curl`. The demuxer
+//! wants to seek, mostly to the metadata listed in the SeekHead (some of it,
+//! like Cues, at the end of the file). So the metadata before the first
+//! cluster is read up front and given a SeekHead of its own that only lists
+//! what's there, and from the first cluster on only a small window of what
+//! was read is kept, for the short seeks back the demuxer makes while reading
+//! blocks.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::ebml::*;
+
+/// Bytes kept behind the read position
+const WINDOW: u64 = 64 * 1024;
+/// Bytes read from the stream at a time
+const CHUNK: usize = 64 * 1024;
+/// Top-level elements the demuxer looks up in the SeekHead
+const INDEXED: [u32; 4] = [ID_INFO, ID_TRACKS, ID_CHAPTERS, ID_TAGS];
+
+/// A non-seekable stream of an MKV, made seekable enough for
+/// `MatroskaFile::open`. Positions past the head are shifted from the
+/// original file's, and seeking back more than a little or from the end
+/// fails, so the Cues of the original are left out (making the demuxer's
+/// `seek` a linear scan).
+pub struct StreamInput<R> {
+    reader: R,
+    /// The rewritten metadata, up to the first cluster
+    head: Vec<u8>,
+    /// What's been read of the rest, starting at `buffer_start`
+    buffer: Vec<u8>,
+    buffer_start: u64,
+    position: u64,
+    eof: bool,
+}
+impl<R: Read> StreamInput<R> {
+    /// Reads the metadata before the first cluster
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let not_matroska = || io::Error::new(io::ErrorKind::InvalidData, "Not a Matroska file");
+        let ebml = match read_element(&mut reader) {
+            Ok(Some((ID_EBML, data))) => data,
+            Err(err)
+                if !matches!(
+                    err.kind(),
+                    io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+                ) =>
+            {
+                return Err(err);
+            }
+            _ => return Err(not_matroska()),
+        };
+        let (id, _) = read_id(&mut reader)?;
+        if id != ID_SEGMENT {
+            return Err(not_matroska());
+        }
+        read_size(&mut reader)?;
+
+        let mut elements = Vec::new();
+        let mut cluster = Vec::new();
+        loop {
+            let mut header = Vec::new();
+            let (id, size) = match read_header(&mut reader, &mut header) {
+                Ok(header) => header,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err),
+            };
+            if id == ID_CLUSTER {
+                cluster = header;
+                break;
+            }
+            if size == UNKNOWN_SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unknown-size element before the first cluster",
+                ));
+            }
+            let header_len = header.len() as u64;
+            let mut element = header;
+            (&mut reader).take(size).read_to_end(&mut element)?;
+            if element.len() as u64 != header_len + size {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            // Positions in these refer to the original file
+            if !matches!(id, ID_SEEK_HEAD | ID_CUES | ID_VOID) {
+                elements.push((id, element));
+            }
+        }
+
+        let mut head = encode_element(ID_EBML, &ebml);
+        head.extend(encode_id(ID_SEGMENT));
+        // Of unknown size, since the original's counts what was left out
+        head.extend([0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        head.extend(seek_head(&elements, !cluster.is_empty()));
+        for (_, element) in &elements {
+            head.extend_from_slice(element);
+        }
+        let buffer_start = head.len() as u64;
+        return Ok(Self {
+            reader,
+            head,
+            buffer: cluster,
+            buffer_start,
+            position: 0,
+            eof: false,
+        });
+    }
+
+    /// The EBML header and the segment's metadata (Info, Tracks,
+    /// Attachments and so on) as a small MKV without clusters, for
+    /// [`crate::attachments::read_attachments`] and the like
+    pub fn head(&self) -> &[u8] {
+        return &self.head;
+    }
+
+    /// Makes the buffer reach past `target` if the stream does, dropping
+    /// what's more than [`WINDOW`] behind it
+    fn fill(&mut self, target: u64) -> io::Result<()> {
+        let buffer_end = self.buffer_start + self.buffer.len() as u64;
+        let keep_from = target.saturating_sub(WINDOW).max(self.buffer_start);
+        if keep_from > buffer_end {
+            let skipped = io::copy(
+                &mut (&mut self.reader).take(keep_from - buffer_end),
+                &mut io::sink(),
+            )?;
+            self.eof = skipped < keep_from - buffer_end;
+            self.buffer.clear();
+            self.buffer_start = buffer_end + skipped;
+        } else {
+            self.buffer
+                .drain(..(keep_from - self.buffer_start) as usize);
+            self.buffer_start = keep_from;
+        }
+        while !self.eof && self.buffer_start + self.buffer.len() as u64 <= target {
+            let read = (&mut self.reader)
+                .take(CHUNK as u64)
+                .read_to_end(&mut self.buffer)?;
+            self.eof = read == 0;
+        }
+        return Ok(());
+    }
+}
+impl<R: Read> Read for StreamInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = match usize::try_from(self.position) {
+            Ok(position) if position < self.head.len() => &self.head[position..],
+            _ => {
+                if self.position >= self.buffer_start + self.buffer.len() as u64 {
+                    self.fill(self.position)?;
+                }
+                let offset = self.position.saturating_sub(self.buffer_start) as usize;
+                self.buffer.get(offset..).unwrap_or_default()
+            }
+        };
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.position += read as u64;
+        return Ok(read);
+    }
+}
+impl<R: Read> Seek for StreamInput<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Can't seek from the end of a stream",
+                ));
+            }
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start"))?;
+        if target >= self.head.len() as u64 && target < self.buffer_start {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Can't seek that far back in a stream",
+            ));
+        }
+        self.position = target;
+        return Ok(target);
+    }
+}
+
+/// Reads a whole element, or `None` at the end of the stream
+fn read_element<R: Read>(reader: &mut R) -> io::Result<Option<(u32, Vec<u8>)>> {
+    let mut header = Vec::new();
+    let (id, size) = match read_header(reader, &mut header) {
+        Ok(header) => header,
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    if size == UNKNOWN_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unknown-size elements are not supported",
+        ));
+    }
+    let mut data = Vec::new();
+    reader.take(size).read_to_end(&mut data)?;
+    if data.len() as u64 != size {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    return Ok(Some((id, data)));
+}
+
+/// Reads an element header, keeping its bytes in `bytes`
+fn read_header<R: Read>(reader: &mut R, bytes: &mut Vec<u8>) -> io::Result<(u32, u64)> {
+    let mut recorded = Recorder { reader, bytes };
+    let (id, _) = read_id(&mut recorded)?;
+    let (size, _) = read_size(&mut recorded)?;
+    return Ok((id, size));
+}
+
+/// Keeps a copy of everything read through it
+struct Recorder<'a, R> {
+    reader: &'a mut R,
+    bytes: &'a mut Vec<u8>,
+}
+impl<R: Read> Read for Recorder<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        self.bytes.extend_from_slice(&buf[..read]);
+        return Ok(read);
+    }
+}
+
+/// A SeekHead for `elements` laid out right after it, and for the first
+/// cluster after them. Positions are written 8 bytes wide so the SeekHead's
+/// size doesn't depend on them.
+fn seek_head(elements: &[(u32, Vec<u8>)], cluster: bool) -> Vec<u8> {
+    // Positions from the end of the SeekHead. The demuxer only reads the
+    // first of each element.
+    let mut targets: Vec<(u32, u64)> = Vec::new();
+    let mut position = 0;
+    for (id, element) in elements {
+        if INDEXED.contains(id) && targets.iter().all(|(target, _)| target != id) {
+            targets.push((*id, position));
+        }
+        position += element.len() as u64;
+    }
+    if cluster {
+        targets.push((ID_CLUSTER, position));
+    }
+    let entries = |offset: u64| {
+        let mut entries = Vec::new();
+        for (id, position) in &targets {
+            let mut seek = encode_element(ID_SEEK_ID, &encode_id(*id));
+            seek.extend(encode_element(
+                ID_SEEK_POSITION,
+                &(offset + position).to_be_bytes(),
+            ));
+            entries.extend(encode_element(ID_SEEK, &seek));
+        }
+        return encode_element(ID_SEEK_HEAD, &entries);
+    };
+    return entries(entries(0).len() as u64);
+}
@@ -0,0 +1,203 @@
+//! Fixing subtitles that drift from the audio, by lining them up against a
+//! reference: another subtitle track that's known to be in sync, or the
+//! speech intervals found by a voice activity detector. The correction is
+//! linear (an offset, and a scale for frame rate conversions like PAL
+//! speedup), chosen to minimize the share of time where exactly one of the
+//! two timelines shows something.
+
+use std::ops::Range;
+
+use crate::{srt::SrtCue, timebase::FrameRate};
+
+/// Scales tried besides 1: the ratios between the common frame rates, which
+/// is how far a track timed for one drifts when played at another
+const SCALES: [(FrameRate, FrameRate); 6] = [
+    (FrameRate::PAL, FrameRate::FILM_NTSC),
+    (FrameRate::FILM_NTSC, FrameRate::PAL),
+    (FrameRate::PAL, FrameRate::FILM),
+    (FrameRate::FILM, FrameRate::PAL),
+    (FrameRate::FILM, FrameRate::FILM_NTSC),
+    (FrameRate::FILM_NTSC, FrameRate::FILM),
+];
+/// Offset steps of the search, coarse to fine, in nanoseconds. Each pass
+/// searches one step of the previous pass either side of its best offset.
+const STEPS: [u64; 3] = [100_000_000, 10_000_000, 1_000_000];
+
+/// A linear timing correction, mapping `t` to `t * scale + offset`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Correction {
+    /// Nanoseconds
+    pub offset: i64,
+    pub scale: f64,
+}
+impl Default for Correction {
+    fn default() -> Self {
+        return Self {
+            offset: 0,
+            scale: 1.0,
+        };
+    }
+}
+impl Correction {
+    /// The corrected time, clamped to 0
+    pub fn apply(&self, ns: u64) -> u64 {
+        let corrected = (ns as f64 * self.scale).round() as i64 + self.offset;
+        return corrected.max(0) as u64;
+    }
+
+    pub fn apply_cues(&self, cues: &mut [SrtCue]) {
+        for cue in cues.iter_mut() {
+            cue.start = self.apply(cue.start);
+            cue.end = self.apply(cue.end);
+        }
+    }
+}
+
+/// How far to search, and whether to try other scales
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncOptions {
+    /// Largest offset tried either way, in nanoseconds
+    pub max_offset: u64,
+    /// Also try the frame rate conversion scales, not just shifting
+    pub scale: bool,
+}
+impl Default for SyncOptions {
+    fn default() -> Self {
+        return Self {
+            max_offset: 60_000_000_000,
+            scale: true,
+        };
+    }
+}
+
+/// The result of [`align`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Alignment {
+    pub correction: Correction,
+    /// How well the timelines agree before and after the correction, from 0
+    /// (never showing something at the same time) to 1 (identical)
+    pub before: f64,
+    pub after: f64,
+}
+
+/// Finds the correction that best lines `cues` up with `reference`, the
+/// intervals when something is being said. Use [`cue_intervals`] to align
+/// against another subtitle track.
+pub fn align(cues: &[SrtCue], reference: &[Range<u64>], options: SyncOptions) -> Alignment {
+    let reference = merged(reference.to_vec());
+    let intervals = cue_intervals(cues);
+    let mut scales = vec![1.0];
+    if options.scale {
+        scales.extend(
+            SCALES
+                .iter()
+                .map(|(from, to)| from.frames_per_second() / to.frames_per_second()),
+        );
+    }
+
+    let mut best = (0.0, Correction::default());
+    for scale in scales {
+        let scaled = merged(
+            intervals
+                .iter()
+                .map(|interval| {
+                    let correction = Correction { offset: 0, scale };
+                    return correction.apply(interval.start)..correction.apply(interval.end);
+                })
+                .collect(),
+        );
+        let mut offset = 0;
+        let mut reach = options.max_offset as i64;
+        let mut best_score = -1.0;
+        for step in STEPS {
+            let step = step as i64;
+            let center = offset;
+            let mut candidate = center - reach / step * step;
+            while candidate <= center + reach {
+                // Ties go to the smaller shift, so timelines that never
+                // overlap are left alone
+                let score = agreement(&scaled, &reference, candidate);
+                if score > best_score || (score == best_score && candidate.abs() < offset.abs()) {
+                    best_score = score;
+                    offset = candidate;
+                }
+                candidate += step;
+            }
+            reach = step;
+        }
+        if best_score > best.0 {
+            best = (best_score, Correction { offset, scale });
+        }
+    }
+
+    let correction = best.1;
+    let corrected: Vec<Range<u64>> = intervals
+        .iter()
+        .map(|interval| correction.apply(interval.start)..correction.apply(interval.end))
+        .collect();
+    return Alignment {
+        correction,
+        before: agreement(&merged(intervals), &reference, 0),
+        after: agreement(&merged(corrected), &reference, 0),
+    };
+}
+
+/// The intervals cues are shown for, skipping blank ones
+pub fn cue_intervals(cues: &[SrtCue]) -> Vec<Range<u64>> {
+    return cues
+        .iter()
+        .filter(|cue| !cue.text.trim().is_empty() && cue.end > cue.start)
+        .map(|cue| cue.start..cue.end)
+        .collect();
+}
+
+/// Sorts intervals and joins overlapping ones, so time shown by two cues at
+/// once counts once
+fn merged(mut intervals: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    intervals.retain(|interval| interval.end > interval.start);
+    intervals.sort_by_key(|interval| interval.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(intervals.len());
+    for interval in intervals {
+        match merged.last_mut() {
+            Some(last) if interval.start <= last.end => last.end = last.end.max(interval.end),
+            _ => merged.push(interval),
+        }
+    }
+    return merged;
+}
+
+fn total(intervals: &[Range<u64>]) -> u64 {
+    return intervals
+        .iter()
+        .map(|interval| interval.end - interval.start)
+        .sum();
+}
+
+/// Time both sorted, merged timelines show something, with `a` shifted by
+/// `offset`
+fn overlap(a: &[Range<u64>], b: &[Range<u64>], offset: i64) -> u64 {
+    let shift = |ns: u64| (ns as i64 + offset).max(0) as u64;
+    let (mut i, mut j) = (0, 0);
+    let mut overlap = 0;
+    while i < a.len() && j < b.len() {
+        let (start, end) = (shift(a[i].start), shift(a[i].end));
+        overlap += end.min(b[j].end).saturating_sub(start.max(b[j].start));
+        if end < b[j].end {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    return overlap;
+}
+
+/// Shared time over the average of both totals (the Dice coefficient), with
+/// `a` shifted by `offset`. Unlike the shared time alone, this doesn't favor
+/// stretching `a` to cover more.
+fn agreement(a: &[Range<u64>], b: &[Range<u64>], offset: i64) -> f64 {
+    let sum = total(a) + total(b);
+    if sum == 0 {
+        return 0.0;
+    }
+    return 2.0 * overlap(a, b, offset) as f64 / sum as f64;
+}
@@ -0,0 +1,191 @@
+//! Terminal handling shared by the preview backends: sizing, and a fallback
+//! that draws images with colored half-block characters for terminals without
+//! sixel support, like the Windows console host.
+
+use std::io::{self, IsTerminal, Write};
+
+use image::{ImageBuffer, Pixel, Rgb, Rgba, RgbaImage, imageops};
+
+/// Cell width assumed when the terminal doesn't report its size in pixels
+const DEFAULT_CELL_WIDTH: u32 = 10;
+/// Block previews are limited to this many columns unless told otherwise
+const DEFAULT_BLOCK_COLUMNS: u32 = 80;
+
+/// How images are drawn in the terminal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PreviewMode {
+    /// Sixel where the terminal is likely to support it, blocks otherwise
+    #[default]
+    Auto,
+    Sixel,
+    /// Unicode half blocks in 24-bit color, which any modern terminal shows
+    Blocks,
+}
+impl PreviewMode {
+    /// Picks a concrete mode for `Auto`
+    pub fn resolve(self) -> PreviewMode {
+        if self != PreviewMode::Auto {
+            return self;
+        }
+        if !cfg!(feature = "preview") {
+            return PreviewMode::Blocks;
+        }
+        // The console host can't show sixel; Windows Terminal sets WT_SESSION
+        if cfg!(windows) && std::env::var_os("WT_SESSION").is_none() {
+            return PreviewMode::Blocks;
+        }
+        return PreviewMode::Sixel;
+    }
+}
+
+/// Whether previews can be printed, i.e. stdout is a terminal rather than a
+/// pipe or file
+pub fn is_available() -> bool {
+    return io::stdout().is_terminal();
+}
+
+/// Upper bound on how wide previews are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewWidth {
+    Pixels(u32),
+    /// Terminal columns, converted using the cell size where the terminal
+    /// reports it
+    Columns(u32),
+}
+impl PreviewWidth {
+    /// Parses `<N>cols` or `<N>px`. A plain number is pixels.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (number, columns) = match value.strip_suffix("cols").or(value.strip_suffix("col")) {
+            Some(number) => (number, true),
+            None => (value.strip_suffix("px").unwrap_or(value), false),
+        };
+        let number: u32 = number.trim().parse().ok().filter(|number| *number > 0)?;
+        return Some(match columns {
+            true => PreviewWidth::Columns(number),
+            false => PreviewWidth::Pixels(number),
+        });
+    }
+
+    pub fn pixels(&self) -> u32 {
+        return match *self {
+            PreviewWidth::Pixels(pixels) => pixels,
+            PreviewWidth::Columns(columns) => columns * cell_width().unwrap_or(DEFAULT_CELL_WIDTH),
+        };
+    }
+
+    pub fn columns(&self) -> u32 {
+        return match *self {
+            PreviewWidth::Pixels(pixels) => {
+                (pixels / cell_width().unwrap_or(DEFAULT_CELL_WIDTH)).max(1)
+            }
+            PreviewWidth::Columns(columns) => columns,
+        };
+    }
+}
+
+/// Width of a terminal cell in pixels, if stdout is a terminal that says
+#[cfg(unix)]
+pub fn cell_width() -> Option<u32> {
+    // SAFETY: TIOCGWINSZ only writes a winsize to the pointer it's given
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 {
+        return None;
+    }
+    if size.ws_col == 0 || size.ws_xpixel == 0 {
+        return None;
+    }
+    return Some(size.ws_xpixel as u32 / size.ws_col as u32);
+}
+
+#[cfg(not(unix))]
+pub fn cell_width() -> Option<u32> {
+    return None;
+}
+
+/// Blends `image` onto a solid `background`, so antialiased edges look the
+/// way they would over video instead of having their alpha thrown away
+pub fn over_background(image: &RgbaImage, background: Rgb<u8>) -> RgbaImage {
+    return RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let Rgba([r, g, b, alpha]) = *image.get_pixel(x, y);
+        let blend = |over: u8, under: u8| {
+            return ((over as u32 * alpha as u32 + under as u32 * (255 - alpha as u32)) / 255)
+                as u8;
+        };
+        let Rgb([under_r, under_g, under_b]) = background;
+        return Rgba([blend(r, under_r), blend(g, under_g), blend(b, under_b), 255]);
+    });
+}
+
+/// Shrinks `image` to at most `max_width` pixels wide, keeping its aspect
+/// ratio. Images that already fit are left alone.
+pub(crate) fn fit<P: Pixel<Subpixel = u8> + 'static>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    max_width: u32,
+) -> Option<ImageBuffer<P, Vec<u8>>> {
+    if image.width() <= max_width {
+        return None;
+    }
+    let height = (image.height() as u64 * max_width as u64 / image.width() as u64).max(1);
+    return Some(imageops::resize(
+        image,
+        max_width,
+        height as u32,
+        imageops::FilterType::Triangle,
+    ));
+}
+
+/// Prints `image` with `▀` characters, each cell showing two pixels stacked
+/// vertically through its foreground and background colors. Alpha is ignored,
+/// so blend images with [`over_background`] first.
+pub fn print_blocks(image: &RgbaImage, max_width: Option<PreviewWidth>) -> io::Result<()> {
+    let columns = max_width.map_or(DEFAULT_BLOCK_COLUMNS, |width| width.columns());
+    let fitted = fit(image, columns);
+    let image = fitted.as_ref().unwrap_or(image);
+    enable_virtual_terminal();
+
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    for y in (0..image.height()).step_by(2) {
+        for x in 0..image.width() {
+            let top = image.get_pixel(x, y).0;
+            let bottom = match y + 1 < image.height() {
+                true => image.get_pixel(x, y + 1).0,
+                false => top,
+            };
+            write!(
+                out,
+                "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}",
+                top[0], top[1], top[2], bottom[0], bottom[1], bottom[2],
+            )?;
+        }
+        writeln!(out, "\x1b[0m")?;
+    }
+    return out.flush();
+}
+
+/// Turns on escape sequence handling, which the Windows console host leaves
+/// off for programs that don't ask
+#[cfg(windows)]
+fn enable_virtual_terminal() {
+    use std::ffi::c_void;
+
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetStdHandle(handle: u32) -> *mut c_void;
+        fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+    }
+    // SAFETY: the handle comes straight from GetStdHandle, and failures just
+    // leave the mode as it was
+    unsafe {
+        let console = GetStdHandle(STD_OUTPUT_HANDLE);
+        let mut mode = 0;
+        if GetConsoleMode(console, &mut mode) != 0 {
+            SetConsoleMode(console, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING);
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn enable_virtual_terminal() {}
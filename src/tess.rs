@@ -680,54 +680,89 @@ Public License instead of this License.  But first, please read
 <https://www.gnu.org/licenses/why-not-lgpl.html>.
 */
 
-use std::{cell::RefCell, io::Cursor};
+use std::io::Cursor;
 
-use image::{DynamicImage, GrayImage};
+use std::path::PathBuf;
+
+use image::GrayImage;
 use leptess::{LepTess, Variable};
 
-thread_local! {
-    static TESSERACT: RefCell<Option<TesseractWrapper>> = const { RefCell::new(None) };
+use crate::ocr::{OcrConstraints, OcrEngine, OcrError, Recognition};
+
+/// [`OcrEngine`] backed by Tesseract through leptess. Not `Send`, so create
+/// one per thread.
+///
+/// Tesseract runs OpenMP threads of its own, which only slow it down next
+/// to one engine per thread. Set `OMP_THREAD_LIMIT=1` in the environment
+/// before starting any threads, as `subproc` does in `main`; changing the
+/// environment once threads are running isn't sound.
+pub struct TesseractEngine {
+    tesseract: TesseractWrapper,
+}
+impl TesseractEngine {
+    /// `language` is a Tesseract language code, e.g. `eng`
+    pub fn new(language: &str) -> Result<Self, OcrError> {
+        return Self::with_constraints(language, &OcrConstraints::default());
+    }
+
+    pub fn with_constraints(
+        language: &str,
+        constraints: &OcrConstraints,
+    ) -> Result<Self, OcrError> {
+        let mut config = vec![(
+            Variable::TesseditCharBlacklist,
+            constraints
+                .blacklist
+                .clone()
+                .unwrap_or_else(|| String::from("|\\/`_~!")),
+        )];
+        if let Some(ref whitelist) = constraints.whitelist {
+            config.push((Variable::TesseditCharWhitelist, whitelist.clone()));
+        }
+        if let Some(mode) = constraints.page_seg_mode {
+            config.push((Variable::TesseditPagesegMode, mode.to_string()));
+        }
+        let tesseract = TesseractWrapper::new(tessdata_dir().as_deref(), language, &config)?;
+        return Ok(Self { tesseract });
+    }
 }
+impl OcrEngine for TesseractEngine {
+    fn recognize(&mut self, image: &GrayImage) -> Result<String, OcrError> {
+        self.tesseract.set_image(image, 150)?;
+        return self.tesseract.get_text();
+    }
 
-pub fn process<Img>(images: Img) -> Vec<String>
-where
-    Img: IntoIterator<Item = GrayImage>,
-{
-    unsafe {
-        std::env::set_var("OMP_THREAD_LIMIT", "1");
+    fn recognize_scored(&mut self, image: &GrayImage) -> Result<Recognition, OcrError> {
+        let text = self.recognize(image)?;
+        return Ok(Recognition {
+            text,
+            confidence: Some(self.tesseract.leptess.mean_text_conf() as f32),
+        });
     }
+}
 
-    // Init tesseract on the main thread:
-    let tesseract = TesseractWrapper::new(
-        None,
-        "eng",
-        &vec![(
-            leptess::Variable::TesseditCharBlacklist,
-            String::from("|\\/`_~!"),
-        )],
-    );
-    if TESSERACT.replace(Some(tesseract)).is_some() {
-        panic!();
-    };
-
-    // Process images
-    let subs = images
-        .into_iter()
-        .map(|image| {
-            TESSERACT.with(|tesseract| {
-                let mut tesseract = tesseract.borrow_mut();
-                let tesseract = tesseract.as_mut().unwrap();
-                tesseract.set_image(image, 150);
-                tesseract.get_text()
-            })
-        })
-        .collect::<Vec<String>>();
-
-    if let Some(tesseract) = TESSERACT.take() {
-        drop(tesseract);
+/// Where Tesseract's language data is, if it won't find it by itself. The
+/// Windows installers put it next to the program without setting
+/// `TESSDATA_PREFIX`, so the usual install locations are checked there.
+fn tessdata_dir() -> Option<String> {
+    if !cfg!(windows) || std::env::var_os("TESSDATA_PREFIX").is_some() {
+        return None;
     }
+    let install_dirs = [
+        std::env::var_os("ProgramFiles").map(PathBuf::from),
+        std::env::var_os("ProgramFiles(x86)").map(PathBuf::from),
+        std::env::var_os("LOCALAPPDATA").map(|path| PathBuf::from(path).join("Programs")),
+    ];
+    return install_dirs
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join("Tesseract-OCR").join("tessdata"))
+        .find(|dir| dir.is_dir())
+        .and_then(|dir| dir.to_str().map(str::to_owned));
+}
 
-    subs
+fn engine_error<E: std::fmt::Display>(err: E) -> OcrError {
+    return OcrError::Engine(err.to_string());
 }
 
 struct TesseractWrapper {
@@ -739,45 +774,43 @@ impl TesseractWrapper {
         datapath: Option<&str>,
         language: impl AsRef<str>,
         config: &[(Variable, String)],
-    ) -> Self {
-        let mut leptess = LepTess::new(datapath, language.as_ref()).unwrap();
+    ) -> Result<Self, OcrError> {
+        let mut leptess = LepTess::new(datapath, language.as_ref()).map_err(engine_error)?;
         // Disable learning by default, though a user could re-enable this
         // option with `-c`. We turn this off since we are are multithreading,
         // so this option would result in non-deterministic output.
         leptess
             .set_variable(leptess::Variable::ClassifyEnableLearning, "1")
-            .unwrap();
+            .map_err(engine_error)?;
         // 6 is PSM_SINGLE_BLOCK. We have preprocessed the input into individual
         // lines, and telling Tesseract this fact greatly improves accuracy.
         leptess
             .set_variable(leptess::Variable::TesseditPagesegMode, "6")
-            .unwrap();
+            .map_err(engine_error)?;
         // Avoid than tesseract tried to invert the image
         leptess
             .set_variable(leptess::Variable::TesseditDoInvert, "0")
-            .unwrap();
+            .map_err(engine_error)?;
         // Add user options.
         for (key, value) in config {
-            leptess.set_variable(*key, value).unwrap();
+            leptess.set_variable(*key, value).map_err(engine_error)?;
         }
-        Self { leptess }
+        Ok(Self { leptess })
     }
 
     /// Set the tesseract image to the given image's contents.
-    fn set_image(&mut self, image: GrayImage, dpi: i32) {
-        let bytes = {
-            let mut bytes: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-            DynamicImage::ImageLuma8(image)
-                .write_to(&mut bytes, image::ImageFormat::Pnm)
-                .unwrap();
-            bytes
-        };
-        self.leptess.set_image_from_mem(bytes.get_ref()).unwrap();
+    fn set_image(&mut self, image: &GrayImage, dpi: i32) -> Result<(), OcrError> {
+        let mut bytes: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        image.write_to(&mut bytes, image::ImageFormat::Pnm)?;
+        self.leptess
+            .set_image_from_mem(bytes.get_ref())
+            .map_err(engine_error)?;
         self.leptess.set_source_resolution(dpi);
+        Ok(())
     }
 
     /// Get text.
-    fn get_text(&mut self) -> String {
-        self.leptess.get_utf8_text().unwrap()
+    fn get_text(&mut self) -> Result<String, OcrError> {
+        self.leptess.get_utf8_text().map_err(engine_error)
     }
 }
@@ -0,0 +1,122 @@
+//! Clean-up applied to subtitle text before it's written. OCR and old
+//! authoring tools leave control characters, decomposed accents and a mix of
+//! quote styles behind, which some players render as boxes or choke on.
+//! Filters run in order, and embedding applications can add their own by
+//! implementing [`TextFilter`].
+
+use regex::Regex;
+use unicode_normalization::UnicodeNormalization;
+
+pub trait TextFilter {
+    fn apply(&self, text: &str) -> String;
+}
+
+/// Removes control and invisible formatting characters, keeping line breaks.
+/// Tabs become spaces.
+pub struct StripControl;
+impl TextFilter for StripControl {
+    fn apply(&self, text: &str) -> String {
+        return text
+            .chars()
+            .filter_map(|c| match c {
+                '\n' => Some('\n'),
+                '\t' => Some(' '),
+                // Zero-width space and the BOM. Joiners and direction marks
+                // are left alone, since some scripts need them.
+                '\u{200B}' | '\u{FEFF}' => None,
+                c if c.is_control() => None,
+                c => Some(c),
+            })
+            .collect();
+    }
+}
+
+/// Normalizes to Unicode NFC, so accented letters are single characters
+pub struct Nfc;
+impl TextFilter for Nfc {
+    fn apply(&self, text: &str) -> String {
+        return text.nfc().collect();
+    }
+}
+
+/// Replaces typographic quotes and ellipses with their ASCII forms, and
+/// closes up OCR's spaced-out ellipses (`. . .`)
+pub struct AsciiPunctuation;
+impl TextFilter for AsciiPunctuation {
+    fn apply(&self, text: &str) -> String {
+        let text: String = text
+            .chars()
+            .map(|c| match c {
+                '‘' | '’' | '‚' | '‛' | '′' => '\'',
+                '“' | '”' | '„' | '‟' | '″' => '"',
+                c => c,
+            })
+            .collect();
+        return text.replace('…', "...").replace(". . .", "...");
+    }
+}
+
+/// A regular expression replacement, with `$1`-style references to groups
+#[derive(Debug, Clone)]
+pub struct Replace {
+    pub pattern: Regex,
+    pub replacement: String,
+}
+impl Replace {
+    /// Parses `/PATTERN/REPLACEMENT/`, where any character can stand in
+    /// for `/` (e.g. `|a/b|c|`)
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let invalid = || format!("Expected /PATTERN/REPLACEMENT/: {expression}");
+        let delimiter = expression.chars().next().ok_or_else(invalid)?;
+        let parts: Vec<&str> = expression[delimiter.len_utf8()..]
+            .split(delimiter)
+            .collect();
+        let [pattern, replacement, ""] = parts[..] else {
+            return Err(invalid());
+        };
+        return Ok(Self {
+            pattern: Regex::new(pattern).map_err(|err| err.to_string())?,
+            replacement: replacement.to_owned(),
+        });
+    }
+}
+impl TextFilter for Replace {
+    fn apply(&self, text: &str) -> String {
+        return self
+            .pattern
+            .replace_all(text, self.replacement.as_str())
+            .into_owned();
+    }
+}
+
+pub struct FilterChain {
+    filters: Vec<Box<dyn TextFilter>>,
+}
+impl Default for FilterChain {
+    /// Control character removal and NFC, which are always safe
+    fn default() -> Self {
+        return Self {
+            filters: vec![Box::new(StripControl), Box::new(Nfc)],
+        };
+    }
+}
+impl FilterChain {
+    /// A chain without any filters
+    pub fn empty() -> Self {
+        return Self {
+            filters: Vec::new(),
+        };
+    }
+
+    pub fn push<F: TextFilter + 'static>(&mut self, filter: F) {
+        self.filters.push(Box::new(filter));
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = text.to_owned();
+        for filter in self.filters.iter() {
+            text = filter.apply(&text);
+        }
+        return text;
+    }
+}
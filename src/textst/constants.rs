@@ -0,0 +1,12 @@
+pub const TEXTST_SEGMENT_TYPE_DSS: u8 = 0x81;
+pub const TEXTST_SEGMENT_TYPE_DPS: u8 = 0x82;
+
+pub const TEXTST_ESCAPE: u8 = 0x1B;
+
+pub const TEXTST_DATA_STRING: u8 = 0x01;
+pub const TEXTST_DATA_FONT_ID: u8 = 0x02;
+pub const TEXTST_DATA_FONT_STYLE: u8 = 0x03;
+pub const TEXTST_DATA_FONT_SIZE: u8 = 0x04;
+pub const TEXTST_DATA_FONT_COLOR: u8 = 0x05;
+pub const TEXTST_DATA_NEWLINE: u8 = 0x0A;
+pub const TEXTST_DATA_RESET_STYLE: u8 = 0x0B;
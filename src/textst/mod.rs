@@ -0,0 +1,432 @@
+//! This implements a parser for the S_HDMV/TEXTST (Blu-ray text subtitle) format.
+//! Unlike PGS, TextST carries the dialog as text along with styling information,
+//! so no OCR is required.
+//!
+//! A stream consists of a single Dialog Style Segment (carried in `CodecPrivate`
+//! when muxed into MKV), followed by one Dialog Presentation Segment per event.
+//!
+//! This code was implemented from the segment layout used by libbluray's
+//! `textst_decode.c`.
+
+use constants::{
+    TEXTST_DATA_FONT_COLOR, TEXTST_DATA_FONT_ID, TEXTST_DATA_FONT_SIZE, TEXTST_DATA_FONT_STYLE,
+    TEXTST_DATA_NEWLINE, TEXTST_DATA_RESET_STYLE, TEXTST_DATA_STRING, TEXTST_ESCAPE,
+    TEXTST_SEGMENT_TYPE_DPS, TEXTST_SEGMENT_TYPE_DSS,
+};
+#[cfg(feature = "demux-mkv")]
+use matroska_demuxer::Frame;
+pub use textst_types::{
+    DialogData, DialogPresentation, DialogRegion, DialogStyle, FontStyle, Rect, RegionStyle,
+    TextstPaletteEntry, UserStyle,
+};
+use thiserror::Error;
+
+use crate::{binary_reader::PacketReader, timebase::pts_to_ns};
+
+mod constants;
+mod textst_types;
+
+#[derive(Error, Debug)]
+pub enum TextstError {
+    #[error("Region style {region_style_id} referenced before being defined.")]
+    MissingRegionStyle { region_style_id: u8 },
+    #[error("Unknown TextST segment type {0:#04x}.")]
+    UnknownSegment(u8),
+    #[error("Invalid TextST segment found.")]
+    FormatError,
+}
+
+/// A run of text sharing the same inline style
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyledSpan {
+    pub text: String,
+    pub font_style: FontStyle,
+    pub font_size: u8,
+    pub font_palette_ref_id: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextstEventRegion {
+    pub forced: bool,
+    /// `None` if the stream never defined a dialog style
+    pub style: Option<RegionStyle>,
+    pub spans: Vec<StyledSpan>,
+}
+impl TextstEventRegion {
+    pub fn text(&self) -> String {
+        return self.spans.iter().map(|span| span.text.as_str()).collect();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TextstEvent {
+    /// Nanoseconds
+    pub start: u64,
+    /// Nanoseconds
+    pub end: u64,
+    pub regions: Vec<TextstEventRegion>,
+}
+impl TextstEvent {
+    pub fn forced(&self) -> bool {
+        return self.regions.iter().any(|region| region.forced);
+    }
+
+    /// Plain text of all regions, separated by line breaks
+    pub fn text(&self) -> String {
+        return self
+            .regions
+            .iter()
+            .map(|region| region.text())
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+}
+
+#[derive(Default)]
+pub struct TextstParser {
+    dialog_style: Option<DialogStyle>,
+}
+impl TextstParser {
+    pub fn new() -> Self {
+        return TextstParser::default();
+    }
+
+    /// Creates a parser using the Dialog Style Segment from an MKV track's `CodecPrivate`
+    pub fn with_codec_private(codec_private: &[u8]) -> Result<Self, TextstError> {
+        let mut parser = Self::new();
+        parser.process_segments(codec_private)?;
+        return Ok(parser);
+    }
+
+    pub fn dialog_style(&self) -> Option<&DialogStyle> {
+        return self.dialog_style.as_ref();
+    }
+
+    /// Reads every segment in `data`, returning the last dialog presentation found
+    pub fn process_segments(
+        &mut self,
+        data: &[u8],
+    ) -> Result<Option<DialogPresentation>, TextstError> {
+        let mut data = PacketReader::new(data);
+        let mut presentation = None;
+        while data.get_remaining_bytes() > 0 {
+            let segment_type = data.read_u8().ok_or(TextstError::FormatError)?;
+            let segment_size = data.read_u16().ok_or(TextstError::FormatError)?;
+            let segment = data
+                .take_bytes(segment_size as usize)
+                .ok_or(TextstError::FormatError)?;
+            match segment_type {
+                TEXTST_SEGMENT_TYPE_DSS => {
+                    self.dialog_style = Some(parse_dss(segment)?);
+                }
+                TEXTST_SEGMENT_TYPE_DPS => {
+                    let dps = parse_dps(segment)?;
+                    if let (Some(style), Some(palette)) =
+                        (&mut self.dialog_style, &dps.palette_update)
+                    {
+                        for entry in palette {
+                            match style
+                                .palette
+                                .iter_mut()
+                                .find(|e| e.palette_entry_id == entry.palette_entry_id)
+                            {
+                                Some(existing) => *existing = entry.clone(),
+                                None => style.palette.push(entry.clone()),
+                            }
+                        }
+                    }
+                    presentation = Some(dps);
+                }
+                other => return Err(TextstError::UnknownSegment(other)),
+            }
+        }
+        return Ok(presentation);
+    }
+
+    /// NOTE: This assumes frame times have already been scaled
+    #[cfg(feature = "demux-mkv")]
+    pub fn process_mkv_frame(&mut self, frame: &Frame) -> Result<Option<TextstEvent>, TextstError> {
+        let Some(dps) = self.process_segments(&frame.data)? else {
+            return Ok(None);
+        };
+        // The container's timing wins, but fall back to the PTS span if the block
+        // didn't carry a duration.
+        let duration = frame
+            .duration
+            .unwrap_or_else(|| pts_to_ns(dps.end_pts.saturating_sub(dps.start_pts)));
+        let mut event = self.to_event(&dps)?;
+        event.start = frame.timestamp;
+        event.end = frame.timestamp.saturating_add(duration);
+        return Ok(Some(event));
+    }
+
+    /// Resolves a dialog presentation's inline styles against the dialog style,
+    /// using the PTS values for timing.
+    pub fn to_event(&self, dps: &DialogPresentation) -> Result<TextstEvent, TextstError> {
+        let mut regions = Vec::new();
+        for region in dps.regions.iter() {
+            let style = match self.dialog_style {
+                Some(ref dialog_style) => Some(
+                    dialog_style
+                        .region_styles
+                        .iter()
+                        .find(|style| style.region_style_id == region.region_style_id)
+                        .ok_or(TextstError::MissingRegionStyle {
+                            region_style_id: region.region_style_id,
+                        })?
+                        .clone(),
+                ),
+                None => None,
+            };
+            regions.push(TextstEventRegion {
+                forced: region.forced,
+                spans: style_spans(style.as_ref(), &region.data),
+                style,
+            });
+        }
+        return Ok(TextstEvent {
+            start: pts_to_ns(dps.start_pts),
+            end: pts_to_ns(dps.end_pts),
+            regions,
+        });
+    }
+}
+
+/// Flattens inline data into spans, starting from the region's default style
+fn style_spans(region_style: Option<&RegionStyle>, data: &[DialogData]) -> Vec<StyledSpan> {
+    let default_span = StyledSpan {
+        text: String::new(),
+        font_style: region_style.map(|s| s.font_style).unwrap_or_default(),
+        font_size: region_style.map(|s| s.font_size).unwrap_or(0),
+        font_palette_ref_id: region_style.map(|s| s.font_palette_ref_id).unwrap_or(0),
+    };
+    let mut spans = Vec::new();
+    let mut current = default_span.clone();
+    for item in data {
+        match item {
+            DialogData::Text(text) => current.text.push_str(text),
+            DialogData::LineBreak => current.text.push('\n'),
+            DialogData::FontStyle { style, .. } => {
+                flush_span(&mut spans, &mut current);
+                current.font_style = *style;
+            }
+            DialogData::FontSize(size) => {
+                flush_span(&mut spans, &mut current);
+                current.font_size = *size;
+            }
+            DialogData::FontColor(color) => {
+                flush_span(&mut spans, &mut current);
+                current.font_palette_ref_id = *color;
+            }
+            DialogData::ResetStyle => {
+                flush_span(&mut spans, &mut current);
+                current = default_span.clone();
+            }
+            DialogData::FontId(_) => {}
+        }
+    }
+    flush_span(&mut spans, &mut current);
+    return spans;
+}
+
+/// Ends the current span (if it has any text) so a style change starts a new one
+fn flush_span(spans: &mut Vec<StyledSpan>, current: &mut StyledSpan) {
+    if !current.text.is_empty() {
+        let next = StyledSpan {
+            text: String::new(),
+            ..current.clone()
+        };
+        spans.push(std::mem::replace(current, next));
+    }
+}
+
+fn read_rect(data: &mut PacketReader) -> Result<Rect, TextstError> {
+    return Ok(Rect {
+        x: data.read_u16().ok_or(TextstError::FormatError)?,
+        y: data.read_u16().ok_or(TextstError::FormatError)?,
+        width: data.read_u16().ok_or(TextstError::FormatError)?,
+        height: data.read_u16().ok_or(TextstError::FormatError)?,
+    });
+}
+
+/// Reads a sign-magnitude value with the sign in the top bit
+fn read_delta_u16(data: &mut PacketReader) -> Result<i16, TextstError> {
+    let raw = data.read_u16().ok_or(TextstError::FormatError)?;
+    let magnitude = (raw & 0x7FFF) as i16;
+    return Ok(if raw & 0x8000 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    });
+}
+fn read_delta_u8(data: &mut PacketReader) -> Result<i8, TextstError> {
+    let raw = data.read_u8().ok_or(TextstError::FormatError)?;
+    let magnitude = (raw & 0x7F) as i8;
+    return Ok(if raw & 0x80 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    });
+}
+
+fn read_palette(data: &mut PacketReader) -> Result<Vec<TextstPaletteEntry>, TextstError> {
+    let length = data.read_u16().ok_or(TextstError::FormatError)?;
+    let mut entries = Vec::new();
+    for _ in 0..length / 5 {
+        entries.push(TextstPaletteEntry {
+            palette_entry_id: data.read_u8().ok_or(TextstError::FormatError)?,
+            luminance: data.read_u8().ok_or(TextstError::FormatError)?,
+            color_diff_red: data.read_u8().ok_or(TextstError::FormatError)?,
+            color_diff_blue: data.read_u8().ok_or(TextstError::FormatError)?,
+            transparency: data.read_u8().ok_or(TextstError::FormatError)?,
+        });
+    }
+    return Ok(entries);
+}
+
+/// Reads a 33-bit PTS stored in 5 bytes (7 reserved bits first)
+fn read_pts(data: &mut PacketReader) -> Result<u64, TextstError> {
+    let high = data.read_u8().ok_or(TextstError::FormatError)? as u64 & 0x1;
+    let low = data.read_u32().ok_or(TextstError::FormatError)? as u64;
+    return Ok(high << 32 | low);
+}
+
+fn parse_dss(data: &[u8]) -> Result<DialogStyle, TextstError> {
+    let mut data = PacketReader::new(data);
+    let player_style_flag = data.read_u8().ok_or(TextstError::FormatError)? & 0x80 > 0;
+    let number_of_region_styles = data.read_u8().ok_or(TextstError::FormatError)?;
+    let number_of_user_styles = data.read_u8().ok_or(TextstError::FormatError)?;
+
+    let mut region_styles = Vec::new();
+    for _ in 0..number_of_region_styles {
+        let region_style_id = data.read_u8().ok_or(TextstError::FormatError)?;
+        let region = read_rect(&mut data)?;
+        let background_palette_ref_id = data.read_u8().ok_or(TextstError::FormatError)?;
+        let _reserved = data.read_u8().ok_or(TextstError::FormatError)?;
+        let text_box = read_rect(&mut data)?;
+        region_styles.push(RegionStyle {
+            region_style_id,
+            region,
+            background_palette_ref_id,
+            text_box,
+            text_flow: data.read_u8().ok_or(TextstError::FormatError)?,
+            text_horizontal_alignment: data.read_u8().ok_or(TextstError::FormatError)?,
+            text_vertical_alignment: data.read_u8().ok_or(TextstError::FormatError)?,
+            line_space: data.read_u8().ok_or(TextstError::FormatError)?,
+            font_id_ref: data.read_u8().ok_or(TextstError::FormatError)?,
+            font_style: FontStyle::from_bits_truncate(
+                data.read_u8().ok_or(TextstError::FormatError)?,
+            ),
+            font_size: data.read_u8().ok_or(TextstError::FormatError)?,
+            font_palette_ref_id: data.read_u8().ok_or(TextstError::FormatError)?,
+            font_outline_palette_ref_id: data.read_u8().ok_or(TextstError::FormatError)?,
+            font_outline_thickness: data.read_u8().ok_or(TextstError::FormatError)?,
+        });
+    }
+
+    let mut user_styles = Vec::new();
+    for _ in 0..number_of_user_styles {
+        user_styles.push(UserStyle {
+            user_style_id: data.read_u8().ok_or(TextstError::FormatError)?,
+            region_horizontal_delta: read_delta_u16(&mut data)?,
+            region_vertical_delta: read_delta_u16(&mut data)?,
+            font_size_delta: read_delta_u8(&mut data)?,
+            text_box_horizontal_delta: read_delta_u16(&mut data)?,
+            text_box_vertical_delta: read_delta_u16(&mut data)?,
+            text_box_width_delta: read_delta_u16(&mut data)?,
+            text_box_height_delta: read_delta_u16(&mut data)?,
+            line_space_delta: read_delta_u8(&mut data)?,
+        });
+    }
+
+    let palette = read_palette(&mut data)?;
+    let number_of_dialog_presentation_segments = data.read_u16().unwrap_or(0);
+
+    return Ok(DialogStyle {
+        player_style_flag,
+        region_styles,
+        user_styles,
+        palette,
+        number_of_dialog_presentation_segments,
+    });
+}
+
+fn parse_dps(data: &[u8]) -> Result<DialogPresentation, TextstError> {
+    let mut data = PacketReader::new(data);
+    let start_pts = read_pts(&mut data)?;
+    let end_pts = read_pts(&mut data)?;
+    let palette_update_flag = data.read_u8().ok_or(TextstError::FormatError)? & 0x80 > 0;
+    let palette_update = if palette_update_flag {
+        Some(read_palette(&mut data)?)
+    } else {
+        None
+    };
+
+    let number_of_regions = data.read_u8().ok_or(TextstError::FormatError)?;
+    let mut regions = Vec::new();
+    for _ in 0..number_of_regions {
+        let flags = data.read_u8().ok_or(TextstError::FormatError)?;
+        let region_style_id = data.read_u8().ok_or(TextstError::FormatError)?;
+        let data_length = data.read_u16().ok_or(TextstError::FormatError)?;
+        let region_data = data
+            .take_bytes(data_length as usize)
+            .ok_or(TextstError::FormatError)?;
+        regions.push(DialogRegion {
+            continuous_present: flags & 0x80 > 0,
+            forced: flags & 0x40 > 0,
+            region_style_id,
+            data: parse_dialog_data(region_data)?,
+        });
+    }
+
+    return Ok(DialogPresentation {
+        start_pts,
+        end_pts,
+        palette_update,
+        regions,
+    });
+}
+
+fn parse_dialog_data(data: &[u8]) -> Result<Vec<DialogData>, TextstError> {
+    let mut data = PacketReader::new(data);
+    let mut items = Vec::new();
+    while let Some(code) = data.read_u8() {
+        if code != TEXTST_ESCAPE {
+            // Stray bytes outside of an escape sequence are skipped, like libbluray does
+            continue;
+        }
+        let data_type = data.read_u8().ok_or(TextstError::FormatError)?;
+        let length = data.read_u8().ok_or(TextstError::FormatError)?;
+        let payload = data
+            .take_bytes(length as usize)
+            .ok_or(TextstError::FormatError)?;
+        let mut payload = PacketReader::new(payload);
+        let item = match data_type {
+            TEXTST_DATA_STRING => {
+                DialogData::Text(String::from_utf8_lossy(payload.get_remainder()).into_owned())
+            }
+            TEXTST_DATA_FONT_ID => {
+                DialogData::FontId(payload.read_u8().ok_or(TextstError::FormatError)?)
+            }
+            TEXTST_DATA_FONT_STYLE => DialogData::FontStyle {
+                style: FontStyle::from_bits_truncate(
+                    payload.read_u8().ok_or(TextstError::FormatError)?,
+                ),
+                outline_palette_ref_id: payload.read_u8().ok_or(TextstError::FormatError)?,
+                outline_thickness: payload.read_u8().ok_or(TextstError::FormatError)?,
+            },
+            TEXTST_DATA_FONT_SIZE => {
+                DialogData::FontSize(payload.read_u8().ok_or(TextstError::FormatError)?)
+            }
+            TEXTST_DATA_FONT_COLOR => {
+                DialogData::FontColor(payload.read_u8().ok_or(TextstError::FormatError)?)
+            }
+            TEXTST_DATA_NEWLINE => DialogData::LineBreak,
+            TEXTST_DATA_RESET_STYLE => DialogData::ResetStyle,
+            // Unknown inline data is skipped using its length
+            _ => continue,
+        };
+        items.push(item);
+    }
+    return Ok(items);
+}
@@ -0,0 +1,103 @@
+use bitflags::bitflags;
+
+#[derive(Debug, Clone)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct FontStyle: u8 {
+        const BOLD             = 0b00000001;
+        const ITALIC           = 0b00000010;
+        const OUTLINE_BORDERED = 0b00000100;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RegionStyle {
+    pub region_style_id: u8,
+    pub region: Rect,
+    pub background_palette_ref_id: u8,
+    pub text_box: Rect,
+    pub text_flow: u8,
+    pub text_horizontal_alignment: u8,
+    pub text_vertical_alignment: u8,
+    pub line_space: u8,
+    pub font_id_ref: u8,
+    pub font_style: FontStyle,
+    pub font_size: u8,
+    pub font_palette_ref_id: u8,
+    pub font_outline_palette_ref_id: u8,
+    pub font_outline_thickness: u8,
+}
+
+/// User styles are stored as signed deltas against a region style
+#[derive(Debug, Clone)]
+pub struct UserStyle {
+    pub user_style_id: u8,
+    pub region_horizontal_delta: i16,
+    pub region_vertical_delta: i16,
+    pub font_size_delta: i8,
+    pub text_box_horizontal_delta: i16,
+    pub text_box_vertical_delta: i16,
+    pub text_box_width_delta: i16,
+    pub text_box_height_delta: i16,
+    pub line_space_delta: i8,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextstPaletteEntry {
+    pub palette_entry_id: u8,
+    pub luminance: u8,
+    pub color_diff_red: u8,
+    pub color_diff_blue: u8,
+    pub transparency: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct DialogStyle {
+    pub player_style_flag: bool,
+    pub region_styles: Vec<RegionStyle>,
+    pub user_styles: Vec<UserStyle>,
+    pub palette: Vec<TextstPaletteEntry>,
+    pub number_of_dialog_presentation_segments: u16,
+}
+
+/// One piece of inline dialog data. Text is interleaved with escape sequences
+/// that change the style for the remainder of the region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DialogData {
+    Text(String),
+    FontId(u8),
+    FontStyle {
+        style: FontStyle,
+        outline_palette_ref_id: u8,
+        outline_thickness: u8,
+    },
+    FontSize(u8),
+    FontColor(u8),
+    LineBreak,
+    ResetStyle,
+}
+
+#[derive(Debug, Clone)]
+pub struct DialogRegion {
+    pub continuous_present: bool,
+    pub forced: bool,
+    pub region_style_id: u8,
+    pub data: Vec<DialogData>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DialogPresentation {
+    /// 90kHz clock
+    pub start_pts: u64,
+    /// 90kHz clock
+    pub end_pts: u64,
+    pub palette_update: Option<Vec<TextstPaletteEntry>>,
+    pub regions: Vec<DialogRegion>,
+}
@@ -0,0 +1,321 @@
+//! Converting between the clocks subtitle formats count time in. The crate
+//! works in nanoseconds throughout; Blu-ray and DVD streams count ticks of
+//! the 90 kHz MPEG system clock, and BDN XML and other frame-based formats
+//! count video frames.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Ticks per second of the MPEG system clock
+pub const PTS_RATE: u64 = 90_000;
+/// PTS and DTS fields are 33 bits, and wrap around after about 26.5 hours
+pub const PTS_MASK: u64 = (1 << 33) - 1;
+
+const NANOS_PER_MILLI: u64 = 1_000_000;
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Converts 90 kHz ticks to nanoseconds, rounding down. Saturates rather
+/// than overflowing, which only ticks far beyond 33 bits could.
+pub fn pts_to_ns(pts: u64) -> u64 {
+    return Timestamp::from_pts(pts).as_nanos();
+}
+
+/// Converts nanoseconds to the nearest 90 kHz tick, wrapped to 33 bits the
+/// way PTS fields store it
+pub fn ns_to_pts(ns: u64) -> u64 {
+    return Timestamp::from_nanos(ns).to_pts();
+}
+
+/// A time (or duration) in nanoseconds, the crate's internal timebase.
+/// Converting from other clocks is checked or saturates instead of
+/// overflowing, and it formats the ways subtitle formats write times.
+/// Displays as `HH:MM:SS.mmm`.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Timestamp(u64);
+impl Timestamp {
+    pub const ZERO: Self = Self(0);
+    pub const MAX: Self = Self(u64::MAX);
+
+    pub const fn from_nanos(ns: u64) -> Self {
+        return Self(ns);
+    }
+
+    pub const fn as_nanos(self) -> u64 {
+        return self.0;
+    }
+
+    /// `None` if it's too far out to count in nanoseconds
+    pub const fn from_millis(ms: u64) -> Option<Self> {
+        return match ms.checked_mul(NANOS_PER_MILLI) {
+            Some(ns) => Some(Self(ns)),
+            None => None,
+        };
+    }
+
+    pub const fn as_millis(self) -> u64 {
+        return self.0 / NANOS_PER_MILLI;
+    }
+
+    /// Rounds to the nearest nanosecond. `None` for negative, NaN or
+    /// infinite seconds, and ones too far out to count in nanoseconds.
+    pub fn from_secs_f64(seconds: f64) -> Option<Self> {
+        let ns = (seconds * NANOS_PER_SECOND as f64).round();
+        // Casting would quietly saturate
+        if !(0.0..u64::MAX as f64).contains(&ns) {
+            return None;
+        }
+        return Some(Self(ns as u64));
+    }
+
+    /// Matroska-style `ticks` of `scale` nanoseconds each (the segment's
+    /// `TimestampScale`). `None` if the product overflows, which only a
+    /// corrupt or hostile file gives.
+    pub const fn from_ticks(ticks: u64, scale: u64) -> Option<Self> {
+        return match ticks.checked_mul(scale) {
+            Some(ns) => Some(Self(ns)),
+            None => None,
+        };
+    }
+
+    /// 90 kHz ticks, rounding down. Saturates at [`Self::MAX`].
+    pub fn from_pts(pts: u64) -> Self {
+        let ns = pts as u128 * 100_000 / 9;
+        return Self(u64::try_from(ns).unwrap_or(u64::MAX));
+    }
+
+    /// The nearest 90 kHz tick, wrapped to 33 bits the way PTS fields store it
+    pub fn to_pts(self) -> u64 {
+        let ticks = (self.0 as u128 * 9 + 50_000) / 100_000;
+        return ticks as u64 & PTS_MASK;
+    }
+
+    pub const fn checked_add(self, other: Self) -> Option<Self> {
+        return match self.0.checked_add(other.0) {
+            Some(ns) => Some(Self(ns)),
+            None => None,
+        };
+    }
+
+    pub const fn checked_sub(self, other: Self) -> Option<Self> {
+        return match self.0.checked_sub(other.0) {
+            Some(ns) => Some(Self(ns)),
+            None => None,
+        };
+    }
+
+    pub const fn saturating_add(self, other: Self) -> Self {
+        return Self(self.0.saturating_add(other.0));
+    }
+
+    pub const fn saturating_sub(self, other: Self) -> Self {
+        return Self(self.0.saturating_sub(other.0));
+    }
+
+    /// Hours, minutes, seconds and the remaining nanoseconds
+    fn parts(self) -> (u64, u64, u64, u64) {
+        let seconds = self.0 / NANOS_PER_SECOND;
+        return (
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            self.0 % NANOS_PER_SECOND,
+        );
+    }
+
+    /// `HH:MM:SS,mmm`, as SRT writes it. Rounds down to the millisecond.
+    pub fn to_srt(self) -> String {
+        let (hours, minutes, seconds, ns) = self.parts();
+        let ms = ns / NANOS_PER_MILLI;
+        return format!("{hours:02}:{minutes:02}:{seconds:02},{ms:03}");
+    }
+
+    /// `HH:MM:SS.cc`, as SubViewer writes it. Rounds down to the
+    /// centisecond.
+    pub fn to_subviewer(self) -> String {
+        let (hours, minutes, seconds, ns) = self.parts();
+        let cs = ns / 10_000_000;
+        return format!("{hours:02}:{minutes:02}:{seconds:02}.{cs:02}");
+    }
+}
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (hours, minutes, seconds, ns) = self.parts();
+        let ms = ns / NANOS_PER_MILLI;
+        return write!(f, "{hours:02}:{minutes:02}:{seconds:02}.{ms:03}");
+    }
+}
+impl From<u64> for Timestamp {
+    fn from(ns: u64) -> Self {
+        return Self(ns);
+    }
+}
+impl From<Timestamp> for u64 {
+    fn from(timestamp: Timestamp) -> Self {
+        return timestamp.0;
+    }
+}
+
+/// A video frame rate, as a fraction so NTSC rates stay exact
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRate {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+impl FrameRate {
+    pub const FILM_NTSC: Self = Self::new(24_000, 1001);
+    pub const FILM: Self = Self::new(24, 1);
+    pub const PAL: Self = Self::new(25, 1);
+    pub const NTSC: Self = Self::new(30_000, 1001);
+    pub const PAL_FIELDS: Self = Self::new(50, 1);
+    pub const NTSC_FIELDS: Self = Self::new(60_000, 1001);
+    /// The rates Blu-ray allows, by their PGS frame rate code
+    const PGS_CODES: [(u8, Self); 6] = [
+        (0x10, Self::FILM_NTSC),
+        (0x20, Self::FILM),
+        (0x30, Self::PAL),
+        (0x40, Self::NTSC),
+        (0x60, Self::PAL_FIELDS),
+        (0x70, Self::NTSC_FIELDS),
+    ];
+
+    pub const fn new(numerator: u32, denominator: u32) -> Self {
+        return Self {
+            numerator,
+            denominator,
+        };
+    }
+
+    /// The rate a PGS presentation composition segment's frame rate byte
+    /// stands for, if it's one Blu-ray defines
+    pub fn from_pgs_code(code: u8) -> Option<Self> {
+        return Self::PGS_CODES
+            .iter()
+            .find(|(other, _)| *other == code)
+            .map(|(_, rate)| *rate);
+    }
+
+    /// The PGS frame rate byte for this rate, if Blu-ray allows it
+    pub fn pgs_code(&self) -> Option<u8> {
+        return Self::PGS_CODES
+            .iter()
+            .find(|(_, rate)| rate == self)
+            .map(|(code, _)| *code);
+    }
+
+    /// The rate with frames `duration` nanoseconds long (Matroska's
+    /// `DefaultDuration`). Durations within a microsecond of a standard rate
+    /// are taken as that rate, since they're stored rounded.
+    pub fn from_frame_duration(duration: u64) -> Option<Self> {
+        if duration == 0 {
+            return None;
+        }
+        for (_, rate) in Self::PGS_CODES {
+            if rate.frame_to_ns(1).abs_diff(duration) < 1_000 {
+                return Some(rate);
+            }
+        }
+        // Otherwise to the nearest thousandth of a frame per second
+        let numerator = u32::try_from((1_000_000_000_000 + duration / 2) / duration).ok()?;
+        if numerator == 0 {
+            return None;
+        }
+        return Some(Self::new(numerator, 1000).reduced());
+    }
+
+    fn reduced(self) -> Self {
+        let (mut a, mut b) = (self.numerator, self.denominator);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        return Self::new(self.numerator / a, self.denominator / a);
+    }
+
+    pub fn frames_per_second(&self) -> f64 {
+        return self.numerator as f64 / self.denominator as f64;
+    }
+
+    /// Frames per second as timecodes count them, e.g. 24 for 23.976
+    pub fn timecode_base(&self) -> u64 {
+        return (self.numerator as u64).div_ceil(self.denominator as u64);
+    }
+
+    /// The frame showing at `ns`, counting from 0 at the start
+    pub fn ns_to_frame(&self, ns: u64) -> u64 {
+        let ticks = ns as u128 * self.numerator as u128;
+        return (ticks / (1_000_000_000 * self.denominator as u128)) as u64;
+    }
+
+    /// The frame whose start is nearest to `ns`
+    pub fn nearest_frame(&self, ns: u64) -> u64 {
+        let ticks = 2 * ns as u128 * self.numerator as u128;
+        let frame = 1_000_000_000 * self.denominator as u128;
+        return ((ticks + frame) / (2 * frame)) as u64;
+    }
+
+    /// When `frame` starts, in nanoseconds. Rounded up, so converting back
+    /// gives the same frame.
+    pub fn frame_to_ns(&self, frame: u64) -> u64 {
+        let ns = frame as u128 * 1_000_000_000 * self.denominator as u128;
+        return ns.div_ceil(self.numerator as u128) as u64;
+    }
+
+    /// `ns` moved to the start of the nearest frame
+    pub fn snap(&self, ns: u64) -> u64 {
+        return self.frame_to_ns(self.nearest_frame(ns));
+    }
+
+    /// The non-drop-frame `HH:MM:SS:FF` timecode of the frame nearest to
+    /// `ns`, as BDN XML uses
+    pub fn timecode(&self, ns: u64) -> String {
+        let frame = self.nearest_frame(ns);
+        let base = self.timecode_base();
+        let seconds = frame / base;
+        return format!(
+            "{:02}:{:02}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            frame % base
+        );
+    }
+}
+impl fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.denominator == 1 {
+            return write!(f, "{}", self.numerator);
+        }
+        let rate = format!("{:.3}", self.frames_per_second());
+        return f.write_str(rate.trim_end_matches('0').trim_end_matches('.'));
+    }
+}
+impl FromStr for FrameRate {
+    type Err = String;
+
+    /// Takes a fraction (`24000/1001`) or a decimal, with the usual NTSC
+    /// decimals (`23.976`, `29.97` and `59.94`) read as their exact rates
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid frame rate: {value} (expected e.g. 25 or 24000/1001)");
+        if let Some((numerator, denominator)) = value.split_once('/') {
+            let numerator = numerator.trim().parse().map_err(|_| invalid())?;
+            let denominator = denominator.trim().parse().map_err(|_| invalid())?;
+            if numerator == 0 || denominator == 0 {
+                return Err(invalid());
+            }
+            return Ok(Self::new(numerator, denominator).reduced());
+        }
+        let rate: f64 = value.trim().parse().map_err(|_| invalid())?;
+        if !(rate > 0.0 && rate < 1000.0) {
+            return Err(invalid());
+        }
+        for (_, standard) in Self::PGS_CODES {
+            if (standard.frames_per_second() - rate).abs() < 0.01 {
+                return Ok(standard);
+            }
+        }
+        return Ok(Self::new((rate * 1000.0).round() as u32, 1000).reduced());
+    }
+}
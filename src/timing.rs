@@ -0,0 +1,96 @@
+//! Repairing cue timing before output. PGS durations often overlap once cues
+//! from several sources are combined (forced and full tracks, linked
+//! segments) or shifted, and some players drop or stack overlapping cues.
+
+use crate::srt::SrtCue;
+
+/// What to do with a cue that starts before the previous one ends
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// End the earlier cue where the later one starts
+    #[default]
+    Trim,
+    /// Show both texts together, for as long as either was shown
+    Merge,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimingRules {
+    pub overlaps: OverlapPolicy,
+    /// Nanoseconds left between consecutive cues, by ending the earlier one sooner
+    pub min_gap: u64,
+    /// Nanoseconds. Longer cues are cut short.
+    pub max_duration: Option<u64>,
+}
+
+/// How many cues [`repair_timing`] changed, by the kind of change
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimingReport {
+    pub trimmed: usize,
+    pub merged: usize,
+    pub capped: usize,
+}
+impl TimingReport {
+    pub fn is_empty(&self) -> bool {
+        return self.trimmed + self.merged + self.capped == 0;
+    }
+}
+
+/// Sorts `cues` by start time and applies `rules`. Blank cues are dropped
+/// first, so they don't push others around. Durations are capped before
+/// overlaps are resolved, so merged cues can end up longer than the cap.
+/// A cue that would be trimmed away entirely is merged into the next one
+/// instead, whatever the policy.
+pub fn repair_timing(cues: Vec<SrtCue>, rules: &TimingRules) -> (Vec<SrtCue>, TimingReport) {
+    let mut report = TimingReport::default();
+    let mut cues: Vec<SrtCue> = cues
+        .into_iter()
+        .filter(|cue| !cue.text.trim().is_empty())
+        .collect();
+    cues.sort_by_key(|cue| cue.start);
+
+    let mut repaired: Vec<SrtCue> = Vec::with_capacity(cues.len());
+    for mut cue in cues {
+        if let Some(max) = rules.max_duration
+            && cue.end.saturating_sub(cue.start) > max
+        {
+            cue.end = cue.start + max;
+            report.capped += 1;
+        }
+        let Some(previous) = repaired.last_mut() else {
+            repaired.push(cue);
+            continue;
+        };
+        let overlapping = cue.start < previous.end;
+        if overlapping && rules.overlaps == OverlapPolicy::Merge {
+            merge(previous, cue);
+            report.merged += 1;
+            continue;
+        }
+        let end = cue.start.saturating_sub(rules.min_gap);
+        if end >= previous.end {
+            repaired.push(cue);
+        } else if end > previous.start {
+            previous.end = end;
+            report.trimmed += 1;
+            repaired.push(cue);
+        } else {
+            merge(previous, cue);
+            report.merged += 1;
+        }
+    }
+    return (repaired, report);
+}
+
+/// Extends `into` to cover `cue`, adding its text unless `into` already
+/// shows every line of it (as with a line repeated across two cues)
+fn merge(into: &mut SrtCue, cue: SrtCue) {
+    into.end = into.end.max(cue.end);
+    let shown = cue
+        .text
+        .lines()
+        .all(|line| into.text.lines().any(|shown| shown.trim() == line.trim()));
+    if !shown {
+        into.text = format!("{}\n{}", into.text.trim_end(), cue.text.trim_start());
+    }
+}
@@ -0,0 +1,135 @@
+//! Rescales image events from the canvas they were authored for (the PGS
+//! composition size or VobSub idx size) to another resolution, e.g. when the
+//! video is being re-encoded at 720p or upscaled to 4K. Bitmaps, placement and
+//! regions are all scaled, so anything positioned off them stays in place.
+
+use image::imageops::{self, FilterType};
+
+use crate::{
+    extract::{EventPayload, SubtitleEvent},
+    indexed::IndexedImage,
+    preprocess::{Placement, Region},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScaleFilter {
+    #[default]
+    Bilinear,
+    /// Keeps hard edges, and is what indexed images always use since
+    /// palette indices can't be blended
+    Nearest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transform {
+    /// Target canvas size
+    pub width: u32,
+    pub height: u32,
+    pub filter: ScaleFilter,
+}
+impl Transform {
+    pub fn new(width: u32, height: u32) -> Self {
+        return Self {
+            width,
+            height,
+            filter: ScaleFilter::default(),
+        };
+    }
+
+    /// Scales an image event to the target canvas. Events without a
+    /// [`Placement`] don't say what canvas they're on, so they're returned
+    /// as-is, as are text events.
+    pub fn apply(&self, mut event: SubtitleEvent) -> SubtitleEvent {
+        let Some(placement) = event.placement else {
+            return event;
+        };
+        let EventPayload::Image(ref image) = event.payload else {
+            return event;
+        };
+        if (placement.screen_width, placement.screen_height) == (self.width, self.height)
+            || placement.screen_width == 0
+            || placement.screen_height == 0
+        {
+            return event;
+        }
+        let scale = Scale {
+            x: self.width as f64 / placement.screen_width as f64,
+            y: self.height as f64 / placement.screen_height as f64,
+        };
+        let width = scale.length_x(image.width());
+        let height = scale.length_y(image.height());
+        let filter = match self.filter {
+            ScaleFilter::Bilinear => FilterType::Triangle,
+            ScaleFilter::Nearest => FilterType::Nearest,
+        };
+        event.payload = EventPayload::Image(imageops::resize(image, width, height, filter));
+        event.placement = Some(Placement {
+            x: scale.position_x(placement.x),
+            y: scale.position_y(placement.y),
+            screen_width: self.width,
+            screen_height: self.height,
+        });
+        event.regions = event
+            .regions
+            .iter()
+            .map(|region| scale.region(*region, width, height))
+            .collect();
+        event.indexed = event
+            .indexed
+            .map(|indexed| resize_indexed(&indexed, width, height));
+        return event;
+    }
+}
+
+struct Scale {
+    x: f64,
+    y: f64,
+}
+impl Scale {
+    fn position_x(&self, x: u32) -> u32 {
+        return (x as f64 * self.x).round() as u32;
+    }
+
+    fn position_y(&self, y: u32) -> u32 {
+        return (y as f64 * self.y).round() as u32;
+    }
+
+    /// Lengths never scale to nothing, so visible content stays visible
+    fn length_x(&self, width: u32) -> u32 {
+        return self.position_x(width).max(1);
+    }
+
+    fn length_y(&self, height: u32) -> u32 {
+        return self.position_y(height).max(1);
+    }
+
+    /// Scales a region, keeping it inside an image of the given size
+    fn region(&self, region: Region, image_width: u32, image_height: u32) -> Region {
+        let x = self.position_x(region.x).min(image_width - 1);
+        let y = self.position_y(region.y).min(image_height - 1);
+        return Region {
+            x,
+            y,
+            width: self.length_x(region.width).min(image_width - x),
+            height: self.length_y(region.height).min(image_height - y),
+        };
+    }
+}
+
+/// Nearest neighbor resize, keeping the palette
+fn resize_indexed(image: &IndexedImage, width: u32, height: u32) -> IndexedImage {
+    let mut resized = IndexedImage::new(
+        width,
+        height,
+        image.palette.clone(),
+        image.source_palette.clone(),
+    );
+    for y in 0..height {
+        let source_y = (y as u64 * image.height as u64 / height as u64) as u32;
+        for x in 0..width {
+            let source_x = (x as u64 * image.width as u64 / width as u64) as u32;
+            resized.set_index(x, y, image.index(source_x, source_y));
+        }
+    }
+    return resized;
+}
@@ -2,61 +2,681 @@
 //!
 //! https://sam.zoy.org/writings/dvd/subtitles/
 
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::{self, Read},
+    sync::Arc,
+};
+
 use image::{Rgb, Rgba, RgbaImage};
 
 use thiserror::Error;
 
+use crate::{
+    binary_reader::BitReader,
+    indexed::IndexedImage,
+    program_stream::{PesPacket, ProgramStream, ProgramStreamError},
+    timebase::pts_to_ns,
+};
+
 #[derive(Error, Debug, Clone)]
 pub enum SubsError {
     #[error("The VobSub idx data is invalid.")]
     InvalidIdx,
+    #[error("The VobSub track has no idx data.")]
+    MissingIdx,
     #[error("Invalid VobSub frame header.")]
     InvalidFrameHeader,
     #[error("Invalid VobSub control data.")]
     InvalidControl,
+    #[error("VobSub control sequences loop back on themselves.")]
+    ControlLoop,
     #[error("Invalid VobSub frame data.")]
     InvalidFrame,
+    #[error("VobSub subpicture area ends before it starts: ({x1}, {y1}) to ({x2}, {y2}).")]
+    InvalidCoordinates { x1: u16, x2: u16, y1: u16, y2: u16 },
+    #[error("The idx points to a subpicture the .sub file doesn't have.")]
+    MissingSubpicture,
+    #[error(transparent)]
+    ProgramStream(#[from] ProgramStreamError),
+    #[error("Error reading the .sub file: {0}")]
+    Io(Arc<io::Error>),
 }
 
+/// Problems in a subpicture that didn't stop it from being decoded
+#[derive(Error, Debug, Clone)]
+pub enum ControlWarning {
+    #[error(
+        "Unknown VobSub control command {command:#04x} at offset {offset}; skipped the rest of its sequence."
+    )]
+    UnknownCommand { command: u8, offset: usize },
+}
+
+/// Contents of a VobSub `.idx` file (also used as the MKV CodecPrivate)
+#[derive(Debug, Clone)]
 pub struct IdxData {
     pub palette: [Rgb<u8>; 16],
+    /// Size of the video the subpictures are positioned on
+    pub size: Option<(u32, u32)>,
+    /// Offset added to subpicture positions
+    pub origin: (i32, i32),
+    /// Horizontal and vertical scaling, in percent
+    pub scale: (u32, u32),
+    /// Opacity, in percent
+    pub alpha: u32,
+    pub smooth: bool,
+    /// Milliseconds
+    pub fade_in: u32,
+    /// Milliseconds
+    pub fade_out: u32,
+    /// Nanoseconds added to every timestamp
+    pub time_offset: i64,
+    /// Only display subpictures flagged as forced
+    pub forced_only: bool,
+    /// Colors used instead of the ones each subpicture picks from `palette`
+    pub custom_colors: Option<CustomColors>,
+    /// Index into `languages` of the default stream
+    pub language_index: Option<usize>,
+    pub languages: Vec<IdxLanguage>,
+}
+impl IdxData {
+    /// Stands in for missing or unreadable idx data, with defaults for
+    /// everything but the palette
+    pub fn with_palette(palette: [Rgb<u8>; 16]) -> Self {
+        return Self {
+            palette,
+            size: None,
+            origin: (0, 0),
+            scale: (100, 100),
+            alpha: 100,
+            smooth: false,
+            fade_in: 0,
+            fade_out: 0,
+            time_offset: 0,
+            forced_only: false,
+            custom_colors: None,
+            language_index: None,
+            languages: Vec::new(),
+        };
+    }
+}
+
+/// The idx `custom colors:` directive, which replaces the colors and
+/// contrast subpictures select with four fixed colors, one per pixel value.
+/// Some authoring tools write it for discs whose own palette is unusable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CustomColors {
+    /// For the background, pattern and two emphasis pixel values, in that
+    /// order
+    pub colors: [Rgb<u8>; 4],
+    /// Which of the pixel values are fully transparent (`tridx`); the others
+    /// are opaque
+    pub transparent: [bool; 4],
+}
+impl CustomColors {
+    /// The colors as a 16 color idx palette, padded with black
+    fn palette(&self) -> [Rgb<u8>; 16] {
+        let mut palette = [Rgb([0, 0, 0]); 16];
+        palette[..4].copy_from_slice(&self.colors);
+        return palette;
+    }
+}
+
+/// Parses `ON, tridx: 1000, colors: 000000, ffffff, 808080, 202020`
+fn parse_custom_colors(value: &str) -> Option<CustomColors> {
+    let (_, rest) = value.split_once("tridx:")?;
+    let (tridx, colors) = rest.split_once("colors:")?;
+    let tridx = tridx.trim().trim_end_matches(',').trim();
+    if tridx.len() != 4 {
+        return None;
+    }
+    let mut transparent = [false; 4];
+    for (value, digit) in transparent.iter_mut().zip(tridx.chars()) {
+        *value = match digit {
+            '0' => false,
+            '1' => true,
+            _ => return None,
+        };
+    }
+    let colors: Vec<&str> = colors.split(',').collect();
+    if colors.len() != 4 {
+        return None;
+    }
+    let mut custom = CustomColors {
+        colors: [Rgb([0, 0, 0]); 4],
+        transparent,
+    };
+    for (color, hex) in custom.colors.iter_mut().zip(colors) {
+        hex::decode_to_slice(hex.trim(), &mut color.0).ok()?;
+    }
+    return Some(custom);
+}
+
+/// One subpicture stream listed in the `.idx`
+#[derive(Debug, Clone)]
+pub struct IdxLanguage {
+    /// Language code, e.g. `en`
+    pub id: String,
+    /// Subpicture stream number (0-31)
+    pub index: u8,
+    pub timestamps: Vec<IdxTimestamp>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IdxTimestamp {
+    /// Nanoseconds, with any `delay:` lines and the time offset applied
+    pub timestamp: i64,
+    /// Byte offset of the subpicture in the `.sub` file
+    pub file_position: u64,
+}
+
+/// Parses `HH:MM:SS:mmm`, optionally negative, into nanoseconds
+fn parse_idx_time(value: &str) -> Option<i64> {
+    let (sign, value) = match value.strip_prefix('-') {
+        Some(value) => (-1, value),
+        None => (1, value),
+    };
+    let mut parts = value.split(':').map(|part| part.trim().parse::<i64>().ok());
+    let (hours, minutes, seconds, millis) = (
+        parts.next()??,
+        parts.next()??,
+        parts.next()??,
+        parts.next()??,
+    );
+    let millis = hours
+        .checked_mul(60)?
+        .checked_add(minutes)?
+        .checked_mul(60)?
+        .checked_add(seconds)?
+        .checked_mul(1000)?
+        .checked_add(millis)?;
+    return millis.checked_mul(sign)?.checked_mul(1_000_000);
 }
+
+fn parse_pair<T: std::str::FromStr>(value: &str, separator: char) -> Option<(T, T)> {
+    let (a, b) = value.split_once(separator)?;
+    return Some((
+        a.trim().trim_end_matches('%').parse().ok()?,
+        b.trim().trim_end_matches('%').parse().ok()?,
+    ));
+}
+
+fn parse_switch(value: &str) -> bool {
+    return value.trim().eq_ignore_ascii_case("ON");
+}
+
 pub fn parse_idx(data: &[u8]) -> Result<IdxData, SubsError> {
-    for line in String::from_utf8_lossy(data).split("\n") {
-        if line.trim_start().starts_with("#") {
+    if data.iter().all(u8::is_ascii_whitespace) {
+        return Err(SubsError::MissingIdx);
+    }
+    let mut palette = None;
+    let mut idx = IdxData::with_palette([Rgb([0, 0, 0]); 16]);
+    // Delay applying to the following timestamps of the current language
+    let mut delay = 0;
+    for line in String::from_utf8_lossy(data).lines() {
+        let line = line.trim();
+        if line.starts_with("#") {
             continue;
         }
-        let (key, value) = line.split_once(": ").ok_or(SubsError::InvalidIdx)?;
-        if key == "palette" {
-            return Ok(IdxData {
-                palette: parse_palette(value).ok_or(SubsError::InvalidIdx)?,
-            });
+        // Unknown lines are ignored, since writers add their own
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        let invalid = || SubsError::InvalidIdx;
+        match key.trim() {
+            "palette" => palette = Some(parse_palette(value).ok_or_else(invalid)?),
+            "size" => idx.size = Some(parse_pair(value, 'x').ok_or_else(invalid)?),
+            "org" => idx.origin = parse_pair(value, ',').ok_or_else(invalid)?,
+            "scale" => idx.scale = parse_pair(value, ',').ok_or_else(invalid)?,
+            "alpha" => {
+                idx.alpha = value.trim_end_matches('%').parse().map_err(|_| invalid())?;
+            }
+            "smooth" => idx.smooth = parse_switch(value),
+            "fadein/out" => {
+                (idx.fade_in, idx.fade_out) = parse_pair(value, ',').ok_or_else(invalid)?
+            }
+            "time offset" => {
+                idx.time_offset = match value.parse::<i64>() {
+                    Ok(millis) => millis.checked_mul(1_000_000).ok_or_else(invalid)?,
+                    Err(_) => parse_idx_time(value).ok_or_else(invalid)?,
+                };
+            }
+            "forced subs" => idx.forced_only = parse_switch(value),
+            "custom colors" if parse_switch(value.split(',').next().unwrap_or_default()) => {
+                idx.custom_colors = Some(parse_custom_colors(value).ok_or_else(invalid)?);
+            }
+            "langidx" => idx.language_index = Some(value.parse().map_err(|_| invalid())?),
+            "id" => {
+                // id: en, index: 0
+                let (id, index) = value.split_once(',').ok_or_else(invalid)?;
+                let index = index
+                    .trim()
+                    .strip_prefix("index:")
+                    .and_then(|index| index.trim().parse().ok())
+                    .ok_or_else(invalid)?;
+                idx.languages.push(IdxLanguage {
+                    id: id.trim().to_owned(),
+                    index,
+                    timestamps: Vec::new(),
+                });
+                delay = 0;
+            }
+            "delay" => delay += parse_idx_time(value).ok_or_else(invalid)?,
+            "timestamp" => {
+                // timestamp: 00:00:01:000, filepos: 000000000
+                let (time, position) = value.split_once(',').ok_or_else(invalid)?;
+                let file_position = position
+                    .trim()
+                    .strip_prefix("filepos:")
+                    .and_then(|position| u64::from_str_radix(position.trim(), 16).ok())
+                    .ok_or_else(invalid)?;
+                let timestamp = parse_idx_time(time).ok_or_else(invalid)? + delay + idx.time_offset;
+                idx.languages
+                    .last_mut()
+                    .ok_or_else(invalid)?
+                    .timestamps
+                    .push(IdxTimestamp {
+                        timestamp,
+                        file_position,
+                    });
+            }
+            _ => {}
         }
     }
-    return Err(SubsError::InvalidIdx);
+    idx.palette = palette.ok_or(SubsError::InvalidIdx)?;
+    return Ok(idx);
 }
 
+/// Parses 16 comma-separated `rrggbb` colors, as in the idx `palette:` line
 pub fn parse_palette(palette: &str) -> Option<[Rgb<u8>; 16]> {
-    let segments = palette.split(",");
+    let segments: Vec<&str> = palette.split(",").collect();
+    if segments.len() != 16 {
+        return None;
+    }
     let mut palette = [Rgb::<u8>([0, 0, 0]); 16];
-    for (i, segment) in segments.enumerate() {
+    for (i, segment) in segments.iter().enumerate() {
         hex::decode_to_slice(segment.trim(), &mut palette[i].0).ok()?;
     }
     return Some(palette);
 }
 
+/// Formats a palette the way [`parse_palette`] reads it
+pub fn format_palette(palette: &[Rgb<u8>; 16]) -> String {
+    return palette
+        .iter()
+        .map(|color| hex::encode(color.0))
+        .collect::<Vec<_>>()
+        .join(", ");
+}
+
+/// Collects the pieces of a subpicture split across several MKV frames or PES
+/// packets, using the size in its header to tell when it's complete
+#[derive(Debug, Default)]
+pub struct SubpictureAssembler {
+    buffer: Vec<u8>,
+}
+impl SubpictureAssembler {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Whether part of a subpicture has been received
+    pub fn in_progress(&self) -> bool {
+        return !self.buffer.is_empty();
+    }
+
+    /// Adds the next piece, returning the subpicture once all of it has
+    /// arrived. Anything after the end of the subpicture is padding and gets
+    /// dropped. On error, the partial subpicture is discarded.
+    pub fn push(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>, SubsError> {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() < 4 {
+            return Ok(None);
+        }
+        let size = u16::from_be_bytes([self.buffer[0], self.buffer[1]]) as usize;
+        let control_offset = u16::from_be_bytes([self.buffer[2], self.buffer[3]]) as usize;
+        if size < 4 || control_offset >= size {
+            self.buffer.clear();
+            return Err(SubsError::InvalidFrameHeader);
+        }
+        if self.buffer.len() < size {
+            return Ok(None);
+        }
+        self.buffer.truncate(size);
+        return Ok(Some(std::mem::take(&mut self.buffer)));
+    }
+}
+
+/// A complete subpicture packet from a `.sub` file
+#[derive(Debug, Clone)]
+pub struct Subpicture {
+    /// Subpicture stream (0-31), matching the `id:` index in the `.idx`
+    pub stream: u8,
+    /// Nanoseconds, from the PTS of the packet the subpicture started in
+    pub timestamp: u64,
+    pub data: Vec<u8>,
+}
+
+/// Reads the subpictures of every stream in a VobSub `.sub` file (an MPEG-2
+/// program stream), reassembling ones split across PES packets
+pub struct SubFileReader<'a> {
+    stream: ProgramStream<'a>,
+    assemblers: Assemblers,
+}
+impl<'a> SubFileReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        return Self {
+            stream: ProgramStream::new(data),
+            assemblers: Assemblers::default(),
+        };
+    }
+}
+impl Iterator for SubFileReader<'_> {
+    type Item = Result<Subpicture, SubsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let packet = match self.stream.next()? {
+                Ok(packet) => packet,
+                Err(err) => return Some(Err(err.into())),
+            };
+            if let Some(result) = self.assemblers.push(&packet) {
+                return Some(result);
+            }
+        }
+    }
+}
+
+/// Size of a DVD sector, and so of the packs in a `.sub` file
+const SECTOR_SIZE: usize = 2048;
+
+/// [`SubFileReader`] for a `.sub` file that can only be read front to back,
+/// like a pipe. The file is read a DVD sector at a time, which the packs of
+/// `.sub` files are aligned to.
+pub struct SubStreamReader<R> {
+    reader: R,
+    assemblers: Assemblers,
+    /// Subpictures completed in the last sector, in order
+    ready: VecDeque<Result<Subpicture, SubsError>>,
+    done: bool,
+}
+impl<R: Read> SubStreamReader<R> {
+    pub fn new(reader: R) -> Self {
+        return Self {
+            reader,
+            assemblers: Assemblers::default(),
+            ready: VecDeque::new(),
+            done: false,
+        };
+    }
+}
+impl<R: Read> Iterator for SubStreamReader<R> {
+    type Item = Result<Subpicture, SubsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.ready.is_empty() && !self.done {
+            let mut sector = Vec::with_capacity(SECTOR_SIZE);
+            match (&mut self.reader)
+                .take(SECTOR_SIZE as u64)
+                .read_to_end(&mut sector)
+            {
+                Ok(read) => self.done = read < SECTOR_SIZE,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(SubsError::Io(Arc::new(err))));
+                }
+            }
+            for packet in ProgramStream::new(&sector) {
+                let result = match packet {
+                    Ok(packet) => self.assemblers.push(&packet),
+                    Err(err) => Some(Err(err.into())),
+                };
+                self.ready.extend(result);
+            }
+        }
+        return self.ready.pop_front();
+    }
+}
+
+/// A subpicture assembler per stream, along with the timestamp of the packet
+/// the subpicture in progress started in
+#[derive(Default)]
+struct Assemblers(HashMap<u8, (u64, SubpictureAssembler)>);
+impl Assemblers {
+    /// Adds a packet's payload to its stream's subpicture, returning the
+    /// subpicture if that completes it. Packets of other streams are ignored.
+    fn push(&mut self, packet: &PesPacket) -> Option<Result<Subpicture, SubsError>> {
+        // Subpicture streams are substreams 0x20-0x3F of private stream 1
+        let (&substream, payload) = packet.payload.split_first()?;
+        if !(0x20..0x40).contains(&substream) {
+            return None;
+        }
+        let stream = substream - 0x20;
+        let (timestamp, assembler) = self.0.entry(stream).or_default();
+        if !assembler.in_progress()
+            && let Some(pts) = packet.pts
+        {
+            *timestamp = pts_to_ns(pts);
+        }
+        return match assembler.push(payload) {
+            Ok(Some(data)) => Some(Ok(Subpicture {
+                stream,
+                timestamp: *timestamp,
+                data,
+            })),
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        };
+    }
+}
+
+/// Reads an idx/sub pair, decoding the subpictures of one language at a time
+/// at the positions the idx lists
+pub struct IdxSubReader<'a> {
+    idx: IdxData,
+    sub: &'a [u8],
+}
+impl<'a> IdxSubReader<'a> {
+    pub fn new(idx: &[u8], sub: &'a [u8]) -> Result<Self, SubsError> {
+        return Ok(Self::with_idx(parse_idx(idx)?, sub));
+    }
+
+    pub fn with_idx(idx: IdxData, sub: &'a [u8]) -> Self {
+        return Self { idx, sub };
+    }
+
+    pub fn idx(&self) -> &IdxData {
+        return &self.idx;
+    }
+
+    /// Index into [`IdxData::languages`] of the language `langidx` selects,
+    /// or the first one if it doesn't
+    pub fn default_language(&self) -> Option<usize> {
+        return match self.idx.language_index {
+            Some(index) if index < self.idx.languages.len() => Some(index),
+            _ if self.idx.languages.is_empty() => None,
+            _ => Some(0),
+        };
+    }
+
+    /// The subpictures of the default language; see [`Self::language_events`]
+    pub fn events(&self) -> IdxSubEvents<'_> {
+        return self.language_events(self.default_language().unwrap_or(usize::MAX));
+    }
+
+    /// The subpictures of `self.idx().languages[language]` in timestamp
+    /// order. Each comes with its timestamp (nanoseconds, with `delay:` and
+    /// the time offset applied, so possibly negative), its combined control
+    /// sequences, and the decoded image. Languages that don't exist have none.
+    pub fn language_events(&self, language: usize) -> IdxSubEvents<'_> {
+        let (stream, mut timestamps) = match self.idx.languages.get(language) {
+            Some(language) => (language.index, language.timestamps.clone()),
+            None => (0, Vec::new()),
+        };
+        timestamps.sort_by_key(|timestamp| timestamp.timestamp);
+        return IdxSubEvents {
+            reader: self,
+            stream,
+            timestamps: timestamps.into_iter(),
+        };
+    }
+
+    /// The first complete subpicture of `stream` at or after `position`
+    fn read_at(&self, position: u64, stream: u8) -> Result<Subpicture, SubsError> {
+        let data = usize::try_from(position)
+            .ok()
+            .and_then(|position| self.sub.get(position..))
+            .ok_or(SubsError::MissingSubpicture)?;
+        for subpicture in SubFileReader::new(data) {
+            let subpicture = subpicture?;
+            if subpicture.stream == stream {
+                return Ok(subpicture);
+            }
+        }
+        return Err(SubsError::MissingSubpicture);
+    }
+}
+
+/// Iterator returned by [`IdxSubReader::events`]. A subpicture that can't be
+/// read comes out as an error, and the ones after it are still read.
+pub struct IdxSubEvents<'a> {
+    reader: &'a IdxSubReader<'a>,
+    stream: u8,
+    timestamps: std::vec::IntoIter<IdxTimestamp>,
+}
+impl Iterator for IdxSubEvents<'_> {
+    type Item = Result<(i64, ControlData, RgbaImage), SubsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let timestamp = self.timestamps.next()?;
+        let result = self
+            .reader
+            .read_at(timestamp.file_position, self.stream)
+            .and_then(|subpicture| decode_frame(&self.reader.idx, &subpicture.data))
+            .map(|frame| {
+                let control = ControlData::from_sequences(&frame.sequences);
+                return (timestamp.timestamp, control, frame.image);
+            });
+        return Some(result);
+    }
+}
+
+/// Same as [`parse_frame`], but keeps the colors as indices into the idx
+/// palette; see [`IndexedImage::source_palette`] for the layout
+pub fn parse_frame_indexed(idx: &IdxData, file_data: &[u8]) -> Result<IndexedImage, SubsError> {
+    if file_data.len() < 4 {
+        return Err(SubsError::InvalidFrameHeader);
+    }
+    let control_offset = u16::from_be_bytes([file_data[2], file_data[3]]);
+    let (sequences, _) = parse_control(file_data, control_offset as usize)?;
+    let control = ControlData::from_sequences(&sequences);
+    check_control(&control)?;
+    return parse_data(idx, control, file_data).ok_or(SubsError::InvalidFrame);
+}
+
+/// A decoded subpicture along with its timing
+#[derive(Debug, Clone)]
+pub struct VobSubFrame {
+    pub image: RgbaImage,
+    /// Nanoseconds after the packet's timestamp that the subtitle appears
+    pub start: u64,
+    /// Nanoseconds after the packet's timestamp that the subtitle disappears,
+    /// if the packet says. Stop times that aren't after `start` are ignored.
+    pub end: Option<u64>,
+    pub forced: bool,
+    /// The raw control sequences, for callers that want to reproduce fades
+    /// and wipes rather than the final state rendered into `image`
+    pub sequences: Vec<ControlSequence>,
+    pub warnings: Vec<ControlWarning>,
+}
+
+/// Converts a control sequence delay (in units of 1024 ticks of the 90kHz
+/// clock) to nanoseconds
+fn delay_to_ns(delay: u16) -> u64 {
+    return pts_to_ns(delay as u64 * 1024);
+}
+
+/// Longest a subpicture without a stop time is assumed to stay up
+pub const MAX_INFERRED_DURATION: u64 = 10_000_000_000;
+
+/// When a subpicture shown at `start` without a stop time of its own ends,
+/// given that the next one is shown at `next`: then, or after
+/// [`MAX_INFERRED_DURATION`] if the next one is much later. `None` if the
+/// next one isn't later, as with duplicated packets.
+pub fn inferred_end(start: u64, next: u64) -> Option<u64> {
+    if next <= start {
+        return None;
+    }
+    return Some(next.min(start.saturating_add(MAX_INFERRED_DURATION)));
+}
+
 pub fn parse_frame(idx: &IdxData, file_data: &[u8]) -> Result<RgbaImage, SubsError> {
+    return Ok(decode_frame(idx, file_data)?.image);
+}
+
+/// Same as [`parse_frame`], but also returns the display times carried by the
+/// packet's control sequences
+pub fn decode_frame(idx: &IdxData, file_data: &[u8]) -> Result<VobSubFrame, SubsError> {
     if file_data.len() < 4 {
         return Err(SubsError::InvalidFrameHeader);
     }
     let _file_size = u16::from_be_bytes([file_data[0], file_data[1]]);
     let control_offset = u16::from_be_bytes([file_data[2], file_data[3]]);
 
-    let control =
-        parse_control(&file_data, control_offset as usize).ok_or(SubsError::InvalidControl)?;
-    return parse_data(&idx.palette, control, &file_data).ok_or(SubsError::InvalidFrame);
+    let (sequences, warnings) = parse_control(file_data, control_offset as usize)?;
+    let control = ControlData::from_sequences(&sequences);
+    let start = control.start_time.map(delay_to_ns).unwrap_or(0);
+    let end = control
+        .stop_time
+        .map(delay_to_ns)
+        .filter(|end| *end > start);
+    let forced = control.force;
+    check_control(&control)?;
+    let image = parse_data(idx, control, file_data)
+        .ok_or(SubsError::InvalidFrame)?
+        .to_rgba();
+    return Ok(VobSubFrame {
+        image,
+        start,
+        end,
+        forced,
+        sequences,
+        warnings,
+    });
 }
 
+/// A subpicture as each of its control sequences leaves it, for reproducing
+/// fades and wipes rather than just the final state: nanoseconds after the
+/// packet's timestamp, the image shown from then (`None` while it's hidden),
+/// and the control data in effect
+pub fn decode_states(
+    idx: &IdxData,
+    file_data: &[u8],
+) -> Result<Vec<(u64, Option<IndexedImage>, ControlData)>, SubsError> {
+    if file_data.len() < 4 {
+        return Err(SubsError::InvalidFrameHeader);
+    }
+    let control_offset = u16::from_be_bytes([file_data[2], file_data[3]]);
+    let (sequences, _) = parse_control(file_data, control_offset as usize)?;
+    let mut shown = false;
+    let mut states = Vec::with_capacity(sequences.len());
+    for (index, sequence) in sequences.iter().enumerate() {
+        shown = (shown || sequence.start_display) && !sequence.stop_display;
+        let control = ControlData::from_sequences(&sequences[..=index]);
+        check_control(&control)?;
+        let image = match shown {
+            true => {
+                Some(parse_data(idx, control.clone(), file_data).ok_or(SubsError::InvalidFrame)?)
+            }
+            false => None,
+        };
+        states.push((delay_to_ns(sequence.delay), image, control));
+    }
+    return Ok(states);
+}
+
+/// The area a subpicture covers on screen. Both corners are inclusive.
 #[derive(Debug, Clone)]
 pub struct Coordinates {
     pub x1: u16,
@@ -64,7 +684,31 @@ pub struct Coordinates {
     pub y1: u16,
     pub y2: u16,
 }
+impl Coordinates {
+    /// Width and height, or an error if the area is inverted
+    pub fn size(&self) -> Result<(u32, u32), SubsError> {
+        return match (self.x2.checked_sub(self.x1), self.y2.checked_sub(self.y1)) {
+            (Some(width), Some(height)) => Ok((width as u32 + 1, height as u32 + 1)),
+            _ => Err(SubsError::InvalidCoordinates {
+                x1: self.x1,
+                x2: self.x2,
+                y1: self.y1,
+                y2: self.y2,
+            }),
+        };
+    }
+}
+
+/// Catches control data [`parse_data`] can't make an image from for a
+/// specific reason
+fn check_control(control: &ControlData) -> Result<(), SubsError> {
+    if let Some(ref coordinates) = control.coordinates {
+        coordinates.size()?;
+    }
+    return Ok(());
+}
 
+/// Everything a subpicture's control sequences set, combined
 #[derive(Default, Debug, Clone)]
 pub struct ControlData {
     pub force: bool,
@@ -74,97 +718,229 @@ pub struct ControlData {
     pub alpha_palette: Option<[u8; 4]>,
     pub coordinates: Option<Coordinates>,
     pub rle_offsets: Option<(u16, u16)>,
+    pub color_changes: Vec<LineChange>,
+}
+impl ControlData {
+    /// Combines sequences in order, with later ones overriding earlier ones.
+    /// The start and stop times are the delays of the sequences that start and
+    /// stop displaying.
+    pub fn from_sequences(sequences: &[ControlSequence]) -> Self {
+        let mut control = ControlData::default();
+        for sequence in sequences {
+            control.force |= sequence.force;
+            if sequence.start_display {
+                control.start_time = Some(sequence.delay);
+            }
+            if sequence.stop_display {
+                control.stop_time = Some(sequence.delay);
+            }
+            if sequence.color_palette.is_some() {
+                control.color_palette = sequence.color_palette;
+            }
+            if sequence.alpha_palette.is_some() {
+                control.alpha_palette = sequence.alpha_palette;
+            }
+            if sequence.coordinates.is_some() {
+                control.coordinates = sequence.coordinates.clone();
+            }
+            if sequence.rle_offsets.is_some() {
+                control.rle_offsets = sequence.rle_offsets;
+            }
+            if let Some(ref changes) = sequence.color_changes {
+                control.color_changes = changes.clone();
+            }
+        }
+        return control;
+    }
+}
+
+/// A single control sequence (SP_DCSQ). Its commands take effect `delay`
+/// after the packet's timestamp.
+#[derive(Default, Debug, Clone)]
+pub struct ControlSequence {
+    /// In units of 1024 ticks of the 90kHz clock (~11.4ms)
+    pub delay: u16,
+    pub force: bool,
+    pub start_display: bool,
+    pub stop_display: bool,
+    pub color_palette: Option<[u8; 4]>,
+    pub alpha_palette: Option<[u8; 4]>,
+    pub coordinates: Option<Coordinates>,
+    pub rle_offsets: Option<(u16, u16)>,
+    pub color_changes: Option<Vec<LineChange>>,
+}
+
+/// Color and contrast overrides for a range of screen lines (CHG_COLCON).
+/// Changing these between sequences is how DVDs do fades and wipes.
+#[derive(Debug, Clone)]
+pub struct LineChange {
+    pub first_line: u16,
+    pub last_line: u16,
+    /// Sorted by column
+    pub columns: Vec<ColumnChange>,
+}
+
+/// From `column` (on screen) to the next change, a line uses these palettes
+#[derive(Debug, Clone)]
+pub struct ColumnChange {
+    pub column: u16,
+    pub color_palette: [u8; 4],
+    pub alpha_palette: [u8; 4],
+}
+
+/// Marks the end of the line controls in a CHG_COLCON command
+const LINE_CHANGES_END: u32 = 0x0FFFFFFF;
+
+fn nibbles(data: [u8; 2]) -> [u8; 4] {
+    return [data[0] >> 4, data[0] & 0xF, data[1] >> 4, data[1] & 0xF];
+}
+
+/// Parses the body of a CHG_COLCON command, after its size
+fn parse_color_changes(mut data: &[u8]) -> Option<Vec<LineChange>> {
+    let mut changes = Vec::new();
+    while data.len() >= 4 {
+        let line = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        data = &data[4..];
+        if line == LINE_CHANGES_END {
+            break;
+        }
+        let count = (line >> 12 & 0xF) as usize;
+        if data.len() < count * 6 {
+            return None;
+        }
+        let mut columns: Vec<ColumnChange> = data[..count * 6]
+            .chunks_exact(6)
+            .map(|change| ColumnChange {
+                column: u16::from_be_bytes([change[0], change[1]]) & 0xFFF,
+                color_palette: nibbles([change[2], change[3]]),
+                alpha_palette: nibbles([change[4], change[5]]),
+            })
+            .collect();
+        columns.sort_by_key(|change| change.column);
+        data = &data[count * 6..];
+        changes.push(LineChange {
+            first_line: (line >> 16 & 0xFFF) as u16,
+            last_line: (line & 0xFFF) as u16,
+            columns,
+        });
+    }
+    return Some(changes);
+}
+
+/// Length in bytes of the control command at `data[cursor]`, including the
+/// command byte. `None` for commands we don't know the length of.
+fn command_length(data: &[u8], cursor: usize) -> Option<usize> {
+    return match data[cursor] {
+        0x00 | 0x01 | 0x02 | 0xFF => Some(1),
+        0x03 | 0x04 => Some(3),
+        0x05 => Some(7),
+        0x06 => Some(5),
+        // Variable length; the size includes its own two bytes
+        0x07 => match data.get(cursor + 1..cursor + 3) {
+            Some(size) => Some(1 + (u16::from_be_bytes([size[0], size[1]]) as usize).max(2)),
+            None => Some(3),
+        },
+        _ => None,
+    };
 }
 
-fn parse_control(data: &[u8], mut cursor: usize) -> Option<ControlData> {
-    let mut control = ControlData::default();
+fn parse_control(
+    data: &[u8],
+    mut cursor: usize,
+) -> Result<(Vec<ControlSequence>, Vec<ControlWarning>), SubsError> {
+    let mut sequences = Vec::new();
+    let mut warnings = Vec::new();
+    let mut visited = HashSet::new();
     loop {
         if data.len() <= cursor + 4 {
-            return None;
+            return Err(SubsError::InvalidControl);
         }
         let this_sequence = cursor;
-        let offset_time = u16::from_be_bytes([data[cursor + 0], data[cursor + 1]]);
-        let next_control = u16::from_be_bytes([data[cursor + 2], data[cursor + 3]]);
+        visited.insert(this_sequence);
+        let offset_time = u16::from_be_bytes([data[cursor], data[cursor + 1]]);
+        let next_control = u16::from_be_bytes([data[cursor + 2], data[cursor + 3]]) as usize;
         cursor += 4;
+        let mut control = ControlSequence {
+            delay: offset_time,
+            ..Default::default()
+        };
         loop {
             if data.len() <= cursor {
-                return None;
+                return Err(SubsError::InvalidControl);
             }
             let command = data[cursor];
+            let Some(length) = command_length(data, cursor) else {
+                // Without a length there's no finding the next command, but
+                // the next sequence can still be found
+                warnings.push(ControlWarning::UnknownCommand {
+                    command,
+                    offset: cursor,
+                });
+                break;
+            };
+            if data.len() < cursor + length {
+                return Err(SubsError::InvalidControl);
+            }
+            let args = &data[cursor + 1..cursor + length];
             match command {
                 0x00 => {
                     // Force displaying
                     control.force = true;
-                    cursor += 1;
                 }
                 0x01 => {
                     // Start date
-                    control.start_time = Some(offset_time);
-                    cursor += 1;
+                    control.start_display = true;
                 }
                 0x02 => {
                     // Stop date
-                    control.stop_time = Some(offset_time);
-                    cursor += 1;
+                    control.stop_display = true;
                 }
                 0x03 => {
                     // Palette
-                    let mut colors = [0u8; 4];
-                    let mut nibbles = NibbleStream::new(&data[cursor + 1..cursor + 3]);
-                    for i in 0..4 {
-                        colors[i] = nibbles.take_nibble()?;
-                    }
-                    control.color_palette = Some(colors);
-                    cursor += 3;
+                    control.color_palette = Some(nibbles([args[0], args[1]]));
                 }
                 0x04 => {
                     // Alpha channel
-                    let mut alphas = [0u8; 4];
-                    let mut nibbles = NibbleStream::new(&data[cursor + 1..cursor + 3]);
-                    for i in 0..4 {
-                        alphas[i] = nibbles.take_nibble()?;
-                    }
-                    control.alpha_palette = Some(alphas);
-                    cursor += 3;
+                    control.alpha_palette = Some(nibbles([args[0], args[1]]));
                 }
                 0x05 => {
                     // Coordinates
-                    if data.len() <= cursor + 6 {
-                        return None;
-                    }
-                    let coordinates = Coordinates {
-                        x1: u16::from_be_bytes([data[cursor + 1], data[cursor + 2]]) >> 4 & 0xFFF,
-                        x2: u16::from_be_bytes([data[cursor + 2], data[cursor + 3]]) & 0xFFF,
-                        y1: u16::from_be_bytes([data[cursor + 4], data[cursor + 5]]) >> 4 & 0xFFF,
-                        y2: u16::from_be_bytes([data[cursor + 5], data[cursor + 6]]) & 0xFFF,
-                    };
-                    control.coordinates = Some(coordinates);
-                    cursor += 7;
+                    control.coordinates = Some(Coordinates {
+                        x1: u16::from_be_bytes([args[0], args[1]]) >> 4 & 0xFFF,
+                        x2: u16::from_be_bytes([args[1], args[2]]) & 0xFFF,
+                        y1: u16::from_be_bytes([args[3], args[4]]) >> 4 & 0xFFF,
+                        y2: u16::from_be_bytes([args[4], args[5]]) & 0xFFF,
+                    });
                 }
                 0x06 => {
                     // RLE offsets
-                    if data.len() <= cursor + 4 {
-                        return None;
-                    }
-                    let evens = u16::from_be_bytes([data[cursor + 1], data[cursor + 2]]);
-                    let odds = u16::from_be_bytes([data[cursor + 3], data[cursor + 4]]);
+                    let evens = u16::from_be_bytes([args[0], args[1]]);
+                    let odds = u16::from_be_bytes([args[2], args[3]]);
                     control.rle_offsets = Some((evens, odds));
-                    cursor += 5;
                 }
-                0xFF => {
+                0x07 => {
+                    // Color/contrast changes
+                    control.color_changes =
+                        Some(parse_color_changes(&args[2..]).ok_or(SubsError::InvalidControl)?);
+                }
+                _ => {
                     // End of command sequence
                     break;
                 }
-                _ => {}
             }
+            cursor += length;
         }
-        if next_control as usize == this_sequence {
+        sequences.push(control);
+        // The last sequence points to itself
+        if next_control == this_sequence {
             break;
-        } else {
-            cursor = next_control as usize;
         }
+        if visited.contains(&next_control) {
+            return Err(SubsError::ControlLoop);
+        }
+        cursor = next_control;
     }
-    return Some(control);
+    return Ok((sequences, warnings));
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -202,17 +978,29 @@ fn read_rle(nibble_stream: &mut NibbleStream) -> Option<Rle> {
     });
 }
 
-fn parse_data(
-    palette: &[Rgb<u8>; 16],
-    control: ControlData,
-    data: &[u8],
-) -> Option<image::ImageBuffer<Rgba<u8>, Vec<u8>>> {
-    let color_palette = control.color_palette?;
-    let alpha_palette = control.alpha_palette?;
+/// Decodes the pixel data to indices of `color << 4 | alpha`, where `color`
+/// indexes the idx palette and `alpha` is the 4-bit contrast value
+/// Custom colors from the idx take the place of the colors and contrast the
+/// subpicture selects, and become the first four colors of the image's
+/// palette.
+fn parse_data(idx: &IdxData, control: ControlData, data: &[u8]) -> Option<IndexedImage> {
+    let palette = match idx.custom_colors {
+        Some(ref custom) => custom.palette(),
+        None => idx.palette,
+    };
+    let (color_palette, alpha_palette) = match idx.custom_colors {
+        // Not needed, so subpictures without them still decode
+        Some(_) => ([0; 4], [0; 4]),
+        None => (control.color_palette?, control.alpha_palette?),
+    };
     let coordinates = control.coordinates?;
-    let width = (coordinates.x2 - coordinates.x1 + 1) as u32;
-    let height = (coordinates.y2 - coordinates.y1 + 1) as u32;
-    let mut image = image::ImageBuffer::<Rgba<u8>, Vec<u8>>::new(width as _, height as _);
+    let (width, height) = coordinates.size().ok()?;
+    let mut image = IndexedImage::new(
+        width,
+        height,
+        indexed_palette(&palette),
+        format_palette(&palette),
+    );
 
     let mut y = 0;
 
@@ -227,6 +1015,11 @@ fn parse_data(
 
     while y < height {
         let this_stream = &mut nibble_streams[(y % 2) as usize];
+        let screen_y = coordinates.y1 + y as u16;
+        let line_change = control
+            .color_changes
+            .iter()
+            .find(|change| (change.first_line..=change.last_line).contains(&screen_y));
         // Read a whole line
         let mut x = 0;
         while x < width {
@@ -235,25 +1028,42 @@ fn parse_data(
                 return None;
             }
             if next_rle.length == 0 {
-                this_stream.byte_align();
                 next_rle.length = width - x;
             }
+            if x + next_rle.length == width {
+                // Lines start on a byte boundary, however the last run
+                // ended
+                this_stream.byte_align();
+            }
             for _ in 0..next_rle.length {
+                let (color_palette, alpha_palette) = line_change
+                    .and_then(|change| {
+                        let screen_x = coordinates.x1 + x as u16;
+                        return change
+                            .columns
+                            .iter()
+                            .rev()
+                            .find(|column| column.column <= screen_x);
+                    })
+                    .map_or((color_palette, alpha_palette), |column| {
+                        (column.color_palette, column.alpha_palette)
+                    });
                 // Color is a two-bit integer ranging from 0 through 3, and
                 // the local palettes are 4 long, so no bounds check needed.
-                let color_idx = color_palette[3 - next_rle.color as usize];
-                let color_alpha = alpha_palette[3 - next_rle.color as usize];
+                let (color_idx, color_alpha) = match idx.custom_colors {
+                    Some(ref custom) => {
+                        let color = next_rle.color as usize;
+                        (color as u8, if custom.transparent[color] { 0 } else { 15 })
+                    }
+                    None => (
+                        color_palette[3 - next_rle.color as usize],
+                        alpha_palette[3 - next_rle.color as usize],
+                    ),
+                };
                 if color_idx >= 16 {
                     return None;
                 }
-                let color_opaque = palette[color_idx as usize].0;
-                let color = Rgba([
-                    color_opaque[0],
-                    color_opaque[1],
-                    color_opaque[2],
-                    color_alpha,
-                ]);
-                image.put_pixel(x, y, color);
+                image.set_index(x, y, color_idx << 4 | color_alpha);
                 x += 1;
             }
         }
@@ -263,35 +1073,34 @@ fn parse_data(
     return Some(image);
 }
 
+/// RGBA for every `color << 4 | alpha` index
+fn indexed_palette(palette: &[Rgb<u8>; 16]) -> Vec<Rgba<u8>> {
+    return (0..=255u8)
+        .map(|index| {
+            let Rgb([r, g, b]) = palette[(index >> 4) as usize];
+            // Alpha is 4-bit; 0xF * 17 = 0xFF
+            return Rgba([r, g, b, (index & 0xF) * 17]);
+        })
+        .collect();
+}
+
 /// Allows cursor-style reading of byte slices as u4 streams
 pub struct NibbleStream<'a> {
-    cursor: usize,
-    data: &'a [u8],
+    bits: BitReader<'a>,
 }
 impl<'a> NibbleStream<'a> {
     pub fn new(data: &'a [u8]) -> Self {
-        return Self { cursor: 0, data };
+        return Self {
+            bits: BitReader::new(data),
+        };
     }
     /// Ensures we are on a byte boundary, skipping a nibble
     /// if necessary.
     pub fn byte_align(&mut self) {
-        if self.cursor % 2 != 0 {
-            self.cursor += 1;
-        }
+        self.bits.byte_align();
     }
     /// Takes the next u4 from the stream
     pub fn take_nibble(&mut self) -> Option<u8> {
-        let byte_cursor = self.cursor / 2;
-        if self.data.len() <= byte_cursor {
-            return None;
-        }
-        let start = self.data[byte_cursor];
-        if self.cursor % 2 == 0 {
-            self.cursor += 1;
-            return Some(start >> 4 & 0xF);
-        } else {
-            self.cursor += 1;
-            return Some(start & 0xF);
-        }
+        return Some(self.bits.read_bits(4)? as u8);
     }
 }
@@ -0,0 +1,93 @@
+//! wasm-bindgen wrappers for in-browser preview. Both decoders hand back RGBA
+//! buffers that can be passed straight to `new ImageData(...)`.
+
+use image::{GrayAlphaImage, RgbaImage, buffer::ConvertBuffer};
+use wasm_bindgen::{Clamped, prelude::*};
+
+use crate::{
+    bdsup::PgsParser,
+    vobs::{self, IdxData},
+};
+
+/// A decoded subtitle bitmap
+#[wasm_bindgen]
+pub struct RgbaFrame {
+    image: RgbaImage,
+}
+#[wasm_bindgen]
+impl RgbaFrame {
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        return self.image.width();
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        return self.image.height();
+    }
+
+    /// RGBA pixels, row by row. Copied into a `Uint8ClampedArray` on the JS side.
+    #[wasm_bindgen(getter)]
+    pub fn pixels(&self) -> Clamped<Vec<u8>> {
+        return Clamped(self.image.as_raw().clone());
+    }
+}
+
+/// Stateful PGS decoder. Display sets must be fed in order, since later ones
+/// refer back to palettes and objects defined earlier in the epoch.
+#[wasm_bindgen]
+pub struct PgsDecoder {
+    parser: PgsParser<'static>,
+    image: GrayAlphaImage,
+}
+#[wasm_bindgen]
+impl PgsDecoder {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        return Self {
+            parser: PgsParser::new(),
+            image: GrayAlphaImage::new(0, 0),
+        };
+    }
+
+    /// Decodes one display set. Returns `undefined` for compositions that
+    /// don't show anything.
+    pub fn decode(&mut self, display_set: &[u8]) -> Result<Option<RgbaFrame>, JsError> {
+        if !self
+            .parser
+            .process_reused_into(display_set, &mut self.image)?
+        {
+            return Ok(None);
+        }
+        return Ok(Some(RgbaFrame {
+            image: self.image.convert(),
+        }));
+    }
+}
+impl Default for PgsDecoder {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+/// VobSub decoder, configured from the track's `.idx` data (the MKV CodecPrivate)
+#[wasm_bindgen]
+pub struct VobSubDecoder {
+    idx: IdxData,
+}
+#[wasm_bindgen]
+impl VobSubDecoder {
+    #[wasm_bindgen(constructor)]
+    pub fn new(idx: &[u8]) -> Result<VobSubDecoder, JsError> {
+        return Ok(Self {
+            idx: vobs::parse_idx(idx)?,
+        });
+    }
+
+    /// Decodes one subpicture packet
+    pub fn decode(&self, packet: &[u8]) -> Result<RgbaFrame, JsError> {
+        return Ok(RgbaFrame {
+            image: vobs::parse_frame(&self.idx, packet)?,
+        });
+    }
+}
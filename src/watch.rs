@@ -0,0 +1,191 @@
+//! `watch` mode: extracts MKVs as they're copied into a directory, for setups
+//! that drop rips into an ingest folder rather than going through mediacorral.
+//! Readiness is judged by polling each new file's size and modification time
+//! rather than by its events, since copies over the network arrive in bursts
+//! and some file systems only report them when rescanned. Settled files go
+//! through a [`JobQueue`], which runs them a few at a time.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        mpsc::{self, RecvTimeoutError},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use notify::{
+    Event, EventKind, PollWatcher, RecursiveMode, Watcher,
+    event::{ModifyKind, RenameMode},
+};
+
+use subproc::queue::{JobQueue, JobTicket};
+
+use crate::{cli::WatchOptions, run};
+
+/// How often files waiting to settle are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Extensions of the files picked up
+const EXTENSIONS: [&str; 2] = ["mkv", "mks"];
+
+/// A new file that may still be being written
+struct Pending {
+    /// Size and modification time when last checked
+    state: Option<(u64, Option<SystemTime>)>,
+    /// When `state` last changed
+    changed: Instant,
+}
+
+/// Watches the directory, queueing each new file once it's settled. Failed
+/// extractions are reported and watching goes on; only failing to watch is
+/// an error.
+pub fn watch(options: WatchOptions) -> Result<(), Box<dyn Error>> {
+    let queue = match options.queue.state {
+        Some(ref path) => JobQueue::open(path, options.queue.limits)?,
+        None => JobQueue::new(options.queue.limits),
+    };
+    let resumed = queue.queued().len();
+    if resumed > 0 {
+        eprintln!("Resuming {resumed} queued files");
+    }
+    let options = Arc::new(options);
+    {
+        let queue = queue.clone();
+        let options = options.clone();
+        thread::spawn(move || dispatch(&queue, &options));
+    }
+
+    let (sender, events) = mpsc::channel();
+    let mut watcher: Box<dyn Watcher> = match options.poll {
+        Some(interval) => Box::new(PollWatcher::new(
+            sender,
+            notify::Config::default().with_poll_interval(Duration::from_nanos(interval)),
+        )?),
+        None => Box::new(notify::recommended_watcher(sender)?),
+    };
+    let mode = match options.recursive {
+        true => RecursiveMode::Recursive,
+        false => RecursiveMode::NonRecursive,
+    };
+    watcher.watch(&options.directory, mode)?;
+    eprintln!("Watching {}", options.directory.display());
+
+    let settle = Duration::from_nanos(options.settle);
+    let mut pending = HashMap::new();
+    let mut checked = Instant::now();
+    loop {
+        match events.recv_timeout(CHECK_INTERVAL) {
+            Ok(Ok(event)) => track(event, &mut pending),
+            Ok(Err(err)) => eprintln!("Warning: {err}"),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Err("The watcher stopped".into()),
+        }
+        if checked.elapsed() < CHECK_INTERVAL {
+            continue;
+        }
+        checked = Instant::now();
+        let mut ready = settled(&mut pending, settle);
+        ready.sort();
+        for path in ready {
+            if let Err(err) = queue.push(path.clone(), 0, options.queue.job) {
+                eprintln!("Error: {}: {err}", path.display());
+            }
+        }
+    }
+}
+
+/// Extracts each file the queue hands out on a thread of its own
+fn dispatch(queue: &JobQueue<PathBuf>, options: &Arc<WatchOptions>) {
+    loop {
+        let (job, ticket) = queue.next();
+        let options = options.clone();
+        thread::spawn(move || {
+            let path = job.spec;
+            eprintln!("Extracting {}", path.display());
+            if let Err(err) = extract(&options, &path, ticket) {
+                eprintln!("Error: {}: {err}", path.display());
+            }
+        });
+    }
+}
+
+fn extract(
+    options: &WatchOptions,
+    path: &Path,
+    mut ticket: JobTicket,
+) -> Result<(), Box<dyn Error>> {
+    ticket.check_input(fs::metadata(path)?.len())?;
+    return run(options.extract_options(path)?, Some(&mut ticket));
+}
+
+/// Starts waiting on MKVs created in or moved into the directory, and stops
+/// waiting on ones removed or moved away
+fn track(event: Event, pending: &mut HashMap<PathBuf, Pending>) {
+    let add = |path: PathBuf| {
+        if is_mkv(&path) {
+            pending.entry(path).or_insert(Pending {
+                state: None,
+                changed: Instant::now(),
+            });
+        }
+    };
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            event.paths.into_iter().for_each(add);
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            let mut paths = event.paths.into_iter();
+            let from = paths.next();
+            paths.for_each(add);
+            if let Some(from) = from {
+                pending.remove(&from);
+            }
+        }
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            for path in &event.paths {
+                pending.remove(path);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Takes the files whose size and modification time haven't changed for
+/// `settle`, and forgets ones that are gone
+fn settled(pending: &mut HashMap<PathBuf, Pending>, settle: Duration) -> Vec<PathBuf> {
+    let now = Instant::now();
+    let mut ready = Vec::new();
+    pending.retain(|path, file| {
+        let Ok(metadata) = fs::metadata(path) else {
+            return false;
+        };
+        let state = Some((metadata.len(), metadata.modified().ok()));
+        if file.state != state {
+            file.state = state;
+            file.changed = now;
+            return true;
+        }
+        if now - file.changed < settle {
+            return true;
+        }
+        ready.push(path.clone());
+        return false;
+    });
+    return ready;
+}
+
+fn is_mkv(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    return !hidden
+        && extension.is_some_and(|extension| EXTENSIONS.contains(&extension.as_str()))
+        && !path.is_dir();
+}
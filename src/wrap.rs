@@ -0,0 +1,179 @@
+//! Re-wrapping cue text to subtitle line limits. OCR reproduces the disc's
+//! line breaks, which were laid out for a different font and screen, so the
+//! text is re-flowed from scratch, preferring breaks after punctuation and
+//! before conjunctions, and keeping the lines close in length.
+
+use crate::{
+    srt::{SrtCue, strip_tags},
+    writer::{SubtitleWriter, WriterError},
+};
+
+/// Words a line reads better starting with
+const CONJUNCTIONS: [&str; 12] = [
+    "and", "but", "or", "so", "because", "that", "which", "who", "when", "while", "if", "than",
+];
+/// Words that belong with the word after them, so a line shouldn't end on one
+const CLINGY: [&str; 17] = [
+    "a", "an", "the", "of", "to", "in", "on", "at", "for", "with", "from", "by", "my", "your",
+    "his", "her", "i",
+];
+
+// Break preferences, weighed against uneven lines (squared characters)
+const SENTENCE_BONUS: i64 = 200;
+const CLAUSE_BONUS: i64 = 120;
+const CONJUNCTION_BONUS: i64 = 100;
+const CLINGY_PENALTY: i64 = 150;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrapOptions {
+    /// Characters per line, not counting tags
+    pub max_line_length: usize,
+    pub max_lines: usize,
+}
+impl Default for WrapOptions {
+    fn default() -> Self {
+        return Self {
+            max_line_length: 42,
+            max_lines: 2,
+        };
+    }
+}
+
+/// Re-wraps `text` into as few lines as fit `options`, or into
+/// `max_lines` lines as even as possible if it doesn't fit at all. Dialogue
+/// (every line starting with a dash) keeps one speaker per line, and a
+/// leading `{\an8}`-style tag stays in front.
+pub fn rewrap(text: &str, options: &WrapOptions) -> String {
+    let text = text.trim();
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    if lines.len() > 1 && lines.iter().all(|line| line.starts_with('-')) {
+        return lines.join("\n");
+    }
+    let (tag, text) = match text.strip_prefix('{').and_then(|rest| rest.split_once('}')) {
+        Some((tag, rest)) => (&text[..tag.len() + 2], rest),
+        None => ("", text),
+    };
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.is_empty() {
+        return tag.to_owned();
+    }
+    let lengths: Vec<usize> = words
+        .iter()
+        .map(|word| strip_tags(word).chars().count())
+        .collect();
+    let max_lines = options.max_lines.max(1).min(words.len());
+    let breaks = (1..=max_lines)
+        .find_map(|lines| best_breaks(&words, &lengths, lines, options.max_line_length, true))
+        .or_else(|| best_breaks(&words, &lengths, max_lines, options.max_line_length, false))
+        .unwrap_or_default();
+
+    let mut wrapped = String::from(tag);
+    let mut start = 0;
+    for end in breaks.into_iter().chain([words.len()]) {
+        if start > 0 {
+            wrapped.push('\n');
+        }
+        wrapped.push_str(&words[start..end].join(" "));
+        start = end;
+    }
+    return wrapped;
+}
+
+/// Re-wraps each cue's text before passing it on, so one output of several
+/// can have its own line limits
+pub struct RewrapWriter<W> {
+    inner: W,
+    options: WrapOptions,
+}
+impl<W: SubtitleWriter> RewrapWriter<W> {
+    pub fn new(inner: W, options: WrapOptions) -> Self {
+        return Self { inner, options };
+    }
+}
+impl<W: SubtitleWriter> SubtitleWriter for RewrapWriter<W> {
+    fn write_event(&mut self, cue: &SrtCue) -> Result<(), WriterError> {
+        return self.inner.write_event(&SrtCue {
+            start: cue.start,
+            end: cue.end,
+            text: rewrap(&cue.text, &self.options),
+        });
+    }
+
+    fn finish(&mut self) -> Result<(), WriterError> {
+        return self.inner.finish();
+    }
+}
+
+/// The word indices to break before to get exactly `lines` lines, picking
+/// the lowest cost. If `strict`, `None` if the words don't fit in lines of
+/// `limit` characters; otherwise overlong lines are allowed but cost heavily.
+fn best_breaks(
+    words: &[&str],
+    lengths: &[usize],
+    lines: usize,
+    limit: usize,
+    strict: bool,
+) -> Option<Vec<usize>> {
+    let count = words.len();
+    // costs[line][end]: best cost of putting words[..end] on `line` lines,
+    // with the start of the last one
+    let mut costs = vec![vec![None::<(i64, usize)>; count + 1]; lines + 1];
+    costs[0][0] = Some((0, 0));
+    for line in 1..=lines {
+        for end in line..=count {
+            for start in line - 1..end {
+                let Some((previous, _)) = costs[line - 1][start] else {
+                    continue;
+                };
+                let length = lengths[start..end].iter().sum::<usize>() + (end - start - 1);
+                let overflow = length.saturating_sub(limit) as i64;
+                if strict && overflow > 0 {
+                    continue;
+                }
+                let mut cost = previous + (length * length) as i64 + overflow * overflow * 100;
+                if start > 0 {
+                    cost -= break_bonus(words[start - 1], words[start]);
+                }
+                if costs[line][end].is_none_or(|(best, _)| cost < best) {
+                    costs[line][end] = Some((cost, start));
+                }
+            }
+        }
+    }
+    costs[lines][count]?;
+    let mut breaks = Vec::with_capacity(lines - 1);
+    let mut end = count;
+    for line in (2..=lines).rev() {
+        let (_, start) = costs[line][end]?;
+        breaks.push(start);
+        end = start;
+    }
+    breaks.reverse();
+    return Some(breaks);
+}
+
+/// How good a place the space between `before` and `after` is for a line break
+fn break_bonus(before: &str, after: &str) -> i64 {
+    let before = strip_tags(before);
+    let bare = |word: &str| {
+        return word
+            .trim_matches(|c: char| !c.is_alphanumeric() && c != '\'')
+            .to_lowercase();
+    };
+    let mut bonus = 0;
+    if before.ends_with(['.', '!', '?', '…']) {
+        bonus += SENTENCE_BONUS;
+    } else if before.ends_with([',', ';', ':', '—']) {
+        bonus += CLAUSE_BONUS;
+    } else if CLINGY.contains(&bare(&before).as_str()) {
+        bonus -= CLINGY_PENALTY;
+    }
+    if CONJUNCTIONS.contains(&bare(&strip_tags(after)).as_str()) {
+        bonus += CONJUNCTION_BONUS;
+    }
+    return bonus;
+}
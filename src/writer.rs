@@ -0,0 +1,217 @@
+//! A common interface over the output formats, so cues can be written to
+//! several of them in one pass instead of recognizing the track once per
+//! format. Formats are looked up by name in a [`WriterRegistry`], which
+//! embedding applications can add their own to.
+
+use std::io::{self, Write};
+
+use thiserror::Error;
+
+use crate::{
+    legacy::{MicroDvdWriter, SubViewerWriter},
+    srt::{SrtCue, SrtWriter},
+    timebase::FrameRate,
+};
+
+#[derive(Error, Debug)]
+pub enum WriterError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Unknown output format: {0}")]
+    UnknownFormat(String),
+    #[error("{0} counts video frames, so it needs a frame rate")]
+    MissingFrameRate(&'static str),
+}
+
+/// Writes cues to one output, one at a time
+pub trait SubtitleWriter {
+    /// Writes the next cue. Cues are passed in order.
+    fn write_event(&mut self, cue: &SrtCue) -> Result<(), WriterError>;
+
+    /// Writes whatever follows the last cue and flushes the output. Nothing
+    /// is written after this.
+    fn finish(&mut self) -> Result<(), WriterError>;
+}
+impl<T: SubtitleWriter + ?Sized> SubtitleWriter for Box<T> {
+    fn write_event(&mut self, cue: &SrtCue) -> Result<(), WriterError> {
+        return (**self).write_event(cue);
+    }
+
+    fn finish(&mut self) -> Result<(), WriterError> {
+        return (**self).finish();
+    }
+}
+
+/// Passes every cue to each of its writers, in the order they were added
+#[derive(Default)]
+pub struct MultiWriter {
+    writers: Vec<Box<dyn SubtitleWriter + Send>>,
+}
+impl MultiWriter {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn push(&mut self, writer: Box<dyn SubtitleWriter + Send>) {
+        self.writers.push(writer);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.writers.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.writers.is_empty();
+    }
+}
+impl SubtitleWriter for MultiWriter {
+    fn write_event(&mut self, cue: &SrtCue) -> Result<(), WriterError> {
+        for writer in &mut self.writers {
+            writer.write_event(cue)?;
+        }
+        return Ok(());
+    }
+
+    /// Finishes every writer, even if one of them fails. The first error is
+    /// returned.
+    fn finish(&mut self) -> Result<(), WriterError> {
+        let mut result = Ok(());
+        for writer in &mut self.writers {
+            let finished = writer.finish();
+            if result.is_ok() {
+                result = finished;
+            }
+        }
+        return result;
+    }
+}
+
+/// Writes all of `cues` and finishes the writer
+pub fn write_all<W: SubtitleWriter + ?Sized>(
+    writer: &mut W,
+    cues: &[SrtCue],
+) -> Result<(), WriterError> {
+    for cue in cues {
+        writer.write_event(cue)?;
+    }
+    return writer.finish();
+}
+
+/// What a format may need besides the output itself
+#[derive(Debug, Clone, Default)]
+pub struct WriterOptions {
+    /// For formats timed in video frames
+    pub frame_rate: Option<FrameRate>,
+}
+
+/// Creates a writer for one format, writing to the given output
+pub type WriterFactory = Box<
+    dyn Fn(
+            Box<dyn Write + Send>,
+            &WriterOptions,
+        ) -> Result<Box<dyn SubtitleWriter + Send>, WriterError>
+        + Send
+        + Sync,
+>;
+
+/// A registered output format
+pub struct WriterFormat {
+    /// What the format is selected by, e.g. `srt`
+    pub name: String,
+    /// File extension, without the dot
+    pub extension: String,
+    /// Whether the writer needs [`WriterOptions::frame_rate`]
+    pub frame_based: bool,
+    factory: WriterFactory,
+}
+impl WriterFormat {
+    pub fn create(
+        &self,
+        out: Box<dyn Write + Send>,
+        options: &WriterOptions,
+    ) -> Result<Box<dyn SubtitleWriter + Send>, WriterError> {
+        return (self.factory)(out, options);
+    }
+}
+
+/// The output formats writers can be created for, by name. [`Default`] has
+/// the built-in ones: `srt`, `microdvd` and `subviewer`.
+pub struct WriterRegistry {
+    formats: Vec<WriterFormat>,
+}
+impl WriterRegistry {
+    /// A registry without any formats
+    pub fn empty() -> Self {
+        return Self {
+            formats: Vec::new(),
+        };
+    }
+
+    /// Adds a format, replacing any already registered under `name`
+    pub fn register(
+        &mut self,
+        name: &str,
+        extension: &str,
+        frame_based: bool,
+        factory: WriterFactory,
+    ) {
+        self.formats.retain(|format| format.name != name);
+        self.formats.push(WriterFormat {
+            name: name.to_owned(),
+            extension: extension.to_owned(),
+            frame_based,
+            factory,
+        });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&WriterFormat> {
+        return self.formats.iter().find(|format| format.name == name);
+    }
+
+    /// Names of the registered formats, in the order they were added
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        return self.formats.iter().map(|format| format.name.as_str());
+    }
+
+    /// Creates a writer for the format registered as `name`
+    pub fn create(
+        &self,
+        name: &str,
+        out: Box<dyn Write + Send>,
+        options: &WriterOptions,
+    ) -> Result<Box<dyn SubtitleWriter + Send>, WriterError> {
+        return self
+            .get(name)
+            .ok_or_else(|| WriterError::UnknownFormat(name.to_owned()))?
+            .create(out, options);
+    }
+}
+impl Default for WriterRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(
+            "srt",
+            "srt",
+            false,
+            Box::new(|out, _| Ok(Box::new(SrtWriter::new(out)))),
+        );
+        registry.register(
+            "microdvd",
+            "sub",
+            true,
+            Box::new(|out, options| {
+                let rate = options
+                    .frame_rate
+                    .ok_or(WriterError::MissingFrameRate("MicroDVD"))?;
+                return Ok(Box::new(MicroDvdWriter::new(out, rate)));
+            }),
+        );
+        registry.register(
+            "subviewer",
+            "sub",
+            false,
+            Box::new(|out, _| Ok(Box::new(SubViewerWriter::new(out)))),
+        );
+        return registry;
+    }
+}
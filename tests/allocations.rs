@@ -0,0 +1,110 @@
+//! Allocations made by the PGS parser once it has seen a few display sets.
+//! Everything it needs per composition should come from buffers it kept.
+
+mod common;
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use common::*;
+use image::GrayAlphaImage;
+use subproc::bdsup::PgsParser;
+
+/// Counts allocations on the current thread, so tests running alongside
+/// don't add to it
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        return unsafe { System.alloc(layout) };
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        return unsafe { System.realloc(ptr, layout, new_size) };
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    return ALLOCATIONS.with(Cell::get) - before;
+}
+
+/// A subtitle shown, faded with a palette update, then cleared. The object
+/// comes in two segments so it has to be reassembled.
+fn subtitle() -> Vec<Vec<u8>> {
+    let objects = [PgsObjectRef {
+        object_id: 1,
+        window_id: 0,
+        x: 0,
+        y: 0,
+        crop: None,
+    }];
+    let window = [(0, 100, 900, 600, 50)];
+    return vec![
+        PgsDisplaySetBuilder::new()
+            .pcs(1, 0x80, 0, &objects)
+            .wds(&window)
+            .pds(0, 0, &[(1, 235, 255), (2, 16, 255), (3, 128, 128)])
+            .ods(1, 0, 600, 50, &pgs_rle(&outlined_bar(600, 50, 1, 2)), 2)
+            .finish(),
+        PgsDisplaySetBuilder::new()
+            .pcs(2, 0x00, 0, &objects)
+            .pds(0, 1, &[(1, 235, 64), (2, 16, 64)])
+            .finish(),
+        PgsDisplaySetBuilder::new()
+            .pcs(3, 0x00, 0, &[])
+            .wds(&window)
+            .finish(),
+    ];
+}
+
+#[test]
+fn steady_state() {
+    let display_sets = subtitle();
+    let mut image = GrayAlphaImage::new(0, 0);
+    let mut parser = PgsParser::new();
+    for _ in 0..2 {
+        for data in display_sets.iter() {
+            parser.process_display_set_into(data, &mut image).unwrap();
+        }
+    }
+    let count = allocations(|| {
+        for _ in 0..10 {
+            for data in display_sets.iter() {
+                parser.process_display_set_into(data, &mut image).unwrap();
+            }
+        }
+    });
+    assert_eq!(count, 0);
+
+    let mut parser = PgsParser::new();
+    for _ in 0..2 {
+        for data in display_sets.iter() {
+            parser.process_reused_into(data, &mut image).unwrap();
+        }
+    }
+    let count = allocations(|| {
+        for _ in 0..10 {
+            for data in display_sets.iter() {
+                parser.process_reused_into(data, &mut image).unwrap();
+            }
+        }
+    });
+    assert_eq!(count, 0);
+}
@@ -0,0 +1,21 @@
+//! ASS/SSA rasterization. Rendering itself needs libass and its fonts, so
+//! only the header handling is checked here.
+
+use subproc::ass::{DEFAULT_PLAY_RES, play_res};
+
+#[test]
+fn reads_play_res_from_the_header() {
+    let header = b"[Script Info]\r\nScriptType: v4.00+\r\nPlayResX: 1920\r\nplayresy:1080\r\n\r\n[V4+ Styles]\r\n";
+    assert_eq!(play_res(header), Some((1920, 1080)));
+}
+
+#[test]
+fn needs_both_dimensions() {
+    assert_eq!(play_res(b"[Script Info]\nPlayResX: 640\n"), None);
+    assert_eq!(
+        play_res(b"[Script Info]\nPlayResX: 0\nPlayResY: 480\n"),
+        None
+    );
+    assert_eq!(play_res(b""), None);
+    assert_eq!(DEFAULT_PLAY_RES, (384, 288));
+}
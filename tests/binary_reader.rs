@@ -0,0 +1,64 @@
+//! Cursor handling and typed reads in `PacketReader`.
+
+use std::io::SeekFrom;
+
+use subproc::binary_reader::PacketReader;
+
+#[test]
+fn typed_reads() {
+    let data = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 0x01];
+    let mut reader = PacketReader::new(&data);
+    assert_eq!(reader.peek_u8(), Some(0x12));
+    assert_eq!(reader.peek_u16(), Some(0x1234));
+    assert_eq!(reader.position(), 0);
+    assert_eq!(reader.read_u24(), Some(0x123456));
+    assert_eq!(reader.read_u32(), Some(0x789abcde));
+    assert_eq!(reader.position(), 7);
+
+    // Short reads fail without consuming anything
+    assert_eq!(reader.read_u24(), None);
+    assert_eq!(reader.peek_u16(), Some(0xf001));
+    let mut buf = [0; 3];
+    assert_eq!(reader.read_exact_into(&mut buf), None);
+    let mut buf = [0; 2];
+    assert_eq!(reader.read_exact_into(&mut buf), Some(()));
+    assert_eq!(buf, [0xf0, 0x01]);
+    assert_eq!(reader.peek_u8(), None);
+}
+
+#[test]
+fn seeking() {
+    let data = [0, 1, 2, 3, 4, 5];
+    let mut reader = PacketReader::new(&data);
+    assert_eq!(reader.seek(SeekFrom::Start(4)), Some(4));
+    assert_eq!(reader.read_u8(), Some(4));
+    assert_eq!(reader.seek(SeekFrom::Current(-3)), Some(2));
+    assert_eq!(reader.read_u8(), Some(2));
+    assert_eq!(reader.seek(SeekFrom::End(-1)), Some(5));
+    assert_eq!(reader.read_u8(), Some(5));
+    assert_eq!(reader.seek(SeekFrom::End(0)), Some(6));
+    assert_eq!(reader.get_remaining_bytes(), 0);
+
+    // Out of range seeks leave the cursor alone
+    assert_eq!(reader.seek(SeekFrom::Start(7)), None);
+    assert_eq!(reader.seek(SeekFrom::Current(-7)), None);
+    assert_eq!(reader.seek(SeekFrom::End(1)), None);
+    assert_eq!(reader.position(), 6);
+}
+
+#[test]
+fn sub_readers() {
+    let data = [0x16, 0x00, 0x03, 0xaa, 0xbb, 0xcc, 0x80];
+    let mut reader = PacketReader::new(&data);
+    assert_eq!(reader.read_u8(), Some(0x16));
+    let length = reader.read_u16().unwrap();
+    let mut segment = reader.sub_reader(length as usize).unwrap();
+
+    // The child stops at the end of its segment, and the parent skips it
+    assert_eq!(segment.read_u16(), Some(0xaabb));
+    assert_eq!(segment.read_u16(), None);
+    assert_eq!(segment.get_remaining_bytes(), 1);
+    assert_eq!(reader.position(), 6);
+    assert_eq!(reader.read_u8(), Some(0x80));
+    assert!(reader.sub_reader(1).is_none());
+}
@@ -0,0 +1,121 @@
+//! Property checks for `BitReader`, run over pseudorandom field layouts
+//! against a straightforward bit-by-bit writer.
+
+mod common;
+
+use common::*;
+use subproc::{binary_reader::BitReader, vobs::NibbleStream};
+
+const ROUNDS: u64 = 500;
+
+/// Packs fields MSB-first one bit at a time
+fn pack(fields: &[(u32, u32)]) -> Vec<u8> {
+    let mut bits = Vec::new();
+    for &(width, value) in fields {
+        for bit in (0..width).rev() {
+            bits.push((value >> bit) & 1 == 1);
+        }
+    }
+    return bits
+        .chunks(8)
+        .map(|byte| {
+            return byte
+                .iter()
+                .enumerate()
+                .fold(0u8, |acc, (i, bit)| acc | (*bit as u8) << (7 - i));
+        })
+        .collect();
+}
+
+fn random_fields(rng: &mut Rng) -> Vec<(u32, u32)> {
+    return (0..rng.below(40))
+        .map(|_| {
+            let width = rng.below(33) as u32;
+            let mask = ((1u64 << width) - 1) as u32;
+            return (width, rng.next() as u32 & mask);
+        })
+        .collect();
+}
+
+#[test]
+fn fields_round_trip() {
+    for round in 0..ROUNDS {
+        let mut rng = Rng::new(round);
+        let fields = random_fields(&mut rng);
+        let data = pack(&fields);
+        let mut reader = BitReader::new(&data);
+        let mut position = 0;
+        for &(width, value) in &fields {
+            assert_eq!(reader.peek_bits(width), Some(value), "round {round}");
+            assert_eq!(reader.read_bits(width), Some(value), "round {round}");
+            position += width as usize;
+            assert_eq!(reader.position(), position, "round {round}");
+        }
+        // Only padding up to the next byte is left
+        assert!(reader.remaining_bits() < 8, "round {round}");
+        assert_eq!(reader.read_bits(0), Some(0));
+    }
+}
+
+#[test]
+fn reads_past_end_fail_cleanly() {
+    for round in 0..ROUNDS {
+        let mut rng = Rng::new(round);
+        let data: Vec<u8> = (0..rng.below(6)).map(|_| rng.next() as u8).collect();
+        let mut reader = BitReader::new(&data);
+        reader.skip_bits(rng.below(data.len() as u64 * 8 + 1) as usize);
+        let remaining = reader.remaining_bits();
+        assert_eq!(remaining + reader.position(), data.len() * 8);
+
+        let width = remaining as u32 + 1;
+        if width <= 32 {
+            assert_eq!(reader.read_bits(width), None, "round {round}");
+        }
+        assert_eq!(reader.skip_bits(remaining + 1), None, "round {round}");
+        assert_eq!(reader.remaining_bits(), remaining, "round {round}");
+    }
+}
+
+#[test]
+fn alignment() {
+    for round in 0..ROUNDS {
+        let mut rng = Rng::new(round);
+        let data: Vec<u8> = (0..1 + rng.below(8)).map(|_| rng.next() as u8).collect();
+        let mut reader = BitReader::new(&data);
+        let skip = rng.below(data.len() as u64 * 8) as usize;
+        reader.skip_bits(skip);
+        reader.byte_align();
+        assert!(reader.is_byte_aligned(), "round {round}");
+        assert_eq!(reader.position(), skip.next_multiple_of(8), "round {round}");
+
+        // Aligned reads see whole bytes
+        let byte = reader.position() / 8;
+        if byte < data.len() {
+            assert_eq!(
+                reader.read_bits(8),
+                Some(data[byte] as u32),
+                "round {round}"
+            );
+        }
+    }
+}
+
+#[test]
+fn nibbles_match_bytes() {
+    for round in 0..ROUNDS {
+        let mut rng = Rng::new(round);
+        let data: Vec<u8> = (0..rng.below(16)).map(|_| rng.next() as u8).collect();
+        let mut nibbles = NibbleStream::new(&data);
+        for byte in &data {
+            assert_eq!(nibbles.take_nibble(), Some(byte >> 4), "round {round}");
+            // Aligning halfway through a byte skips its low nibble
+            if rng.below(4) == 0 {
+                nibbles.byte_align();
+                continue;
+            }
+            assert_eq!(nibbles.take_nibble(), Some(byte & 0xf), "round {round}");
+            nibbles.byte_align();
+        }
+        assert_eq!(nibbles.take_nibble(), None, "round {round}");
+    }
+}
@@ -0,0 +1,42 @@
+//! Cancellation of OCR and writers from another handle to the token.
+
+use std::io::Write;
+
+use image::GrayImage;
+use subproc::{
+    cancel::{CancellationToken, Cancelled},
+    ocr::{CancellableEngine, OcrEngine, OcrError},
+};
+
+struct Counter(usize);
+impl OcrEngine for Counter {
+    fn recognize(&mut self, _: &GrayImage) -> Result<String, OcrError> {
+        self.0 += 1;
+        return Ok(self.0.to_string());
+    }
+}
+
+#[test]
+fn cancels_ocr() {
+    let token = CancellationToken::new();
+    let mut engine = CancellableEngine::new(Counter(0), token.clone());
+    let image = GrayImage::new(1, 1);
+    assert_eq!(engine.recognize(&image).unwrap(), "1");
+    token.cancel();
+    assert!(matches!(
+        engine.recognize(&image),
+        Err(OcrError::Cancelled(Cancelled))
+    ));
+    assert_eq!(engine.inner().0, 1);
+}
+
+#[test]
+fn cancels_writes() {
+    let token = CancellationToken::new();
+    let mut writer = token.writer(Vec::new());
+    writer.write_all(b"1\n").unwrap();
+    token.clone().cancel();
+    let err = writer.write_all(b"2\n").unwrap_err();
+    assert!(err.get_ref().is_some_and(|inner| inner.is::<Cancelled>()));
+    assert_eq!(writer.into_inner(), b"1\n");
+}
@@ -0,0 +1,169 @@
+//! Reading MKV chapters and splitting cues by them.
+
+mod common;
+
+use std::io::Cursor;
+
+use common::*;
+use matroska_demuxer::MatroskaFile;
+use subproc::{
+    chapters::{
+        Chapter, OrderedChapter, SegmentLinks, place_cues, read_chapters, read_segment_links,
+        split_cues,
+    },
+    ebml::{ID_CHAPTERS, encode_element},
+};
+
+const MS: u64 = 1_000_000;
+
+#[test]
+fn reads_chapters() {
+    // Out of order, and with the middle one ending early
+    let file = build_mkv_with(
+        &[(1, "S_TEXT/UTF8", None)],
+        &[(1, 1_000, b"Hello".to_vec())],
+        None,
+        &mkv_chapters(&[
+            (600_000, None, "Episode 2"),
+            (0, None, "Episode 1"),
+            (1_200_000, Some(1_500_000), "Episode 3"),
+        ]),
+    );
+    let mkv = MatroskaFile::open(Cursor::new(file)).unwrap();
+    let chapters = read_chapters(&mkv);
+    let bounds: Vec<(u64, Option<u64>, Option<&str>)> = chapters
+        .iter()
+        .map(|chapter| {
+            (
+                chapter.start / MS,
+                chapter.end.map(|end| end / MS),
+                chapter.title.as_deref(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        bounds,
+        [
+            (0, Some(600_000), Some("Episode 1")),
+            (600_000, Some(1_200_000), Some("Episode 2")),
+            (1_200_000, Some(1_500_000), Some("Episode 3")),
+        ]
+    );
+}
+
+#[test]
+fn splits_and_rebases() {
+    let chapters = [
+        Chapter {
+            start: 1_000 * MS,
+            end: Some(10_000 * MS),
+            title: None,
+        },
+        Chapter {
+            start: 12_000 * MS,
+            end: None,
+            title: None,
+        },
+    ];
+    let cues = [
+        cue(500, 1_500, "Before the first chapter"),
+        cue(9_000, 11_000, "Runs past the end"),
+        cue(10_500, 11_500, "Between chapters"),
+        cue(12_000, 14_000, "Second"),
+    ];
+    let split = split_cues(&cues, &chapters);
+    let texts: Vec<Vec<(u64, u64, &str)>> = split
+        .iter()
+        .map(|cues| {
+            return cues
+                .iter()
+                .map(|cue| (cue.start / MS, cue.end / MS, cue.text.as_str()))
+                .collect();
+        })
+        .collect();
+    assert_eq!(
+        texts,
+        [
+            vec![
+                (0, 500, "Before the first chapter"),
+                (8_000, 9_000, "Runs past the end"),
+            ],
+            vec![(0, 2_000, "Second")],
+        ]
+    );
+    assert!(split_cues(&cues, &[]).is_empty());
+}
+
+#[test]
+fn reads_ordered_edition() {
+    let own = [1; 16];
+    let intro = [2; 16];
+    // A plain edition first, then the ordered one: an opening shared with
+    // other episodes, the episode, and its credits by explicit UID
+    let mut editions = mkv_edition(false, &[(None, 0, None, "Whole file")]);
+    editions.extend(mkv_edition(
+        true,
+        &[
+            (Some(intro), 0, Some(90_000), "Opening"),
+            (None, 0, Some(1_200_000), "Episode"),
+            (Some(own), 1_200_000, Some(1_300_000), "Credits"),
+        ],
+    ));
+    let file = build_mkv_with(
+        &[(1, "S_TEXT/UTF8", None)],
+        &[(1, 1_000, b"Hello".to_vec())],
+        Some(own),
+        &encode_element(ID_CHAPTERS, &editions),
+    );
+    let links = read_segment_links(&mut Cursor::new(file)).unwrap();
+    assert_eq!(links.uid, Some(own));
+    let chapters = links.ordered.unwrap();
+    let segments: Vec<(Option<[u8; 16]>, u64, u64)> = chapters
+        .iter()
+        .map(|chapter| (chapter.segment, chapter.start / MS, chapter.end / MS))
+        .collect();
+    assert_eq!(
+        segments,
+        [
+            (Some(intro), 0, 90_000),
+            (None, 0, 1_200_000),
+            (Some(own), 1_200_000, 1_300_000),
+        ]
+    );
+    assert_eq!(chapters[1].title.as_deref(), Some("Episode"));
+
+    let plain = build_mkv(&[(1, "S_TEXT/UTF8", None)], &[]);
+    assert_eq!(
+        read_segment_links(&mut Cursor::new(plain)).unwrap(),
+        SegmentLinks::default()
+    );
+}
+
+#[test]
+fn places_cues_on_the_edition_timeline() {
+    let chapter = OrderedChapter {
+        segment: None,
+        start: 10_000 * MS,
+        end: 20_000 * MS,
+        title: None,
+    };
+    let cues = [
+        cue(9_000, 11_000, "Straddles the start"),
+        cue(15_000, 16_000, "Inside"),
+        cue(19_500, 21_000, "Straddles the end"),
+        cue(20_000, 21_000, "After"),
+    ];
+    let placed = place_cues(&cues, &chapter, 90_000 * MS);
+    let placed: Vec<(u64, u64, &str)> = placed
+        .iter()
+        .map(|cue| (cue.start / MS, cue.end / MS, cue.text.as_str()))
+        .collect();
+    assert_eq!(
+        placed,
+        [
+            (90_000, 91_000, "Straddles the start"),
+            (95_000, 96_000, "Inside"),
+            (99_500, 100_000, "Straddles the end"),
+        ]
+    );
+}
@@ -0,0 +1,75 @@
+//! PGS palette conversion to RGB.
+
+use image::{Rgb, Rgba};
+use subproc::{
+    bdsup::PaletteEntry,
+    color::{ColorMatrix, to_rgb, to_rgba},
+};
+
+fn entry(luminance: u8, color_diff_red: u8, color_diff_blue: u8) -> PaletteEntry {
+    return PaletteEntry {
+        palette_entry_id: 1,
+        luminance,
+        color_diff_red,
+        color_diff_blue,
+        transparency: 128,
+    };
+}
+
+#[test]
+fn grays_are_the_same_in_both() {
+    for matrix in [ColorMatrix::Bt601, ColorMatrix::Bt709] {
+        assert_eq!(to_rgb(&entry(235, 128, 128), matrix), Rgb([255, 255, 255]));
+        assert_eq!(to_rgb(&entry(16, 128, 128), matrix), Rgb([0, 0, 0]));
+        assert_eq!(to_rgb(&entry(126, 128, 128), matrix), Rgb([128, 128, 128]));
+    }
+    assert_eq!(
+        to_rgba(&entry(235, 128, 128), ColorMatrix::Bt709),
+        Rgba([255, 255, 255, 128])
+    );
+}
+
+/// Within rounding of `expected`, since 8-bit YCrCb can't hit every color
+fn assert_close(actual: Rgb<u8>, expected: Rgb<u8>) {
+    let close = actual
+        .0
+        .iter()
+        .zip(expected.0)
+        .all(|(a, b)| a.abs_diff(b) <= 1);
+    assert!(close, "{actual:?} != {expected:?}");
+}
+
+#[test]
+fn primaries() {
+    // Red, as each standard encodes it
+    assert_close(
+        to_rgb(&entry(81, 240, 90), ColorMatrix::Bt601),
+        Rgb([255, 0, 0]),
+    );
+    assert_close(
+        to_rgb(&entry(63, 240, 102), ColorMatrix::Bt709),
+        Rgb([255, 0, 0]),
+    );
+    // Decoding BT.709 yellow as BT.601 shifts it
+    let yellow = entry(219, 138, 16);
+    assert_close(to_rgb(&yellow, ColorMatrix::Bt709), Rgb([255, 255, 0]));
+    assert_eq!(to_rgb(&yellow, ColorMatrix::Bt601), Rgb([252, 255, 10]));
+}
+
+#[test]
+fn auto_follows_resolution() {
+    assert_eq!(ColorMatrix::Auto.resolve(480), ColorMatrix::Bt601);
+    assert_eq!(ColorMatrix::Auto.resolve(576), ColorMatrix::Bt601);
+    assert_eq!(ColorMatrix::Auto.resolve(720), ColorMatrix::Bt709);
+    assert_eq!(ColorMatrix::Auto.resolve(1080), ColorMatrix::Bt709);
+    assert_eq!(ColorMatrix::Bt601.resolve(1080), ColorMatrix::Bt601);
+}
+
+#[test]
+fn parsing() {
+    assert_eq!("BT.709".parse(), Ok(ColorMatrix::Bt709));
+    assert_eq!("601".parse(), Ok(ColorMatrix::Bt601));
+    assert_eq!("auto".parse(), Ok(ColorMatrix::Auto));
+    assert!("srgb".parse::<ColorMatrix>().is_err());
+    assert_eq!(ColorMatrix::Bt601.to_string(), "bt601");
+}
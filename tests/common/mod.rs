@@ -0,0 +1,725 @@
+//! Helpers for building small synthetic subtitle payloads. Real disc rips can't be
+//! redistributed, so the fixtures are assembled by hand from the format specs and
+//! kept small enough to reason about.
+
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+use image::{GrayAlphaImage, RgbaImage};
+#[cfg(feature = "demux-mkv")]
+use matroska_demuxer::Frame;
+use subproc::binary_reader::PacketWriter;
+#[cfg(feature = "writers")]
+use subproc::srt::SrtCue;
+
+/// xorshift64*, so every run draws the same numbers and failures reproduce
+/// from the seed
+pub struct Rng(u64);
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        return Self(seed.wrapping_mul(0x9e3779b97f4a7c15) | 1);
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        return self.0.wrapping_mul(0x2545f4914f6cdd1d);
+    }
+
+    pub fn below(&mut self, max: u64) -> u64 {
+        return self.next() % max;
+    }
+}
+
+/// FNV-1a over the image dimensions and raw pixel data. Stable across
+/// platforms and Rust versions, unlike `DefaultHasher`.
+pub fn hash_bytes(width: u32, height: u32, data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in width
+        .to_be_bytes()
+        .iter()
+        .chain(height.to_be_bytes().iter())
+        .chain(data.iter())
+    {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return hash;
+}
+
+/// Bounding box of all pixels with a non-zero value in `alpha_channel`
+fn alpha_bounds<F: Fn(u32, u32) -> u8>(
+    width: u32,
+    height: u32,
+    alpha: F,
+) -> Option<(u32, u32, u32, u32)> {
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for y in 0..height {
+        for x in 0..width {
+            if alpha(x, y) == 0 {
+                continue;
+            }
+            bounds = Some(match bounds {
+                None => (x, y, x, y),
+                Some((x1, y1, x2, y2)) => (x1.min(x), y1.min(y), x2.max(x), y2.max(y)),
+            });
+        }
+    }
+    return bounds;
+}
+
+fn describe(width: u32, height: u32, hash: u64, bounds: Option<(u32, u32, u32, u32)>) -> String {
+    let bounds = match bounds {
+        Some((x1, y1, x2, y2)) => format!("{x1},{y1}-{x2},{y2}"),
+        None => String::from("empty"),
+    };
+    return format!("{width}x{height} bbox={bounds} hash={hash:016x}");
+}
+
+pub fn describe_gray_alpha(image: &GrayAlphaImage) -> String {
+    return describe(
+        image.width(),
+        image.height(),
+        hash_bytes(image.width(), image.height(), image.as_raw()),
+        alpha_bounds(image.width(), image.height(), |x, y| {
+            image.get_pixel(x, y).0[1]
+        }),
+    );
+}
+
+pub fn describe_rgba(image: &RgbaImage) -> String {
+    return describe(
+        image.width(),
+        image.height(),
+        hash_bytes(image.width(), image.height(), image.as_raw()),
+        alpha_bounds(image.width(), image.height(), |x, y| {
+            image.get_pixel(x, y).0[3]
+        }),
+    );
+}
+
+/// Compares `actual` against `tests/golden/<name>.txt`. Run with
+/// `UPDATE_GOLDEN=1` to (re)write the expected file after an intentional change.
+pub fn assert_golden(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{name}.txt"));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("Missing golden file {}", path.display()));
+    assert_eq!(
+        expected, actual,
+        "Output for {name} differs from the golden file"
+    );
+}
+
+/// A cue from `start` to `end` milliseconds
+#[cfg(feature = "writers")]
+pub fn cue(start: u64, end: u64, text: &str) -> SrtCue {
+    return SrtCue {
+        start: start * 1_000_000,
+        end: end * 1_000_000,
+        text: text.to_owned(),
+    };
+}
+
+#[cfg(feature = "demux-mkv")]
+pub fn mkv_frame(timestamp: u64, data: Vec<u8>) -> Frame {
+    return Frame {
+        track: 1,
+        timestamp,
+        data,
+        ..Default::default()
+    };
+}
+
+// PGS ------------------------------------------------------------------------
+
+pub struct PgsObjectRef {
+    pub object_id: u16,
+    pub window_id: u8,
+    pub x: u16,
+    pub y: u16,
+    /// (x, y, width, height)
+    pub crop: Option<(u16, u16, u16, u16)>,
+}
+
+/// Assembles the segments of a single PGS display set
+#[derive(Default)]
+pub struct PgsDisplaySetBuilder {
+    segments: Vec<u8>,
+}
+impl PgsDisplaySetBuilder {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    fn segment(&mut self, segment_type: u8, payload: Vec<u8>) {
+        self.segments.push(segment_type);
+        self.segments
+            .extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        self.segments.extend(payload);
+    }
+
+    /// `state` is the raw composition state byte (0x00, 0x40 or 0x80)
+    pub fn pcs(
+        mut self,
+        composition_number: u16,
+        state: u8,
+        palette_id: u8,
+        objects: &[PgsObjectRef],
+    ) -> Self {
+        let mut w = PacketWriter::new();
+        w.write_u16(1920);
+        w.write_u16(1080);
+        w.write_u8(0x10);
+        w.write_u16(composition_number);
+        w.write_u8(state);
+        w.write_u8(0);
+        w.write_u8(palette_id);
+        w.write_u8(objects.len() as u8);
+        for object in objects {
+            w.write_u16(object.object_id);
+            w.write_u8(object.window_id);
+            w.write_u8(if object.crop.is_some() { 0x80 } else { 0 });
+            w.write_u16(object.x);
+            w.write_u16(object.y);
+            if let Some((x, y, width, height)) = object.crop {
+                w.write_u16(x);
+                w.write_u16(y);
+                w.write_u16(width);
+                w.write_u16(height);
+            }
+        }
+        self.segment(0x16, w.finish());
+        return self;
+    }
+
+    /// Windows as (id, x, y, width, height)
+    pub fn wds(mut self, windows: &[(u8, u16, u16, u16, u16)]) -> Self {
+        let mut w = PacketWriter::new();
+        w.write_u8(windows.len() as u8);
+        for (id, x, y, width, height) in windows {
+            w.write_u8(*id);
+            w.write_u16(*x);
+            w.write_u16(*y);
+            w.write_u16(*width);
+            w.write_u16(*height);
+        }
+        self.segment(0x17, w.finish());
+        return self;
+    }
+
+    /// Entries as (entry id, luminance, alpha)
+    pub fn pds(mut self, palette_id: u8, version: u8, entries: &[(u8, u8, u8)]) -> Self {
+        let mut w = PacketWriter::new();
+        w.write_u8(palette_id);
+        w.write_u8(version);
+        for (id, y, a) in entries {
+            w.write_u8(*id);
+            w.write_u8(*y);
+            w.write_u8(128);
+            w.write_u8(128);
+            w.write_u8(*a);
+        }
+        self.segment(0x14, w.finish());
+        return self;
+    }
+
+    /// Adds an object, split across `fragments` ODS segments
+    pub fn ods(
+        mut self,
+        object_id: u16,
+        version: u8,
+        width: u16,
+        height: u16,
+        rle: &[u8],
+        fragments: usize,
+    ) -> Self {
+        let chunk_size = rle.len().div_ceil(fragments.max(1)).max(1);
+        let chunks: Vec<&[u8]> = rle.chunks(chunk_size).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut flags = 0u8;
+            if i == 0 {
+                flags |= 0x80 >> 1;
+            }
+            if i == chunks.len() - 1 {
+                flags |= 0x80;
+            }
+            let mut w = PacketWriter::new();
+            w.write_u16(object_id);
+            w.write_u8(version);
+            w.write_u8(flags);
+            // Only the first fragment carries the length & dimensions
+            if i == 0 {
+                let length = (rle.len() + 4) as u32;
+                w.write_u8((length >> 16) as u8);
+                w.write_u16(length as u16);
+                w.write_u16(width);
+                w.write_u16(height);
+            }
+            for byte in chunk.iter() {
+                w.write_u8(*byte);
+            }
+            self.segment(0x15, w.finish());
+        }
+        return self;
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.segment(0x80, Vec::new());
+        return self.segments;
+    }
+}
+
+/// Encodes rows of palette indices into PGS RLE
+pub fn pgs_rle(rows: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for row in rows {
+        let mut x = 0;
+        while x < row.len() {
+            let color = row[x];
+            let mut length = 1;
+            while x + length < row.len() && row[x + length] == color && length < 0x3FFF {
+                length += 1;
+            }
+            match (color, length) {
+                (c, 1) if c != 0 => out.push(c),
+                (0, l) if l < 64 => out.extend_from_slice(&[0, l as u8]),
+                (0, l) => out.extend_from_slice(&[0, 0x40 | (l >> 8) as u8, l as u8]),
+                (c, l) if l < 64 => out.extend_from_slice(&[0, 0x80 | l as u8, c]),
+                (c, l) => out.extend_from_slice(&[0, 0xC0 | (l >> 8) as u8, l as u8, c]),
+            }
+            x += length;
+        }
+        out.extend_from_slice(&[0, 0]);
+    }
+    return out;
+}
+
+/// A simple glyph-ish test pattern: a filled bar with an outline
+pub fn outlined_bar(width: usize, height: usize, fill: u8, outline: u8) -> Vec<Vec<u8>> {
+    let mut rows = Vec::new();
+    for y in 0..height {
+        let mut row = Vec::new();
+        for x in 0..width {
+            let edge = x == 0 || y == 0 || x == width - 1 || y == height - 1;
+            row.push(if edge { outline } else { fill });
+        }
+        rows.push(row);
+    }
+    return rows;
+}
+
+// VobSub ---------------------------------------------------------------------
+
+pub const VOBSUB_IDX: &str = "# VobSub index file, v7 (do not modify this line!)\n\
+size: 720x480\n\
+palette: 000000, ffffff, 808080, 202020, ff0000, 00ff00, 0000ff, ffff00, \
+00ffff, ff00ff, 404040, c0c0c0, 101010, 303030, 505050, 606060\n";
+
+struct NibbleWriter {
+    data: Vec<u8>,
+    half: bool,
+}
+impl NibbleWriter {
+    fn new() -> Self {
+        return Self {
+            data: Vec::new(),
+            half: false,
+        };
+    }
+    fn push(&mut self, nibble: u8) {
+        if self.half {
+            *self.data.last_mut().unwrap() |= nibble & 0xF;
+        } else {
+            self.data.push((nibble & 0xF) << 4);
+        }
+        self.half = !self.half;
+    }
+    fn align(&mut self) {
+        if self.half {
+            self.half = false;
+        }
+    }
+}
+
+fn vobsub_encode_line(writer: &mut NibbleWriter, row: &[u8]) {
+    let mut x = 0;
+    while x < row.len() {
+        let color = row[x];
+        let mut length = 1;
+        while x + length < row.len() && row[x + length] == color && length < 255 {
+            length += 1;
+        }
+        let value = ((length as u16) << 2) | color as u16;
+        if x + length == row.len() {
+            // Fill to end of line
+            for nibble in [0, 0, 0, color] {
+                writer.push(nibble);
+            }
+        } else if value < 0x10 {
+            writer.push(value as u8);
+        } else if value < 0x40 {
+            writer.push((value >> 4) as u8);
+            writer.push(value as u8);
+        } else if value < 0x100 {
+            writer.push(0);
+            writer.push((value >> 4) as u8);
+            writer.push(value as u8);
+        } else {
+            writer.push(0);
+            writer.push((value >> 8) as u8);
+            writer.push((value >> 4) as u8);
+            writer.push(value as u8);
+        }
+        x += length;
+    }
+    writer.align();
+}
+
+/// Builds a complete single-sequence subpicture packet. `rows` holds 2-bit
+/// color indices, which select into `colors`/`alphas` the same way the decoder does.
+pub fn vobsub_subpicture(
+    x: u16,
+    y: u16,
+    rows: &[Vec<u8>],
+    colors: [u8; 4],
+    alphas: [u8; 4],
+) -> Vec<u8> {
+    return vobsub_subpicture_timed(x, y, rows, colors, alphas, &[], None);
+}
+
+/// Same as [`vobsub_subpicture`], with extra raw `commands` in the first
+/// control sequence, and `stop` adding a second sequence that stops
+/// displaying after that delay (in 1024/90000s units)
+pub fn vobsub_subpicture_timed(
+    x: u16,
+    y: u16,
+    rows: &[Vec<u8>],
+    colors: [u8; 4],
+    alphas: [u8; 4],
+    commands: &[u8],
+    stop: Option<u16>,
+) -> Vec<u8> {
+    let mut even = NibbleWriter::new();
+    let mut odd = NibbleWriter::new();
+    for (i, row) in rows.iter().enumerate() {
+        if i % 2 == 0 {
+            vobsub_encode_line(&mut even, row);
+        } else {
+            vobsub_encode_line(&mut odd, row);
+        }
+    }
+    let size = (rows[0].len() as u16, rows.len() as u16);
+    return vobsub_subpicture_fields(
+        (x, y),
+        size,
+        (&even.data, &odd.data),
+        colors,
+        alphas,
+        commands,
+        stop,
+    );
+}
+
+/// Same as [`vobsub_subpicture_timed`], with the RLE data of the even and
+/// odd fields given as-is
+pub fn vobsub_subpicture_fields(
+    (x, y): (u16, u16),
+    (width, height): (u16, u16),
+    (even, odd): (&[u8], &[u8]),
+    colors: [u8; 4],
+    alphas: [u8; 4],
+    commands: &[u8],
+    stop: Option<u16>,
+) -> Vec<u8> {
+    let even_offset = 4u16;
+    let odd_offset = even_offset + even.len() as u16;
+    let control_offset = odd_offset + odd.len() as u16;
+
+    let (x2, y2) = (x + width - 1, y + height - 1);
+    let mut control = vec![0, 0];
+    control.extend_from_slice(&control_offset.to_be_bytes());
+    control.push(0x01);
+    control.extend_from_slice(&[0x03, colors[0] << 4 | colors[1], colors[2] << 4 | colors[3]]);
+    control.extend_from_slice(&[0x04, alphas[0] << 4 | alphas[1], alphas[2] << 4 | alphas[3]]);
+    control.extend_from_slice(&[
+        0x05,
+        (x >> 4) as u8,
+        ((x & 0xF) << 4) as u8 | (x2 >> 8) as u8,
+        x2 as u8,
+        (y >> 4) as u8,
+        ((y & 0xF) << 4) as u8 | (y2 >> 8) as u8,
+        y2 as u8,
+    ]);
+    control.push(0x06);
+    control.extend_from_slice(&even_offset.to_be_bytes());
+    control.extend_from_slice(&odd_offset.to_be_bytes());
+    control.extend_from_slice(commands);
+    control.push(0xFF);
+    if let Some(stop) = stop {
+        // Point the first sequence at a second one, which points to itself
+        let stop_offset = control_offset + control.len() as u16;
+        control[2..4].copy_from_slice(&stop_offset.to_be_bytes());
+        control.extend_from_slice(&stop.to_be_bytes());
+        control.extend_from_slice(&stop_offset.to_be_bytes());
+        control.extend_from_slice(&[0x02, 0xFF]);
+    }
+
+    let total = control_offset as usize + control.len();
+    let mut packet = Vec::with_capacity(total);
+    packet.extend_from_slice(&(total as u16).to_be_bytes());
+    packet.extend_from_slice(&control_offset.to_be_bytes());
+    packet.extend_from_slice(even);
+    packet.extend_from_slice(odd);
+    packet.extend(control);
+    return packet;
+}
+
+/// Wraps subpicture pieces into an MPEG-2 program stream, as found in a `.sub`
+/// file. Pieces are (stream, PTS in 90kHz ticks, data), each in its own pack
+/// and PES packet, with a padding packet after each.
+pub fn vobsub_program_stream(pieces: &[(u8, Option<u64>, &[u8])]) -> Vec<u8> {
+    let mut stream = Vec::new();
+    for (substream, pts, data) in pieces {
+        // Pack header with no stuffing
+        stream.extend_from_slice(&[0, 0, 1, 0xBA, 0x44, 0, 4, 0, 4, 1, 1, 0x89, 0xC3, 0xF8]);
+
+        let mut header = Vec::new();
+        if let Some(pts) = pts {
+            header.push(0x21 | ((pts >> 29) & 0x0E) as u8);
+            header.extend_from_slice(&((((pts >> 14) & 0xFFFE) | 1) as u16).to_be_bytes());
+            header.extend_from_slice(&((((pts << 1) & 0xFFFE) | 1) as u16).to_be_bytes());
+        }
+        let mut body = vec![
+            0x81,
+            if pts.is_some() { 0x80 } else { 0 },
+            header.len() as u8,
+        ];
+        body.extend(header);
+        body.push(0x20 + substream);
+        body.extend_from_slice(data);
+        stream.extend_from_slice(&[0, 0, 1, 0xBD]);
+        stream.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        stream.extend(body);
+
+        stream.extend_from_slice(&[0, 0, 1, 0xBE, 0, 4, 0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+    stream.extend_from_slice(&[0, 0, 1, 0xB9]);
+    return stream;
+}
+
+// TextST ---------------------------------------------------------------------
+
+fn textst_segment(segment_type: u8, payload: Vec<u8>) -> Vec<u8> {
+    let mut segment = vec![segment_type];
+    segment.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    segment.extend(payload);
+    return segment;
+}
+
+/// A dialog style segment with a single bottom-centered region style (id 0)
+pub fn textst_dss() -> Vec<u8> {
+    let mut w = PacketWriter::new();
+    w.write_u8(0);
+    w.write_u8(1);
+    w.write_u8(0);
+    // Region style 0
+    w.write_u8(0);
+    for value in [0u16, 880, 1920, 200] {
+        w.write_u16(value);
+    }
+    w.write_u8(0);
+    w.write_u8(0);
+    for value in [160u16, 20, 1600, 160] {
+        w.write_u16(value);
+    }
+    for value in [0u8, 2, 2, 60, 0, 0, 48, 1, 2, 2] {
+        w.write_u8(value);
+    }
+    // Palette
+    w.write_u16(10);
+    for entry in [[1u8, 235, 128, 128, 255], [2, 16, 128, 128, 255]] {
+        for byte in entry {
+            w.write_u8(byte);
+        }
+    }
+    w.write_u16(1);
+    return textst_segment(0x81, w.finish());
+}
+
+/// A dialog presentation segment. `lines` are separated by line breaks, and
+/// the second line is italicized to exercise inline styles.
+pub fn textst_dps(start_pts: u64, end_pts: u64, forced: bool, lines: &[&str]) -> Vec<u8> {
+    let mut region = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            region.extend_from_slice(&[0x1B, 0x0A, 0]);
+        }
+        if i == 1 {
+            region.extend_from_slice(&[0x1B, 0x03, 3, 0b10, 2, 2]);
+        }
+        region.extend_from_slice(&[0x1B, 0x01, line.len() as u8]);
+        region.extend_from_slice(line.as_bytes());
+        if i == 1 {
+            region.extend_from_slice(&[0x1B, 0x0B, 0]);
+        }
+    }
+
+    let mut w = PacketWriter::new();
+    for pts in [start_pts, end_pts] {
+        w.write_u8((pts >> 32) as u8 & 1);
+        w.write_u32(pts as u32);
+    }
+    w.write_u8(0);
+    w.write_u8(1);
+    w.write_u8(if forced { 0x40 } else { 0 });
+    w.write_u8(0);
+    w.write_u16(region.len() as u16);
+    for byte in region {
+        w.write_u8(byte);
+    }
+    return textst_segment(0x82, w.finish());
+}
+
+/// A track for [`build_mkv`]: (number, codec ID, codec private)
+pub type MkvTrack<'a> = (u64, &'a str, Option<&'a [u8]>);
+
+/// Assembles a minimal MKV with a 1ms timestamp scale. Frames are
+/// (track, timestamp in ms, data) and get split into clusters every 10
+/// seconds, each referenced from Cues.
+pub fn build_mkv(tracks: &[MkvTrack], frames: &[(u64, u64, Vec<u8>)]) -> Vec<u8> {
+    return build_mkv_with(tracks, frames, None, &[]);
+}
+
+/// [`build_mkv`] with a segment UID, and `extra` (complete top-level
+/// elements, like Chapters) placed after Tracks
+pub fn build_mkv_with(
+    tracks: &[MkvTrack],
+    frames: &[(u64, u64, Vec<u8>)],
+    segment_uid: Option<[u8; 16]>,
+    extra: &[u8],
+) -> Vec<u8> {
+    use subproc::ebml::*;
+
+    let mut header = encode_uint(0x4286, 1); // EBMLVersion
+    header.extend(encode_uint(0x42F7, 1)); // EBMLReadVersion
+    header.extend(encode_uint(0x42F2, 4)); // EBMLMaxIDLength
+    header.extend(encode_uint(0x42F3, 8)); // EBMLMaxSizeLength
+    header.extend(encode_string(0x4282, "matroska")); // DocType
+    header.extend(encode_uint(0x4287, 4)); // DocTypeVersion
+    header.extend(encode_uint(0x4285, 2)); // DocTypeReadVersion
+
+    let mut info = encode_uint(ID_TIMESTAMP_SCALE, 1_000_000);
+    info.extend(encode_string(0x4D80, "subproc tests")); // MuxingApp
+    info.extend(encode_string(0x5741, "subproc tests")); // WritingApp
+    if let Some(uid) = segment_uid {
+        info.extend(encode_element(ID_SEGMENT_UID, &uid));
+    }
+    let mut segment = encode_element(ID_INFO, &info);
+    let mut entries = Vec::new();
+    for (number, codec, private) in tracks {
+        let mut entry = encode_uint(ID_TRACK_NUMBER, *number);
+        entry.extend(encode_uint(ID_TRACK_UID, *number));
+        entry.extend(encode_uint(ID_TRACK_TYPE, 0x11));
+        entry.extend(encode_string(ID_CODEC_ID, codec));
+        if let Some(private) = private {
+            entry.extend(encode_element(ID_CODEC_PRIVATE, private));
+        }
+        entries.extend(encode_element(ID_TRACK_ENTRY, &entry));
+    }
+    segment.extend(encode_element(ID_TRACKS, &entries));
+    segment.extend_from_slice(extra);
+
+    let mut cues = Vec::new();
+    let mut cluster: Option<(u64, Vec<u8>)> = None;
+    let mut flush = |segment: &mut Vec<u8>, cluster: Option<(u64, Vec<u8>)>| {
+        if let Some((timestamp, data)) = cluster {
+            let mut positions = encode_uint(ID_CUE_TRACK, tracks[0].0);
+            positions.extend(encode_uint(ID_CUE_CLUSTER_POSITION, segment.len() as u64));
+            let mut point = encode_uint(ID_CUE_TIME, timestamp);
+            point.extend(encode_element(ID_CUE_TRACK_POSITIONS, &positions));
+            cues.extend(encode_element(ID_CUE_POINT, &point));
+            segment.extend(encode_element(ID_CLUSTER, &data));
+        }
+    };
+    for (track, timestamp, data) in frames {
+        let cluster_start = timestamp - timestamp % 10_000;
+        if cluster
+            .as_ref()
+            .is_none_or(|(start, _)| *start != cluster_start)
+        {
+            flush(&mut segment, cluster.take());
+            cluster = Some((cluster_start, encode_uint(ID_TIMESTAMP, cluster_start)));
+        }
+        let (_, cluster_data) = cluster.as_mut().unwrap();
+        let mut block = encode_size(*track, None);
+        block.extend(((timestamp - cluster_start) as i16).to_be_bytes());
+        block.push(0x80); // keyframe
+        block.extend_from_slice(data);
+        cluster_data.extend(encode_element(ID_SIMPLE_BLOCK, &block));
+    }
+    flush(&mut segment, cluster.take());
+    segment.extend(encode_element(ID_CUES, &cues));
+
+    let mut file = encode_element(ID_EBML, &header);
+    file.extend(encode_element(ID_SEGMENT, &segment));
+    return file;
+}
+
+/// A Chapters element with one edition. Chapters are (start ms, end ms, title).
+pub fn mkv_chapters(chapters: &[(u64, Option<u64>, &str)]) -> Vec<u8> {
+    use subproc::ebml::*;
+
+    let chapters: Vec<MkvChapter> = chapters
+        .iter()
+        .map(|(start, end, title)| (None, *start, *end, *title))
+        .collect();
+    return encode_element(ID_CHAPTERS, &mkv_edition(false, &chapters));
+}
+
+/// A chapter for [`mkv_edition`]: (segment UID, start ms, end ms, title)
+pub type MkvChapter<'a> = (Option<[u8; 16]>, u64, Option<u64>, &'a str);
+
+/// An EditionEntry element, to be wrapped in Chapters
+pub fn mkv_edition(ordered: bool, chapters: &[MkvChapter]) -> Vec<u8> {
+    use subproc::ebml::*;
+
+    let mut edition = encode_uint(ID_EDITION_FLAG_ORDERED, ordered as u64);
+    for (i, (segment, start, end, title)) in chapters.iter().enumerate() {
+        let mut atom = encode_uint(ID_CHAPTER_UID, i as u64 + 1);
+        atom.extend(encode_uint(ID_CHAPTER_TIME_START, start * 1_000_000));
+        if let Some(end) = end {
+            atom.extend(encode_uint(ID_CHAPTER_TIME_END, end * 1_000_000));
+        }
+        if let Some(segment) = segment {
+            atom.extend(encode_element(ID_CHAPTER_SEGMENT_UID, segment));
+        }
+        let display = encode_string(ID_CHAP_STRING, title);
+        atom.extend(encode_element(ID_CHAPTER_DISPLAY, &display));
+        edition.extend(encode_element(ID_CHAPTER_ATOM, &atom));
+    }
+    return encode_element(ID_EDITION_ENTRY, &edition);
+}
+
+/// An Attachments element. Files are (name, media type, data).
+pub fn mkv_attachments(files: &[(&str, &str, &[u8])]) -> Vec<u8> {
+    use subproc::ebml::*;
+
+    let mut attachments = Vec::new();
+    for (i, (name, media_type, data)) in files.iter().enumerate() {
+        let mut file = encode_string(ID_FILE_NAME, name);
+        file.extend(encode_string(ID_FILE_MEDIA_TYPE, media_type));
+        file.extend(encode_element(ID_FILE_DATA, data));
+        file.extend(encode_uint(ID_FILE_UID, i as u64 + 1));
+        attachments.extend(encode_element(ID_ATTACHED_FILE, &file));
+    }
+    return encode_element(ID_ATTACHMENTS, &attachments);
+}
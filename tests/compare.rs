@@ -0,0 +1,39 @@
+//! Scoring OCR output against a reference transcript.
+
+mod common;
+
+use common::*;
+use subproc::compare::{edit_distance, score};
+
+#[test]
+fn distance() {
+    let chars = |text: &str| text.chars().collect::<Vec<_>>();
+    assert_eq!(edit_distance(&chars("kitten"), &chars("sitting")), 3);
+    assert_eq!(edit_distance(&chars(""), &chars("abc")), 3);
+    assert_eq!(edit_distance(&chars("same"), &chars("same")), 0);
+}
+
+#[test]
+fn scores_by_time() {
+    let reference = [
+        cue(1_000, 2_000, "Hello there."),
+        cue(3_000, 4_000, "<i>General Kenobi!</i>"),
+    ];
+    // Tags, line breaks and timing differences don't count
+    let perfect = [
+        cue(1_050, 2_100, "Hello\nthere."),
+        cue(2_900, 4_000, "General  Kenobi!"),
+    ];
+    let result = score(&perfect, &reference);
+    assert_eq!(result.errors, 0);
+    assert_eq!(result.characters, 12 + 15);
+
+    // A misread, a missed cue and a cue that's not in the reference
+    let ocr = [
+        cue(1_000, 2_000, "He1lo there."),
+        cue(5_000, 6_000, "Noise"),
+    ];
+    let result = score(&ocr, &reference);
+    assert_eq!(result.errors, 1 + 15 + 5);
+    assert!((result.error_rate() - 21.0 / 27.0).abs() < 1e-9);
+}
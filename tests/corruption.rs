@@ -0,0 +1,428 @@
+//! Every parser entry point run over truncated and bit-flipped copies of
+//! valid samples. Errors are fine; panics and huge allocations aren't, since
+//! all of these read untrusted files.
+
+mod common;
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::RefCell,
+    io::Cursor,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Once,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use common::*;
+use matroska_demuxer::MatroskaFile;
+use subproc::{
+    attachments::read_attachments,
+    bdsup::{PgsParser, read_palettes, shows_objects},
+    extract::SubtitleExtractor,
+    textst::TextstParser,
+    vobs,
+};
+
+/// Far more than any valid input needs (a 4K PGS screen is 32 MB), far
+/// less than what a corrupted size field can ask for
+const ALLOCATION_LIMIT: usize = 64 << 20;
+const FLIPS_PER_SAMPLE: usize = 2000;
+
+/// Tracks the largest single allocation, which is where a size field read
+/// from the file would show up
+struct LargestAllocation;
+
+static LARGEST: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for LargestAllocation {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LARGEST.fetch_max(layout.size(), Ordering::Relaxed);
+        return unsafe { System.alloc(layout) };
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        LARGEST.fetch_max(layout.size(), Ordering::Relaxed);
+        return unsafe { System.alloc_zeroed(layout) };
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LARGEST.fetch_max(new_size, Ordering::Relaxed);
+        return unsafe { System.realloc(ptr, layout, new_size) };
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: LargestAllocation = LargestAllocation;
+
+thread_local! {
+    /// Where panics in parsers on this thread go, with their location,
+    /// instead of being printed
+    static PANIC: RefCell<Option<Option<String>>> = const { RefCell::new(None) };
+}
+
+fn record_panics() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let default = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            PANIC.with(|panic| match *panic.borrow_mut() {
+                Some(ref mut recorded) => *recorded = Some(info.to_string()),
+                None => default(info),
+            });
+        }));
+    });
+}
+
+/// Every truncation of `sample`, then copies with one to three random bits
+/// flipped or bytes overwritten, each with a description for failures
+fn corruptions(sample: &[u8]) -> impl Iterator<Item = (String, Vec<u8>)> + '_ {
+    let truncated = (0..sample.len()).map(|length| {
+        return (format!("truncated to {length}"), sample[..length].to_vec());
+    });
+    let mut rng = Rng::new(sample.len() as u64);
+    let flipped = (0..FLIPS_PER_SAMPLE).map(move |_| {
+        let mut data = sample.to_vec();
+        let mut changes = Vec::new();
+        for _ in 0..1 + rng.below(3) {
+            let offset = rng.below(data.len() as u64) as usize;
+            if rng.below(4) == 0 {
+                data[offset] = rng.next() as u8;
+                changes.push(format!("byte {offset} = {:#04x}", data[offset]));
+            } else {
+                let bit = rng.below(8);
+                data[offset] ^= 1 << bit;
+                changes.push(format!("bit {bit} of byte {offset}"));
+            }
+        }
+        return (changes.join(", "), data);
+    });
+    return truncated.chain(flipped);
+}
+
+/// Runs `parse` over every corruption of `sample`, failing on a panic or an
+/// allocation over [`ALLOCATION_LIMIT`]
+fn check(name: &str, sample: &[u8], parse: impl Fn(&[u8])) {
+    record_panics();
+    let mut failures = Vec::new();
+    for (corruption, data) in corruptions(sample) {
+        PANIC.with(|panic| *panic.borrow_mut() = Some(None));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| parse(&data)));
+        let message = PANIC.with(|panic| panic.borrow_mut().take()).flatten();
+        if result.is_err() {
+            failures.push(format!(
+                "{name}, {corruption}: {}",
+                message.unwrap_or_default()
+            ));
+        }
+        let largest = LARGEST.load(Ordering::Relaxed);
+        if largest > ALLOCATION_LIMIT {
+            failures.push(format!("{name}, {corruption}: allocated {largest} bytes"));
+            LARGEST.store(0, Ordering::Relaxed);
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} failures:\n{}",
+        failures.len(),
+        failures[..failures.len().min(20)].join("\n")
+    );
+}
+
+/// Sets a display set's video size to 160x40, so the screens rendered for
+/// thousands of inputs are small
+fn small_screen(mut display_set: Vec<u8>) -> Vec<u8> {
+    display_set[3..7].copy_from_slice(&[0, 160, 0, 40]);
+    return display_set;
+}
+
+fn pgs_samples() -> Vec<(&'static str, Vec<u8>)> {
+    let objects = [PgsObjectRef {
+        object_id: 1,
+        window_id: 0,
+        x: 0,
+        y: 0,
+        crop: Some((2, 2, 60, 16)),
+    }];
+    let epoch = PgsDisplaySetBuilder::new()
+        .pcs(1, 0x80, 0, &objects)
+        .wds(&[(0, 30, 10, 100, 20)])
+        .pds(0, 0, &[(1, 235, 255), (2, 16, 255), (3, 128, 128)])
+        .ods(1, 0, 100, 20, &pgs_rle(&outlined_bar(100, 20, 1, 2)), 2)
+        .finish();
+    let update = PgsDisplaySetBuilder::new()
+        .pcs(2, 0x00, 0, &objects)
+        .pds(0, 1, &[(1, 235, 64), (2, 16, 64)])
+        .finish();
+    let clear = PgsDisplaySetBuilder::new()
+        .pcs(3, 0x00, 0, &[])
+        .wds(&[(0, 30, 10, 100, 20)])
+        .finish();
+    // Acquisition points add to the running composition, so two of these
+    // after the epoch pass 256 objects. A tiny object keeps drawing them
+    // cheap.
+    let many: Vec<_> = (0..129)
+        .map(|_| PgsObjectRef {
+            object_id: 2,
+            window_id: 0,
+            x: 0,
+            y: 0,
+            crop: Some((0, 0, 2, 2)),
+        })
+        .collect();
+    let acquisition = PgsDisplaySetBuilder::new()
+        .pcs(4, 0x40, 0, &many)
+        .ods(2, 0, 2, 2, &pgs_rle(&outlined_bar(2, 2, 1, 2)), 1)
+        .finish();
+    return vec![
+        ("PGS epoch", small_screen(epoch)),
+        ("PGS update", small_screen(update)),
+        ("PGS clear", small_screen(clear)),
+        ("PGS acquisition point", small_screen(acquisition)),
+    ];
+}
+
+#[test]
+fn pgs_display_sets() {
+    let samples = pgs_samples();
+    let (_, epoch) = &samples[0];
+    // The samples' screens are too small for a corrupted size to stand out
+    let mut huge = epoch.clone();
+    huge[3..7].copy_from_slice(&[0xFF; 4]);
+    assert!(PgsParser::new().process_display_set(&huge).is_err());
+
+    for (name, sample) in samples.iter() {
+        check(name, sample, |data| {
+            let _ = read_palettes(data);
+            let _ = shows_objects(data);
+            // Fresh, and following a valid epoch so updates have something
+            // to apply to
+            let _ = PgsParser::new().process_display_set(data);
+            let mut parser = PgsParser::with_recovery();
+            let _ = parser.process_display_set(epoch);
+            // Twice, since acquisition points add to what's already shown
+            for _ in 0..2 {
+                if parser.process_display_set(data).is_err() {
+                    break;
+                }
+                let _ = parser.render_indexed();
+                let _ = parser.regions();
+            }
+        });
+    }
+}
+
+#[test]
+fn pgs_padded_segments() {
+    let (_, epoch) = &pgs_samples()[0];
+    // Two zero bytes after the composition's fields
+    let size = u16::from_be_bytes([epoch[1], epoch[2]]);
+    let mut padded = vec![epoch[0]];
+    padded.extend((size + 2).to_be_bytes());
+    padded.extend(&epoch[3..3 + size as usize]);
+    padded.extend([0, 0]);
+    padded.extend(&epoch[3 + size as usize..]);
+
+    let mut parser = PgsParser::new();
+    parser.process_display_set(epoch).unwrap();
+    assert_eq!(parser.padded_segments(), 0);
+    assert!(parser.process_display_set(&padded).unwrap().is_some());
+    assert_eq!(parser.padded_segments(), 1);
+}
+
+#[test]
+fn pgs_run_lengths_overflowing_a_line() {
+    // Enough of the longest transparent runs on one line to pass u32::MAX
+    let mut rle = Vec::new();
+    for _ in 0..(u32::MAX / 16_383 + 1) {
+        rle.extend([0x00, 0x7F, 0xFF]);
+    }
+    rle.extend([1, 0x00, 0x00]);
+    let display_set = PgsDisplaySetBuilder::new()
+        .pcs(
+            1,
+            0x80,
+            0,
+            &[PgsObjectRef {
+                object_id: 1,
+                window_id: 0,
+                x: 0,
+                y: 0,
+                crop: None,
+            }],
+        )
+        .wds(&[(0, 0, 0, 100, 20)])
+        .pds(0, 0, &[(1, 235, 255)])
+        .ods(1, 0, 100, 20, &rle, rle.len().div_ceil(60_000))
+        .finish();
+    let display_set = small_screen(display_set);
+    let mut parser = PgsParser::new();
+    parser.process_display_set(&display_set).unwrap();
+    let _ = parser.render_indexed();
+    let _ = parser.regions();
+}
+
+#[test]
+fn pgs_acquisition_points_past_256_objects() {
+    let samples = pgs_samples();
+    let (_, epoch) = &samples[0];
+    let object = [PgsObjectRef {
+        object_id: 1,
+        window_id: 0,
+        x: 0,
+        y: 0,
+        crop: None,
+    }];
+    // Each acquisition point adds its objects to the running composition
+    let acquisition_points: Vec<_> = (2..301)
+        .map(|composition_number| {
+            let display_set = PgsDisplaySetBuilder::new()
+                .pcs(composition_number, 0x40, 0, &object)
+                .finish();
+            return small_screen(display_set);
+        })
+        .collect();
+    let mut parser = PgsParser::new();
+    parser.process_display_set(epoch).unwrap();
+    for display_set in acquisition_points.iter() {
+        parser.process_display_set(display_set).unwrap();
+    }
+    let _ = parser.render_indexed();
+    let _ = parser.regions();
+}
+
+#[test]
+fn vobsub() {
+    check("VobSub idx", VOBSUB_IDX.as_bytes(), |data| {
+        let _ = vobs::parse_idx(data);
+    });
+
+    let idx = vobs::parse_idx(VOBSUB_IDX.as_bytes()).unwrap();
+    let rows = outlined_bar(40, 7, 1, 2);
+    let packet = vobsub_subpicture_timed(
+        100,
+        400,
+        &rows,
+        [1, 2, 3, 0],
+        [15, 15, 15, 0],
+        &[],
+        Some(10),
+    );
+    check("VobSub subpicture", &packet, |data| {
+        let _ = vobs::decode_frame(&idx, data);
+        let _ = vobs::parse_frame_indexed(&idx, data);
+        let mut assembler = vobs::SubpictureAssembler::new();
+        let _ = assembler.push(data);
+    });
+
+    let (first, rest) = packet.split_at(10);
+    let sub_file = vobsub_program_stream(&[(0, Some(90_000), first), (0, None, rest)]);
+    check("VobSub program stream", &sub_file, |data| {
+        for subpicture in vobs::SubFileReader::new(data).flatten() {
+            let _ = vobs::decode_frame(&idx, &subpicture.data);
+        }
+    });
+}
+
+#[test]
+fn vobsub_coordinates() {
+    let idx = vobs::parse_idx(VOBSUB_IDX.as_bytes()).unwrap();
+    let rows = outlined_bar(40, 7, 1, 2);
+    // A second coordinates command after the builder's own replaces them
+    let packet = |(x1, x2, y1, y2): (u16, u16, u16, u16)| {
+        let coordinates = [
+            0x05,
+            (x1 >> 4) as u8,
+            ((x1 & 0xF) << 4) as u8 | (x2 >> 8) as u8,
+            x2 as u8,
+            (y1 >> 4) as u8,
+            ((y1 & 0xF) << 4) as u8 | (y2 >> 8) as u8,
+            y2 as u8,
+        ];
+        return vobsub_subpicture_timed(
+            100,
+            400,
+            &rows,
+            [1, 2, 3, 0],
+            [15, 15, 15, 0],
+            &coordinates,
+            None,
+        );
+    };
+
+    for inverted in [
+        (200, 100, 400, 406),
+        (100, 139, 406, 400),
+        (4095, 0, 4095, 0),
+    ] {
+        let packet = packet(inverted);
+        assert!(matches!(
+            vobs::decode_frame(&idx, &packet),
+            Err(vobs::SubsError::InvalidCoordinates { .. })
+        ));
+        assert!(matches!(
+            vobs::parse_frame_indexed(&idx, &packet),
+            Err(vobs::SubsError::InvalidCoordinates { .. })
+        ));
+    }
+    // Smaller than the image data, which then runs past the end of a line
+    assert!(matches!(
+        vobs::decode_frame(&idx, &packet((100, 120, 400, 406))),
+        Err(vobs::SubsError::InvalidFrame)
+    ));
+    // A single pixel is still an area
+    let frame = vobs::decode_frame(&idx, &packet((100, 100, 400, 400))).unwrap();
+    assert_eq!(frame.image.dimensions(), (1, 1));
+    // Larger, which runs out of image data
+    assert!(vobs::decode_frame(&idx, &packet((0, 4095, 0, 4095))).is_err());
+    let frame = vobs::decode_frame(&idx, &packet((100, 139, 400, 406))).unwrap();
+    assert_eq!(frame.image.dimensions(), (40, 7));
+}
+
+#[test]
+fn textst() {
+    let dss = textst_dss();
+    check("TextST style", &dss, |data| {
+        let _ = TextstParser::with_codec_private(data);
+    });
+    let dps = textst_dps(90_000, 180_000, false, &["Hello", "there"]);
+    check("TextST presentation", &dps, |data| {
+        let mut parser = TextstParser::with_codec_private(&dss).unwrap();
+        if let Ok(Some(presentation)) = parser.process_segments(data) {
+            let _ = parser.to_event(&presentation);
+        }
+    });
+}
+
+#[test]
+fn matroska() {
+    let samples = pgs_samples();
+    let (_, epoch) = &samples[0];
+    let (_, clear) = &samples[2];
+    let mkv = build_mkv_with(
+        &[(1, "S_HDMV/PGS", None)],
+        &[(1, 1_000, epoch.clone()), (1, 2_000, clear.clone())],
+        Some([7; 16]),
+        &mkv_attachments(&[("notes.txt", "text/plain", b"Hello")]),
+    );
+    // The rest of the container is parsed by matroska-demuxer, which isn't
+    // covered here
+    check("MKV", &mkv, |data| {
+        let _ = read_attachments(&mut Cursor::new(data));
+    });
+    check("MKV block", epoch, |data| {
+        let mkv = build_mkv(
+            &[(1, "S_HDMV/PGS", None)],
+            &[(1, 1_000, data.to_vec()), (1, 2_000, clear.clone())],
+        );
+        let mkv = MatroskaFile::open(Cursor::new(mkv)).unwrap();
+        let mut extractor = SubtitleExtractor::new(mkv, None).unwrap();
+        extractor.set_indexed(true);
+        for _ in extractor {}
+    });
+}
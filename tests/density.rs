@@ -0,0 +1,97 @@
+//! Scanning block times for the subtitle density map.
+
+mod common;
+
+use std::io::Cursor;
+
+use common::*;
+use subproc::density::{Density, Gap, GapKind, ScanSource, find_gaps, scan_track};
+
+const MS: u64 = 1_000_000;
+const MINUTE: u64 = 60_000 * MS;
+
+#[test]
+fn scans_clusters_without_cues() {
+    // The test muxer only indexes the first track, as video would be
+    let file = build_mkv(
+        &[(1, "V_MPEG4/ISO/AVC", None), (2, "S_TEXT/UTF8", None)],
+        &[
+            (1, 0, vec![0; 64]),
+            (2, 1_500, b"a".to_vec()),
+            (1, 9_000, vec![0; 64]),
+            (2, 24_250, b"b".to_vec()),
+            (2, 24_900, b"c".to_vec()),
+        ],
+    );
+    let scan = scan_track(&mut Cursor::new(file), 2).unwrap();
+    assert_eq!(scan.source, ScanSource::Clusters);
+    assert_eq!(scan.times, [1_500 * MS, 24_250 * MS, 24_900 * MS]);
+}
+
+#[test]
+fn prefers_cues() {
+    let file = build_mkv(
+        &[(1, "S_TEXT/UTF8", None)],
+        &[(1, 1_500, b"a".to_vec()), (1, 24_250, b"b".to_vec())],
+    );
+    let scan = scan_track(&mut Cursor::new(file), 1).unwrap();
+    assert_eq!(scan.source, ScanSource::Cues);
+    // The cluster starts the test muxer indexes
+    assert_eq!(scan.times, [0, 20_000 * MS]);
+}
+
+#[test]
+fn buckets_and_gaps() {
+    let times: Vec<u64> = [0, 10, 70, 80, 90, 130, 600, 610]
+        .iter()
+        .map(|seconds| seconds * 1_000 * MS)
+        .collect();
+    let density = Density::new(&times, MINUTE, Some(12 * MINUTE));
+    assert_eq!(density.counts, [2, 3, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0]);
+    assert_eq!(density.sparkline(), "▆█▃       ▆ ");
+    assert_eq!(density.render(6), "00:00:00 ▆█▃\n00:06:00     ▆");
+    let gaps: Vec<(u64, u64)> = density
+        .empty_ranges(5)
+        .iter()
+        .map(|gap| (gap.start, gap.end))
+        .collect();
+    assert_eq!(gaps, [(3 * MINUTE, 10 * MINUTE)]);
+    assert!(density.empty_ranges(8).is_empty());
+}
+
+#[test]
+fn exact_gaps() {
+    let times: Vec<u64> = [30, 40, 400, 410, 1_000]
+        .iter()
+        .map(|seconds| seconds * 1_000 * MS)
+        .collect();
+    let gaps = find_gaps(&times, Some(40 * MINUTE), 5 * MINUTE, 15 * MINUTE);
+    assert_eq!(
+        gaps,
+        [
+            Gap {
+                start: 40_000 * MS,
+                end: 400_000 * MS,
+                kind: GapKind::Between,
+            },
+            Gap {
+                start: 410_000 * MS,
+                end: 1_000_000 * MS,
+                kind: GapKind::Between,
+            },
+            // The track stops 23 minutes before the end of the file
+            Gap {
+                start: 1_000_000 * MS,
+                end: 40 * MINUTE,
+                kind: GapKind::Tail,
+            },
+        ]
+    );
+    assert_eq!(gaps[0].duration(), 6 * MINUTE);
+
+    // End credits without subtitles are fine
+    let gaps = find_gaps(&times, Some(25 * MINUTE), 10 * MINUTE, 15 * MINUTE);
+    assert!(gaps.is_empty());
+    assert!(find_gaps(&times, None, 10 * MINUTE, 0).is_empty());
+    assert!(find_gaps(&[], Some(40 * MINUTE), MINUTE, MINUTE).is_empty());
+}
@@ -0,0 +1,44 @@
+//! Collecting problems into the diagnostics report.
+
+use std::path::Path;
+
+use subproc::diagnostics::{Diagnostic, Diagnostics, Stage};
+
+const MS: u64 = 1_000_000;
+
+#[test]
+fn summarizes_by_stage() {
+    let mut diagnostics = Diagnostics::default();
+    assert_eq!(diagnostics.summary(), "No problems");
+    diagnostics.push(Diagnostic::new(Stage::Ocr, "Engine crashed").at_event(3, 2_000 * MS));
+    assert_eq!(diagnostics.summary(), "1 problem (ocr: 1)");
+    diagnostics.push(Diagnostic::new(Stage::Extract, "Bad frame").at_time(500 * MS));
+    diagnostics.push(Diagnostic::new(Stage::Extract, "Another bad frame"));
+    assert_eq!(diagnostics.count(Stage::Extract), 2);
+    assert_eq!(diagnostics.summary(), "3 problems (extract: 2, ocr: 1)");
+}
+
+#[test]
+fn writes_csv() {
+    let mut diagnostics = Diagnostics::default();
+    diagnostics.push(Diagnostic::new(Stage::Extract, "Bad frame").at_time(500 * MS));
+    let mut linked = Diagnostics::default();
+    linked.push(
+        Diagnostic::new(Stage::Ocr, "OCR failed for event 7: \"oops\", twice")
+            .at_event(7, 61_250 * MS),
+    );
+    diagnostics.append(linked, Path::new("part2.mkv"));
+    assert_eq!(
+        diagnostics.entries()[1].source.as_deref(),
+        Some(Path::new("part2.mkv"))
+    );
+
+    let mut report = Vec::new();
+    diagnostics.write_report(&mut report).unwrap();
+    assert_eq!(
+        String::from_utf8(report).unwrap(),
+        "stage,event,time,source,message\n\
+         extract,,\"00:00:00,500\",,Bad frame\n\
+         ocr,7,\"00:01:01,250\",part2.mkv,\"OCR failed for event 7: \"\"oops\"\", twice\"\n"
+    );
+}
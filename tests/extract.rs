@@ -0,0 +1,863 @@
+//! End-to-end extraction from MKV files, covering the event timing the
+//! extractor derives on top of the decoders.
+
+mod common;
+
+use std::io::Cursor;
+
+use common::*;
+use image::{GrayImage, Rgb, RgbImage, Rgba};
+use matroska_demuxer::MatroskaFile;
+use subproc::{
+    attachments::read_attachments,
+    bilingual::{DualLayout, detect_layout, split_languages},
+    cancel::CancellationToken,
+    composite::{overlay, overlay_at},
+    extract::{
+        EventPayload, ExtractError, Screen, SubtitleEvent, SubtitleExtractor, get_subtitle_at,
+    },
+    model::{self, EndSource},
+    ocr::{OcrEngine, OcrError, RegionPolicy, recognize_regions},
+    preprocess::{FlattenOptions, Placement, Region},
+    sink::{EventSink, ImageDirSink, SinkError, TeeSink},
+    transform::{ScaleFilter, Transform},
+    vobs,
+};
+
+const MS: u64 = 1_000_000;
+
+fn show(composition_number: u16) -> Vec<u8> {
+    return PgsDisplaySetBuilder::new()
+        .pcs(
+            composition_number,
+            0x80,
+            0,
+            &[PgsObjectRef {
+                object_id: 1,
+                window_id: 0,
+                x: 0,
+                y: 0,
+                crop: None,
+            }],
+        )
+        .wds(&[(0, 800, 900, 100, 20)])
+        .pds(0, 0, &[(1, 235, 255), (2, 16, 255)])
+        .ods(1, 0, 100, 20, &pgs_rle(&outlined_bar(100, 20, 1, 2)), 1)
+        .finish();
+}
+
+fn clear(composition_number: u16) -> Vec<u8> {
+    return PgsDisplaySetBuilder::new()
+        .pcs(composition_number, 0x00, 0, &[])
+        .wds(&[(0, 800, 900, 100, 20)])
+        .finish();
+}
+
+#[test]
+fn pgs_end_times_from_clears() {
+    let mkv = build_mkv(
+        &[(1, "S_HDMV/PGS", None)],
+        &[
+            (1, 1_000, show(1)),
+            (1, 2_500, clear(2)),
+            // Replaced directly by the next subtitle, without a clear
+            (1, 4_000, show(3)),
+            (1, 6_000, show(4)),
+            (1, 7_000, clear(5)),
+            // Never cleared
+            (1, 9_000, show(6)),
+        ],
+    );
+    let extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(mkv)).unwrap(), None).unwrap();
+    let timing: Vec<(u64, Option<u64>, Option<EndSource>)> = extractor
+        .map(|event| {
+            let event = event.unwrap();
+            return (
+                event.start / MS,
+                event.end.map(|end| end / MS),
+                event.end_source,
+            );
+        })
+        .collect();
+    assert_eq!(
+        timing,
+        [
+            (1_000, Some(2_500), Some(EndSource::Stream)),
+            (4_000, Some(6_000), Some(EndSource::Stream)),
+            (6_000, Some(7_000), Some(EndSource::Stream)),
+            // Nothing says when the last one ends
+            (9_000, Some(14_000), Some(EndSource::Fallback)),
+        ]
+    );
+}
+
+#[test]
+fn pgs_regions() {
+    let objects = [
+        PgsObjectRef {
+            object_id: 1,
+            window_id: 0,
+            x: 0,
+            y: 0,
+            crop: None,
+        },
+        PgsObjectRef {
+            object_id: 2,
+            window_id: 1,
+            x: 0,
+            y: 0,
+            crop: None,
+        },
+    ];
+    let show_two = PgsDisplaySetBuilder::new()
+        .pcs(1, 0x80, 0, &objects)
+        .wds(&[(0, 700, 60, 400, 40), (1, 600, 950, 600, 50)])
+        .pds(0, 0, &[(1, 235, 255), (2, 16, 255)])
+        .ods(1, 0, 400, 40, &pgs_rle(&outlined_bar(400, 40, 1, 2)), 1)
+        .ods(2, 0, 600, 50, &pgs_rle(&outlined_bar(600, 50, 1, 2)), 1)
+        .finish();
+    let mkv = build_mkv(
+        &[(1, "S_HDMV/PGS", None)],
+        &[(1, 1_000, show_two), (1, 2_000, clear(2))],
+    );
+    let mut extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(mkv)).unwrap(), None).unwrap();
+    let event = extractor.next_event().unwrap().unwrap();
+    assert_eq!(
+        event.regions,
+        [
+            Region {
+                x: 100,
+                y: 0,
+                width: 400,
+                height: 40,
+            },
+            Region {
+                x: 0,
+                y: 890,
+                width: 600,
+                height: 50,
+            },
+        ]
+    );
+
+    // Stand-in OCR that just reports what it was given
+    struct Dimensions;
+    impl OcrEngine for Dimensions {
+        fn recognize(&mut self, image: &GrayImage) -> Result<String, OcrError> {
+            return Ok(format!("{}x{}", image.width(), image.height()));
+        }
+    }
+    let EventPayload::Image(ref image) = event.payload else {
+        panic!("expected an image");
+    };
+    let recognize = |policy| {
+        return recognize_regions(
+            &mut Dimensions,
+            image,
+            &event.regions,
+            event.placement,
+            policy,
+            FlattenOptions::default(),
+        )
+        .unwrap();
+    };
+    assert_eq!(recognize(RegionPolicy::Merge), ["400x40\n600x50"]);
+    assert_eq!(recognize(RegionPolicy::Separate), ["400x40", "600x50"]);
+    assert_eq!(
+        recognize(RegionPolicy::Position),
+        ["{\\an8}400x40", "600x50"]
+    );
+
+    let parts = event.split_regions();
+    assert_eq!(parts.len(), 2);
+    for (part, (width, height)) in parts.iter().zip([(400, 40), (600, 50)]) {
+        let EventPayload::Image(ref image) = part.payload else {
+            panic!("expected an image");
+        };
+        assert_eq!(image.dimensions(), (width, height));
+        assert_eq!(part.end, Some(2_000 * MS));
+    }
+}
+
+#[test]
+fn cancellation() {
+    let mkv = build_mkv(
+        &[(1, "S_HDMV/PGS", None)],
+        &[
+            (1, 1_000, show(1)),
+            (1, 3_000, show(2)),
+            (1, 4_000, clear(3)),
+            (1, 5_000, show(4)),
+        ],
+    );
+    let mut extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(mkv)).unwrap(), None).unwrap();
+    let token = CancellationToken::new();
+    extractor.set_cancellation(token.clone());
+    let first = extractor.next_event().unwrap().unwrap();
+    assert_eq!(first.end, Some(3_000 * MS));
+
+    // The first event ended when the second was shown, so the second is
+    // already in progress and comes out with the fallback duration
+    token.cancel();
+    let second = extractor.next_event().unwrap().unwrap();
+    assert_eq!((second.start, second.end), (3_000 * MS, Some(8_000 * MS)));
+    assert!(extractor.next_event().unwrap().is_none());
+}
+
+/// An epoch shown at 1s, with a palette update at 3s that only makes sense
+/// after it, and a second epoch in the next cluster
+fn two_epochs() -> Vec<u8> {
+    let fade = PgsDisplaySetBuilder::new()
+        .pcs(
+            2,
+            0x00,
+            0,
+            &[PgsObjectRef {
+                object_id: 1,
+                window_id: 0,
+                x: 0,
+                y: 0,
+                crop: None,
+            }],
+        )
+        .pds(0, 1, &[(1, 235, 128)])
+        .finish();
+    return build_mkv(
+        &[(1, "S_HDMV/PGS", None)],
+        &[
+            (1, 1_000, show(1)),
+            (1, 3_000, fade),
+            (1, 5_000, clear(3)),
+            (1, 12_000, show(4)),
+            (1, 13_000, clear(5)),
+        ],
+    );
+}
+
+fn timing(extractor: &mut SubtitleExtractor<Cursor<Vec<u8>>>) -> Vec<(u64, Option<u64>)> {
+    return extractor
+        .map(|event| {
+            let event = event.unwrap();
+            return (event.start / MS, event.end.map(|end| end / MS));
+        })
+        .collect();
+}
+
+#[test]
+fn epoch_index() {
+    let mut extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(two_epochs())).unwrap(), None)
+            .unwrap();
+    extractor.index_epochs().unwrap();
+    assert_eq!(extractor.epochs().points(), [1_000 * MS, 12_000 * MS]);
+    let index = extractor.epochs().clone();
+    assert_eq!(
+        timing(&mut extractor),
+        [
+            (1_000, Some(3_000)),
+            (3_000, Some(5_000)),
+            (12_000, Some(13_000))
+        ]
+    );
+
+    // Back into the middle of the first epoch. A plain seek skips the fade,
+    // which depends on the display set before it
+    extractor.seek(3_000 * MS).unwrap();
+    assert_eq!(timing(&mut extractor), [(12_000, Some(13_000))]);
+    assert_eq!(extractor.skipped_events(), 1);
+    assert_eq!(extractor.seek_epoch(4_000 * MS).unwrap(), 1_000 * MS);
+    assert_eq!(
+        timing(&mut extractor),
+        [
+            (1_000, Some(3_000)),
+            (3_000, Some(5_000)),
+            (12_000, Some(13_000))
+        ]
+    );
+
+    // Also indexed along the way by extracting
+    let mut extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(two_epochs())).unwrap(), None)
+            .unwrap();
+    timing(&mut extractor);
+    assert_eq!(extractor.epochs(), &index);
+    assert_eq!(extractor.seek_epoch(500 * MS).unwrap(), 500 * MS);
+    assert_eq!(extractor.seek_epoch(12_500 * MS).unwrap(), 12_000 * MS);
+    assert_eq!(timing(&mut extractor), [(12_000, Some(13_000))]);
+}
+
+#[test]
+fn subtitle_at() {
+    let at = |timestamp| {
+        return get_subtitle_at(Cursor::new(two_epochs()), None, timestamp * MS)
+            .unwrap()
+            .map(|event| (event.start / MS, event.end.map(|end| end / MS)));
+    };
+    assert_eq!(at(500), None);
+    assert_eq!(at(1_000), Some((1_000, Some(3_000))));
+    assert_eq!(at(4_000), Some((3_000, Some(5_000))));
+    assert_eq!(at(5_000), None);
+    assert_eq!(at(12_500), Some((12_000, Some(13_000))));
+    assert_eq!(at(30_000), None);
+
+    // Going back and forth within one file
+    let mut extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(two_epochs())).unwrap(), None)
+            .unwrap();
+    let event = extractor.subtitle_at(12_500 * MS).unwrap().unwrap();
+    assert_eq!(event.start, 12_000 * MS);
+    let faded = extractor.subtitle_at(3_500 * MS).unwrap().unwrap();
+    let shown = extractor.subtitle_at(2_000 * MS).unwrap().unwrap();
+    assert_eq!((faded.start, shown.start), (3_000 * MS, 1_000 * MS));
+    let (EventPayload::Image(faded), EventPayload::Image(shown)) = (faded.payload, shown.payload)
+    else {
+        panic!("expected images");
+    };
+    assert_ne!(faded, shown);
+    assert_eq!(extractor.epochs().points(), [1_000 * MS, 12_000 * MS]);
+}
+
+#[test]
+fn vobsub_events() {
+    let rows = outlined_bar(40, 7, 1, 2);
+    let packet = vobsub_subpicture_timed(
+        100,
+        400,
+        &rows,
+        [1, 2, 3, 0],
+        [15, 15, 15, 0],
+        &[],
+        Some(88),
+    );
+    // Split across two blocks, as muxers do with large subpictures
+    let (first, second) = packet.split_at(packet.len() / 2);
+    let mkv = build_mkv(
+        &[(1, "S_VOBSUB", Some(VOBSUB_IDX.as_bytes()))],
+        &[(1, 1_000, first.to_vec()), (1, 1_000, second.to_vec())],
+    );
+    let open = || {
+        return SubtitleExtractor::new(MatroskaFile::open(Cursor::new(mkv.clone())).unwrap(), None)
+            .unwrap();
+    };
+    let lumas = |event: &SubtitleEvent| {
+        let EventPayload::Image(ref image) = event.payload else {
+            panic!("expected an image");
+        };
+        let mut lumas: Vec<u8> = image.pixels().map(|pixel| pixel.0[0]).collect();
+        lumas.sort();
+        lumas.dedup();
+        return lumas;
+    };
+
+    let events: Vec<SubtitleEvent> = open().map(Result::unwrap).collect();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].start, 1_000 * MS);
+    assert_eq!(events[0].end.map(|end| end / MS), Some(2_001));
+    let placement = events[0].placement.unwrap();
+    assert_eq!((placement.x, placement.y), (100, 400));
+    assert_eq!(
+        (placement.screen_width, placement.screen_height),
+        (720, 480)
+    );
+    assert_eq!(lumas(&events[0]), [32, 128]);
+
+    let mut extractor = open();
+    extractor.set_palette(vobs::parse_palette(&["ffffff"; 16].join(",")).unwrap());
+    let event = extractor.next_event().unwrap().unwrap();
+    assert_eq!(lumas(&event), [255]);
+}
+
+#[test]
+fn vobsub_end_times() {
+    let subpicture = |width: usize, stop: Option<u16>| {
+        let rows = outlined_bar(width, 7, 1, 2);
+        return vobsub_subpicture_timed(100, 400, &rows, [1, 2, 3, 0], [15, 15, 15, 0], &[], stop);
+    };
+    let mkv = build_mkv(
+        &[(1, "S_VOBSUB", Some(VOBSUB_IDX.as_bytes()))],
+        &[
+            // Stops itself after 88 * 1024 ticks
+            (1, 1_000, subpicture(40, Some(88))),
+            // Ends when the next starts
+            (1, 3_000, subpicture(50, None)),
+            // Repeated twice, the second time with a bogus stop time
+            (1, 4_000, subpicture(60, None)),
+            (1, 4_500, subpicture(60, None)),
+            (1, 5_000, subpicture(60, Some(0))),
+            // Nothing for a minute, so it's capped
+            (1, 6_000, subpicture(40, None)),
+            (1, 66_000, subpicture(50, None)),
+        ],
+    );
+    let extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(mkv)).unwrap(), None).unwrap();
+    let timing: Vec<(u64, Option<u64>)> = extractor
+        .map(|event| {
+            let event = event.unwrap();
+            return (event.start / MS, event.end.map(|end| end / MS));
+        })
+        .collect();
+    assert_eq!(
+        timing,
+        [
+            (1_000, Some(2_001)),
+            (3_000, Some(4_000)),
+            (4_000, Some(6_000)),
+            (6_000, Some(16_000)),
+            // The container says nothing about the last one
+            (66_000, Some(71_000)),
+        ]
+    );
+    assert_eq!(vobs::inferred_end(5, 5), None);
+}
+
+#[test]
+fn text_without_durations() {
+    let text = |text: &str| text.as_bytes().to_vec();
+    let mkv = build_mkv(
+        &[(1, "S_TEXT/UTF8", None)],
+        &[
+            (1, 1_000, text("First")),
+            (1, 2_500, text("Second")),
+            (1, 30_000, text("Third")),
+        ],
+    );
+    let extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(mkv)).unwrap(), None).unwrap();
+    let timing: Vec<(u64, Option<u64>, Option<EndSource>)> = extractor
+        .map(|event| {
+            let event = event.unwrap();
+            return (
+                event.start / MS,
+                event.end.map(|end| end / MS),
+                event.end_source,
+            );
+        })
+        .collect();
+    assert_eq!(
+        timing,
+        [
+            (1_000, Some(2_500), Some(EndSource::NextEvent)),
+            // Capped, like VobSub subpictures without a stop time
+            (2_500, Some(12_500), Some(EndSource::NextEvent)),
+            (30_000, Some(35_000), Some(EndSource::Fallback)),
+        ]
+    );
+}
+
+/// The timestamps (ms) of played states, and the alpha in the middle of the
+/// image, or `None` once cleared
+fn played(extractor: SubtitleExtractor<Cursor<Vec<u8>>>) -> Vec<(u64, Option<u8>)> {
+    return extractor
+        .play()
+        .map(|state| {
+            let state = state.unwrap();
+            let alpha = match state.screen {
+                Screen::Image { image, .. } => {
+                    Some(image.get_pixel(image.width() / 2, image.height() / 2).0[3])
+                }
+                Screen::Clear => None,
+                Screen::Text(_) => panic!("expected images"),
+            };
+            return (state.timestamp / MS, alpha);
+        })
+        .collect();
+}
+
+#[test]
+fn playback() {
+    let extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(two_epochs())).unwrap(), None)
+            .unwrap();
+    assert_eq!(
+        played(extractor),
+        [
+            (1_000, Some(255)),
+            // The palette update fading it
+            (3_000, Some(128)),
+            (5_000, None),
+            (12_000, Some(255)),
+            (13_000, None),
+        ]
+    );
+
+    let subpicture = |width: usize, alpha: u8, stop: Option<u16>| {
+        let rows = outlined_bar(width, 7, 1, 2);
+        return vobsub_subpicture_timed(
+            100,
+            400,
+            &rows,
+            [1, 2, 3, 0],
+            [alpha, alpha, alpha, 0],
+            &[],
+            stop,
+        );
+    };
+    let mkv = build_mkv(
+        &[(1, "S_VOBSUB", Some(VOBSUB_IDX.as_bytes()))],
+        &[
+            (1, 1_000, subpicture(40, 15, Some(88))),
+            (1, 3_000, subpicture(50, 8, None)),
+            // Changes nothing
+            (1, 3_500, subpicture(50, 8, None)),
+            (1, 4_000, subpicture(50, 15, None)),
+        ],
+    );
+    let extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(mkv)).unwrap(), None).unwrap();
+    assert_eq!(
+        played(extractor),
+        [
+            (1_000, Some(255)),
+            (2_001, None),
+            (3_000, Some(136)),
+            (4_000, Some(255)),
+        ]
+    );
+}
+
+#[test]
+fn vobsub_attached_idx() {
+    let rows = outlined_bar(40, 7, 1, 2);
+    let packet = vobsub_subpicture(100, 400, &rows, [1, 2, 3, 0], [15, 15, 15, 0]);
+    let extra = mkv_attachments(&[
+        ("font.ttf", "font/ttf", b"not really a font"),
+        ("Movie.IDX", "text/plain", VOBSUB_IDX.as_bytes()),
+    ]);
+    let mkv = build_mkv_with(
+        &[(1, "S_VOBSUB", None)],
+        &[(1, 1_000, packet)],
+        None,
+        &extra,
+    );
+
+    let attachments = read_attachments(&mut Cursor::new(&mkv)).unwrap();
+    assert_eq!(attachments.len(), 2);
+    assert_eq!(attachments[0].name, "font.ttf");
+    assert_eq!(attachments[0].media_type, "font/ttf");
+    assert_eq!(attachments[0].uid, 1);
+    assert!(!attachments[0].is_idx());
+    let idx = attachments.iter().find(|attachment| attachment.is_idx());
+    assert_eq!(idx.unwrap().data, VOBSUB_IDX.as_bytes());
+
+    // The attached idx gives the events the screen size and palette the
+    // track itself lacks
+    let mkv = MatroskaFile::open(Cursor::new(mkv)).unwrap();
+    let mut extractor =
+        SubtitleExtractor::with_idx(mkv, None, idx.map(|idx| idx.data.as_slice()), None).unwrap();
+    let event = extractor.next_event().unwrap().unwrap();
+    let placement = event.placement.unwrap();
+    assert_eq!(
+        (placement.screen_width, placement.screen_height),
+        (720, 480)
+    );
+}
+
+#[test]
+fn vobsub_palette_fallback() {
+    let rows = outlined_bar(40, 7, 1, 2);
+    let packet = vobsub_subpicture(100, 400, &rows, [1, 2, 3, 0], [15, 15, 15, 0]);
+    let white = vobs::parse_palette(&["ffffff"; 16].join(",")).unwrap();
+    let open = |private: Option<&[u8]>, palette| {
+        let mkv = build_mkv(&[(1, "S_VOBSUB", private)], &[(1, 1_000, packet.clone())]);
+        let mkv = MatroskaFile::open(Cursor::new(mkv)).unwrap();
+        return SubtitleExtractor::with_idx(mkv, None, None, palette);
+    };
+
+    for private in [None, Some(&b"size: 720x480\npalette: 000000"[..])] {
+        assert!(open(private, None).is_err());
+        let mut extractor = open(private, Some(white)).unwrap();
+        assert!(extractor.idx_error().is_some());
+        let event = extractor.next_event().unwrap().unwrap();
+        let EventPayload::Image(ref image) = event.payload else {
+            panic!("expected an image");
+        };
+        assert!(image.pixels().all(|pixel| pixel.0[0] == 255));
+        assert!(event.placement.is_none());
+    }
+    assert!(matches!(
+        open(None, None),
+        Err(ExtractError::VobSub(vobs::SubsError::MissingIdx))
+    ));
+
+    // A readable idx keeps its size, with the palette replaced
+    let extractor = open(Some(VOBSUB_IDX.as_bytes()), Some(white)).unwrap();
+    assert!(extractor.idx_error().is_none());
+}
+
+#[test]
+fn event_sinks() {
+    let mkv = build_mkv(
+        &[(1, "S_HDMV/PGS", None)],
+        &[
+            (1, 1_000, show(1)),
+            (1, 2_000, clear(2)),
+            (1, 3_000, show(3)),
+        ],
+    );
+    let extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(mkv)).unwrap(), None).unwrap();
+    let directory = std::env::temp_dir().join(format!("subproc-sink-{}", std::process::id()));
+    let mut images = ImageDirSink::new(&directory);
+    let mut starts = Vec::new();
+    let mut record = |index: usize, event: &SubtitleEvent| -> Result<(), SinkError> {
+        starts.push((index, event.start / MS));
+        return Ok(());
+    };
+    for (index, event) in extractor.enumerate() {
+        let event = event.unwrap();
+        images.event(index, &event).unwrap();
+        record.event(index, &event).unwrap();
+    }
+    images.finish().unwrap();
+
+    assert_eq!(starts, [(0, 1_000), (1, 3_000)]);
+    let saved = image::open(directory.join("00002.png")).unwrap();
+    assert_eq!((saved.width(), saved.height()), (100, 20));
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn tee_sink() {
+    let mkv = build_mkv(
+        &[(1, "S_HDMV/PGS", None)],
+        &[(1, 1_000, show(1)), (1, 2_000, clear(2))],
+    );
+    let extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(mkv)).unwrap(), None).unwrap();
+    // Sinks can borrow, like the binary's composites borrowing its options
+    let seen = std::cell::RefCell::new(Vec::new());
+    let mut tee = TeeSink::new();
+    for name in ["preview", "output"] {
+        let seen = &seen;
+        tee.push(Box::new(
+            move |index: usize, event: &SubtitleEvent| -> Result<(), SinkError> {
+                seen.borrow_mut().push((name, index, event.start / MS));
+                return Ok(());
+            },
+        ));
+    }
+    for (index, event) in extractor.enumerate() {
+        tee.event(index, &event.unwrap()).unwrap();
+    }
+    tee.finish().unwrap();
+    assert_eq!(
+        *seen.borrow(),
+        [("preview", 0, 1_000), ("output", 0, 1_000)]
+    );
+}
+
+#[test]
+fn indexed_events() {
+    let mkv = build_mkv(
+        &[(1, "S_HDMV/PGS", None)],
+        &[(1, 1_000, show(1)), (1, 2_000, clear(2))],
+    );
+    let mut extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(mkv)).unwrap(), None).unwrap();
+    extractor.set_indexed(true);
+    let event = extractor.next_event().unwrap().unwrap();
+    let indexed = event.indexed.unwrap();
+    assert_eq!((indexed.width, indexed.height), (100, 20));
+    assert_eq!((indexed.index(0, 0), indexed.index(50, 10)), (2, 1));
+    assert_eq!(indexed.color(1), Rgba([255, 255, 255, 255]));
+    assert_eq!(indexed.color(2), Rgba([0, 0, 0, 255]));
+    assert_eq!(indexed.source_palette, "01=eb8080ff, 02=108080ff");
+
+    let mut png = Vec::new();
+    indexed.write_png(&mut png).unwrap();
+    let read = subproc::indexed::read_png(Cursor::new(png))
+        .unwrap()
+        .unwrap();
+    assert_eq!(read.indices, indexed.indices);
+    assert_eq!(read.source_palette, indexed.source_palette);
+    assert_eq!(read.to_rgba(), indexed.to_rgba());
+}
+
+#[test]
+fn scale_transform() {
+    let mkv = build_mkv(
+        &[(1, "S_HDMV/PGS", None)],
+        &[(1, 1_000, show(1)), (1, 2_000, clear(2))],
+    );
+    let mut extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(mkv)).unwrap(), None).unwrap();
+    extractor.set_indexed(true);
+    let event = extractor.next_event().unwrap().unwrap();
+    let transform = Transform {
+        filter: ScaleFilter::Nearest,
+        ..Transform::new(960, 540)
+    };
+    let scaled = transform.apply(event);
+    let EventPayload::Image(ref image) = scaled.payload else {
+        panic!("expected an image");
+    };
+    assert_eq!(image.dimensions(), (50, 10));
+    let placement = scaled.placement.unwrap();
+    assert_eq!(
+        (placement.x, placement.y, placement.screen_width),
+        (400, 450, 960)
+    );
+    let indexed = scaled.indexed.unwrap();
+    assert_eq!((indexed.width, indexed.height), (50, 10));
+    assert_eq!((indexed.index(0, 0), indexed.index(25, 5)), (2, 1));
+}
+
+#[test]
+fn composite_overlay() {
+    let mkv = build_mkv(
+        &[(1, "S_HDMV/PGS", None)],
+        &[(1, 1_000, show(1)), (1, 2_000, clear(2))],
+    );
+    let mut extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(mkv)).unwrap(), None).unwrap();
+    let event = extractor.next_event().unwrap().unwrap();
+    // Half the canvas size, so the subtitle is scaled to fit
+    let mut frame = RgbImage::from_pixel(960, 540, Rgb([0, 0, 255]));
+    overlay(&mut frame, &event);
+    assert_eq!(frame.get_pixel(425, 455).0, [235, 235, 235]);
+    assert_eq!(frame.get_pixel(399, 455).0, [0, 0, 255]);
+    assert_eq!(frame.get_pixel(425, 445).0, [0, 0, 255]);
+}
+
+#[test]
+fn burn_in_overlay() {
+    let mkv = build_mkv(
+        &[(1, "S_HDMV/PGS", None)],
+        &[(1, 1_000, show(1)), (1, 2_000, clear(2))],
+    );
+    let mut extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(mkv)).unwrap(), None).unwrap();
+    let overlay = overlay_at(&mut extractor, 1_500 * MS, 960, 540)
+        .unwrap()
+        .unwrap();
+    assert_eq!(overlay.dimensions(), (960, 540));
+    // In the palette's color rather than gray
+    assert_eq!(overlay.get_pixel(425, 455).0, [255, 255, 255, 255]);
+    assert_eq!(overlay.get_pixel(399, 455).0, [0, 0, 0, 0]);
+    assert_eq!(overlay.get_pixel(425, 445).0, [0, 0, 0, 0]);
+    assert!(
+        overlay_at(&mut extractor, 2_500 * MS, 960, 540)
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn model_round_trip() {
+    let mkv = build_mkv(
+        &[(1, "S_HDMV/PGS", None)],
+        &[(1, 1_000, show(1)), (1, 2_500, clear(2))],
+    );
+    let mut extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(mkv)).unwrap(), None).unwrap();
+    let mut track = model::SubtitleTrack::from_entry(extractor.track());
+    let event = extractor.next_event().unwrap().unwrap();
+    let mut converted = model::SubtitleEvent::from_extracted(&event, "0001.png");
+    converted.set_ocr_text("Hello");
+    track.events.push(converted);
+    let document = model::SubtitleDocument {
+        source: Some(String::from("movie.mkv")),
+        tracks: vec![track],
+    };
+
+    let json = serde_json::to_value(&document).unwrap();
+    assert_eq!(json["tracks"][0]["codec"], "S_HDMV/PGS");
+    let event = &json["tracks"][0]["events"][0];
+    assert_eq!(event["start"], 1_000 * MS);
+    assert_eq!(event["end"], 2_500 * MS);
+    assert_eq!(event["end_source"], "stream");
+    assert_eq!(event["text"]["origin"], "ocr");
+    assert_eq!(event["image"]["path"], "0001.png");
+    assert_eq!(event["image"]["placement"]["x"], 800);
+    assert_eq!(
+        serde_json::from_value::<model::SubtitleDocument>(json).unwrap(),
+        document
+    );
+}
+
+#[test]
+fn dual_language_tracks() {
+    // A line of each language, the upper one 20 rows tall and the lower 16,
+    // with a 10 row gap, placed at y = 900
+    let stacked = |start: u64| {
+        let mut image = GrayImage::new(200, 46);
+        for y in (0..20).chain(30..46) {
+            for x in 0..200 {
+                image.put_pixel(x, y, image::Luma([255]));
+            }
+        }
+        return SubtitleEvent {
+            start: start * MS,
+            end: Some((start + 500) * MS),
+            end_source: None,
+            forced: false,
+            payload: EventPayload::Image(image::DynamicImage::ImageLuma8(image).to_luma_alpha8()),
+            regions: vec![
+                Region {
+                    x: 0,
+                    y: 30,
+                    width: 120,
+                    height: 16,
+                },
+                Region {
+                    x: 20,
+                    y: 0,
+                    width: 180,
+                    height: 20,
+                },
+            ],
+            placement: Some(Placement {
+                x: 800,
+                y: 900,
+                screen_width: 1920,
+                screen_height: 1080,
+            }),
+            indexed: None,
+        };
+    };
+    let mut events: Vec<SubtitleEvent> = (0..9).map(|i| stacked(i * 1_000)).collect();
+    // Only the lower language, in its usual place
+    let mut single = stacked(9_000);
+    single.regions.truncate(1);
+    events.push(single);
+
+    let layout = detect_layout(&events).unwrap();
+    assert_eq!(
+        layout,
+        DualLayout {
+            split: 925,
+            stacked: 9,
+            images: 10,
+        }
+    );
+    let (upper, lower) = split_languages(events.clone(), layout.split);
+    assert_eq!((upper.len(), lower.len()), (9, 10));
+    let EventPayload::Image(ref image) = upper[0].payload else {
+        panic!("Expected an image");
+    };
+    assert_eq!((image.width(), image.height()), (180, 20));
+    assert_eq!(
+        upper[0]
+            .placement
+            .map(|placement| (placement.x, placement.y)),
+        Some((820, 900))
+    );
+    assert_eq!(
+        lower[0]
+            .placement
+            .map(|placement| (placement.x, placement.y)),
+        Some((800, 930))
+    );
+    assert_eq!(lower[0].regions[0].y, 0);
+    assert_eq!(lower[9].start, 9_000 * MS);
+
+    // Too few stacked subtitles to go by
+    for event in events.iter_mut().skip(3) {
+        event.regions.truncate(1);
+    }
+    assert_eq!(detect_layout(&events), None);
+}
@@ -0,0 +1,111 @@
+//! Event filter expressions.
+
+use image::GrayAlphaImage;
+use subproc::{
+    extract::{EventPayload, SubtitleEvent},
+    filter::{Filter, FilterError, filter_events},
+    preprocess::Placement,
+};
+
+const MS: u64 = 1_000_000;
+
+fn image_event(start_ms: u64, end_ms: Option<u64>, width: u32, height: u32) -> SubtitleEvent {
+    return SubtitleEvent {
+        start: start_ms * MS,
+        end: end_ms.map(|end| end * MS),
+        end_source: None,
+        forced: false,
+        payload: EventPayload::Image(GrayAlphaImage::new(width, height)),
+        regions: Vec::new(),
+        placement: Some(Placement {
+            x: 0,
+            y: 0,
+            screen_width: 1920,
+            screen_height: 1080,
+        }),
+        indexed: None,
+    };
+}
+
+fn text_event(start_ms: u64, end_ms: u64, forced: bool) -> SubtitleEvent {
+    return SubtitleEvent {
+        start: start_ms * MS,
+        end: Some(end_ms * MS),
+        end_source: None,
+        forced,
+        payload: EventPayload::Text(String::from("Hello")),
+        regions: Vec::new(),
+        placement: None,
+        indexed: None,
+    };
+}
+
+#[test]
+fn filter_expressions() {
+    let dialogue = image_event(1_000, Some(3_000), 800, 80);
+    let flash = image_event(5_000, Some(5_040), 800, 80);
+    let logo = image_event(10_000, Some(20_000), 1800, 1000);
+    let open_ended = image_event(30_000, None, 800, 80);
+    let sign = text_event(12_000, 14_000, true);
+    let events = [&dialogue, &flash, &logo, &open_ended, &sign];
+
+    let matching = |expression: &str| {
+        let filter = Filter::parse(expression).unwrap();
+        return events
+            .iter()
+            .enumerate()
+            .filter(|(_, event)| filter.matches(event))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+    };
+    assert_eq!(matching("duration >= 100ms"), [0, 2, 4]);
+    assert_eq!(matching("area < 80%"), [0, 1, 3]);
+    assert_eq!(matching("duration >= 100ms and area < 80%"), [0]);
+    // Unlike `duration >= 100ms`, this keeps events without a known duration
+    assert_eq!(matching("!(duration < 0.1) && width <= 50%"), [0, 3]);
+    assert_eq!(matching("forced or time in 00:04..00:06"), [1, 4]);
+    assert_eq!(matching("text"), [4]);
+    assert_eq!(matching("image and not (end > 0:10)"), [0, 1, 3]);
+    assert_eq!(matching("height == 80 || regions != 1"), [0, 1, 3, 4]);
+    assert!(matching("start >= 1.5m").is_empty());
+
+    assert_eq!(
+        Filter::parse("colour > 3"),
+        Err(FilterError::UnknownField(String::from("colour")))
+    );
+    assert_eq!(
+        Filter::parse("duration < 3 apples"),
+        Err(FilterError::Unexpected(String::from("apples")))
+    );
+    assert_eq!(
+        Filter::parse("duration < 80%"),
+        Err(FilterError::InvalidValue {
+            field: String::from("duration"),
+            value: String::from("80%"),
+        })
+    );
+    // Past what nanoseconds can count, rather than clamped
+    assert_eq!(
+        Filter::parse("start < 1e12s"),
+        Err(FilterError::InvalidValue {
+            field: String::from("start"),
+            value: String::from("1e12s"),
+        })
+    );
+    assert_eq!(Filter::parse("(forced"), Err(FilterError::UnexpectedEnd));
+}
+
+#[test]
+fn filter_event_stream() {
+    let events: Vec<Result<SubtitleEvent, String>> = vec![
+        Ok(image_event(1_000, Some(3_000), 800, 80)),
+        Err(String::from("broken frame")),
+        Ok(image_event(5_000, Some(5_040), 800, 80)),
+    ];
+    let kept: Vec<Result<u64, String>> = filter_events(events, |event| {
+        return event.end.is_some_and(|end| end - event.start > 100 * MS);
+    })
+    .map(|event| event.map(|event| event.start / MS))
+    .collect();
+    assert_eq!(kept, [Ok(1_000), Err(String::from("broken frame"))]);
+}
@@ -0,0 +1,675 @@
+//! Golden-file regression tests. Each test decodes a small synthetic stream and
+//! compares the resulting event timeline (timestamps, image bounds and pixel
+//! hashes) against `tests/golden/*.txt`.
+
+mod common;
+
+use common::*;
+use image::{GrayAlphaImage, LumaA};
+use matroska_demuxer::Frame;
+use subproc::{
+    bdsup::PgsParser,
+    contact_sheet::{ContactSheet, SheetLayout},
+    textst::TextstParser,
+    vobs,
+};
+
+const SECOND: u64 = 1_000_000_000;
+
+fn pgs_timeline(frames: &[Frame]) -> String {
+    return pgs_timeline_with(&mut PgsParser::new(), frames);
+}
+
+fn pgs_timeline_with<'a>(parser: &mut PgsParser<'a>, frames: &'a [Frame]) -> String {
+    let mut timeline = String::new();
+    for frame in frames {
+        let line = match parser.process_mkv_frame(frame) {
+            Ok(Some(image)) => describe_gray_alpha(&image),
+            Ok(None) => String::from("none"),
+            Err(err) => format!("error: {err}"),
+        };
+        timeline.push_str(&format!("{} {}\n", frame.timestamp, line));
+    }
+    return timeline;
+}
+
+fn dialogue_palette(builder: PgsDisplaySetBuilder) -> PgsDisplaySetBuilder {
+    return builder.pds(0, 0, &[(1, 235, 255), (2, 16, 255), (3, 128, 128)]);
+}
+
+#[test]
+fn pgs_basic() {
+    let frames = vec![
+        mkv_frame(
+            SECOND,
+            dialogue_palette(
+                PgsDisplaySetBuilder::new()
+                    .pcs(
+                        1,
+                        0x80,
+                        0,
+                        &[PgsObjectRef {
+                            object_id: 1,
+                            window_id: 0,
+                            x: 10,
+                            y: 5,
+                            crop: None,
+                        }],
+                    )
+                    .wds(&[(0, 800, 900, 320, 40)]),
+            )
+            .ods(1, 0, 300, 30, &pgs_rle(&outlined_bar(300, 30, 1, 2)), 1)
+            .finish(),
+        ),
+        mkv_frame(
+            3 * SECOND,
+            PgsDisplaySetBuilder::new()
+                .pcs(2, 0x00, 0, &[])
+                .wds(&[(0, 800, 900, 320, 40)])
+                .finish(),
+        ),
+    ];
+    assert_golden("pgs_basic", &pgs_timeline(&frames));
+}
+
+#[test]
+fn pgs_cropped_object() {
+    let frames = vec![mkv_frame(
+        SECOND,
+        dialogue_palette(
+            PgsDisplaySetBuilder::new()
+                .pcs(
+                    1,
+                    0x80,
+                    0,
+                    &[PgsObjectRef {
+                        object_id: 1,
+                        window_id: 0,
+                        x: 0,
+                        y: 0,
+                        crop: Some((50, 5, 100, 20)),
+                    }],
+                )
+                .wds(&[(0, 600, 800, 200, 40)]),
+        )
+        .ods(1, 0, 200, 30, &pgs_rle(&outlined_bar(200, 30, 1, 3)), 1)
+        .finish(),
+    )];
+    assert_golden("pgs_cropped_object", &pgs_timeline(&frames));
+}
+
+#[test]
+fn pgs_two_windows() {
+    let frames = vec![mkv_frame(
+        SECOND,
+        dialogue_palette(
+            PgsDisplaySetBuilder::new()
+                .pcs(
+                    1,
+                    0x80,
+                    0,
+                    &[
+                        PgsObjectRef {
+                            object_id: 1,
+                            window_id: 0,
+                            x: 0,
+                            y: 0,
+                            crop: None,
+                        },
+                        PgsObjectRef {
+                            object_id: 2,
+                            window_id: 1,
+                            x: 0,
+                            y: 0,
+                            crop: None,
+                        },
+                    ],
+                )
+                .wds(&[(0, 700, 60, 400, 40), (1, 600, 950, 600, 50)]),
+        )
+        .ods(1, 0, 400, 40, &pgs_rle(&outlined_bar(400, 40, 1, 2)), 1)
+        .ods(2, 0, 600, 50, &pgs_rle(&outlined_bar(600, 50, 3, 2)), 1)
+        .finish(),
+    )];
+    assert_golden("pgs_two_windows", &pgs_timeline(&frames));
+}
+
+#[test]
+fn pgs_fragmented_object() {
+    let frames = vec![mkv_frame(
+        SECOND,
+        dialogue_palette(
+            PgsDisplaySetBuilder::new()
+                .pcs(
+                    1,
+                    0x80,
+                    0,
+                    &[PgsObjectRef {
+                        object_id: 1,
+                        window_id: 0,
+                        x: 0,
+                        y: 0,
+                        crop: None,
+                    }],
+                )
+                .wds(&[(0, 400, 900, 1000, 60)]),
+        )
+        .ods(1, 0, 1000, 60, &pgs_rle(&outlined_bar(1000, 60, 1, 2)), 3)
+        .finish(),
+    )];
+    assert_golden("pgs_fragmented_object", &pgs_timeline(&frames));
+}
+
+#[test]
+fn pgs_palette_update() {
+    let objects = [PgsObjectRef {
+        object_id: 1,
+        window_id: 0,
+        x: 0,
+        y: 0,
+        crop: None,
+    }];
+    let frames = vec![
+        mkv_frame(
+            SECOND,
+            dialogue_palette(
+                PgsDisplaySetBuilder::new()
+                    .pcs(1, 0x80, 0, &objects)
+                    .wds(&[(0, 800, 900, 100, 20)]),
+            )
+            .ods(1, 0, 100, 20, &pgs_rle(&outlined_bar(100, 20, 1, 2)), 1)
+            .finish(),
+        ),
+        // Palette-only update, as used for fades
+        mkv_frame(
+            SECOND + SECOND / 2,
+            PgsDisplaySetBuilder::new()
+                .pcs(2, 0x00, 0, &objects)
+                .pds(0, 1, &[(1, 235, 64), (2, 16, 64)])
+                .finish(),
+        ),
+    ];
+    assert_golden("pgs_palette_update", &pgs_timeline(&frames));
+}
+
+#[test]
+fn pgs_recovery() {
+    let objects = [PgsObjectRef {
+        object_id: 1,
+        window_id: 0,
+        x: 0,
+        y: 0,
+        crop: None,
+    }];
+    let frames = vec![
+        // Joined mid-epoch: the object and palette were defined before the seek point
+        mkv_frame(
+            SECOND,
+            PgsDisplaySetBuilder::new()
+                .pcs(7, 0x00, 0, &objects)
+                .pds(0, 1, &[(1, 235, 64), (2, 16, 64)])
+                .finish(),
+        ),
+        mkv_frame(
+            2 * SECOND,
+            PgsDisplaySetBuilder::new()
+                .pcs(8, 0x00, 0, &[])
+                .wds(&[(0, 800, 900, 100, 20)])
+                .finish(),
+        ),
+        // Acquisition points repeat everything needed to display them
+        mkv_frame(
+            3 * SECOND,
+            dialogue_palette(
+                PgsDisplaySetBuilder::new()
+                    .pcs(9, 0x40, 0, &objects)
+                    .wds(&[(0, 800, 900, 100, 20)]),
+            )
+            .ods(1, 0, 100, 20, &pgs_rle(&outlined_bar(100, 20, 1, 2)), 1)
+            .finish(),
+        ),
+        mkv_frame(
+            4 * SECOND,
+            PgsDisplaySetBuilder::new()
+                .pcs(10, 0x00, 0, &[])
+                .wds(&[(0, 800, 900, 100, 20)])
+                .finish(),
+        ),
+    ];
+    assert!(pgs_timeline(&frames).contains("error"));
+
+    let mut parser = PgsParser::with_recovery();
+    assert_golden("pgs_recovery", &pgs_timeline_with(&mut parser, &frames));
+    assert_eq!(parser.skipped_compositions(), 1);
+}
+
+#[test]
+fn pgs_object_versions() {
+    let objects = [PgsObjectRef {
+        object_id: 1,
+        window_id: 0,
+        x: 0,
+        y: 0,
+        crop: None,
+    }];
+    let window = [(0, 800, 900, 100, 20)];
+    let define = |number: u16, state: u8, version: u8, width: usize| {
+        return dialogue_palette(
+            PgsDisplaySetBuilder::new()
+                .pcs(number, state, 0, &objects)
+                .wds(&window),
+        )
+        .ods(
+            1,
+            version,
+            width as u16,
+            20,
+            &pgs_rle(&outlined_bar(width, 20, 1, 2)),
+            1,
+        )
+        .finish();
+    };
+    let frames = vec![
+        mkv_frame(SECOND, define(1, 0x80, 0, 100)),
+        // Redefined within the epoch
+        mkv_frame(2 * SECOND, define(2, 0x00, 1, 60)),
+        // Acquisition points repeat the current version
+        mkv_frame(3 * SECOND, define(3, 0x40, 1, 60)),
+        // A stale version must not replace the current one
+        mkv_frame(4 * SECOND, define(4, 0x00, 0, 100)),
+    ];
+    let mut parser = PgsParser::new();
+    assert_golden(
+        "pgs_object_versions",
+        &pgs_timeline_with(&mut parser, &frames),
+    );
+    assert_eq!(parser.stale_updates(), 1);
+}
+
+#[test]
+fn vobsub_basic() {
+    let idx = vobs::parse_idx(VOBSUB_IDX.as_bytes()).unwrap();
+    let rows = outlined_bar(40, 7, 1, 2);
+    let packet = vobsub_subpicture(100, 400, &rows, [1, 2, 3, 0], [15, 15, 15, 0]);
+    let line = match vobs::parse_frame(&idx, &packet) {
+        Ok(image) => describe_rgba(&image),
+        Err(err) => format!("error: {err}"),
+    };
+    assert_golden("vobsub_basic", &format!("0 {line}\n"));
+}
+
+#[test]
+fn vobsub_stop_sequence() {
+    let idx = vobs::parse_idx(VOBSUB_IDX.as_bytes()).unwrap();
+    let rows = outlined_bar(40, 7, 1, 2);
+    // 264 * 1024 / 90000 ~= 3.004 seconds
+    let packet = vobsub_subpicture_timed(
+        100,
+        400,
+        &rows,
+        [1, 2, 3, 0],
+        [15, 15, 15, 0],
+        &[],
+        Some(264),
+    );
+    let frame = vobs::decode_frame(&idx, &packet).unwrap();
+    assert_eq!(frame.start, 0);
+    assert_eq!(frame.end, Some(3_003_733_333));
+    assert!(!frame.forced);
+    // The stop sequence doesn't change what's displayed
+    assert_golden(
+        "vobsub_basic",
+        &format!("0 {}\n", describe_rgba(&frame.image)),
+    );
+}
+
+#[test]
+fn vobsub_color_change() {
+    let idx = vobs::parse_idx(VOBSUB_IDX.as_bytes()).unwrap();
+    let rows = outlined_bar(40, 7, 1, 2);
+    // CHG_COLCON hiding everything from screen column 120 on, on every line
+    let mut command = vec![0x07, 0, 0];
+    command.extend_from_slice(&(400u32 << 16 | 1 << 12 | 406).to_be_bytes());
+    command.extend_from_slice(&[0x00, 120, 0x12, 0x30, 0x00, 0x00]);
+    command.extend_from_slice(&0x0FFFFFFFu32.to_be_bytes());
+    let size = (command.len() - 1) as u16;
+    command[1..3].copy_from_slice(&size.to_be_bytes());
+
+    let packet = vobsub_subpicture_timed(
+        100,
+        400,
+        &rows,
+        [1, 2, 3, 0],
+        [15, 15, 15, 0],
+        &command,
+        None,
+    );
+    let frame = vobs::decode_frame(&idx, &packet).unwrap();
+    assert_eq!(frame.image.get_pixel(10, 3).0[3], 255);
+    assert_eq!(frame.image.get_pixel(19, 3).0[3], 255);
+    assert_eq!(frame.image.get_pixel(20, 3).0[3], 0);
+    assert_eq!(frame.image.get_pixel(39, 3).0[3], 0);
+    assert_eq!(frame.sequences[0].color_changes.as_ref().unwrap().len(), 1);
+}
+
+#[test]
+fn vobsub_malformed_control() {
+    let idx = vobs::parse_idx(VOBSUB_IDX.as_bytes()).unwrap();
+    let rows = outlined_bar(40, 7, 1, 2);
+
+    // An unknown command ends its sequence early, with a warning
+    let packet = vobsub_subpicture_timed(
+        100,
+        400,
+        &rows,
+        [1, 2, 3, 0],
+        [15, 15, 15, 0],
+        &[0x09],
+        None,
+    );
+    let frame = vobs::decode_frame(&idx, &packet).unwrap();
+    assert_eq!(frame.warnings.len(), 1);
+    assert_golden(
+        "vobsub_basic",
+        &format!("0 {}\n", describe_rgba(&frame.image)),
+    );
+
+    // A stop sequence pointing back at the first one
+    let mut packet = vobsub_subpicture_timed(
+        100,
+        400,
+        &rows,
+        [1, 2, 3, 0],
+        [15, 15, 15, 0],
+        &[],
+        Some(10),
+    );
+    let first = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+    let second = u16::from_be_bytes([packet[first + 2], packet[first + 3]]) as usize;
+    packet[second + 2..second + 4].copy_from_slice(&(first as u16).to_be_bytes());
+    assert!(matches!(
+        vobs::decode_frame(&idx, &packet),
+        Err(vobs::SubsError::ControlLoop)
+    ));
+
+    // Truncated in the middle of a command
+    let packet = vobsub_subpicture(100, 400, &rows, [1, 2, 3, 0], [15, 15, 15, 0]);
+    let truncated = &packet[..packet.len() - 4];
+    assert!(vobs::decode_frame(&idx, truncated).is_err());
+}
+
+#[test]
+fn vobsub_program_stream_reassembly() {
+    let idx = vobs::parse_idx(VOBSUB_IDX.as_bytes()).unwrap();
+    let rows = outlined_bar(40, 7, 1, 2);
+    let packet = vobsub_subpicture(100, 400, &rows, [1, 2, 3, 0], [15, 15, 15, 0]);
+    let (first, rest) = packet.split_at(10);
+    let (second, third) = rest.split_at(rest.len() / 2);
+    let sub_file = vobsub_program_stream(&[
+        (0, Some(90_000), first),
+        // Another stream interleaved with the pieces
+        (1, Some(95_000), &packet),
+        (0, None, second),
+        (0, Some(99_000), third),
+    ]);
+
+    let subpictures: Vec<vobs::Subpicture> = vobs::SubFileReader::new(&sub_file)
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(subpictures.len(), 2);
+    assert_eq!(subpictures[0].stream, 1);
+    assert_eq!(subpictures[1].stream, 0);
+    assert_eq!(subpictures[1].timestamp, SECOND);
+    assert_eq!(subpictures[1].data, packet);
+
+    let image = vobs::parse_frame(&idx, &subpictures[1].data).unwrap();
+    assert_golden("vobsub_basic", &format!("0 {}\n", describe_rgba(&image)));
+}
+
+#[test]
+fn vobsub_idx_metadata() {
+    let idx = format!(
+        "{VOBSUB_IDX}\
+org: 10, -4\n\
+scale: 100%, 90%\n\
+alpha: 80%\n\
+smooth: ON\n\
+fadein/out: 50, 100\n\
+time offset: 500\n\
+forced subs: OFF\n\
+custom colors: OFF, tridx: 0000, colors: 000000, 000000, 000000, 000000\n\
+langidx: 1\n\
+\n\
+# English\n\
+id: en, index: 0\n\
+timestamp: 00:00:01:000, filepos: 000000000\n\
+delay: 00:00:02:000\n\
+timestamp: 00:01:00:250, filepos: 00000a800\n\
+id: fr, index: 1\n\
+timestamp: 00:00:03:000, filepos: 000001000\n"
+    );
+    let idx = vobs::parse_idx(idx.as_bytes()).unwrap();
+    assert_eq!(idx.size, Some((720, 480)));
+    assert_eq!(idx.origin, (10, -4));
+    assert_eq!(idx.scale, (100, 90));
+    assert_eq!(idx.alpha, 80);
+    assert!(idx.smooth);
+    assert_eq!((idx.fade_in, idx.fade_out), (50, 100));
+    assert!(!idx.forced_only);
+    assert_eq!(idx.custom_colors, None);
+    assert_eq!(idx.language_index, Some(1));
+
+    let ms = 1_000_000;
+    let timestamps = |index: usize| -> Vec<(i64, u64)> {
+        return idx.languages[index]
+            .timestamps
+            .iter()
+            .map(|timestamp| (timestamp.timestamp, timestamp.file_position))
+            .collect();
+    };
+    assert_eq!(idx.languages.len(), 2);
+    assert_eq!(
+        (idx.languages[0].id.as_str(), idx.languages[0].index),
+        ("en", 0)
+    );
+    assert_eq!(timestamps(0), vec![(1_500 * ms, 0), (62_750 * ms, 0xa800)]);
+    // The delay doesn't carry over to the next language
+    assert_eq!(
+        (idx.languages[1].id.as_str(), idx.languages[1].index),
+        ("fr", 1)
+    );
+    assert_eq!(timestamps(1), vec![(3_500 * ms, 0x1000)]);
+
+    let missing_palette = vobs::parse_idx(b"size: 720x480\n");
+    assert!(matches!(missing_palette, Err(vobs::SubsError::InvalidIdx)));
+}
+
+#[test]
+fn vobsub_fields() {
+    let idx = vobs::parse_idx(VOBSUB_IDX.as_bytes()).unwrap();
+    // Explicit runs instead of fill-to-end codes, so lines can end halfway
+    // through a byte, followed by a padding nibble. Odd height, so the even
+    // field has the extra line.
+    let rows = [[1, 2, 3, 3], [3, 3, 3, 1], [2, 2, 2, 2]];
+    let even = [0x56, 0xB0, 0x12];
+    let odd = [0xF5];
+    let decode = |even: &[u8], odd: &[u8]| {
+        let packet = vobsub_subpicture_fields(
+            (100, 400),
+            (4, rows.len() as u16),
+            (even, odd),
+            [1, 2, 3, 0],
+            [15, 15, 15, 15],
+            &[],
+            None,
+        );
+        return vobs::parse_frame_indexed(&idx, &packet);
+    };
+    // Pixel values select these idx colors
+    let colors = [0, 3, 2, 1];
+
+    let image = decode(&even, &odd).unwrap();
+    assert_eq!(image.height, 3);
+    for (y, row) in rows.iter().enumerate() {
+        for (x, value) in row.iter().enumerate() {
+            assert_eq!(
+                image.index(x as u32, y as u32) >> 4,
+                colors[*value],
+                "at {x},{y}"
+            );
+        }
+    }
+
+    // Both fields from the same data, as some encoders do for line doubling
+    let image = decode(&even, &even[..2]).unwrap();
+    let second_row: Vec<u8> = (0..4).map(|x| image.index(x, 1) >> 4).collect();
+    assert_eq!(second_row, [3, 2, 1, 1]);
+}
+
+#[test]
+fn vobsub_custom_colors() {
+    let idx = format!(
+        "{VOBSUB_IDX}custom colors: ON, tridx: 1001, colors: 000000, ff0000, 00ff00, 0000ff\n"
+    );
+    let idx = vobs::parse_idx(idx.as_bytes()).unwrap();
+    let custom = idx.custom_colors.unwrap();
+    assert_eq!(custom.transparent, [true, false, false, true]);
+    assert_eq!(custom.colors[2], image::Rgb([0, 255, 0]));
+
+    // Pixel values pick the custom colors directly, whatever the subpicture
+    // selects from the palette
+    let mut rows = outlined_bar(8, 4, 1, 2);
+    rows[1][1] = 0;
+    rows[2][1] = 3;
+    let packet = vobsub_subpicture(100, 400, &rows, [4, 5, 6, 7], [15, 15, 15, 15]);
+    let image = vobs::parse_frame(&idx, &packet).unwrap();
+    assert_eq!(image.get_pixel(0, 0).0, [0, 255, 0, 255]);
+    assert_eq!(image.get_pixel(2, 2).0, [255, 0, 0, 255]);
+    assert_eq!(image.get_pixel(1, 1).0[3], 0);
+    assert_eq!(image.get_pixel(1, 2).0[3], 0);
+    let indexed = vobs::parse_frame_indexed(&idx, &packet).unwrap();
+    assert_eq!(indexed.index(2, 2), 1 << 4 | 15);
+    assert!(
+        indexed
+            .source_palette
+            .starts_with("000000, ff0000, 00ff00, 0000ff, 000000")
+    );
+
+    let broken = format!("{VOBSUB_IDX}custom colors: ON, tridx: 10, colors: 000000\n");
+    assert!(matches!(
+        vobs::parse_idx(broken.as_bytes()),
+        Err(vobs::SubsError::InvalidIdx)
+    ));
+}
+
+#[test]
+fn vobsub_idx_sub_reader() {
+    let packet = |width: usize, stop: Option<u16>| {
+        let rows = outlined_bar(width, 7, 1, 2);
+        return vobsub_subpicture_timed(100, 400, &rows, [1, 2, 3, 0], [15, 15, 15, 0], &[], stop);
+    };
+    let (first, second, third) = (packet(40, Some(88)), packet(30, None), packet(50, None));
+    let pieces: [(u8, Option<u64>, &[u8]); 3] = [
+        (0, Some(90_000), &first),
+        (1, Some(180_000), &second),
+        (0, Some(270_000), &third),
+    ];
+    let sub = vobsub_program_stream(&pieces);
+    // Where each piece's pack starts, leaving off the end code
+    let position = |piece: usize| vobsub_program_stream(&pieces[..piece]).len() - 4;
+    let idx = format!(
+        "{VOBSUB_IDX}\
+langidx: 1\n\
+id: en, index: 0\n\
+timestamp: 00:00:03:000, filepos: {:09x}\n\
+timestamp: 00:00:01:000, filepos: {:09x}\n\
+id: de, index: 1\n\
+delay: -00:00:00:500\n\
+timestamp: 00:00:02:000, filepos: {:09x}\n\
+timestamp: 00:00:04:000, filepos: {:09x}\n",
+        position(2),
+        position(0),
+        position(1),
+        sub.len() + 16,
+    );
+    let reader = vobs::IdxSubReader::new(idx.as_bytes(), &sub).unwrap();
+    assert_eq!(reader.default_language(), Some(1));
+
+    // In timestamp order, whatever order the idx lists them in
+    let english: Vec<(i64, vobs::ControlData, image::RgbaImage)> =
+        reader.language_events(0).map(Result::unwrap).collect();
+    assert_eq!(english.len(), 2);
+    assert_eq!(english[0].0, SECOND as i64);
+    assert_eq!(english[0].1.stop_time, Some(88));
+    assert_eq!(english[0].2.width(), 40);
+    assert_eq!(english[1].0, 3 * SECOND as i64);
+    assert_eq!(english[1].2.width(), 50);
+
+    // The delay moves the German subtitles, and a position past the end of
+    // the .sub doesn't stop the rest
+    let german: Vec<_> = reader.events().collect();
+    assert_eq!(german.len(), 2);
+    let (timestamp, control, image) = german[0].as_ref().unwrap();
+    assert_eq!(*timestamp, 1_500 * SECOND as i64 / 1000);
+    assert_eq!(control.stop_time, None);
+    assert_eq!(image.width(), 30);
+    assert!(matches!(german[1], Err(vobs::SubsError::MissingSubpicture)));
+
+    assert_eq!(reader.language_events(2).count(), 0);
+}
+
+#[test]
+fn textst_dialogs() {
+    let mut parser = TextstParser::with_codec_private(&textst_dss()).unwrap();
+    let frames = vec![
+        mkv_frame(
+            SECOND,
+            textst_dps(90_000, 270_000, false, &["Hello there.", "General Kenobi!"]),
+        ),
+        mkv_frame(
+            4 * SECOND,
+            textst_dps(360_000, 450_000, true, &["[FORCED]"]),
+        ),
+    ];
+    let mut timeline = String::new();
+    for frame in frames.iter() {
+        let line = match parser.process_mkv_frame(frame) {
+            Ok(Some(event)) => {
+                let spans = event.regions[0]
+                    .spans
+                    .iter()
+                    .map(|span| format!("{:?}:{:?}", span.font_style, span.text))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                format!("{} forced={} {spans}", event.end, event.forced())
+            }
+            Ok(None) => String::from("none"),
+            Err(err) => format!("error: {err}"),
+        };
+        timeline.push_str(&format!("{} {}\n", frame.timestamp, line));
+    }
+    assert_golden("textst_dialogs", &timeline);
+}
+
+#[test]
+fn contact_sheet_layout() {
+    let mut sheet = ContactSheet::new(SheetLayout {
+        columns: 2,
+        rows: 1,
+        thumb_width: 100,
+        thumb_height: 30,
+    });
+    for (i, (width, height)) in [(300, 40), (80, 20), (200, 100)].into_iter().enumerate() {
+        let image = GrayAlphaImage::from_fn(width, height, |x, y| {
+            LumaA([if (x + y) % 8 < 4 { 235 } else { 16 }, 255])
+        });
+        sheet.add(i + 1, (i as u64 + 1) * 61 * SECOND + 250_000_000, &image);
+    }
+    let timeline: String = sheet
+        .render()
+        .iter()
+        .enumerate()
+        .map(|(i, image)| format!("{i} {}\n", describe_rgba(image)))
+        .collect();
+    assert_golden("contact_sheet_layout", &timeline);
+}
@@ -0,0 +1,97 @@
+//! Reading inputs with read-ahead, and limiting open files.
+
+use std::{
+    io::{Cursor, Read, Seek, SeekFrom},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use subproc::input::{FileLimiter, IoStrategy, ReadAhead};
+
+/// Reads `length` bytes, or up to the end
+fn read_up_to<R: Read>(reader: &mut R, length: u64) -> Vec<u8> {
+    let mut data = Vec::new();
+    reader.take(length).read_to_end(&mut data).unwrap();
+    return data;
+}
+
+fn data() -> Vec<u8> {
+    return (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+}
+
+#[test]
+fn read_ahead_matches_plain_reads() {
+    let mut expected = Cursor::new(data());
+    let mut reader = ReadAhead::new(Cursor::new(data()), 1000, 3).unwrap();
+    // Reads spanning chunks, seeks within a chunk, back, forward, from the end
+    // and past the end
+    let steps: [(SeekFrom, u64); 7] = [
+        (SeekFrom::Current(0), 2_500),
+        (SeekFrom::Current(-300), 10),
+        (SeekFrom::Start(50_000), 1_234),
+        (SeekFrom::Start(10), 5),
+        (SeekFrom::Current(20_000), 3_000),
+        (SeekFrom::End(-100), 500),
+        (SeekFrom::End(100), 10),
+    ];
+    for (seek, length) in steps {
+        assert_eq!(reader.seek(seek).unwrap(), expected.seek(seek).unwrap());
+        assert_eq!(
+            read_up_to(&mut reader, length),
+            read_up_to(&mut expected, length)
+        );
+    }
+    assert!(reader.seek(SeekFrom::Current(-1_000_000)).is_err());
+
+    let mut all = Vec::new();
+    reader.seek(SeekFrom::Start(0)).unwrap();
+    reader.read_to_end(&mut all).unwrap();
+    assert_eq!(all, data());
+}
+
+#[test]
+fn opens_files() {
+    let path = std::env::temp_dir().join(format!("subproc-input-{}", std::process::id()));
+    std::fs::write(&path, data()).unwrap();
+    for strategy in [
+        IoStrategy::default(),
+        IoStrategy {
+            buffer_size: 4096,
+            read_ahead: 2,
+            ..IoStrategy::default()
+        },
+    ] {
+        let mut input = strategy.open(&path).unwrap();
+        input.seek(SeekFrom::Start(99_990)).unwrap();
+        let mut tail = Vec::new();
+        input.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, data()[99_990..]);
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn limits_open_files() {
+    let limiter = Arc::new(FileLimiter::new(2));
+    let most = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..6)
+        .map(|_| {
+            let limiter = limiter.clone();
+            let most = most.clone();
+            return thread::spawn(move || {
+                let _permit = limiter.acquire();
+                most.fetch_max(limiter.open_files(), Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(10));
+            });
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert_eq!(most.load(Ordering::SeqCst), 2);
+    assert_eq!(limiter.open_files(), 0);
+}
@@ -0,0 +1,59 @@
+//! Reading keyframe times from MKV Cues and snapping cues to them.
+
+mod common;
+
+use std::io::Cursor;
+
+use common::*;
+use subproc::keyframes::{read_keyframes, snap_to_keyframes};
+
+const MS: u64 = 1_000_000;
+
+#[test]
+fn reads_cue_points() {
+    // One cluster, and so one cue point, per 10 seconds with frames in it
+    let file = build_mkv(
+        &[(1, "S_TEXT/UTF8", None)],
+        &[
+            (1, 1_000, b"a".to_vec()),
+            (1, 12_000, b"b".to_vec()),
+            (1, 35_000, b"c".to_vec()),
+        ],
+    );
+    assert_eq!(
+        read_keyframes(&mut Cursor::new(file)).unwrap(),
+        [0, 10_000 * MS, 30_000 * MS]
+    );
+
+    let empty = build_mkv(&[(1, "S_TEXT/UTF8", None)], &[]);
+    assert!(read_keyframes(&mut Cursor::new(empty)).unwrap().is_empty());
+}
+
+#[test]
+fn snaps_within_window() {
+    let keyframes = [1_000 * MS, 2_000 * MS, 5_000 * MS, 5_100 * MS];
+    let mut cues = vec![
+        // Both ends close enough
+        cue(960, 2_030, "Hello"),
+        // Only the end, to the nearer of two
+        cue(3_000, 5_070, "Hello"),
+        // Would collapse onto one keyframe
+        cue(4_980, 5_020, "Hello"),
+        // Nothing nearby
+        cue(7_000, 8_000, "Hello"),
+    ];
+    assert_eq!(snap_to_keyframes(&mut cues, &keyframes, 50 * MS), 2);
+    let times: Vec<(u64, u64)> = cues
+        .iter()
+        .map(|cue| (cue.start / MS, cue.end / MS))
+        .collect();
+    assert_eq!(
+        times,
+        [
+            (1_000, 2_000),
+            (3_000, 5_100),
+            (4_980, 5_020),
+            (7_000, 8_000)
+        ]
+    );
+}
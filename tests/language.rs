@@ -0,0 +1,32 @@
+//! Language detection for tracks tagged `und`.
+
+use subproc::language::DetectedLanguage;
+
+#[test]
+fn detects_dialogue() {
+    let english = [
+        "Where were you last night?",
+        "I told you, I was at work until late.",
+        "- Nobody at the office saw you.\n- Then they weren't looking very hard.",
+        "We should talk about this tomorrow.",
+    ];
+    let detected = DetectedLanguage::from_texts(english).unwrap();
+    assert_eq!(detected.code, "eng");
+    assert!(detected.reliable);
+
+    // Bibliographic codes where Matroska differs from ISO 639-3
+    let german = [
+        "Wo warst du gestern Abend?",
+        "Ich habe dir doch gesagt, dass ich bis spät in der Arbeit war.",
+        "Niemand im Büro hat dich gesehen.",
+        "Darüber sollten wir morgen reden.",
+    ];
+    assert_eq!(DetectedLanguage::from_texts(german).unwrap().code, "ger");
+}
+
+#[test]
+fn short_text_is_unreliable() {
+    assert!(DetectedLanguage::from_texts([]).is_none());
+    let detected = DetectedLanguage::from_texts(["Oh."]);
+    assert!(detected.is_none_or(|detected| !detected.reliable));
+}
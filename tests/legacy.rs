@@ -0,0 +1,66 @@
+//! MicroDVD and SubViewer output.
+
+use subproc::{
+    legacy::{write_microdvd, write_subviewer},
+    srt::SrtCue,
+    timebase::FrameRate,
+};
+
+const MS: u64 = 1_000_000;
+
+fn cues() -> Vec<SrtCue> {
+    return vec![
+        SrtCue {
+            start: 1_001 * MS,
+            end: 3_420 * MS,
+            text: String::from("{\\an8}<i>First line</i>\nSecond line"),
+        },
+        SrtCue {
+            start: 4_000 * MS,
+            end: 4_010 * MS,
+            text: String::from("Short"),
+        },
+        SrtCue {
+            start: 5_000 * MS,
+            end: 6_000 * MS,
+            text: String::from(" \n "),
+        },
+    ];
+}
+
+#[test]
+fn microdvd() {
+    let mut out = Vec::new();
+    write_microdvd(&mut out, &cues(), FrameRate::FILM_NTSC).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "{1}{1}23.976\n\
+         {24}{82}First line|Second line\n\
+         {96}{97}Short\n"
+    );
+
+    let mut out = Vec::new();
+    write_microdvd(&mut out, &cues()[1..2], FrameRate::PAL).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "{1}{1}25\n{100}{101}Short\n"
+    );
+}
+
+#[test]
+fn subviewer() {
+    let mut out = Vec::new();
+    write_subviewer(&mut out, &cues()).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "[INFORMATION]\n\
+         [END INFORMATION]\n\
+         [SUBTITLE]\n\
+         00:00:01.00,00:00:03.42\n\
+         First line[br]Second line\n\
+         \n\
+         00:00:04.00,00:00:04.01\n\
+         Short\n\
+         \n"
+    );
+}
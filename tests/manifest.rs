@@ -0,0 +1,176 @@
+//! The manifest saved alongside `--save-images` directories, which the
+//! `subtitle-ocr` command reads the timing of each image from and
+//! `rehydrate` merges corrected text into.
+
+mod common;
+
+use std::{fs::File, io::Cursor};
+
+use common::*;
+use matroska_demuxer::MatroskaFile;
+use subproc::{
+    extract::SubtitleExtractor,
+    model::{
+        ImageRef, MANIFEST_FILE, MergeReport, SubtitleDocument, SubtitleEvent, SubtitleTrack,
+        TextOrigin, TextPayload,
+    },
+    sink::{EventSink, ImageDirSink},
+    srt::parse_srt,
+};
+
+const MS: u64 = 1_000_000;
+
+#[test]
+fn image_dir_manifest() {
+    let objects = [PgsObjectRef {
+        object_id: 1,
+        window_id: 0,
+        x: 0,
+        y: 0,
+        crop: None,
+    }];
+    let window = [(0, 800, 900, 100, 20)];
+    let show = |composition_number| {
+        return PgsDisplaySetBuilder::new()
+            .pcs(composition_number, 0x80, 0, &objects)
+            .wds(&window)
+            .pds(0, 0, &[(1, 235, 255), (2, 16, 255)])
+            .ods(1, 0, 100, 20, &pgs_rle(&outlined_bar(100, 20, 1, 2)), 1)
+            .finish();
+    };
+    let clear = |composition_number| {
+        return PgsDisplaySetBuilder::new()
+            .pcs(composition_number, 0x00, 0, &[])
+            .wds(&window)
+            .finish();
+    };
+    let mkv = build_mkv(
+        &[(1, "S_HDMV/PGS", None)],
+        &[
+            (1, 1_000, show(1)),
+            (1, 2_500, clear(2)),
+            (1, 3_000, show(3)),
+            (1, 4_000, clear(4)),
+        ],
+    );
+    let extractor =
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(mkv)).unwrap(), None).unwrap();
+    let directory = std::env::temp_dir().join(format!("subproc-manifest-{}", std::process::id()));
+    let mut images = ImageDirSink::new(&directory).with_manifest(
+        Some(String::from("movie.mkv")),
+        SubtitleTrack::from_entry(extractor.track()),
+    );
+    for (index, event) in extractor.enumerate() {
+        images.event(index, &event.unwrap()).unwrap();
+    }
+    images.finish().unwrap();
+
+    let file = File::open(directory.join(MANIFEST_FILE)).unwrap();
+    let document = SubtitleDocument::read_json(file).unwrap();
+    assert_eq!(document.source.as_deref(), Some("movie.mkv"));
+    let track = &document.tracks[0];
+    assert_eq!(track.codec, "S_HDMV/PGS");
+    let timing: Vec<_> = track
+        .events
+        .iter()
+        .map(|event| (event.start / MS, event.end.map(|end| end / MS)))
+        .collect();
+    assert_eq!(timing, [(1_000, Some(2_500)), (3_000, Some(4_000))]);
+    for (i, event) in track.events.iter().enumerate() {
+        let image = event.image.as_ref().unwrap();
+        assert_eq!(
+            image.path.to_str(),
+            Some(format!("{:05}.png", i + 1).as_str())
+        );
+        assert_eq!((image.width, image.height), (100, 20));
+        assert_eq!(image.placement.map(|placement| placement.x), Some(800));
+        assert!(directory.join(&image.path).exists());
+    }
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn merge_corrected_text() {
+    let image = |path: &str| {
+        return Some(ImageRef {
+            path: path.into(),
+            width: 100,
+            height: 20,
+            placement: None,
+            regions: Vec::new(),
+        });
+    };
+    let mut track = SubtitleTrack {
+        codec: String::from("S_HDMV/PGS"),
+        events: vec![
+            SubtitleEvent {
+                start: 1_000 * MS,
+                end: Some(2_000 * MS),
+                end_source: None,
+                forced: true,
+                text: None,
+                image: image("00001.png"),
+            },
+            // Ends when the next one starts
+            SubtitleEvent {
+                start: 3_000 * MS,
+                end: None,
+                end_source: None,
+                forced: false,
+                text: None,
+                image: image("00002.png"),
+            },
+            SubtitleEvent {
+                start: 5_000 * MS,
+                end: Some(6_000 * MS),
+                end_source: None,
+                forced: false,
+                text: Some(TextPayload {
+                    text: String::from("Deleted"),
+                    origin: TextOrigin::Stream,
+                }),
+                image: None,
+            },
+        ],
+        ..Default::default()
+    };
+    let cues = parse_srt(
+        "1\n00:00:01,000 --> 00:00:02,000\nFirst\n\n\
+         2\n00:00:03,000 --> 00:00:03,500\nSecond\n\n\
+         3\n00:00:03,500 --> 00:00:04,800\nand third\n\n\
+         4\n00:00:08,000 --> 00:00:09,000\nAdded\n",
+    );
+    let report = track.merge_text(&cues);
+    assert_eq!(
+        report,
+        MergeReport {
+            updated: 2,
+            cleared: 1,
+            added: 1
+        }
+    );
+
+    let texts: Vec<_> = track
+        .events
+        .iter()
+        .map(|event| {
+            event
+                .text
+                .as_ref()
+                .map(|text| (text.text.as_str(), text.origin))
+        })
+        .collect();
+    assert_eq!(
+        texts,
+        [
+            Some(("First", TextOrigin::Edited)),
+            Some(("Second\nand third", TextOrigin::Edited)),
+            None,
+            Some(("Added", TextOrigin::Edited)),
+        ]
+    );
+    // The rest of each event is kept
+    assert!(track.events[0].forced);
+    assert_eq!(track.events[1].end, None);
+    assert_eq!(track.events[3].end, Some(9_000 * MS));
+}
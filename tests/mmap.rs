@@ -0,0 +1,25 @@
+//! Memory-mapped inputs.
+
+use std::io::{Read, Seek, SeekFrom};
+
+use subproc::input::IoStrategy;
+
+#[test]
+fn maps_files() {
+    let path = std::env::temp_dir().join(format!("subproc-mmap-{}", std::process::id()));
+    let data: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    std::fs::write(&path, &data).unwrap();
+    let strategy = IoStrategy {
+        mmap: true,
+        ..IoStrategy::default()
+    };
+    let mut input = strategy.open(&path).unwrap();
+    assert_eq!(input.as_slice(), Some(&data[..]));
+    input.seek(SeekFrom::End(-10)).unwrap();
+    let mut tail = Vec::new();
+    input.read_to_end(&mut tail).unwrap();
+    assert_eq!(tail, data[99_990..]);
+
+    assert_eq!(IoStrategy::default().open(&path).unwrap().as_slice(), None);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,94 @@
+//! Music note detection on synthetic subtitle bitmaps.
+
+use image::{GrayAlphaImage, LumaA};
+use subproc::music_notes::NoteDetection;
+
+const FILL: LumaA<u8> = LumaA([255, 255]);
+
+fn fill_rect(image: &mut GrayAlphaImage, x1: u32, y1: u32, x2: u32, y2: u32) {
+    for y in y1..y2 {
+        for x in x1..x2 {
+            image.put_pixel(x, y, FILL);
+        }
+    }
+}
+
+/// An eighth note, 18x30 pixels with its top left corner at `x`, `y`
+fn draw_eighth_note(image: &mut GrayAlphaImage, x: u32, y: u32) {
+    for dy in 0..30 {
+        for dx in 0..18 {
+            let ex = (dx as f64 + 0.5 - 6.0) / 6.0;
+            let ey = (dy as f64 + 0.5 - 24.0) / 5.5;
+            if ex * ex + ey * ey <= 1.0 {
+                image.put_pixel(x + dx, y + dy, FILL);
+            }
+        }
+    }
+    fill_rect(image, x + 9, y, x + 12, y + 24);
+    for i in 0..12 {
+        fill_rect(image, x + 12, y + i, x + 12 + (12 - i).min(6), y + i + 2);
+    }
+}
+
+/// Stand-in for a word: a few ring-shaped letters with a `d`-like one at the end
+fn draw_word(image: &mut GrayAlphaImage, x: u32, y: u32, letters: u32) -> u32 {
+    let mut x = x;
+    for _ in 0..letters {
+        fill_rect(image, x, y + 8, x + 14, y + 30);
+        for dy in y + 11..y + 27 {
+            for dx in x + 3..x + 11 {
+                image.put_pixel(dx, dy, LumaA([0, 0]));
+            }
+        }
+        x += 18;
+    }
+    // `d`: a ring with a stem on the right, about the size of a note
+    fill_rect(image, x, y + 8, x + 16, y + 30);
+    fill_rect(image, x + 13, y, x + 16, y + 30);
+    for dy in y + 11..y + 27 {
+        for dx in x + 3..x + 13 {
+            image.put_pixel(dx, dy, LumaA([0, 0]));
+        }
+    }
+    return x + 16;
+}
+
+#[test]
+fn finds_and_reinserts_notes() {
+    let mut image = GrayAlphaImage::new(400, 40);
+    draw_eighth_note(&mut image, 5, 5);
+    let end = draw_word(&mut image, 40, 5, 4);
+    let end = draw_word(&mut image, end + 20, 5, 3);
+    draw_eighth_note(&mut image, end + 20, 5);
+
+    let detection = NoteDetection::detect(&image);
+    assert_eq!(detection.notes.len(), 2);
+    assert!(detection.notes.iter().all(|note| note.glyph == '♪'));
+    assert_eq!(detection.notes[0].x, 5);
+
+    detection.erase(&mut image);
+    assert_eq!(image.get_pixel(15, 10).0[1], 0);
+    assert_eq!(image.get_pixel(41, 20).0[1], 255);
+
+    assert_eq!(detection.reinsert("Singing in\n"), "♪ Singing in ♪");
+}
+
+#[test]
+fn notes_on_their_own_row() {
+    let mut image = GrayAlphaImage::new(200, 80);
+    draw_eighth_note(&mut image, 80, 5);
+    draw_word(&mut image, 40, 45, 3);
+
+    let detection = NoteDetection::detect(&image);
+    assert_eq!(detection.notes.len(), 1);
+    assert_eq!(detection.reinsert("Hello"), "♪\nHello");
+}
+
+#[test]
+fn ignores_plain_text() {
+    let mut image = GrayAlphaImage::new(200, 40);
+    draw_word(&mut image, 5, 5, 5);
+    let detection = NoteDetection::detect(&image);
+    assert!(detection.notes.is_empty());
+    assert_eq!(detection.reinsert("Hello there"), "Hello there");
+}
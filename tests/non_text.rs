@@ -0,0 +1,128 @@
+//! Telling text bitmaps from logos, specks and rules before OCR.
+
+use image::{GrayAlphaImage, LumaA};
+use subproc::{
+    non_text::{NonTextReason, NonTextRules, classify},
+    preprocess::Region,
+};
+
+/// A transparent bitmap with opaque `(x, y, width, height)` blocks
+fn blocks(width: u32, height: u32, blocks: &[(u32, u32, u32, u32)]) -> GrayAlphaImage {
+    let mut image = GrayAlphaImage::new(width, height);
+    for &(x, y, w, h) in blocks {
+        for dy in 0..h {
+            for dx in 0..w {
+                image.put_pixel(x + dx, y + dy, LumaA([235, 255]));
+            }
+        }
+    }
+    return image;
+}
+
+#[test]
+fn keeps_text() {
+    let rules = NonTextRules::default();
+    // Letters along a line
+    let letters: Vec<_> = (0..20).map(|i| (i * 20, 0, 12, 30)).collect();
+    assert_eq!(classify(&blocks(392, 30, &letters), &[], &rules), None);
+    // A lone "!"
+    assert_eq!(
+        classify(&blocks(5, 30, &[(0, 0, 5, 30)]), &[], &rules),
+        None
+    );
+}
+
+#[test]
+fn judges_regions_separately() {
+    let rules = NonTextRules::default();
+    // A sign at the top and dialogue at the bottom, far apart in one image
+    let mut letters: Vec<_> = (0..10).map(|i| (i * 20, 0, 8, 12)).collect();
+    letters.extend((0..10).map(|i| (300 + i * 20, 570, 12, 30)));
+    let image = blocks(1000, 600, &letters);
+    assert!(matches!(
+        classify(&image, &[], &rules),
+        Some(NonTextReason::Sparse(_))
+    ));
+    let regions = [
+        Region {
+            x: 0,
+            y: 0,
+            width: 200,
+            height: 20,
+        },
+        Region {
+            x: 300,
+            y: 570,
+            width: 400,
+            height: 30,
+        },
+    ];
+    assert_eq!(classify(&image, &regions, &rules), None);
+
+    // Text alongside a rule is still text
+    let image = blocks(
+        900,
+        60,
+        &[(0, 0, 900, 4), (0, 30, 12, 30), (20, 30, 12, 30)],
+    );
+    let regions = [
+        Region {
+            x: 0,
+            y: 0,
+            width: 900,
+            height: 4,
+        },
+        Region {
+            x: 0,
+            y: 30,
+            width: 32,
+            height: 30,
+        },
+    ];
+    assert_eq!(classify(&image, &regions, &rules), None);
+    assert_eq!(
+        classify(&image, &regions[..1], &rules),
+        Some(NonTextReason::Aspect(225.0))
+    );
+}
+
+#[test]
+fn skips_non_text() {
+    let rules = NonTextRules::default();
+
+    let faint = GrayAlphaImage::from_pixel(200, 40, LumaA([235, 60]));
+    assert_eq!(
+        classify(&faint, &[], &rules),
+        Some(NonTextReason::Faint(60))
+    );
+
+    // Three dots
+    let dots = blocks(43, 3, &[(0, 0, 3, 3), (20, 0, 3, 3), (40, 0, 3, 3)]);
+    assert_eq!(
+        classify(&dots, &[], &rules),
+        Some(NonTextReason::LittleInk(27))
+    );
+    let rules_for_dots = NonTextRules {
+        min_ink: 10,
+        ..rules
+    };
+    assert_eq!(classify(&dots, &[], &rules_for_dots), None);
+
+    // Two specks in opposite corners of the screen
+    let specks = blocks(1000, 500, &[(0, 0, 8, 8), (992, 492, 8, 8)]);
+    assert!(matches!(
+        classify(&specks, &[], &rules),
+        Some(NonTextReason::Sparse(_))
+    ));
+
+    // A horizontal rule
+    let rule = blocks(900, 6, &[(0, 0, 900, 6)]);
+    assert_eq!(
+        classify(&rule, &[], &rules),
+        Some(NonTextReason::Aspect(150.0))
+    );
+    assert_eq!(
+        NonTextReason::Aspect(150.0).to_string(),
+        "150.00 times as wide as it is tall"
+    );
+}
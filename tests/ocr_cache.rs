@@ -0,0 +1,39 @@
+//! Reuse of OCR results across runs through `CachedEngine`.
+
+use image::{GrayImage, Luma};
+use subproc::ocr::{CachedEngine, OcrEngine, OcrError};
+
+/// Stand-in OCR that counts how often it's asked
+struct Counting(usize);
+impl OcrEngine for Counting {
+    fn recognize(&mut self, image: &GrayImage) -> Result<String, OcrError> {
+        self.0 += 1;
+        return Ok(format!("{}x{}", image.width(), image.height()));
+    }
+}
+
+#[test]
+fn cached_results() {
+    let directory = std::env::temp_dir().join(format!("subproc-ocr-cache-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    let first = GrayImage::from_pixel(20, 10, Luma([255]));
+    let mut second = first.clone();
+    second.put_pixel(3, 4, Luma([0]));
+
+    let mut engine = CachedEngine::new(Counting(0), &directory, "settings").unwrap();
+    assert_eq!(engine.recognize(&first).unwrap(), "20x10");
+    assert_eq!(engine.recognize(&second).unwrap(), "20x10");
+    assert_eq!(engine.recognize(&first).unwrap(), "20x10");
+    assert_eq!(engine.hits(), 1);
+
+    // A later run picks up where the first left off, unless settings change
+    let mut engine = CachedEngine::new(Counting(0), &directory, "settings").unwrap();
+    engine.recognize(&first).unwrap();
+    engine.recognize(&second).unwrap();
+    assert_eq!(engine.hits(), 2);
+    let mut engine = CachedEngine::new(Counting(0), &directory, "other settings").unwrap();
+    engine.recognize(&first).unwrap();
+    assert_eq!(engine.hits(), 0);
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,65 @@
+//! Low-confidence retries through `RetryEngine`, and confidence surviving
+//! the cache.
+
+use image::{GrayImage, Luma};
+use subproc::ocr::{
+    CachedEngine, OcrEngine, OcrError, Recognition, RetryEngine, RetryPolicy, Variant,
+};
+
+/// Stand-in OCR that reads dark text on light backgrounds well, and is only
+/// sure of anything when the image is mostly white
+struct Brightness;
+impl OcrEngine for Brightness {
+    fn recognize(&mut self, image: &GrayImage) -> Result<String, OcrError> {
+        return Ok(self.recognize_scored(image)?.text);
+    }
+
+    fn recognize_scored(&mut self, image: &GrayImage) -> Result<Recognition, OcrError> {
+        let total: u64 = image.pixels().map(|pixel| pixel.0[0] as u64).sum();
+        let mean = total / (image.width() * image.height()) as u64;
+        return Ok(Recognition {
+            text: format!("{}x{} {mean}", image.width(), image.height()),
+            confidence: Some(mean as f32 * 100.0 / 255.0),
+        });
+    }
+}
+
+#[test]
+fn retries_keep_most_confident() {
+    let mut engine = RetryEngine::new(Brightness, RetryPolicy::new(50.0));
+    let light = GrayImage::from_pixel(4, 2, Luma([230]));
+    assert_eq!(engine.recognize(&light).unwrap(), "4x2 230");
+    assert!(engine.records().is_empty());
+
+    // Inverting turns the dark image light, which the others can't beat
+    let dark = GrayImage::from_pixel(4, 2, Luma([20]));
+    assert_eq!(engine.recognize(&dark).unwrap(), "4x2 235");
+    let record = &engine.records()[0];
+    assert_eq!(record.winner, Some(Variant::Inverted));
+    assert!(record.original < 10.0 && record.confidence > 90.0);
+
+    // Engines without confidence pass straight through
+    struct Unscored;
+    impl OcrEngine for Unscored {
+        fn recognize(&mut self, _: &GrayImage) -> Result<String, OcrError> {
+            return Ok(String::from("text"));
+        }
+    }
+    let mut engine = RetryEngine::new(Unscored, RetryPolicy::new(100.0));
+    assert_eq!(engine.recognize(&dark).unwrap(), "text");
+    assert!(engine.records().is_empty());
+}
+
+#[test]
+fn cache_keeps_confidence() {
+    let directory = std::env::temp_dir().join(format!("subproc-ocr-retry-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    let image = GrayImage::from_pixel(4, 2, Luma([51]));
+    let mut engine = CachedEngine::new(Brightness, &directory, "settings").unwrap();
+    let first = engine.recognize_scored(&image).unwrap();
+    let second = engine.recognize_scored(&image).unwrap();
+    assert_eq!(engine.hits(), 1);
+    assert_eq!(first, second);
+    assert_eq!(second.confidence, Some(20.0));
+    std::fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,144 @@
+//! `PgsParser` against the naive reference renderer `--verify` uses.
+
+mod common;
+
+use common::*;
+use subproc::bdsup::{
+    PgsParser,
+    reference::{ReferenceRenderer, differing_pixels},
+};
+
+fn objects(crop: Option<(u16, u16, u16, u16)>) -> Vec<PgsObjectRef> {
+    return vec![
+        PgsObjectRef {
+            object_id: 1,
+            window_id: 0,
+            x: 10,
+            y: 5,
+            crop,
+        },
+        PgsObjectRef {
+            object_id: 2,
+            window_id: 1,
+            x: 0,
+            y: 0,
+            crop: None,
+        },
+    ];
+}
+
+#[test]
+fn renderers_agree() {
+    let windows = [(0, 700, 60, 400, 40), (1, 600, 950, 600, 50)];
+    let display_sets = vec![
+        // Two windows, one object split over several segments
+        PgsDisplaySetBuilder::new()
+            .pcs(1, 0x80, 0, &objects(None))
+            .wds(&windows)
+            .pds(0, 0, &[(1, 235, 255), (2, 16, 255), (3, 128, 128)])
+            .ods(1, 0, 300, 30, &pgs_rle(&outlined_bar(300, 30, 1, 2)), 3)
+            .ods(2, 0, 600, 50, &pgs_rle(&outlined_bar(600, 50, 3, 2)), 1)
+            .finish(),
+        // Palette update, then cropping the cached object
+        PgsDisplaySetBuilder::new()
+            .pcs(2, 0x00, 0, &objects(None))
+            .pds(0, 1, &[(1, 128, 255)])
+            .finish(),
+        PgsDisplaySetBuilder::new()
+            .pcs(3, 0x00, 0, &objects(Some((50, 5, 100, 20))))
+            .finish(),
+        // Acquisition points repeat everything
+        PgsDisplaySetBuilder::new()
+            .pcs(4, 0x40, 0, &objects(None))
+            .wds(&windows)
+            .pds(0, 1, &[(1, 128, 255), (2, 16, 255), (3, 128, 128)])
+            .ods(1, 0, 300, 30, &pgs_rle(&outlined_bar(300, 30, 1, 2)), 1)
+            .ods(2, 1, 600, 50, &pgs_rle(&outlined_bar(600, 50, 2, 1)), 2)
+            .finish(),
+        PgsDisplaySetBuilder::new()
+            .pcs(5, 0x00, 0, &[])
+            .wds(&windows)
+            .finish(),
+    ];
+
+    let mut parser = PgsParser::new();
+    let mut reference = ReferenceRenderer::new();
+    for (i, data) in display_sets.iter().enumerate() {
+        let ours = parser.process_display_set(data).unwrap().unwrap();
+        let theirs = reference.process_display_set(data).unwrap().unwrap();
+        assert_eq!(differing_pixels(&ours, &theirs), Some(0), "display set {i}");
+    }
+}
+
+#[test]
+fn incremental_updates() {
+    let windows = [(0, 700, 60, 400, 40), (1, 600, 950, 600, 50)];
+    let object = |object_id, window_id, x, y| PgsObjectRef {
+        object_id,
+        window_id,
+        x,
+        y,
+        crop: None,
+    };
+    let both = [object(1, 0, 10, 5), object(2, 1, 0, 0)];
+    let display_sets = vec![
+        PgsDisplaySetBuilder::new()
+            .pcs(1, 0x80, 0, &both)
+            .wds(&windows)
+            .pds(0, 0, &[(1, 235, 255), (2, 16, 255), (3, 128, 128)])
+            .ods(1, 0, 300, 30, &pgs_rle(&outlined_bar(300, 30, 1, 2)), 1)
+            .ods(2, 0, 600, 50, &pgs_rle(&outlined_bar(600, 50, 3, 2)), 1)
+            .finish(),
+        // Only the bottom object changes
+        PgsDisplaySetBuilder::new()
+            .pcs(2, 0x00, 0, &both)
+            .ods(2, 1, 500, 40, &pgs_rle(&outlined_bar(500, 40, 1, 1)), 2)
+            .finish(),
+        // The top one moves, then goes away
+        PgsDisplaySetBuilder::new()
+            .pcs(3, 0x00, 0, &[object(1, 0, 60, 0), object(2, 1, 0, 0)])
+            .finish(),
+        PgsDisplaySetBuilder::new()
+            .pcs(4, 0x00, 0, &[object(2, 1, 0, 0)])
+            .finish(),
+        // Its window moves while it's hidden
+        PgsDisplaySetBuilder::new()
+            .pcs(5, 0x00, 0, &both)
+            .wds(&[(0, 650, 100, 400, 40)])
+            .finish(),
+        // Overlapping objects, then redefining the one underneath
+        PgsDisplaySetBuilder::new()
+            .pcs(6, 0x00, 0, &[object(1, 0, 0, 0), object(2, 0, 100, 10)])
+            .finish(),
+        PgsDisplaySetBuilder::new()
+            .pcs(7, 0x00, 0, &[object(1, 0, 0, 0), object(2, 0, 100, 10)])
+            .ods(1, 1, 300, 30, &pgs_rle(&outlined_bar(300, 30, 2, 3)), 1)
+            .finish(),
+        // The same objects in the other order
+        PgsDisplaySetBuilder::new()
+            .pcs(8, 0x00, 0, &[object(2, 0, 100, 10), object(1, 0, 0, 0)])
+            .finish(),
+    ];
+
+    let mut parser = PgsParser::new();
+    let mut reference = ReferenceRenderer::new();
+    let mut ours = image::GrayAlphaImage::new(0, 0);
+    for (i, data) in display_sets.iter().enumerate() {
+        assert!(parser.process_display_set_into(data, &mut ours).unwrap());
+        let theirs = reference.process_display_set(data).unwrap().unwrap();
+        assert_eq!(differing_pixels(&ours, &theirs), Some(0), "display set {i}");
+    }
+}
+
+#[test]
+fn missing_data() {
+    // A composition without the epoch it belongs to
+    let data = PgsDisplaySetBuilder::new()
+        .pcs(7, 0x00, 0, &objects(None))
+        .finish();
+    let ours = PgsParser::new().process_display_set(&data).unwrap_err();
+    let theirs = ReferenceRenderer::new()
+        .process_display_set(&data)
+        .unwrap_err();
+    assert_eq!(ours.to_string(), theirs.to_string());
+}
@@ -0,0 +1,234 @@
+//! Flattening of subtitle bitmaps for OCR.
+
+use image::{GrayAlphaImage, LumaA, Rgba};
+use subproc::{
+    indexed::IndexedImage,
+    preprocess::{
+        Background, FlattenOptions, PaletteRole, Placement, classify_palette, flatten, segment,
+        strip_outline,
+    },
+};
+
+#[test]
+fn flatten_thresholds() {
+    // Fill, antialiased edge, faint shadow and transparent background
+    let pixels = [
+        LumaA([235, 255]),
+        LumaA([150, 136]),
+        LumaA([40, 34]),
+        LumaA([16, 0]),
+    ];
+    let mut image = GrayAlphaImage::new(4, 1);
+    for (x, pixel) in pixels.iter().enumerate() {
+        image.put_pixel(x as u32, 0, *pixel);
+    }
+    let luma = |options| {
+        return flatten(&image, options).into_raw();
+    };
+
+    assert_eq!(luma(FlattenOptions::default()), [235, 150, 40, 16]);
+    assert_eq!(
+        luma(FlattenOptions {
+            min_alpha: 128,
+            binarize: None,
+            ..Default::default()
+        }),
+        [235, 150, 0, 0]
+    );
+    assert_eq!(
+        luma(FlattenOptions {
+            min_alpha: 128,
+            binarize: Some(128),
+            ..Default::default()
+        }),
+        [255, 255, 0, 0]
+    );
+}
+
+#[test]
+fn flatten_backgrounds() {
+    let pixels = [
+        LumaA([235, 255]),
+        LumaA([150, 136]),
+        LumaA([40, 34]),
+        LumaA([16, 0]),
+    ];
+    let mut image = GrayAlphaImage::new(4, 1);
+    for (x, pixel) in pixels.iter().enumerate() {
+        image.put_pixel(x as u32, 0, *pixel);
+    }
+    let luma = |image: &GrayAlphaImage, background| {
+        let options = FlattenOptions {
+            background,
+            ..Default::default()
+        };
+        return flatten(image, options).into_raw();
+    };
+
+    assert_eq!(luma(&image, Background::Black), [235, 80, 5, 0]);
+    assert_eq!(luma(&image, Background::White), [235, 199, 226, 255]);
+    // Light text, so black gives the most contrast
+    assert_eq!(luma(&image, Background::Estimated), [235, 80, 5, 0]);
+    assert_eq!(luma(&image, Background::Alpha), [255, 255, 0, 0]);
+    // Without palette indices, the same as alpha
+    assert_eq!(luma(&image, Background::Palette), [255, 255, 0, 0]);
+
+    let mut dark = image.clone();
+    dark.put_pixel(0, 0, LumaA([20, 255]));
+    assert_eq!(luma(&dark, Background::Estimated), [20, 199, 226, 255]);
+
+    assert_eq!("auto".parse(), Ok(Background::Estimated));
+    assert_eq!(Background::Alpha.to_string(), "alpha");
+    assert!("grey".parse::<Background>().is_err());
+}
+
+/// An `O`: a white ring with a dark outline inside and out
+fn outlined_ring() -> GrayAlphaImage {
+    let rows = [
+        "...........",
+        ".#########.",
+        ".#OOOOOOO#.",
+        ".#O#####O#.",
+        ".#O#...#O#.",
+        ".#O#####O#.",
+        ".#OOOOOOO#.",
+        ".#########.",
+        "...........",
+    ];
+    let mut image = GrayAlphaImage::new(11, 9);
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            let pixel = match c {
+                '#' => LumaA([16, 255]),
+                'O' => LumaA([235, 255]),
+                _ => LumaA([0, 0]),
+            };
+            image.put_pixel(x as u32, y as u32, pixel);
+        }
+    }
+    return image;
+}
+
+#[test]
+fn strips_outlines() {
+    let image = outlined_ring();
+    let stripped = strip_outline(&image);
+    for (x, y, pixel) in stripped.enumerate_pixels() {
+        let original = image.get_pixel(x, y);
+        match original.0 {
+            [235, 255] => assert_eq!(pixel, original, "fill at {x},{y}"),
+            _ => assert_eq!(pixel.0[1], 0, "outline at {x},{y}"),
+        }
+    }
+    let options = FlattenOptions {
+        strip_outline: true,
+        binarize: Some(128),
+        ..Default::default()
+    };
+    let flattened = flatten(&image, options);
+    assert_eq!(flattened.get_pixel(2, 2).0, [255]);
+    assert_eq!(flattened.get_pixel(1, 1).0, [0]);
+
+    // A dark fill with a light outline, as some discs do it
+    let mut inverted = image.clone();
+    for pixel in inverted.pixels_mut() {
+        if pixel.0[1] > 0 {
+            pixel.0[0] = 251 - pixel.0[0];
+        }
+    }
+    let stripped = strip_outline(&inverted);
+    assert_eq!(stripped.get_pixel(2, 2).0, [16, 255]);
+    assert_eq!(stripped.get_pixel(1, 1).0[1], 0);
+    assert_eq!(stripped.get_pixel(3, 3).0[1], 0);
+
+    // Nothing to separate
+    let mut plain = GrayAlphaImage::new(4, 1);
+    plain.put_pixel(1, 0, LumaA([235, 255]));
+    plain.put_pixel(2, 0, LumaA([220, 255]));
+    assert_eq!(strip_outline(&plain), plain);
+}
+
+#[test]
+fn segments_palettes() {
+    // A fill with an antialiased edge, an outline, a shadow offset down and
+    // to the right, and an unused entry
+    let rows = [
+        "######.", "#OOaO#.", "#O##O#s", "#OOOO#s", "######s", ".ssssss",
+    ];
+    let palette = vec![
+        Rgba([0, 0, 0, 0]),
+        Rgba([235, 235, 235, 255]),
+        Rgba([180, 180, 180, 255]),
+        Rgba([16, 16, 16, 255]),
+        Rgba([16, 16, 16, 128]),
+        Rgba([255, 0, 0, 255]),
+    ];
+    let mut image = IndexedImage::new(7, 6, palette, String::new());
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            let index = match c {
+                'O' => 1,
+                'a' => 2,
+                '#' => 3,
+                's' => 4,
+                _ => 0,
+            };
+            image.set_index(x as u32, y as u32, index);
+        }
+    }
+    let roles = classify_palette(&image);
+    assert_eq!(roles[0], PaletteRole::Background);
+    assert_eq!(roles[1], PaletteRole::Fill);
+    assert_eq!(roles[2], PaletteRole::Fill);
+    assert_eq!(roles[3], PaletteRole::Outline);
+    assert_eq!(roles[4], PaletteRole::Shadow);
+    // Past the end of the palette
+    assert_eq!(roles[200], PaletteRole::Background);
+
+    let mask = segment(&image);
+    for (x, y, pixel) in mask.enumerate_pixels() {
+        let expected = match image.index(x, y) {
+            1 | 2 => LumaA([255, 255]),
+            _ => LumaA([0, 0]),
+        };
+        assert_eq!(*pixel, expected, "at {x},{y}");
+    }
+
+    // Dark text with a light outline
+    image.palette[1] = Rgba([16, 16, 16, 255]);
+    image.palette[2] = Rgba([60, 60, 60, 255]);
+    image.palette[3] = Rgba([235, 235, 235, 255]);
+    image.palette[4] = Rgba([235, 235, 235, 128]);
+    let roles = classify_palette(&image);
+    assert_eq!(
+        &roles[1..5],
+        [
+            PaletteRole::Fill,
+            PaletteRole::Fill,
+            PaletteRole::Outline,
+            PaletteRole::Shadow
+        ]
+    );
+
+    // Nothing but fill
+    image.palette[3] = Rgba([16, 16, 16, 255]);
+    image.palette[4] = Rgba([16, 16, 16, 128]);
+    let roles = classify_palette(&image);
+    assert!(roles[1..5].iter().all(|role| *role == PaletteRole::Fill));
+    assert_eq!("palette".parse(), Ok(Background::Palette));
+}
+
+#[test]
+fn top_half() {
+    let placement = Placement {
+        x: 100,
+        y: 400,
+        screen_width: 1920,
+        screen_height: 1080,
+    };
+    // A 200 pixel tall image centered at 500 and one centered at 700
+    assert!(placement.is_top(0, 200));
+    assert!(!placement.is_top(200, 200));
+    // Exactly in the middle counts as the bottom
+    assert!(!placement.is_top(40, 200));
+}
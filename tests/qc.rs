@@ -0,0 +1,56 @@
+//! Reading speed analysis and the CSV report.
+
+mod common;
+
+use common::*;
+use subproc::qc::{ReadingSpeedLimits, SpeedIssue, analyze, write_report};
+
+#[test]
+fn flags_fast_and_short_cues() {
+    let cues = vec![
+        cue(0, 2_000, "{\\an8}<i>Hello there.</i>"),
+        cue(2_000, 2_500, ""),
+        cue(
+            3_000,
+            4_000,
+            "You'll never believe\nwhat happened to me today.",
+        ),
+        cue(5_000, 5_500, "Go!"),
+    ];
+    let speeds = analyze(&cues, &ReadingSpeedLimits::default());
+    assert_eq!(speeds.len(), 3);
+
+    // Tags and line breaks aren't counted
+    assert_eq!((speeds[0].characters, speeds[0].words), (12, 2));
+    assert_eq!(speeds[0].cps(), 6.0);
+    assert!(speeds[0].issues.is_empty());
+
+    assert_eq!(speeds[1].index, 2);
+    assert_eq!(speeds[1].characters, 46);
+    assert_eq!(speeds[1].issues, [SpeedIssue::Cps, SpeedIssue::Wpm]);
+    assert_eq!(speeds[2].issues, [SpeedIssue::TooShort]);
+
+    let relaxed = ReadingSpeedLimits {
+        max_cps: 50.0,
+        max_wpm: 600.0,
+        min_duration: 0,
+    };
+    let speeds = analyze(&cues, &relaxed);
+    assert!(speeds.iter().all(|speed| speed.issues.is_empty()));
+}
+
+#[test]
+fn writes_csv() {
+    let cues = vec![
+        cue(1_000, 3_000, "Hello, \"friend\"."),
+        cue(3_000, 3_200, "Hi"),
+    ];
+    let mut report = Vec::new();
+    write_report(&mut report, &analyze(&cues, &ReadingSpeedLimits::default())).unwrap();
+    assert_eq!(
+        String::from_utf8(report).unwrap(),
+        "index,start,end,duration,characters,words,cps,wpm,issues,text\n\
+         1,\"00:00:01,000\",\"00:00:03,000\",2.000,16,2,8.0,60,,\"Hello, \"\"friend\"\".\"\n\
+         2,\"00:00:03,000\",\"00:00:03,200\",0.200,2,1,10.0,300,WPM too high; duration too short,Hi\n"
+    );
+}
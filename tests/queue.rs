@@ -0,0 +1,142 @@
+//! The job queue shared by `serve` and `watch`.
+
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use subproc::queue::{JobLimits, JobQueue, LimitExceeded, QueueLimits, Stage};
+
+fn limits(decode: usize, ocr: usize) -> QueueLimits {
+    return QueueLimits { decode, ocr };
+}
+
+#[test]
+fn starts_by_priority_then_order() {
+    let queue = JobQueue::new(limits(0, 0));
+    for (name, priority) in [("a", 0), ("b", 5), ("c", 0), ("d", 5)] {
+        queue
+            .push(String::from(name), priority, JobLimits::default())
+            .unwrap();
+    }
+    let order: Vec<String> = queue.queued().into_iter().map(|job| job.spec).collect();
+    assert_eq!(order, ["b", "d", "a", "c"]);
+    let mut started = Vec::new();
+    for _ in 0..4 {
+        let (job, _ticket) = queue.next();
+        started.push(job.spec);
+    }
+    assert_eq!(started, order);
+}
+
+#[test]
+fn limits_jobs_per_stage() {
+    let queue = JobQueue::new(limits(1, 1));
+    for id in 0..3 {
+        queue.push(id, 0, JobLimits::default()).unwrap();
+    }
+    let (_, mut first) = queue.next();
+    assert_eq!(first.stage(), Stage::Decode);
+
+    // The second waits for the decode slot
+    let (sender, started) = mpsc::channel();
+    let waiting = queue.clone();
+    let worker = thread::spawn(move || {
+        let (job, mut ticket) = waiting.next();
+        sender.send(job.spec).unwrap();
+        ticket.start_ocr();
+        sender.send(-1).unwrap();
+    });
+    assert!(started.recv_timeout(Duration::from_millis(100)).is_err());
+    first.start_ocr();
+    assert_eq!(first.stage(), Stage::Ocr);
+    assert_eq!(started.recv_timeout(Duration::from_secs(5)), Ok(1));
+
+    // and then for the OCR slot
+    assert!(started.recv_timeout(Duration::from_millis(100)).is_err());
+    drop(first);
+    assert_eq!(started.recv_timeout(Duration::from_secs(5)), Ok(-1));
+    worker.join().unwrap();
+}
+
+#[test]
+fn requeues_unfinished_jobs() {
+    let path = std::env::temp_dir().join(format!("subproc-queue-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let queue = JobQueue::open(&path, limits(0, 0)).unwrap();
+    let limits_a = JobLimits {
+        max_events: Some(10),
+        ..JobLimits::default()
+    };
+    queue.push(String::from("a"), 0, limits_a).unwrap();
+    queue
+        .push(String::from("b"), 0, JobLimits::default())
+        .unwrap();
+    queue
+        .push(String::from("c"), 0, JobLimits::default())
+        .unwrap();
+    let (finished, ticket) = queue.next();
+    assert_eq!(finished.spec, "a");
+    drop(ticket);
+    // Running when the process "crashed"
+    let (running, ticket) = queue.next();
+    std::mem::forget(ticket);
+    assert_eq!(running.spec, "b");
+    assert_eq!(queue.remove(3).map(|job| job.spec), Some(String::from("c")));
+    queue
+        .push(String::from("d"), 1, JobLimits::default())
+        .unwrap();
+
+    let reopened: JobQueue<String> = JobQueue::open(&path, limits(0, 0)).unwrap();
+    let jobs: Vec<(u64, String)> = reopened
+        .queued()
+        .into_iter()
+        .map(|job| (job.id, job.spec))
+        .collect();
+    assert_eq!(jobs, [(4, String::from("d")), (2, String::from("b"))]);
+    assert_eq!(
+        reopened
+            .push(String::from("e"), 0, JobLimits::default())
+            .unwrap(),
+        5
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn enforces_job_limits() {
+    let queue = JobQueue::new(limits(0, 0));
+    let job_limits = JobLimits {
+        max_input_size: Some(1000),
+        max_events: Some(2),
+        timeout: Some(50_000_000),
+    };
+    queue.push((), 0, job_limits).unwrap();
+    let (_, ticket) = queue.next();
+    assert_eq!(ticket.check_input(1000), Ok(()));
+    assert_eq!(
+        ticket.check_input(1001),
+        Err(LimitExceeded::InputSize {
+            size: 1001,
+            limit: 1000
+        })
+    );
+    assert_eq!(ticket.check_events(2), Ok(()));
+    assert_eq!(
+        ticket.check_events(3),
+        Err(LimitExceeded::Events { limit: 2 })
+    );
+
+    let cancel = ticket.cancellation();
+    assert!(!cancel.is_cancelled());
+    let start = Instant::now();
+    while !cancel.is_cancelled() && start.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert!(cancel.is_cancelled());
+    assert!(matches!(
+        ticket.check_time(),
+        Err(LimitExceeded::Timeout { .. })
+    ));
+}
@@ -0,0 +1,141 @@
+//! Round trip through the remuxer: the copy must keep the original track and
+//! frames intact, and the added text track must read back with its timing.
+
+mod common;
+
+use std::io::Cursor;
+
+use common::*;
+use matroska_demuxer::{Frame, MatroskaFile, TrackType};
+use subproc::{
+    model::SubtitleTrack,
+    remux::{TextTrack, remux_with_text_track},
+    sidecar::TrackRole,
+    srt::SrtCue,
+};
+
+const MS: u64 = 1_000_000;
+
+#[test]
+fn adds_text_track() {
+    let source = build_mkv(
+        &[(1, "S_TEXT/UTF8", None)],
+        &[
+            (1, 1_000, b"first".to_vec()),
+            (1, 12_000, b"second".to_vec()),
+            // Far enough from the last cluster to need a cluster of its own
+            (1, 15_000, b"third".to_vec()),
+        ],
+    );
+    let cues = [
+        SrtCue {
+            start: 500 * MS,
+            end: 2_500 * MS,
+            text: String::from("Hello"),
+        },
+        SrtCue {
+            start: 13_000 * MS,
+            end: 14_000 * MS,
+            text: String::from("  "),
+        },
+        SrtCue {
+            start: 60_000 * MS,
+            end: 61_000 * MS,
+            text: String::from("Goodbye"),
+        },
+    ];
+    let mut output = Cursor::new(Vec::new());
+    let track_number = remux_with_text_track(
+        &mut Cursor::new(&source),
+        &mut output,
+        &TextTrack {
+            cues: &cues,
+            language: Some("eng"),
+            name: Some("English (OCR)"),
+            forced: false,
+            sdh: true,
+            default: false,
+        },
+    )
+    .unwrap();
+    assert_eq!(track_number, 2);
+    let output = output.into_inner();
+
+    let mut mkv = MatroskaFile::open(Cursor::new(&output)).unwrap();
+    let track = mkv.tracks()[1].clone();
+    assert_eq!(track.track_number().get(), 2);
+    assert_eq!(track.track_type(), TrackType::Subtitle);
+    assert_eq!(track.codec_id(), "S_TEXT/UTF8");
+    assert_eq!(track.language(), Some("eng"));
+    assert_eq!(track.name(), Some("English (OCR)"));
+    assert!(track.flag_hearing_impaired());
+    assert!(!track.flag_default());
+
+    let mut frame = Frame::default();
+    let mut frames = Vec::new();
+    while mkv.next_frame(&mut frame).unwrap() {
+        frames.push((
+            frame.track,
+            frame.timestamp,
+            frame.duration,
+            String::from_utf8_lossy(&frame.data).into_owned(),
+        ));
+    }
+    frames.sort_by_key(|frame| (frame.1, frame.0));
+    assert_eq!(
+        frames,
+        vec![
+            (2, 500, Some(2_000), String::from("Hello")),
+            (1, 1_000, None, String::from("first")),
+            (1, 12_000, None, String::from("second")),
+            (1, 15_000, None, String::from("third")),
+            (2, 60_000, Some(1_000), String::from("Goodbye")),
+        ]
+    );
+
+    // Seeking goes through the rewritten Cues, which must still point at the
+    // start of a cluster
+    mkv.seek(10_000).unwrap();
+    assert!(mkv.next_frame(&mut frame).unwrap());
+    assert_eq!((frame.track, frame.timestamp), (1, 12_000));
+    assert_eq!(frame.data, b"second");
+}
+
+#[test]
+fn track_flags() {
+    let source = build_mkv(
+        &[(1, "S_TEXT/UTF8", None)],
+        &[(1, 1_000, b"first".to_vec())],
+    );
+    let mut output = Cursor::new(Vec::new());
+    remux_with_text_track(
+        &mut Cursor::new(&source),
+        &mut output,
+        &TextTrack {
+            cues: &[],
+            language: None,
+            name: Some("English"),
+            forced: true,
+            sdh: true,
+            default: true,
+        },
+    )
+    .unwrap();
+    let output = output.into_inner();
+    let mkv = MatroskaFile::open(Cursor::new(&output)).unwrap();
+
+    // Taken from the flags, as the name doesn't say
+    let role = TrackRole::from_track(&mkv.tracks()[1]);
+    assert_eq!(
+        role,
+        TrackRole {
+            forced: true,
+            sdh: true,
+            default: true
+        }
+    );
+    let track = SubtitleTrack::from_entry(&mkv.tracks()[1]);
+    assert!(track.default && track.forced && track.hearing_impaired);
+    // Tracks without a FlagDefault are default
+    assert!(TrackRole::from_track(&mkv.tracks()[0]).default);
+}
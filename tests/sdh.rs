@@ -0,0 +1,36 @@
+//! SDH detection and stripping on typical cue text.
+
+use subproc::sdh::{SdhClassification, has_sdh_markers, strip_sdh};
+
+#[test]
+fn detects_markers() {
+    assert!(has_sdh_markers("[door slams]"));
+    assert!(has_sdh_markers("♪ Never gonna give you up ♪"));
+    assert!(has_sdh_markers("MAN: Get down!"));
+    assert!(has_sdh_markers("- Who's there?\n- GUARD #2: Nobody."));
+    assert!(!has_sdh_markers("Meet me at 10:30."));
+    assert!(!has_sdh_markers("Listen: we have to go."));
+    assert!(!has_sdh_markers("I know."));
+}
+
+#[test]
+fn strips_to_dialogue() {
+    assert_eq!(strip_sdh("[door slams]"), "");
+    assert_eq!(strip_sdh("♪ ♪"), "");
+    assert_eq!(strip_sdh("MAN: Get down! [gunshot]"), "Get down!");
+    assert_eq!(
+        strip_sdh("- [sighs] Fine.\n- WOMAN: Thank you."),
+        "- Fine.\n- Thank you."
+    );
+    // The dash goes once only one speaker is left
+    assert_eq!(strip_sdh("- [gasps]\n- What was that?"), "What was that?");
+    assert_eq!(strip_sdh("It's at 10:30 (I think)."), "It's at 10:30.");
+}
+
+#[test]
+fn classifies_tracks() {
+    let regular = ["Hello.", "How are you?", "[Unclear] maybe.", "Fine."];
+    assert!(!SdhClassification::from_texts(regular).is_sdh());
+    let sdh = ["[thunder]", "Hello.", "MAN: Run!", "How are you?", "Fine."];
+    assert!(SdhClassification::from_texts(sdh).is_sdh());
+}
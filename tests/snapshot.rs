@@ -0,0 +1,85 @@
+//! Checkpointing a `PgsParser` with `snapshot`/`restore`.
+
+mod common;
+
+use common::*;
+use subproc::bdsup::{PgsParser, PgsSnapshot};
+
+fn bar(object_id: u16, x: u16) -> PgsObjectRef {
+    return PgsObjectRef {
+        object_id,
+        window_id: 0,
+        x,
+        y: 0,
+        crop: None,
+    };
+}
+
+fn display_sets() -> Vec<Vec<u8>> {
+    let windows = [(0, 100, 800, 600, 60)];
+    return vec![
+        PgsDisplaySetBuilder::new()
+            .pcs(1, 0x80, 0, &[bar(1, 100)])
+            .wds(&windows)
+            .pds(0, 0, &[(1, 235, 255), (2, 16, 255)])
+            .ods(1, 0, 300, 40, &pgs_rle(&outlined_bar(300, 40, 1, 2)), 2)
+            .finish(),
+        // Only updates the palette, so it depends on everything before it
+        PgsDisplaySetBuilder::new()
+            .pcs(2, 0x00, 0, &[bar(1, 100)])
+            .pds(0, 1, &[(1, 128, 255)])
+            .finish(),
+        // A new epoch replacing all of it
+        PgsDisplaySetBuilder::new()
+            .pcs(3, 0x80, 0, &[bar(2, 0)])
+            .wds(&windows)
+            .pds(0, 0, &[(1, 60, 255), (2, 200, 128)])
+            .ods(2, 0, 500, 60, &pgs_rle(&outlined_bar(500, 60, 2, 1)), 1)
+            .finish(),
+    ];
+}
+
+#[test]
+fn restores_decoding_state() {
+    let display_sets = display_sets();
+    let mut parser = PgsParser::new();
+    parser.process_display_set(&display_sets[0]).unwrap();
+    let snapshot = parser.snapshot();
+    let expected = parser.process_display_set(&display_sets[1]).unwrap();
+    assert!(expected.is_some());
+    parser.process_display_set(&display_sets[2]).unwrap();
+    assert_ne!(parser.snapshot(), snapshot);
+
+    parser.restore(&snapshot);
+    assert_eq!(parser.snapshot(), snapshot);
+    assert_eq!(
+        parser.process_display_set(&display_sets[1]).unwrap(),
+        expected
+    );
+
+    // Into a parser that has never seen the epoch's start
+    let mut resumed = PgsParser::new();
+    resumed.restore(&snapshot);
+    assert_eq!(
+        resumed.process_display_set(&display_sets[1]).unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn snapshots_serialize() {
+    let display_sets = display_sets();
+    let mut parser = PgsParser::new();
+    parser.process_display_set(&display_sets[0]).unwrap();
+    let snapshot = parser.snapshot();
+    let json = serde_json::to_string(&snapshot).unwrap();
+    let decoded: PgsSnapshot = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded, snapshot);
+
+    let mut resumed = PgsParser::new();
+    resumed.restore(&decoded);
+    assert_eq!(
+        resumed.process_display_set(&display_sets[1]).unwrap(),
+        parser.process_display_set(&display_sets[1]).unwrap()
+    );
+}
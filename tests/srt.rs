@@ -0,0 +1,73 @@
+//! SRT ordering, output and parsing.
+
+mod common;
+
+use common::*;
+use subproc::srt::{SrtCue, parse_srt, parse_timestamp, sort_cues, write_srt};
+
+const MS: u64 = 1_000_000;
+
+fn srt(cues: &[SrtCue]) -> String {
+    let mut out = Vec::new();
+    write_srt(&mut out, cues).unwrap();
+    return String::from_utf8(out).unwrap();
+}
+
+#[test]
+fn sorts_stably() {
+    let mut cues = vec![
+        cue(2_000, 3_000, "Later"),
+        cue(1_000, 2_500, "Longer"),
+        cue(1_000, 2_000, "Top region"),
+        cue(1_000, 2_000, "Bottom region"),
+    ];
+    sort_cues(&mut cues);
+    let texts: Vec<&str> = cues.iter().map(|cue| cue.text.as_str()).collect();
+    assert_eq!(texts, ["Top region", "Bottom region", "Longer", "Later"]);
+}
+
+#[test]
+fn deterministic_output() {
+    let cues = vec![
+        cue(1_000, 2_000, "Top region"),
+        cue(1_000, 2_000, "Bottom region"),
+        cue(1_000, 2_500, "Longer"),
+        cue(2_000, 3_000, "Later"),
+    ];
+    // Arriving in a different order only changes anything for exact ties
+    let mut shuffled = vec![
+        cues[3].clone(),
+        cues[0].clone(),
+        cues[2].clone(),
+        cues[1].clone(),
+    ];
+    sort_cues(&mut shuffled);
+    let expected = srt(&cues);
+    assert_eq!(srt(&shuffled), expected);
+    assert_eq!(srt(&shuffled), expected);
+    assert!(expected.starts_with("1\n00:00:01,000 --> 00:00:02,000\nTop region\n\n2\n"));
+}
+
+#[test]
+fn parses() {
+    let text = "\u{FEFF}1\r\n00:00:01,000 --> 00:00:02,500\r\n<i>Hello,</i>\r\nthere\r\n\r\n\
+                2\r\n00:00:03.000 --> 00:00:04.000 X1:10 X2:20\r\nAgain\r\n\r\n\
+                3\r\nnot a cue\r\n";
+    let cues = parse_srt(text);
+    assert_eq!(cues.len(), 2);
+    assert_eq!((cues[0].start, cues[0].end), (1_000 * MS, 2_500 * MS));
+    assert_eq!(cues[0].text, "<i>Hello,</i>\nthere");
+    assert_eq!((cues[1].start, cues[1].end), (3_000 * MS, 4_000 * MS));
+
+    // What's written reads back the same
+    let written = vec![
+        cue(1_000, 2_000, "One"),
+        cue(61_500, 3_725_250, "Two\nlines"),
+    ];
+    let read = parse_srt(&srt(&written));
+    assert_eq!(srt(&read), srt(&written));
+
+    assert_eq!(parse_timestamp("01:02:03,004"), Some(3_723_004 * MS));
+    assert_eq!(parse_timestamp("01:02,004"), None);
+    assert_eq!(parse_timestamp("00:00:01,5"), None);
+}
@@ -0,0 +1,223 @@
+//! Reading MKVs, SUP and VobSub `.sub` files from streams that can't seek.
+
+mod common;
+
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+use common::*;
+use matroska_demuxer::{Frame, MatroskaFile};
+use subproc::{
+    attachments::read_attachments,
+    bdsup::{PgsParser, sup::SupReader},
+    ebml::*,
+    extract::SubtitleExtractor,
+    stream::StreamInput,
+    vobs::{SubFileReader, SubStreamReader},
+};
+
+/// Data that can only be read, a few bytes at a time, like a pipe
+struct Pipe(Cursor<Vec<u8>>);
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let length = buf.len().min(1000);
+        return self.0.read(&mut buf[..length]);
+    }
+}
+
+fn pipe(data: &[u8]) -> Pipe {
+    return Pipe(Cursor::new(data.to_vec()));
+}
+
+fn show(composition_number: u16) -> Vec<u8> {
+    let objects = [PgsObjectRef {
+        object_id: 1,
+        window_id: 0,
+        x: 0,
+        y: 0,
+        crop: None,
+    }];
+    return PgsDisplaySetBuilder::new()
+        .pcs(composition_number, 0x80, 0, &objects)
+        .wds(&[(0, 800, 900, 100, 20)])
+        .pds(0, 0, &[(1, 235, 255), (2, 16, 255)])
+        .ods(1, 0, 100, 20, &pgs_rle(&outlined_bar(100, 20, 1, 2)), 1)
+        .finish();
+}
+
+fn clear(composition_number: u16) -> Vec<u8> {
+    return PgsDisplaySetBuilder::new()
+        .pcs(composition_number, 0x00, 0, &[])
+        .wds(&[(0, 800, 900, 100, 20)])
+        .finish();
+}
+
+/// A PGS track interleaved with a video track of frames bigger than what
+/// the stream keeps behind the read position, with a SeekHead (after
+/// Tracks, where only the stream looks for it) pointing past the end
+fn sample_mkv() -> Vec<u8> {
+    let mut frames = Vec::new();
+    for index in 0..30u64 {
+        frames.push((1, index * 1000, vec![index as u8; 200_000]));
+        if index % 3 == 0 {
+            frames.push((2, index * 1000, show(index as u16)));
+            frames.push((2, index * 1000 + 1500, clear(index as u16 + 1)));
+        }
+    }
+    let mut seek = encode_element(ID_SEEK_ID, &encode_id(ID_CUES));
+    seek.extend(encode_uint(ID_SEEK_POSITION, 1 << 40));
+    let mut extra = encode_element(ID_SEEK_HEAD, &encode_element(ID_SEEK, &seek));
+    extra.extend(mkv_attachments(&[("fonts.txt", "text/plain", b"Arial")]));
+    return build_mkv_with(
+        &[(1, "V_MPEG4/ISO/AVC", None), (2, "S_HDMV/PGS", None)],
+        &frames,
+        None,
+        &extra,
+    );
+}
+
+fn all_frames<R: Read + Seek>(mut mkv: MatroskaFile<R>) -> Vec<(u64, u64, Vec<u8>)> {
+    let mut frames = Vec::new();
+    let mut frame = Frame::default();
+    while mkv.next_frame(&mut frame).unwrap() {
+        frames.push((frame.track, frame.timestamp, frame.data.clone()));
+    }
+    return frames;
+}
+
+#[test]
+fn streamed_mkvs_demux_like_files() {
+    let file = sample_mkv();
+    let expected = all_frames(MatroskaFile::open(Cursor::new(&file)).unwrap());
+    assert_eq!(expected.len(), 50);
+
+    let stream = StreamInput::new(pipe(&file)).unwrap();
+    let mkv = MatroskaFile::open(stream).unwrap();
+    assert_eq!(mkv.tracks().len(), 2);
+    assert_eq!(all_frames(mkv), expected);
+}
+
+fn event_times<R: Read + Seek>(extractor: SubtitleExtractor<R>) -> Vec<(u64, Option<u64>)> {
+    return extractor
+        .map(|event| {
+            let event = event.unwrap();
+            return (event.start, event.end);
+        })
+        .collect();
+}
+
+#[test]
+fn streamed_mkvs_extract_like_files() {
+    let file = sample_mkv();
+    let expected = event_times(
+        SubtitleExtractor::new(MatroskaFile::open(Cursor::new(&file)).unwrap(), Some(2)).unwrap(),
+    );
+    assert_eq!(expected.len(), 10);
+    let stream = StreamInput::new(pipe(&file)).unwrap();
+    let streamed =
+        event_times(SubtitleExtractor::new(MatroskaFile::open(stream).unwrap(), Some(2)).unwrap());
+    assert_eq!(streamed, expected);
+}
+
+#[test]
+fn stream_head_has_the_attachments() {
+    let stream = StreamInput::new(pipe(&sample_mkv())).unwrap();
+    let attachments = read_attachments(&mut Cursor::new(stream.head())).unwrap();
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0].data, b"Arial");
+}
+
+#[test]
+fn streams_only_seek_back_a_little() {
+    let mut stream = StreamInput::new(pipe(&sample_mkv())).unwrap();
+    let head = stream.head().len() as u64;
+    let mut data = vec![0; 500_000];
+    stream.read_exact(&mut data).unwrap();
+    // The head is always there, as is what was just read
+    stream.seek(SeekFrom::Start(10)).unwrap();
+    stream.seek(SeekFrom::Start(499_000)).unwrap();
+    let mut byte = [0];
+    stream.read_exact(&mut byte).unwrap();
+    assert_eq!(byte[0], data[499_000]);
+
+    assert!(stream.seek(SeekFrom::Start(head + 10)).is_err());
+    assert!(stream.seek(SeekFrom::End(0)).is_err());
+    assert!(StreamInput::new(pipe(b"PG not an MKV")).is_err());
+}
+
+/// Wraps each segment of a display set in a SUP header
+fn sup(display_sets: &[(u32, Vec<u8>)]) -> Vec<u8> {
+    let mut sup = Vec::new();
+    for (pts, data) in display_sets {
+        let mut rest = &data[..];
+        while !rest.is_empty() {
+            let length = 3 + u16::from_be_bytes([rest[1], rest[2]]) as usize;
+            sup.extend_from_slice(b"PG");
+            sup.extend_from_slice(&pts.to_be_bytes());
+            sup.extend_from_slice(&0u32.to_be_bytes());
+            sup.extend_from_slice(&rest[..length]);
+            rest = &rest[length..];
+        }
+    }
+    return sup;
+}
+
+#[test]
+fn sup_streams_split_into_display_sets() {
+    let display_sets = vec![(90_000, show(1)), (180_000, clear(2))];
+    let read: Vec<_> = SupReader::new(pipe(&sup(&display_sets)))
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(read.len(), 2);
+    assert_eq!(read[0].timestamp, 1_000_000_000);
+    assert_eq!(read[1].timestamp, 2_000_000_000);
+    for (read, (_, data)) in read.iter().zip(&display_sets) {
+        assert_eq!(&read.data, data);
+    }
+    let mut parser = PgsParser::new();
+    let image = parser.process_display_set(&read[0].data).unwrap().unwrap();
+    assert_eq!((image.width(), image.height()), (1920, 1080));
+
+    // A stream cut short ends with an error, after the complete display sets
+    let data = sup(&display_sets);
+    let results: Vec<_> = SupReader::new(pipe(&data[..data.len() - 5])).collect();
+    assert_eq!(results.len(), 2);
+    assert!(results[0].is_ok());
+    assert!(results[1].is_err());
+    let results: Vec<_> = SupReader::new(pipe(b"not a SUP file")).collect();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_err());
+}
+
+#[test]
+fn sub_streams_read_like_sub_files() {
+    let rows = vec![vec![1; 8]; 4];
+    let packet = vobsub_subpicture(100, 400, &rows, [1, 2, 3, 0], [15, 15, 15, 0]);
+    let (first, second) = packet.split_at(10);
+    // One pack per sector, as in real `.sub` files
+    let mut sub = Vec::new();
+    for piece in [
+        (0, Some(90_000), first),
+        (1, Some(95_000), &packet[..]),
+        (0, None, second),
+        (1, Some(180_000), &packet[..]),
+    ] {
+        let mut pack = vobsub_program_stream(&[piece]);
+        pack.truncate(pack.len() - 4);
+        pack.resize(2048, 0xFF);
+        sub.extend(pack);
+    }
+    let expected: Vec<_> = SubFileReader::new(&sub)
+        .map(|subpicture| {
+            let subpicture = subpicture.unwrap();
+            return (subpicture.stream, subpicture.timestamp, subpicture.data);
+        })
+        .collect();
+    assert_eq!(expected.len(), 3);
+    let streamed: Vec<_> = SubStreamReader::new(pipe(&sub))
+        .map(|subpicture| {
+            let subpicture = subpicture.unwrap();
+            return (subpicture.stream, subpicture.timestamp, subpicture.data);
+        })
+        .collect();
+    assert_eq!(streamed, expected);
+}
@@ -0,0 +1,90 @@
+//! Lining drifted subtitles up with a reference.
+
+use subproc::{
+    srt::SrtCue,
+    sync::{Correction, SyncOptions, align, cue_intervals},
+    timebase::FrameRate,
+};
+
+const MS: u64 = 1_000_000;
+
+/// Cues of irregular lengths and gaps, so only one offset lines them up
+fn reference() -> Vec<SrtCue> {
+    let mut seed: u64 = 7;
+    let mut next = |range: u64| {
+        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+        return (seed >> 33) % range;
+    };
+    let mut cues = Vec::new();
+    let mut time = 10_000 * MS;
+    for index in 0..200 {
+        time += (200 + next(2_800)) * MS;
+        let end = time + (1_000 + next(3_000)) * MS;
+        cues.push(SrtCue {
+            start: time,
+            end,
+            text: format!("Line {index}"),
+        });
+        time = end;
+    }
+    return cues;
+}
+
+fn drifted(correction: Correction) -> Vec<SrtCue> {
+    let mut cues = reference();
+    correction.apply_cues(&mut cues);
+    return cues;
+}
+
+#[test]
+fn shifted() {
+    let cues = drifted(Correction {
+        offset: 2_500 * MS as i64,
+        scale: 1.0,
+    });
+    let alignment = align(
+        &cues,
+        &cue_intervals(&reference()),
+        SyncOptions {
+            scale: false,
+            ..SyncOptions::default()
+        },
+    );
+    assert_eq!(
+        alignment.correction,
+        Correction {
+            offset: -2_500 * MS as i64,
+            scale: 1.0,
+        }
+    );
+    assert!(alignment.before < 0.5);
+    assert!(alignment.after > 0.999);
+}
+
+#[test]
+fn frame_rate_drift() {
+    // Timed for 25 fps playback of a 23.976 fps video, and late by 3 seconds
+    let speedup = FrameRate::FILM_NTSC.frames_per_second() / FrameRate::PAL.frames_per_second();
+    let mut cues = drifted(Correction {
+        offset: 3_000 * MS as i64,
+        scale: speedup,
+    });
+    let alignment = align(&cues, &cue_intervals(&reference()), SyncOptions::default());
+    assert_eq!(alignment.correction.scale, 1.0 / speedup);
+    let offset = -3_000.0 * MS as f64 / speedup;
+    assert!((alignment.correction.offset as f64 - offset).abs() < 2.0 * MS as f64);
+    assert!(alignment.after > 0.99);
+
+    alignment.correction.apply_cues(&mut cues);
+    for (cue, expected) in cues.iter().zip(reference()) {
+        assert!(cue.start.abs_diff(expected.start) < 2 * MS);
+        assert!(cue.end.abs_diff(expected.end) < 2 * MS);
+    }
+}
+
+#[test]
+fn unrelated_timelines_are_left_alone() {
+    let alignment = align(&reference(), &[], SyncOptions::default());
+    assert_eq!(alignment.correction, Correction::default());
+    assert_eq!(alignment.after, 0.0);
+}
@@ -0,0 +1,32 @@
+//! Terminal preview helpers that don't need a terminal
+
+use image::{Rgb, Rgba, RgbaImage};
+use subproc::terminal::{PreviewMode, PreviewWidth, over_background};
+
+#[test]
+fn preview_options() {
+    assert_eq!(
+        PreviewWidth::parse("80cols"),
+        Some(PreviewWidth::Columns(80))
+    );
+    assert_eq!(
+        PreviewWidth::parse("640px"),
+        Some(PreviewWidth::Pixels(640))
+    );
+    assert_eq!(PreviewWidth::parse("640"), Some(PreviewWidth::Pixels(640)));
+    assert_eq!(PreviewWidth::parse("0cols"), None);
+    assert_eq!(PreviewWidth::parse("wide"), None);
+    assert_eq!(PreviewWidth::Columns(80).columns(), 80);
+    assert_eq!(PreviewMode::Blocks.resolve(), PreviewMode::Blocks);
+    assert_ne!(PreviewMode::Auto.resolve(), PreviewMode::Auto);
+
+    let image = RgbaImage::from_fn(3, 1, |x, _| {
+        Rgba([255, 255, 255, [0, 128, 255][x as usize]])
+    });
+    let blended = over_background(&image, Rgb([0, 0, 255]));
+    let pixels: Vec<[u8; 4]> = blended.pixels().map(|pixel| pixel.0).collect();
+    assert_eq!(
+        pixels,
+        [[0, 0, 255, 255], [128, 128, 255, 255], [255, 255, 255, 255]]
+    );
+}
@@ -0,0 +1,35 @@
+//! The text clean-up filters applied before writing.
+
+use subproc::text_filter::{AsciiPunctuation, FilterChain, Replace, TextFilter};
+
+#[test]
+fn default_chain() {
+    let filters = FilterChain::default();
+    // Decomposed accents, a BOM, a stray escape and a tab
+    assert_eq!(
+        filters.apply("\u{FEFF}Cafe\u{301}\u{1B}\tnoe\u{308}l\r\nZ\u{200D}"),
+        "Café noël\nZ\u{200D}"
+    );
+    assert_eq!(filters.apply("“Hello…”"), "“Hello…”");
+}
+
+#[test]
+fn ascii_punctuation() {
+    assert_eq!(
+        AsciiPunctuation.apply("“It’s late. . .” ‘Fine…’"),
+        "\"It's late...\" 'Fine...'"
+    );
+}
+
+#[test]
+fn replacements() {
+    let mut filters = FilterChain::empty();
+    filters.push(Replace::parse(r"/\bl\b/I/").unwrap());
+    filters.push(Replace::parse(r"|(\d+)/(\d+)|$2 of $1|").unwrap());
+    assert_eq!(filters.apply("l think l saw 1/2"), "I think I saw 2 of 1");
+
+    assert!(Replace::parse("/missing replacement").is_err());
+    assert!(Replace::parse("/a/b/c/").is_err());
+    assert!(Replace::parse("/(/x/").is_err());
+    assert!(Replace::parse("").is_err());
+}
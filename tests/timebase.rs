@@ -0,0 +1,106 @@
+//! Conversions between nanoseconds, 90 kHz ticks and video frames.
+
+use subproc::timebase::{FrameRate, PTS_MASK, Timestamp, ns_to_pts, pts_to_ns};
+
+#[test]
+fn pts() {
+    assert_eq!(pts_to_ns(90_000), 1_000_000_000);
+    assert_eq!(ns_to_pts(1_000_000_000), 90_000);
+    // Every tick survives the round trip
+    for pts in [0, 1, 2, 7, 89_999, 900_001, PTS_MASK] {
+        assert_eq!(ns_to_pts(pts_to_ns(pts)), pts);
+    }
+    // Nanoseconds round to the nearest tick
+    assert_eq!(ns_to_pts(5_555), 0);
+    assert_eq!(ns_to_pts(5_556), 1);
+    // and wrap at 33 bits
+    assert_eq!(ns_to_pts(pts_to_ns(PTS_MASK + 1)), 0);
+}
+
+#[test]
+fn frames() {
+    let rate = FrameRate::FILM_NTSC;
+    assert_eq!(rate.to_string(), "23.976");
+    assert_eq!(rate.timecode_base(), 24);
+    for frame in [0, 1, 23, 24, 1001, 86_400 * 24] {
+        assert_eq!(rate.ns_to_frame(rate.frame_to_ns(frame)), frame);
+        assert_eq!(rate.nearest_frame(rate.frame_to_ns(frame)), frame);
+    }
+    // 41.708ms frames: 60ms is closer to the second frame's start
+    assert_eq!(rate.snap(60_000_000), 41_708_334);
+    assert_eq!(rate.snap(20_000_000), 0);
+    // Timecodes count 24 frames a second even though it's a bit slower
+    assert_eq!(rate.timecode(rate.frame_to_ns(24)), "00:00:01:00");
+    assert_eq!(rate.timecode(rate.frame_to_ns(86_400 + 23)), "01:00:00:23");
+    assert_eq!(FrameRate::PAL.timecode(61_520_000_000), "00:01:01:13");
+}
+
+#[test]
+fn rates() {
+    assert_eq!(FrameRate::from_pgs_code(0x10), Some(FrameRate::FILM_NTSC));
+    assert_eq!(FrameRate::from_pgs_code(0x50), None);
+    assert_eq!(FrameRate::NTSC.pgs_code(), Some(0x40));
+    assert_eq!(FrameRate::new(15, 1).pgs_code(), None);
+
+    // Matroska's DefaultDuration, rounded to the nanosecond
+    assert_eq!(
+        FrameRate::from_frame_duration(41_708_333),
+        Some(FrameRate::FILM_NTSC)
+    );
+    assert_eq!(
+        FrameRate::from_frame_duration(40_000_000),
+        Some(FrameRate::PAL)
+    );
+    assert_eq!(
+        FrameRate::from_frame_duration(80_000_000),
+        Some(FrameRate::new(25, 2))
+    );
+    assert_eq!(FrameRate::from_frame_duration(0), None);
+
+    assert_eq!("29.97".parse(), Ok(FrameRate::NTSC));
+    assert_eq!("24000/1001".parse(), Ok(FrameRate::FILM_NTSC));
+    assert_eq!("48/2".parse(), Ok(FrameRate::FILM));
+    assert_eq!("12.5".parse(), Ok(FrameRate::new(25, 2)));
+    assert_eq!(FrameRate::new(25, 2).to_string(), "12.5");
+    assert!("0".parse::<FrameRate>().is_err());
+    assert!("fast".parse::<FrameRate>().is_err());
+}
+
+#[test]
+fn timestamps() {
+    let time = Timestamp::from_nanos(3_723_456_789_000);
+    assert_eq!(time.to_srt(), "01:02:03,456");
+    assert_eq!(time.to_subviewer(), "01:02:03.45");
+    assert_eq!(time.to_string(), "01:02:03.456");
+    assert_eq!(time.as_millis(), 3_723_456);
+    assert_eq!(
+        Timestamp::from_millis(1500),
+        Some(Timestamp::from_nanos(1_500_000_000))
+    );
+    // The largest time formats without wrapping the hours
+    assert_eq!(Timestamp::MAX.to_srt(), "5124095:34:33,709");
+
+    assert_eq!(
+        Timestamp::from_ticks(3, 1_000_000),
+        Some(Timestamp::from_nanos(3_000_000))
+    );
+    assert_eq!(Timestamp::from_ticks(u64::MAX / 2, 1_000_000), None);
+    assert_eq!(Timestamp::from_millis(u64::MAX), None);
+    assert_eq!(
+        Timestamp::from_secs_f64(1.5),
+        Some(Timestamp::from_nanos(1_500_000_000))
+    );
+    for seconds in [-1.0, f64::NAN, f64::INFINITY, 1e12] {
+        assert_eq!(Timestamp::from_secs_f64(seconds), None);
+    }
+
+    assert_eq!(Timestamp::from_pts(90_000).as_nanos(), 1_000_000_000);
+    assert_eq!(Timestamp::from_pts(PTS_MASK).to_pts(), PTS_MASK);
+    assert_eq!(Timestamp::from_pts(u64::MAX), Timestamp::MAX);
+
+    let second = Timestamp::from_nanos(1_000_000_000);
+    assert_eq!(Timestamp::MAX.checked_add(second), None);
+    assert_eq!(Timestamp::MAX.saturating_add(second), Timestamp::MAX);
+    assert_eq!(Timestamp::ZERO.checked_sub(second), None);
+    assert_eq!(Timestamp::ZERO.saturating_sub(second), Timestamp::ZERO);
+}
@@ -0,0 +1,100 @@
+//! Repairing overlapping, crowded and overlong cues.
+
+mod common;
+
+use common::*;
+use subproc::{
+    srt::SrtCue,
+    timing::{OverlapPolicy, TimingReport, TimingRules, repair_timing},
+};
+
+const MS: u64 = 1_000_000;
+
+fn times(cues: &[SrtCue]) -> Vec<(u64, u64, &str)> {
+    return cues
+        .iter()
+        .map(|cue| (cue.start / MS, cue.end / MS, cue.text.as_str()))
+        .collect();
+}
+
+#[test]
+fn trims_overlaps_and_gaps() {
+    let cues = vec![
+        cue(3_000, 4_000, "Third"),
+        cue(0, 1_500, "First"),
+        cue(1_000, 2_000, "Second"),
+        cue(2_500, 2_900, " "),
+    ];
+    let rules = TimingRules {
+        min_gap: 100 * MS,
+        ..Default::default()
+    };
+    let (repaired, report) = repair_timing(cues, &rules);
+    assert_eq!(
+        times(&repaired),
+        [
+            (0, 900, "First"),
+            (1_000, 2_000, "Second"),
+            (3_000, 4_000, "Third")
+        ]
+    );
+    assert_eq!(
+        report,
+        TimingReport {
+            trimmed: 1,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn merges_overlaps() {
+    let cues = vec![
+        cue(0, 2_000, "Sign"),
+        cue(1_000, 3_000, "Dialogue"),
+        cue(2_500, 3_500, "Dialogue"),
+        // Starts with the previous cue, so there's nothing to trim it to
+        cue(5_000, 6_000, "A"),
+        cue(5_000, 5_500, "B"),
+    ];
+    let merge = TimingRules {
+        overlaps: OverlapPolicy::Merge,
+        ..Default::default()
+    };
+    let (repaired, report) = repair_timing(cues.clone(), &merge);
+    assert_eq!(
+        times(&repaired),
+        [(0, 3_500, "Sign\nDialogue"), (5_000, 6_000, "A\nB")]
+    );
+    assert_eq!(report.merged, 3);
+
+    let (repaired, report) = repair_timing(cues, &TimingRules::default());
+    assert_eq!(
+        times(&repaired),
+        [
+            (0, 1_000, "Sign"),
+            (1_000, 2_500, "Dialogue"),
+            (2_500, 3_500, "Dialogue"),
+            (5_000, 6_000, "A\nB")
+        ]
+    );
+    assert_eq!((report.trimmed, report.merged), (2, 1));
+}
+
+#[test]
+fn caps_durations() {
+    let rules = TimingRules {
+        max_duration: Some(7_000 * MS),
+        ..Default::default()
+    };
+    let (repaired, report) = repair_timing(
+        vec![cue(0, 60_000, "Stuck"), cue(8_000, 9_000, "Next")],
+        &rules,
+    );
+    assert_eq!(
+        times(&repaired),
+        [(0, 7_000, "Stuck"), (8_000, 9_000, "Next")]
+    );
+    assert_eq!(report.capped, 1);
+    assert_eq!(report.trimmed, 0);
+}
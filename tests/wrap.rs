@@ -0,0 +1,84 @@
+//! Re-wrapping cue text to line limits.
+
+use subproc::wrap::{WrapOptions, rewrap};
+
+#[test]
+fn joins_short_text() {
+    let options = WrapOptions::default();
+    assert_eq!(
+        rewrap("Where are\nyou going?", &options),
+        "Where are you going?"
+    );
+    assert_eq!(rewrap("  ", &options), "");
+}
+
+#[test]
+fn breaks_at_sensible_points() {
+    let options = WrapOptions::default();
+    // After a sentence, even though the lines end up uneven
+    assert_eq!(
+        rewrap(
+            "I told you already. We can't stay here any longer.",
+            &options
+        ),
+        "I told you already.\nWe can't stay here any longer."
+    );
+    // Before a conjunction
+    assert_eq!(
+        rewrap("It was late and cold but we kept on walking home", &options),
+        "It was late and cold\nbut we kept on walking home"
+    );
+    // Not after an article
+    assert_eq!(
+        rewrap(
+            "Could you please hand me the screwdriver from the toolbox",
+            &options
+        ),
+        "Could you please hand me\nthe screwdriver from the toolbox"
+    );
+    // Otherwise as evenly as possible
+    assert_eq!(
+        rewrap(
+            "this line has no punctuation at all just plain words here",
+            &options
+        ),
+        "this line has no punctuation\nat all just plain words here"
+    );
+}
+
+#[test]
+fn keeps_tags_and_dialogue() {
+    let options = WrapOptions::default();
+    assert_eq!(
+        rewrap(
+            "{\\an8}<i>The quick brown fox\njumps over the lazy dog, again and again.</i>",
+            &options
+        ),
+        "{\\an8}<i>The quick brown fox jumps over\nthe lazy dog, again and again.</i>"
+    );
+    assert_eq!(
+        rewrap("- Who's there?\n  - Nobody.", &options),
+        "- Who's there?\n- Nobody."
+    );
+}
+
+#[test]
+fn overlong_text() {
+    let options = WrapOptions {
+        max_line_length: 10,
+        max_lines: 2,
+    };
+    // Stays within two lines, as evenly as it can
+    assert_eq!(
+        rewrap("one two three four five six", &options),
+        "one two three\nfour five six"
+    );
+    let options = WrapOptions {
+        max_line_length: 10,
+        max_lines: 3,
+    };
+    assert_eq!(
+        rewrap("one two three four five six", &options),
+        "one two\nthree four\nfive six"
+    );
+}
@@ -0,0 +1,170 @@
+//! Writing one pass of cues to several formats through the writer registry.
+
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use subproc::{
+    legacy::write_microdvd,
+    srt::{SrtCue, write_srt},
+    timebase::FrameRate,
+    wrap::{RewrapWriter, WrapOptions},
+    writer::{MultiWriter, SubtitleWriter, WriterError, WriterOptions, WriterRegistry, write_all},
+};
+
+const MS: u64 = 1_000_000;
+
+/// An output that can still be read after a writer has taken it
+#[derive(Clone, Default)]
+struct Shared(Arc<Mutex<Vec<u8>>>);
+impl Shared {
+    fn text(&self) -> String {
+        return String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+    }
+}
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        return self.0.lock().unwrap().write(buf);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+fn cues() -> Vec<SrtCue> {
+    return vec![
+        SrtCue {
+            start: 1_000 * MS,
+            end: 2_500 * MS,
+            text: String::from("<i>First</i>"),
+        },
+        SrtCue {
+            start: 3_000 * MS,
+            end: 4_000 * MS,
+            text: String::from("Second\nline"),
+        },
+    ];
+}
+
+#[test]
+fn writes_every_format_in_one_pass() {
+    let registry = WriterRegistry::default();
+    let options = WriterOptions {
+        frame_rate: Some(FrameRate::PAL),
+    };
+    let (srt, microdvd) = (Shared::default(), Shared::default());
+    let mut writers = MultiWriter::new();
+    writers.push(
+        registry
+            .create("srt", Box::new(srt.clone()), &options)
+            .unwrap(),
+    );
+    writers.push(
+        registry
+            .create("microdvd", Box::new(microdvd.clone()), &options)
+            .unwrap(),
+    );
+    write_all(&mut writers, &cues()).unwrap();
+
+    let mut expected = Vec::new();
+    write_srt(&mut expected, &cues()).unwrap();
+    assert_eq!(srt.text(), String::from_utf8(expected).unwrap());
+    let mut expected = Vec::new();
+    write_microdvd(&mut expected, &cues(), FrameRate::PAL).unwrap();
+    assert_eq!(microdvd.text(), String::from_utf8(expected).unwrap());
+}
+
+#[test]
+fn headers_are_written_without_cues() {
+    let out = Shared::default();
+    let mut writer = WriterRegistry::default()
+        .create(
+            "subviewer",
+            Box::new(out.clone()),
+            &WriterOptions::default(),
+        )
+        .unwrap();
+    writer.finish().unwrap();
+    assert_eq!(out.text(), "[INFORMATION]\n[END INFORMATION]\n[SUBTITLE]\n");
+}
+
+#[test]
+fn reports_unusable_formats() {
+    let registry = WriterRegistry::default();
+    let create = |name: &str| {
+        return registry.create(name, Box::new(io::sink()), &WriterOptions::default());
+    };
+    assert!(matches!(create("vtt"), Err(WriterError::UnknownFormat(name)) if name == "vtt"));
+    assert!(matches!(
+        create("microdvd"),
+        Err(WriterError::MissingFrameRate(_))
+    ));
+}
+
+/// Writes the start of each cue, in milliseconds
+struct Starts(Box<dyn Write + Send>);
+impl SubtitleWriter for Starts {
+    fn write_event(&mut self, cue: &SrtCue) -> Result<(), WriterError> {
+        writeln!(self.0, "{}", cue.start / MS)?;
+        return Ok(());
+    }
+
+    fn finish(&mut self) -> Result<(), WriterError> {
+        return Ok(self.0.flush()?);
+    }
+}
+
+#[test]
+fn registers_custom_formats() {
+    let mut registry = WriterRegistry::default();
+    registry.register(
+        "starts",
+        "txt",
+        false,
+        Box::new(|out, _| Ok(Box::new(Starts(out)))),
+    );
+    assert_eq!(
+        registry.names().collect::<Vec<_>>(),
+        ["srt", "microdvd", "subviewer", "starts"]
+    );
+    assert_eq!(registry.get("starts").unwrap().extension, "txt");
+
+    let out = Shared::default();
+    let mut writer = registry
+        .create("starts", Box::new(out.clone()), &WriterOptions::default())
+        .unwrap();
+    write_all(&mut writer, &cues()).unwrap();
+    assert_eq!(out.text(), "1000\n3000\n");
+}
+
+#[test]
+fn rewraps_one_output_only() {
+    let registry = WriterRegistry::default();
+    let (wide, narrow) = (Shared::default(), Shared::default());
+    let mut writers = MultiWriter::new();
+    writers.push(
+        registry
+            .create("srt", Box::new(wide.clone()), &WriterOptions::default())
+            .unwrap(),
+    );
+    let wrapped = registry
+        .create("srt", Box::new(narrow.clone()), &WriterOptions::default())
+        .unwrap();
+    writers.push(Box::new(RewrapWriter::new(
+        wrapped,
+        WrapOptions {
+            max_line_length: 12,
+            max_lines: 2,
+        },
+    )));
+    let cues = [SrtCue {
+        start: 0,
+        end: 1_000 * MS,
+        text: String::from("A short line that wraps"),
+    }];
+    write_all(&mut writers, &cues).unwrap();
+    assert!(wide.text().contains("\nA short line that wraps\n"));
+    assert!(narrow.text().contains("\nA short line\nthat wraps\n"));
+}